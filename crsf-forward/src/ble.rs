@@ -0,0 +1,528 @@
+//! Bluetooth LE transport (`--port ble://MAC`), for handsets and ELRS
+//! Backpacks that carry CRSF over a BLE serial service.
+//!
+//! The GATT client talks ATT directly over a Linux L2CAP socket, so only the
+//! kernel's Bluetooth stack is needed. Two serial services are recognized:
+//! the Nordic UART service, and the HM-10 style FFE0 service of the
+//! Bluetooth modules in radios. Data is written with write commands, in
+//! pieces of the negotiated MTU, and received as notifications.
+
+use std::io;
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use tokio::io::unix::AsyncFd;
+use tokio::io::{AsyncRead, AsyncWrite, Interest, ReadBuf};
+
+use crate::sys::cvt;
+
+const AF_BLUETOOTH: libc::sa_family_t = 31;
+const BTPROTO_L2CAP: libc::c_int = 0;
+/// L2CAP channel of the attribute protocol.
+const ATT_CID: u16 = 4;
+const BDADDR_LE_PUBLIC: u8 = 1;
+const BDADDR_LE_RANDOM: u8 = 2;
+
+/// ATT opcodes.
+const ERROR_RSP: u8 = 0x01;
+const EXCHANGE_MTU_REQ: u8 = 0x02;
+const EXCHANGE_MTU_RSP: u8 = 0x03;
+const FIND_INFORMATION_REQ: u8 = 0x04;
+const FIND_INFORMATION_RSP: u8 = 0x05;
+const READ_BY_TYPE_REQ: u8 = 0x08;
+const READ_BY_TYPE_RSP: u8 = 0x09;
+const WRITE_REQ: u8 = 0x12;
+const WRITE_RSP: u8 = 0x13;
+const HANDLE_VALUE_NTF: u8 = 0x1b;
+const HANDLE_VALUE_IND: u8 = 0x1d;
+const HANDLE_VALUE_CFM: u8 = 0x1e;
+const WRITE_CMD: u8 = 0x52;
+const ATTRIBUTE_NOT_FOUND: u8 = 0x0a;
+
+const DEFAULT_MTU: u16 = 23;
+/// MTU asked for: enough for any CRSF frame in one notification.
+const MTU: u16 = 247;
+
+/// GATT attribute types.
+const CHARACTERISTIC: u16 = 0x2803;
+const CLIENT_CHARACTERISTIC_CONFIG: u16 = 0x2902;
+/// Characteristic properties.
+const PROP_WRITE_WITHOUT_RESPONSE: u8 = 0x04;
+const PROP_NOTIFY: u8 = 0x10;
+
+/// Nordic UART service: the client writes to RX, the device notifies TX.
+const NUS_RX: u128 = 0x6e400002_b5a3_f393_e0a9_e50e24dcca9e;
+const NUS_TX: u128 = 0x6e400003_b5a3_f393_e0a9_e50e24dcca9e;
+/// HM-10 style serial characteristic, both ways.
+const HM10_DATA: u128 = uuid16(0xffe1);
+
+/// A 16-bit UUID in the Bluetooth base UUID.
+const fn uuid16(uuid: u16) -> u128 {
+    ((uuid as u128) << 96) | 0x0000_0000_0000_1000_8000_0080_5f9b_34fb
+}
+
+/// `struct sockaddr_l2` from BlueZ.
+#[repr(C)]
+struct SockaddrL2 {
+    l2_family: libc::sa_family_t,
+    l2_psm: u16,
+    l2_bdaddr: [u8; 6],
+    l2_cid: u16,
+    l2_bdaddr_type: u8,
+}
+
+/// A device address, `AA:BB:CC:DD:EE:FF` with `/random` for a random
+/// address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Device {
+    /// In the byte order of the socket address, least significant first.
+    bdaddr: [u8; 6],
+    addr_type: u8,
+}
+
+fn parse_device(s: &str) -> Result<Device, String> {
+    let (mac, addr_type) = match s.split_once('/') {
+        None => (s, BDADDR_LE_PUBLIC),
+        Some((mac, "public")) => (mac, BDADDR_LE_PUBLIC),
+        Some((mac, "random")) => (mac, BDADDR_LE_RANDOM),
+        Some((_, other)) => {
+            return Err(format!(
+                "unknown address type `{}` (public or random)",
+                other
+            ));
+        }
+    };
+    let bytes: Vec<u8> = mac
+        .split(':')
+        .map(|b| u8::from_str_radix(b, 16))
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("invalid Bluetooth address `{}`", mac))?;
+    let mut bdaddr: [u8; 6] = bytes
+        .try_into()
+        .map_err(|_| format!("invalid Bluetooth address `{}`", mac))?;
+    bdaddr.reverse();
+    Ok(Device { bdaddr, addr_type })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Characteristic {
+    /// Handle of the declaration.
+    handle: u16,
+    properties: u8,
+    value_handle: u16,
+    uuid: u128,
+}
+
+fn u16_at(data: &[u8], i: usize) -> u16 {
+    u16::from_le_bytes([data[i], data[i + 1]])
+}
+
+/// A 16 or 128 bit UUID, little-endian.
+fn parse_uuid(data: &[u8]) -> Option<u128> {
+    match data.len() {
+        2 => Some(uuid16(u16_at(data, 0))),
+        16 => Some(u128::from_le_bytes(data.try_into().ok()?)),
+        _ => None,
+    }
+}
+
+/// The characteristic declarations in a Read By Type response.
+fn parse_characteristics(rsp: &[u8]) -> Vec<Characteristic> {
+    let Some(&len) = rsp.get(1) else {
+        return Vec::new();
+    };
+    if len < 7 {
+        return Vec::new();
+    }
+    rsp[2..]
+        .chunks_exact(len as usize)
+        .filter_map(|c| {
+            Some(Characteristic {
+                handle: u16_at(c, 0),
+                properties: c[2],
+                value_handle: u16_at(c, 3),
+                uuid: parse_uuid(&c[5..])?,
+            })
+        })
+        .collect()
+}
+
+/// The handles and types in a Find Information response.
+fn parse_information(rsp: &[u8]) -> Vec<(u16, u128)> {
+    let len = match rsp.get(1) {
+        Some(1) => 4,
+        Some(2) => 18,
+        _ => return Vec::new(),
+    };
+    rsp[2..]
+        .chunks_exact(len)
+        .filter_map(|c| Some((u16_at(c, 0), parse_uuid(&c[2..])?)))
+        .collect()
+}
+
+/// The characteristics to write to and to be notified by.
+fn find_serial(chars: &[Characteristic]) -> Option<(Characteristic, Characteristic)> {
+    let find = |uuid: u128, property: u8| {
+        chars
+            .iter()
+            .find(|c| c.uuid == uuid && c.properties & property != 0)
+            .copied()
+    };
+    let nus = find(NUS_RX, PROP_WRITE_WITHOUT_RESPONSE).zip(find(NUS_TX, PROP_NOTIFY));
+    nus.or_else(|| find(HM10_DATA, PROP_WRITE_WITHOUT_RESPONSE).zip(find(HM10_DATA, PROP_NOTIFY)))
+}
+
+/// Whether an ATT PDU is `opcode`'s response or error response.
+fn answers(pdu: &[u8], opcode: u8) -> bool {
+    pdu.first() == Some(&(opcode + 1))
+        || (pdu.first() == Some(&ERROR_RSP) && pdu.get(1) == Some(&opcode))
+}
+
+fn att_error(pdu: &[u8]) -> Option<u8> {
+    (pdu.first() == Some(&ERROR_RSP)).then(|| pdu.get(4).copied().unwrap_or(0))
+}
+
+/// A connection to a BLE serial service.
+pub struct Ble {
+    fd: AsyncFd<OwnedFd>,
+    /// Largest payload of a write command.
+    chunk: usize,
+    write_handle: u16,
+    notify_handle: u16,
+    /// Notification data not read yet.
+    pending: Vec<u8>,
+}
+
+fn sockaddr(bdaddr: [u8; 6], addr_type: u8) -> SockaddrL2 {
+    SockaddrL2 {
+        l2_family: AF_BLUETOOTH,
+        l2_psm: 0,
+        l2_bdaddr: bdaddr,
+        l2_cid: ATT_CID.to_le(),
+        l2_bdaddr_type: addr_type,
+    }
+}
+
+fn send(fd: &OwnedFd, pdu: &[u8]) -> io::Result<usize> {
+    // SAFETY: the pointer and length describe `pdu`.
+    let n = unsafe { libc::send(fd.as_raw_fd(), pdu.as_ptr().cast(), pdu.len(), 0) };
+    if n < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(n as usize)
+    }
+}
+
+fn recv(fd: &OwnedFd, buf: &mut [u8]) -> io::Result<usize> {
+    // SAFETY: the pointer and length describe `buf`.
+    let n = unsafe { libc::recv(fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len(), 0) };
+    if n < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(n as usize)
+    }
+}
+
+/// Connect to the serial service of `device` (see [`parse_device`]).
+pub async fn connect(device: &str) -> io::Result<Ble> {
+    let device =
+        parse_device(device).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    // SAFETY: plain socket calls; the addresses are valid `sockaddr_l2`s.
+    let fd = unsafe {
+        let fd = cvt(libc::socket(
+            AF_BLUETOOTH as libc::c_int,
+            libc::SOCK_SEQPACKET | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+            BTPROTO_L2CAP,
+        ))?;
+        let fd = OwnedFd::from_raw_fd(fd);
+        let local = sockaddr([0; 6], BDADDR_LE_PUBLIC);
+        cvt(libc::bind(
+            fd.as_raw_fd(),
+            (&local as *const SockaddrL2).cast(),
+            mem::size_of::<SockaddrL2>() as libc::socklen_t,
+        ))?;
+        let remote = sockaddr(device.bdaddr, device.addr_type);
+        let ret = libc::connect(
+            fd.as_raw_fd(),
+            (&remote as *const SockaddrL2).cast(),
+            mem::size_of::<SockaddrL2>() as libc::socklen_t,
+        );
+        if ret < 0 {
+            let e = io::Error::last_os_error();
+            if e.raw_os_error() != Some(libc::EINPROGRESS) {
+                return Err(e);
+            }
+        }
+        fd
+    };
+    let fd = AsyncFd::new(fd)?;
+    fd.writable().await?.retain_ready();
+    let mut error: libc::c_int = 0;
+    let mut len = mem::size_of::<libc::c_int>() as libc::socklen_t;
+    // SAFETY: `error` and `len` are valid for getsockopt to write.
+    cvt(unsafe {
+        libc::getsockopt(
+            fd.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_ERROR,
+            (&mut error as *mut libc::c_int).cast(),
+            &mut len,
+        )
+    })?;
+    if error != 0 {
+        return Err(io::Error::from_raw_os_error(error));
+    }
+
+    let mut ble = Ble {
+        fd,
+        chunk: (DEFAULT_MTU - 3) as usize,
+        write_handle: 0,
+        notify_handle: 0,
+        pending: Vec::new(),
+    };
+    ble.setup().await?;
+    Ok(ble)
+}
+
+impl Ble {
+    async fn send(&self, pdu: &[u8]) -> io::Result<()> {
+        self.fd
+            .async_io(Interest::WRITABLE, |fd| send(fd, pdu))
+            .await
+            .map(|_| ())
+    }
+
+    /// Send a request and wait for its response; other PDUs are ignored.
+    async fn request(&self, pdu: &[u8]) -> io::Result<Vec<u8>> {
+        self.send(pdu).await?;
+        let mut buf = [0u8; MTU as usize];
+        loop {
+            let n = self
+                .fd
+                .async_io(Interest::READABLE, |fd| recv(fd, &mut buf))
+                .await?;
+            if n == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            if answers(&buf[..n], pdu[0]) {
+                return Ok(buf[..n].to_vec());
+            }
+        }
+    }
+
+    async fn setup(&mut self) -> io::Result<()> {
+        let mut req = vec![EXCHANGE_MTU_REQ];
+        req.extend_from_slice(&MTU.to_le_bytes());
+        let rsp = self.request(&req).await?;
+        if rsp[0] == EXCHANGE_MTU_RSP && rsp.len() >= 3 {
+            self.chunk = (u16_at(&rsp, 1).clamp(DEFAULT_MTU, MTU) - 3) as usize;
+        }
+
+        let mut chars = Vec::new();
+        let mut start = 1u16;
+        loop {
+            let mut req = vec![READ_BY_TYPE_REQ];
+            req.extend_from_slice(&start.to_le_bytes());
+            req.extend_from_slice(&u16::MAX.to_le_bytes());
+            req.extend_from_slice(&CHARACTERISTIC.to_le_bytes());
+            let rsp = self.request(&req).await?;
+            match att_error(&rsp) {
+                Some(ATTRIBUTE_NOT_FOUND) => break,
+                Some(e) => return Err(io::Error::other(format!("ATT error {:#04x}", e))),
+                None if rsp[0] != READ_BY_TYPE_RSP => break,
+                None => {}
+            }
+            let found = parse_characteristics(&rsp);
+            let Some(last) = found.last() else { break };
+            if last.value_handle == u16::MAX {
+                chars.extend(found);
+                break;
+            }
+            start = last.value_handle + 1;
+            chars.extend(found);
+        }
+        let (write, notify) = find_serial(&chars).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "no BLE serial service (Nordic UART or HM-10)",
+            )
+        })?;
+        self.write_handle = write.value_handle;
+        self.notify_handle = notify.value_handle;
+
+        // The client characteristic configuration descriptor is among the
+        // descriptors between the value and the next characteristic.
+        let end = chars
+            .iter()
+            .map(|c| c.handle)
+            .filter(|&h| h > notify.value_handle)
+            .min()
+            .map_or(u16::MAX, |h| h - 1);
+        let mut req = vec![FIND_INFORMATION_REQ];
+        req.extend_from_slice(&(notify.value_handle + 1).to_le_bytes());
+        req.extend_from_slice(&end.to_le_bytes());
+        let rsp = self.request(&req).await?;
+        let cccd = if rsp[0] == FIND_INFORMATION_RSP {
+            parse_information(&rsp)
+                .into_iter()
+                .find(|&(_, uuid)| uuid == uuid16(CLIENT_CHARACTERISTIC_CONFIG))
+                .map(|(handle, _)| handle)
+        } else {
+            None
+        }
+        .unwrap_or(notify.value_handle + 1);
+
+        let mut req = vec![WRITE_REQ];
+        req.extend_from_slice(&cccd.to_le_bytes());
+        // Notifications on.
+        req.extend_from_slice(&[0x01, 0x00]);
+        let rsp = self.request(&req).await?;
+        if rsp[0] != WRITE_RSP {
+            return Err(io::Error::other("can't enable notifications"));
+        }
+        Ok(())
+    }
+}
+
+impl AsyncRead for Ble {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let mut pdu = [0u8; MTU as usize];
+        while this.pending.is_empty() {
+            let mut guard = ready!(this.fd.poll_read_ready(cx))?;
+            let n = match guard.try_io(|fd| recv(fd.get_ref(), &mut pdu)) {
+                Ok(n) => n?,
+                Err(_would_block) => continue,
+            };
+            if n == 0 {
+                return Poll::Ready(Ok(()));
+            }
+            let pdu = &pdu[..n];
+            match pdu[0] {
+                HANDLE_VALUE_NTF | HANDLE_VALUE_IND if n >= 3 => {
+                    if pdu[0] == HANDLE_VALUE_IND {
+                        // Best effort: a lost confirmation ends the
+                        // indications, which the serial services don't use.
+                        let _ = send(this.fd.get_ref(), &[HANDLE_VALUE_CFM]);
+                    }
+                    if u16_at(pdu, 1) == this.notify_handle {
+                        this.pending.extend_from_slice(&pdu[3..]);
+                    }
+                }
+                _ => {}
+            }
+        }
+        let n = this.pending.len().min(buf.remaining());
+        buf.put_slice(&this.pending[..n]);
+        this.pending.drain(..n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for Ble {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let data = &buf[..buf.len().min(this.chunk)];
+        let mut pdu = vec![WRITE_CMD];
+        pdu.extend_from_slice(&this.write_handle.to_le_bytes());
+        pdu.extend_from_slice(data);
+        loop {
+            let mut guard = ready!(this.fd.poll_write_ready(cx))?;
+            match guard.try_io(|fd| send(fd.get_ref(), &pdu)) {
+                Ok(r) => return Poll::Ready(r.map(|_| data.len())),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gatt() {
+        assert_eq!(
+            parse_device("C8:2B:96:01:02:03"),
+            Ok(Device {
+                bdaddr: [0x03, 0x02, 0x01, 0x96, 0x2b, 0xc8],
+                addr_type: BDADDR_LE_PUBLIC
+            })
+        );
+        assert_eq!(
+            parse_device("c8:2b:96:01:02:03/random").map(|d| d.addr_type),
+            Ok(BDADDR_LE_RANDOM)
+        );
+        assert!(parse_device("c8:2b:96:01:02").is_err());
+        assert!(parse_device("c8:2b:96:01:02:03/other").is_err());
+        assert_eq!(mem::size_of::<SockaddrL2>(), 14);
+
+        // Two characteristics of the Nordic UART service.
+        let mut rsp = vec![READ_BY_TYPE_RSP, 21];
+        for (handle, properties, uuid) in [(0x0010u16, 0x0c, NUS_RX), (0x0012, PROP_NOTIFY, NUS_TX)]
+        {
+            rsp.extend_from_slice(&handle.to_le_bytes());
+            rsp.push(properties);
+            rsp.extend_from_slice(&(handle + 1).to_le_bytes());
+            rsp.extend_from_slice(&uuid.to_le_bytes());
+        }
+        let chars = parse_characteristics(&rsp);
+        assert_eq!(chars.len(), 2);
+        assert_eq!(chars[1].value_handle, 0x0013);
+        let (write, notify) = find_serial(&chars).unwrap();
+        assert_eq!((write.value_handle, notify.value_handle), (0x0011, 0x0013));
+
+        // A radio's HM-10 style module: one characteristic both ways.
+        let rsp = [
+            READ_BY_TYPE_RSP,
+            7,
+            0x20,
+            0x00,
+            0x16,
+            0x21,
+            0x00,
+            0xe1,
+            0xff,
+        ];
+        let chars = parse_characteristics(&rsp);
+        assert_eq!(chars[0].uuid, HM10_DATA);
+        let (write, notify) = find_serial(&chars).unwrap();
+        assert_eq!((write.value_handle, notify.value_handle), (0x0021, 0x0021));
+        assert_eq!(find_serial(&chars[..0]), None);
+
+        let rsp = [FIND_INFORMATION_RSP, 1, 0x22, 0x00, 0x02, 0x29];
+        assert_eq!(
+            parse_information(&rsp),
+            [(0x0022, uuid16(CLIENT_CHARACTERISTIC_CONFIG))]
+        );
+
+        assert!(answers(&[WRITE_RSP], WRITE_REQ));
+        assert!(answers(
+            &[ERROR_RSP, WRITE_REQ, 0x22, 0x00, 0x03],
+            WRITE_REQ
+        ));
+        assert!(!answers(&[HANDLE_VALUE_NTF, 0x21, 0x00], WRITE_REQ));
+        assert_eq!(
+            att_error(&[ERROR_RSP, READ_BY_TYPE_REQ, 1, 0, ATTRIBUTE_NOT_FOUND]),
+            Some(ATTRIBUTE_NOT_FOUND)
+        );
+    }
+}
@@ -0,0 +1,200 @@
+//! Capture of the serial traffic to a pcapng file (`--capture`), for
+//! analysis in Wireshark.
+//!
+//! Each frame is a packet, with its direction, and a CRC error flag if its
+//! checksum is wrong; bytes received between frames are packets with the
+//! comment "garbage". Timestamps are in µs. The link type is `USER0`
+//! (147): in Wireshark, have it decoded by a CRSF dissector in the
+//! DLT_USER preferences.
+//!
+//! [`received`] reads the received bytes back from such a capture, for
+//! `--replay`.
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use telemetry_lib::pcap::{
+    self, BYTE_ORDER_MAGIC, CRC_ERROR, ENHANCED_PACKET, EPB_FLAGS, IF_NAME, INBOUND,
+    INTERFACE_DESCRIPTION, LINKTYPE_USER0, OPT_COMMENT, OPT_END, OUTBOUND, SECTION_HEADER,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// From the radio.
+    Rx,
+    /// To the radio.
+    Tx,
+}
+
+fn option(block: &mut Vec<u8>, code: u16, value: &[u8]) {
+    block.extend_from_slice(&code.to_le_bytes());
+    block.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    block.extend_from_slice(value);
+    block.resize(block.len().next_multiple_of(4), 0);
+}
+
+/// Frame `body` as a block of `kind`.
+fn block(kind: u32, body: &[u8]) -> Vec<u8> {
+    let len = (12 + body.len()) as u32;
+    let mut block = Vec::with_capacity(len as usize);
+    block.extend_from_slice(&kind.to_le_bytes());
+    block.extend_from_slice(&len.to_le_bytes());
+    block.extend_from_slice(body);
+    block.extend_from_slice(&len.to_le_bytes());
+    block
+}
+
+pub struct Capture<W: Write = File> {
+    out: W,
+}
+
+impl Capture {
+    pub fn create(path: impl AsRef<Path>, port: &str) -> io::Result<Self> {
+        Self::new(File::create(path)?, port)
+    }
+}
+
+impl<W: Write> Capture<W> {
+    /// Start a capture of the traffic on `port`.
+    pub fn new(mut out: W, port: &str) -> io::Result<Self> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        body.extend_from_slice(&1u16.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        // Section length unknown.
+        body.extend_from_slice(&(-1i64).to_le_bytes());
+        out.write_all(&block(SECTION_HEADER, &body))?;
+
+        let mut body = Vec::new();
+        body.extend_from_slice(&(LINKTYPE_USER0 as u16).to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        // No snap length.
+        body.extend_from_slice(&0u32.to_le_bytes());
+        option(&mut body, IF_NAME, port.as_bytes());
+        option(&mut body, OPT_END, &[]);
+        out.write_all(&block(INTERFACE_DESCRIPTION, &body))?;
+        out.flush()?;
+        Ok(Self { out })
+    }
+
+    fn packet(&mut self, data: &[u8], flags: u32, comment: Option<&str>) -> io::Result<()> {
+        let us = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+        let mut body = Vec::new();
+        // Interface 0.
+        body.extend_from_slice(&0u32.to_le_bytes());
+        body.extend_from_slice(&((us >> 32) as u32).to_le_bytes());
+        body.extend_from_slice(&(us as u32).to_le_bytes());
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(data);
+        body.resize(body.len().next_multiple_of(4), 0);
+        option(&mut body, EPB_FLAGS, &flags.to_le_bytes());
+        if let Some(comment) = comment {
+            option(&mut body, OPT_COMMENT, comment.as_bytes());
+        }
+        option(&mut body, OPT_END, &[]);
+        self.out.write_all(&block(ENHANCED_PACKET, &body))?;
+        self.out.flush()
+    }
+
+    /// A frame, `valid` unless its checksum is wrong.
+    pub fn frame(&mut self, direction: Direction, data: &[u8], valid: bool) -> io::Result<()> {
+        let mut flags = match direction {
+            Direction::Rx => INBOUND,
+            Direction::Tx => OUTBOUND,
+        };
+        if !valid {
+            flags |= CRC_ERROR;
+        }
+        self.packet(data, flags, None)
+    }
+
+    /// Received bytes that are not part of a frame.
+    pub fn garbage(&mut self, data: &[u8]) -> io::Result<()> {
+        self.packet(data, INBOUND, Some("garbage"))
+    }
+}
+
+/// The packets received in a capture written by [`Capture`]: time (s)
+/// and data.
+pub fn received(data: &[u8]) -> io::Result<Vec<(f64, &[u8])>> {
+    Ok(pcap::packets(data)?
+        .into_iter()
+        .filter(|packet| packet.link_type == LINKTYPE_USER0 && !packet.outbound())
+        .map(|packet| (packet.time, packet.data))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(data: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(data[at..at + 4].try_into().unwrap())
+    }
+
+    /// The blocks in `data`: type and body.
+    fn blocks(mut data: &[u8]) -> Vec<(u32, &[u8])> {
+        let mut blocks = Vec::new();
+        while !data.is_empty() {
+            let len = word(data, 4) as usize;
+            assert_eq!(len % 4, 0);
+            assert_eq!(word(data, len - 4) as usize, len);
+            blocks.push((word(data, 0), &data[8..len - 4]));
+            data = &data[len..];
+        }
+        blocks
+    }
+
+    #[test]
+    fn pcapng() {
+        let mut capture = Capture::new(Vec::new(), "/dev/ttyUSB0").unwrap();
+        capture
+            .frame(Direction::Rx, &[0xc8, 2, 0x16, 0xaa], true)
+            .unwrap();
+        capture
+            .frame(Direction::Tx, &[0xc8, 3, 0x14, 0, 0], false)
+            .unwrap();
+        capture.garbage(&[0x55]).unwrap();
+
+        let blocks = blocks(&capture.out);
+        let kinds: Vec<u32> = blocks.iter().map(|&(kind, _)| kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                SECTION_HEADER,
+                INTERFACE_DESCRIPTION,
+                ENHANCED_PACKET,
+                ENHANCED_PACKET,
+                ENHANCED_PACKET
+            ]
+        );
+        assert_eq!(blocks[0].1[..4], BYTE_ORDER_MAGIC.to_le_bytes());
+        assert_eq!(blocks[1].1[..2], (LINKTYPE_USER0 as u16).to_le_bytes());
+        assert_eq!(&blocks[1].1[12..24], b"/dev/ttyUSB0");
+
+        // Lengths, data padded to 4 bytes, and the flags option.
+        let rx = blocks[2].1;
+        assert_eq!(rx[12..20], [4, 0, 0, 0, 4, 0, 0, 0]);
+        assert_eq!(rx[20..24], [0xc8, 2, 0x16, 0xaa]);
+        assert_eq!(rx[24..32], [2, 0, 4, 0, 1, 0, 0, 0]);
+        let tx = blocks[3].1;
+        assert_eq!(tx[20..28], [0xc8, 3, 0x14, 0, 0, 0, 0, 0]);
+        assert_eq!(tx[28..36], [2, 0, 4, 0, 2, 0, 0, 1]);
+        let garbage = blocks[4].1;
+        assert_eq!(&garbage[36..43], b"garbage");
+
+        // The frame from the radio, and the garbage.
+        let packets = received(&capture.out).unwrap();
+        let data: Vec<&[u8]> = packets.iter().map(|&(_, data)| data).collect();
+        assert_eq!(data, [&[0xc8, 2, 0x16, 0xaa][..], &[0x55]]);
+        assert!(packets[0].0 <= packets[1].0);
+        assert!(received(&capture.out[..40]).is_err());
+        assert!(received(b"not a capture").is_err());
+    }
+}
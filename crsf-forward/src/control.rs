@@ -0,0 +1,199 @@
+//! Control socket (`--control PATH`), and its client (`crsf-forward ctl`).
+//!
+//! A Unix socket that takes a command per line and answers each with a
+//! line of JSON, for a look at a running bridge without the metrics
+//! exporter:
+//!
+//! - `status`: the state of the serial link, frame rates and error counts,
+//!   as served at /healthz;
+//! - `pause` and `resume`: hold back the telemetry from the sim, or send it
+//!   again;
+//! - `log FILTER`: set the log filter, as in `RUST_LOG` (e.g. `debug`, or
+//!   `crsf_forward=trace,zenoh=warn`).
+//!
+//! Errors are answered with `{"error": ...}`. There are no Unix sockets on
+//! Windows, so no control socket either.
+
+use std::io;
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+use std::path::Path;
+use std::sync::{OnceLock, PoisonError, RwLock};
+use std::time::Instant;
+
+use log::{Log, Metadata, Record, info};
+#[cfg(unix)]
+use log::{debug, warn};
+use serde_json::json;
+#[cfg(unix)]
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+
+use crate::health::{self, Shared};
+
+/// An env_logger whose filter can be replaced at runtime.
+struct Logger(RwLock<env_logger::Logger>);
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let logger = self.0.read().unwrap_or_else(PoisonError::into_inner);
+        logger.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        let logger = self.0.read().unwrap_or_else(PoisonError::into_inner);
+        logger.log(record)
+    }
+
+    fn flush(&self) {
+        let logger = self.0.read().unwrap_or_else(PoisonError::into_inner);
+        logger.flush()
+    }
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+/// Log as env_logger, configured by `RUST_LOG`.
+pub fn init_logging() {
+    let logger = env_logger::Builder::from_default_env().build();
+    log::set_max_level(logger.filter());
+    let _ = log::set_logger(LOGGER.get_or_init(|| Logger(RwLock::new(logger))));
+}
+
+#[cfg_attr(not(unix), allow(dead_code))]
+fn set_log_filter(filter: &str) {
+    let logger = env_logger::Builder::new().parse_filters(filter).build();
+    log::set_max_level(logger.filter());
+    if let Some(Logger(current)) = LOGGER.get() {
+        *current.write().unwrap_or_else(PoisonError::into_inner) = logger;
+    }
+}
+
+/// Answer a command line.
+#[cfg_attr(not(unix), allow(dead_code))]
+fn execute(line: &str, status: &Shared) -> serde_json::Value {
+    let mut words = line.split_whitespace();
+    match (words.next(), words.next(), words.next()) {
+        (Some("status"), None, _) => health::update(status, |s| s.report(Instant::now())),
+        (Some("pause"), None, _) => {
+            info!("Telemetry paused");
+            health::update(status, |s| s.pause(true));
+            json!({"paused": true})
+        }
+        (Some("resume"), None, _) => {
+            info!("Telemetry resumed");
+            health::update(status, |s| s.pause(false));
+            json!({"paused": false})
+        }
+        (Some("log"), Some(filter), None) => {
+            set_log_filter(filter);
+            info!("Log filter set to {}", filter);
+            json!({"log": filter})
+        }
+        _ => json!({"error": format!("unknown command `{}`", line.trim())}),
+    }
+}
+
+/// Listen at `path`, replacing a socket left behind there.
+#[cfg(unix)]
+fn bind(path: &Path) -> io::Result<UnixListener> {
+    if std::fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+        std::fs::remove_file(path)?;
+    }
+    UnixListener::bind(path)
+}
+
+#[cfg(unix)]
+async fn handle(socket: UnixStream, status: Shared) -> io::Result<()> {
+    let (reader, mut writer) = socket.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut answer = execute(&line, &status).to_string();
+        answer.push('\n');
+        writer.write_all(answer.as_bytes()).await?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn serve(listener: UnixListener, status: Shared) {
+    loop {
+        match listener.accept().await {
+            Ok((socket, _)) => {
+                let status = status.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle(socket, status).await {
+                        debug!("Control client: {}", e);
+                    }
+                });
+            }
+            Err(e) => warn!("Control socket accept: {}", e),
+        }
+    }
+}
+
+/// Serve the control socket at `path`, in the background.
+#[cfg(unix)]
+pub fn listen(path: &Path, status: Shared) -> io::Result<()> {
+    let listener = bind(path)?;
+    tokio::spawn(serve(listener, status));
+    Ok(())
+}
+
+/// Send `command` to the control socket at `path`, and print the answer.
+#[cfg(unix)]
+pub async fn send(path: &Path, command: &[String]) -> io::Result<()> {
+    let mut socket = UnixStream::connect(path).await?;
+    socket
+        .write_all(format!("{}\n", command.join(" ")).as_bytes())
+        .await?;
+    let mut answer = String::new();
+    BufReader::new(socket).read_line(&mut answer).await?;
+    print!("{}", answer);
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn listen(_path: &Path, _status: Shared) -> io::Result<()> {
+    Err(unsupported())
+}
+
+#[cfg(not(unix))]
+pub async fn send(_path: &Path, _command: &[String]) -> io::Result<()> {
+    Err(unsupported())
+}
+
+#[cfg(not(unix))]
+fn unsupported() -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, "no Unix sockets")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use crate::health::Status;
+
+    #[test]
+    fn commands() {
+        let status: Shared = Arc::new(Mutex::new(Status::new(
+            Duration::from_secs(5),
+            Instant::now(),
+        )));
+        assert_eq!(execute("status", &status)["serial"], "closed");
+        assert_eq!(execute("pause", &status), json!({"paused": true}));
+        assert!(health::update(&status, |s| s.paused()));
+        assert_eq!(execute("status", &status)["telemetry_paused"], true);
+        assert_eq!(execute(" resume ", &status), json!({"paused": false}));
+        assert!(!health::update(&status, |s| s.paused()));
+        assert!(execute("pause now", &status)["error"].is_string());
+        assert!(execute("log", &status)["error"].is_string());
+        assert!(execute("reboot", &status)["error"].is_string());
+    }
+}
@@ -0,0 +1,77 @@
+//! Decimation of the RC frames from the radio (`--rc-rate`).
+//!
+//! Radios send RC frames at up to 1000 Hz, more than most sims read. The
+//! decimator passes the first frame of every period and drops the others,
+//! so that the frames that get through are not delayed. It keeps to the
+//! rate on average when the frames jitter, but doesn't catch up after a
+//! gap.
+
+use std::time::{Duration, Instant};
+
+/// Parse an RC rate, for use as a clap value parser.
+pub fn parse_rate(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(hz) if hz > 0.0 && hz.is_finite() => Ok(hz),
+        _ => Err(format!("invalid rate `{}`, must be above 0 Hz", s)),
+    }
+}
+
+#[derive(Debug)]
+pub struct Decimator {
+    period: Duration,
+    /// Start of the next period, if a frame was passed.
+    due: Option<Instant>,
+}
+
+impl Decimator {
+    pub fn new(hz: f64) -> Self {
+        Self {
+            period: Duration::from_secs_f64(1.0 / hz),
+            due: None,
+        }
+    }
+
+    /// Whether to pass a frame received at `now`.
+    pub fn pass(&mut self, now: Instant) -> bool {
+        if self.due.is_some_and(|due| now < due) {
+            return false;
+        }
+        self.due = Some(match self.due {
+            Some(due) if due + self.period > now => due + self.period,
+            _ => now + self.period,
+        });
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decimation() {
+        let start = Instant::now();
+        let at = |us: u64| start + Duration::from_micros(us);
+
+        // 500 Hz, jittering by 0.5 ms, to 100 Hz: every fifth frame.
+        let mut decimator = Decimator::new(100.0);
+        let passed: Vec<u64> = (0..500)
+            .map(|i| i * 2000 + [0, 500, 0, 250][i as usize % 4])
+            .filter(|&us| decimator.pass(at(us)))
+            .collect();
+        assert_eq!(passed.len(), 100);
+        assert_eq!(passed[..3], [0, 10_500, 20_000]);
+
+        // After a gap, the periods start from the next frame.
+        assert!(decimator.pass(at(2_000_000)));
+        assert!(!decimator.pass(at(2_005_000)));
+        assert!(decimator.pass(at(2_010_000)));
+
+        // Slower than the rate: every frame.
+        let mut decimator = Decimator::new(100.0);
+        assert!((0..50).all(|i| decimator.pass(at(i * 20_000))));
+
+        assert!(parse_rate("0").is_err());
+        assert_eq!(parse_rate("250"), Ok(250.0));
+    }
+}
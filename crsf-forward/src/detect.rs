@@ -0,0 +1,157 @@
+//! Finding the radio's serial port by USB vendor and product ID
+//! (`--port auto`).
+//!
+//! Radios and ELRS transmitter modules show up as ttyUSB* or ttyACM*
+//! depending on their USB-serial chip (a COM port on Windows,
+//! cu.usbserial-* or cu.usbmodem* on macOS), and the number depends on
+//! what else is plugged in. The ports are matched against a list of known IDs, in
+//! order: the first ID in the list that any port has wins.
+
+use tokio_serial::{SerialPortInfo, SerialPortType};
+
+/// A USB vendor and product ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UsbId {
+    pub vid: u16,
+    pub pid: u16,
+}
+
+/// IDs of the radios and USB-serial chips that carry CRSF, in order of
+/// preference.
+pub const KNOWN_IDS: &[UsbId] = &[
+    // EdgeTX/OpenTX radio
+    UsbId {
+        vid: 0x1209,
+        pid: 0x4f54,
+    },
+    // STM32 virtual COM port (radios, FCs)
+    UsbId {
+        vid: 0x0483,
+        pid: 0x5740,
+    },
+    // ESP32 USB serial
+    UsbId {
+        vid: 0x303a,
+        pid: 0x1001,
+    },
+    // CP210x, on ELRS modules
+    UsbId {
+        vid: 0x10c4,
+        pid: 0xea60,
+    },
+    // CH340, on ELRS modules
+    UsbId {
+        vid: 0x1a86,
+        pid: 0x7523,
+    },
+    // FTDI FT232R
+    UsbId {
+        vid: 0x0403,
+        pid: 0x6001,
+    },
+];
+
+/// Parse `VID:PID` in hex, for use as a clap value parser.
+pub fn parse_usb_id(s: &str) -> Result<UsbId, String> {
+    let (vid, pid) = s
+        .split_once(':')
+        .ok_or_else(|| format!("expected VID:PID, got `{}`", s))?;
+    let hex = |v: &str| u16::from_str_radix(v, 16).map_err(|e| format!("`{}`: {}", v, e));
+    Ok(UsbId {
+        vid: hex(vid)?,
+        pid: hex(pid)?,
+    })
+}
+
+/// The port with the first of `ids` that matches; among ports with the same
+/// ID, the first by name.
+pub fn find_port(ports: &[SerialPortInfo], ids: &[UsbId]) -> Option<String> {
+    ids.iter().find_map(|id| {
+        ports
+            .iter()
+            .filter(|p| {
+                matches!(&p.port_type, SerialPortType::UsbPort(usb)
+                    if usb.vid == id.vid && usb.pid == id.pid)
+            })
+            .map(|p| p.port_name.clone())
+            .min()
+    })
+}
+
+/// Scan the serial ports for one of `ids`.
+pub fn detect(ids: &[UsbId]) -> Result<String, String> {
+    let ports = tokio_serial::available_ports().map_err(|e| e.to_string())?;
+    find_port(&ports, ids).ok_or_else(|| {
+        let found: Vec<String> = ports
+            .iter()
+            .map(|p| match &p.port_type {
+                SerialPortType::UsbPort(usb) => {
+                    format!("{} ({:04x}:{:04x})", p.port_name, usb.vid, usb.pid)
+                }
+                _ => p.port_name.clone(),
+            })
+            .collect();
+        format!(
+            "no serial port with a known USB ID (found: {})",
+            if found.is_empty() {
+                "none".to_string()
+            } else {
+                found.join(", ")
+            }
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_serial::UsbPortInfo;
+
+    fn usb(name: &str, vid: u16, pid: u16) -> SerialPortInfo {
+        SerialPortInfo {
+            port_name: name.to_string(),
+            port_type: SerialPortType::UsbPort(UsbPortInfo {
+                vid,
+                pid,
+                serial_number: None,
+                manufacturer: None,
+                product: None,
+            }),
+        }
+    }
+
+    #[test]
+    fn detection() {
+        assert_eq!(
+            parse_usb_id("10C4:ea60"),
+            Ok(UsbId {
+                vid: 0x10c4,
+                pid: 0xea60
+            })
+        );
+        assert!(parse_usb_id("10c4").is_err());
+        assert!(parse_usb_id("10c4:xyz").is_err());
+
+        let ids = KNOWN_IDS;
+        let ports = [
+            SerialPortInfo {
+                port_name: "/dev/ttyS0".to_string(),
+                port_type: SerialPortType::Unknown,
+            },
+            usb("/dev/ttyUSB1", 0x1a86, 0x7523),
+            usb("/dev/ttyUSB0", 0x1a86, 0x7523),
+            usb("/dev/ttyACM0", 0x2341, 0x0043),
+        ];
+        assert_eq!(find_port(&ports, ids).as_deref(), Some("/dev/ttyUSB0"));
+        // A radio comes before a USB-serial chip.
+        let mut ports = ports.to_vec();
+        ports.push(usb("/dev/ttyACM1", 0x1209, 0x4f54));
+        assert_eq!(find_port(&ports, ids).as_deref(), Some("/dev/ttyACM1"));
+        let arduino = parse_usb_id("2341:0043").unwrap();
+        assert_eq!(
+            find_port(&ports, &[arduino]).as_deref(),
+            Some("/dev/ttyACM0")
+        );
+        assert_eq!(find_port(&ports[..1], ids), None);
+    }
+}
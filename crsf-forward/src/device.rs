@@ -0,0 +1,107 @@
+//! Answers to device pings, as a flight controller.
+//!
+//! A radio pings the devices on the link to list them, and keeps pinging
+//! while one it has seen doesn't answer. The bridge takes the place of the
+//! flight controller on the link, so it answers pings to the flight
+//! controller and broadcast pings with its device info, without parameters.
+
+use telemetry_lib::crsf::{self, PacketType, device_address};
+
+/// The name in the device list of the radio.
+pub const NAME: &str = "Liftoff Bridge";
+
+/// The device that sent a ping for the flight controller, if `frame` is
+/// one.
+pub fn ping_origin(frame: &[u8]) -> Option<u8> {
+    match *frame {
+        [_, _, frame_type, destination, origin, _]
+            if frame_type == PacketType::DevicePing as u8
+                && matches!(
+                    destination,
+                    device_address::BROADCAST | device_address::FLIGHT_CONTROLLER
+                ) =>
+        {
+            Some(origin)
+        }
+        _ => None,
+    }
+}
+
+/// The version of crsf-forward as a firmware ID: major, minor and patch in
+/// the lower three bytes.
+fn firmware_id() -> u32 {
+    [
+        env!("CARGO_PKG_VERSION_MAJOR"),
+        env!("CARGO_PKG_VERSION_MINOR"),
+        env!("CARGO_PKG_VERSION_PATCH"),
+    ]
+    .iter()
+    .fold(0, |id, part| {
+        (id << 8) | part.parse::<u32>().unwrap_or(0) & 0xff
+    })
+}
+
+/// The device info frame to answer a ping from `origin`.
+pub fn info(origin: u8) -> Vec<u8> {
+    let mut frame = vec![
+        device_address::FLIGHT_CONTROLLER,
+        0,
+        PacketType::DeviceInfo as u8,
+        origin,
+        device_address::FLIGHT_CONTROLLER,
+    ];
+    frame.extend_from_slice(NAME.as_bytes());
+    frame.push(0);
+    // Serial number and hardware ID.
+    frame.extend_from_slice(&[0; 8]);
+    frame.extend_from_slice(&firmware_id().to_be_bytes());
+    // No parameters, parameter protocol version 0.
+    frame.extend_from_slice(&[0, 0]);
+    frame[1] = (frame.len() - 1) as u8;
+    frame.push(crsf::calc_crc8(&frame[2..]));
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ping() {
+        let ping = |destination| {
+            let mut frame = vec![
+                device_address::FLIGHT_CONTROLLER,
+                4,
+                PacketType::DevicePing as u8,
+                destination,
+                device_address::RADIO_TRANSMITTER,
+            ];
+            frame.push(crsf::calc_crc8(&frame[2..]));
+            frame
+        };
+        assert_eq!(
+            ping_origin(&ping(device_address::BROADCAST)),
+            Some(device_address::RADIO_TRANSMITTER)
+        );
+        assert_eq!(
+            ping_origin(&ping(device_address::FLIGHT_CONTROLLER)),
+            Some(device_address::RADIO_TRANSMITTER)
+        );
+        assert_eq!(ping_origin(&ping(device_address::VTX)), None);
+
+        let info = info(device_address::RADIO_TRANSMITTER);
+        assert!(crsf::frame_check_crc(&info));
+        assert_eq!(
+            info[2..5],
+            [
+                PacketType::DeviceInfo as u8,
+                device_address::RADIO_TRANSMITTER,
+                device_address::FLIGHT_CONTROLLER
+            ]
+        );
+        let (name, rest) = info[5..].split_at(NAME.len());
+        assert_eq!(name, NAME.as_bytes());
+        assert_eq!(rest.len(), 1 + 12 + 2 + 1);
+        assert_eq!(rest[9..13], firmware_id().to_be_bytes());
+    }
+}
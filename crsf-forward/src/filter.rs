@@ -0,0 +1,98 @@
+//! Filtering of the CRSF frames by type, both ways (`--allow-types`,
+//! `--deny-types`).
+//!
+//! A frame type is given as its type byte, or as the name of a
+//! [`PacketType`] in any case, with or without underscores: `0x2d`,
+//! `config_write` and `ConfigWrite` are the same type. The frames that
+//! crsf-forward generates itself (link statistics, replies to the radio)
+//! are not filtered.
+
+use telemetry_lib::crsf::PacketType;
+
+/// Lower case, without separators.
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, '_' | '-'))
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Parse a frame type, for use as a clap value parser.
+pub fn parse_type(s: &str) -> Result<u8, String> {
+    let number = match s.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    };
+    if let Some(frame_type) = number {
+        return Ok(frame_type);
+    }
+    let name = normalize(s);
+    (0..=u8::MAX)
+        .find(|&t| PacketType::try_from(t).is_ok_and(|p| normalize(&format!("{:?}", p)) == name))
+        .ok_or_else(|| format!("unknown frame type `{}`", s))
+}
+
+/// The types of CRSF frames to forward.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TypeFilter {
+    /// Bit per type.
+    blocked: [u64; 4],
+}
+
+impl TypeFilter {
+    /// Forward only the `allow`ed types if any are given, and none of the
+    /// `deny`ed types.
+    pub fn new(allow: &[u8], deny: &[u8]) -> Self {
+        let mut filter = Self::default();
+        for frame_type in 0..=u8::MAX {
+            let allowed = allow.is_empty() || allow.contains(&frame_type);
+            if !allowed || deny.contains(&frame_type) {
+                filter.blocked[frame_type as usize / 64] |= 1 << (frame_type % 64);
+            }
+        }
+        filter
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocked == [0; 4]
+    }
+
+    /// Whether to forward a CRSF `frame`.
+    pub fn pass(&self, frame: &[u8]) -> bool {
+        let Some(&frame_type) = frame.get(2) else {
+            return true;
+        };
+        self.blocked[frame_type as usize / 64] & 1 << (frame_type % 64) == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter() {
+        let write = PacketType::ConfigWrite as u8;
+        assert_eq!(parse_type("0x2d"), Ok(write));
+        assert_eq!(parse_type("45"), Ok(write));
+        assert_eq!(parse_type("config_write"), Ok(write));
+        assert_eq!(parse_type("ConfigWrite"), Ok(write));
+        assert!(parse_type("config_erase").is_err());
+        assert!(parse_type("0x100").is_err());
+
+        let frame = |frame_type: u8| [0xc8, 2, frame_type, 0];
+        let rc = PacketType::RcChannelsPacked as u8;
+        assert!(TypeFilter::default().is_empty());
+
+        let deny = TypeFilter::new(&[], &[write]);
+        assert!(!deny.pass(&frame(write)));
+        assert!(deny.pass(&frame(rc)));
+        assert!(deny.pass(&frame(0xff)));
+
+        let allow = TypeFilter::new(&[rc, 0xff], &[0xff]);
+        assert!(allow.pass(&frame(rc)));
+        assert!(!allow.pass(&frame(write)));
+        assert!(!allow.pass(&frame(0xff)));
+        assert!(!allow.is_empty());
+    }
+}
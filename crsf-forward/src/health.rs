@@ -0,0 +1,318 @@
+//! Health of the serial link, for supervisors: systemd watchdog pings and
+//! `GET /healthz` (`--health-bind`).
+//!
+//! The link counts as wedged when the port is open but nothing was read
+//! from it for `--serial-timeout`. Then the watchdog is no longer pinged,
+//! so that systemd restarts crsf-forward when run with `WatchdogSec=`, and
+//! /healthz answers 503. While the port is closed and being reopened, the
+//! bridge is not wedged.
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use log::{debug, warn};
+use serde_json::json;
+use telemetry_lib::systemd;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Longest request header accepted.
+const MAX_REQUEST: usize = 8192;
+/// Shortest window over which a frame rate is measured.
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+pub type Shared = Arc<Mutex<Status>>;
+
+/// Update the status, also if another thread panicked while holding it.
+pub fn update<T>(status: &Shared, f: impl FnOnce(&mut Status) -> T) -> T {
+    f(&mut status.lock().unwrap_or_else(PoisonError::into_inner))
+}
+
+/// A count of frames, and their rate over the last window.
+#[derive(Debug)]
+struct Rate {
+    total: u64,
+    window: Instant,
+    count: u64,
+    hz: f64,
+}
+
+impl Rate {
+    fn new(now: Instant) -> Self {
+        Self {
+            total: 0,
+            window: now,
+            count: 0,
+            hz: 0.0,
+        }
+    }
+
+    fn roll(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.window);
+        if elapsed >= RATE_WINDOW {
+            self.hz = self.count as f64 / elapsed.as_secs_f64();
+            self.window = now;
+            self.count = 0;
+        }
+    }
+
+    fn add(&mut self, now: Instant) {
+        self.roll(now);
+        self.count += 1;
+        self.total += 1;
+    }
+
+    fn hz(&mut self, now: Instant) -> f64 {
+        self.roll(now);
+        self.hz
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Closed,
+    Open,
+    /// Open, but nothing was read from it for the timeout.
+    Wedged,
+}
+
+impl State {
+    fn label(self) -> &'static str {
+        match self {
+            State::Closed => "closed",
+            State::Open => "open",
+            State::Wedged => "wedged",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Status {
+    timeout: Duration,
+    /// The port, while it is open.
+    port: Option<String>,
+    /// When the port was opened, or bytes were last read from it.
+    last_read: Instant,
+    rx: Rate,
+    tx: Rate,
+    crc_errors: u64,
+    garbage: u64,
+    /// Whether the telemetry from the sim is held back (`pause` on the
+    /// control socket).
+    paused: bool,
+}
+
+impl Status {
+    pub fn new(timeout: Duration, now: Instant) -> Self {
+        Self {
+            timeout,
+            port: None,
+            last_read: now,
+            rx: Rate::new(now),
+            tx: Rate::new(now),
+            crc_errors: 0,
+            garbage: 0,
+            paused: false,
+        }
+    }
+
+    pub fn opened(&mut self, port: &str, now: Instant) {
+        self.port = Some(port.to_string());
+        self.last_read = now;
+    }
+
+    pub fn closed(&mut self) {
+        self.port = None;
+    }
+
+    /// Bytes were read from the port.
+    pub fn read(&mut self, now: Instant) {
+        self.last_read = now;
+    }
+
+    /// A frame was received, `valid` unless its checksum was wrong.
+    pub fn received(&mut self, now: Instant, valid: bool) {
+        if valid {
+            self.rx.add(now);
+        } else {
+            self.crc_errors += 1;
+        }
+    }
+
+    /// Bytes that are not part of a frame were received.
+    pub fn garbage(&mut self, len: usize) {
+        self.garbage += len as u64;
+    }
+
+    /// A frame was written to the radio.
+    pub fn sent(&mut self, now: Instant) {
+        self.tx.add(now);
+    }
+
+    /// Hold back the telemetry, from the control socket.
+    #[cfg_attr(not(unix), allow(dead_code))]
+    pub fn pause(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn state(&self, now: Instant) -> State {
+        if self.port.is_none() {
+            State::Closed
+        } else if now.saturating_duration_since(self.last_read) >= self.timeout {
+            State::Wedged
+        } else {
+            State::Open
+        }
+    }
+
+    /// The /healthz report.
+    pub fn report(&mut self, now: Instant) -> serde_json::Value {
+        let state = self.state(now);
+        json!({
+            "serial": state.label(),
+            "port": self.port,
+            "since_read_ms": self.port.as_ref()
+                .map(|_| now.saturating_duration_since(self.last_read).as_millis() as u64),
+            "rx": {"frames": self.rx.total, "rate_hz": self.rx.hz(now)},
+            "tx": {"frames": self.tx.total, "rate_hz": self.tx.hz(now)},
+            "crc_errors": self.crc_errors,
+            "garbage_bytes": self.garbage,
+            "telemetry_paused": self.paused,
+        })
+    }
+}
+
+/// Ping the systemd watchdog every `interval` while the link isn't wedged.
+pub async fn watchdog(status: Shared, interval: Duration) {
+    let mut timer = tokio::time::interval(interval);
+    let mut wedged = false;
+    loop {
+        timer.tick().await;
+        let state = update(&status, |s| s.state(Instant::now()));
+        if state == State::Wedged {
+            if !wedged {
+                warn!("Nothing read from the serial port; not pinging the watchdog");
+            }
+            wedged = true;
+            continue;
+        }
+        wedged = false;
+        if let Err(e) = systemd::notify("WATCHDOG=1") {
+            warn!("sd_notify WATCHDOG=1 failed: {}", e);
+        }
+    }
+}
+
+/// The path of an HTTP GET request.
+fn parse_request(req: &str) -> Option<&str> {
+    let mut first = req.lines().next()?.split_whitespace();
+    if first.next()? != "GET" {
+        return None;
+    }
+    first.next()
+}
+
+async fn handle(mut socket: TcpStream, addr: SocketAddr, status: Shared) -> std::io::Result<()> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = socket.read(&mut chunk).await?;
+        if n == 0 || buf.len() > MAX_REQUEST {
+            return Ok(());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    let req = String::from_utf8_lossy(&buf);
+    let (status, body) = match parse_request(&req) {
+        Some("/healthz") => {
+            let now = Instant::now();
+            let (state, report) = update(&status, |s| (s.state(now), s.report(now)));
+            let status = match state {
+                State::Wedged => "503 Service Unavailable",
+                State::Closed | State::Open => "200 OK",
+            };
+            (status, report.to_string())
+        }
+        Some(_) => ("404 Not Found", String::new()),
+        None => ("400 Bad Request", String::new()),
+    };
+    debug!("Health {} from {}", status, addr);
+    let response = format!(
+        "HTTP/1.1 {}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    socket.write_all(response.as_bytes()).await?;
+    socket.shutdown().await
+}
+
+/// Serve /healthz on `listener`.
+pub async fn serve(listener: TcpListener, status: Shared) {
+    loop {
+        match listener.accept().await {
+            Ok((socket, addr)) => {
+                let status = status.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle(socket, addr, status).await {
+                        debug!("Health client {}: {}", addr, e);
+                    }
+                });
+            }
+            Err(e) => warn!("Health server accept: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let mut status = Status::new(Duration::from_secs(5), start);
+        assert_eq!(status.state(at(60_000)), State::Closed);
+
+        status.opened("/dev/ttyACM0", at(1000));
+        assert_eq!(status.state(at(5999)), State::Open);
+        assert_eq!(status.state(at(6000)), State::Wedged);
+        // 100 Hz for 2 s, with a CRC error.
+        for i in 0..200 {
+            status.read(at(2000 + i * 10));
+            status.received(at(2000 + i * 10), i != 50);
+        }
+        status.garbage(3);
+        assert_eq!(status.state(at(6000)), State::Open);
+
+        let report = status.report(at(4000));
+        assert_eq!(report["serial"], "open");
+        assert_eq!(report["port"], "/dev/ttyACM0");
+        assert_eq!(report["since_read_ms"], 10);
+        assert_eq!(report["rx"]["frames"], 199);
+        let hz = report["rx"]["rate_hz"].as_f64().unwrap();
+        assert!((98.0..=100.0).contains(&hz), "{}", hz);
+        assert_eq!(report["crc_errors"], 1);
+        assert_eq!(report["garbage_bytes"], 3);
+        assert_eq!(report["telemetry_paused"], false);
+
+        status.closed();
+        assert_eq!(status.state(at(60_000)), State::Closed);
+        assert!(status.report(at(60_000))["since_read_ms"].is_null());
+
+        assert_eq!(
+            parse_request("GET /healthz HTTP/1.1\r\nHost: x\r\n\r\n"),
+            Some("/healthz")
+        );
+        assert_eq!(parse_request("POST /healthz HTTP/1.1\r\n\r\n"), None);
+    }
+}
@@ -0,0 +1,154 @@
+//! Optional link impairments (`--inject-*`), for testing how the sim feels
+//! over a poor link and how the failsafes downstream behave.
+//!
+//! Frames in each direction go through a delay line that drops a share of
+//! them and delays the rest by a fixed latency plus a random jitter. Frames
+//! stay in order, as on a serial link: a frame is never released before the
+//! one ahead of it.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ImpairConfig {
+    /// Added delay.
+    pub latency: Duration,
+    /// Largest random deviation from `latency`, either way.
+    pub jitter: Duration,
+    /// Share of frames dropped, 0 to 1.
+    pub loss: f64,
+    /// Seed for the random numbers, for reproducible runs.
+    pub seed: Option<u64>,
+}
+
+impl ImpairConfig {
+    pub fn is_enabled(&self) -> bool {
+        !self.latency.is_zero() || !self.jitter.is_zero() || self.loss > 0.0
+    }
+}
+
+/// Parse a loss percentage, for use as a clap value parser.
+pub fn parse_loss(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(pct) if (0.0..=100.0).contains(&pct) => Ok(pct),
+        _ => Err(format!("invalid loss `{}`, must be 0 to 100 %", s)),
+    }
+}
+
+#[derive(Debug)]
+pub struct DelayLine<T> {
+    config: ImpairConfig,
+    rng: SmallRng,
+    /// Frames with their release time, in order.
+    queue: VecDeque<(Instant, T)>,
+}
+
+impl<T> DelayLine<T> {
+    /// A delay line for direction number `stream`: directions get
+    /// different random numbers from the same seed.
+    pub fn new(config: ImpairConfig, stream: u64) -> Self {
+        let rng = match config.seed {
+            Some(seed) => SmallRng::seed_from_u64(seed.wrapping_add(stream)),
+            None => SmallRng::from_os_rng(),
+        };
+        Self {
+            config,
+            rng,
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Queue `item`, received at `now`; false if it was dropped.
+    pub fn push(&mut self, item: T, now: Instant) -> bool {
+        if !self.config.is_enabled() {
+            self.queue.push_back((now, item));
+            return true;
+        }
+        if self.rng.random::<f64>() < self.config.loss {
+            return false;
+        }
+        let jitter = self.config.jitter.as_secs_f64();
+        let delay = self.config.latency.as_secs_f64() + self.rng.random_range(-jitter..=jitter);
+        let mut due = now + Duration::from_secs_f64(delay.max(0.0));
+        if let Some(&(last, _)) = self.queue.back() {
+            due = due.max(last);
+        }
+        self.queue.push_back((due, item));
+        true
+    }
+
+    /// The next item due at `now`, if any.
+    pub fn pop(&mut self, now: Instant) -> Option<T> {
+        if self.queue.front().is_some_and(|&(due, _)| due <= now) {
+            self.queue.pop_front().map(|(_, item)| item)
+        } else {
+            None
+        }
+    }
+
+    /// When the next item is due.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.queue.front().map(|&(due, _)| due)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_line() {
+        let t0 = Instant::now();
+        let ms = |n| t0 + Duration::from_millis(n);
+
+        // Disabled: everything is due at once.
+        let mut line = DelayLine::new(ImpairConfig::default(), 0);
+        assert!(line.push(1, t0));
+        assert_eq!(line.pop(t0), Some(1));
+        assert_eq!(line.pop(t0), None);
+        assert_eq!(line.next_due(), None);
+
+        let config = ImpairConfig {
+            latency: Duration::from_millis(50),
+            jitter: Duration::from_millis(20),
+            loss: 0.25,
+            seed: Some(7),
+        };
+        let mut line = DelayLine::new(config, 0);
+        let mut kept = Vec::new();
+        for n in 0..1000 {
+            if line.push(n, ms(n)) {
+                kept.push(n);
+            }
+        }
+        assert!((700..800).contains(&kept.len()), "{}", kept.len());
+        // In order, each between 30 and 70 ms late.
+        let mut released = Vec::new();
+        let mut now = t0;
+        while let Some(due) = line.next_due() {
+            now = due;
+            while let Some(n) = line.pop(now) {
+                let late = now.duration_since(ms(n));
+                assert!(late >= Duration::from_millis(30), "{} {:?}", n, late);
+                assert!(late <= Duration::from_millis(70), "{} {:?}", n, late);
+                released.push(n);
+            }
+        }
+        assert_eq!(released, kept);
+        assert!(now >= ms(999 + 30));
+
+        // The same seed gives the same losses.
+        let mut again = DelayLine::new(config, 0);
+        let kept_again: Vec<u64> = (0..1000).filter(|&n| again.push(n, ms(n))).collect();
+        assert_eq!(kept_again, kept);
+        let mut other = DelayLine::new(config, 1);
+        let kept_other: Vec<u64> = (0..1000).filter(|&n| other.push(n, ms(n))).collect();
+        assert_ne!(kept_other, kept);
+
+        assert_eq!(parse_loss("2.5"), Ok(2.5));
+        assert!(parse_loss("101").is_err());
+    }
+}
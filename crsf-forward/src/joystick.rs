@@ -0,0 +1,175 @@
+//! A virtual joystick driven by the radio directly (`--joystick`).
+//!
+//! With the sim on the same machine as the radio, this takes the place of
+//! crsf-joystick: the RC frames drive a uinput `CRSF Joystick` without
+//! going through zenoh and back. They still pass the injected impairments,
+//! and are still published unless `--joystick-only`. Unlike crsf-joystick,
+//! there is no mux with the autopilot, and the profiles of the mapping
+//! aren't switched. Like crsf-joystick, this is Linux only.
+
+use std::path::Path;
+
+#[cfg(target_os = "linux")]
+use crsf_joystick::mapping::{MappingConfig, NUM_CHANNELS};
+#[cfg(target_os = "linux")]
+use crsf_joystick::{AXIS_MID, Joystick, Output, preflight};
+#[cfg(target_os = "linux")]
+use log::{error, info};
+#[cfg(target_os = "linux")]
+use metrics::counter;
+#[cfg(target_os = "linux")]
+use telemetry_lib::crsf::{self, CrsfPacket};
+#[cfg(target_os = "linux")]
+use telemetry_lib::sbus;
+
+#[cfg(target_os = "linux")]
+pub struct LocalJoystick {
+    joystick: Joystick,
+    /// Whether the RC frames are SBUS rather than CRSF.
+    sbus: bool,
+    /// The last channels, which CRSF subset frames update in part.
+    channels: [u16; NUM_CHANNELS],
+}
+
+#[cfg(target_os = "linux")]
+impl LocalJoystick {
+    /// Create the joystick, with the channel mapping at `mapping` if any.
+    pub fn open(mapping: Option<&Path>, sbus: bool) -> Result<Self, String> {
+        let mapping = match mapping {
+            Some(path) => {
+                info!("Loading channel mapping from {}", path.display());
+                MappingConfig::load(path)
+                    .map_err(|e| format!("can't load {}: {}", path.display(), e))?
+            }
+            None => MappingConfig::default(),
+        };
+        if let Err(diag) = preflight::check(&preflight::UINPUT) {
+            error!("{}", diag);
+            return Err("can't create the joystick".to_string());
+        }
+        let joystick = Joystick::with_mapping(mapping.profile(None))
+            .map_err(|e| format!("can't create the joystick: {}", e))?;
+        Ok(Self {
+            joystick,
+            sbus,
+            channels: [AXIS_MID; NUM_CHANNELS],
+        })
+    }
+
+    /// Apply a valid RC frame from the radio.
+    pub fn frame(&mut self, frame: &[u8]) {
+        let Some(channels) = decode(self.sbus, frame, &mut self.channels) else {
+            return;
+        };
+        match self.joystick.update(channels) {
+            Ok(()) => counter!("crsf.joystick.updates").increment(1),
+            Err(e) => {
+                error!("Joystick update failed: {}", e);
+                counter!("crsf.joystick.errors").increment(1);
+            }
+        }
+    }
+
+    /// Center the sticks and release the buttons, as when the radio is
+    /// gone.
+    pub fn neutralize(&mut self) {
+        if let Err(e) = self.joystick.neutralize() {
+            error!("Failed to neutralize the joystick: {}", e);
+        }
+    }
+}
+
+/// No joystick without uinput; there is never one.
+#[cfg(not(target_os = "linux"))]
+pub enum LocalJoystick {}
+
+#[cfg(not(target_os = "linux"))]
+impl LocalJoystick {
+    pub fn open(_mapping: Option<&Path>, _sbus: bool) -> Result<Self, String> {
+        Err("--joystick is only supported on Linux".to_string())
+    }
+
+    pub fn frame(&mut self, _frame: &[u8]) {
+        match *self {}
+    }
+
+    pub fn neutralize(&mut self) {
+        match *self {}
+    }
+}
+
+/// Decode an RC frame into `state`, the last channels, and return them.
+/// SBUS frames in failsafe are dropped.
+#[cfg(target_os = "linux")]
+fn decode(
+    sbus: bool,
+    frame: &[u8],
+    state: &mut [u16; NUM_CHANNELS],
+) -> Option<[u16; NUM_CHANNELS]> {
+    if sbus {
+        let frame = sbus::parse_frame(frame)?;
+        if frame.failsafe {
+            return None;
+        }
+        *state = frame.channels;
+        return Some(*state);
+    }
+    match crsf::parse_packet_check(frame)? {
+        CrsfPacket::RcChannelsPacked(rc) => *state = rc.channels,
+        CrsfPacket::RcChannelsSubset(subset) => subset.merge_into(state),
+        _ => return None,
+    }
+    Some(*state)
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use telemetry_lib::crsf::{RcChannelsPacked, RcChannelsSubset, device_address};
+    use telemetry_lib::sbus::SbusFrame;
+
+    #[test]
+    fn decoding() {
+        let mut state = [AXIS_MID; NUM_CHANNELS];
+        let mut channels = [AXIS_MID; NUM_CHANNELS];
+        channels[2] = 172;
+        let packed = crsf::build_packet(
+            device_address::FLIGHT_CONTROLLER,
+            &CrsfPacket::RcChannelsPacked(RcChannelsPacked { channels }),
+        )
+        .unwrap();
+        assert_eq!(decode(false, &packed, &mut state), Some(channels));
+
+        // A subset frame changes only its channels.
+        let subset = crsf::build_packet(
+            device_address::FLIGHT_CONTROLLER,
+            &CrsfPacket::RcChannelsSubset(RcChannelsSubset {
+                first_channel: 4,
+                resolution: 11,
+                values: vec![0],
+            }),
+        )
+        .unwrap();
+        let decoded = decode(false, &subset, &mut state).unwrap();
+        assert_eq!(decoded[2], 172);
+        assert!(decoded[4] < AXIS_MID);
+        assert_eq!(decoded[5..], channels[5..]);
+
+        let mut bad = packed.clone();
+        *bad.last_mut().unwrap() ^= 0xff;
+        assert_eq!(decode(false, &bad, &mut state), None);
+
+        let mut sbus_frame = SbusFrame {
+            channels,
+            ch17: false,
+            ch18: false,
+            frame_lost: false,
+            failsafe: false,
+        };
+        let frame = sbus::build_frame(&sbus_frame).unwrap();
+        assert_eq!(decode(true, &frame, &mut state), Some(channels));
+        sbus_frame.failsafe = true;
+        let frame = sbus::build_frame(&sbus_frame).unwrap();
+        assert_eq!(decode(true, &frame, &mut state), None);
+    }
+}
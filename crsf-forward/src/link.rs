@@ -0,0 +1,182 @@
+//! Link statistics for the radio from the health of the bridge
+//! (`--link-stats`).
+//!
+//! Without an RF link, nothing tells the radio when the bridge degrades.
+//! Instead, the link quality figures are measured over the last second:
+//!
+//! - uplink LQ: the share of frames from the radio with a good CRC;
+//! - downlink LQ: the share of 100 ms slots in which telemetry arrived from
+//!   the sim.
+//!
+//! Signal strength and SNR are fixed at healthy values. The jitter of the
+//! telemetry arrivals is measured as well, for the metrics.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use telemetry_lib::crsf::{self, CrsfPacket, LinkStatistics, device_address};
+
+/// Period that the statistics cover.
+const WINDOW: Duration = Duration::from_secs(1);
+/// Downlink slot: telemetry is expected at least this often.
+const SLOT: Duration = Duration::from_millis(100);
+/// Fixed signal figures: -50 dBm, 10 dB.
+const RSSI: u8 = 50;
+const SNR: u8 = 10;
+
+/// Measured link quality.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quality {
+    /// Uplink (radio to sim) link quality, %.
+    pub uplink_lq: u8,
+    /// Downlink (sim to radio) link quality, %.
+    pub downlink_lq: u8,
+    /// Standard deviation of the interval between telemetry frames.
+    pub jitter: Duration,
+}
+
+#[derive(Debug, Default)]
+pub struct LinkHealth {
+    /// Frames from the radio, and whether their CRC was good.
+    rx: VecDeque<(Instant, bool)>,
+    /// Telemetry arrivals.
+    telemetry: VecDeque<Instant>,
+}
+
+impl LinkHealth {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn expire(&mut self, now: Instant) {
+        let start = now.checked_sub(WINDOW).unwrap_or(now);
+        while self.rx.front().is_some_and(|&(t, _)| t < start) {
+            self.rx.pop_front();
+        }
+        while self.telemetry.front().is_some_and(|&t| t < start) {
+            self.telemetry.pop_front();
+        }
+    }
+
+    /// A frame from the radio, `valid` if its CRC was good.
+    pub fn rx_frame(&mut self, now: Instant, valid: bool) {
+        self.rx.push_back((now, valid));
+        self.expire(now);
+    }
+
+    /// A telemetry frame from the sim.
+    pub fn telemetry(&mut self, now: Instant) {
+        self.telemetry.push_back(now);
+        self.expire(now);
+    }
+
+    pub fn quality(&mut self, now: Instant) -> Quality {
+        self.expire(now);
+        let valid = self.rx.iter().filter(|&&(_, valid)| valid).count();
+        let uplink_lq = (valid * 100).checked_div(self.rx.len()).unwrap_or(0);
+
+        let slots = (WINDOW.as_nanos() / SLOT.as_nanos()) as usize;
+        let mut seen = vec![false; slots];
+        for &t in &self.telemetry {
+            let age = now.saturating_duration_since(t);
+            let slot = (age.as_nanos() / SLOT.as_nanos()) as usize;
+            if let Some(seen) = seen.get_mut(slot) {
+                *seen = true;
+            }
+        }
+        let downlink_lq = seen.iter().filter(|&&s| s).count() * 100 / slots;
+
+        let intervals: Vec<f64> = self
+            .telemetry
+            .iter()
+            .zip(self.telemetry.iter().skip(1))
+            .map(|(a, b)| b.saturating_duration_since(*a).as_secs_f64())
+            .collect();
+        let jitter = if intervals.len() >= 2 {
+            let mean = intervals.iter().sum::<f64>() / intervals.len() as f64;
+            let var =
+                intervals.iter().map(|i| (i - mean).powi(2)).sum::<f64>() / intervals.len() as f64;
+            Duration::from_secs_f64(var.sqrt())
+        } else {
+            Duration::ZERO
+        };
+
+        Quality {
+            uplink_lq: uplink_lq as u8,
+            downlink_lq: downlink_lq as u8,
+            jitter,
+        }
+    }
+}
+
+/// A LinkStatistics frame reporting `quality`.
+pub fn frame(quality: &Quality) -> Vec<u8> {
+    // The fields of `LinkStatistics` in the order of the CRSF spec.
+    let stats = LinkStatistics {
+        // Uplink RSSI, antennas 1 and 2, -dBm.
+        snr: RSSI,
+        rf_mode: RSSI,
+        // Uplink LQ and SNR.
+        rssi: quality.uplink_lq,
+        lq: SNR,
+        // Active antenna, RF mode, TX power.
+        tx_power: 0,
+        tx_auc: 0,
+        rx_auc: 0,
+        // Downlink RSSI, LQ and SNR.
+        snr_rx: RSSI,
+        rssi_rx: quality.downlink_lq,
+        lq_rx: SNR,
+    };
+    crsf::build_packet(
+        device_address::FLIGHT_CONTROLLER,
+        &CrsfPacket::LinkStatistics(stats),
+    )
+    .expect("link statistics fit in a frame")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn link_quality() {
+        let t0 = Instant::now();
+        let ms = |n| t0 + Duration::from_millis(n);
+        let mut health = LinkHealth::new();
+        assert_eq!(
+            health.quality(t0),
+            Quality {
+                uplink_lq: 0,
+                downlink_lq: 0,
+                jitter: Duration::ZERO
+            }
+        );
+
+        // 3 out of 4 frames good; telemetry every 20 ms for the first half
+        // of the second only.
+        for (i, valid) in [true, false, true, true].into_iter().enumerate() {
+            health.rx_frame(ms(2000 + i as u64 * 100), valid);
+        }
+        for n in 0..25 {
+            health.telemetry(ms(2000 + n * 20));
+        }
+        let q = health.quality(ms(2999));
+        assert_eq!((q.uplink_lq, q.downlink_lq), (75, 50));
+        assert!(q.jitter < Duration::from_micros(1));
+
+        // A second later, nothing is left.
+        let q = health.quality(ms(4100));
+        assert_eq!((q.uplink_lq, q.downlink_lq), (0, 0));
+
+        let frame = frame(&Quality {
+            uplink_lq: 75,
+            downlink_lq: 50,
+            jitter: Duration::ZERO,
+        });
+        assert!(crsf::frame_check_crc(&frame));
+        assert_eq!(frame[2], crsf::PacketType::LinkStatistics as u8);
+        // Uplink LQ and downlink LQ at their places in the payload.
+        assert_eq!((frame[3 + 2], frame[3 + 8]), (75, 50));
+    }
+}
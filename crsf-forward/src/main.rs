@@ -1,23 +1,149 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use telemetry_lib::crsf::{self};
+use telemetry_lib::framing::Parsed;
+use telemetry_lib::systemd;
 use telemetry_lib::topics;
 use log::{error, info, trace, warn};
-use metrics::{Unit, counter, describe_counter, describe_histogram, histogram};
+use metrics::{
+    Unit, counter, describe_counter, describe_gauge, describe_histogram, gauge, histogram,
+};
 use metrics_exporter_tcp::TcpBuilder;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio_serial::SerialPortBuilderExt;
-use zenoh::Config;
+use std::cell::RefCell;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf};
+use tokio::sync::Notify;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use zenoh::handlers::FifoChannelHandler;
+use zenoh::pubsub::{Publisher, Subscriber};
+use zenoh::sample::Sample;
+use zenoh::time::Timestamp;
+use zenoh::{Config, Session};
+
+#[cfg(target_os = "linux")]
+mod ble;
+mod capture;
+mod control;
+mod decimate;
+mod detect;
+mod device;
+mod filter;
+mod health;
+mod impair;
+mod joystick;
+mod link;
+mod pacing;
+mod protocol;
+mod replay;
+mod routes;
+mod scheduler;
+mod speed;
+#[cfg(target_os = "linux")]
+mod sys;
+mod transport;
+mod tuning;
+mod websocket;
+
+use capture::{Capture, Direction};
+use decimate::Decimator;
+use detect::UsbId;
+use filter::TypeFilter;
+use health::Status;
+use impair::{DelayLine, ImpairConfig};
+use joystick::LocalJoystick;
+use link::LinkHealth;
+use pacing::Pacer;
+use protocol::{Kind, Protocol};
+use routes::{Rewrite, Router};
+use scheduler::{Pushed, Scheduler};
+use speed::{Proposal, SerialSpeed};
+use transport::Port;
+use tuning::Tuning;
+
+/// The serial port if none is given. Device names other than on Linux
+/// aren't predictable (e.g. `/dev/cu.usbserial-A50285BI` on macOS).
+#[cfg(target_os = "linux")]
+const DEFAULT_PORT: &str = "/dev/ttyUSB0";
+#[cfg(not(target_os = "linux"))]
+const DEFAULT_PORT: &str = "auto";
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// Serial port to use.
-    #[arg(short, long, default_value = "/dev/ttyUSB0")]
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Serial port to use (e.g. `/dev/ttyUSB0`, or `COM3` on Windows), or
+    /// `auto` to look for a radio or ELRS module by its USB ID (see --usb-id);
+    /// `auto` is the default other than on Linux. A port on another machine,
+    /// shared with e.g. ser2net, is `tcp://HOST:PORT` for a raw TCP connection,
+    /// or `rfc2217://HOST:PORT` for telnet with RFC 2217, which also sets the
+    /// baudrate. A handset or ELRS Backpack with a BLE serial service (Nordic
+    /// UART or HM-10) is `ble://MAC`, or `ble://MAC/random` for a random
+    /// address. For CRSF frames in binary WebSocket messages, e.g. from a
+    /// browser-based tool, `ws://HOST:PORT/PATH` connects to a server, and
+    /// `ws-listen://ADDR` waits for a client.
+    #[arg(short, long, default_value = DEFAULT_PORT)]
     port: String,
 
-    /// Serial baudrate to use.
-    #[arg(short, long, default_value_t = 420000)]
-    baud: u32,
+    /// USB ID to look for with --port auto, VID:PID in hex (e.g.
+    /// 10c4:ea60), in order of preference. Can be given more than once.
+    /// Replaces the built-in list of EdgeTX radios and the USB-serial chips
+    /// of ELRS modules.
+    #[arg(long, value_parser = detect::parse_usb_id)]
+    usb_id: Vec<UsbId>,
+
+    /// Serial baudrate to use; by default 420000 for CRSF, 100000 for SBUS
+    /// and 115200 for IBUS and SUMD. Any rate that the serial driver
+    /// supports can be used, e.g. 921600, 1870000, 3750000 or 5250000.
+    #[arg(short, long)]
+    baud: Option<u32>,
+
+    /// Accept CRSFv3 speed proposals from the radio up to this baudrate,
+    /// and switch to the proposed rate. Only for a local serial port, on
+    /// Linux; without it, proposals are declined.
+    #[arg(long)]
+    max_baud: Option<u32>,
+
+    /// Protocol of the radio. IBUS, SBUS and SUMD carry RC channels only,
+    /// no telemetry. SBUS is inverted: read it through an inverter, or from
+    /// an uninverted SBUS output.
+    #[arg(long, value_enum, default_value_t = Protocol::Crsf)]
+    protocol: Protocol,
+
+    /// Publish the RC frames at this rate at most, Hz, dropping the others,
+    /// for a sim that reads fewer than the radio sends (e.g. 100 Hz from a
+    /// 500 Hz link). Other frames are not affected.
+    #[arg(long, value_parser = decimate::parse_rate)]
+    rc_rate: Option<f64>,
+
+    /// Drop an incomplete frame from the radio after this long, ms, and look
+    /// for the next frame in the bytes after its start. This recovers from
+    /// a garbage sync byte or lost bytes without waiting for more data.
+    #[arg(long, default_value_t = 50)]
+    resync_timeout: u64,
+
+    /// Telemetry to send to the radio, bytes/s; 0 for no limit. Set it to
+    /// what the link's telemetry rate carries: link statistics and
+    /// parameter replies go first, the other telemetry types share the rest
+    /// by weight, and a newer frame of a type replaces one not sent yet.
+    #[arg(long, default_value_t = 0.0)]
+    telemetry_budget: f64,
+
+    /// Send link statistics to the radio at this rate, Hz, measured from the
+    /// health of the bridge: uplink LQ from the frames with a bad CRC, and
+    /// downlink LQ from the gaps in the telemetry from the sim. They replace
+    /// the link statistics from the sim.
+    #[arg(long, value_parser = parse_link_stats)]
+    link_stats: Option<f64>,
+
+    /// Send telemetry in the gaps between the RC frames from the radio, one
+    /// frame after each, like a receiver. Avoids collisions on a half-duplex
+    /// link, and evens out the telemetry the radio sees.
+    #[arg(long, default_value_t = false)]
+    pace_telemetry: bool,
 
     /// Zenoh connect endpoint (e.g. tcp/192.168.1.1:7447). Omit for peer discovery.
     #[arg(long)]
@@ -31,6 +157,127 @@ struct Args {
     #[arg(long, default_value = topics::DEFAULT_PREFIX)]
     zenoh_prefix: String,
 
+    /// Capture the traffic on the port to this pcapng file, for Wireshark:
+    /// the frames both ways, and the garbage received between them.
+    #[arg(long)]
+    capture: Option<PathBuf>,
+
+    /// Replay the frames received in a --capture file, with their original
+    /// timing, instead of opening the port; what is sent to the radio is
+    /// discarded. Exits at the end of the capture.
+    #[arg(long, conflicts_with_all = ["port", "extra_port"])]
+    replay: Option<PathBuf>,
+
+    /// Routes config (JSON): the topics to publish the frames from the
+    /// radio on, by the device they are addressed to, and the device
+    /// addresses to rewrite both ways. Without it, every frame goes to the
+    /// RC topic, or the topics of --topic.
+    #[arg(long)]
+    routes: Option<PathBuf>,
+
+    /// Publish the frames from the radio on this topic instead of the RC
+    /// topic, relative to the prefix. Can be given more than once, to feed
+    /// several consumers (e.g. crsf-joystick and a recorder) the same
+    /// frames.
+    #[arg(long, conflicts_with = "routes")]
+    topic: Vec<String>,
+
+    /// Another radio to forward at the same time, as NAME=PORT, e.g. a
+    /// student radio next to the instructor's. Its frames are published on
+    /// the RC topic plus `/NAME`, and it is sent the telemetry from the
+    /// telemetry topic plus `/NAME`. Can be given more than once.
+    #[arg(long, value_parser = parse_extra_port)]
+    extra_port: Vec<(String, String)>,
+
+    /// Publish the frames of all radios on the topics of --port, and send
+    /// them all its telemetry. Every frame carries its source as a zenoh
+    /// attachment: `main`, or the NAME of an --extra-port.
+    #[arg(long, requires = "extra_port", default_value_t = false)]
+    merge_ports: bool,
+
+    /// Delay the frames both ways by this much, ms, to test how the sim
+    /// feels over a poor link.
+    #[arg(long, default_value_t = 0)]
+    inject_latency: u64,
+
+    /// Vary the delay by up to this much either way, ms. Frames stay in
+    /// order.
+    #[arg(long, default_value_t = 0)]
+    inject_jitter: u64,
+
+    /// Drop this share of the frames both ways, %, e.g. to test the
+    /// failsafes. Dropped frames from the radio count against the uplink LQ
+    /// of --link-stats.
+    #[arg(long, value_parser = impair::parse_loss, default_value_t = 0.0)]
+    inject_loss: f64,
+
+    /// Seed for the injected jitter and loss, for reproducible runs.
+    #[arg(long)]
+    inject_seed: Option<u64>,
+
+    /// Consider the serial link wedged when nothing was read from the open
+    /// port for this long, s: the systemd watchdog (`WatchdogSec=`) is no
+    /// longer pinged, and /healthz fails.
+    #[arg(long, default_value_t = 5)]
+    serial_timeout: u64,
+
+    /// Serve the health of the serial link at http://ADDR/healthz, as JSON:
+    /// its state, frame rates and error counts. Answers 503 while the link
+    /// is wedged.
+    #[arg(long)]
+    health_bind: Option<std::net::SocketAddr>,
+
+    /// Control socket: answers `status`, `pause` and `resume` (the
+    /// telemetry from the sim), and `log FILTER`, a line at a time. See the
+    /// `ctl` command.
+    #[arg(long, global = true)]
+    control: Option<PathBuf>,
+
+    /// Set the low-latency flag of a local serial port, so that the driver
+    /// passes received bytes on right away instead of buffering them.
+    #[arg(long, default_value_t = false)]
+    low_latency: bool,
+
+    /// Set the latency timer of an FTDI USB-serial chip, ms (1 to 255, 16 by
+    /// default in the chip).
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..))]
+    latency_timer: Option<u8>,
+
+    /// Run the thread that forwards the frames with the real-time FIFO policy
+    /// at this priority (1 to 99). Needs CAP_SYS_NICE.
+    #[arg(long, value_parser = tuning::parse_rt_priority)]
+    rt_priority: Option<i32>,
+
+    /// Pin the thread that forwards the frames to this CPU.
+    #[arg(long)]
+    cpu: Option<usize>,
+
+    /// Forward only CRSF frames of these types, both ways: type bytes (e.g.
+    /// 0x16) or names (e.g. rc_channels_packed). Comma-separated, or given
+    /// more than once.
+    #[arg(long, value_delimiter = ',', value_parser = filter::parse_type)]
+    allow_types: Vec<u8>,
+
+    /// Don't forward CRSF frames of these types, both ways, e.g. config_write
+    /// to block parameter writes. Comma-separated, or given more than once.
+    #[arg(long, value_delimiter = ',', value_parser = filter::parse_type)]
+    deny_types: Vec<u8>,
+
+    /// Drive a virtual joystick (`CRSF Joystick`) with the RC frames from
+    /// the radio, like crsf-joystick but without the hops through zenoh, for
+    /// a sim on this machine. There is no mux with the autopilot.
+    #[arg(long, default_value_t = false)]
+    joystick: bool,
+
+    /// Channel mapping config (JSON) of the joystick, as written by
+    /// `crsf-joystick calibrate`. Its profiles aren't switched.
+    #[arg(long, requires = "joystick")]
+    joystick_mapping: Option<PathBuf>,
+
+    /// Don't publish the RC frames to zenoh; they only drive the joystick.
+    #[arg(long, requires = "joystick", default_value_t = false)]
+    joystick_only: bool,
+
     /// Enable metrics reporting using metrics-rs-tcp-exporter.
     #[arg(long, default_value_t = false)]
     metrics_tcp: bool,
@@ -40,11 +287,809 @@ struct Args {
     metrics_tcp_bind: std::net::SocketAddr,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Send a command to the --control socket of a running crsf-forward,
+    /// and print the answer.
+    Ctl {
+        #[arg(required = true)]
+        command: Vec<String>,
+    },
+}
+
+/// Parse an extra port, NAME=PORT, for use as a clap value parser.
+fn parse_extra_port(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((name, port)) if !name.is_empty() && !name.contains('/') && !port.is_empty() => {
+            Ok((name.to_string(), port.to_string()))
+        }
+        _ => Err(format!("expected NAME=PORT, got `{}`", s)),
+    }
+}
+
+/// Parse a link statistics rate, for use as a clap value parser.
+fn parse_link_stats(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(hz) if hz > 0.0 && hz <= 50.0 => Ok(hz),
+        _ => Err(format!("invalid rate `{}`, must be above 0 up to 50 Hz", s)),
+    }
+}
+
+/// Notify systemd of a state change, logging failures.
+fn sd_notify(state: &str) {
+    if let Err(e) = systemd::notify(state) {
+        warn!("sd_notify {} failed: {}", state, e);
+    }
+}
+
+/// Delay before reopening the serial port after the first failure; it
+/// doubles with every failure after that, up to [`MAX_BACKOFF`].
+const INITIAL_BACKOFF: Duration = Duration::from_millis(250);
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+type TelemetrySubscriber = Subscriber<FifoChannelHandler<Sample>>;
+
+/// Where the frames from the radio go: a publisher per routed topic, and
+/// the joystick if enabled.
+struct Outputs<'a> {
+    router: Router,
+    publishers: Vec<Publisher<'a>>,
+    /// Session for the timestamps.
+    clock: Session,
+    joystick: Option<RefCell<LocalJoystick>>,
+    /// Whether to publish the RC frames along with driving the joystick.
+    mirror: bool,
+    /// Attachment naming the radio, with --merge-ports.
+    source: Option<String>,
+}
+
+impl Outputs<'_> {
+    /// Publish a frame from the radio on the topics of its destination. An
+    /// RC frame also drives the joystick.
+    async fn publish(&self, protocol: Protocol, frame: &[u8], timestamp: Timestamp) {
+        if protocol.kind(frame) == Kind::Rc
+            && let Some(joystick) = &self.joystick
+        {
+            joystick.borrow_mut().frame(frame);
+            if !self.mirror {
+                return;
+            }
+        }
+        for &i in self.router.route(protocol.destination(frame)) {
+            let put = self.publishers[i].put(frame).timestamp(timestamp);
+            let put = match self.source {
+                Some(ref source) => put.attachment(source.as_str()),
+                None => put,
+            };
+            if let Err(e) = put.await {
+                warn!("Zenoh publish error: {}", e);
+            }
+        }
+    }
+}
+
+/// How telemetry is sent to the radio.
+#[derive(Debug, Clone, Copy)]
+struct Downlink<'a> {
+    /// Bytes per second; 0 for no limit.
+    budget: f64,
+    /// Interval between generated link statistics, if enabled.
+    link_stats: Option<Duration>,
+    rewrite: &'a Rewrite,
+    /// The types of telemetry frames to send.
+    filter: TypeFilter,
+    /// Whether to pace the telemetry by the RC frames.
+    pace: bool,
+}
+
+/// How the frames on the port are handled.
+#[derive(Debug, Clone, Copy)]
+struct PortConfig {
+    protocol: Protocol,
+    impair: ImpairConfig,
+    /// Highest baud rate to accept in a speed proposal, if any.
+    max_baud: Option<u32>,
+    /// Rate to decimate the RC frames to, Hz, if any.
+    rc_rate: Option<f64>,
+    /// Time after which an incomplete frame is dropped.
+    resync_timeout: Duration,
+    /// The types of frames from the radio to publish.
+    filter: TypeFilter,
+}
+
+/// A frame to send to the radio right away, and the baud rate to switch to
+/// once it is sent.
+type Reply = (Vec<u8>, Option<u32>);
+
+/// Longest queue of replies to the radio; more are dropped.
+const MAX_REPLIES: usize = 16;
+
+/// What the traffic on the ports is reported to, across reopens.
+struct Reports {
+    capture: RefCell<Option<Capture>>,
+    status: health::Shared,
+}
+
+/// A radio, and where its frames go.
+struct Radio<'a> {
+    /// The name and port of an --extra-port; `None` for --port.
+    extra: Option<(String, String)>,
+    tel_subscriber: TelemetrySubscriber,
+    outputs: Outputs<'a>,
+    reports: Reports,
+}
+
+impl Radio<'_> {
+    fn name(&self) -> &str {
+        self.extra.as_ref().map_or("main", |(name, _)| name)
+    }
+}
+
+/// What the traffic on the port is reported to.
+struct Monitor<'a> {
+    health: RefCell<LinkHealth>,
+    /// The pacing of the telemetry, if enabled.
+    pacer: Option<RefCell<Pacer>>,
+    /// Wakes the writer after an RC frame, while it waits for one.
+    rc_received: Notify,
+    reports: &'a Reports,
+}
+
+impl Monitor<'_> {
+    /// Write to the capture file, if any; it is closed on failure.
+    fn capture(&self, write: impl FnOnce(&mut Capture) -> io::Result<()>) {
+        let mut capture = self.reports.capture.borrow_mut();
+        if let Some(c) = capture.as_mut()
+            && let Err(e) = write(c)
+        {
+            warn!("Capture stopped: {}", e);
+            *capture = None;
+        }
+    }
+
+    fn status<T>(&self, f: impl FnOnce(&mut Status) -> T) -> T {
+        health::update(&self.reports.status, f)
+    }
+
+    /// Report a frame received from the radio.
+    fn received(&self, frame: &[u8], valid: bool) {
+        self.status(|s| s.received(Instant::now(), valid));
+        self.capture(|c| c.frame(Direction::Rx, frame, valid));
+    }
+
+    /// Report a valid RC frame from the radio.
+    fn rc_frame(&self, now: Instant) {
+        if let Some(pacer) = &self.pacer {
+            pacer.borrow_mut().rc_frame(now);
+            self.rc_received.notify_one();
+        }
+    }
+
+    /// Until when to hold the telemetry back; `None` to send it now.
+    fn pace(&self, now: Instant) -> Option<Instant> {
+        self.pacer.as_ref()?.borrow().wait(now)
+    }
+
+    /// Report received bytes that are not part of a frame.
+    fn garbage(&self, data: &[u8]) {
+        counter!("crsf.rx.garbage").increment(data.len() as u64);
+        self.status(|s| s.garbage(data.len()));
+        self.capture(|c| c.garbage(data));
+    }
+
+    /// Report a frame written to the radio.
+    fn sent(&self, frame: &[u8]) {
+        counter!("crsf.tx.frames", "kind" => Kind::of(frame).label()).increment(1);
+        self.status(|s| s.sent(Instant::now()));
+        self.capture(|c| c.frame(Direction::Tx, frame, true));
+    }
+}
+
+/// Why forwarding stopped.
+enum Stop {
+    /// The serial port failed or went away; it can be reopened.
+    Serial(io::Error),
+    /// The telemetry subscription ended.
+    Subscriber,
+}
+
+/// Forward between `port` and zenoh until either side fails. `speed`
+/// controls the baud rate of a local serial port.
+async fn forward(
+    port: Port,
+    speed: Option<SerialSpeed>,
+    tel_subscriber: &TelemetrySubscriber,
+    downlink: Downlink<'_>,
+    mut config: PortConfig,
+    outputs: &Outputs<'_>,
+    reports: &Reports,
+) -> Stop {
+    let (reader, writer) = tokio::io::split(port);
+    let monitor = Monitor {
+        health: RefCell::new(LinkHealth::new()),
+        pacer: downlink.pace.then(|| RefCell::new(Pacer::new())),
+        rc_received: Notify::new(),
+        reports,
+    };
+    let rc_line = DelayLine::new(config.impair, 0);
+    let telemetry_line = DelayLine::new(config.impair, 1);
+    let (reply_tx, reply_rx) = mpsc::channel(MAX_REPLIES);
+    if speed.is_none() {
+        config.max_baud = None;
+    }
+    let downstream = async {
+        if !config.protocol.has_telemetry() {
+            drain_telemetry(tel_subscriber, Duration::MAX).await;
+            return Stop::Subscriber;
+        }
+        write_serial(
+            writer,
+            tel_subscriber,
+            downlink,
+            telemetry_line,
+            &monitor,
+            reply_rx,
+            speed,
+        )
+        .await
+    };
+    tokio::select! {
+        stop = downstream => stop,
+        e = read_serial(reader, config, outputs, rc_line, &monitor, reply_tx) => {
+            Stop::Serial(e)
+        }
+    }
+}
+
+/// Write a reply to the radio, and switch the baud rate if it asks to.
+async fn write_reply(
+    writer: &mut WriteHalf<Port>,
+    (frame, baud): Reply,
+    speed: Option<&SerialSpeed>,
+    monitor: &Monitor<'_>,
+) -> io::Result<()> {
+    writer.write_all(&frame).await?;
+    monitor.sent(&frame);
+    if let (Some(baud), Some(speed)) = (baud, speed) {
+        writer.flush().await?;
+        let actual = speed.set(baud)?;
+        info!("Switched to {} baud", actual);
+    }
+    Ok(())
+}
+
+/// Zenoh CRSF telemetry -> Serial (with CRC check), through the
+/// [`Scheduler`], along with the link statistics if enabled. Replies to the
+/// radio have a queue of their own and go before any telemetry not written
+/// yet, so that a flood of telemetry can't hold them up. With pacing, the
+/// telemetry waits for its slot after an RC frame.
+async fn write_serial(
+    mut writer: WriteHalf<Port>,
+    tel_subscriber: &TelemetrySubscriber,
+    downlink: Downlink<'_>,
+    mut delay_line: DelayLine<Sample>,
+    monitor: &Monitor<'_>,
+    mut replies: mpsc::Receiver<Reply>,
+    speed: Option<SerialSpeed>,
+) -> Stop {
+    let mut scheduler = Scheduler::new(downlink.budget, Instant::now());
+    let mut link_stats = downlink.link_stats.map(tokio::time::interval);
+    loop {
+        while let Some(sample) = delay_line.pop(Instant::now()) {
+            queue_telemetry(&mut scheduler, &sample, downlink, monitor);
+        }
+        let paced = loop {
+            while let Ok(reply) = replies.try_recv() {
+                if let Err(e) = write_reply(&mut writer, reply, speed.as_ref(), monitor).await {
+                    return Stop::Serial(e);
+                }
+            }
+            let paced = monitor.pace(Instant::now());
+            if paced.is_some() {
+                break paced;
+            }
+            let Some(frame) = scheduler.pop(Instant::now()) else {
+                break None;
+            };
+            if let Err(e) = writer.write_all(&frame).await {
+                return Stop::Serial(e);
+            }
+            monitor.sent(&frame);
+            if let Some(pacer) = &monitor.pacer {
+                pacer.borrow_mut().sent();
+            }
+        };
+        gauge!("crsf.tx.queue_depth", "queue" => "reply").set(replies.len() as f64);
+        gauge!("crsf.tx.queue_depth", "queue" => "telemetry").set(scheduler.queued() as f64);
+
+        // While paced, the telemetry waits for the next RC frame instead.
+        let wait = scheduler.wait(Instant::now()).filter(|_| paced.is_none());
+        let due = delay_line.next_due();
+        // Until when the queued telemetry is held back, if there is any.
+        let held = paced.filter(|_| scheduler.queued() > 0);
+        let sample = tokio::select! {
+            sample = tel_subscriber.recv_async() => sample,
+            _ = tokio::time::sleep(wait.unwrap_or_default()), if wait.is_some() => continue,
+            _ = monitor.rc_received.notified(), if held.is_some() => continue,
+            _ = sleep_until(held), if held.is_some() => continue,
+            _ = sleep_until(due), if due.is_some() => continue,
+            _ = async { link_stats.as_mut().unwrap().tick().await }, if link_stats.is_some() => {
+                let quality = monitor.health.borrow_mut().quality(Instant::now());
+                gauge!("crsf.link.uplink_lq").set(quality.uplink_lq as f64);
+                gauge!("crsf.link.downlink_lq").set(quality.downlink_lq as f64);
+                gauge!("crsf.link.telemetry_jitter").set(quality.jitter.as_secs_f64());
+                scheduler.push(link::frame(&quality));
+                continue;
+            }
+            Some(reply) = replies.recv() => {
+                if let Err(e) = write_reply(&mut writer, reply, speed.as_ref(), monitor).await {
+                    return Stop::Serial(e);
+                }
+                continue;
+            }
+        };
+        match sample {
+            Ok(sample) => {
+                delay_telemetry(&mut delay_line, sample);
+                // Take everything that arrived meanwhile, so that stale
+                // frames are superseded rather than sent.
+                while let Ok(Some(sample)) = tel_subscriber.try_recv() {
+                    delay_telemetry(&mut delay_line, sample);
+                }
+            }
+            Err(e) => {
+                error!("Telemetry subscriber error: {}", e);
+                return Stop::Subscriber;
+            }
+        }
+    }
+}
+
+/// Pass a telemetry frame from zenoh through the injected impairments.
+fn delay_telemetry(delay_line: &mut DelayLine<Sample>, sample: Sample) {
+    if !delay_line.push(sample, Instant::now()) {
+        counter!("crsf.tx.injected_loss").increment(1);
+    }
+}
+
+/// Check a telemetry frame from zenoh and queue it for sending.
+fn queue_telemetry(
+    scheduler: &mut Scheduler,
+    sample: &Sample,
+    downlink: Downlink<'_>,
+    monitor: &Monitor<'_>,
+) {
+    let frame = sample.payload().to_bytes();
+    let frame_size = frame.len();
+    if frame_size > crsf::MAX_FRAME_SIZE {
+        warn!("Packet too large: {}", frame_size);
+        return;
+    }
+
+    trace!("tx: {:02x?}", &*frame);
+    counter!("crsf.tx.count").increment(1);
+    histogram!("crsf.tx.frame_size").record(frame.len() as f64);
+
+    if !crsf::frame_check_crc(&frame) {
+        trace!("Invalid CRC on incoming telemetry packet");
+        counter!("crsf.tx.crc_err").increment(1);
+        return;
+    }
+    monitor.health.borrow_mut().telemetry(Instant::now());
+    if monitor.status(|s| s.paused()) {
+        counter!("crsf.tx.paused").increment(1);
+        return;
+    }
+    // The generated link statistics replace the sim's.
+    if downlink.link_stats.is_some() && frame[2] == crsf::PacketType::LinkStatistics as u8 {
+        return;
+    }
+    if !downlink.filter.pass(&frame) {
+        counter!("crsf.tx.filtered").increment(1);
+        return;
+    }
+
+    let mut frame = frame.into_owned();
+    downlink.rewrite.to_serial(&mut frame);
+    match scheduler.push(frame) {
+        Pushed::Added => {}
+        Pushed::Superseded => counter!("crsf.tx.superseded").increment(1),
+        Pushed::Overflow => {
+            counter!("crsf.tx.dropped").increment(1);
+            counter!("crsf.tx.queue_dropped", "queue" => "telemetry").increment(1);
+        }
+    }
+}
+
+/// Queue a reply to the radio, unless too many are queued already.
+fn send_reply(replies: &mpsc::Sender<Reply>, reply: Reply) {
+    match replies.try_send(reply) {
+        Ok(()) => {
+            let depth = replies.max_capacity() - replies.capacity();
+            gauge!("crsf.tx.queue_depth", "queue" => "reply").set(depth as f64);
+        }
+        Err(TrySendError::Full(_)) => {
+            warn!("Too many replies to the radio queued, dropping one");
+            counter!("crsf.tx.queue_dropped", "queue" => "reply").increment(1);
+        }
+        // The writer only goes away along with the reader.
+        Err(TrySendError::Closed(_)) => {}
+    }
+}
+
+/// Sleep until `at`, for a `select!` branch that is disabled without it.
+fn sleep_until(at: Option<Instant>) -> tokio::time::Sleep {
+    tokio::time::sleep_until(at.unwrap_or_else(Instant::now).into())
+}
+
+/// Serial -> Zenoh (RC channels); returns the error that ended it.
+async fn read_serial(
+    mut reader: ReadHalf<Port>,
+    config: PortConfig,
+    outputs: &Outputs<'_>,
+    mut delay_line: DelayLine<(Vec<u8>, Timestamp)>,
+    monitor: &Monitor<'_>,
+    replies: mpsc::Sender<Reply>,
+) -> io::Error {
+    let protocol = config.protocol;
+    let mut decimator = config.rc_rate.map(Decimator::new);
+    // When the last valid RC frame was received, and the interval before it.
+    let mut last_rc: Option<Instant> = None;
+    let mut rc_interval: Option<Duration> = None;
+    let mut buf = Vec::new(); // Buffer for incoming data
+    let mut tmp = [0u8; 1024];
+    // Since when the frame at the start of `buf` has been incomplete.
+    let mut partial_since: Option<Instant> = None;
+
+    loop {
+        while let Some((frame, timestamp)) = delay_line.pop(Instant::now()) {
+            outputs.publish(protocol, &frame, timestamp).await;
+        }
+
+        let due = delay_line.next_due();
+        let stale = partial_since.map(|since| since + config.resync_timeout);
+        tokio::select! {
+            read = reader.read(&mut tmp) => match read {
+                Ok(0) => return io::Error::new(io::ErrorKind::UnexpectedEof, "EOF"),
+                Ok(n) => {
+                    histogram!("crsf.rx.read_size").record(n as f64);
+                    monitor.status(|s| s.read(Instant::now()));
+                    buf.extend_from_slice(&tmp[0..n]);
+                }
+                Err(e) => return e,
+            },
+            _ = sleep_until(due), if due.is_some() => continue,
+            _ = sleep_until(stale), if stale.is_some() => {
+                // The frame at the start of the buffer wasn't completed in
+                // time: its sync byte was garbage, or bytes of it were lost.
+                let len = protocol.resync(&buf);
+                trace!("Resync, dropping {} bytes", len);
+                counter!("crsf.rx.resync").increment(1);
+                monitor.garbage(&buf[..len]);
+                buf.drain(0..len);
+                partial_since = None;
+            }
+        }
+
+        while let Some(parsed) = protocol.next_frame(&buf) {
+            let (len, valid) = match parsed {
+                Parsed::Frame { len, valid } => (len, valid),
+                Parsed::Garbage(len) => {
+                    monitor.garbage(&buf[..len]);
+                    buf.drain(0..len);
+                    partial_since = None;
+                    continue;
+                }
+            };
+            counter!("crsf.rx.count").increment(1);
+            histogram!("crsf.rx.frame_size").record(len as f64);
+
+            let frame = &buf[0..len];
+            monitor.received(frame, valid);
+            let proposal = match protocol {
+                Protocol::Crsf if valid => Proposal::parse(frame),
+                _ => None,
+            };
+            if let Some(proposal) = proposal {
+                let accepted = config.max_baud.is_some_and(|max| proposal.baud <= max);
+                info!(
+                    "Speed proposal of {} baud from {:#04x} {}",
+                    proposal.baud,
+                    proposal.origin,
+                    if accepted { "accepted" } else { "declined" }
+                );
+                let reply = (
+                    proposal.response(accepted),
+                    accepted.then_some(proposal.baud),
+                );
+                send_reply(&replies, reply);
+                monitor.health.borrow_mut().rx_frame(Instant::now(), true);
+            } else if valid {
+                trace!("rx: {:02x?}", frame);
+                if protocol == Protocol::Crsf
+                    && let Some(origin) = device::ping_origin(frame)
+                {
+                    trace!("Device ping from {:#04x}", origin);
+                    send_reply(&replies, (device::info(origin), None));
+                }
+                counter!("crsf.rx.valid").increment(1);
+                // Stamp with the receive time so that subscribers
+                // can measure end-to-end latency.
+                let timestamp = outputs.clock.new_timestamp();
+                let now = Instant::now();
+                let kind = protocol.kind(frame);
+                counter!("crsf.rx.frames", "kind" => kind.label()).increment(1);
+                if kind == Kind::Rc {
+                    monitor.rc_frame(now);
+                    if let Some(prev) = last_rc.replace(now) {
+                        let interval = now - prev;
+                        histogram!("crsf.rx.rc_interval").record(interval.as_micros() as f64);
+                        if let Some(prev) = rc_interval.replace(interval) {
+                            histogram!("crsf.rx.rc_jitter")
+                                .record(interval.abs_diff(prev).as_micros() as f64);
+                        }
+                    }
+                }
+                let kept = match protocol.publish_frame(frame) {
+                    Some(_) if !config.filter.pass(frame) => {
+                        counter!("crsf.rx.filtered").increment(1);
+                        true
+                    }
+                    Some(_)
+                        if kind == Kind::Rc && decimator.as_mut().is_some_and(|d| !d.pass(now)) =>
+                    {
+                        counter!("crsf.rx.decimated").increment(1);
+                        true
+                    }
+                    Some(mut frame) => {
+                        if kind == Kind::Extended {
+                            outputs.router.rewrite.to_zenoh(&mut frame);
+                        }
+                        let kept = delay_line.push((frame, timestamp), now);
+                        if !kept {
+                            counter!("crsf.rx.injected_loss").increment(1);
+                        }
+                        kept
+                    }
+                    None => {
+                        counter!("crsf.rx.failsafe").increment(1);
+                        false
+                    }
+                };
+                monitor.health.borrow_mut().rx_frame(now, kept);
+            } else {
+                trace!("CRC mismatch");
+                counter!("crsf.rx.crc_err").increment(1);
+                monitor.health.borrow_mut().rx_frame(Instant::now(), false);
+            }
+
+            buf.drain(0..len);
+            partial_since = None;
+        }
+        // Whatever is left is the start of a frame, which times out if it
+        // isn't completed.
+        if !buf.is_empty() {
+            partial_since.get_or_insert_with(Instant::now);
+        }
+    }
+}
+
+/// Discard the telemetry received for `duration`, while there is no serial
+/// port to write it to, so that it doesn't queue up. Returns false if the
+/// subscription ended.
+async fn drain_telemetry(tel_subscriber: &TelemetrySubscriber, duration: Duration) -> bool {
+    let deadline = tokio::time::sleep(duration);
+    tokio::pin!(deadline);
+    loop {
+        tokio::select! {
+            _ = &mut deadline => return true,
+            sample = tel_subscriber.recv_async() => {
+                if let Err(e) = sample {
+                    error!("Telemetry subscriber error: {}", e);
+                    return false;
+                }
+                counter!("crsf.tx.dropped").increment(1);
+            }
+        }
+    }
+}
+
+/// The routes of the frames from --port.
+fn router(args: &Args) -> Result<Router, String> {
+    Ok(match args.routes {
+        Some(ref path) => Router::load(path)
+            .map_err(|e| format!("can't load routes from {}: {}", path.display(), e))?,
+        None if args.topic.is_empty() => Router::default(),
+        None => Router::to_topics(args.topic.clone()),
+    })
+}
+
+async fn subscribe_telemetry(
+    session: &Session,
+    args: &Args,
+    suffix: &str,
+) -> zenoh::Result<TelemetrySubscriber> {
+    let topic = topics::topic(&args.zenoh_prefix, suffix);
+    info!("Subscribing to: {}", topic);
+    session.declare_subscriber(topic).await
+}
+
+/// A publisher for each of the topics of `router`.
+async fn declare_publishers(
+    session: &Session,
+    args: &Args,
+    router: &Router,
+) -> zenoh::Result<Vec<Publisher<'static>>> {
+    let mut publishers = Vec::new();
+    for suffix in &router.topics {
+        let topic = topics::topic(&args.zenoh_prefix, suffix);
+        info!("Publishing on: {}", topic);
+        publishers.push(session.declare_publisher(topic).await?);
+    }
+    Ok(publishers)
+}
+
+fn capture(args: &Args) -> Result<Option<Capture>, String> {
+    let Some(ref path) = args.capture else {
+        return Ok(None);
+    };
+    let capture = Capture::create(path, &args.port)
+        .map_err(|e| format!("can't create {}: {}", path.display(), e))?;
+    info!("Capturing to {}", path.display());
+    Ok(Some(capture))
+}
+
+fn status(args: &Args) -> health::Shared {
+    Arc::new(Mutex::new(Status::new(
+        Duration::from_secs(args.serial_timeout),
+        Instant::now(),
+    )))
+}
+
+/// Forward `radio`, reopening its port when it fails, until the telemetry
+/// subscription ends or the replay is done.
+async fn run(
+    args: &Args,
+    radio: &Radio<'_>,
+    impair: ImpairConfig,
+    filter: TypeFilter,
+) -> Result<(), String> {
+    let tel_subscriber = &radio.tel_subscriber;
+    let outputs = &radio.outputs;
+    let reports = &radio.reports;
+    let extra = radio.extra.as_ref().map(|(_, port)| port.as_str());
+    let connected = gauge!("crsf.serial.connected", "radio" => radio.name().to_string());
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        // With --port auto, the radio may come back on another port.
+        // Opening can take a while (waiting for a WebSocket client, for
+        // one); the telemetry meanwhile is dropped.
+        let opened = tokio::select! {
+            opened = args.open_port(extra) => opened,
+            _ = drain_telemetry(tel_subscriber, Duration::MAX) => break,
+        };
+        let (name, port, speed) = match opened {
+            Ok(port) => port,
+            Err(e) if args.replay.is_some() => return Err(e),
+            Err(e) => {
+                warn!("Serial port: {}; retrying in {:?}", e, backoff);
+                if !drain_telemetry(tel_subscriber, backoff).await {
+                    break;
+                }
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                continue;
+            }
+        };
+        info!("Opened {}", name);
+        connected.set(1.0);
+        let opened = Instant::now();
+        health::update(&reports.status, |s| s.opened(&name, opened));
+        let stop = forward(
+            port,
+            speed,
+            tel_subscriber,
+            Downlink {
+                budget: args.telemetry_budget,
+                link_stats: args.link_stats.map(|hz| Duration::from_secs_f64(1.0 / hz)),
+                rewrite: &outputs.router.rewrite,
+                filter,
+                pace: args.pace_telemetry,
+            },
+            PortConfig {
+                protocol: args.protocol,
+                impair,
+                max_baud: args.max_baud,
+                rc_rate: args.rc_rate,
+                resync_timeout: Duration::from_millis(args.resync_timeout),
+                filter,
+            },
+            outputs,
+            reports,
+        )
+        .await;
+        connected.set(0.0);
+        health::update(&reports.status, Status::closed);
+        if args.replay.is_some() {
+            info!("Replay finished");
+            break;
+        }
+        match stop {
+            Stop::Serial(e) => error!("Serial port {} lost: {}", name, e),
+            Stop::Subscriber => break,
+        }
+        if let Some(joystick) = &outputs.joystick {
+            joystick.borrow_mut().neutralize();
+        }
+        counter!("crsf.serial.reconnect").increment(1);
+        // Back off again only if the port fails right after opening.
+        if opened.elapsed() > MAX_BACKOFF {
+            backoff = INITIAL_BACKOFF;
+        }
+        if !drain_telemetry(tel_subscriber, backoff).await {
+            break;
+        }
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+    Ok(())
+}
+
+impl Args {
+    /// The serial port to open: `--port`, or with `--port auto`, the one
+    /// found by its USB ID.
+    fn port_name(&self) -> Result<String, String> {
+        if self.port != "auto" {
+            return Ok(self.port.clone());
+        }
+        let ids: Vec<UsbId> = if self.usb_id.is_empty() {
+            detect::KNOWN_IDS.to_vec()
+        } else {
+            self.usb_id.clone()
+        };
+        let port = detect::detect(&ids)?;
+        info!("Found radio on {}", port);
+        Ok(port)
+    }
+
+    /// Open `--port`, or the `extra` port.
+    async fn open_port(
+        &self,
+        extra: Option<&str>,
+    ) -> Result<(String, Port, Option<SerialSpeed>), String> {
+        if let Some(ref path) = self.replay {
+            let port = replay::open(path)
+                .map_err(|e| format!("can't replay {}: {}", path.display(), e))?;
+            return Ok((path.display().to_string(), port, None));
+        }
+        let name = match extra {
+            Some(port) => port.to_string(),
+            None => self.port_name()?,
+        };
+        let tuning = Tuning {
+            low_latency: self.low_latency,
+            latency_timer: self.latency_timer,
+        };
+        let (port, speed) = transport::open(&name, self.protocol.line(self.baud), tuning)
+            .await
+            .map_err(|e| format!("can't open {}: {}", name, e))?;
+        Ok((name, port, speed))
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    env_logger::init();
+    control::init_logging();
     let args = Args::parse();
 
+    if let Some(Command::Ctl { ref command }) = args.command {
+        let Some(ref path) = args.control else {
+            return Err("ctl needs --control <path>".into());
+        };
+        control::send(path, command)
+            .await
+            .map_err(|e| format!("can't reach {}: {}", path.display(), e))?;
+        return Ok(());
+    }
+
     if args.metrics_tcp {
         let builder = TcpBuilder::new().listen_address(args.metrics_tcp_bind);
         builder
@@ -58,6 +1103,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         "Sent telemetry CRSF packet count"
     );
     describe_counter!("crsf.rx.count", Unit::Count, "Received CRSF packet count");
+    describe_counter!(
+        "crsf.rx.frames",
+        Unit::Count,
+        "Valid frames from the radio by kind: rc, link_stats, telemetry or extended"
+    );
+    describe_counter!(
+        "crsf.tx.frames",
+        Unit::Count,
+        "Frames written to the radio by kind: rc, link_stats, telemetry or extended"
+    );
     describe_counter!(
         "crsf.rx.valid",
         Unit::Count,
@@ -73,7 +1128,122 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         Unit::Count,
         "Number of received CRSF packets with CRC mismatch"
     );
+    describe_counter!(
+        "crsf.tx.dropped",
+        Unit::Count,
+        "Telemetry CRSF packets dropped while the serial port was closed, or because too many were queued"
+    );
+    describe_gauge!(
+        "crsf.tx.queue_depth",
+        Unit::Count,
+        "Frames queued for the radio, by queue: reply or telemetry"
+    );
+    describe_counter!(
+        "crsf.tx.queue_dropped",
+        Unit::Count,
+        "Frames for the radio dropped because their queue was full, by queue: reply or telemetry"
+    );
+    describe_counter!(
+        "crsf.tx.superseded",
+        Unit::Count,
+        "Telemetry CRSF packets replaced by a newer one of the same type before being sent"
+    );
+    describe_counter!(
+        "crsf.rx.garbage",
+        Unit::Bytes,
+        "Received bytes that are not part of a frame"
+    );
+    describe_counter!(
+        "crsf.rx.resync",
+        Unit::Count,
+        "Incomplete received frames dropped after --resync-timeout"
+    );
+    describe_counter!(
+        "crsf.rx.failsafe",
+        Unit::Count,
+        "Received RC frames not published because the receiver is in failsafe"
+    );
+    describe_counter!(
+        "crsf.rx.decimated",
+        Unit::Count,
+        "Received RC frames not published because of --rc-rate"
+    );
+    describe_counter!(
+        "crsf.rx.injected_loss",
+        Unit::Count,
+        "Received CRSF packets dropped by --inject-loss"
+    );
+    describe_counter!(
+        "crsf.tx.injected_loss",
+        Unit::Count,
+        "Telemetry CRSF packets dropped by --inject-loss"
+    );
+    describe_counter!(
+        "crsf.serial.reconnect",
+        Unit::Count,
+        "Times the serial port was lost"
+    );
+    describe_gauge!(
+        "crsf.serial.connected",
+        Unit::Count,
+        "Whether the serial port is open"
+    );
+    describe_gauge!(
+        "crsf.link.uplink_lq",
+        Unit::Percent,
+        "Share of the frames from the radio with a good CRC, with --link-stats"
+    );
+    describe_gauge!(
+        "crsf.link.downlink_lq",
+        Unit::Percent,
+        "Share of 100 ms slots with telemetry from the sim, with --link-stats"
+    );
+    describe_gauge!(
+        "crsf.link.telemetry_jitter",
+        Unit::Seconds,
+        "Standard deviation of the interval between telemetry frames, with --link-stats"
+    );
+    describe_counter!(
+        "crsf.tx.paused",
+        Unit::Count,
+        "Telemetry frames not sent while paused on the control socket"
+    );
+    describe_counter!(
+        "crsf.rx.filtered",
+        Unit::Count,
+        "Frames from the radio not published because of --allow-types or --deny-types"
+    );
+    describe_counter!(
+        "crsf.tx.filtered",
+        Unit::Count,
+        "Telemetry frames not sent because of --allow-types or --deny-types"
+    );
+    describe_counter!(
+        "crsf.joystick.updates",
+        Unit::Count,
+        "RC frames applied to the joystick, with --joystick"
+    );
+    describe_counter!(
+        "crsf.joystick.errors",
+        Unit::Count,
+        "Joystick updates that failed, with --joystick"
+    );
     describe_histogram!("crsf.rx.frame_size", Unit::Bytes, "Receive frame size");
+    describe_histogram!(
+        "crsf.rx.read_size",
+        Unit::Bytes,
+        "Bytes per read from the serial port"
+    );
+    describe_histogram!(
+        "crsf.rx.rc_interval",
+        Unit::Microseconds,
+        "Time between valid RC frames from the radio"
+    );
+    describe_histogram!(
+        "crsf.rx.rc_jitter",
+        Unit::Microseconds,
+        "Change in the time between valid RC frames from one to the next"
+    );
     describe_histogram!(
         "crsf.tx.frame_size",
         Unit::Bytes,
@@ -81,9 +1251,49 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     );
 
     info!("Starting crsf-forward");
-    info!("Serial Port: {} @ {}", args.port, args.baud);
 
-    let port = tokio_serial::new(&args.port, args.baud).open_native_async()?;
+    // The frames are forwarded on this thread, by the future of main.
+    if let Some(priority) = args.rt_priority {
+        tuning::set_rt_priority(priority)
+            .map_err(|e| format!("can't set real-time priority {}: {}", priority, e))?;
+    }
+    if let Some(cpu) = args.cpu {
+        tuning::pin_cpu(cpu).map_err(|e| format!("can't pin to CPU {}: {}", cpu, e))?;
+    }
+    let line = args.protocol.line(args.baud);
+    if args.replay.is_none() {
+        info!("Serial Port: {} @ {}", args.port, line.baud);
+    }
+    if args.link_stats.is_some() && !args.protocol.has_telemetry() {
+        return Err("--link-stats needs a protocol with telemetry".into());
+    }
+    let filter = TypeFilter::new(&args.allow_types, &args.deny_types);
+    if !filter.is_empty() && args.protocol != Protocol::Crsf {
+        return Err("--allow-types and --deny-types need --protocol crsf".into());
+    }
+    let impair = ImpairConfig {
+        latency: Duration::from_millis(args.inject_latency),
+        jitter: Duration::from_millis(args.inject_jitter),
+        loss: args.inject_loss / 100.0,
+        seed: args.inject_seed,
+    };
+    if impair.is_enabled() {
+        warn!(
+            "Injecting {} ms latency, {} ms jitter, {} % loss",
+            args.inject_latency, args.inject_jitter, args.inject_loss
+        );
+    }
+
+    let joystick = if args.joystick {
+        let joystick = LocalJoystick::open(
+            args.joystick_mapping.as_deref(),
+            args.protocol == Protocol::Sbus,
+        )?;
+        info!("Driving the joystick");
+        Some(RefCell::new(joystick))
+    } else {
+        None
+    };
 
     // Zenoh session
     let mut config = Config::default();
@@ -94,137 +1304,82 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 
     let session = zenoh::open(config).await?;
 
-    let crsf_tel_topic = topics::topic(&args.zenoh_prefix, topics::CRSF_TELEMETRY);
-    let crsf_rc_topic = topics::topic(&args.zenoh_prefix, topics::CRSF_RC);
-
-    info!("Subscribing to: {}", crsf_tel_topic);
-    info!("Publishing on: {}", crsf_rc_topic);
-
-    let tel_subscriber = session.declare_subscriber(&crsf_tel_topic).await?;
-    let rc_publisher = session.declare_publisher(crsf_rc_topic).await?;
-
-    let (mut reader, mut writer) = tokio::io::split(port);
-
-    // Task: Zenoh CRSF telemetry -> Serial (with CRC check)
-    let mut writer_handle = tokio::spawn(async move {
-        loop {
-            match tel_subscriber.recv_async().await {
-                Ok(sample) => {
-                    let frame = sample.payload().to_bytes();
-                    let frame_size = frame.len();
-                    if frame_size > crsf::MAX_FRAME_SIZE {
-                        warn!("Packet too large: {}", frame_size);
-                        continue;
-                    }
-
-                    trace!("tx: {:02x?}", &*frame);
-                    counter!("crsf.tx.count").increment(1);
-                    histogram!("crsf.tx.frame_size").record(frame.len() as f64);
-
-                    if !crsf::frame_check_crc(&frame) {
-                        trace!("Invalid CRC on incoming telemetry packet");
-                        counter!("crsf.tx.crc_err").increment(1);
-                        continue;
-                    }
-
-                    if let Err(e) = writer.write_all(&frame).await {
-                        error!("Serial write error: {}", e);
-                        break;
-                    }
-                }
-                Err(e) => {
-                    error!("Telemetry subscriber error: {}", e);
-                    break;
-                }
-            }
+    let main_router = router(&args)?;
+    let main_radio = Radio {
+        extra: None,
+        tel_subscriber: subscribe_telemetry(&session, &args, topics::CRSF_TELEMETRY).await?,
+        outputs: Outputs {
+            publishers: declare_publishers(&session, &args, &main_router).await?,
+            router: main_router,
+            clock: session.clone(),
+            joystick,
+            mirror: !args.joystick_only,
+            source: args.merge_ports.then(|| "main".to_string()),
+        },
+        reports: Reports {
+            capture: RefCell::new(capture(&args)?),
+            status: status(&args),
+        },
+    };
+    let mut extras = Vec::new();
+    for (name, port) in &args.extra_port {
+        if name == "main" || extras.iter().any(|r: &Radio| r.name() == name) {
+            return Err(format!("duplicate radio name `{}`", name).into());
         }
-    });
-
-    // Task: Serial -> Zenoh (RC channels)
-    let mut reader_handle = tokio::spawn(async move {
-        let mut buf = Vec::new(); // Buffer for incoming data
-        let mut tmp = [0u8; 1024];
-
-        loop {
-            match reader.read(&mut tmp).await {
-                Ok(0) => {
-                    // EOF
-                    error!("Serial EOF");
-                    break;
-                }
-                Ok(n) => {
-                    buf.extend_from_slice(&tmp[0..n]);
+        let (router, tel_topic) = if args.merge_ports {
+            (router(&args)?, topics::CRSF_TELEMETRY.to_string())
+        } else {
+            (
+                Router::to_topics(vec![format!("{}/{}", topics::CRSF_RC, name)]),
+                format!("{}/{}", topics::CRSF_TELEMETRY, name),
+            )
+        };
+        extras.push(Radio {
+            extra: Some((name.clone(), port.clone())),
+            tel_subscriber: subscribe_telemetry(&session, &args, &tel_topic).await?,
+            outputs: Outputs {
+                publishers: declare_publishers(&session, &args, &router).await?,
+                router,
+                clock: session.clone(),
+                joystick: None,
+                mirror: true,
+                source: args.merge_ports.then(|| name.clone()),
+            },
+            reports: Reports {
+                capture: RefCell::new(None),
+                status: status(&args),
+            },
+        });
+    }
 
-                    // Process buffer
-                    loop {
-                        // Find sync byte (we are the flight controller, in this context).
-                        if let Some(pos) = buf
-                            .iter()
-                            .position(|&b| b == crsf::device_address::FLIGHT_CONTROLLER)
-                        {
-                            // Trim garbage before sync
-                            if pos > 0 {
-                                buf.drain(0..pos);
-                            }
-
-                            // Check length
-                            if buf.len() < 2 {
-                                break; // Need more data
-                            }
-                            let len = buf[1] as usize; // Length of Payload + CRC
-                            let total_len = len + 2; // Sync + Len + Payload + CRC
-
-                            if total_len > crsf::MAX_FRAME_SIZE {
-                                // "Each CRSF frame is not longer than 64 bytes (including the Sync and CRC bytes)"
-                                // This packet would be too long. Drop sync byte and try again.
-                                buf.remove(0);
-                                continue;
-                            }
-                            if buf.len() < total_len {
-                                break; // Need more data
-                            }
-                            counter!("crsf.rx.count").increment(1);
-                            histogram!("crsf.rx.frame_size").record(total_len as f64);
-
-                            // Full packet found
-                            let frame = &buf[0..total_len];
-                            // Verify CRC
-                            let payload = &frame[2..total_len - 1];
-                            let crc_byte = frame[total_len - 1];
-
-                            if crsf::calc_crc8(payload) == crc_byte {
-                                // Valid packet
-                                trace!("rx: {:02x?}", payload);
-                                counter!("crsf.rx.valid").increment(1);
-                                if let Err(e) = rc_publisher.put(frame).await {
-                                    warn!("Zenoh publish error: {}", e);
-                                }
-                            } else {
-                                trace!("CRC mismatch");
-                                counter!("crsf.rx.crc_err").increment(1);
-                            }
-
-                            buf.drain(0..total_len);
-                        } else {
-                            // No sync found, clear buffer
-                            buf.clear();
-                            break;
-                        }
-                    }
-                }
-                Err(e) => {
-                    error!("Serial read error: {}", e);
-                    break;
-                }
-            }
-        }
-    });
+    let reports = &main_radio.reports;
+    if let Some(addr) = args.health_bind {
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .map_err(|e| format!("can't listen on {}: {}", addr, e))?;
+        info!("Serving health on http://{}/healthz", addr);
+        tokio::spawn(health::serve(listener, reports.status.clone()));
+    }
+    if let Some(ref path) = args.control {
+        control::listen(path, reports.status.clone())
+            .map_err(|e| format!("can't listen on {}: {}", path.display(), e))?;
+        info!("Control socket at {}", path.display());
+    }
+    if let Some(interval) = systemd::watchdog_interval() {
+        info!("systemd watchdog enabled, pinging every {:?}", interval);
+        tokio::spawn(health::watchdog(reports.status.clone(), interval));
+    }
+    sd_notify("READY=1");
 
+    let extras = futures_util::future::join_all(
+        extras.iter().map(|radio| run(&args, radio, impair, filter)),
+    );
     tokio::select! {
-        _ = &mut writer_handle => error!("Writer task finished"),
-        _ = &mut reader_handle => error!("Reader task finished"),
+        result = run(&args, &main_radio, impair, filter) => result?,
+        _ = extras, if !args.extra_port.is_empty() => {}
     }
 
+    sd_notify("STOPPING=1");
     session.close().await?;
     Ok(())
 }
@@ -0,0 +1,93 @@
+//! Pacing of the telemetry to the radio by its RC frames
+//! (`--pace-telemetry`).
+//!
+//! A receiver answers the radio in the gap after each RC frame, one
+//! telemetry frame at a time. On a half-duplex link, where both share one
+//! wire, a frame written while the radio sends collides with it; on any
+//! link, the radio sees the telemetry at an even rate. The pacer measures
+//! the cadence of the RC frames, and lets one telemetry frame go in the
+//! first half of the interval after each. Without a cadence, before the
+//! RC frames start or after they stop, it doesn't hold telemetry back.
+
+use std::time::{Duration, Instant};
+
+/// RC frames that don't come for this many intervals have stopped.
+const LOST: u32 = 3;
+
+#[derive(Debug, Default)]
+pub struct Pacer {
+    /// When the last RC frame was received.
+    last_rc: Option<Instant>,
+    /// Interval between the RC frames, smoothed.
+    interval: Option<Duration>,
+    /// Whether a frame was sent since the last RC frame.
+    sent: bool,
+}
+
+impl Pacer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An RC frame was received at `now`.
+    pub fn rc_frame(&mut self, now: Instant) {
+        if let Some(last) = self.last_rc.replace(now) {
+            let sample = now.saturating_duration_since(last);
+            self.interval = Some(match self.interval {
+                // A gap, not the cadence.
+                Some(interval) if sample > interval * LOST => interval,
+                Some(interval) => (interval * 7 + sample) / 8,
+                None => sample,
+            });
+        }
+        self.sent = false;
+    }
+
+    /// A telemetry frame was sent.
+    pub fn sent(&mut self) {
+        self.sent = true;
+    }
+
+    /// Until when to wait for the next RC frame before sending telemetry
+    /// at `now`; `None` to send it now.
+    pub fn wait(&self, now: Instant) -> Option<Instant> {
+        let (last, interval) = (self.last_rc?, self.interval?);
+        let since = now.saturating_duration_since(last);
+        if since >= interval * LOST || (!self.sent && since < interval / 2) {
+            return None;
+        }
+        Some(last + interval * LOST)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pacing() {
+        let t0 = Instant::now();
+        let at = |us: u64| t0 + Duration::from_micros(us);
+        let mut pacer = Pacer::new();
+        assert_eq!(pacer.wait(at(0)), None);
+        pacer.rc_frame(at(0));
+        assert_eq!(pacer.wait(at(100)), None);
+
+        // 500 Hz: one frame in the first millisecond after each RC frame.
+        pacer.rc_frame(at(2000));
+        assert_eq!(pacer.wait(at(2100)), None);
+        pacer.sent();
+        assert_eq!(pacer.wait(at(2200)), Some(at(8000)));
+        pacer.rc_frame(at(4000));
+        assert_eq!(pacer.wait(at(5100)), Some(at(10_000)));
+        assert_eq!(pacer.wait(at(4900)), None);
+
+        // A gap doesn't change the cadence, and the frames go freely
+        // during it.
+        assert_eq!(pacer.wait(at(10_000)), None);
+        pacer.rc_frame(at(50_000));
+        assert_eq!(pacer.interval, Some(Duration::from_micros(2000)));
+        pacer.rc_frame(at(52_800));
+        assert_eq!(pacer.interval, Some(Duration::from_micros(2100)));
+    }
+}
@@ -0,0 +1,351 @@
+//! Serial protocols of the radio (`--protocol`).
+//!
+//! CRSF carries RC channels from the radio and telemetry back to it. The
+//! others are one-way, RC channels only:
+//!
+//! - SBUS, at 100000 baud 8E2; the frames are published as they are, 25
+//!   bytes each, which the RC subscribers accept along with CRSF.
+//! - IBUS and SUMD, at 115200 baud 8N1; the channels are published as CRSF
+//!   RC channels frames, with the channels that a frame lacks centered.
+//!   SUMD frames sent while the receiver is in failsafe are not published.
+//!
+//! SBUS is inverted logic. Serial ports on Linux can't invert their input,
+//! so the signal has to go through an inverter, unless it comes from an
+//! uninverted SBUS output (as on many FrSky receivers).
+
+use clap::ValueEnum;
+use telemetry_lib::crsf::{self, CrsfPacket, RcChannelsPacked, device_address};
+use telemetry_lib::framing::{self, Found, Parsed};
+use telemetry_lib::{ibus, sbus, sumd};
+use tokio_serial::{Parity, StopBits};
+
+use crate::transport::Line;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// CRSF (TBS Crossfire, ExpressLRS), with telemetry.
+    Crsf,
+    /// SBUS (Futaba, FrSky), RC channels only.
+    Sbus,
+    /// IBUS (FlySky), RC channels only.
+    Ibus,
+    /// SUMD (Graupner HoTT), RC channels only.
+    Sumd,
+}
+
+/// What a frame carries, for the per-kind metrics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Rc,
+    LinkStats,
+    /// Sensor frames.
+    Telemetry,
+    /// Frames with addresses: device info, parameters, commands.
+    Extended,
+}
+
+impl Kind {
+    /// The kind of a CRSF frame.
+    pub fn of(frame: &[u8]) -> Self {
+        let Some(&frame_type) = frame.get(2) else {
+            return Kind::Telemetry;
+        };
+        match crsf::PacketType::try_from(frame_type) {
+            Ok(crsf::PacketType::RcChannelsPacked | crsf::PacketType::RcChannelsSubset) => Kind::Rc,
+            Ok(
+                crsf::PacketType::LinkStatistics
+                | crsf::PacketType::LinkStatisticsRx
+                | crsf::PacketType::LinkStatisticsTx,
+            ) => Kind::LinkStats,
+            // Extended frames (0x28 and up) carry destination and origin
+            // addresses.
+            _ if frame_type >= 0x28 => Kind::Extended,
+            _ => Kind::Telemetry,
+        }
+    }
+
+    /// Label value for the metrics.
+    pub fn label(self) -> &'static str {
+        match self {
+            Kind::Rc => "rc",
+            Kind::LinkStats => "link_stats",
+            Kind::Telemetry => "telemetry",
+            Kind::Extended => "extended",
+        }
+    }
+}
+
+impl Protocol {
+    /// Line settings, at `baud` if given.
+    pub fn line(self, baud: Option<u32>) -> Line {
+        match self {
+            Protocol::Crsf => Line {
+                baud: baud.unwrap_or(420_000),
+                parity: Parity::None,
+                stop_bits: StopBits::One,
+            },
+            Protocol::Sbus => Line {
+                baud: baud.unwrap_or(100_000),
+                parity: Parity::Even,
+                stop_bits: StopBits::Two,
+            },
+            Protocol::Ibus | Protocol::Sumd => Line {
+                baud: baud.unwrap_or(115_200),
+                parity: Parity::None,
+                stop_bits: StopBits::One,
+            },
+        }
+    }
+
+    /// Whether telemetry can be sent to the radio.
+    pub fn has_telemetry(self) -> bool {
+        self == Protocol::Crsf
+    }
+
+    /// What `buf` starts with: a frame, or garbage up to the next one.
+    /// Returns `None` if more data is needed.
+    pub fn next_frame(self, buf: &[u8]) -> Option<Parsed> {
+        let frame = match self {
+            Protocol::Crsf => framing::crsf_frame,
+            Protocol::Sbus => sbus_frame,
+            Protocol::Ibus => ibus_frame,
+            Protocol::Sumd => sumd_frame,
+        };
+        framing::next_frame(buf, &[self.sync()], frame)
+    }
+
+    /// Give up on the incomplete frame that `buf` starts with: the garbage
+    /// up to the next sync byte.
+    pub fn resync(self, buf: &[u8]) -> usize {
+        framing::resync(buf, &[self.sync()])
+    }
+
+    /// The first byte of a frame.
+    fn sync(self) -> u8 {
+        match self {
+            // Frames for the flight controller, which we are in this
+            // context.
+            Protocol::Crsf => device_address::FLIGHT_CONTROLLER,
+            Protocol::Sbus => sbus::START_BYTE,
+            Protocol::Ibus => ibus::HEADER[0],
+            Protocol::Sumd => sumd::HEADER,
+        }
+    }
+
+    /// The frame to publish for a valid `frame`; `None` if the receiver is
+    /// in failsafe.
+    pub fn publish_frame(self, frame: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            Protocol::Crsf | Protocol::Sbus => Some(frame.to_vec()),
+            Protocol::Ibus => Some(rc_frame(&ibus::parse_frame(frame)?)),
+            Protocol::Sumd => {
+                let frame = sumd::parse_frame(frame)?;
+                (!frame.failsafe).then(|| rc_frame(&frame.channels))
+            }
+        }
+    }
+
+    /// What a received or published frame carries.
+    pub fn kind(self, frame: &[u8]) -> Kind {
+        match self {
+            Protocol::Crsf => Kind::of(frame),
+            Protocol::Sbus | Protocol::Ibus | Protocol::Sumd => Kind::Rc,
+        }
+    }
+
+    /// The device a published frame is addressed to, for routing.
+    pub fn destination(self, frame: &[u8]) -> u8 {
+        match self {
+            Protocol::Crsf => crate::routes::destination(frame),
+            Protocol::Sbus | Protocol::Ibus | Protocol::Sumd => device_address::FLIGHT_CONTROLLER,
+        }
+    }
+}
+
+/// A CRSF RC channels frame from channel values in µs, with the channels
+/// after the given ones centered.
+fn rc_frame(us: &[u16]) -> Vec<u8> {
+    // The range of the 11-bit CRSF channel values.
+    let (min, max) = (crsf::ticks_to_us(0), crsf::ticks_to_us(0x7ff));
+    let mut channels = [crsf::us_to_ticks(1500); 16];
+    for (ch, &us) in channels.iter_mut().zip(us) {
+        *ch = crsf::us_to_ticks(us.clamp(min, max));
+    }
+    crsf::build_packet(
+        device_address::FLIGHT_CONTROLLER,
+        &CrsfPacket::RcChannelsPacked(RcChannelsPacked { channels }),
+    )
+    .expect("channels are in range")
+}
+
+fn sbus_frame(buf: &[u8]) -> Found {
+    // SBUS has no checksum: a start byte that isn't followed by a valid
+    // end byte 24 bytes later was channel data.
+    if buf.len() < sbus::FRAME_SIZE {
+        Found::Incomplete
+    } else if sbus::parse_frame(&buf[..sbus::FRAME_SIZE]).is_some() {
+        Found::Frame {
+            len: sbus::FRAME_SIZE,
+            valid: true,
+        }
+    } else {
+        Found::NotAFrame
+    }
+}
+
+fn ibus_frame(buf: &[u8]) -> Found {
+    if buf.len() < 2 {
+        Found::Incomplete
+    } else if !ibus::is_header(buf) {
+        Found::NotAFrame
+    } else if buf.len() < ibus::FRAME_SIZE {
+        Found::Incomplete
+    } else {
+        Found::Frame {
+            len: ibus::FRAME_SIZE,
+            valid: ibus::parse_frame(&buf[..ibus::FRAME_SIZE]).is_some(),
+        }
+    }
+}
+
+fn sumd_frame(buf: &[u8]) -> Found {
+    if buf.len() < 3 {
+        return Found::Incomplete;
+    }
+    match sumd::frame_len(buf) {
+        None => Found::NotAFrame,
+        Some(len) if buf.len() < len => Found::Incomplete,
+        Some(len) => Found::Frame {
+            len,
+            valid: sumd::parse_frame(&buf[..len]).is_some(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use telemetry_lib::crsf::{CrsfPacket, RcChannelsPacked};
+
+    /// Split `data` as `read_serial` does: what was found, and what is
+    /// left for more data to complete.
+    fn split(protocol: Protocol, data: &[u8]) -> (Vec<Parsed>, Vec<u8>) {
+        let mut buf = data.to_vec();
+        let mut found = Vec::new();
+        while let Some(parsed) = protocol.next_frame(&buf) {
+            found.push(parsed);
+            let (Parsed::Frame { len, .. } | Parsed::Garbage(len)) = parsed;
+            buf.drain(..len);
+        }
+        (found, buf)
+    }
+
+    const fn frame(len: usize, valid: bool) -> Parsed {
+        Parsed::Frame { len, valid }
+    }
+
+    #[test]
+    fn framing() {
+        let rc = crsf::build_packet(
+            device_address::FLIGHT_CONTROLLER,
+            &CrsfPacket::RcChannelsPacked(RcChannelsPacked {
+                channels: [992; 16],
+            }),
+        )
+        .unwrap();
+        let mut bad = rc.clone();
+        *bad.last_mut().unwrap() ^= 1;
+        let crsf = Protocol::Crsf;
+        // Garbage, a sync byte with a length that is too long, a good and
+        // a bad frame, and the start of another.
+        let (found, left) = split(
+            crsf,
+            &[&[0x55, 0xc8, 0xff][..], &rc, &bad, &rc[..4]].concat(),
+        );
+        assert_eq!(
+            found,
+            [Parsed::Garbage(3), frame(26, true), frame(26, false)]
+        );
+        assert_eq!(left, rc[..4]);
+        // A sync byte with a length that runs into the next frame, which
+        // is found once that times out.
+        let stuck = [&[0xc8, 30, 0x01][..], &rc].concat();
+        assert_eq!(crsf.next_frame(&stuck), None);
+        assert_eq!(crsf.resync(&stuck), 3);
+        assert_eq!(split(crsf, &stuck[3..]), (vec![frame(26, true)], vec![]));
+        assert_eq!(crsf.resync(&rc[..4]), 4);
+        assert_eq!(split(crsf, &[1, 2, 3]), (vec![Parsed::Garbage(3)], vec![]));
+        assert_eq!(crsf.next_frame(&[]), None);
+        assert_eq!(crsf.kind(&rc), Kind::Rc);
+        assert_eq!(crsf.kind(&[0xc8, 4, 0x28, 0x00, 0xea, 0]), Kind::Extended);
+        assert_eq!(crsf.kind(&[0xc8, 12, 0x14]), Kind::LinkStats);
+        assert_eq!(crsf.kind(&[0xc8, 10, 0x08]), Kind::Telemetry);
+
+        let mut channels = [992; 16];
+        channels[2] = 0x0f << 2;
+        let data = sbus::build_frame(&sbus::SbusFrame {
+            channels,
+            ch17: false,
+            ch18: false,
+            frame_lost: false,
+            failsafe: false,
+        })
+        .unwrap();
+        // Join a frame midway: the start bytes in the channel data are
+        // skipped.
+        let sbus = Protocol::Sbus;
+        let (found, left) = split(sbus, &[&data[3..], &data, &data[..10]].concat());
+        assert_eq!(found, [Parsed::Garbage(22), frame(25, true)]);
+        assert_eq!(left, data[..10]);
+        assert_eq!(sbus.destination(&data), device_address::FLIGHT_CONTROLLER);
+        assert_eq!(sbus.kind(&data), Kind::Rc);
+        assert_eq!(sbus.publish_frame(&data), Some(data.to_vec()));
+
+        let mut channels = [1500; ibus::CHANNELS];
+        channels[0] = 1000;
+        channels[1] = 700;
+        let data = ibus::build_frame(&channels);
+        let mut bad = data;
+        bad[10] ^= 1;
+        let ibus = Protocol::Ibus;
+        let (found, left) = split(ibus, &[&[0x20, 0x20][..], &data, &bad].concat());
+        assert_eq!(
+            found,
+            [Parsed::Garbage(2), frame(32, true), frame(32, false)]
+        );
+        assert!(left.is_empty());
+        // Channels in CRSF ticks, the second clamped to the lowest value
+        // and the last two centered.
+        let rc = ibus.publish_frame(&data).unwrap();
+        let Some(CrsfPacket::RcChannelsPacked(rc)) = crsf::parse_packet_check(&rc) else {
+            panic!("not an RC frame");
+        };
+        assert_eq!(rc.channels[..3], [192, 0, 992]);
+        assert_eq!(rc.channels[14..], [992, 992]);
+
+        let live = sumd::build_frame(&sumd::SumdFrame {
+            channels: vec![2000; 8],
+            failsafe: false,
+        })
+        .unwrap();
+        let failsafe = sumd::build_frame(&sumd::SumdFrame {
+            channels: vec![1500; 8],
+            failsafe: true,
+        })
+        .unwrap();
+        let sumd = Protocol::Sumd;
+        let (found, left) = split(
+            sumd,
+            &[&[0xa8, 0xa8, 0x00][..], &live, &failsafe[..5]].concat(),
+        );
+        assert_eq!(found, [Parsed::Garbage(3), frame(21, true)]);
+        assert_eq!(left, failsafe[..5]);
+        let rc = sumd.publish_frame(&live).unwrap();
+        assert_eq!(rc[2], crsf::PacketType::RcChannelsPacked as u8);
+        assert_eq!(sumd.publish_frame(&failsafe), None);
+
+        assert_eq!(sbus.line(None).baud, 100_000);
+        assert_eq!(sumd.line(None).baud, 115_200);
+        assert_eq!(crsf.line(Some(921_600)).baud, 921_600);
+    }
+}
@@ -0,0 +1,61 @@
+//! Replay of a capture in place of the port (`--replay`), to work on the
+//! zenoh side without a radio attached.
+//!
+//! The bytes received in a capture written with `--capture` (the frames,
+//! also those with a CRC error, and the garbage) are read from the port
+//! again with their original timing. What is written to the port is
+//! discarded. After the last packet, the port reads EOF.
+
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use log::info;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+use crate::capture;
+use crate::transport::Port;
+
+/// Buffer of the port both ways.
+const BUFFER: usize = 4096;
+
+/// Open the capture at `path` as a port.
+pub fn open(path: &Path) -> io::Result<Port> {
+    let data = std::fs::read(path)?;
+    let packets: Vec<(f64, Vec<u8>)> = capture::received(&data)?
+        .into_iter()
+        .map(|(time, data)| (time, data.to_vec()))
+        .collect();
+    info!(
+        "Replaying {} packets from {}",
+        packets.len(),
+        path.display()
+    );
+    let (port, radio) = tokio::io::duplex(BUFFER);
+    tokio::spawn(feed(radio, packets));
+    Ok(Box::new(port))
+}
+
+/// Write the `packets` to the port on time, and read what is written to it
+/// meanwhile.
+async fn feed(mut radio: DuplexStream, packets: Vec<(f64, Vec<u8>)>) {
+    let start = Instant::now();
+    let first = packets.first().map_or(0.0, |&(time, _)| time);
+    let mut discard = [0u8; BUFFER];
+    for (time, data) in packets {
+        let due = start + Duration::from_secs_f64((time - first).max(0.0));
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep_until(due.into()) => break,
+                read = radio.read(&mut discard) => {
+                    if !matches!(read, Ok(n) if n > 0) {
+                        return;
+                    }
+                }
+            }
+        }
+        if radio.write_all(&data).await.is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,372 @@
+//! Routing of the frames from the radio by destination (`--routes`).
+//!
+//! By default every frame is published on the RC topic. A routes config
+//! (JSON) sends frames to other topics, under the zenoh prefix, by the
+//! device they are addressed to:
+//!
+//! ```json
+//! {
+//!   "routes": [
+//!     { "destination": "vtx", "topics": ["crsf/vtx"] },
+//!     { "destination": "0xcc", "topics": ["crsf/rc", "crsf/debug"] }
+//!   ],
+//!   "default": ["crsf/rc"]
+//! }
+//! ```
+//!
+//! The destination of an extended frame (device info, parameters) is its
+//! destination address; other frames are for the flight controller.
+//! Frames for a destination without a route go to the `default` topics
+//! (the RC topic if omitted), and broadcast frames go to every topic.
+//!
+//! The addresses of extended frames can be rewritten on the way, so that
+//! a device on one side appears as another on the other side:
+//!
+//! ```json
+//! {
+//!   "rewrite": [
+//!     { "serial": "flight-controller", "zenoh": "0xca" },
+//!     { "serial": "radio", "zenoh": "transmitter" }
+//!   ]
+//! }
+//! ```
+//!
+//! From the radio, the `serial` address becomes the `zenoh` address, as
+//! destination or origin, and the other way around to the radio. Here a
+//! tool on the zenoh side at `0xca` talks to the radio as the flight
+//! controller, and sees the radio as the transmitter. Routes are by the
+//! rewritten destination.
+
+use std::io;
+use std::path::Path;
+
+use serde::Deserialize;
+use telemetry_lib::crsf::{self, PacketType, device_address};
+use telemetry_lib::topics;
+
+use crate::speed::command_crc;
+
+/// Frame types from here on have destination and origin addresses.
+const EXTENDED_FRAME: u8 = 0x28;
+
+const DESTINATIONS: [(&str, u8); 6] = [
+    ("broadcast", device_address::BROADCAST),
+    ("flight-controller", device_address::FLIGHT_CONTROLLER),
+    ("vtx", device_address::VTX),
+    ("radio", device_address::RADIO_TRANSMITTER),
+    ("receiver", device_address::CRSF_RECEIVER),
+    ("transmitter", device_address::CRSF_TRANSMITTER),
+];
+
+/// Parse a device name or address (`0xce` or decimal).
+fn parse_destination(s: &str) -> Result<u8, String> {
+    if let Some(&(_, addr)) = DESTINATIONS.iter().find(|(name, _)| *name == s) {
+        return Ok(addr);
+    }
+    let addr = match s.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    addr.map_err(|_| {
+        let names: Vec<&str> = DESTINATIONS.iter().map(|(name, _)| *name).collect();
+        format!(
+            "unknown destination `{}` (an address, or one of: {})",
+            s,
+            names.join(", ")
+        )
+    })
+}
+
+/// The device a frame is addressed to.
+pub fn destination(frame: &[u8]) -> u8 {
+    if frame[2] >= EXTENDED_FRAME && frame.len() > 3 {
+        frame[3]
+    } else {
+        device_address::FLIGHT_CONTROLLER
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Route {
+    destination: String,
+    topics: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RewriteRule {
+    serial: String,
+    zenoh: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RoutesConfig {
+    #[serde(default)]
+    routes: Vec<Route>,
+    #[serde(default = "default_topics")]
+    default: Vec<String>,
+    #[serde(default)]
+    rewrite: Vec<RewriteRule>,
+}
+
+fn default_topics() -> Vec<String> {
+    vec![topics::CRSF_RC.to_string()]
+}
+
+/// Address rewriting of the extended frames between the serial and the
+/// zenoh side.
+#[derive(Debug, Default)]
+pub struct Rewrite {
+    /// Serial and zenoh address.
+    pairs: Vec<(u8, u8)>,
+}
+
+impl Rewrite {
+    fn new(rules: &[RewriteRule]) -> Result<Self, String> {
+        let mut pairs: Vec<(u8, u8)> = Vec::new();
+        for rule in rules {
+            let pair = (
+                parse_destination(&rule.serial)?,
+                parse_destination(&rule.zenoh)?,
+            );
+            if pairs.iter().any(|p| p.0 == pair.0 || p.1 == pair.1) {
+                return Err(format!(
+                    "more than one rewrite of `{}` or `{}`",
+                    rule.serial, rule.zenoh
+                ));
+            }
+            pairs.push(pair);
+        }
+        Ok(Self { pairs })
+    }
+
+    /// Rewrite the addresses of a frame from the radio.
+    pub fn to_zenoh(&self, frame: &mut [u8]) {
+        self.apply(frame, |&(serial, zenoh)| (serial, zenoh));
+    }
+
+    /// Rewrite the addresses of a frame to the radio.
+    pub fn to_serial(&self, frame: &mut [u8]) {
+        self.apply(frame, |&(serial, zenoh)| (zenoh, serial));
+    }
+
+    fn apply(&self, frame: &mut [u8], from_to: impl Fn(&(u8, u8)) -> (u8, u8)) {
+        if self.pairs.is_empty() || frame.len() < 6 || frame[2] < EXTENDED_FRAME {
+            return;
+        }
+        let mut changed = false;
+        for addr in &mut frame[3..5] {
+            if let Some((_, to)) = self
+                .pairs
+                .iter()
+                .map(&from_to)
+                .find(|&(from, _)| from == *addr)
+            {
+                *addr = to;
+                changed = true;
+            }
+        }
+        if !changed {
+            return;
+        }
+        // Commands have a CRC of their own, which covers the addresses.
+        let end = frame.len() - 1;
+        if frame[2] == PacketType::Command as u8 {
+            frame[end - 1] = command_crc(&frame[2..end - 1]);
+        }
+        frame[end] = crsf::calc_crc8(&frame[2..end]);
+    }
+}
+
+/// The topics to publish each frame on, and the address rewriting.
+#[derive(Debug)]
+pub struct Router {
+    /// Topic suffixes, each once.
+    pub topics: Vec<String>,
+    /// Indices into `topics` per destination.
+    routes: Vec<(u8, Vec<usize>)>,
+    default: Vec<usize>,
+    all: Vec<usize>,
+    pub rewrite: Rewrite,
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Self::to_topics(default_topics())
+    }
+}
+
+impl Router {
+    /// Publish every frame on all of `topics`, each once.
+    pub fn to_topics(topics: Vec<String>) -> Self {
+        let mut default = Vec::new();
+        for topic in topics {
+            if !default.contains(&topic) {
+                default.push(topic);
+            }
+        }
+        Self::new(RoutesConfig {
+            routes: Vec::new(),
+            default,
+            rewrite: Vec::new(),
+        })
+        .expect("routes without destinations are valid")
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let data = std::fs::read_to_string(path)?;
+        let config: RoutesConfig = serde_json::from_str(&data)?;
+        Self::new(config).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn new(config: RoutesConfig) -> Result<Self, String> {
+        let mut topics: Vec<String> = Vec::new();
+        let mut index = |topic: &String| match topics.iter().position(|t| t == topic) {
+            Some(i) => i,
+            None => {
+                topics.push(topic.clone());
+                topics.len() - 1
+            }
+        };
+        let default = config.default.iter().map(&mut index).collect();
+        let mut routes: Vec<(u8, Vec<usize>)> = Vec::new();
+        for route in &config.routes {
+            let addr = parse_destination(&route.destination)?;
+            if routes.iter().any(|(a, _)| *a == addr) {
+                return Err(format!("more than one route for `{}`", route.destination));
+            }
+            routes.push((addr, route.topics.iter().map(&mut index).collect()));
+        }
+        let all = (0..topics.len()).collect();
+        Ok(Self {
+            topics,
+            routes,
+            default,
+            all,
+            rewrite: Rewrite::new(&config.rewrite)?,
+        })
+    }
+
+    /// Indices into `topics` to publish a frame for `addr` on.
+    pub fn route(&self, addr: u8) -> &[usize] {
+        if addr == device_address::BROADCAST {
+            return &self.all;
+        }
+        self.routes
+            .iter()
+            .find(|(a, _)| *a == addr)
+            .map_or(&self.default, |(_, topics)| topics)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routing() {
+        let rc = [0xc8, 24, 0x16];
+        // Device info request from the radio to the VTX, and a broadcast
+        // ping.
+        let vtx = [0xc8, 6, 0x29, 0xce, 0xea, 0, 0];
+        let ping = [0xc8, 4, 0x28, 0x00, 0xea, 0];
+        let receiver = [0xc8, 6, 0x2c, 0xec, 0xea, 0, 0];
+        assert_eq!(destination(&rc), 0xc8);
+        assert_eq!(destination(&vtx), 0xce);
+
+        let router = Router::default();
+        assert_eq!(router.topics, ["crsf/rc"]);
+        assert_eq!(router.route(destination(&rc)), [0]);
+        assert_eq!(router.route(destination(&vtx)), [0]);
+
+        let router = Router::to_topics(vec!["crsf/rc".into(), "rec".into(), "rec".into()]);
+        assert_eq!(router.topics, ["crsf/rc", "rec"]);
+        assert_eq!(router.route(destination(&rc)), [0, 1]);
+
+        let config: RoutesConfig = serde_json::from_str(
+            r#"{"routes": [
+                {"destination": "vtx", "topics": ["crsf/vtx"]},
+                {"destination": "0xec", "topics": ["crsf/rc", "crsf/debug"]}
+            ]}"#,
+        )
+        .unwrap();
+        let router = Router::new(config).unwrap();
+        assert_eq!(router.topics, ["crsf/rc", "crsf/vtx", "crsf/debug"]);
+        assert_eq!(router.route(destination(&rc)), [0]);
+        assert_eq!(router.route(destination(&vtx)), [1]);
+        assert_eq!(router.route(destination(&receiver)), [0, 2]);
+        assert_eq!(router.route(destination(&ping)), [0, 1, 2]);
+
+        let config: RoutesConfig = serde_json::from_str(
+            r#"{"routes": [{"destination": "flight-controller", "topics": []}], "default": []}"#,
+        )
+        .unwrap();
+        let router = Router::new(config).unwrap();
+        assert!(router.route(destination(&rc)).is_empty());
+
+        let duplicate: RoutesConfig = serde_json::from_str(
+            r#"{"routes": [
+                {"destination": "vtx", "topics": ["a"]},
+                {"destination": "206", "topics": ["b"]}
+            ]}"#,
+        )
+        .unwrap();
+        assert!(Router::new(duplicate).is_err());
+        assert!(parse_destination("osd").unwrap_err().contains("vtx"));
+        assert!(serde_json::from_str::<RoutesConfig>(r#"{"route": []}"#).is_err());
+    }
+
+    #[test]
+    fn rewrite() {
+        let frame = |frame_type, destination, origin, payload: &[u8]| {
+            let mut frame = vec![0xc8, 0, frame_type, destination, origin];
+            frame.extend_from_slice(payload);
+            frame[1] = (frame.len() - 1) as u8;
+            frame.push(crsf::calc_crc8(&frame[2..]));
+            frame
+        };
+        let config: RoutesConfig = serde_json::from_str(
+            r#"{"rewrite": [
+                {"serial": "flight-controller", "zenoh": "0xca"},
+                {"serial": "radio", "zenoh": "transmitter"}
+            ]}"#,
+        )
+        .unwrap();
+        let rewrite = Router::new(config).unwrap().rewrite;
+
+        // A parameter read from the radio, and back.
+        let mut read = frame(0x2c, 0xc8, 0xea, &[1, 0]);
+        rewrite.to_zenoh(&mut read);
+        assert_eq!(read, frame(0x2c, 0xca, 0xee, &[1, 0]));
+        rewrite.to_serial(&mut read);
+        assert_eq!(read, frame(0x2c, 0xc8, 0xea, &[1, 0]));
+
+        // A command to the radio, with its own CRC.
+        let mut command = frame(0x32, 0xee, 0xca, &[0x0a, 0x70, 0, 0]);
+        rewrite.to_serial(&mut command);
+        assert!(crsf::frame_check_crc(&command));
+        assert_eq!(command[3..5], [0xea, 0xc8]);
+        assert_eq!(command[8], command_crc(&command[2..8]));
+
+        // Frames without addresses, or addresses without a rule.
+        let rc = frame(0x16, 0xc8, 0xea, &[]);
+        let mut unchanged = rc.clone();
+        rewrite.to_zenoh(&mut unchanged);
+        assert_eq!(unchanged, rc);
+        let vtx = frame(0x2c, 0xce, 0xec, &[]);
+        let mut unchanged = vtx.clone();
+        rewrite.to_serial(&mut unchanged);
+        assert_eq!(unchanged, vtx);
+
+        let duplicate: RoutesConfig = serde_json::from_str(
+            r#"{"rewrite": [
+                {"serial": "radio", "zenoh": "0xca"},
+                {"serial": "vtx", "zenoh": "0xca"}
+            ]}"#,
+        )
+        .unwrap();
+        assert!(Router::new(duplicate).is_err());
+    }
+}
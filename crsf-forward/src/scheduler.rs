@@ -0,0 +1,291 @@
+//! Scheduling of the telemetry sent to the radio (`--telemetry-budget`).
+//!
+//! The telemetry downlink of a CRSF link has room for only so many bytes
+//! per second: ELRS, for one, sends telemetry in one of every so many
+//! packets. Forwarding frames in arrival order lets a burst of one type hold
+//! up the others, and queues up stale data. Instead, like the slots of an
+//! ELRS transmitter, only the latest frame of each type is kept, and frames
+//! are sent within a byte budget:
+//!
+//! - link-critical frames (link statistics, heartbeat) and extended frames
+//!   (device info, parameter replies, which are addressed and must not be
+//!   dropped) go first, in order;
+//! - the other types share the rest in proportion to their weights, in
+//!   smooth weighted round-robin order, so that no type waits long.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use telemetry_lib::crsf::{MAX_FRAME_SIZE, PacketType};
+
+/// Budget that can be saved up while idle, s.
+const BURST: f64 = 0.1;
+/// Longest queue of link-critical and extended frames; the oldest are
+/// dropped beyond this.
+const MAX_URGENT: usize = 32;
+/// Weight of the types not in [`weight`].
+const DEFAULT_WEIGHT: u32 = 1;
+
+/// Whether a frame of this type goes before the others, in order.
+fn is_urgent(frame_type: u8) -> bool {
+    // Extended frames (0x28 and up) carry destination and origin addresses.
+    frame_type >= 0x28
+        || matches!(
+            PacketType::try_from(frame_type),
+            Ok(PacketType::LinkStatistics
+                | PacketType::LinkStatisticsRx
+                | PacketType::LinkStatisticsTx
+                | PacketType::Heartbeat)
+        )
+}
+
+/// Relative share of the budget of the other types.
+fn weight(frame_type: u8) -> u32 {
+    match PacketType::try_from(frame_type) {
+        Ok(PacketType::Attitude) => 4,
+        Ok(PacketType::Gps | PacketType::Vario | PacketType::BaroAlt) => 3,
+        Ok(PacketType::BatterySensor) => 2,
+        _ => DEFAULT_WEIGHT,
+    }
+}
+
+#[derive(Debug)]
+struct Slot {
+    frame_type: u8,
+    weight: u32,
+    /// The latest frame not sent yet.
+    pending: Option<Vec<u8>>,
+    /// Round-robin credit: the slot with the most goes next.
+    credit: i64,
+}
+
+/// What happened to a frame given to [`Scheduler::push`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pushed {
+    Added,
+    /// It replaced an older frame of its type that wasn't sent yet.
+    Superseded,
+    /// The urgent queue was full; the oldest frame in it was dropped.
+    Overflow,
+}
+
+#[derive(Debug)]
+pub struct Scheduler {
+    /// Bytes per second; 0 for no limit.
+    budget: f64,
+    /// Budget available, bytes; negative after urgent frames overdrew it.
+    tokens: f64,
+    refilled: Instant,
+    urgent: VecDeque<Vec<u8>>,
+    slots: Vec<Slot>,
+}
+
+impl Scheduler {
+    pub fn new(budget: f64, now: Instant) -> Self {
+        let mut scheduler = Self {
+            budget,
+            tokens: 0.0,
+            refilled: now,
+            urgent: VecDeque::new(),
+            slots: Vec::new(),
+        };
+        scheduler.tokens = scheduler.capacity();
+        scheduler
+    }
+
+    /// The most budget that can be saved up: enough for at least one frame.
+    fn capacity(&self) -> f64 {
+        (self.budget * BURST).max(MAX_FRAME_SIZE as f64)
+    }
+
+    /// Queue a complete, checked frame.
+    pub fn push(&mut self, frame: Vec<u8>) -> Pushed {
+        let frame_type = frame[2];
+        if is_urgent(frame_type) {
+            self.urgent.push_back(frame);
+            if self.urgent.len() > MAX_URGENT {
+                self.urgent.pop_front();
+                return Pushed::Overflow;
+            }
+            return Pushed::Added;
+        }
+        let index = match self.slots.iter().position(|s| s.frame_type == frame_type) {
+            Some(index) => index,
+            None => {
+                self.slots.push(Slot {
+                    frame_type,
+                    weight: weight(frame_type),
+                    pending: None,
+                    credit: 0,
+                });
+                self.slots.len() - 1
+            }
+        };
+        match self.slots[index].pending.replace(frame) {
+            Some(_) => Pushed::Superseded,
+            None => Pushed::Added,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+        self.refilled = now;
+        self.tokens = (self.tokens + self.budget * elapsed).min(self.capacity());
+    }
+
+    /// The slot whose frame goes next: the most credit once every slot
+    /// with a frame gets its weight.
+    fn next_slot(&self) -> Option<usize> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.pending.is_some())
+            .min_by_key(|(_, s)| std::cmp::Reverse(s.credit + s.weight as i64))
+            .map(|(i, _)| i)
+    }
+
+    fn affordable(&self, len: usize) -> bool {
+        self.budget <= 0.0 || self.tokens >= len as f64
+    }
+
+    /// The next frame to send at `now`, if any is due within the budget.
+    pub fn pop(&mut self, now: Instant) -> Option<Vec<u8>> {
+        self.refill(now);
+        let frame = if let Some(frame) = self.urgent.pop_front() {
+            frame
+        } else {
+            let index = self.next_slot()?;
+            let len = self.slots[index].pending.as_ref()?.len();
+            if !self.affordable(len) {
+                return None;
+            }
+            let mut total = 0;
+            for slot in self.slots.iter_mut().filter(|s| s.pending.is_some()) {
+                slot.credit += slot.weight as i64;
+                total += slot.weight as i64;
+            }
+            let slot = &mut self.slots[index];
+            slot.credit -= total;
+            slot.pending.take()?
+        };
+        if self.budget > 0.0 {
+            self.tokens -= frame.len() as f64;
+        }
+        Some(frame)
+    }
+
+    /// The number of frames queued.
+    pub fn queued(&self) -> usize {
+        self.urgent.len() + self.slots.iter().filter(|s| s.pending.is_some()).count()
+    }
+
+    /// How long until [`pop`](Self::pop) can return a frame; `None` if
+    /// nothing is queued.
+    pub fn wait(&self, now: Instant) -> Option<Duration> {
+        if !self.urgent.is_empty() {
+            return Some(Duration::ZERO);
+        }
+        let len = self.slots[self.next_slot()?].pending.as_ref()?.len();
+        if self.affordable(len) {
+            return Some(Duration::ZERO);
+        }
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+        let missing = len as f64 - self.tokens - self.budget * elapsed;
+        Some(Duration::from_secs_f64((missing / self.budget).max(0.0)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(frame_type: u8, len: usize) -> Vec<u8> {
+        let mut frame = vec![0u8; len];
+        frame[0] = 0xea;
+        frame[1] = (len - 2) as u8;
+        frame[2] = frame_type;
+        frame
+    }
+
+    const GPS: u8 = PacketType::Gps as u8;
+    const ATTITUDE: u8 = PacketType::Attitude as u8;
+    const BATTERY: u8 = PacketType::BatterySensor as u8;
+    const LINK: u8 = PacketType::LinkStatistics as u8;
+    const CONFIG: u8 = PacketType::ConfigRead as u8;
+
+    #[test]
+    fn priorities() {
+        let t0 = Instant::now();
+        let ms = |n| t0 + Duration::from_millis(n);
+        let mut s = Scheduler::new(0.0, t0);
+        assert_eq!(s.pop(t0), None);
+        assert_eq!(s.wait(t0), None);
+
+        s.push(frame(GPS, 18));
+        assert_eq!(s.push(frame(GPS, 19)), Pushed::Superseded);
+        s.push(frame(BATTERY, 12));
+        s.push(frame(CONFIG, 20));
+        s.push(frame(LINK, 14));
+        s.push(frame(CONFIG, 21));
+        assert_eq!(s.queued(), 5);
+        assert_eq!(s.wait(ms(5)), Some(Duration::ZERO));
+        let sent: Vec<(u8, usize)> = std::iter::from_fn(|| s.pop(ms(10)))
+            .map(|f| (f[2], f.len()))
+            .collect();
+        // Urgent frames in order, then the latest GPS frame before the
+        // battery, which weighs less.
+        assert_eq!(
+            sent,
+            [
+                (CONFIG, 20),
+                (LINK, 14),
+                (CONFIG, 21),
+                (GPS, 19),
+                (BATTERY, 12)
+            ]
+        );
+        assert_eq!(s.queued(), 0);
+    }
+
+    #[test]
+    fn weights() {
+        let t0 = Instant::now();
+        let mut s = Scheduler::new(0.0, t0);
+        // Frames of three types keep coming, but only one can go per tick.
+        let types = [ATTITUDE, GPS, BATTERY];
+        let mut counts = [0; 3];
+        for tick in 1..=900 {
+            for frame_type in types {
+                s.push(frame(frame_type, 12));
+            }
+            let sent = s.pop(t0 + Duration::from_millis(tick * 10)).unwrap();
+            counts[types.iter().position(|&t| t == sent[2]).unwrap()] += 1;
+        }
+        // Shares 4:3:2.
+        assert_eq!(counts, [400, 300, 200]);
+    }
+
+    #[test]
+    fn budget() {
+        let t0 = Instant::now();
+        let ms = |n| t0 + Duration::from_millis(n);
+        // 1000 bytes/s: up to 100 bytes saved up.
+        let mut s = Scheduler::new(1000.0, t0);
+        for frame_type in [GPS, ATTITUDE, BATTERY, 0x0d, 0x0e] {
+            s.push(frame(frame_type, 30));
+        }
+        let mut sent = 0;
+        while s.pop(t0).is_some() {
+            sent += 1;
+        }
+        assert_eq!(sent, 3);
+        assert_eq!(s.wait(t0), Some(Duration::from_millis(20)));
+        assert_eq!(s.pop(ms(10)), None);
+        assert!(s.pop(ms(20)).is_some());
+
+        // Urgent frames go anyway, and are paid for later.
+        s.push(frame(LINK, 50));
+        assert!(s.pop(ms(20)).is_some());
+        assert_eq!(s.wait(ms(20)), Some(Duration::from_millis(80)));
+    }
+}
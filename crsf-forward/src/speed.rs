@@ -0,0 +1,191 @@
+//! CRSFv3 speed negotiation (`--max-baud`).
+//!
+//! A CRSFv3 device proposes a faster baud rate to the flight controller
+//! with a command frame. The flight controller replies whether it accepts,
+//! and if so, both switch to the new rate once the reply is sent. If the
+//! link fails after that, crsf-forward reopens the port at `--baud`, which
+//! the device falls back to as well.
+//!
+//! The new rate is set with `termios2` and `BOTHER`, so that any rate the
+//! serial driver supports can be used, not just the standard ones. That is
+//! Linux only; elsewhere, proposals are declined.
+
+use std::io;
+#[cfg(target_os = "linux")]
+use std::mem;
+#[cfg(target_os = "linux")]
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+use telemetry_lib::crsf::{self, device_address};
+
+#[cfg(target_os = "linux")]
+use crate::sys::cvt;
+
+/// Command frame type, and the general command with its speed proposal
+/// and response subcommands.
+const COMMAND: u8 = 0x32;
+const GENERAL: u8 = 0x0a;
+const SPEED_PROPOSAL: u8 = 0x70;
+const SPEED_RESPONSE: u8 = 0x71;
+
+/// CRC-8 with polynomial 0xba, over a command from the frame type on.
+pub fn command_crc(data: &[u8]) -> u8 {
+    data.iter().fold(0, |crc, &b| {
+        (0..8).fold(crc ^ b, |crc, _| {
+            if crc & 0x80 != 0 {
+                (crc << 1) ^ 0xba
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// A speed proposal to the flight controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Proposal {
+    /// The device that sent it.
+    pub origin: u8,
+    pub port_id: u8,
+    pub baud: u32,
+}
+
+impl Proposal {
+    /// Parse a speed proposal from a frame with a valid CRC.
+    pub fn parse(frame: &[u8]) -> Option<Self> {
+        match *frame {
+            [
+                _,
+                _,
+                COMMAND,
+                device_address::FLIGHT_CONTROLLER,
+                origin,
+                GENERAL,
+                SPEED_PROPOSAL,
+                port_id,
+                b0,
+                b1,
+                b2,
+                b3,
+                crc,
+                _,
+            ] if command_crc(&frame[2..12]) == crc => Some(Self {
+                origin,
+                port_id,
+                baud: u32::from_be_bytes([b0, b1, b2, b3]),
+            }),
+            _ => None,
+        }
+    }
+
+    /// The response frame, accepting the proposal or not.
+    pub fn response(&self, accepted: bool) -> Vec<u8> {
+        let mut frame = vec![
+            device_address::FLIGHT_CONTROLLER,
+            9,
+            COMMAND,
+            self.origin,
+            device_address::FLIGHT_CONTROLLER,
+            GENERAL,
+            SPEED_RESPONSE,
+            self.port_id,
+            accepted as u8,
+        ];
+        frame.push(command_crc(&frame[2..]));
+        frame.push(crsf::calc_crc8(&frame[2..]));
+        frame
+    }
+}
+
+/// Control of the baud rate of a local serial port.
+#[cfg(target_os = "linux")]
+#[derive(Debug)]
+pub struct SerialSpeed(OwnedFd);
+
+#[cfg(target_os = "linux")]
+impl SerialSpeed {
+    pub fn new(port: &impl AsRawFd) -> io::Result<Self> {
+        let fd = cvt(unsafe { libc::dup(port.as_raw_fd()) })?;
+        Ok(Self(unsafe { OwnedFd::from_raw_fd(fd) }))
+    }
+
+    /// Wait until the output has been sent, then switch to `baud`. Returns
+    /// the rate that the driver set, which may be rounded.
+    pub fn set(&self, baud: u32) -> io::Result<u32> {
+        let fd = self.0.as_raw_fd();
+        let mut tio: libc::termios2 = unsafe { mem::zeroed() };
+        cvt(unsafe { libc::tcdrain(fd) })?;
+        cvt(unsafe { libc::ioctl(fd, libc::TCGETS2, &mut tio) })?;
+        tio.c_cflag &= !(libc::CBAUD | libc::CIBAUD);
+        tio.c_cflag |= libc::BOTHER;
+        tio.c_ispeed = baud;
+        tio.c_ospeed = baud;
+        cvt(unsafe { libc::ioctl(fd, libc::TCSETS2, &tio) })?;
+        cvt(unsafe { libc::ioctl(fd, libc::TCGETS2, &mut tio) })?;
+        Ok(tio.c_ospeed)
+    }
+}
+
+/// No control of the baud rate; there is never one.
+#[cfg(not(target_os = "linux"))]
+#[derive(Debug)]
+pub enum SerialSpeed {}
+
+#[cfg(not(target_os = "linux"))]
+impl SerialSpeed {
+    pub fn set(&self, _baud: u32) -> io::Result<u32> {
+        match *self {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiation() {
+        // A proposal of 1870000 baud from the receiver, on port 0.
+        let mut frame = vec![
+            0xc8,
+            12,
+            COMMAND,
+            0xc8,
+            0xec,
+            GENERAL,
+            SPEED_PROPOSAL,
+            0,
+            0x00,
+            0x1c,
+            0x88,
+            0xb0,
+        ];
+        frame.push(command_crc(&frame[2..]));
+        frame.push(crsf::calc_crc8(&frame[2..]));
+        assert!(crsf::frame_check_crc(&frame));
+        let proposal = Proposal::parse(&frame).unwrap();
+        assert_eq!(
+            proposal,
+            Proposal {
+                origin: 0xec,
+                port_id: 0,
+                baud: 1_870_000
+            }
+        );
+
+        let response = proposal.response(true);
+        assert!(crsf::frame_check_crc(&response));
+        assert_eq!(
+            response[..9],
+            [0xc8, 9, COMMAND, 0xec, 0xc8, GENERAL, SPEED_RESPONSE, 0, 1]
+        );
+        assert_eq!(response[9], command_crc(&response[2..9]));
+
+        // Not for the flight controller, or a bad command CRC.
+        let mut other = frame.clone();
+        other[3] = 0xee;
+        assert_eq!(Proposal::parse(&other), None);
+        let mut bad = frame.clone();
+        bad[12] ^= 1;
+        assert_eq!(Proposal::parse(&bad), None);
+    }
+}
@@ -0,0 +1,13 @@
+//! Linux system calls.
+
+use std::io;
+
+/// The result of a system call that returns a negative value, with the
+/// error in `errno`, on failure.
+pub(crate) fn cvt(ret: libc::c_int) -> io::Result<libc::c_int> {
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret)
+    }
+}
@@ -0,0 +1,412 @@
+//! The connection to the radio: a local serial port, or one on another
+//! machine through a serial-to-network server such as ser2net.
+//!
+//! - `tcp://HOST:PORT` is a raw TCP connection; the server sets the baud
+//!   rate.
+//! - `rfc2217://HOST:PORT` is a telnet connection with the COM port control
+//!   option (RFC 2217), which sets the baud rate and framing on the
+//!   remote port. The server's option negotiation is read and ignored.
+//! - `ble://MAC` is a Bluetooth LE serial service (see `crate::ble`), on
+//!   Linux only.
+//! - `ws://` and `ws-listen://` are WebSocket connections (see
+//!   [`crate::websocket`]).
+//! - anything else is a local serial port: a device such as `/dev/ttyUSB0`
+//!   on Linux and macOS, or `COM3` on Windows.
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use log::warn;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::net::TcpStream;
+use tokio_serial::{DataBits, Parity, SerialPortBuilderExt, StopBits};
+
+#[cfg(target_os = "linux")]
+use crate::ble;
+use crate::speed::SerialSpeed;
+use crate::tuning::Tuning;
+use crate::websocket;
+
+/// Telnet commands and options.
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+const BINARY: u8 = 0;
+const COM_PORT_OPTION: u8 = 44;
+/// RFC 2217 subnegotiation commands, client to server.
+const SET_BAUDRATE: u8 = 1;
+const SET_DATASIZE: u8 = 2;
+const SET_PARITY: u8 = 3;
+const SET_STOPSIZE: u8 = 4;
+const PARITY_NONE: u8 = 1;
+const PARITY_ODD: u8 = 2;
+const PARITY_EVEN: u8 = 3;
+const STOPSIZE_1: u8 = 1;
+const STOPSIZE_2: u8 = 2;
+
+/// A byte stream to the radio.
+pub trait Stream: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Stream for T {}
+
+pub type Port = Box<dyn Stream>;
+
+/// Serial line settings, with 8 data bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Line {
+    pub baud: u32,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Address<'a> {
+    Serial(&'a str),
+    Tcp(&'a str),
+    Rfc2217(&'a str),
+    Ble(&'a str),
+    /// The URL to connect to.
+    WebSocket(&'a str),
+    /// The address to listen on.
+    WebSocketListen(&'a str),
+}
+
+impl<'a> Address<'a> {
+    pub fn parse(port: &'a str) -> Self {
+        if let Some(host) = port.strip_prefix("tcp://") {
+            Address::Tcp(host)
+        } else if let Some(host) = port.strip_prefix("rfc2217://") {
+            Address::Rfc2217(host)
+        } else if let Some(device) = port.strip_prefix("ble://") {
+            Address::Ble(device)
+        } else if port.starts_with("ws://") {
+            Address::WebSocket(port)
+        } else if let Some(addr) = port.strip_prefix("ws-listen://") {
+            Address::WebSocketListen(addr)
+        } else {
+            Address::Serial(port)
+        }
+    }
+}
+
+/// Open `port` with the settings of `line`. For a local serial port, also
+/// returns control of its baud rate.
+pub async fn open(
+    port: &str,
+    line: Line,
+    tuning: Tuning,
+) -> io::Result<(Port, Option<SerialSpeed>)> {
+    let port: Port = match Address::parse(port) {
+        Address::Serial(path) => {
+            let stream = tokio_serial::new(path, line.baud)
+                .data_bits(DataBits::Eight)
+                .parity(line.parity)
+                .stop_bits(line.stop_bits)
+                .open_native_async()?;
+            if let Err(e) = tuning.apply(path, &stream) {
+                warn!("Can't tune {}: {}", path, e);
+            }
+            #[cfg(target_os = "linux")]
+            let speed = Some(SerialSpeed::new(&stream)?);
+            #[cfg(not(target_os = "linux"))]
+            let speed = None;
+            return Ok((Box::new(stream), speed));
+        }
+        Address::Tcp(host) => Box::new(connect(host).await?),
+        Address::Rfc2217(host) => {
+            let mut stream = connect(host).await?;
+            stream.write_all(&rfc2217_setup(line)).await?;
+            Box::new(Telnet::new(stream))
+        }
+        #[cfg(target_os = "linux")]
+        Address::Ble(device) => Box::new(ble::connect(device).await?),
+        #[cfg(not(target_os = "linux"))]
+        Address::Ble(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "ble:// is only supported on Linux",
+            ));
+        }
+        Address::WebSocket(url) => Box::new(websocket::connect(url).await?),
+        Address::WebSocketListen(addr) => Box::new(websocket::accept(addr).await?),
+    };
+    Ok((port, None))
+}
+
+async fn connect(host: &str) -> io::Result<TcpStream> {
+    let stream = TcpStream::connect(host).await?;
+    // Frames are small and latency matters more than throughput.
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+/// Append `data` to `out`, doubling IAC bytes.
+fn escape(data: &[u8], out: &mut Vec<u8>) {
+    for &b in data {
+        if b == IAC {
+            out.push(IAC);
+        }
+        out.push(b);
+    }
+}
+
+/// Option negotiation and port settings sent on connecting: binary mode
+/// both ways, then the line settings.
+fn rfc2217_setup(line: Line) -> Vec<u8> {
+    let mut out = vec![
+        IAC,
+        WILL,
+        BINARY,
+        IAC,
+        DO,
+        BINARY,
+        IAC,
+        WILL,
+        COM_PORT_OPTION,
+    ];
+    let mut command = |command: u8, value: &[u8]| {
+        out.extend_from_slice(&[IAC, SB, COM_PORT_OPTION, command]);
+        escape(value, &mut out);
+        out.extend_from_slice(&[IAC, SE]);
+    };
+    command(SET_BAUDRATE, &line.baud.to_be_bytes());
+    command(SET_DATASIZE, &[8]);
+    command(
+        SET_PARITY,
+        &[match line.parity {
+            Parity::None => PARITY_NONE,
+            Parity::Odd => PARITY_ODD,
+            Parity::Even => PARITY_EVEN,
+        }],
+    );
+    command(
+        SET_STOPSIZE,
+        &[match line.stop_bits {
+            StopBits::One => STOPSIZE_1,
+            StopBits::Two => STOPSIZE_2,
+        }],
+    );
+    out
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum State {
+    #[default]
+    Data,
+    Iac,
+    /// The option byte after WILL, WONT, DO or DONT.
+    Option,
+    Sub,
+    SubIac,
+}
+
+/// Separates the data from the telnet commands in the received bytes.
+#[derive(Debug, Default)]
+struct Decoder {
+    state: State,
+}
+
+impl Decoder {
+    /// Strip the commands from `buf` in place, returning the length of the
+    /// data left at its start. Commands may be split across calls.
+    fn decode(&mut self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        for i in 0..buf.len() {
+            let b = buf[i];
+            self.state = match (self.state, b) {
+                (State::Data, IAC) => State::Iac,
+                (State::Data, _) | (State::Iac, IAC) => {
+                    buf[len] = b;
+                    len += 1;
+                    State::Data
+                }
+                (State::Iac, SB) => State::Sub,
+                (State::Iac, WILL..=DONT) => State::Option,
+                (State::Iac | State::Option, _) => State::Data,
+                (State::Sub, IAC) => State::SubIac,
+                (State::SubIac, SE) => State::Data,
+                (State::Sub | State::SubIac, _) => State::Sub,
+            };
+        }
+        len
+    }
+}
+
+/// Telnet framing over `S`.
+///
+/// A write that returns `Pending` must be retried with the same data, as
+/// `write_all` does: the escaped data in flight is not taken from it again.
+pub struct Telnet<S> {
+    inner: S,
+    decoder: Decoder,
+    /// Escaped data not written yet, and the length of the data it was
+    /// escaped from.
+    out: Vec<u8>,
+    out_len: usize,
+}
+
+impl<S> Telnet<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            decoder: Decoder::default(),
+            out: Vec::new(),
+            out_len: 0,
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Telnet<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            let start = buf.filled().len();
+            ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+            let end = buf.filled().len();
+            if end == start {
+                // End of stream.
+                return Poll::Ready(Ok(()));
+            }
+            let len = this.decoder.decode(&mut buf.filled_mut()[start..]);
+            buf.set_filled(start + len);
+            if len > 0 {
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Telnet<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.out.is_empty() {
+            escape(buf, &mut this.out);
+            this.out_len = buf.len();
+        }
+        while !this.out.is_empty() {
+            let n = ready!(Pin::new(&mut this.inner).poll_write(cx, &this.out))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            this.out.drain(..n);
+        }
+        Poll::Ready(Ok(this.out_len))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn telnet() {
+        assert_eq!(
+            Address::parse("/dev/ttyUSB0"),
+            Address::Serial("/dev/ttyUSB0")
+        );
+        assert_eq!(Address::parse("tcp://pi:2000"), Address::Tcp("pi:2000"));
+        assert_eq!(
+            Address::parse("rfc2217://pi:2001"),
+            Address::Rfc2217("pi:2001")
+        );
+        assert_eq!(
+            Address::parse("ble://c8:2b:96:01:02:03"),
+            Address::Ble("c8:2b:96:01:02:03")
+        );
+        assert_eq!(
+            Address::parse("ws://pi:8765/crsf"),
+            Address::WebSocket("ws://pi:8765/crsf")
+        );
+        assert_eq!(
+            Address::parse("ws-listen://0.0.0.0:8765"),
+            Address::WebSocketListen("0.0.0.0:8765")
+        );
+
+        let line = |baud| Line {
+            baud,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+        };
+        // 420000 baud is 0x000668a0.
+        let setup = rfc2217_setup(line(420_000));
+        assert_eq!(
+            &setup[9..19],
+            [
+                IAC,
+                SB,
+                COM_PORT_OPTION,
+                SET_BAUDRATE,
+                0x00,
+                0x06,
+                0x68,
+                0xa0,
+                IAC,
+                SE
+            ]
+        );
+        // A 0xff byte in a value is doubled.
+        assert_eq!(&rfc2217_setup(line(0xff))[15..20], [0, IAC, IAC, IAC, SE]);
+        // 8E2 for SBUS.
+        let setup = rfc2217_setup(Line {
+            baud: 100_000,
+            parity: Parity::Even,
+            stop_bits: StopBits::Two,
+        });
+        assert_eq!(
+            setup[setup.len() - 14..],
+            [
+                IAC,
+                SB,
+                COM_PORT_OPTION,
+                SET_PARITY,
+                PARITY_EVEN,
+                IAC,
+                SE,
+                IAC,
+                SB,
+                COM_PORT_OPTION,
+                SET_STOPSIZE,
+                STOPSIZE_2,
+                IAC,
+                SE
+            ]
+        );
+
+        let mut escaped = Vec::new();
+        escape(&[0xc8, 0xff, 0x16], &mut escaped);
+        assert_eq!(escaped, [0xc8, 0xff, 0xff, 0x16]);
+
+        // Data with an escaped IAC, a DO, and a subnegotiation reply split
+        // across two reads.
+        let mut decoder = Decoder::default();
+        let mut buf = [0xc8, IAC, IAC, 0x16, IAC, DO, BINARY, 0x01, IAC, SB, 44];
+        let len = decoder.decode(&mut buf);
+        assert_eq!(&buf[..len], [0xc8, 0xff, 0x16, 0x01]);
+        let mut buf = [101, 0x00, IAC, IAC, 0x06, IAC, SE, 0x02, IAC];
+        let len = decoder.decode(&mut buf);
+        assert_eq!(&buf[..len], [0x02]);
+        let mut buf = [IAC, 0x03];
+        let len = decoder.decode(&mut buf);
+        assert_eq!(&buf[..len], [0xff, 0x03]);
+    }
+}
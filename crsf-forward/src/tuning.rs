@@ -0,0 +1,175 @@
+//! Tuning for low latency: of a local serial port (`--low-latency`,
+//! `--latency-timer`), and of the thread that forwards the frames
+//! (`--rt-priority`, `--cpu`).
+//!
+//! USB-serial chips hold received bytes back until their buffer fills or a
+//! timer runs out; for FTDI chips that is the latency timer, 16 ms by
+//! default, so that a radio at 500 Hz arrives in bursts of several frames.
+//! The `ASYNC_LOW_LATENCY` flag asks the driver to pass bytes on right away
+//! (FTDI drivers set the latency timer to 1 ms for it), and the latency
+//! timer can also be set directly. The effect shows in the bytes per read
+//! (`crsf.rx.read_size`) and the time between RC frames
+//! (`crsf.rx.rc_interval`, `crsf.rx.rc_jitter`).
+//!
+//! All of it is Linux only; elsewhere, the tuning fails with an error.
+
+use std::io;
+#[cfg(target_os = "linux")]
+use std::mem;
+#[cfg(target_os = "linux")]
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use log::info;
+use tokio_serial::SerialStream;
+
+#[cfg(target_os = "linux")]
+use crate::sys::cvt;
+
+/// `ASYNCB_LOW_LATENCY` in `linux/tty_flags.h`.
+#[cfg(target_os = "linux")]
+const ASYNC_LOW_LATENCY: libc::c_int = 1 << 13;
+
+/// `struct serial_struct` in `linux/serial.h`.
+#[cfg(target_os = "linux")]
+#[repr(C)]
+struct SerialStruct {
+    kind: libc::c_int,
+    line: libc::c_int,
+    port: libc::c_uint,
+    irq: libc::c_int,
+    flags: libc::c_int,
+    xmit_fifo_size: libc::c_int,
+    custom_divisor: libc::c_int,
+    baud_base: libc::c_int,
+    close_delay: libc::c_ushort,
+    io_type: libc::c_char,
+    reserved_char: libc::c_char,
+    hub6: libc::c_int,
+    closing_wait: libc::c_ushort,
+    closing_wait2: libc::c_ushort,
+    iomem_base: *mut libc::c_uchar,
+    iomem_reg_shift: libc::c_ushort,
+    port_high: libc::c_uint,
+    iomap_base: libc::c_ulong,
+}
+
+/// Tuning of a local serial port.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tuning {
+    pub low_latency: bool,
+    /// Latency timer of an FTDI chip, ms.
+    pub latency_timer: Option<u8>,
+}
+
+impl Tuning {
+    /// Tune the serial port at `path`, opened as `port`.
+    pub fn apply(&self, path: &str, port: &SerialStream) -> io::Result<()> {
+        if self.low_latency {
+            set_low_latency(port)?;
+            info!("Set {} to low latency", path);
+        }
+        if let Some(ms) = self.latency_timer {
+            let timer = latency_timer_path(Path::new(path))?;
+            let before = std::fs::read_to_string(&timer)?;
+            std::fs::write(&timer, ms.to_string())?;
+            info!(
+                "Set the latency timer of {} from {} to {} ms",
+                path,
+                before.trim(),
+                ms
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_low_latency(port: &SerialStream) -> io::Result<()> {
+    let fd = port.as_raw_fd();
+    let mut serial: SerialStruct = unsafe { mem::zeroed() };
+    cvt(unsafe { libc::ioctl(fd, libc::TIOCGSERIAL, &mut serial) })?;
+    serial.flags |= ASYNC_LOW_LATENCY;
+    cvt(unsafe { libc::ioctl(fd, libc::TIOCSSERIAL, &serial) })?;
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_low_latency(_port: &SerialStream) -> io::Result<()> {
+    Err(linux_only())
+}
+
+/// The sysfs latency timer of the USB-serial device at `path`, which may
+/// be a link such as `/dev/serial/by-id/...`.
+fn latency_timer_path(path: &Path) -> io::Result<PathBuf> {
+    let device = path.canonicalize()?;
+    let name = device
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a device"))?;
+    let timer = Path::new("/sys/class/tty")
+        .join(name)
+        .join("device/latency_timer");
+    if !timer.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "no latency timer, not an FTDI chip",
+        ));
+    }
+    Ok(timer)
+}
+
+/// Run the calling thread with the real-time FIFO policy at `priority`.
+#[cfg(target_os = "linux")]
+pub fn set_rt_priority(priority: i32) -> io::Result<()> {
+    let param = libc::sched_param {
+        sched_priority: priority,
+    };
+    cvt(unsafe { libc::sched_setscheduler(0, libc::SCHED_FIFO, &param) })?;
+    Ok(())
+}
+
+/// Run the calling thread on `cpu` only.
+#[cfg(target_os = "linux")]
+pub fn pin_cpu(cpu: usize) -> io::Result<()> {
+    let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+    unsafe { libc::CPU_SET(cpu, &mut set) };
+    cvt(unsafe { libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) })?;
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_rt_priority(_priority: i32) -> io::Result<()> {
+    Err(linux_only())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn pin_cpu(_cpu: usize) -> io::Result<()> {
+    Err(linux_only())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn linux_only() -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, "only supported on Linux")
+}
+
+/// Parse a real-time priority, for use as a clap value parser.
+pub fn parse_rt_priority(s: &str) -> Result<i32, String> {
+    match s.parse::<i32>() {
+        Ok(priority) if (1..=99).contains(&priority) => Ok(priority),
+        _ => Err(format!("invalid priority `{}`, must be 1 to 99", s)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tuning() {
+        #[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+        assert_eq!(mem::size_of::<SerialStruct>(), 72);
+        assert!(latency_timer_path(Path::new("/dev/null")).is_err());
+        assert_eq!(parse_rt_priority("50"), Ok(50));
+        assert!(parse_rt_priority("0").is_err());
+    }
+}
@@ -0,0 +1,122 @@
+//! CRSF over WebSocket, for browser-based tools and for machines that can
+//! only make outgoing connections.
+//!
+//! - `ws://HOST:PORT/PATH` connects to a WebSocket server;
+//! - `ws-listen://ADDR` waits for one WebSocket client on `ADDR`.
+//!
+//! Each telemetry frame is sent as one binary message. Received binary
+//! messages are parsed like serial data, so they may hold any number of
+//! frames, or parts of them; other messages are ignored.
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use futures_util::{SinkExt, StreamExt};
+use log::info;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::tungstenite::protocol::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+/// Connect to the WebSocket server at `url`.
+pub async fn connect(url: &str) -> io::Result<WebSocket<MaybeTlsStream<TcpStream>>> {
+    let (stream, _) = tokio_tungstenite::connect_async(url)
+        .await
+        .map_err(io::Error::other)?;
+    Ok(WebSocket::new(stream))
+}
+
+/// Wait for a WebSocket client on `addr`.
+pub async fn accept(addr: &str) -> io::Result<WebSocket<TcpStream>> {
+    let listener = TcpListener::bind(addr).await?;
+    info!(
+        "Waiting for a WebSocket client on {}",
+        listener.local_addr()?
+    );
+    let (socket, peer) = listener.accept().await?;
+    socket.set_nodelay(true)?;
+    let stream = tokio_tungstenite::accept_async(socket)
+        .await
+        .map_err(io::Error::other)?;
+    info!("WebSocket client {} connected", peer);
+    Ok(WebSocket::new(stream))
+}
+
+/// A WebSocket as a byte stream.
+///
+/// A write that returns `Pending` must be retried with the same data, as
+/// `write_all` does: the message in flight is not taken from it again.
+pub struct WebSocket<S> {
+    inner: WebSocketStream<S>,
+    /// Received data not read yet.
+    pending: Vec<u8>,
+    /// Whether a message was queued and is being flushed.
+    sending: bool,
+}
+
+impl<S> WebSocket<S> {
+    fn new(inner: WebSocketStream<S>) -> Self {
+        Self {
+            inner,
+            pending: Vec::new(),
+            sending: false,
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for WebSocket<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        while this.pending.is_empty() {
+            match ready!(this.inner.poll_next_unpin(cx)) {
+                Some(Ok(Message::Binary(data))) => this.pending = data,
+                Some(Ok(Message::Close(_))) | None => return Poll::Ready(Ok(())),
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Poll::Ready(Err(io::Error::other(e))),
+            }
+        }
+        let n = this.pending.len().min(buf.remaining());
+        buf.put_slice(&this.pending[..n]);
+        this.pending.drain(..n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for WebSocket<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if !this.sending {
+            ready!(this.inner.poll_ready_unpin(cx)).map_err(io::Error::other)?;
+            this.inner
+                .start_send_unpin(Message::Binary(buf.to_vec()))
+                .map_err(io::Error::other)?;
+            this.sending = true;
+        }
+        ready!(this.inner.poll_flush_unpin(cx)).map_err(io::Error::other)?;
+        this.sending = false;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut()
+            .inner
+            .poll_flush_unpin(cx)
+            .map_err(io::Error::other)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut()
+            .inner
+            .poll_close_unpin(cx)
+            .map_err(io::Error::other)
+    }
+}
@@ -0,0 +1,94 @@
+//! The position fix served to clients, built from CRSF telemetry.
+
+use telemetry_lib::{crsf, geo};
+
+/// Knots per m/s.
+const MPS_TO_KNOTS: f64 = 3600.0 / 1852.0;
+/// Default range error per unit of DOP, m.
+pub const DEFAULT_UERE: f64 = 2.5;
+/// Reported speed accuracy, m/s.
+pub const SPEED_ACCURACY: f64 = 0.3;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FixMode {
+    /// Horizontal position only; the altitude is not valid.
+    TwoD,
+    #[default]
+    ThreeD,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fix {
+    pub mode: FixMode,
+    /// Degrees, positive north.
+    pub lat: f64,
+    /// Degrees, positive east.
+    pub lon: f64,
+    /// Altitude above mean sea level, m.
+    pub alt: f64,
+    /// Speed over ground, m/s.
+    pub speed: f64,
+    /// Course over ground, degrees true.
+    pub track: f64,
+    /// Vertical speed, m/s, positive up; `None` without vario telemetry.
+    pub climb: Option<f64>,
+    /// Satellites used, as reported by the flight controller.
+    pub sats: u32,
+    /// Factor the DOP values of the satellite geometry are multiplied by,
+    /// 1 for a clear sky.
+    pub dop_scale: f64,
+    /// Range error per unit of DOP (user equivalent range error), m. The
+    /// reported accuracies are the DOP values times this.
+    pub uere: f64,
+}
+
+impl Fix {
+    pub fn from_crsf(gps: &crsf::Gps, vario: Option<&crsf::Vario>) -> Self {
+        Self {
+            mode: FixMode::ThreeD,
+            lat: gps.lat_deg(),
+            lon: gps.lon_deg(),
+            alt: gps.alt_m(),
+            speed: gps.speed_kmh() / 3.6,
+            track: gps.heading_deg(),
+            climb: vario.map(|v| v.vertical_speed as f64 / 100.0),
+            sats: gps.sats as u32,
+            dop_scale: 1.0,
+            uere: DEFAULT_UERE,
+        }
+    }
+
+    pub fn speed_knots(&self) -> f64 {
+        self.speed * MPS_TO_KNOTS
+    }
+
+    /// Magnetic declination here, degrees, positive east.
+    pub fn magvar(&self) -> f64 {
+        geo::magnetic_declination(self.lat, self.lon)
+    }
+
+    /// Course over ground, degrees magnetic.
+    pub fn magtrack(&self) -> f64 {
+        (self.track - self.magvar()).rem_euclid(360.0)
+    }
+}
+
+#[cfg(test)]
+impl Fix {
+    /// A 3D fix at `lat`, `lon` and `alt`, standing still, with 9
+    /// satellites under a clear sky.
+    pub fn at(lat: f64, lon: f64, alt: f64) -> Self {
+        Self {
+            mode: FixMode::ThreeD,
+            lat,
+            lon,
+            alt,
+            speed: 0.0,
+            track: 0.0,
+            climb: None,
+            sats: 9,
+            dop_scale: 1.0,
+            uere: DEFAULT_UERE,
+        }
+    }
+}
@@ -0,0 +1,670 @@
+//! The gpsd JSON protocol: client commands and the report objects sent
+//! back (see gpsd_json(5)).
+//!
+//! Commands start with `?` and are terminated by `;` or a newline, e.g.
+//! `?WATCH={"enable":true,"json":true};`. Every reply and report is one
+//! JSON object per line.
+//!
+//! `?LIFTOFF` is an extension: `?LIFTOFF={"set_home":true};` moves the
+//! drone's current position to the home location, and
+//! `?LIFTOFF={"set_home":{"lat":47.4,"lon":8.5,"alt":410}};` does so with a
+//! new home location. `"device"` limits it to one device. The reply is a
+//! `LIFTOFF` object per device with its home; `?LIFTOFF;` only reports.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::fix::{Fix, FixMode, SPEED_ACCURACY};
+use crate::home::Home;
+use crate::sats::Constellation;
+
+/// gpsd release whose protocol version is emulated.
+const RELEASE: &str = "3.25";
+const PROTO_MAJOR: u32 = 3;
+const PROTO_MINOR: u32 = 15;
+
+/// Round to two decimals.
+fn round(v: f64) -> f64 {
+    (v * 100.0).round() / 100.0
+}
+
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// A client command.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Version,
+    /// `?WATCH;` or `?WATCH={...};`, with the JSON argument if any.
+    Watch(Option<String>),
+    Poll,
+    Devices,
+    /// `?DEVICE;` or `?DEVICE={...};`, with the JSON argument if any.
+    Device(Option<String>),
+    /// `?LIFTOFF;` or `?LIFTOFF={...};`, with the JSON argument if any.
+    Liftoff(Option<String>),
+    Unknown(String),
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Self {
+        let (name, arg) = match line.split_once('=') {
+            Some((name, arg)) => (name, Some(arg.to_string())),
+            None => (line, None),
+        };
+        match (name, arg) {
+            ("?VERSION", None) => Command::Version,
+            ("?WATCH", arg) => Command::Watch(arg),
+            ("?POLL", None) => Command::Poll,
+            ("?DEVICES", None) => Command::Devices,
+            ("?DEVICE", arg) => Command::Device(arg),
+            ("?LIFTOFF", arg) => Command::Liftoff(arg),
+            _ => Command::Unknown(line.to_string()),
+        }
+    }
+}
+
+/// Splits the bytes received from a client into commands.
+#[derive(Debug, Default)]
+pub struct CommandBuffer {
+    buf: Vec<u8>,
+}
+
+impl CommandBuffer {
+    /// Longest command kept; anything longer is discarded.
+    const MAX_LEN: usize = 4096;
+
+    pub fn push(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    /// The next complete, non-empty command, if any.
+    pub fn next_command(&mut self) -> Option<String> {
+        loop {
+            let Some(end) = self.buf.iter().position(|&b| b == b';' || b == b'\n') else {
+                if self.buf.len() > Self::MAX_LEN {
+                    self.buf.clear();
+                }
+                return None;
+            };
+            let line: Vec<u8> = self.buf.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line[..end]).trim().to_string();
+            if !line.is_empty() {
+                return Some(line);
+            }
+        }
+    }
+}
+
+/// Per-client watch policy, as set by `?WATCH`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Watch {
+    pub enable: bool,
+    /// Send TPV and SKY reports.
+    pub json: bool,
+    /// Send NMEA sentences.
+    pub nmea: bool,
+    /// 1: send the device's native data, which is NMEA here. 2 (binary
+    /// super-raw) is not supported.
+    pub raw: u8,
+    /// Accepted and reported back; there are no scaled fields to apply it
+    /// to.
+    pub scaled: bool,
+    pub timing: bool,
+    pub split24: bool,
+    /// There is no PPS source, so no PPS reports are sent.
+    pub pps: bool,
+    /// Only report this device.
+    pub device: Option<String>,
+}
+
+impl Watch {
+    /// Apply the fields present in a `?WATCH=` argument; absent fields
+    /// keep their value. `devices` are the paths that can be watched. On
+    /// error the watch is left unchanged.
+    pub fn update(&mut self, arg: &str, devices: &[&str]) -> Result<(), String> {
+        let val: Value = serde_json::from_str(arg).map_err(|e| e.to_string())?;
+        let obj = val.as_object().ok_or("expected a JSON object")?;
+        let mut new = self.clone();
+        for (name, val) in obj {
+            let flag = || {
+                val.as_bool()
+                    .ok_or_else(|| format!("{} must be a boolean", name))
+            };
+            match name.as_str() {
+                "class" => {}
+                "enable" => new.enable = flag()?,
+                "json" => new.json = flag()?,
+                "nmea" => new.nmea = flag()?,
+                "scaled" => new.scaled = flag()?,
+                "timing" => new.timing = flag()?,
+                "split24" => new.split24 = flag()?,
+                "pps" => new.pps = flag()?,
+                // libgps sends a number, some clients a boolean.
+                "raw" => {
+                    new.raw = match val {
+                        Value::Bool(raw) => *raw as u8,
+                        Value::Number(n) => match n.as_u64() {
+                            Some(raw @ 0..=1) => raw as u8,
+                            Some(2) => return Err("raw 2 (binary) is not supported".into()),
+                            _ => return Err("raw must be 0, 1 or 2".into()),
+                        },
+                        _ => return Err("raw must be a number".into()),
+                    }
+                }
+                "device" => {
+                    let path = val.as_str().ok_or("device must be a string")?;
+                    if !devices.contains(&path) {
+                        return Err(format!("no such device {}", path));
+                    }
+                    new.device = Some(path.to_string());
+                }
+                // Unknown fields are ignored, like gpsd does.
+                _ => {}
+            }
+        }
+        // Like gpsd, a watch that asks for nothing in particular gets JSON.
+        if new.enable && !new.json && !new.nmea && new.raw == 0 {
+            new.json = true;
+        }
+        *self = new;
+        Ok(())
+    }
+
+    /// Whether reports of device `path` go to this client.
+    pub fn watches(&self, path: &str) -> bool {
+        self.enable && self.device.as_deref().is_none_or(|d| d == path)
+    }
+
+    /// Whether NMEA sentences go to this client.
+    pub fn wants_nmea(&self) -> bool {
+        self.nmea || self.raw > 0
+    }
+
+    pub fn report(&self) -> WatchReport {
+        WatchReport {
+            class: "WATCH",
+            enable: self.enable,
+            json: self.json,
+            nmea: self.nmea,
+            raw: self.raw,
+            scaled: self.scaled,
+            timing: self.timing,
+            split24: self.split24,
+            pps: self.pps,
+            device: self.device.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct VersionReport {
+    class: &'static str,
+    release: &'static str,
+    rev: &'static str,
+    proto_major: u32,
+    proto_minor: u32,
+}
+
+impl VersionReport {
+    pub fn new() -> Self {
+        Self {
+            class: "VERSION",
+            release: RELEASE,
+            rev: env!("CARGO_PKG_VERSION"),
+            proto_major: PROTO_MAJOR,
+            proto_minor: PROTO_MINOR,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct WatchReport {
+    class: &'static str,
+    enable: bool,
+    json: bool,
+    nmea: bool,
+    raw: u8,
+    scaled: bool,
+    timing: bool,
+    split24: bool,
+    pps: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeviceReport {
+    class: &'static str,
+    path: String,
+    driver: &'static str,
+    activated: String,
+    flags: u32,
+    native: u32,
+}
+
+impl DeviceReport {
+    /// `flags` bit 0: the device has seen GPS data.
+    pub fn new(path: &str, activated: DateTime<Utc>, seen_gps: bool) -> Self {
+        Self {
+            class: "DEVICE",
+            path: path.to_string(),
+            driver: "CRSF",
+            activated: timestamp(activated),
+            flags: seen_gps as u32,
+            native: 0,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DevicesReport {
+    class: &'static str,
+    devices: Vec<DeviceReport>,
+}
+
+impl DevicesReport {
+    pub fn new(devices: Vec<DeviceReport>) -> Self {
+        Self {
+            class: "DEVICES",
+            devices,
+        }
+    }
+}
+
+/// Time-position-velocity report.
+#[derive(Debug, Serialize)]
+pub struct Tpv {
+    class: &'static str,
+    device: String,
+    /// 1: no fix, 3: 3D fix.
+    mode: u8,
+    time: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lat: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lon: Option<f64>,
+    #[serde(rename = "altMSL", skip_serializing_if = "Option::is_none")]
+    alt_msl: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alt: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    track: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    magtrack: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    magvar: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    speed: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    climb: Option<f64>,
+    /// Speed error, m/s.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    eps: Option<f64>,
+}
+
+impl Tpv {
+    pub fn new(device: &str, time: DateTime<Utc>, fix: Option<&Fix>) -> Self {
+        let fix_3d = fix.filter(|f| f.mode == FixMode::ThreeD);
+        Self {
+            class: "TPV",
+            device: device.to_string(),
+            mode: match fix.map(|f| f.mode) {
+                None => 1,
+                Some(FixMode::TwoD) => 2,
+                Some(FixMode::ThreeD) => 3,
+            },
+            time: timestamp(time),
+            lat: fix.map(|f| f.lat),
+            lon: fix.map(|f| f.lon),
+            alt_msl: fix_3d.map(|f| f.alt),
+            alt: fix_3d.map(|f| f.alt),
+            track: fix.map(|f| f.track),
+            magtrack: fix.map(|f| round(f.magtrack())),
+            magvar: fix.map(|f| round(f.magvar())),
+            speed: fix.map(|f| f.speed),
+            climb: fix_3d.and_then(|f| f.climb),
+            eps: fix.map(|_| SPEED_ACCURACY),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SkySatellite {
+    #[serde(rename = "PRN")]
+    prn: u8,
+    el: f64,
+    az: f64,
+    ss: f64,
+    used: bool,
+    /// GNSS ID: GPS.
+    gnssid: u8,
+    svid: u8,
+}
+
+/// Satellite report.
+#[derive(Debug, Serialize)]
+pub struct Sky {
+    class: &'static str,
+    device: String,
+    time: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hdop: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vdop: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pdop: Option<f64>,
+    #[serde(rename = "nSat")]
+    n_sat: u32,
+    #[serde(rename = "uSat")]
+    u_sat: u32,
+    satellites: Vec<SkySatellite>,
+}
+
+impl Sky {
+    pub fn new(device: &str, time: DateTime<Utc>, sky: &Constellation) -> Self {
+        // Rounded like gpsd does, to keep the reports short.
+        Self {
+            class: "SKY",
+            device: device.to_string(),
+            time: timestamp(time),
+            hdop: sky.dop.map(|d| round(d.hdop)),
+            vdop: sky.dop.map(|d| round(d.vdop)),
+            pdop: sky.dop.map(|d| round(d.pdop)),
+            n_sat: sky.sats.len() as u32,
+            u_sat: sky.num_used(),
+            satellites: sky
+                .sats
+                .iter()
+                .map(|s| SkySatellite {
+                    prn: s.prn,
+                    el: round(s.elevation),
+                    az: round(s.azimuth),
+                    ss: s.snr as f64,
+                    used: s.used,
+                    gnssid: 0,
+                    svid: s.prn,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Reply to `?POLL`: the latest reports of every active device.
+#[derive(Debug, Serialize)]
+pub struct PollReport {
+    class: &'static str,
+    time: String,
+    active: usize,
+    tpv: Vec<Tpv>,
+    sky: Vec<Sky>,
+}
+
+impl PollReport {
+    pub fn new(time: DateTime<Utc>, tpv: Vec<Tpv>, sky: Vec<Sky>) -> Self {
+        Self {
+            class: "POLL",
+            time: timestamp(time),
+            active: tpv.len(),
+            tpv,
+            sky,
+        }
+    }
+}
+
+/// The device path a `?DEVICE=` argument refers to, if it names one.
+/// Other settings in the argument (speed, mode, ...) don't apply to a
+/// telemetry stream and are ignored.
+pub fn device_path(arg: &str) -> Result<Option<String>, String> {
+    let val: Value = serde_json::from_str(arg).map_err(|e| e.to_string())?;
+    let obj = val.as_object().ok_or("expected a JSON object")?;
+    match obj.get("path") {
+        None => Ok(None),
+        Some(Value::String(path)) => Ok(Some(path.clone())),
+        Some(_) => Err("path must be a string".to_string()),
+    }
+}
+
+/// What `set_home` in a `?LIFTOFF=` argument asks for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SetHome {
+    /// Move the current position to the home location.
+    Here,
+    /// Move the current position to a new home location.
+    At(Home),
+}
+
+/// A `?LIFTOFF=` argument.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LiftoffRequest {
+    /// The device it applies to; all if `None`.
+    pub device: Option<String>,
+    pub set_home: Option<SetHome>,
+}
+
+impl LiftoffRequest {
+    pub fn parse(arg: &str) -> Result<Self, String> {
+        let val: Value = serde_json::from_str(arg).map_err(|e| e.to_string())?;
+        let obj = val.as_object().ok_or("expected a JSON object")?;
+        let device = match obj.get("device") {
+            None => None,
+            Some(Value::String(path)) => Some(path.clone()),
+            Some(_) => return Err("device must be a string".to_string()),
+        };
+        let set_home = match obj.get("set_home") {
+            None | Some(Value::Bool(false)) => None,
+            Some(Value::Bool(true)) => Some(SetHome::Here),
+            Some(Value::Object(home)) => {
+                let get = |key: &str| home.get(key).and_then(Value::as_f64);
+                let (Some(lat), Some(lon)) = (get("lat"), get("lon")) else {
+                    return Err("set_home needs a lat and lon".to_string());
+                };
+                if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+                    return Err("set_home lat or lon out of range".to_string());
+                }
+                let alt = get("alt").unwrap_or(0.0);
+                Some(SetHome::At(Home { lat, lon, alt }))
+            }
+            Some(_) => return Err("set_home must be a boolean or an object".to_string()),
+        };
+        Ok(Self { device, set_home })
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct HomeReport {
+    lat: f64,
+    lon: f64,
+    alt: f64,
+}
+
+/// A device's home location, in reply to `?LIFTOFF`.
+#[derive(Debug, Serialize)]
+pub struct LiftoffReport {
+    class: &'static str,
+    device: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    home: Option<HomeReport>,
+}
+
+impl LiftoffReport {
+    pub fn new(device: &str, home: Option<&Home>) -> Self {
+        Self {
+            class: "LIFTOFF",
+            device: device.to_string(),
+            home: home.map(|h| HomeReport {
+                lat: h.lat,
+                lon: h.lon,
+                alt: h.alt,
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorReport {
+    class: &'static str,
+    message: String,
+}
+
+impl ErrorReport {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            class: "ERROR",
+            message: message.into(),
+        }
+    }
+}
+
+/// Serialize a report as one protocol line.
+pub fn line(report: &impl Serialize) -> String {
+    let mut s = serde_json::to_string(report).expect("reports always serialize");
+    s.push('\n');
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn command_buffer() {
+        let mut buf = CommandBuffer::default();
+        buf.push(b"?WATCH={\"enable\":true};\n?VERS");
+        assert_eq!(
+            buf.next_command(),
+            Some("?WATCH={\"enable\":true}".to_string())
+        );
+        assert_eq!(buf.next_command(), None);
+        buf.push(b"ION\n");
+        assert_eq!(buf.next_command(), Some("?VERSION".to_string()));
+        assert_eq!(Command::parse("?VERSION"), Command::Version);
+        assert_eq!(
+            Command::parse("?WATCH={}"),
+            Command::Watch(Some("{}".to_string()))
+        );
+        assert_eq!(Command::parse("?FOO"), Command::Unknown("?FOO".to_string()));
+        assert_eq!(Command::parse("?LIFTOFF"), Command::Liftoff(None));
+    }
+
+    #[test]
+    fn liftoff_request() {
+        assert_eq!(
+            LiftoffRequest::parse(r#"{"set_home":true}"#),
+            Ok(LiftoffRequest {
+                device: None,
+                set_home: Some(SetHome::Here),
+            })
+        );
+        let home = Home {
+            lat: 47.4,
+            lon: 8.5,
+            alt: 0.0,
+        };
+        assert_eq!(
+            LiftoffRequest::parse(r#"{"device":"d","set_home":{"lat":47.4,"lon":8.5}}"#),
+            Ok(LiftoffRequest {
+                device: Some("d".to_string()),
+                set_home: Some(SetHome::At(home)),
+            })
+        );
+        assert_eq!(LiftoffRequest::parse("{}"), Ok(LiftoffRequest::default()));
+        for arg in [
+            r#"{"set_home":1}"#,
+            r#"{"set_home":{"lat":47.4}}"#,
+            r#"{"set_home":{"lat":91,"lon":0}}"#,
+            r#"{"device":1}"#,
+            "[]",
+        ] {
+            assert!(LiftoffRequest::parse(arg).is_err(), "{}", arg);
+        }
+        assert_eq!(
+            line(&LiftoffReport::new("d", Some(&home))),
+            "{\"class\":\"LIFTOFF\",\"device\":\"d\",\"home\":{\"lat\":47.4,\"lon\":8.5,\"alt\":0.0}}\n"
+        );
+    }
+
+    #[test]
+    fn watch_update() {
+        let devices = ["dev"];
+        let mut watch = Watch::default();
+        watch
+            .update(r#"{"enable":true,"json":true}"#, &devices)
+            .unwrap();
+        assert!(watch.enable && watch.json && !watch.wants_nmea());
+        watch
+            .update(r#"{"nmea":true,"json":false}"#, &devices)
+            .unwrap();
+        assert!(watch.enable && !watch.json && watch.wants_nmea());
+        assert!(watch.watches("dev") && watch.watches("other"));
+        // Enabling without a format selects JSON.
+        let mut watch = Watch::default();
+        watch.update(r#"{"enable":true}"#, &devices).unwrap();
+        assert!(watch.json);
+
+        // The classic NMEA-only client.
+        let mut watch = Watch::default();
+        watch
+            .update(r#"{"enable":true,"raw":1,"device":"dev"}"#, &devices)
+            .unwrap();
+        assert!(!watch.json && watch.wants_nmea());
+        assert!(watch.watches("dev") && !watch.watches("other"));
+        watch
+            .update(r#"{"raw":false,"nmea":true,"scaled":true}"#, &devices)
+            .unwrap();
+        assert_eq!(watch.raw, 0);
+        assert!(watch.scaled && watch.wants_nmea());
+
+        // Rejected arguments leave the watch alone.
+        let before = watch.clone();
+        for arg in [
+            "[1]",
+            "{",
+            r#"{"enable":false,"raw":2}"#,
+            r#"{"enable":false,"raw":"1"}"#,
+            r#"{"enable":false,"json":1}"#,
+            r#"{"enable":false,"device":"other"}"#,
+        ] {
+            assert!(watch.update(arg, &devices).is_err(), "{}", arg);
+            assert_eq!(watch, before);
+        }
+    }
+
+    #[test]
+    fn tpv_report() {
+        let time = Utc.with_ymd_and_hms(2024, 5, 6, 7, 8, 9).unwrap();
+        let mut fix = Fix {
+            speed: 10.0,
+            track: 90.0,
+            climb: Some(-1.5),
+            ..Fix::at(52.5, -4.25, 12.5)
+        };
+        assert_eq!(
+            line(&Tpv::new("dev", time, Some(&fix))),
+            "{\"class\":\"TPV\",\"device\":\"dev\",\"mode\":3,\
+             \"time\":\"2024-05-06T07:08:09.000Z\",\"lat\":52.5,\"lon\":-4.25,\
+             \"altMSL\":12.5,\"alt\":12.5,\"track\":90.0,\"magtrack\":90.86,\"magvar\":-0.86,\
+             \"speed\":10.0,\"climb\":-1.5,\"eps\":0.3}\n"
+        );
+        fix.mode = FixMode::TwoD;
+        assert_eq!(
+            line(&Tpv::new("dev", time, Some(&fix))),
+            "{\"class\":\"TPV\",\"device\":\"dev\",\"mode\":2,\
+             \"time\":\"2024-05-06T07:08:09.000Z\",\"lat\":52.5,\"lon\":-4.25,\
+             \"track\":90.0,\"magtrack\":90.86,\"magvar\":-0.86,\"speed\":10.0,\"eps\":0.3}\n"
+        );
+        assert_eq!(
+            line(&Tpv::new("dev", time, None)),
+            "{\"class\":\"TPV\",\"device\":\"dev\",\"mode\":1,\
+             \"time\":\"2024-05-06T07:08:09.000Z\"}\n"
+        );
+        assert_eq!(
+            line(&PollReport::new(time, vec![], vec![])),
+            "{\"class\":\"POLL\",\"time\":\"2024-05-06T07:08:09.000Z\",\
+             \"active\":0,\"tpv\":[],\"sky\":[]}\n"
+        );
+    }
+}
@@ -0,0 +1,230 @@
+//! GPX track logging (`--gpx-out`).
+//!
+//! Each session gets its own file, named after its start time. A session
+//! starts at the first fix, and ends when there has been no fix for
+//! [`SESSION_GAP`]; shorter losses of fix end the current track segment.
+//!
+//! The closing tags are rewritten after every point, so the file is valid
+//! GPX at any time, also if the process is killed.
+
+use std::fs::{self, File};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::info;
+
+use crate::fix::{Fix, FixMode};
+
+/// A loss of fix longer than this starts a new file.
+pub const SESSION_GAP: Duration = Duration::from_secs(60);
+
+struct Track {
+    path: PathBuf,
+    file: File,
+    /// Length of the file without the closing tags.
+    end: u64,
+    in_segment: bool,
+}
+
+impl Track {
+    fn create(path: PathBuf, name: &str, time: DateTime<Utc>) -> io::Result<Self> {
+        let file = File::create(&path)?;
+        let mut track = Self {
+            path,
+            file,
+            end: 0,
+            in_segment: false,
+        };
+        let time = time.to_rfc3339_opts(SecondsFormat::Millis, true);
+        track.append(&format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <gpx version=\"1.1\" creator=\"crsf-gpsd\" xmlns=\"http://www.topografix.com/GPX/1/1\">\n\
+             \x20 <metadata>\n\
+             \x20   <name>{name}</name>\n\
+             \x20   <time>{time}</time>\n\
+             \x20 </metadata>\n\
+             \x20 <trk>\n\
+             \x20   <name>{name}</name>\n",
+            name = escape(name),
+            time = time
+        ))?;
+        Ok(track)
+    }
+
+    fn footer(&self) -> &'static str {
+        if self.in_segment {
+            "    </trkseg>\n  </trk>\n</gpx>\n"
+        } else {
+            "  </trk>\n</gpx>\n"
+        }
+    }
+
+    /// Append `text` before the closing tags.
+    fn append(&mut self, text: &str) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(self.end))?;
+        self.file.write_all(text.as_bytes())?;
+        self.end += text.len() as u64;
+        let footer = self.footer();
+        self.file.write_all(footer.as_bytes())?;
+        self.file.set_len(self.end + footer.len() as u64)?;
+        self.file.flush()
+    }
+
+    fn point(&mut self, time: DateTime<Utc>, fix: &Fix, sats: u32) -> io::Result<()> {
+        let mut text = String::new();
+        if !self.in_segment {
+            text += "    <trkseg>\n";
+        }
+        text += &format!(
+            "      <trkpt lat=\"{:.7}\" lon=\"{:.7}\">",
+            fix.lat, fix.lon
+        );
+        if fix.mode == FixMode::ThreeD {
+            text += &format!("<ele>{:.2}</ele>", fix.alt);
+        }
+        text += &format!(
+            "<time>{}</time><fix>{}</fix><sat>{}</sat></trkpt>\n",
+            time.to_rfc3339_opts(SecondsFormat::Millis, true),
+            match fix.mode {
+                FixMode::TwoD => "2d",
+                FixMode::ThreeD => "3d",
+            },
+            sats
+        );
+        // The footer depends on the segment state, so update it first.
+        self.in_segment = true;
+        self.append(&text)
+    }
+
+    fn end_segment(&mut self) -> io::Result<()> {
+        if self.in_segment {
+            self.in_segment = false;
+            self.append("    </trkseg>\n")?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the fixes to GPX files in a directory.
+pub struct GpxLog {
+    dir: PathBuf,
+    /// Track name, e.g. the home preset.
+    name: String,
+    track: Option<Track>,
+    last_fix: Option<Instant>,
+}
+
+impl GpxLog {
+    pub fn new(dir: &Path, name: &str) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            name: name.to_string(),
+            track: None,
+            last_fix: None,
+        })
+    }
+
+    /// Log `fix` (or the lack of one) at `now`/`time`. `sats` is the number
+    /// of satellites used.
+    pub fn record(
+        &mut self,
+        now: Instant,
+        time: DateTime<Utc>,
+        fix: Option<&Fix>,
+        sats: u32,
+    ) -> io::Result<()> {
+        let Some(fix) = fix else {
+            if let Some(track) = &mut self.track {
+                track.end_segment()?;
+            }
+            return Ok(());
+        };
+        let new_session = self
+            .last_fix
+            .is_none_or(|t| now.saturating_duration_since(t) > SESSION_GAP);
+        if new_session {
+            let path = self
+                .dir
+                .join(format!("{}.gpx", time.format("%Y%m%d-%H%M%S")));
+            let track = Track::create(path, &self.name, time)?;
+            info!("Logging GPX track to {}", track.path.display());
+            self.track = Some(track);
+        }
+        self.last_fix = Some(now);
+        if let Some(track) = &mut self.track {
+            track.point(time, fix, sats)?;
+        }
+        Ok(())
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn sessions_and_segments() {
+        let dir = std::env::temp_dir().join(format!("crsf-gpsd-gpx-{}", std::process::id()));
+        let mut log = GpxLog::new(&dir, "zurich").unwrap();
+        let t0 = Instant::now();
+        let time0 = Utc.with_ymd_and_hms(2024, 5, 6, 7, 8, 9).unwrap();
+        let at = |s: u64| {
+            (
+                t0 + Duration::from_secs(s),
+                time0 + chrono::Duration::seconds(s as i64),
+            )
+        };
+        let mut fix = Fix::at(47.5, 8.5, 410.0);
+
+        let (now, time) = at(0);
+        log.record(now, time, None, 0).unwrap();
+        assert!(log.track.is_none(), "no file before the first fix");
+        let (now, time) = at(1);
+        log.record(now, time, Some(&fix), 9).unwrap();
+        let (now, time) = at(2);
+        log.record(now, time, None, 0).unwrap();
+        fix.mode = FixMode::TwoD;
+        let (now, time) = at(3);
+        log.record(now, time, Some(&fix), 9).unwrap();
+
+        let first = dir.join("20240506-070810.gpx");
+        assert_eq!(
+            fs::read_to_string(&first).unwrap(),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <gpx version=\"1.1\" creator=\"crsf-gpsd\" xmlns=\"http://www.topografix.com/GPX/1/1\">\n\
+             \x20 <metadata>\n\
+             \x20   <name>zurich</name>\n\
+             \x20   <time>2024-05-06T07:08:10.000Z</time>\n\
+             \x20 </metadata>\n\
+             \x20 <trk>\n\
+             \x20   <name>zurich</name>\n\
+             \x20   <trkseg>\n\
+             \x20     <trkpt lat=\"47.5000000\" lon=\"8.5000000\"><ele>410.00</ele>\
+             <time>2024-05-06T07:08:10.000Z</time><fix>3d</fix><sat>9</sat></trkpt>\n\
+             \x20   </trkseg>\n\
+             \x20   <trkseg>\n\
+             \x20     <trkpt lat=\"47.5000000\" lon=\"8.5000000\">\
+             <time>2024-05-06T07:08:12.000Z</time><fix>2d</fix><sat>9</sat></trkpt>\n\
+             \x20   </trkseg>\n\
+             \x20 </trk>\n\
+             </gpx>\n"
+        );
+
+        // A long loss of fix starts a new file.
+        let (now, time) = at(100);
+        log.record(now, time, Some(&fix), 9).unwrap();
+        assert!(dir.join("20240506-070949.gpx").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
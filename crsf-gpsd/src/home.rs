@@ -0,0 +1,157 @@
+//! Moving the scene to a real-world location.
+//!
+//! The sim bridges place the scene origin at 0°N 0°E. A home location
+//! re-bases the reported positions onto a chosen spot, keeping the local
+//! offsets (in metres) from the origin, and raises the altitude by the home
+//! altitude. A client can move the origin to the drone's current position
+//! (`?LIFTOFF`), e.g. after switching to another map.
+
+use telemetry_lib::geo;
+
+use crate::fix::Fix;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Home {
+    /// Degrees, positive north.
+    pub lat: f64,
+    /// Degrees, positive east.
+    pub lon: f64,
+    /// Altitude of the scene origin above mean sea level, m.
+    pub alt: f64,
+}
+
+/// Named home locations for `--home`.
+pub const PRESETS: &[(&str, Home)] = &[
+    (
+        "null-island",
+        Home {
+            lat: 0.0,
+            lon: 0.0,
+            alt: 0.0,
+        },
+    ),
+    (
+        "greenwich",
+        Home {
+            lat: 51.47787,
+            lon: -0.00147,
+            alt: 46.0,
+        },
+    ),
+    (
+        "amsterdam",
+        Home {
+            lat: 52.37308,
+            lon: 4.89245,
+            alt: 0.0,
+        },
+    ),
+    (
+        "zurich",
+        Home {
+            lat: 47.36667,
+            lon: 8.55,
+            alt: 408.0,
+        },
+    ),
+    (
+        "golden-gate",
+        Home {
+            lat: 37.80775,
+            lon: -122.47499,
+            alt: 67.0,
+        },
+    ),
+    (
+        "sydney",
+        Home {
+            lat: -33.85678,
+            lon: 151.21530,
+            alt: 5.0,
+        },
+    ),
+];
+
+/// Look up a preset by name, for use as a clap value parser.
+pub fn parse_preset(name: &str) -> Result<(&'static str, Home), String> {
+    PRESETS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .copied()
+        .ok_or_else(|| {
+            let names: Vec<&str> = PRESETS.iter().map(|(n, _)| *n).collect();
+            format!("unknown preset `{}` (one of: {})", name, names.join(", "))
+        })
+}
+
+/// Latitude, longitude (degrees) and altitude (m) of a point in the scene.
+pub type ScenePoint = (f64, f64, f64);
+
+/// The scene origin.
+pub const SCENE_ORIGIN: ScenePoint = (0.0, 0.0, 0.0);
+
+impl Home {
+    /// Move `fix` from around `origin` in the scene to around this home.
+    pub fn rebase(&self, origin: ScenePoint, fix: &mut Fix) {
+        let (origin_lat, origin_lon, origin_alt) = origin;
+        let coord = geo::coord_from_gps((fix.lon, fix.lat, fix.alt), (origin_lon, origin_lat));
+        let (lon, lat, _) = geo::gps_from_coord(&coord, (self.lon, self.lat));
+        fix.lat = lat.clamp(-90.0, 90.0);
+        fix.lon = if lon.abs() > 180.0 {
+            (lon + 180.0).rem_euclid(360.0) - 180.0
+        } else {
+            lon
+        };
+        fix.alt += self.alt - origin_alt;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fix(lat: f64, lon: f64, alt: f64) -> Fix {
+        Fix {
+            sats: 8,
+            ..Fix::at(lat, lon, alt)
+        }
+    }
+
+    #[test]
+    fn rebase() {
+        let (_, home) = parse_preset("zurich").unwrap();
+        let mut f = fix(0.0, 0.0, 10.0);
+        home.rebase(SCENE_ORIGIN, &mut f);
+        assert_eq!((f.lat, f.lon, f.alt), (home.lat, home.lon, 418.0));
+
+        // 100 m north and 100 m east of the origin.
+        let mut f = fix(100.0 / 111111.0, 100.0 / 111111.0, 0.0);
+        home.rebase(SCENE_ORIGIN, &mut f);
+        let north = (f.lat - home.lat) * 111111.0;
+        let east = (f.lon - home.lon) * 111111.0 * f.lat.to_radians().cos();
+        assert!((north - 100.0).abs() < 1e-6, "{}", north);
+        assert!((east - 100.0).abs() < 1e-6, "{}", east);
+
+        // West of the antimeridian wraps around.
+        let home = Home {
+            lat: 0.0,
+            lon: 179.99999,
+            alt: 0.0,
+        };
+        let mut f = fix(0.0, 0.0001, 0.0);
+        home.rebase(SCENE_ORIGIN, &mut f);
+        assert!((f.lon - (-179.99991)).abs() < 1e-9, "{}", f.lon);
+
+        // A moved origin ends up at home, and the offsets from it are kept.
+        let origin = (0.01, -0.02, 35.0);
+        let mut f = fix(0.01, -0.02, 35.0);
+        home.rebase(origin, &mut f);
+        assert_eq!((f.lat, f.lon, f.alt), (0.0, 179.99999, 0.0));
+        let mut f = fix(0.01 + 100.0 / 111111.0, -0.02, 45.0);
+        home.rebase(origin, &mut f);
+        assert!(((f.lat - home.lat) * 111111.0 - 100.0).abs() < 1e-6);
+        assert_eq!(f.alt, 10.0);
+
+        assert!(parse_preset("atlantis").unwrap_err().contains("greenwich"));
+    }
+}
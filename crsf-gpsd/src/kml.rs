@@ -0,0 +1,242 @@
+//! Live view in Google Earth (`--kml-bind`).
+//!
+//! A small HTTP server hands out a KML NetworkLink (`/live.kml`), which
+//! makes Google Earth fetch `/update.kml` every second: the current
+//! position, the trail flown so far and, with `--kml-follow`, a view that
+//! follows the drone.
+
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::{debug, warn};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::fix::Fix;
+
+/// How often Google Earth refreshes the view.
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+/// Longest request header accepted.
+const MAX_REQUEST: usize = 8192;
+/// Distance of the follow view from the drone, m.
+const FOLLOW_RANGE: f64 = 150.0;
+/// Tilt of the follow view from vertical, degrees.
+const FOLLOW_TILT: f64 = 65.0;
+
+/// The latest fix and the trail, sampled once per [`REFRESH_INTERVAL`].
+#[derive(Debug)]
+pub struct LiveTrack {
+    fix: Option<Fix>,
+    /// Longitude, latitude, altitude.
+    trail: VecDeque<(f64, f64, f64)>,
+    max_trail: usize,
+}
+
+impl LiveTrack {
+    pub fn new(max_trail: usize) -> Self {
+        Self {
+            fix: None,
+            trail: VecDeque::new(),
+            max_trail,
+        }
+    }
+
+    pub fn push(&mut self, fix: Option<Fix>) {
+        if let Some(fix) = &fix
+            && self.max_trail > 0
+        {
+            while self.trail.len() >= self.max_trail {
+                self.trail.pop_front();
+            }
+            self.trail.push_back((fix.lon, fix.lat, fix.alt));
+        }
+        self.fix = fix;
+    }
+}
+
+/// The NetworkLink document; `base` is the URL the server is reached at.
+pub fn network_link(base: &str, follow: bool) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <kml xmlns=\"http://www.opengis.net/kml/2.2\">\n\
+         <NetworkLink>\n\
+         \x20 <name>crsf-gpsd</name>\n\
+         \x20 <flyToView>{}</flyToView>\n\
+         \x20 <Link>\n\
+         \x20   <href>{}/update.kml</href>\n\
+         \x20   <refreshMode>onInterval</refreshMode>\n\
+         \x20   <refreshInterval>{}</refreshInterval>\n\
+         \x20 </Link>\n\
+         </NetworkLink>\n\
+         </kml>\n",
+        follow as u8,
+        base,
+        REFRESH_INTERVAL.as_secs()
+    )
+}
+
+/// The document with the current position and trail.
+pub fn update(track: &LiveTrack, follow: bool) -> String {
+    let mut kml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <kml xmlns=\"http://www.opengis.net/kml/2.2\">\n\
+         <Document>\n",
+    );
+    if follow && let Some(fix) = &track.fix {
+        let _ = writeln!(
+            kml,
+            "  <LookAt><longitude>{:.7}</longitude><latitude>{:.7}</latitude>\
+             <altitude>{:.1}</altitude><heading>{:.1}</heading><tilt>{}</tilt>\
+             <range>{}</range><altitudeMode>absolute</altitudeMode></LookAt>",
+            fix.lon, fix.lat, fix.alt, fix.track, FOLLOW_TILT, FOLLOW_RANGE
+        );
+    }
+    kml += "  <Style id=\"trail\"><LineStyle><color>ff00ffff</color><width>3</width></LineStyle></Style>\n";
+    if track.trail.len() >= 2 {
+        kml += "  <Placemark><name>Trail</name><styleUrl>#trail</styleUrl><LineString>\
+                <altitudeMode>absolute</altitudeMode><coordinates>\n";
+        for (lon, lat, alt) in &track.trail {
+            let _ = writeln!(kml, "    {:.7},{:.7},{:.1}", lon, lat, alt);
+        }
+        kml += "  </coordinates></LineString></Placemark>\n";
+    }
+    if let Some(fix) = &track.fix {
+        let _ = writeln!(
+            kml,
+            "  <Placemark><name>Drone</name><Point><altitudeMode>absolute</altitudeMode>\
+             <coordinates>{:.7},{:.7},{:.1}</coordinates></Point></Placemark>",
+            fix.lon, fix.lat, fix.alt
+        );
+    }
+    kml += "</Document>\n</kml>\n";
+    kml
+}
+
+/// Path and `Host` header of an HTTP GET request.
+fn parse_request(req: &str) -> Option<(&str, Option<&str>)> {
+    let mut lines = req.lines();
+    let mut first = lines.next()?.split_whitespace();
+    if first.next()? != "GET" {
+        return None;
+    }
+    let path = first.next()?;
+    let host = lines.find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.eq_ignore_ascii_case("host").then_some(value.trim())
+    });
+    Some((path, host))
+}
+
+async fn handle(
+    mut socket: TcpStream,
+    addr: SocketAddr,
+    local: SocketAddr,
+    track: Arc<Mutex<LiveTrack>>,
+    follow: bool,
+) -> std::io::Result<()> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = socket.read(&mut chunk).await?;
+        if n == 0 || buf.len() > MAX_REQUEST {
+            return Ok(());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    let req = String::from_utf8_lossy(&buf);
+    let local = local.to_string();
+    let (status, body) = match parse_request(&req) {
+        Some(("/" | "/live.kml", host)) => (
+            "200 OK",
+            network_link(&format!("http://{}", host.unwrap_or(&local)), follow),
+        ),
+        Some(("/update.kml", _)) => {
+            let body = match track.lock() {
+                Ok(track) => update(&track, follow),
+                Err(_) => String::new(),
+            };
+            ("200 OK", body)
+        }
+        Some(_) => ("404 Not Found", String::new()),
+        None => ("400 Bad Request", String::new()),
+    };
+    debug!("KML {} from {}", status, addr);
+    let response = format!(
+        "HTTP/1.1 {}\r\n\
+         Content-Type: application/vnd.google-earth.kml+xml\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    socket.write_all(response.as_bytes()).await?;
+    socket.shutdown().await
+}
+
+/// Serve the KML documents on `listener`.
+pub async fn serve(listener: TcpListener, track: Arc<Mutex<LiveTrack>>, follow: bool) {
+    let local = match listener.local_addr() {
+        Ok(local) => local,
+        Err(e) => {
+            warn!("KML server: {}", e);
+            return;
+        }
+    };
+    loop {
+        match listener.accept().await {
+            Ok((socket, addr)) => {
+                let track = track.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle(socket, addr, local, track, follow).await {
+                        debug!("KML client {}: {}", addr, e);
+                    }
+                });
+            }
+            Err(e) => warn!("KML server accept: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn documents() {
+        assert_eq!(
+            parse_request("GET /live.kml HTTP/1.1\r\nHOST: 10.0.0.2:8090\r\n\r\n"),
+            Some(("/live.kml", Some("10.0.0.2:8090")))
+        );
+        assert_eq!(parse_request("POST / HTTP/1.1\r\n\r\n"), None);
+        assert!(network_link("http://h:1", true).contains("<href>http://h:1/update.kml</href>"));
+
+        let mut track = LiveTrack::new(2);
+        assert!(!update(&track, true).contains("Placemark"));
+        let fix = |lat| Fix {
+            track: 90.0,
+            ..Fix::at(lat, 8.5, 410.0)
+        };
+        for lat in [47.0, 47.1, 47.2] {
+            track.push(Some(fix(lat)));
+        }
+        track.push(None);
+        // The trail keeps the last points; no fix removes only the drone.
+        let kml = update(&track, true);
+        assert!(!kml.contains("47.0000000") && kml.contains("8.5000000,47.2000000,410.0"));
+        assert!(!kml.contains("<name>Drone</name>") && !kml.contains("<LookAt>"));
+
+        track.push(Some(fix(47.3)));
+        let kml = update(&track, true);
+        assert!(kml.contains("<name>Drone</name>") && kml.contains("<LookAt>"));
+        assert!(!update(&track, false).contains("<LookAt>"));
+
+        // No trail.
+        let mut track = LiveTrack::new(0);
+        track.push(Some(fix(47.0)));
+        assert!(track.trail.is_empty() && update(&track, false).contains("<name>Drone</name>"));
+    }
+}
@@ -1,17 +1,46 @@
 use chrono::{DateTime, Utc};
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use telemetry_lib::crsf::{self, CrsfPacket};
-use telemetry_lib::topics;
-use log::{debug, info, warn};
-use metrics::{Unit, counter, describe_counter};
+use telemetry_lib::{telemetry as sim_telemetry, topics};
+use log::{debug, error, info, warn};
+use metrics::{Unit, counter, describe_counter, describe_gauge, gauge};
+use metrics_exporter_prometheus::PrometheusBuilder;
 use metrics_exporter_tcp::TcpBuilder;
-use serde_json::Value;
-use std::sync::Arc;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::TcpListener;
-use tokio::time::{Duration, interval};
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::{Duration, timeout};
 use zenoh::Config;
 
+mod fix;
+mod gpsd;
+mod gpx;
+mod home;
+mod kml;
+mod nmea;
+mod noise;
+mod pty;
+mod resample;
+mod sats;
+mod schedule;
+mod ubx;
+
+use fix::Fix;
+use gpx::GpxLog;
+use home::{Home, ScenePoint};
+use kml::LiveTrack;
+use nmea::Talker;
+use noise::{Noise, NoiseConfig};
+use pty::NmeaPty;
+use resample::Resampler;
+use sats::Constellation;
+use schedule::{Due, Message, Rates, Schedule, Ticker};
+
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
@@ -19,9 +48,147 @@ struct Args {
     #[arg(long, default_value = "127.0.0.1:2947")]
     gpsd_bind: std::net::SocketAddr,
 
-    /// GPS position update frequency.
-    #[arg(short, long, default_value_t = 10)]
-    frequency: u64,
+    /// Navigation rate, Hz: the rate of the position messages. Up to 100;
+    /// see also --interpolate.
+    #[arg(short, long, default_value_t = 10.0, value_parser = schedule::parse_hz)]
+    frequency: f64,
+
+    /// Rate of one message type, NAME=HZ: gga, rmc, vtg, gst, nav-pvt and
+    /// tpv (gpsd JSON) default to the navigation rate, gsa, gsv, nav-sat and
+    /// sky (gpsd JSON) to 1 Hz. 0 disables a message. Can be given more than
+    /// once.
+    #[arg(long, value_parser = schedule::parse_rate)]
+    rate: Vec<(Message, f64)>,
+
+    /// Interpolate the position between GPS packets, for smooth output at
+    /// rates above the telemetry rate. The position then trails the
+    /// telemetry by about one packet interval.
+    #[arg(long)]
+    interpolate: bool,
+
+    /// Home latitude, degrees. Positions are moved from the scene origin
+    /// (0°N 0°E) to the home location. A gpsd client can move the origin to
+    /// the drone's current position, and change the home location, with
+    /// ?LIFTOFF={"set_home":true} or ?LIFTOFF={"set_home":{"lat":..,"lon":..}}.
+    #[arg(
+        long,
+        allow_negative_numbers = true,
+        requires = "home_lon",
+        conflicts_with = "home"
+    )]
+    home_lat: Option<f64>,
+
+    /// Home longitude, degrees.
+    #[arg(
+        long,
+        allow_negative_numbers = true,
+        requires = "home_lat",
+        conflicts_with = "home"
+    )]
+    home_lon: Option<f64>,
+
+    /// Altitude of the scene origin above mean sea level, m. Overrides the
+    /// altitude of a --home preset.
+    #[arg(long, allow_negative_numbers = true)]
+    home_alt: Option<f64>,
+
+    /// Named home location: null-island, greenwich, amsterdam, zurich,
+    /// golden-gate or sydney.
+    #[arg(long, value_parser = home::parse_preset)]
+    home: Option<(&'static str, Home)>,
+
+    /// GPS telemetry older than this is not reported as a fix, s.
+    #[arg(long, default_value_t = 10.0, value_parser = parse_timeout)]
+    stale_timeout: f64,
+
+    /// Consecutive fresh GPS packets needed to report a fix again after
+    /// losing it, so that clients don't see the fix flap when the sim
+    /// pauses.
+    #[arg(long, default_value_t = 3)]
+    fix_hysteresis: u32,
+
+    /// Reported altitude: msl (the sim altitude above the scene origin plus
+    /// the home altitude), raw (the sim altitude as is) or home (relative to
+    /// the first fix).
+    #[arg(long, value_enum, default_value_t = Altitude::Msl)]
+    altitude: Altitude,
+
+    /// Take the time from the sim's telemetry timestamps instead of the
+    /// clock, counting from this start time (e.g. 2024-06-01T12:00:00Z), so
+    /// that replayed telemetry gives consistent times.
+    #[arg(long)]
+    sim_epoch: Option<DateTime<Utc>>,
+
+    /// Simulated position error: standard deviation per horizontal axis, m
+    /// (vertical: 1.5 times as much). The error wanders slowly, like a real
+    /// receiver's.
+    #[arg(long, default_value_t = 0.0)]
+    noise_position: f64,
+
+    /// Simulated reception quality variation: standard deviation of the
+    /// natural log of the factor the DOP values and position error are
+    /// multiplied by.
+    #[arg(long, default_value_t = 0.0)]
+    noise_dop: f64,
+
+    /// Simulated fix dropouts of 2-10 s, mean number per minute.
+    #[arg(long, default_value_t = 0.0)]
+    noise_dropouts: f64,
+
+    /// Simulated downgrades to a 2D fix for 5-30 s, mean number per minute.
+    #[arg(long, default_value_t = 0.0)]
+    noise_downgrades: f64,
+
+    /// Range error per unit of DOP (user equivalent range error), m: the
+    /// reported position accuracies (GST sentences, UBX accuracies) are the
+    /// DOP values times this. Defaults to --noise-position if given, else
+    /// 2.5.
+    #[arg(long)]
+    uere: Option<f64>,
+
+    /// Seed for the simulated errors, for reproducible runs.
+    #[arg(long)]
+    noise_seed: Option<u64>,
+
+    /// Write a GPX track per session to this directory.
+    #[arg(long)]
+    gpx_out: Option<PathBuf>,
+
+    /// Bind address for an HTTP server with a live KML view for Google
+    /// Earth: add a network link to http://<address>/live.kml.
+    #[arg(long)]
+    kml_bind: Option<std::net::SocketAddr>,
+
+    /// Make the KML view follow the drone.
+    #[arg(long, requires = "kml_bind")]
+    kml_follow: bool,
+
+    /// Length of the KML trail, s; 0 for none.
+    #[arg(long, default_value_t = 300, requires = "kml_bind")]
+    kml_trail: usize,
+
+    /// Also send the NMEA sentences (or UBX messages, see --protocol) as UDP
+    /// datagrams to this address, which may be a broadcast address. Can be
+    /// given more than once.
+    #[arg(long)]
+    nmea_udp: Vec<SocketAddr>,
+
+    /// Also write the NMEA sentences to a pseudo-terminal, and make this
+    /// path (e.g. /tmp/crsf-gps) a symlink to it, for software that expects
+    /// a serial GPS device.
+    #[arg(long)]
+    pty: Option<PathBuf>,
+
+    /// NMEA talker ID: GP (GPS), GN (combined GNSS), GL (GLONASS), GA
+    /// (Galileo) or any other two letters. GSV sentences use GP with GN, as
+    /// the satellites are GPS ones.
+    #[arg(long, value_parser = nmea::parse_talker, default_value = "GP")]
+    talker: Talker,
+
+    /// Protocol for the --nmea-udp and --pty outputs: NMEA 0183 sentences,
+    /// or u-blox UBX NAV-PVT and NAV-SAT messages.
+    #[arg(long, value_enum, default_value_t = Protocol::Nmea)]
+    protocol: Protocol,
 
     /// Zenoh connect endpoint (e.g. tcp/192.168.1.1:7447). Omit for peer discovery.
     #[arg(long)]
@@ -31,9 +198,11 @@ struct Args {
     #[arg(long, default_value = "client")]
     zenoh_mode: String,
 
-    /// Zenoh topic prefix.
+    /// Zenoh topic prefix. Can be given more than once, to serve several
+    /// drones (e.g. sim0, sim1) as separate gpsd devices; --gpx-out,
+    /// --kml-bind, --nmea-udp and --pty follow the first one.
     #[arg(long, default_value = topics::DEFAULT_PREFIX)]
-    zenoh_prefix: String,
+    zenoh_prefix: Vec<String>,
 
     /// Enable metrics reporting using metrics-rs-tcp-exporter.
     #[arg(long, default_value_t = false)]
@@ -42,79 +211,670 @@ struct Args {
     /// Bind address for metrics-rs-tcp-exporter.
     #[arg(long, default_value = "127.0.0.1:5003")]
     metrics_tcp_bind: std::net::SocketAddr,
+
+    /// Serve metrics for Prometheus to scrape on this address (e.g.
+    /// 127.0.0.1:9000).
+    #[arg(long, conflicts_with = "metrics_tcp")]
+    metrics_prometheus: Option<std::net::SocketAddr>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Protocol {
+    Nmea,
+    Ubx,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Altitude {
+    Raw,
+    #[default]
+    Msl,
+    Home,
 }
 
-// NMEA formatting helpers
-fn format_nmea(body: &str) -> String {
-    let mut checksum = 0u8;
-    for b in body.bytes() {
-        checksum ^= b;
+impl Protocol {
+    fn name(self) -> &'static str {
+        match self {
+            Protocol::Nmea => "NMEA",
+            Protocol::Ubx => "UBX",
+        }
     }
-    format!("${}*{:02X}\r\n", body, checksum)
 }
 
-fn to_nmea_coord(val: f64, is_lat: bool) -> (String, char) {
-    let abs_val = val.abs();
-    let deg = abs_val.floor();
-    let min = (abs_val - deg) * 60.0;
+/// Parse a timeout in seconds, for use as a clap value parser.
+fn parse_timeout(s: &str) -> Result<f64, String> {
+    let secs: f64 = s
+        .parse()
+        .map_err(|e| format!("invalid timeout `{}`: {}", s, e))?;
+    if secs <= 0.0 || Duration::try_from_secs_f64(secs).is_err() {
+        return Err(format!("invalid timeout `{}`, must be above 0 s", s));
+    }
+    Ok(secs)
+}
 
-    let format_str = if is_lat {
-        format!("{:02}{:07.4}", deg as u32, min)
-    } else {
-        format!("{:03}{:07.4}", deg as u32, min)
-    };
+impl Args {
+    /// The home location from `--home` or `--home-lat`/`--home-lon`, with
+    /// `--home-alt` applied.
+    fn home(&self) -> Option<Home> {
+        let home = match (self.home, self.home_lat, self.home_lon) {
+            (Some((_, home)), _, _) => Some(home),
+            (None, Some(lat), Some(lon)) => Some(Home { lat, lon, alt: 0.0 }),
+            _ => None,
+        };
+        match (home, self.home_alt) {
+            (Some(home), Some(alt)) => Some(Home { alt, ..home }),
+            (None, Some(alt)) => Some(Home {
+                lat: 0.0,
+                lon: 0.0,
+                alt,
+            }),
+            (home, None) => home,
+        }
+    }
 
-    let dir = if is_lat {
-        if val >= 0.0 { 'N' } else { 'S' }
-    } else {
-        if val >= 0.0 { 'E' } else { 'W' }
-    };
+    fn uere(&self) -> f64 {
+        self.uere.unwrap_or(if self.noise_position > 0.0 {
+            self.noise_position
+        } else {
+            fix::DEFAULT_UERE
+        })
+    }
 
-    (format_str, dir)
+    /// The simulated errors of device `index`; each device gets its own.
+    fn noise(&self, index: usize) -> Option<Noise> {
+        let config = NoiseConfig {
+            position: self.noise_position,
+            dop: self.noise_dop,
+            dropouts: self.noise_dropouts,
+            downgrades: self.noise_downgrades,
+        };
+        config.is_enabled().then(|| {
+            Noise::new(
+                config,
+                self.noise_seed.map(|s| s.wrapping_add(index as u64)),
+            )
+        })
+    }
 }
 
-fn generate_gga(time: DateTime<Utc>, lat: f64, lon: f64, alt: f64, sats: u32) -> String {
-    let (lat_str, lat_dir) = to_nmea_coord(lat, true);
-    let (lon_str, lon_dir) = to_nmea_coord(lon, false);
-    let time_str = time.format("%H%M%S.%3f");
+/// Interval between GPX track points.
+const GPX_INTERVAL: Duration = Duration::from_secs(1);
+/// Updates queued per output; a slower output skips updates.
+const UPDATE_QUEUE: usize = 16;
+/// A client that takes longer than this to accept a write is disconnected.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
 
-    // $GPGGA,hhmmss.ss,llll.ll,a,yyyy.yy,a,x,xx,x.x,x.x,M,x.x,M,x.x,xxxx*hh
-    let body = format!(
-        "GPGGA,{},{},{},{},{},1,{:02},0.9,{:.1},M,46.9,M,,",
-        time_str, lat_str, lat_dir, lon_str, lon_dir, sats, alt
-    );
-    format_nmea(&body)
+/// Latest GPS-related telemetry.
+#[derive(Debug, Default)]
+struct Telemetry {
+    gps: Option<(Instant, crsf::Gps)>,
+    /// GPS packets received without a stale gap.
+    gps_streak: u32,
+    vario: Option<(Instant, crsf::Vario)>,
+    stale_timeout: Duration,
+    fix_hysteresis: u32,
+    home: Option<Home>,
+    /// The point in the scene moved to the home location.
+    origin: ScenePoint,
+    altitude: Altitude,
+    /// Sim altitude of the first fix, for `--altitude home`.
+    takeoff_alt: Option<f64>,
+    /// With `--interpolate`.
+    resampler: Option<Resampler>,
+    noise: Option<Noise>,
+    /// Range error per unit of DOP, m.
+    uere: f64,
+    /// Time of sim timestamp 0, with `--sim-epoch`.
+    sim_epoch: Option<DateTime<Utc>>,
+    /// Latest sim timestamp, s.
+    sim_time: Option<(Instant, f32)>,
+}
+
+impl Telemetry {
+    /// The current time: the sim time extrapolated from the latest
+    /// timestamp with `--sim-epoch`, otherwise the clock.
+    fn time(&self) -> DateTime<Utc> {
+        match (self.sim_epoch, self.sim_time) {
+            (Some(epoch), Some((at, timestamp))) => {
+                let secs = timestamp as f64 + at.elapsed().as_secs_f64();
+                epoch + chrono::Duration::milliseconds((secs * 1000.0).round() as i64)
+            }
+            _ => Utc::now(),
+        }
+    }
+
+    fn on_gps(&mut self, now: Instant, gps: crsf::Gps) {
+        let fresh = self
+            .gps
+            .as_ref()
+            .is_some_and(|(t, _)| now.saturating_duration_since(*t) < self.stale_timeout);
+        self.gps_streak = if fresh {
+            self.gps_streak.saturating_add(1)
+        } else {
+            1
+        };
+        if let Some(resampler) = &mut self.resampler {
+            resampler.push((gps.lat_deg(), gps.lon_deg(), gps.alt_m()), now);
+        }
+        self.gps = Some((now, gps));
+    }
+
+    /// Move the current position to the home location (`?LIFTOFF`), and
+    /// restart `--altitude home` from it.
+    fn set_home(&mut self, set: gpsd::SetHome) -> Result<(), &'static str> {
+        let (_, gps) = self
+            .gps
+            .as_ref()
+            .filter(|(t, _)| t.elapsed() < self.stale_timeout)
+            .ok_or("no recent GPS telemetry")?;
+        self.origin = (gps.lat_deg(), gps.lon_deg(), gps.alt_m());
+        self.home = Some(match set {
+            gpsd::SetHome::Here => self.home.unwrap_or(Home {
+                lat: 0.0,
+                lon: 0.0,
+                alt: 0.0,
+            }),
+            gpsd::SetHome::At(home) => home,
+        });
+        self.takeoff_alt = None;
+        Ok(())
+    }
+
+    /// The current fix, or `None` if there is no recent GPS telemetry or
+    /// it has not been steady for long enough yet.
+    fn fix(&mut self) -> Option<Fix> {
+        let fresh = |t: &Instant| t.elapsed() < self.stale_timeout;
+        let (_, gps) = self.gps.as_ref().filter(|(t, _)| fresh(t))?;
+        if self.gps_streak < self.fix_hysteresis {
+            return None;
+        }
+        let vario = self.vario.as_ref().filter(|(t, _)| fresh(t));
+        let mut fix = Fix::from_crsf(gps, vario.map(|(_, v)| v));
+        fix.uere = self.uere;
+        if let Some((lat, lon, alt)) = self
+            .resampler
+            .as_ref()
+            .and_then(|r| r.sample(Instant::now()))
+        {
+            (fix.lat, fix.lon, fix.alt) = (lat, lon, alt);
+        }
+        let sim_alt = fix.alt;
+        if let Some(home) = &self.home {
+            home.rebase(self.origin, &mut fix);
+        }
+        match self.altitude {
+            Altitude::Raw => fix.alt = sim_alt,
+            Altitude::Msl => {}
+            Altitude::Home => fix.alt = sim_alt - *self.takeoff_alt.get_or_insert(sim_alt),
+        }
+        match &mut self.noise {
+            Some(noise) => noise.apply(Instant::now(), fix),
+            None => Some(fix),
+        }
+    }
 }
 
-fn generate_gga_nofix(time: DateTime<Utc>) -> String {
-    let time_str = time.format("%H%M%S.%3f");
+type SharedTelemetry = Arc<RwLock<Telemetry>>;
+
+/// The current time and fix.
+fn sample(telemetry: &SharedTelemetry) -> (DateTime<Utc>, Option<Fix>) {
+    match telemetry.write() {
+        Ok(mut t) => (t.time(), t.fix()),
+        Err(_) => (Utc::now(), None),
+    }
+}
 
-    // $GPGGA,hhmmss.ss,,,,,0,00,99.99,,,,,,*hh
-    let body = format!("GPGGA,{},,,,,0,00,99.99,,,,,,", time_str);
-    format_nmea(&body)
+/// The fix of one device at one tick, computed once and shared by all
+/// outputs.
+#[derive(Debug)]
+struct Update {
+    /// Index of the device.
+    device: usize,
+    time: DateTime<Utc>,
+    fix: Option<Fix>,
+    sky: Constellation,
+    due: Due,
 }
 
-fn generate_rmc(time: DateTime<Utc>, lat: f64, lon: f64, speed_knots: f64, course: f64) -> String {
-    let (lat_str, lat_dir) = to_nmea_coord(lat, true);
-    let (lon_str, lon_dir) = to_nmea_coord(lon, false);
-    let time_str = time.format("%H%M%S.%3f");
-    let date_str = time.format("%d%m%y");
+type Updates = broadcast::Sender<Arc<Update>>;
 
-    // $GPRMC,hhmmss.ss,A,llll.ll,a,yyyy.yy,a,x.x,x.x,ddmmyy,x.x,a*hh
-    let body = format!(
-        "GPRMC,{},A,{},{},{},{},{:.1},{:.1},{},,,A",
-        time_str, lat_str, lat_dir, lon_str, lon_dir, speed_knots, course, date_str
-    );
-    format_nmea(&body)
+/// Compute an update of `device` at every tick and send it to the outputs.
+async fn pipeline(device: DeviceInfo, index: usize, rates: Rates, updates: Updates) {
+    let telemetry = &device.telemetry;
+    let mut ticker = Ticker::new(&rates);
+    let schedule = Schedule::new(&rates);
+    loop {
+        let due = schedule.due(ticker.tick().await);
+        let (time, fix) = sample(telemetry);
+        let sky = constellation(time, fix.as_ref());
+        debug!("out {} {:?}", device.path, fix);
+        if let Ok(t) = telemetry.read()
+            && let Some((at, _)) = &t.gps
+        {
+            gauge!("gpsd.telemetry.age", "device" => device.path.clone())
+                .set(at.elapsed().as_secs_f64());
+        }
+        // Fails only while there are no outputs.
+        updates
+            .send(Arc::new(Update {
+                device: index,
+                time,
+                fix,
+                sky,
+                due,
+            }))
+            .ok();
+    }
+}
+
+/// The next update; `None` if the pipeline has stopped. An output that
+/// falls behind skips to the oldest queued update.
+async fn next_update(updates: &mut broadcast::Receiver<Arc<Update>>) -> Option<Arc<Update>> {
+    loop {
+        match updates.recv().await {
+            Ok(update) => return Some(update),
+            Err(RecvError::Lagged(n)) => debug!("Output fell behind, skipped {} updates", n),
+            Err(RecvError::Closed) => return None,
+        }
+    }
+}
+
+/// Whether an output sampled every `period` is due at `now`, given the
+/// last time it was. Updates come once per `tick`, so accept one up to
+/// half a tick early rather than waiting a whole tick more.
+fn sample_due(last: &mut Option<Instant>, now: Instant, period: Duration, tick: Duration) -> bool {
+    let due = last.is_none_or(|t| now.saturating_duration_since(t) + tick / 2 >= period);
+    if due {
+        *last = Some(now);
+    }
+    due
+}
+
+/// A drone, served as a gpsd device.
+#[derive(Debug, Clone)]
+struct DeviceInfo {
+    /// Reported as the device path: the telemetry topic.
+    path: String,
+    activated: DateTime<Utc>,
+    telemetry: SharedTelemetry,
+}
+
+impl DeviceInfo {
+    fn report(&self) -> gpsd::DeviceReport {
+        let seen_gps = self.telemetry.read().is_ok_and(|t| t.gps.is_some());
+        gpsd::DeviceReport::new(&self.path, self.activated, seen_gps)
+    }
+}
+
+fn devices_report(devices: &[DeviceInfo]) -> gpsd::DevicesReport {
+    gpsd::DevicesReport::new(devices.iter().map(DeviceInfo::report).collect())
+}
+
+/// Handle the commands in `buf`, writing the replies to `out`. `latest`
+/// is the latest update per device.
+fn handle_commands(
+    buf: &mut gpsd::CommandBuffer,
+    watch: &mut gpsd::Watch,
+    devices: &[DeviceInfo],
+    latest: &[Option<Arc<Update>>],
+    out: &mut String,
+) {
+    let paths: Vec<&str> = devices.iter().map(|d| d.path.as_str()).collect();
+    while let Some(line) = buf.next_command() {
+        debug!("command {}", line);
+        match gpsd::Command::parse(&line) {
+            gpsd::Command::Version => out.push_str(&gpsd::line(&gpsd::VersionReport::new())),
+            gpsd::Command::Watch(arg) => {
+                if let Some(arg) = arg
+                    && let Err(e) = watch.update(&arg, &paths)
+                {
+                    warn!("Invalid WATCH argument {}: {}", arg, e);
+                    out.push_str(&gpsd::line(&gpsd::ErrorReport::new(format!(
+                        "Invalid WATCH: {}",
+                        e
+                    ))));
+                    continue;
+                }
+                out.push_str(&gpsd::line(&devices_report(devices)));
+                out.push_str(&gpsd::line(&watch.report()));
+            }
+            gpsd::Command::Poll => {
+                let now = Utc::now();
+                let none = Constellation::default();
+                let (mut tpv, mut sky) = (Vec::new(), Vec::new());
+                for (device, latest) in devices.iter().zip(latest) {
+                    let latest = latest.as_deref();
+                    let time = latest.map_or(now, |u| u.time);
+                    let fix = latest.and_then(|u| u.fix.as_ref());
+                    tpv.push(gpsd::Tpv::new(&device.path, time, fix));
+                    let constellation = latest.map_or(&none, |u| &u.sky);
+                    sky.push(gpsd::Sky::new(&device.path, time, constellation));
+                }
+                let time = latest
+                    .first()
+                    .and_then(|u| u.as_deref())
+                    .map_or(now, |u| u.time);
+                out.push_str(&gpsd::line(&gpsd::PollReport::new(time, tpv, sky)));
+            }
+            gpsd::Command::Devices => out.push_str(&gpsd::line(&devices_report(devices))),
+            gpsd::Command::Device(arg) => {
+                let path = match arg.as_deref().map(gpsd::device_path) {
+                    Some(Err(e)) => {
+                        warn!("Invalid DEVICE argument: {}", e);
+                        out.push_str(&gpsd::line(&gpsd::ErrorReport::new(format!(
+                            "Invalid DEVICE: {}",
+                            e
+                        ))));
+                        continue;
+                    }
+                    Some(Ok(path)) => path,
+                    None => None,
+                };
+                // Without a path, the first device.
+                let Some(device) = devices
+                    .iter()
+                    .find(|d| path.as_ref().is_none_or(|p| *p == d.path))
+                else {
+                    out.push_str(&gpsd::line(&gpsd::ErrorReport::new(
+                        "Can't perform DEVICE configuration, no such device",
+                    )));
+                    continue;
+                };
+                out.push_str(&gpsd::line(&device.report()));
+            }
+            gpsd::Command::Liftoff(arg) => {
+                let request = match arg.as_deref().map(gpsd::LiftoffRequest::parse) {
+                    Some(Err(e)) => {
+                        warn!("Invalid LIFTOFF argument: {}", e);
+                        out.push_str(&gpsd::line(&gpsd::ErrorReport::new(format!(
+                            "Invalid LIFTOFF: {}",
+                            e
+                        ))));
+                        continue;
+                    }
+                    Some(Ok(request)) => request,
+                    None => gpsd::LiftoffRequest::default(),
+                };
+                let mut targets = devices
+                    .iter()
+                    .filter(|d| request.device.as_ref().is_none_or(|p| *p == d.path))
+                    .peekable();
+                if targets.peek().is_none() {
+                    out.push_str(&gpsd::line(&gpsd::ErrorReport::new(
+                        "Can't perform LIFTOFF, no such device",
+                    )));
+                    continue;
+                }
+                for device in targets {
+                    let Ok(mut telemetry) = device.telemetry.write() else {
+                        continue;
+                    };
+                    if let Some(set) = request.set_home {
+                        if let Err(e) = telemetry.set_home(set) {
+                            out.push_str(&gpsd::line(&gpsd::ErrorReport::new(format!(
+                                "Can't set home of {}: {}",
+                                device.path, e
+                            ))));
+                            continue;
+                        }
+                        info!(
+                            "Home of {} moved to the current position, at {:?}",
+                            device.path, telemetry.home
+                        );
+                    }
+                    let report = gpsd::LiftoffReport::new(&device.path, telemetry.home.as_ref());
+                    out.push_str(&gpsd::line(&report));
+                }
+            }
+            gpsd::Command::Unknown(line) => {
+                warn!("Unrecognized command: {}", line);
+                out.push_str(&gpsd::line(&gpsd::ErrorReport::new("Unrecognized request")));
+            }
+        }
+    }
+}
+
+/// The simulated satellites for `fix`; none without a fix.
+fn constellation(time: DateTime<Utc>, fix: Option<&Fix>) -> Constellation {
+    fix.map(|f| {
+        let mut sky = Constellation::simulate(time, f.sats);
+        sky.scale_dop(f.dop_scale);
+        sky
+    })
+    .unwrap_or_default()
+}
+
+/// The reports due at one update, according to the client's watch.
+fn reports(watch: &gpsd::Watch, device: &DeviceInfo, talker: Talker, update: &Update) -> String {
+    let mut out = String::new();
+    if !watch.watches(&device.path) {
+        return out;
+    }
+    let Update {
+        time,
+        fix,
+        sky,
+        due,
+        ..
+    } = update;
+    let (time, fix) = (*time, fix.as_ref());
+    if watch.json {
+        if due.has(Message::Tpv) {
+            out.push_str(&gpsd::line(&gpsd::Tpv::new(&device.path, time, fix)));
+            counter!("gpsd.json.tx").increment(1);
+        }
+        if due.has(Message::Sky) {
+            out.push_str(&gpsd::line(&gpsd::Sky::new(&device.path, time, sky)));
+            counter!("gpsd.json.tx").increment(1);
+        }
+    }
+    if watch.wants_nmea() {
+        let sentences = nmea_sentences(talker, time, fix, sky, due);
+        counter!("gpsd.nmea.tx").increment(sentences.len() as u64);
+        out.extend(sentences);
+    }
+    out
+}
+
+/// The NMEA sentences due at one update tick.
+fn nmea_sentences(
+    talker: Talker,
+    time: DateTime<Utc>,
+    fix: Option<&Fix>,
+    sky: &Constellation,
+    due: &Due,
+) -> Vec<String> {
+    let mut sentences = Vec::new();
+    if due.has(Message::Gga) {
+        sentences.push(nmea::gga(talker, time, fix, sky));
+    }
+    if due.has(Message::Rmc) {
+        sentences.push(nmea::rmc(talker, time, fix));
+    }
+    if due.has(Message::Vtg) {
+        sentences.push(nmea::vtg(talker, fix));
+    }
+    if due.has(Message::Gst) {
+        sentences.push(nmea::gst(talker, time, fix, sky));
+    }
+    if due.has(Message::Gsa) {
+        sentences.push(nmea::gsa(talker, fix, sky));
+    }
+    if due.has(Message::Gsv) {
+        sentences.extend(nmea::gsv(talker, sky));
+    }
+    sentences
+}
+
+/// The UBX messages due at one update tick.
+fn ubx_messages(
+    time: DateTime<Utc>,
+    fix: Option<&Fix>,
+    sky: &Constellation,
+    due: &Due,
+) -> Vec<Vec<u8>> {
+    let mut messages = Vec::new();
+    if due.has(Message::NavPvt) {
+        messages.push(ubx::nav_pvt(time, fix, sky));
+    }
+    if due.has(Message::NavSat) {
+        messages.push(ubx::nav_sat(time, sky));
+    }
+    messages
 }
 
-fn generate_rmc_nofix(time: DateTime<Utc>) -> String {
-    let time_str = time.format("%H%M%S.%3f");
-    let date_str = time.format("%d%m%y");
+/// A destination for NMEA sentences or UBX messages outside gpsd sessions.
+enum Sink {
+    /// One message per datagram, to each target.
+    Udp {
+        socket: UdpSocket,
+        targets: Vec<SocketAddr>,
+    },
+    Pty(NmeaPty),
+}
 
-    // $GPRMC,hhmmss.ss,V,,,,,,,ddmmyy,,*hh
-    let body = format!("GPRMC,{},V,,,,,,,{},,", time_str, date_str);
-    format_nmea(&body)
+impl Sink {
+    /// Send `messages`; returns the number sent.
+    async fn send(&mut self, messages: &[Vec<u8>]) -> usize {
+        match self {
+            Sink::Udp { socket, targets } => {
+                let mut sent = 0;
+                for target in targets.iter() {
+                    for message in messages {
+                        if let Err(e) = socket.send_to(message, target).await {
+                            debug!("Output to udp://{}: {}", target, e);
+                            counter!("gpsd.output.error").increment(1);
+                            break;
+                        }
+                        sent += 1;
+                    }
+                }
+                sent
+            }
+            Sink::Pty(pty) => match pty.write(&messages.concat()) {
+                Ok(()) => messages.len(),
+                Err(e) => {
+                    debug!("Output to {}: {}", pty.link().display(), e);
+                    counter!("gpsd.output.error").increment(1);
+                    0
+                }
+            },
+        }
+    }
+}
+
+/// Send NMEA sentences or UBX messages to `sinks` at every update.
+async fn output(
+    mut sinks: Vec<Sink>,
+    protocol: Protocol,
+    talker: Talker,
+    mut updates: broadcast::Receiver<Arc<Update>>,
+) {
+    while let Some(update) = next_update(&mut updates).await {
+        let Update {
+            time,
+            fix,
+            sky,
+            due,
+            ..
+        } = &*update;
+        // Only the first device: one stream can't carry several receivers.
+        if update.device != 0 {
+            continue;
+        }
+        let messages = match protocol {
+            Protocol::Nmea => nmea_sentences(talker, *time, fix.as_ref(), sky, due)
+                .into_iter()
+                .map(String::into_bytes)
+                .collect(),
+            Protocol::Ubx => ubx_messages(*time, fix.as_ref(), sky, due),
+        };
+        if messages.is_empty() {
+            continue;
+        }
+        for sink in &mut sinks {
+            let sent = sink.send(&messages).await as u64;
+            match protocol {
+                Protocol::Nmea => counter!("gpsd.nmea.tx").increment(sent),
+                Protocol::Ubx => counter!("gpsd.ubx.tx").increment(sent),
+            }
+        }
+    }
+}
+
+/// Write `data` to a client, giving up after [`WRITE_TIMEOUT`].
+async fn write_client(writer: &mut (impl AsyncWrite + Unpin), data: &str) -> io::Result<()> {
+    let result = match timeout(WRITE_TIMEOUT, writer.write_all(data.as_bytes())).await {
+        Ok(result) => result,
+        Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, "write timed out")),
+    };
+    if result.is_err() {
+        counter!("gpsd.client.write_error").increment(1);
+    }
+    result
+}
+
+/// Serve one client until it disconnects, fails or stops reading.
+async fn handle_client(
+    mut socket: TcpStream,
+    devices: Arc<[DeviceInfo]>,
+    mut updates: broadcast::Receiver<Arc<Update>>,
+    talker: Talker,
+) -> io::Result<()> {
+    let (mut reader, mut writer) = socket.split();
+    write_client(&mut writer, &gpsd::line(&gpsd::VersionReport::new())).await?;
+
+    let mut commands = gpsd::CommandBuffer::default();
+    let mut watch = gpsd::Watch::default();
+    let mut latest = vec![None; devices.len()];
+    let mut read_buf = [0u8; 1024];
+    loop {
+        let out = tokio::select! {
+            n = reader.read(&mut read_buf) => {
+                let n = n?;
+                if n == 0 {
+                    return Ok(());
+                }
+                commands.push(&read_buf[..n]);
+                let mut out = String::new();
+                handle_commands(&mut commands, &mut watch, &devices, &latest, &mut out);
+                out
+            }
+            update = next_update(&mut updates) => {
+                let Some(update) = update else {
+                    return Ok(());
+                };
+                let device = update.device;
+                let out = if watch.enable {
+                    reports(&watch, &devices[device], talker, &update)
+                } else {
+                    String::new()
+                };
+                latest[device] = Some(update);
+                out
+            }
+        };
+        if !out.is_empty() {
+            write_client(&mut writer, &out).await?;
+        }
+    }
+}
+
+/// Counts a client in `gpsd.client.connected` for as long as it lives,
+/// however its task ends.
+struct ConnectedClient;
+
+impl ConnectedClient {
+    fn new() -> Self {
+        gauge!("gpsd.client.connected").increment(1.0);
+        Self
+    }
+}
+
+impl Drop for ConnectedClient {
+    fn drop(&mut self) {
+        gauge!("gpsd.client.connected").decrement(1.0);
+    }
 }
 
 #[tokio::main]
@@ -130,6 +890,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
             .install()
             .expect("failed to install metrics TCP exporter");
     }
+    if let Some(addr) = args.metrics_prometheus {
+        PrometheusBuilder::new()
+            .with_http_listener(addr)
+            .install()
+            .expect("failed to install metrics Prometheus exporter");
+    }
 
     describe_counter!(
         "gpsd.telemetry.rx",
@@ -137,7 +903,25 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         "Telemetry packets received"
     );
     describe_counter!("gpsd.client.accept", Unit::Count, "Clients accepted");
+    describe_gauge!("gpsd.client.connected", Unit::Count, "Clients connected");
+    describe_counter!(
+        "gpsd.client.write_error",
+        Unit::Count,
+        "Failed writes to clients"
+    );
+    describe_counter!(
+        "gpsd.output.error",
+        Unit::Count,
+        "Failed writes to the UDP and pty outputs"
+    );
+    describe_gauge!(
+        "gpsd.telemetry.age",
+        Unit::Seconds,
+        "Time since the latest GPS telemetry"
+    );
     describe_counter!("gpsd.nmea.tx", Unit::Count, "NMEA sentences sent");
+    describe_counter!("gpsd.json.tx", Unit::Count, "JSON reports sent");
+    describe_counter!("gpsd.ubx.tx", Unit::Count, "UBX messages sent");
 
     // Zenoh session
     let mut config = Config::default();
@@ -147,123 +931,364 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     }
 
     let session = zenoh::open(config).await?;
-    let crsf_tel_topic = topics::topic(&args.zenoh_prefix, topics::CRSF_TELEMETRY);
-    info!("Subscribing to: {}", crsf_tel_topic);
-    let crsf_tel_subscriber = session.declare_subscriber(&crsf_tel_topic).await?;
-
-    // Shared state for latest GPS from CRSF telemetry
-    let shared_state: Arc<std::sync::RwLock<Option<(std::time::Instant, crsf::Gps)>>> =
-        Arc::new(std::sync::RwLock::new(None));
-    let tx = shared_state.clone();
-    let rx = shared_state.clone();
-
-    // CRSF telemetry reader task — extract GPS packets
-    tokio::spawn(async move {
-        loop {
-            match crsf_tel_subscriber.recv_async().await {
-                Ok(sample) => {
-                    let payload = sample.payload().to_bytes();
-                    counter!("gpsd.telemetry.rx").increment(1);
-                    if let Some(CrsfPacket::Gps(gps)) = crsf::parse_packet_check(&payload) {
-                        if let Ok(mut lock) = tx.write() {
-                            *lock = Some((std::time::Instant::now(), gps));
+    let home = args.home();
+    if let Some(home) = &home {
+        info!("Home: {:.6}, {:.6}, {:.1} m", home.lat, home.lon, home.alt);
+    }
+
+    // One device per prefix, each with its latest GPS from CRSF telemetry
+    let mut devices = Vec::new();
+    for (index, prefix) in args.zenoh_prefix.iter().enumerate() {
+        let crsf_tel_topic = topics::topic(prefix, topics::CRSF_TELEMETRY);
+        info!("Subscribing to: {}", crsf_tel_topic);
+        let crsf_tel_subscriber = session.declare_subscriber(&crsf_tel_topic).await?;
+        let telemetry = SharedTelemetry::new(RwLock::new(Telemetry {
+            stale_timeout: Duration::from_secs_f64(args.stale_timeout),
+            fix_hysteresis: args.fix_hysteresis,
+            home,
+            origin: home::SCENE_ORIGIN,
+            altitude: args.altitude,
+            resampler: args.interpolate.then(Resampler::new),
+            noise: args.noise(index),
+            uere: args.uere(),
+            sim_epoch: args.sim_epoch,
+            ..Default::default()
+        }));
+        let tx = telemetry.clone();
+
+        if let Some(epoch) = args.sim_epoch {
+            let sim_tel_topic = topics::topic(prefix, topics::TELEMETRY);
+            info!("Sim time from {}, starting at {}", sim_tel_topic, epoch);
+            let sim_tel_subscriber = session.declare_subscriber(&sim_tel_topic).await?;
+            let format = sim_telemetry::default_stream_format();
+            let tx = telemetry.clone();
+            tokio::spawn(async move {
+                loop {
+                    match sim_tel_subscriber.recv_async().await {
+                        Ok(sample) => {
+                            let payload = sample.payload().to_bytes();
+                            match sim_telemetry::parse_packet(&payload, &format) {
+                                Ok(packet) => {
+                                    if let Some(timestamp) = packet.timestamp
+                                        && let Ok(mut lock) = tx.write()
+                                    {
+                                        lock.sim_time = Some((Instant::now(), timestamp));
+                                    }
+                                }
+                                Err(e) => debug!("Sim telemetry: {}", e),
+                            }
+                        }
+                        Err(e) => {
+                            warn!("Sim telemetry subscriber error: {}", e);
+                            break;
                         }
                     }
                 }
-                Err(e) => {
-                    warn!("CRSF telemetry subscriber error: {}", e);
+            });
+        }
+
+        // CRSF telemetry reader task — extract GPS and vario packets
+        tokio::spawn(async move {
+            loop {
+                match crsf_tel_subscriber.recv_async().await {
+                    Ok(sample) => {
+                        let payload = sample.payload().to_bytes();
+                        counter!("gpsd.telemetry.rx").increment(1);
+                        let Ok(mut lock) = tx.write() else {
+                            continue;
+                        };
+                        match crsf::parse_packet_check(&payload) {
+                            Some(CrsfPacket::Gps(gps)) => lock.on_gps(Instant::now(), gps),
+                            Some(CrsfPacket::Vario(vario)) => {
+                                lock.vario = Some((Instant::now(), vario))
+                            }
+                            _ => {}
+                        }
+                    }
+                    Err(e) => {
+                        warn!("CRSF telemetry subscriber error: {}", e);
+                        break;
+                    }
+                }
+            }
+        });
+
+        devices.push(DeviceInfo {
+            path: crsf_tel_topic,
+            activated: Utc::now(),
+            telemetry,
+        });
+    }
+    let devices: Arc<[DeviceInfo]> = devices.into();
+
+    // Every output takes the fix from a single pipeline per device, so that
+    // they agree and the simulated errors advance once per tick. The GPX,
+    // KML, UDP and pty outputs follow the first device.
+    let rates = Rates::new(args.frequency, &args.rate);
+    let (updates, _) = broadcast::channel(UPDATE_QUEUE * devices.len());
+    for (index, device) in devices.iter().enumerate() {
+        tokio::spawn(pipeline(device.clone(), index, rates, updates.clone()));
+    }
+
+    if let Some(dir) = &args.gpx_out {
+        let name = args.home.map_or("crsf-gpsd", |(name, _)| name);
+        let mut gpx = GpxLog::new(dir, name)?;
+        let mut updates = updates.subscribe();
+        tokio::spawn(async move {
+            let mut last = None;
+            while let Some(update) = next_update(&mut updates).await {
+                let now = Instant::now();
+                if update.device != 0 || !sample_due(&mut last, now, GPX_INTERVAL, rates.tick()) {
+                    continue;
+                }
+                let sats = update.sky.num_used();
+                if let Err(e) = gpx.record(now, update.time, update.fix.as_ref(), sats) {
+                    error!("GPX logging stopped: {}", e);
                     break;
                 }
             }
+        });
+    }
+
+    if let Some(addr) = args.kml_bind {
+        let listener = TcpListener::bind(addr).await?;
+        info!("Serving KML on http://{}/live.kml", addr);
+        let track = Arc::new(Mutex::new(LiveTrack::new(args.kml_trail)));
+        tokio::spawn(kml::serve(listener, track.clone(), args.kml_follow));
+        let mut updates = updates.subscribe();
+        tokio::spawn(async move {
+            let mut last = None;
+            while let Some(update) = next_update(&mut updates).await {
+                if update.device == 0
+                    && sample_due(
+                        &mut last,
+                        Instant::now(),
+                        kml::REFRESH_INTERVAL,
+                        rates.tick(),
+                    )
+                    && let Ok(mut track) = track.lock()
+                {
+                    track.push(update.fix.clone());
+                }
+            }
+        });
+    }
+
+    let mut sinks = Vec::new();
+    if !args.nmea_udp.is_empty() {
+        let any = if args.nmea_udp.iter().all(SocketAddr::is_ipv6) {
+            "[::]:0"
+        } else {
+            "0.0.0.0:0"
+        };
+        let socket = UdpSocket::bind(any).await?;
+        socket.set_broadcast(true)?;
+        for target in &args.nmea_udp {
+            info!("Sending {} to udp://{}", args.protocol.name(), target);
         }
-    });
+        sinks.push(Sink::Udp {
+            socket,
+            targets: args.nmea_udp.clone(),
+        });
+    }
+    if let Some(link) = &args.pty {
+        let pty = NmeaPty::open(link)?;
+        info!(
+            "Writing {} to {} -> {}",
+            args.protocol.name(),
+            link.display(),
+            pty.device()?.display()
+        );
+        sinks.push(Sink::Pty(pty));
+    }
+    if !sinks.is_empty() {
+        tokio::spawn(output(
+            sinks,
+            args.protocol,
+            args.talker,
+            updates.subscribe(),
+        ));
+    }
 
     // TCP Listener for GPSD clients
     let listener = TcpListener::bind(&args.gpsd_bind).await?;
 
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
     loop {
-        let (mut socket, addr) = listener.accept().await?;
-        info!("Accepted connection from {}", addr);
-        counter!("gpsd.client.accept").increment(1);
-        let rx = rx.clone();
-        let freq = args.frequency;
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (socket, addr) = accepted?;
+                info!("Accepted connection from {}", addr);
+                counter!("gpsd.client.accept").increment(1);
+                let client = handle_client(
+                    socket,
+                    devices.clone(),
+                    updates.subscribe(),
+                    args.talker,
+                );
+                let connected = ConnectedClient::new();
+                tokio::spawn(async move {
+                    let _connected = connected;
+                    match client.await {
+                        Ok(()) => info!("Client {} disconnected", addr),
+                        Err(e) => info!("Client {} dropped: {}", addr, e),
+                    }
+                });
+            }
+            _ = &mut shutdown => {
+                info!("Shutdown signal received");
+                break;
+            }
+        }
+    }
+    // The pty belongs to the NMEA task, which isn't dropped at exit.
+    if let Some(link) = &args.pty {
+        std::fs::remove_file(link).ok();
+    }
+    Ok(())
+}
 
-        tokio::spawn(async move {
-            let (reader, mut writer) = socket.split();
-            let mut reader = BufReader::new(reader);
-
-            // Send banner
-            let banner = r#"{"class":"VERSION","release":"2.93","rev":"2010-03-30T12:18:17", "proto_major":3,"proto_minor":2}"#;
-            writer
-                .write_all(format!("{}\n", banner).as_bytes())
-                .await
-                .ok();
-
-            // Read ?WATCH command (terminated by ;)
-            let mut line_bytes = Vec::new();
-            if let Ok(_) = reader.read_until(b';', &mut line_bytes).await {
-                let line_raw = String::from_utf8_lossy(&line_bytes);
-                let line = line_raw.trim();
-
-                if line.starts_with("?WATCH=") && line.ends_with(';') {
-                    let json_str = &line[7..line.len() - 1]; // Strip ?WATCH= and ;
-
-                    let valid = if let Ok(val) = serde_json::from_str::<Value>(json_str) {
-                        let enable = val.get("enable").and_then(|v| v.as_bool()).unwrap_or(false);
-                        let nmea = val.get("nmea").and_then(|v| v.as_bool()).unwrap_or(false);
-                        let raw = val.get("raw").and_then(|v| v.as_bool()).unwrap_or(false);
-
-                        enable && nmea && raw
-                    } else {
-                        false
-                    };
+/// Resolves on Ctrl-C or SIGTERM.
+async fn shutdown_signal() {
+    let mut term = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+        .expect("failed to install SIGTERM handler");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = term.recv() => {}
+    }
+}
 
-                    if valid {
-                        let mut interval = interval(Duration::from_millis(1000 / freq));
-                        loop {
-                            interval.tick().await;
-
-                            let packet_data = if let Ok(lock) = rx.read() {
-                                lock.clone()
-                            } else {
-                                None
-                            };
-
-                            let time = Utc::now();
-                            let mut sentences = Vec::<String>::new();
-                            let mut have_fix = false;
-                            if let Some((recv_time, ref gps)) = packet_data {
-                                if recv_time.elapsed() < Duration::from_secs(10) {
-                                    debug!("in {:?}", gps);
-                                    let lat = gps.lat_deg();
-                                    let lon = gps.lon_deg();
-                                    let alt = gps.alt_m();
-                                    let knots = gps.speed_kmh() / 1.852;
-                                    let course = gps.heading_deg();
-
-                                    sentences.push(generate_gga(time, lat, lon, alt, gps.sats as u32));
-                                    sentences.push(generate_rmc(time, lat, lon, knots, course));
-                                    have_fix = true;
-                                }
-                            }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                            if !have_fix {
-                                // Send invalid GPS fix
-                                sentences.push(generate_gga_nofix(time));
-                                sentences.push(generate_rmc_nofix(time));
-                            }
+    #[test]
+    fn fix_hysteresis() {
+        let mut telemetry = Telemetry {
+            stale_timeout: Duration::from_secs(1),
+            fix_hysteresis: 3,
+            ..Default::default()
+        };
+        let gps = crsf::Gps::from_values(47.0, 8.0, 500.0, 0.0, 0.0, 10).unwrap();
+        let now = Instant::now();
+        let ago = |ms| now - Duration::from_millis(ms);
 
-                            for sentence_out in sentences {
-                                debug!("out {}", sentence_out);
-                                writer.write_all(sentence_out.as_bytes()).await.ok();
-                                counter!("gpsd.nmea.tx").increment(1);
-                            }
-                        }
-                    } else {
-                        warn!("Invalid WATCH command: {}", line);
-                    }
-                }
-            }
-        });
+        // Packets with gaps never give a fix.
+        for ms in [5000, 3000, 1500] {
+            telemetry.on_gps(ago(ms), gps.clone());
+        }
+        assert_eq!(telemetry.gps_streak, 1);
+        telemetry.on_gps(ago(200), gps.clone());
+        telemetry.on_gps(ago(100), gps.clone());
+        assert!(telemetry.fix().is_none());
+        telemetry.on_gps(now, gps.clone());
+        assert!(telemetry.fix().is_some());
+
+        // A stale fix is lost.
+        telemetry.gps = Some((ago(1000), gps));
+        assert!(telemetry.fix().is_none());
+    }
+
+    #[test]
+    fn set_home() {
+        let (_, zurich) = home::parse_preset("zurich").unwrap();
+        let mut telemetry = Telemetry {
+            stale_timeout: Duration::from_secs(1),
+            fix_hysteresis: 1,
+            home: Some(zurich),
+            altitude: Altitude::Home,
+            ..Default::default()
+        };
+        assert!(telemetry.set_home(gpsd::SetHome::Here).is_err());
+        let gps = crsf::Gps::from_values(0.01, 0.02, 500.0, 0.0, 0.0, 10).unwrap();
+        telemetry.on_gps(Instant::now(), gps.clone());
+        let fix = telemetry.fix().unwrap();
+        assert!(fix.lat > zurich.lat + 0.009);
+        assert_eq!(fix.alt, 0.0);
+
+        // The current position becomes home; the takeoff altitude restarts.
+        telemetry.altitude = Altitude::Msl;
+        telemetry.set_home(gpsd::SetHome::Here).unwrap();
+        let fix = telemetry.fix().unwrap();
+        assert!((fix.lat - zurich.lat).abs() < 1e-6 && (fix.lon - zurich.lon).abs() < 1e-6);
+        assert!((fix.alt - zurich.alt).abs() < 0.01, "{}", fix.alt);
+        assert_eq!(telemetry.takeoff_alt, None);
+
+        let sydney = home::parse_preset("sydney").unwrap().1;
+        telemetry.set_home(gpsd::SetHome::At(sydney)).unwrap();
+        let fix = telemetry.fix().unwrap();
+        assert!((fix.lat - sydney.lat).abs() < 1e-6 && (fix.lon - sydney.lon).abs() < 1e-6);
+    }
+
+    #[test]
+    fn devices() {
+        let device = |path: &str| DeviceInfo {
+            path: path.to_string(),
+            activated: Utc::now(),
+            telemetry: SharedTelemetry::default(),
+        };
+        let devices = [device("sim0/crsf/telemetry"), device("sim1/crsf/telemetry")];
+        let fix = Fix {
+            sats: 10,
+            ..Fix::at(47.0, 8.0, 500.0)
+        };
+        let latest = [
+            None,
+            Some(Arc::new(Update {
+                device: 1,
+                time: Utc::now(),
+                fix: Some(fix),
+                sky: Constellation::default(),
+                due: Schedule::new(&Rates::new(1.0, &[])).due(0),
+            })),
+        ];
+        let mut commands = gpsd::CommandBuffer::default();
+        commands.push(
+            b"?DEVICES;\n?WATCH={\"enable\":true,\"device\":\"sim1/crsf/telemetry\"}\n\
+              ?DEVICE={\"path\":\"sim2/crsf/telemetry\"}\n?POLL;\n",
+        );
+        let mut watch = gpsd::Watch::default();
+        let mut out = String::new();
+        handle_commands(&mut commands, &mut watch, &devices, &latest, &mut out);
+        let replies: Vec<serde_json::Value> = out
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        let classes: Vec<&str> = replies
+            .iter()
+            .map(|r| r["class"].as_str().unwrap())
+            .collect();
+        assert_eq!(classes, ["DEVICES", "DEVICES", "WATCH", "ERROR", "POLL"]);
+        assert_eq!(replies[0]["devices"].as_array().unwrap().len(), 2);
+        assert!(watch.watches("sim1/crsf/telemetry") && !watch.watches("sim0/crsf/telemetry"));
+        let tpv = replies[4]["tpv"].as_array().unwrap();
+        assert_eq!(tpv[0]["device"], "sim0/crsf/telemetry");
+        assert_eq!(
+            (tpv[0]["mode"].as_u64(), tpv[1]["mode"].as_u64()),
+            (Some(1), Some(3))
+        );
+    }
+
+    #[test]
+    fn slower_outputs() {
+        let tick = Duration::from_millis(100);
+        let t0 = Instant::now();
+        let at = |ms| t0 + Duration::from_millis(ms);
+        // Updates with some jitter: 1 Hz samples out of 10 Hz updates.
+        let mut last = None;
+        let samples: Vec<u64> = [0, 98, 205, 497, 951, 1003, 1102, 1996, 2101, 2950]
+            .into_iter()
+            .filter(|&ms| sample_due(&mut last, at(ms), Duration::from_secs(1), tick))
+            .collect();
+        assert_eq!(samples, [0, 951, 1996, 2950]);
+    }
+
+    #[test]
+    fn stale_timeout() {
+        assert_eq!(parse_timeout("0.5"), Ok(0.5));
+        for bad in ["0", "-1", "nan", "inf", "1e30", "soon"] {
+            assert!(parse_timeout(bad).is_err(), "{}", bad);
+        }
     }
 }
@@ -0,0 +1,355 @@
+//! NMEA 0183 sentence generation.
+
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use telemetry_lib::geo;
+
+use crate::fix::{Fix, FixMode};
+use crate::sats::Constellation;
+
+fn format_nmea(body: &str) -> String {
+    let mut checksum = 0u8;
+    for b in body.bytes() {
+        checksum ^= b;
+    }
+    format!("${}*{:02X}\r\n", body, checksum)
+}
+
+fn to_nmea_coord(val: f64, is_lat: bool) -> (String, char) {
+    let abs_val = val.abs();
+    let deg = abs_val.floor();
+    let min = (abs_val - deg) * 60.0;
+
+    let format_str = if is_lat {
+        format!("{:02}{:07.4}", deg as u32, min)
+    } else {
+        format!("{:03}{:07.4}", deg as u32, min)
+    };
+
+    let dir = if is_lat {
+        if val >= 0.0 { 'N' } else { 'S' }
+    } else {
+        if val >= 0.0 { 'E' } else { 'W' }
+    };
+
+    (format_str, dir)
+}
+
+/// NMEA talker ID: `GP` (GPS), `GN` (combined GNSS), `GL` (GLONASS), `GA`
+/// (Galileo) or any other two letters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Talker([u8; 2]);
+
+impl Talker {
+    pub const GPS: Talker = Talker(*b"GP");
+    const COMBINED: Talker = Talker(*b"GN");
+
+    /// The talker for satellite sentences.
+    fn satellites(self) -> Talker {
+        if self == Self::COMBINED {
+            Self::GPS
+        } else {
+            self
+        }
+    }
+}
+
+impl fmt::Display for Talker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.0[0] as char, self.0[1] as char)
+    }
+}
+
+/// Parse a talker ID, for use as a clap value parser.
+pub fn parse_talker(s: &str) -> Result<Talker, String> {
+    match s.as_bytes() {
+        &[a, b] if a.is_ascii_alphabetic() && b.is_ascii_alphabetic() => {
+            Ok(Talker([a.to_ascii_uppercase(), b.to_ascii_uppercase()]))
+        }
+        _ => Err(format!("`{}` is not two letters", s)),
+    }
+}
+
+/// Satellites per GSV sentence.
+const GSV_SATS: usize = 4;
+/// Satellite slots in a GSA sentence.
+const GSA_SATS: usize = 12;
+
+fn opt_dop(dop: Option<f64>) -> String {
+    dop.map(|d| format!("{:.1}", d)).unwrap_or_default()
+}
+
+/// GGA (fix data), or a no-fix GGA if `fix` is `None`.
+pub fn gga(talker: Talker, time: DateTime<Utc>, fix: Option<&Fix>, sky: &Constellation) -> String {
+    let time_str = time.format("%H%M%S.%3f");
+    let Some(fix) = fix else {
+        // $GPGGA,hhmmss.ss,,,,,0,00,99.99,,,,,,*hh
+        return format_nmea(&format!("{}GGA,{},,,,,0,00,99.99,,,,,,", talker, time_str));
+    };
+    let (lat_str, lat_dir) = to_nmea_coord(fix.lat, true);
+    let (lon_str, lon_dir) = to_nmea_coord(fix.lon, false);
+    let alt = match fix.mode {
+        FixMode::TwoD => String::new(),
+        FixMode::ThreeD => format!("{:.1}", fix.alt),
+    };
+
+    // $GPGGA,hhmmss.ss,llll.ll,a,yyyy.yy,a,x,xx,x.x,x.x,M,x.x,M,x.x,xxxx*hh
+    let body = format!(
+        "{}GGA,{},{},{},{},{},1,{:02},{},{},M,{:.1},M,,",
+        talker,
+        time_str,
+        lat_str,
+        lat_dir,
+        lon_str,
+        lon_dir,
+        sky.num_used(),
+        opt_dop(sky.dop.map(|d| d.hdop)),
+        alt,
+        geo::geoid_separation(fix.lat, fix.lon)
+    );
+    format_nmea(&body)
+}
+
+/// RMC (recommended minimum data), or a void RMC if `fix` is `None`.
+pub fn rmc(talker: Talker, time: DateTime<Utc>, fix: Option<&Fix>) -> String {
+    let time_str = time.format("%H%M%S.%3f");
+    let date_str = time.format("%d%m%y");
+    let Some(fix) = fix else {
+        // $GPRMC,hhmmss.ss,V,,,,,,,ddmmyy,,*hh
+        return format_nmea(&format!(
+            "{}RMC,{},V,,,,,,,{},,",
+            talker, time_str, date_str
+        ));
+    };
+    let (lat_str, lat_dir) = to_nmea_coord(fix.lat, true);
+    let (lon_str, lon_dir) = to_nmea_coord(fix.lon, false);
+
+    // $GPRMC,hhmmss.ss,A,llll.ll,a,yyyy.yy,a,x.x,x.x,ddmmyy,x.x,a*hh
+    let body = format!(
+        "{}RMC,{},A,{},{},{},{},{:.1},{:.1},{},,,A",
+        talker,
+        time_str,
+        lat_str,
+        lat_dir,
+        lon_str,
+        lon_dir,
+        fix.speed_knots(),
+        fix.track,
+        date_str
+    );
+    format_nmea(&body)
+}
+
+/// VTG (course and ground speed), or an invalid VTG if `fix` is `None`.
+pub fn vtg(talker: Talker, fix: Option<&Fix>) -> String {
+    let Some(fix) = fix else {
+        // $GPVTG,,T,,M,,N,,K,N*hh
+        return format_nmea(&format!("{}VTG,,T,,M,,N,,K,N", talker));
+    };
+    // $GPVTG,x.x,T,x.x,M,x.x,N,x.x,K,a*hh
+    let body = format!(
+        "{}VTG,{:.1},T,,M,{:.1},N,{:.1},K,A",
+        talker,
+        fix.track,
+        fix.speed_knots(),
+        fix.speed * 3.6
+    );
+    format_nmea(&body)
+}
+
+/// GST (position error statistics): standard deviations derived from the
+/// DOP values and the fix's range error, with a circular error ellipse.
+/// Empty fields without a fix.
+pub fn gst(talker: Talker, time: DateTime<Utc>, fix: Option<&Fix>, sky: &Constellation) -> String {
+    let time_str = time.format("%H%M%S.%3f");
+    let (Some(fix), Some(dop)) = (fix, sky.dop) else {
+        // $GPGST,hhmmss.ss,,,,,,,*hh
+        return format_nmea(&format!("{}GST,{},,,,,,,", talker, time_str));
+    };
+    // Per horizontal axis.
+    let horizontal = dop.hdop * fix.uere / std::f64::consts::SQRT_2;
+    let vertical = match fix.mode {
+        FixMode::TwoD => String::new(),
+        FixMode::ThreeD => format!("{:.1}", dop.vdop * fix.uere),
+    };
+    // $GPGST,hhmmss.ss,x.x,x.x,x.x,x.x,x.x,x.x,x.x*hh
+    let body = format!(
+        "{}GST,{},{:.1},{:.1},{:.1},0.0,{:.1},{:.1},{}",
+        talker, time_str, fix.uere, horizontal, horizontal, horizontal, horizontal, vertical
+    );
+    format_nmea(&body)
+}
+
+/// GSA (DOP and used satellites). Without a fix, reports mode 1 and no
+/// satellites.
+pub fn gsa(talker: Talker, fix: Option<&Fix>, sky: &Constellation) -> String {
+    let (mode, prns): (u8, Vec<String>) = match fix {
+        Some(fix) => (
+            match fix.mode {
+                FixMode::TwoD => 2,
+                FixMode::ThreeD => 3,
+            },
+            sky.used().map(|s| format!("{:02}", s.prn)).collect(),
+        ),
+        None => (1, Vec::new()),
+    };
+    let mut slots = vec![String::new(); GSA_SATS];
+    for (slot, prn) in slots.iter_mut().zip(prns) {
+        *slot = prn;
+    }
+    let dop = fix.and(sky.dop);
+    // $GPGSA,A,x,xx,xx,xx,xx,xx,xx,xx,xx,xx,xx,xx,xx,x.x,x.x,x.x*hh
+    let body = format!(
+        "{}GSA,A,{},{},{},{},{}",
+        talker,
+        mode,
+        slots.join(","),
+        opt_dop(dop.map(|d| d.pdop)),
+        opt_dop(dop.map(|d| d.hdop)),
+        opt_dop(dop.map(|d| d.vdop))
+    );
+    format_nmea(&body)
+}
+
+/// GSV (satellites in view), as many sentences as needed. The satellites
+/// are GPS ones, so these use `GP` if `talker` is the combined `GN`.
+pub fn gsv(talker: Talker, sky: &Constellation) -> Vec<String> {
+    let talker = talker.satellites();
+    if sky.sats.is_empty() {
+        return vec![format_nmea(&format!("{}GSV,1,1,00", talker))];
+    }
+    let chunks = sky.sats.chunks(GSV_SATS);
+    let total = chunks.len();
+    chunks
+        .enumerate()
+        .map(|(i, chunk)| {
+            // $GPGSV,x,x,xx,xx,xx,xxx,xx,...*hh
+            let mut body = format!("{}GSV,{},{},{:02}", talker, total, i + 1, sky.sats.len());
+            for sat in chunk {
+                body += &format!(
+                    ",{:02},{:02},{:03},{:02}",
+                    sat.prn,
+                    sat.elevation.round() as u32,
+                    sat.azimuth.round() as u32 % 360,
+                    sat.snr
+                );
+            }
+            format_nmea(&body)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sats::{Dop, Satellite};
+    use chrono::TimeZone;
+
+    fn fix() -> Fix {
+        Fix {
+            speed: 10.0,
+            track: 90.0,
+            ..Fix::at(52.5, -4.25, 12.3)
+        }
+    }
+
+    fn sky() -> Constellation {
+        let sat = |prn, elevation, azimuth, snr, used| Satellite {
+            prn,
+            elevation,
+            azimuth,
+            snr,
+            used,
+        };
+        Constellation {
+            sats: vec![
+                sat(2, 62.4, 301.0, 42, true),
+                sat(5, 33.0, 45.0, 35, true),
+                sat(7, 12.0, 359.6, 25, false),
+                sat(12, 88.0, 180.0, 45, true),
+                sat(25, 41.0, 100.0, 38, true),
+            ],
+            dop: Some(Dop {
+                hdop: 0.94,
+                vdop: 1.21,
+                pdop: 1.53,
+            }),
+        }
+    }
+
+    #[test]
+    fn sentences() {
+        let time = Utc.with_ymd_and_hms(2024, 5, 6, 7, 8, 9).unwrap();
+        assert_eq!(
+            gga(Talker::GPS, time, Some(&fix()), &sky()),
+            "$GPGGA,070809.000,5230.0000,N,00415.0000,W,1,04,0.9,12.3,M,49.5,M,,*79\r\n"
+        );
+        assert_eq!(
+            rmc(Talker::GPS, time, Some(&fix())),
+            "$GPRMC,070809.000,A,5230.0000,N,00415.0000,W,19.4,90.0,060524,,,A*7E\r\n"
+        );
+        assert_eq!(
+            gga(Talker::GPS, time, None, &Constellation::default()),
+            "$GPGGA,070809.000,,,,,0,00,99.99,,,,,,*50\r\n"
+        );
+        assert_eq!(
+            rmc(Talker::GPS, time, None),
+            "$GPRMC,070809.000,V,,,,,,,060524,,*2C\r\n"
+        );
+        assert_eq!(
+            vtg(Talker::GPS, Some(&fix())),
+            "$GPVTG,90.0,T,,M,19.4,N,36.0,K,A*3D\r\n"
+        );
+        assert_eq!(vtg(Talker::GPS, None), "$GPVTG,,T,,M,,N,,K,N*2C\r\n");
+        assert_eq!(
+            gst(Talker::GPS, time, Some(&fix()), &sky()),
+            "$GPGST,070809.000,2.5,1.7,1.7,0.0,1.7,1.7,3.0*65\r\n"
+        );
+        assert_eq!(
+            gst(Talker::GPS, time, None, &sky()),
+            "$GPGST,070809.000,,,,,,,*4F\r\n"
+        );
+    }
+
+    #[test]
+    fn satellites() {
+        assert_eq!(
+            gsa(Talker::GPS, Some(&fix()), &sky()),
+            "$GPGSA,A,3,02,05,12,25,,,,,,,,,1.5,0.9,1.2*3F\r\n"
+        );
+        assert_eq!(
+            gsa(Talker::GPS, None, &sky()),
+            "$GPGSA,A,1,,,,,,,,,,,,,,,*1E\r\n"
+        );
+        assert_eq!(
+            gsv(Talker::GPS, &sky()),
+            vec![
+                "$GPGSV,2,1,05,02,62,301,42,05,33,045,35,07,12,000,25,12,88,180,45*77\r\n",
+                "$GPGSV,2,2,05,25,41,100,38*44\r\n"
+            ]
+        );
+        assert_eq!(
+            gsv(Talker::GPS, &Constellation::default()),
+            vec!["$GPGSV,1,1,00*79\r\n"]
+        );
+    }
+    #[test]
+    fn talker() {
+        let gn = parse_talker("gn").unwrap();
+        assert_eq!(gn.to_string(), "GN");
+        assert!(parse_talker("G").is_err() && parse_talker("G1").is_err());
+        let time = Utc.with_ymd_and_hms(2024, 5, 6, 7, 8, 9).unwrap();
+        assert_eq!(
+            gga(gn, time, None, &Constellation::default()),
+            "$GNGGA,070809.000,,,,,0,00,99.99,,,,,,*4E\r\n"
+        );
+        // The satellites are GPS ones, also in a combined fix.
+        assert!(gsv(gn, &sky())[0].starts_with("$GPGSV,"));
+        let gl = parse_talker("GL").unwrap();
+        assert_eq!(
+            gsv(gl, &Constellation::default()),
+            vec!["$GLGSV,1,1,00*65\r\n"]
+        );
+    }
+}
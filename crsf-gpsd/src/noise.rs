@@ -0,0 +1,215 @@
+//! Optional GPS error model, for testing how clients cope with imperfect
+//! data.
+//!
+//! The position error wanders slowly like a real receiver's (a first-order
+//! Gauss-Markov process per axis) instead of jumping from fix to fix. The DOP
+//! values vary the same way, and the position error grows with them.
+//! Dropouts (no fix) and downgrades to a 2D fix start at random, at a given
+//! mean rate, and last a few seconds.
+
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+use crate::fix::{Fix, FixMode};
+
+/// Correlation time of the position and DOP errors.
+const CORRELATION_TIME: Duration = Duration::from_secs(20);
+/// Vertical error relative to the horizontal error.
+const VERTICAL_FACTOR: f64 = 1.5;
+/// Duration of a dropout, s.
+const DROPOUT_DURATION: Range<f64> = 2.0..10.0;
+/// Duration of a downgrade to a 2D fix, s.
+const DOWNGRADE_DURATION: Range<f64> = 5.0..30.0;
+/// Metres per degree of latitude, as in `telemetry_lib::geo`.
+const METRES_PER_DEGREE: f64 = 111111.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NoiseConfig {
+    /// Standard deviation of the horizontal position error per axis, m.
+    pub position: f64,
+    /// Standard deviation of the natural log of the DOP factor.
+    pub dop: f64,
+    /// Mean dropouts per minute.
+    pub dropouts: f64,
+    /// Mean downgrades to a 2D fix per minute.
+    pub downgrades: f64,
+}
+
+impl NoiseConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.position > 0.0 || self.dop > 0.0 || self.dropouts > 0.0 || self.downgrades > 0.0
+    }
+}
+
+#[derive(Debug)]
+pub struct Noise {
+    config: NoiseConfig,
+    rng: SmallRng,
+    last: Option<Instant>,
+    /// East, north, up error with unit variance, m.
+    offset: [f64; 3],
+    /// DOP error with unit variance.
+    dop: f64,
+    dropout_until: Option<Instant>,
+    downgrade_until: Option<Instant>,
+}
+
+impl Noise {
+    /// An error model seeded with `seed`, or randomly if `None`.
+    pub fn new(config: NoiseConfig, seed: Option<u64>) -> Self {
+        let rng = match seed {
+            Some(seed) => SmallRng::seed_from_u64(seed),
+            None => SmallRng::from_os_rng(),
+        };
+        Self {
+            config,
+            rng,
+            last: None,
+            offset: [0.0; 3],
+            dop: 0.0,
+            dropout_until: None,
+            downgrade_until: None,
+        }
+    }
+
+    /// Standard normal sample (Box-Muller).
+    fn normal(&mut self) -> f64 {
+        let u1: f64 = 1.0 - self.rng.random::<f64>();
+        let u2: f64 = self.rng.random();
+        (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()
+    }
+
+    /// Whether an event with `rate` per minute starts within `dt`.
+    fn starts(&mut self, rate: f64, dt: Duration) -> bool {
+        rate > 0.0 && self.rng.random::<f64>() < 1.0 - (-rate / 60.0 * dt.as_secs_f64()).exp()
+    }
+
+    /// Advance the model to `now`.
+    fn step(&mut self, now: Instant) {
+        // Correlation between the previous state and the new one; the first
+        // step starts from the stationary distribution.
+        let dt = self.last.map(|last| now.saturating_duration_since(last));
+        let a = dt.map_or(0.0, |dt| {
+            (-dt.as_secs_f64() / CORRELATION_TIME.as_secs_f64()).exp()
+        });
+        let dt = dt.unwrap_or_default();
+        self.last = Some(now);
+
+        let b = (1.0 - a * a).sqrt();
+        let n = [self.normal(), self.normal(), self.normal()];
+        for (o, n) in self.offset.iter_mut().zip(n) {
+            *o = a * *o + b * n;
+        }
+        self.dop = a * self.dop + b * self.normal();
+
+        if self.dropout_until.is_some_and(|t| now >= t) {
+            self.dropout_until = None;
+        }
+        if self.downgrade_until.is_some_and(|t| now >= t) {
+            self.downgrade_until = None;
+        }
+        if self.dropout_until.is_none() && self.starts(self.config.dropouts, dt) {
+            let secs = self.rng.random_range(DROPOUT_DURATION);
+            self.dropout_until = Some(now + Duration::from_secs_f64(secs));
+        }
+        if self.downgrade_until.is_none() && self.starts(self.config.downgrades, dt) {
+            let secs = self.rng.random_range(DOWNGRADE_DURATION);
+            self.downgrade_until = Some(now + Duration::from_secs_f64(secs));
+        }
+    }
+
+    /// Apply the error model at `now` to `fix`; `None` during a dropout.
+    pub fn apply(&mut self, now: Instant, mut fix: Fix) -> Option<Fix> {
+        self.step(now);
+        if self.dropout_until.is_some() {
+            return None;
+        }
+        // Degraded geometry only makes the position worse.
+        let dop_scale = (self.config.dop * self.dop).abs().exp();
+        let sigma = self.config.position * dop_scale;
+        let [east, north, up] = self.offset.map(|o| o * sigma);
+        fix.lat += north / METRES_PER_DEGREE;
+        fix.lon += east / (METRES_PER_DEGREE * fix.lat.to_radians().cos());
+        fix.alt += up * VERTICAL_FACTOR;
+        fix.dop_scale *= dop_scale;
+        if self.downgrade_until.is_some() {
+            fix.mode = FixMode::TwoD;
+        }
+        Some(fix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fix() -> Fix {
+        Fix {
+            sats: 10,
+            ..Fix::at(0.0, 0.0, 100.0)
+        }
+    }
+
+    #[test]
+    fn position_error() {
+        let config = NoiseConfig {
+            position: 2.0,
+            ..Default::default()
+        };
+        let mut noise = Noise::new(config, Some(1));
+        let t0 = Instant::now();
+        let (mut sum_sq, mut max_step, mut prev) = (0.0, 0.0f64, None::<f64>);
+        let n = 20000;
+        for i in 0..n {
+            let f = noise
+                .apply(t0 + Duration::from_millis(100 * i), fix())
+                .unwrap();
+            let north = f.lat * METRES_PER_DEGREE;
+            sum_sq += north * north;
+            if let Some(prev) = prev {
+                max_step = max_step.max((north - prev).abs());
+            }
+            prev = Some(north);
+            assert_eq!(f.mode, FixMode::ThreeD);
+            assert_eq!(f.dop_scale, 1.0);
+        }
+        // About the configured deviation, and wandering rather than jumping.
+        let sigma = (sum_sq / n as f64).sqrt();
+        assert!(sigma > 1.0 && sigma < 3.0, "{}", sigma);
+        assert!(max_step < 1.5, "{}", max_step);
+    }
+
+    #[test]
+    fn dropouts_and_downgrades() {
+        let config = NoiseConfig {
+            dop: 0.5,
+            dropouts: 2.0,
+            downgrades: 2.0,
+            ..Default::default()
+        };
+        assert!(config.is_enabled() && !NoiseConfig::default().is_enabled());
+        let mut noise = Noise::new(config, Some(2));
+        let t0 = Instant::now();
+        let (mut dropped, mut downgraded, mut scaled) = (0, 0, 0);
+        // One hour at 1 Hz.
+        for i in 0..3600 {
+            match noise.apply(t0 + Duration::from_secs(i), fix()) {
+                None => dropped += 1,
+                Some(f) => {
+                    downgraded += (f.mode == FixMode::TwoD) as u32;
+                    scaled += (f.dop_scale > 1.01) as u32;
+                    assert!(f.dop_scale >= 1.0);
+                    assert_eq!((f.lat, f.lon), (0.0, 0.0));
+                }
+            }
+        }
+        // Events of a kind don't overlap: dropouts take about 1/6 of the
+        // time, downgrades about 1/3 of the rest.
+        assert!(dropped > 300 && dropped < 1200, "{}", dropped);
+        assert!(downgraded > 500 && downgraded < 3000, "{}", downgraded);
+        assert!(scaled > 1800, "{}", scaled);
+    }
+}
@@ -0,0 +1,116 @@
+//! NMEA on a pseudo-terminal (`--pty`), for software that only takes a
+//! serial GPS device.
+//!
+//! The slave side is put in raw mode, so readers get the sentences
+//! unchanged, and is kept open so that writes don't fail while no reader
+//! is attached. When nobody reads, the buffered sentences are discarded
+//! instead of blocking, so a reader that attaches later starts with
+//! current data.
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+
+use nix::fcntl::{FcntlArg, OFlag, fcntl};
+use nix::pty::openpty;
+use nix::sys::termios::{self, FlushArg, SetArg};
+use nix::unistd::ttyname;
+
+pub struct NmeaPty {
+    master: File,
+    slave: OwnedFd,
+    /// Symlink to the slave device.
+    link: PathBuf,
+}
+
+impl NmeaPty {
+    /// Allocate a pseudo-terminal and point `link` at its slave device.
+    /// An existing symlink (from an earlier run) is replaced; anything else
+    /// at `link` is an error.
+    pub fn open(link: &Path) -> io::Result<Self> {
+        let pty = openpty(None, None)?;
+        let mut attrs = termios::tcgetattr(&pty.slave)?;
+        termios::cfmakeraw(&mut attrs);
+        termios::tcsetattr(&pty.slave, SetArg::TCSANOW, &attrs)?;
+        fcntl(pty.master.as_raw_fd(), FcntlArg::F_SETFL(OFlag::O_NONBLOCK))?;
+        let device = ttyname(&pty.slave)?;
+
+        match fs::symlink_metadata(link) {
+            Ok(meta) if meta.file_type().is_symlink() => fs::remove_file(link)?,
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} exists and is not a symlink", link.display()),
+                ));
+            }
+            Err(_) => {}
+        }
+        symlink(&device, link)?;
+        Ok(Self {
+            master: File::from(pty.master),
+            slave: pty.slave,
+            link: link.to_path_buf(),
+        })
+    }
+
+    /// The slave device, e.g. `/dev/pts/3`.
+    pub fn device(&self) -> io::Result<PathBuf> {
+        Ok(ttyname(&self.slave)?)
+    }
+
+    pub fn link(&self) -> &Path {
+        &self.link
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        match self.master.write_all(data) {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                // Nobody is reading: drop what's queued for the reader.
+                termios::tcflush(&self.slave, FlushArg::TCIFLUSH)?;
+                Ok(())
+            }
+            r => r,
+        }
+    }
+}
+
+impl Drop for NmeaPty {
+    fn drop(&mut self) {
+        fs::remove_file(&self.link).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn pty() {
+        let link = std::env::temp_dir().join(format!("crsf-gpsd-pty-{}", std::process::id()));
+        // A leftover symlink is replaced.
+        symlink("/nonexistent", &link).unwrap();
+        let mut pty = NmeaPty::open(&link).unwrap();
+        assert_eq!(fs::read_link(&link).unwrap(), pty.device().unwrap());
+
+        let mut reader = File::open(&link).unwrap();
+        pty.write(b"$GPGGA\r\n").unwrap();
+        let mut buf = [0u8; 8];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"$GPGGA\r\n", "raw mode keeps the line endings");
+
+        // Without a reader, writes don't block.
+        drop(reader);
+        for _ in 0..10000 {
+            pty.write(b"$GPRMC,,,,,,,,,,,*00\r\n").unwrap();
+        }
+
+        drop(pty);
+        assert!(fs::symlink_metadata(&link).is_err());
+        fs::write(&link, "").unwrap();
+        assert!(NmeaPty::open(&link).is_err());
+        fs::remove_file(&link).unwrap();
+    }
+}
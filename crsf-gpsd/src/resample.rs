@@ -0,0 +1,119 @@
+//! Interpolation of the position between GPS packets (`--interpolate`).
+//!
+//! The flight controller sends GPS telemetry at a few Hz, so at higher
+//! output rates the position would jump once per packet and stand still in
+//! between. Instead, each packet becomes the new target, approached
+//! linearly from the current position over the (smoothed) packet interval.
+//! The position thus trails the telemetry by about one packet interval,
+//! but moves smoothly.
+
+use std::time::{Duration, Instant};
+
+/// A gap between packets longer than this restarts the interval estimate,
+/// and the next position is used without interpolation.
+pub const MAX_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Weight of a new interval sample in the running estimate, as 1/N.
+const INTERVAL_SMOOTHING: u32 = 8;
+
+/// Latitude, longitude (degrees) and altitude (m).
+pub type Position = (f64, f64, f64);
+
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    from: Position,
+    to: Position,
+    start: Instant,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Resampler {
+    segment: Option<Segment>,
+    /// Estimated interval between packets; zero disables interpolation.
+    interval: Duration,
+}
+
+impl Resampler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start moving towards `position`, received at `now`.
+    pub fn push(&mut self, position: Position, now: Instant) {
+        let from = self.sample(now).unwrap_or(position);
+        let gap = self.segment.map(|s| now.saturating_duration_since(s.start));
+        self.interval = match gap {
+            Some(gap) if gap <= MAX_INTERVAL && !self.interval.is_zero() => {
+                (self.interval * (INTERVAL_SMOOTHING - 1) + gap) / INTERVAL_SMOOTHING
+            }
+            Some(gap) if gap <= MAX_INTERVAL => gap,
+            _ => Duration::ZERO,
+        };
+        self.segment = Some(Segment {
+            from,
+            to: position,
+            start: now,
+        });
+    }
+
+    /// The interpolated position at `now`, or `None` before the first
+    /// packet.
+    pub fn sample(&self, now: Instant) -> Option<Position> {
+        let seg = self.segment?;
+        let elapsed = now.saturating_duration_since(seg.start);
+        if self.interval.is_zero() || elapsed >= self.interval {
+            return Some(seg.to);
+        }
+        let frac = elapsed.as_secs_f64() / self.interval.as_secs_f64();
+        let (lat0, lon0, alt0) = seg.from;
+        let (lat1, lon1, alt1) = seg.to;
+        // The short way across the antimeridian.
+        let dlon = (lon1 - lon0 + 540.0).rem_euclid(360.0) - 180.0;
+        let lon = (lon0 + dlon * frac + 540.0).rem_euclid(360.0) - 180.0;
+        Some((
+            lat0 + (lat1 - lat0) * frac,
+            lon,
+            alt0 + (alt1 - alt0) * frac,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: Position, b: Position) {
+        assert!(
+            (a.0 - b.0).abs() < 1e-9 && (a.1 - b.1).abs() < 1e-9 && (a.2 - b.2).abs() < 1e-9,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn interpolates_positions() {
+        let t0 = Instant::now();
+        let ms = |n| t0 + Duration::from_millis(n);
+        let mut r = Resampler::new();
+        assert_eq!(r.sample(t0), None);
+        // First packet and a packet after a long gap are used directly.
+        r.push((47.0, 8.0, 400.0), ms(0));
+        assert_eq!(r.sample(ms(0)), Some((47.0, 8.0, 400.0)));
+
+        r.push((47.001, 8.002, 410.0), ms(200));
+        assert_near(r.sample(ms(200)).unwrap(), (47.0, 8.0, 400.0));
+        assert_near(r.sample(ms(300)).unwrap(), (47.0005, 8.001, 405.0));
+        assert_near(r.sample(ms(400)).unwrap(), (47.001, 8.002, 410.0));
+        assert_near(r.sample(ms(900)).unwrap(), (47.001, 8.002, 410.0));
+
+        r.push((47.0, 8.0, 400.0), ms(3000));
+        assert_eq!(r.sample(ms(3000)), Some((47.0, 8.0, 400.0)));
+
+        // Across the antimeridian.
+        let mut r = Resampler::new();
+        r.push((0.0, 179.9, 0.0), ms(0));
+        r.push((0.0, -179.9, 0.0), ms(100));
+        assert_near(r.sample(ms(150)).unwrap(), (0.0, -180.0, 0.0));
+    }
+}
@@ -0,0 +1,217 @@
+//! A simulated GPS constellation, so that clients that want to see
+//! satellites and DOP values before trusting a fix get them.
+//!
+//! The satellites move along circles through the zenith, one full orbit per
+//! half sidereal day like real GPS satellites, spread over six planes. The
+//! ones in view change slowly over a session. The DOP values follow from the
+//! geometry of the satellites marked as used.
+
+use chrono::{DateTime, Utc};
+
+/// Number of PRNs in the constellation.
+const NUM_PRNS: u8 = 32;
+/// Number of orbital planes the PRNs are spread over.
+const NUM_PLANES: u8 = 6;
+/// Orbital period, s.
+const ORBIT_PERIOD: f64 = 43082.0;
+/// Satellites below this elevation, degrees, are not in view.
+const ELEVATION_MASK: f64 = 5.0;
+/// Fewest used satellites that give a 3D position (and DOP values).
+const MIN_USED: usize = 4;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Satellite {
+    pub prn: u8,
+    /// Degrees above the horizon.
+    pub elevation: f64,
+    /// Degrees true.
+    pub azimuth: f64,
+    /// Signal strength, dB-Hz.
+    pub snr: u8,
+    /// Used in the fix.
+    pub used: bool,
+}
+
+/// Dilutions of precision.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dop {
+    pub hdop: f64,
+    pub vdop: f64,
+    pub pdop: f64,
+}
+
+/// The satellites in view, and the DOP of the used ones if there are enough.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Constellation {
+    /// Ordered by PRN.
+    pub sats: Vec<Satellite>,
+    pub dop: Option<Dop>,
+}
+
+impl Constellation {
+    /// The constellation at `time`, with the `used` highest satellites
+    /// marked as used (at least four, at most all in view).
+    pub fn simulate(time: DateTime<Utc>, used: u32) -> Self {
+        let t = time.timestamp_millis() as f64 / 1000.0;
+        let mut sats: Vec<Satellite> = (1..=NUM_PRNS).filter_map(|prn| position(prn, t)).collect();
+
+        let mut by_elevation: Vec<usize> = (0..sats.len()).collect();
+        by_elevation.sort_by(|&a, &b| sats[b].elevation.total_cmp(&sats[a].elevation));
+        let used = (used as usize).clamp(MIN_USED, sats.len().max(MIN_USED));
+        for &i in by_elevation.iter().take(used) {
+            sats[i].used = true;
+        }
+        let dop = dop(&sats);
+        Self { sats, dop }
+    }
+
+    /// Multiply the DOP values by `factor`, for degraded reception.
+    pub fn scale_dop(&mut self, factor: f64) {
+        if let Some(dop) = &mut self.dop {
+            dop.hdop *= factor;
+            dop.vdop *= factor;
+            dop.pdop *= factor;
+        }
+    }
+
+    pub fn used(&self) -> impl Iterator<Item = &Satellite> {
+        self.sats.iter().filter(|s| s.used)
+    }
+
+    pub fn num_used(&self) -> u32 {
+        self.used().count() as u32
+    }
+}
+
+/// Position of satellite `prn` at `t` (s since the epoch), or `None` if it is
+/// not in view.
+fn position(prn: u8, t: f64) -> Option<Satellite> {
+    let plane = (prn - 1) % NUM_PLANES;
+    let slot = (prn - 1) / NUM_PLANES;
+    let phase = slot as f64 * std::f64::consts::TAU / 6.0 + plane as f64 * 0.5;
+    let angle = t / ORBIT_PERIOD * std::f64::consts::TAU + phase;
+    let elevation = 90.0 * angle.sin();
+    if elevation < ELEVATION_MASK {
+        return None;
+    }
+    // The plane's ground track turns slowly; each pass crosses the sky
+    // from one side to the other.
+    let azimuth = (plane as f64 * 60.0 + angle.to_degrees() / 2.0).rem_euclid(360.0);
+    let snr = 20.0 + 25.0 * elevation.to_radians().sin();
+    Some(Satellite {
+        prn,
+        elevation,
+        azimuth,
+        snr: snr.round() as u8,
+        used: false,
+    })
+}
+
+/// DOP of the used satellites: the diagonal of (GᵀG)⁻¹, with G's rows the
+/// east, north, up line-of-sight unit vectors plus a clock term.
+fn dop(sats: &[Satellite]) -> Option<Dop> {
+    let mut m = [[0.0; 4]; 4];
+    for sat in sats.iter().filter(|s| s.used) {
+        let (el, az) = (sat.elevation.to_radians(), sat.azimuth.to_radians());
+        let row = [el.cos() * az.sin(), el.cos() * az.cos(), el.sin(), 1.0];
+        for (i, mi) in m.iter_mut().enumerate() {
+            for (j, mij) in mi.iter_mut().enumerate() {
+                *mij += row[i] * row[j];
+            }
+        }
+    }
+    let q = invert(m)?;
+    Some(Dop {
+        hdop: (q[0][0] + q[1][1]).sqrt(),
+        vdop: q[2][2].sqrt(),
+        pdop: (q[0][0] + q[1][1] + q[2][2]).sqrt(),
+    })
+}
+
+/// Gauss-Jordan inversion; `None` if `m` is (nearly) singular.
+fn invert(mut m: [[f64; 4]; 4]) -> Option<[[f64; 4]; 4]> {
+    let mut inv = [[0.0; 4]; 4];
+    for (i, row) in inv.iter_mut().enumerate() {
+        row[i] = 1.0;
+    }
+    for col in 0..4 {
+        let pivot = (col..4).max_by(|&a, &b| m[a][col].abs().total_cmp(&m[b][col].abs()))?;
+        if m[pivot][col].abs() < 1e-9 {
+            return None;
+        }
+        m.swap(col, pivot);
+        inv.swap(col, pivot);
+        let p = m[col][col];
+        for j in 0..4 {
+            m[col][j] /= p;
+            inv[col][j] /= p;
+        }
+        for row in 0..4 {
+            if row != col {
+                let f = m[row][col];
+                for j in 0..4 {
+                    m[row][j] -= f * m[col][j];
+                    inv[row][j] -= f * inv[col][j];
+                }
+            }
+        }
+    }
+    Some(inv)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn constellation() {
+        let time = Utc.with_ymd_and_hms(2024, 5, 6, 7, 8, 9).unwrap();
+        let c = Constellation::simulate(time, 8);
+        assert!(c.sats.len() >= 8, "{} in view", c.sats.len());
+        assert_eq!(c.num_used(), 8);
+        assert!(c.sats.windows(2).all(|w| w[0].prn < w[1].prn));
+        for sat in &c.sats {
+            assert!(sat.elevation >= ELEVATION_MASK && sat.elevation <= 90.0);
+            assert!((0.0..360.0).contains(&sat.azimuth));
+        }
+        // The used ones are the highest.
+        let lowest_used = c.used().map(|s| s.elevation).fold(90.0, f64::min);
+        assert!(
+            c.sats
+                .iter()
+                .filter(|s| !s.used)
+                .all(|s| s.elevation <= lowest_used)
+        );
+        let dop = c.dop.unwrap();
+        assert!(dop.hdop > 0.5 && dop.hdop < 5.0, "{:?}", dop);
+        assert!(dop.pdop > dop.hdop && dop.pdop > dop.vdop);
+
+        // Fewer than four used still gives a 3D solution; the set moves.
+        assert_eq!(Constellation::simulate(time, 0).num_used(), 4);
+        let later = Constellation::simulate(time + chrono::Duration::minutes(30), 8);
+        assert_ne!(later.sats, c.sats);
+    }
+
+    #[test]
+    fn dop_geometry() {
+        let sat = |elevation, azimuth| Satellite {
+            prn: 1,
+            elevation,
+            azimuth,
+            snr: 40,
+            used: true,
+        };
+        // One overhead and three spread out on the horizon.
+        let d = dop(&[
+            sat(90.0, 0.0),
+            sat(0.0, 0.0),
+            sat(0.0, 120.0),
+            sat(0.0, 240.0),
+        ])
+        .unwrap();
+        assert!((d.hdop - (4.0f64 / 3.0).sqrt()).abs() < 1e-9, "{:?}", d);
+        // All in one spot: no solution.
+        assert_eq!(dop(&vec![sat(45.0, 10.0); 4]), None);
+    }
+}
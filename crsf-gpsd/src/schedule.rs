@@ -0,0 +1,276 @@
+//! Output rates per message type (`--rate`).
+//!
+//! Like a real receiver, the position messages go out at the navigation
+//! rate (`--frequency`) and the satellite messages once per second, unless
+//! overridden per message. Updates tick at the highest rate, and a message
+//! is sent every so many ticks, so the rates stay in step.
+//!
+//! The ticks are at absolute deadlines from the start, so that timer jitter
+//! and rounding don't add up to a drift in the rate.
+
+use std::time::Duration;
+
+use tokio::time::{Instant, sleep_until};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message {
+    Gga,
+    Rmc,
+    Vtg,
+    Gst,
+    Gsa,
+    Gsv,
+    NavPvt,
+    NavSat,
+    Tpv,
+    Sky,
+}
+
+const MESSAGES: [Message; 10] = [
+    Message::Gga,
+    Message::Rmc,
+    Message::Vtg,
+    Message::Gst,
+    Message::Gsa,
+    Message::Gsv,
+    Message::NavPvt,
+    Message::NavSat,
+    Message::Tpv,
+    Message::Sky,
+];
+
+/// Default rate of the satellite messages, Hz.
+const SKY_RATE: f64 = 1.0;
+/// Highest rate of any message, Hz.
+pub const MAX_RATE: f64 = 100.0;
+
+impl Message {
+    /// Name for `--rate`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Message::Gga => "gga",
+            Message::Rmc => "rmc",
+            Message::Vtg => "vtg",
+            Message::Gst => "gst",
+            Message::Gsa => "gsa",
+            Message::Gsv => "gsv",
+            Message::NavPvt => "nav-pvt",
+            Message::NavSat => "nav-sat",
+            Message::Tpv => "tpv",
+            Message::Sky => "sky",
+        }
+    }
+
+    /// Satellite messages, as opposed to position messages.
+    fn is_sky(self) -> bool {
+        matches!(
+            self,
+            Message::Gsa | Message::Gsv | Message::NavSat | Message::Sky
+        )
+    }
+}
+
+/// Parse a rate in Hz, up to [`MAX_RATE`], for use as a clap value parser.
+pub fn parse_hz(s: &str) -> Result<f64, String> {
+    let hz: f64 = s
+        .parse()
+        .map_err(|e| format!("invalid rate `{}`: {}", s, e))?;
+    if !(0.0..=MAX_RATE).contains(&hz) {
+        return Err(format!(
+            "invalid rate `{}`, must be 0 to {} Hz",
+            s, MAX_RATE
+        ));
+    }
+    Ok(hz)
+}
+
+/// Parse `NAME=HZ`, for use as a clap value parser.
+pub fn parse_rate(s: &str) -> Result<(Message, f64), String> {
+    let (name, hz) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=HZ, got `{}`", s))?;
+    let message = MESSAGES
+        .into_iter()
+        .find(|m| m.name().eq_ignore_ascii_case(name))
+        .ok_or_else(|| {
+            let names: Vec<&str> = MESSAGES.iter().map(|m| m.name()).collect();
+            format!("unknown message `{}` (one of: {})", name, names.join(", "))
+        })?;
+    Ok((message, parse_hz(hz)?))
+}
+
+/// Rate per message, Hz; 0 disables a message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rates {
+    hz: [f64; MESSAGES.len()],
+}
+
+impl Rates {
+    /// Position messages at `frequency`, satellite messages at 1 Hz (or
+    /// `frequency` if lower), then `overrides`.
+    pub fn new(frequency: f64, overrides: &[(Message, f64)]) -> Self {
+        let mut hz = MESSAGES.map(|m| {
+            if m.is_sky() {
+                SKY_RATE.min(frequency)
+            } else {
+                frequency
+            }
+        });
+        for &(message, rate) in overrides {
+            hz[message as usize] = rate;
+        }
+        Self { hz }
+    }
+
+    fn max(&self) -> f64 {
+        self.hz.iter().copied().fold(0.0, f64::max)
+    }
+
+    /// Interval between ticks: that of the highest rate.
+    pub fn tick(&self) -> Duration {
+        let max = self.max();
+        if max > 0.0 {
+            Duration::from_secs_f64(1.0 / max)
+        } else {
+            Duration::from_secs(1)
+        }
+    }
+}
+
+/// The messages due at each tick.
+#[derive(Debug)]
+pub struct Schedule {
+    /// Ticks between messages; 0 for never.
+    every: [u64; MESSAGES.len()],
+}
+
+impl Schedule {
+    pub fn new(rates: &Rates) -> Self {
+        let max = rates.max();
+        Self {
+            every: rates.hz.map(|hz| {
+                if hz > 0.0 {
+                    ((max / hz).round() as u64).max(1)
+                } else {
+                    0
+                }
+            }),
+        }
+    }
+
+    /// The messages due at tick number `tick`; tick 0 has every message
+    /// due.
+    pub fn due(&self, tick: u64) -> Due {
+        Due(self
+            .every
+            .map(|every| every != 0 && tick.is_multiple_of(every)))
+    }
+}
+
+/// Waits for the ticks at the highest rate.
+#[derive(Debug)]
+pub struct Ticker {
+    start: Instant,
+    hz: f64,
+    next: u64,
+}
+
+impl Ticker {
+    pub fn new(rates: &Rates) -> Self {
+        let max = rates.max();
+        Self {
+            start: Instant::now(),
+            hz: if max > 0.0 { max } else { 1.0 },
+            next: 0,
+        }
+    }
+
+    fn deadline(&self, tick: u64) -> Instant {
+        self.start + Duration::from_secs_f64(tick as f64 / self.hz)
+    }
+
+    /// The tick to wait for at `now`: the next one, or if that one is more
+    /// than a tick late, the latest one that has passed, skipping the rest.
+    fn upcoming(&self, now: Instant) -> u64 {
+        if now < self.deadline(self.next + 1) {
+            return self.next;
+        }
+        let elapsed = now.saturating_duration_since(self.start).as_secs_f64();
+        ((elapsed * self.hz).floor() as u64).max(self.next)
+    }
+
+    /// Wait for the next tick, and return its number.
+    pub async fn tick(&mut self) -> u64 {
+        let tick = self.upcoming(Instant::now());
+        sleep_until(self.deadline(tick)).await;
+        self.next = tick + 1;
+        tick
+    }
+}
+
+/// The messages due at a tick.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Due([bool; MESSAGES.len()]);
+
+impl Due {
+    pub fn has(&self, message: Message) -> bool {
+        self.0[message as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schedule() {
+        assert_eq!(parse_rate("GSV=0.5"), Ok((Message::Gsv, 0.5)));
+        assert_eq!(parse_rate("nav-pvt=5"), Ok((Message::NavPvt, 5.0)));
+        assert!(parse_rate("gsv").is_err());
+        assert!(parse_rate("gsv=-1").is_err());
+        assert!(parse_rate("gga=101").is_err());
+        assert_eq!(parse_hz("100"), Ok(100.0));
+        assert!(parse_hz("NaN").is_err());
+        assert!(parse_rate("zda=1").unwrap_err().contains("nav-sat"));
+
+        let rates = Rates::new(10.0, &[(Message::Gst, 0.0), (Message::Gsv, 0.5)]);
+        assert_eq!(rates.tick(), Duration::from_millis(100));
+        let schedule = Schedule::new(&rates);
+        let ticks: Vec<Due> = (0..40).map(|t| schedule.due(t)).collect();
+        let count = |m| ticks.iter().filter(|d| d.has(m)).count();
+        assert_eq!(count(Message::Gga), 40);
+        assert_eq!(count(Message::Gst), 0);
+        assert_eq!(count(Message::Gsa), 4);
+        assert_eq!(count(Message::Gsv), 2);
+        assert!(ticks[0].has(Message::Gsv) && !ticks[1].has(Message::Gsa));
+        assert!(ticks[10].has(Message::Sky) && !ticks[10].has(Message::Gsv));
+
+        // A message faster than the navigation rate sets the tick.
+        let rates = Rates::new(2.0, &[(Message::Tpv, 4.0)]);
+        assert_eq!(rates.tick(), Duration::from_millis(250));
+        let schedule = Schedule::new(&rates);
+        let ticks: Vec<Due> = (0..4).map(|t| schedule.due(t)).collect();
+        assert_eq!(ticks.iter().filter(|d| d.has(Message::Rmc)).count(), 2);
+        assert_eq!(ticks.iter().filter(|d| d.has(Message::Sky)).count(), 1);
+
+        let rates = Rates::new(0.0, &[]);
+        assert_eq!(rates.tick(), Duration::from_secs(1));
+        assert!(!Schedule::new(&rates).due(0).has(Message::Gga));
+    }
+
+    #[test]
+    fn ticker() {
+        let mut ticker = Ticker::new(&Rates::new(30.0, &[]));
+        let at = |ms| ticker.start + Duration::from_millis(ms);
+        // Deadlines don't accumulate the rounding of 1/30 s.
+        assert_eq!(ticker.deadline(3000), at(100_000));
+        assert_eq!(ticker.upcoming(at(0)), 0);
+        ticker.next = 1;
+        // Late, but within a tick: no tick is skipped.
+        assert_eq!(ticker.upcoming(at(60)), 1);
+        // Stalled: skip to the latest tick that has passed.
+        assert_eq!(ticker.upcoming(at(1000)), 30);
+        ticker.next = 31;
+        assert_eq!(ticker.upcoming(at(1001)), 31);
+    }
+}
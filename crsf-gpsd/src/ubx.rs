@@ -0,0 +1,214 @@
+//! u-blox UBX protocol messages (`--protocol ubx`), for tools and flight
+//! controllers that expect a u-blox receiver rather than NMEA.
+//!
+//! Only the navigation solution (NAV-PVT) and the satellite view (NAV-SAT)
+//! are sent; the receiver does not accept configuration messages.
+
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use telemetry_lib::geo;
+
+use crate::fix::{Fix, FixMode, SPEED_ACCURACY};
+use crate::sats::Constellation;
+
+const SYNC: [u8; 2] = [0xb5, 0x62];
+const CLASS_NAV: u8 = 0x01;
+const ID_NAV_PVT: u8 = 0x07;
+const ID_NAV_SAT: u8 = 0x35;
+
+/// GPS time minus UTC, s.
+const LEAP_SECONDS: i64 = 18;
+/// Start of GPS time, 1980-01-06, in s since the Unix epoch.
+const GPS_EPOCH: i64 = 315_964_800;
+const MS_PER_WEEK: i64 = 7 * 24 * 3600 * 1000;
+/// Reported heading accuracy, degrees.
+const HEADING_ACCURACY: f64 = 2.0;
+/// Reported time accuracy, ns.
+const TIME_ACCURACY: u32 = 30;
+
+/// A complete message: sync chars, class, id, length, payload, checksum.
+pub fn frame(class: u8, id: u8, payload: &[u8]) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(payload.len() + 8);
+    msg.extend_from_slice(&SYNC);
+    msg.extend_from_slice(&[class, id]);
+    msg.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+    msg.extend_from_slice(payload);
+    let (mut a, mut b) = (0u8, 0u8);
+    for &byte in &msg[2..] {
+        a = a.wrapping_add(byte);
+        b = b.wrapping_add(a);
+    }
+    msg.extend_from_slice(&[a, b]);
+    msg
+}
+
+/// GPS time of week of `time`, ms.
+fn itow(time: DateTime<Utc>) -> u32 {
+    let ms = time.timestamp_millis() + (LEAP_SECONDS - GPS_EPOCH) * 1000;
+    ms.rem_euclid(MS_PER_WEEK) as u32
+}
+
+/// Accuracy from a DOP value, mm; unknown without one.
+fn accuracy(dop: Option<f64>, uere: f64) -> u32 {
+    dop.map_or(u32::MAX, |d| (d * uere * 1000.0).round() as u32)
+}
+
+/// NAV-PVT (navigation solution), or a no-fix one if `fix` is `None`.
+pub fn nav_pvt(time: DateTime<Utc>, fix: Option<&Fix>, sky: &Constellation) -> Vec<u8> {
+    let mut p = Vec::with_capacity(92);
+    p.extend_from_slice(&itow(time).to_le_bytes());
+    p.extend_from_slice(&(time.year() as u16).to_le_bytes());
+    p.extend_from_slice(&[
+        time.month() as u8,
+        time.day() as u8,
+        time.hour() as u8,
+        time.minute() as u8,
+        time.second().min(59) as u8,
+        // validDate, validTime, fullyResolved
+        0x07,
+    ]);
+    p.extend_from_slice(&TIME_ACCURACY.to_le_bytes());
+    p.extend_from_slice(&(time.nanosecond().min(999_999_999) as i32).to_le_bytes());
+
+    let dop = sky.dop.filter(|_| fix.is_some());
+    let (fix_type, flags) = match fix.map(|f| f.mode) {
+        None => (0u8, 0u8),
+        Some(FixMode::TwoD) => (2, 0x01),
+        Some(FixMode::ThreeD) => (3, 0x01),
+    };
+    let num_sv = if fix.is_some() { sky.num_used() } else { 0 };
+    // flags2: confirmedAvai, confirmedDate, confirmedTime
+    p.extend_from_slice(&[fix_type, flags, 0xe0, num_sv as u8]);
+
+    let deg = |v: f64| ((v * 1e7).round() as i32).to_le_bytes();
+    let mm = |v: f64| ((v * 1000.0).round() as i32).to_le_bytes();
+    let head = |v: f64| ((v * 1e5).round() as i32).to_le_bytes();
+    match fix {
+        Some(fix) => {
+            let v_acc = match fix.mode {
+                FixMode::TwoD => u32::MAX,
+                FixMode::ThreeD => accuracy(dop.map(|d| d.vdop), fix.uere),
+            };
+            let track = fix.track.to_radians();
+            p.extend_from_slice(&deg(fix.lon));
+            p.extend_from_slice(&deg(fix.lat));
+            p.extend_from_slice(&mm(fix.alt + geo::geoid_separation(fix.lat, fix.lon)));
+            p.extend_from_slice(&mm(fix.alt));
+            p.extend_from_slice(&accuracy(dop.map(|d| d.hdop), fix.uere).to_le_bytes());
+            p.extend_from_slice(&v_acc.to_le_bytes());
+            p.extend_from_slice(&mm(fix.speed * track.cos()));
+            p.extend_from_slice(&mm(fix.speed * track.sin()));
+            p.extend_from_slice(&mm(-fix.climb.unwrap_or(0.0)));
+            p.extend_from_slice(&mm(fix.speed));
+            p.extend_from_slice(&head(fix.track));
+            p.extend_from_slice(&((SPEED_ACCURACY * 1000.0) as u32).to_le_bytes());
+            p.extend_from_slice(&((HEADING_ACCURACY * 1e5) as u32).to_le_bytes());
+        }
+        None => {
+            p.extend_from_slice(&[0; 16]);
+            p.extend_from_slice(&u32::MAX.to_le_bytes());
+            p.extend_from_slice(&u32::MAX.to_le_bytes());
+            p.extend_from_slice(&[0; 20]);
+            p.extend_from_slice(&u32::MAX.to_le_bytes());
+            p.extend_from_slice(&((180.0 * 1e5) as u32).to_le_bytes());
+        }
+    }
+    let pdop = dop.map_or(9999, |d| (d.pdop * 100.0).round().min(9999.0) as u16);
+    p.extend_from_slice(&pdop.to_le_bytes());
+    // flags3: invalidLlh
+    p.push(fix.is_none() as u8);
+    p.extend_from_slice(&[0; 5]);
+    // headVeh, magDec, magAcc: not valid (flags bit 5 is clear).
+    p.extend_from_slice(&[0; 8]);
+    frame(CLASS_NAV, ID_NAV_PVT, &p)
+}
+
+/// NAV-SAT (satellites in view).
+pub fn nav_sat(time: DateTime<Utc>, sky: &Constellation) -> Vec<u8> {
+    let mut p = Vec::with_capacity(8 + 12 * sky.sats.len());
+    p.extend_from_slice(&itow(time).to_le_bytes());
+    // version, numSvs, reserved
+    p.extend_from_slice(&[1, sky.sats.len() as u8, 0, 0]);
+    for sat in &sky.sats {
+        // qualityInd: code and carrier locked if used, else code locked;
+        // health: healthy; orbitSource: ephemeris; ephAvail.
+        let quality: u32 = if sat.used { 7 } else { 4 };
+        let flags = quality | (sat.used as u32) << 3 | 1 << 4 | 1 << 8 | 1 << 11;
+        // gnssId: GPS
+        p.extend_from_slice(&[0, sat.prn, sat.snr, sat.elevation.round() as i8 as u8]);
+        p.extend_from_slice(&(sat.azimuth.round() as i16).to_le_bytes());
+        // prRes
+        p.extend_from_slice(&[0, 0]);
+        p.extend_from_slice(&flags.to_le_bytes());
+    }
+    frame(CLASS_NAV, ID_NAV_SAT, &p)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn messages() {
+        // UBX-MON-VER poll, a well-known frame.
+        assert_eq!(
+            frame(0x0a, 0x04, &[]),
+            [0xb5, 0x62, 0x0a, 0x04, 0, 0, 0x0e, 0x34]
+        );
+
+        // Sunday 2024-05-05 00:00:00 UTC is 18 s into the GPS week.
+        let time = Utc.with_ymd_and_hms(2024, 5, 5, 0, 0, 0).unwrap();
+        assert_eq!(itow(time), 18_000);
+        let time = time + chrono::Duration::milliseconds(3_723_250);
+
+        let fix = Fix {
+            speed: 10.0,
+            track: 90.0,
+            climb: Some(1.5),
+            ..Fix::at(47.3666667, -8.55, 408.0)
+        };
+        let sky = Constellation::simulate(time, fix.sats);
+        let dop = sky.dop.unwrap();
+        let msg = nav_pvt(time, Some(&fix), &sky);
+        assert_eq!(msg.len(), 92 + 8);
+        assert_eq!(&msg[..6], [0xb5, 0x62, 0x01, 0x07, 92, 0]);
+        let p = &msg[6..98];
+        let u32_at = |i: usize| u32::from_le_bytes(p[i..i + 4].try_into().unwrap());
+        let i32_at = |i: usize| i32::from_le_bytes(p[i..i + 4].try_into().unwrap());
+        assert_eq!(u32_at(0), 18_000 + 3_723_250);
+        assert_eq!(&p[4..11], [0xe8, 0x07, 5, 5, 1, 2, 3]);
+        assert_eq!(i32_at(16), 250_000_000);
+        assert_eq!((p[20], p[21], p[23] as u32), (3, 0x01, sky.num_used()));
+        assert_eq!((i32_at(24), i32_at(28)), (-85_500_000, 473_666_667));
+        let height = (fix.alt + geo::geoid_separation(fix.lat, fix.lon)) * 1000.0;
+        assert_eq!((i32_at(32), i32_at(36)), (height.round() as i32, 408_000));
+        assert_eq!(u32_at(40), (dop.hdop * 2500.0).round() as u32);
+        // North, east, down velocity; ground speed and heading.
+        assert_eq!((i32_at(48), i32_at(52), i32_at(56)), (0, 10_000, -1_500));
+        assert_eq!((i32_at(60), i32_at(64)), (10_000, 9_000_000));
+        assert_eq!(
+            u16::from_le_bytes([p[76], p[77]]),
+            (dop.pdop * 100.0).round() as u16
+        );
+        assert_eq!(p[78], 0);
+
+        let msg = nav_pvt(time, None, &Constellation::default());
+        let p = &msg[6..98];
+        assert_eq!((p[20], p[21], p[23], p[78]), (0, 0, 0, 1));
+        assert_eq!(u16::from_le_bytes([p[76], p[77]]), 9999);
+
+        let msg = nav_sat(time, &sky);
+        assert_eq!(msg.len(), 8 + 8 + 12 * sky.sats.len());
+        assert_eq!(&msg[2..4], [0x01, 0x35]);
+        assert_eq!(msg[6 + 5] as usize, sky.sats.len());
+        let used = (0..sky.sats.len())
+            .filter(|i| msg[6 + 8 + 12 * i + 8] & 0x08 != 0)
+            .count();
+        assert_eq!(used as u32, sky.num_used());
+        let sat = &sky.sats[0];
+        assert_eq!(
+            &msg[14..18],
+            [0, sat.prn, sat.snr, sat.elevation.round() as u8]
+        );
+    }
+}
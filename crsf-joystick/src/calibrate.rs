@@ -0,0 +1,251 @@
+//! Learning channel endpoints and switch positions from live RC frames.
+//!
+//! Used by the `calibrate` subcommand in two phases:
+//!
+//! 1. With all sticks centered, [`Calibrator::capture_center`] records the
+//!    rest position of every channel.
+//! 2. While the pilot moves every stick to its extremes and flips every
+//!    switch through all of its positions, each frame is passed to
+//!    [`Calibrator::observe`].
+//!
+//! [`Calibrator::apply`] then writes the learned endpoints into a
+//! [`MappingConfig`], and sets the thresholds of the channels configured
+//! as a switch halfway between the learned positions. Channels that did
+//! not move are left untouched.
+
+use std::collections::BTreeSet;
+
+use crate::mapping::{MappingConfig, NUM_CHANNELS};
+
+/// Raw values closer together than this are treated as the same switch
+/// position (ELRS is quantized but not necessarily noise-free).
+const POSITION_TOLERANCE: u16 = 16;
+
+/// A channel that settled on at most this many distinct positions is a
+/// switch rather than a stick or pot.
+const MAX_SWITCH_POSITIONS: usize = 3;
+
+/// A channel must travel at least this far to be considered calibrated.
+const MIN_TRAVEL: u16 = 100;
+
+/// Accumulated observations for all channels.
+#[derive(Debug, Clone)]
+pub struct Calibrator {
+    center: Option<[u16; NUM_CHANNELS]>,
+    seen: Vec<BTreeSet<u16>>,
+}
+
+/// Learned calibration for one channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelResult {
+    pub min: u16,
+    pub center: u16,
+    pub max: u16,
+    /// Detent positions when the channel behaved like a switch.
+    pub positions: Vec<u16>,
+}
+
+impl Default for Calibrator {
+    fn default() -> Self {
+        Self {
+            center: None,
+            seen: vec![BTreeSet::new(); NUM_CHANNELS],
+        }
+    }
+}
+
+impl Calibrator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the rest position of every channel.
+    pub fn capture_center(&mut self, channels: &[u16; NUM_CHANNELS]) {
+        self.center = Some(*channels);
+    }
+
+    /// Record one frame of the sweep phase.
+    pub fn observe(&mut self, channels: &[u16; NUM_CHANNELS]) {
+        for (seen, &value) in self.seen.iter_mut().zip(channels) {
+            seen.insert(value);
+        }
+    }
+
+    /// Calibration for channel `ch`, or `None` if it didn't move enough.
+    pub fn result(&self, ch: usize) -> Option<ChannelResult> {
+        let seen = self.seen.get(ch)?;
+        let min = *seen.first()?;
+        let max = *seen.last()?;
+        if max - min < MIN_TRAVEL {
+            return None;
+        }
+
+        let clusters = cluster(seen);
+        let is_switch = clusters.len() <= MAX_SWITCH_POSITIONS
+            && clusters
+                .iter()
+                .all(|&(lo, hi)| hi - lo <= POSITION_TOLERANCE);
+        if is_switch {
+            let positions: Vec<u16> = clusters
+                .iter()
+                .map(|&(lo, hi)| lo + (hi - lo) / 2)
+                .collect();
+            let center = if positions.len() == 3 {
+                positions[1]
+            } else {
+                min + (max - min) / 2
+            };
+            return Some(ChannelResult {
+                min,
+                center,
+                max,
+                positions,
+            });
+        }
+
+        let center = self
+            .center
+            .map(|c| c[ch].clamp(min, max))
+            .unwrap_or(min + (max - min) / 2);
+        Some(ChannelResult {
+            min,
+            center,
+            max,
+            positions: Vec::new(),
+        })
+    }
+
+    /// Write all learned channels into `config`, returning the indices of
+    /// the channels that were updated.
+    pub fn apply(&self, config: &mut MappingConfig) -> Vec<usize> {
+        let mut updated = Vec::new();
+        for ch in 0..NUM_CHANNELS {
+            if let Some(res) = self.result(ch) {
+                let cfg = config.channel_mut(ch);
+                cfg.min = res.min;
+                cfg.center = res.center;
+                cfg.max = res.max;
+                // Thresholds are normalized, and reversed with the channel.
+                let mut thresholds: Vec<u16> = res
+                    .positions
+                    .windows(2)
+                    .map(|w| cfg.normalize(w[0] + (w[1] - w[0]) / 2))
+                    .collect();
+                thresholds.sort_unstable();
+                thresholds.dedup();
+                if let Some(switch) = &mut cfg.switch
+                    && !thresholds.is_empty()
+                {
+                    switch.thresholds = thresholds;
+                }
+                updated.push(ch);
+            }
+        }
+        updated
+    }
+}
+
+/// Group sorted values into clusters whose neighbouring values are at most
+/// `POSITION_TOLERANCE` apart, returning the `(lowest, highest)` value of
+/// each cluster.
+fn cluster(values: &BTreeSet<u16>) -> Vec<(u16, u16)> {
+    let mut clusters = Vec::new();
+    let mut iter = values.iter().copied();
+    let Some(first) = iter.next() else {
+        return clusters;
+    };
+    let (mut lo, mut hi) = (first, first);
+    for v in iter {
+        if v - hi > POSITION_TOLERANCE {
+            clusters.push((lo, hi));
+            lo = v;
+        }
+        hi = v;
+    }
+    clusters.push((lo, hi));
+    clusters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapping::SwitchConfig;
+
+    #[test]
+    fn stick_uses_captured_center() {
+        let mut cal = Calibrator::new();
+        let mut frame = [992u16; NUM_CHANNELS];
+        frame[0] = 1000;
+        cal.capture_center(&frame);
+        for v in (172..=1811).step_by(7) {
+            frame[0] = v;
+            cal.observe(&frame);
+        }
+        let res = cal.result(0).unwrap();
+        assert_eq!(res.min, 172);
+        assert_eq!(res.center, 1000);
+        assert!(res.max >= 1805);
+        assert!(res.positions.is_empty());
+        // Channel 1 never moved.
+        assert_eq!(cal.result(1), None);
+    }
+
+    #[test]
+    fn three_position_switch() {
+        let mut cal = Calibrator::new();
+        let mut frame = [992u16; NUM_CHANNELS];
+        cal.capture_center(&frame);
+        for v in [191, 192, 997, 998, 1792, 1790] {
+            frame[4] = v;
+            cal.observe(&frame);
+        }
+        let res = cal.result(4).unwrap();
+        assert_eq!(res.min, 191);
+        assert_eq!(res.max, 1792);
+        assert_eq!(res.positions, vec![191, 997, 1791]);
+        assert_eq!(res.center, 997);
+    }
+
+    #[test]
+    fn apply_updates_only_moved_channels() {
+        let mut cal = Calibrator::new();
+        let mut frame = [992u16; NUM_CHANNELS];
+        cal.capture_center(&frame);
+        for v in [172, 1811] {
+            frame[5] = v;
+            cal.observe(&frame);
+        }
+        let mut config = MappingConfig::default();
+        assert_eq!(cal.apply(&mut config), vec![5]);
+        let ch = config.channel(5);
+        assert_eq!((ch.min, ch.center, ch.max), (172, 991, 1811));
+        assert_eq!(ch.switch, None);
+        assert_eq!(config.channel(0), Default::default());
+    }
+
+    #[test]
+    fn apply_sets_switch_thresholds() {
+        let mut cal = Calibrator::new();
+        let mut frame = [992u16; NUM_CHANNELS];
+        cal.capture_center(&frame);
+        for v in [191, 997, 1791] {
+            frame[4] = v;
+            cal.observe(&frame);
+        }
+        let mut config = MappingConfig::default();
+        config.channel_mut(4).switch = Some(SwitchConfig::default());
+        config.channel_mut(4).reverse = true;
+        cal.apply(&mut config);
+        let ch = config.channel(4);
+        let thresholds = &ch.switch.as_ref().unwrap().thresholds;
+        assert_eq!(thresholds.len(), 2);
+        assert_eq!(*thresholds, [ch.normalize(1394), ch.normalize(594)]);
+        // Each detent lands in its own position.
+        let sw = ch.switch.as_ref().unwrap();
+        let positions: Vec<usize> = [191, 997, 1791]
+            .iter()
+            .map(|&raw| sw.position(ch.normalize(raw), None))
+            .collect();
+        assert_eq!(positions, [2, 1, 0]);
+    }
+}
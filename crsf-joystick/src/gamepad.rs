@@ -0,0 +1,230 @@
+//! CRSF RC channels → uhid virtual gamepad.
+//!
+//! Alternative to the uinput [`Joystick`](crate::Joystick): a HID device
+//! created through `/dev/uhid` with a standard *Game Pad* report
+//! descriptor (two sticks, two triggers, sixteen buttons). Sims and Steam
+//! Input treat it like any other generic gamepad, which some of them
+//! handle much better than a multi-axis joystick.
+//!
+//! Layout (mode 2 radio):
+//!
+//! | Gamepad          | Channel                          |
+//! |------------------|----------------------------------|
+//! | left stick X/Y   | 3 RUD / 2 THR                    |
+//! | right stick X/Y  | 0 AIL / 1 ELE                    |
+//! | left trigger     | 6 S1 pot                         |
+//! | right trigger    | 4 SD (arm switch, as an axis)    |
+//! | buttons 1–3      | 4 SD, 5 SE, 7 SA (high position) |
+//! | buttons 5–12     | trims 8–11, low/high             |
+//!
+//! Stick Y axes are inverted, as gamepads report "up" as the minimum, so
+//! the neutral left stick (throttle low) is at the Y maximum.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+
+use metrics::counter;
+
+use crate::mapping::MappingConfig;
+use crate::{AXIS_3POS_LEFT, AXIS_3POS_RIGHT, AXIS_MAX, AXIS_MID, Output};
+
+/// `struct uhid_event` event types (`linux/uhid.h`).
+const UHID_DESTROY: u32 = 1;
+const UHID_CREATE2: u32 = 11;
+const UHID_INPUT2: u32 = 12;
+
+/// `sizeof(struct uhid_event)`.
+const UHID_EVENT_SIZE: usize = 4 + 4372;
+
+const BUS_USB: u16 = 0x03;
+/// Same vendor as the uinput joystick; a different product so that sims
+/// with per-controller bind files can tell the two apart.
+const VENDOR: u32 = 0x1209;
+const PRODUCT: u32 = 0x4f55;
+
+/// Six 16-bit axes followed by a 16-bit button bitmap.
+pub const REPORT_SIZE: usize = 14;
+
+#[rustfmt::skip]
+const REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01,        // Usage Page (Generic Desktop)
+    0x09, 0x05,        // Usage (Game Pad)
+    0xa1, 0x01,        // Collection (Application)
+    0x15, 0x00,        //   Logical Minimum (0)
+    0x26, 0xbf, 0x07,  //   Logical Maximum (1983)
+    0x75, 0x10,        //   Report Size (16)
+    0x09, 0x01,        //   Usage (Pointer)
+    0xa1, 0x00,        //   Collection (Physical)
+    0x09, 0x30,        //     Usage (X)
+    0x09, 0x31,        //     Usage (Y)
+    0x09, 0x33,        //     Usage (Rx)
+    0x09, 0x34,        //     Usage (Ry)
+    0x95, 0x04,        //     Report Count (4)
+    0x81, 0x02,        //     Input (Data, Variable, Absolute)
+    0xc0,              //   End Collection
+    0x09, 0x32,        //   Usage (Z)
+    0x09, 0x35,        //   Usage (Rz)
+    0x95, 0x02,        //   Report Count (2)
+    0x81, 0x02,        //   Input (Data, Variable, Absolute)
+    0x05, 0x09,        //   Usage Page (Button)
+    0x19, 0x01,        //   Usage Minimum (1)
+    0x29, 0x10,        //   Usage Maximum (16)
+    0x25, 0x01,        //   Logical Maximum (1)
+    0x75, 0x01,        //   Report Size (1)
+    0x95, 0x10,        //   Report Count (16)
+    0x81, 0x02,        //   Input (Data, Variable, Absolute)
+    0xc0,              // End Collection
+];
+
+/// A virtual HID gamepad driven by 16-channel CRSF RC frames.
+pub struct Gamepad {
+    file: File,
+    mapping: MappingConfig,
+    last_report: Option<[u8; REPORT_SIZE]>,
+}
+
+impl Gamepad {
+    /// Create the HID device using the given channel mapping. Requires
+    /// write access to `/dev/uhid`.
+    pub fn with_mapping(mapping: MappingConfig) -> io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/uhid")?;
+
+        let mut ev = vec![0u8; UHID_EVENT_SIZE];
+        ev[0..4].copy_from_slice(&UHID_CREATE2.to_ne_bytes());
+        let name = b"CRSF Gamepad";
+        ev[4..4 + name.len()].copy_from_slice(name);
+        // name[128], phys[64], uniq[64], then the fixed-size fields.
+        let req = 4 + 128 + 64 + 64;
+        ev[req..req + 2].copy_from_slice(&(REPORT_DESCRIPTOR.len() as u16).to_ne_bytes());
+        ev[req + 2..req + 4].copy_from_slice(&BUS_USB.to_ne_bytes());
+        ev[req + 4..req + 8].copy_from_slice(&VENDOR.to_ne_bytes());
+        ev[req + 8..req + 12].copy_from_slice(&PRODUCT.to_ne_bytes());
+        // version, country: 0
+        let rd = req + 20;
+        ev[rd..rd + REPORT_DESCRIPTOR.len()].copy_from_slice(REPORT_DESCRIPTOR);
+        file.write_all(&ev)?;
+
+        Ok(Self {
+            file,
+            mapping,
+            last_report: None,
+        })
+    }
+
+    /// Update the gamepad from a raw 16-channel CRSF RC frame. A report is
+    /// only sent when it differs from the previous one.
+    pub fn update(&mut self, channels: [u16; 16]) -> io::Result<()> {
+        let report = report(&self.mapping.apply(channels));
+        if self.last_report == Some(report) {
+            return Ok(());
+        }
+        self.send(report)
+    }
+
+    fn send(&mut self, report: [u8; REPORT_SIZE]) -> io::Result<()> {
+        let mut ev = Vec::with_capacity(6 + REPORT_SIZE);
+        ev.extend_from_slice(&UHID_INPUT2.to_ne_bytes());
+        ev.extend_from_slice(&(REPORT_SIZE as u16).to_ne_bytes());
+        ev.extend_from_slice(&report);
+        self.file.write_all(&ev)?;
+        self.last_report = Some(report);
+        counter!("joystick.uhid.update").increment(1);
+        Ok(())
+    }
+}
+
+impl Output for Gamepad {
+    fn update(&mut self, channels: [u16; 16]) -> io::Result<()> {
+        Gamepad::update(self, channels)
+    }
+
+    fn neutralize(&mut self) -> io::Result<()> {
+        let mut report = [0u8; REPORT_SIZE];
+        for (i, v) in [AXIS_MID, AXIS_MAX, AXIS_MID, AXIS_MID].iter().enumerate() {
+            report[i * 2..i * 2 + 2].copy_from_slice(&v.to_le_bytes());
+        }
+        self.send(report)?;
+        self.last_report = None;
+        Ok(())
+    }
+}
+
+impl Drop for Gamepad {
+    fn drop(&mut self) {
+        // Closing the fd destroys the device too; be explicit anyway. The
+        // kernel rejects writes under 6 bytes, so write a whole event.
+        let mut ev = vec![0u8; UHID_EVENT_SIZE];
+        ev[0..4].copy_from_slice(&UHID_DESTROY.to_ne_bytes());
+        let _ = self.file.write_all(&ev);
+    }
+}
+
+/// Build the input report for a normalized 16-channel frame.
+pub fn report(channels: &[u16; 16]) -> [u8; REPORT_SIZE] {
+    let axes = [
+        channels[3],
+        AXIS_MAX.saturating_sub(channels[2]),
+        channels[0],
+        AXIS_MAX.saturating_sub(channels[1]),
+        channels[6],
+        channels[4],
+    ];
+    let pressed = [
+        (0, channels[4] >= AXIS_MID),
+        (1, channels[5] >= AXIS_MID),
+        (2, channels[7] >= AXIS_MID),
+        (4, channels[8] <= AXIS_3POS_LEFT),
+        (5, channels[8] >= AXIS_3POS_RIGHT),
+        (6, channels[9] <= AXIS_3POS_LEFT),
+        (7, channels[9] >= AXIS_3POS_RIGHT),
+        (8, channels[10] <= AXIS_3POS_LEFT),
+        (9, channels[10] >= AXIS_3POS_RIGHT),
+        (10, channels[11] <= AXIS_3POS_LEFT),
+        (11, channels[11] >= AXIS_3POS_RIGHT),
+    ];
+    let buttons = pressed
+        .iter()
+        .filter(|(_, on)| *on)
+        .fold(0u16, |acc, (bit, _)| acc | (1 << bit));
+
+    let mut out = [0u8; REPORT_SIZE];
+    for (i, v) in axes.iter().enumerate() {
+        out[i * 2..i * 2 + 2].copy_from_slice(&v.min(&AXIS_MAX).to_le_bytes());
+    }
+    out[12..14].copy_from_slice(&buttons.to_le_bytes());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_layout() {
+        let mut ch = [AXIS_MID; 16];
+        ch[0] = 100; // AIL -> right X
+        ch[1] = AXIS_MAX; // ELE full up -> right Y minimum
+        ch[2] = 0; // THR low -> left Y maximum
+        ch[4] = AXIS_MAX; // SD armed
+        ch[7] = 0; // SA low
+        ch[8] = 0; // RUD trim left
+        let r = report(&ch);
+        assert_eq!(u16::from_le_bytes([r[0], r[1]]), AXIS_MID);
+        assert_eq!(u16::from_le_bytes([r[2], r[3]]), AXIS_MAX);
+        assert_eq!(u16::from_le_bytes([r[4], r[5]]), 100);
+        assert_eq!(u16::from_le_bytes([r[6], r[7]]), 0);
+        assert_eq!(u16::from_le_bytes([r[10], r[11]]), AXIS_MAX);
+        let buttons = u16::from_le_bytes([r[12], r[13]]);
+        // SD + SE (at mid counts as high) + RUD trim left.
+        assert_eq!(buttons, 0b1_0011);
+    }
+
+    #[test]
+    fn descriptor_report_size() {
+        // 6 axes * 16 bits + 16 buttons * 1 bit.
+        assert_eq!(REPORT_SIZE * 8, 6 * 16 + 16);
+    }
+}
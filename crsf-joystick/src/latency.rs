@@ -0,0 +1,113 @@
+//! Latency bookkeeping for the periodic `--latency-report` log line.
+//!
+//! Two latencies are tracked per applied RC frame:
+//!
+//! - **end-to-end**: from the Zenoh timestamp the publisher (e.g.
+//!   `crsf-forward`, right after reading the frame from the radio link)
+//!   attached to the sample, to the moment the uinput events were emitted.
+//!   Only meaningful when both hosts share a synchronized clock.
+//! - **local**: from receiving the sample in this process to emitting the
+//!   uinput events.
+//!
+//! The same values are also recorded as metrics histograms; this module
+//! only keeps the samples of the current report window.
+
+use std::fmt;
+use std::time::Duration;
+
+/// Latency samples collected over one report window.
+#[derive(Debug, Clone, Default)]
+pub struct LatencyStats {
+    samples: Vec<Duration>,
+}
+
+/// Summary of a window of latency samples.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencySummary {
+    pub count: usize,
+    pub min: Duration,
+    pub avg: Duration,
+    pub p50: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl LatencyStats {
+    pub fn record(&mut self, latency: Duration) {
+        self.samples.push(latency);
+    }
+
+    /// Summarize the samples collected so far, or `None` if there are none.
+    pub fn summary(&self) -> Option<LatencySummary> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let mut sorted = self.samples.clone();
+        sorted.sort_unstable();
+        let count = sorted.len();
+        let total: Duration = sorted.iter().sum();
+        let percentile = |p: usize| sorted[((count - 1) * p + 50) / 100];
+        Some(LatencySummary {
+            count,
+            min: sorted[0],
+            avg: total / count as u32,
+            p50: percentile(50),
+            p99: percentile(99),
+            max: sorted[count - 1],
+        })
+    }
+
+    /// Start a new window.
+    pub fn reset(&mut self) {
+        self.samples.clear();
+    }
+}
+
+impl fmt::Display for LatencySummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        write!(
+            f,
+            "n={} min={:.2}ms avg={:.2}ms p50={:.2}ms p99={:.2}ms max={:.2}ms",
+            self.count,
+            ms(self.min),
+            ms(self.avg),
+            ms(self.p50),
+            ms(self.p99),
+            ms(self.max)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_percentiles() {
+        let mut stats = LatencyStats::default();
+        assert_eq!(stats.summary(), None);
+        for ms in (1..=100).rev() {
+            stats.record(Duration::from_millis(ms));
+        }
+        let s = stats.summary().unwrap();
+        assert_eq!(s.count, 100);
+        assert_eq!(s.min, Duration::from_millis(1));
+        assert_eq!(s.max, Duration::from_millis(100));
+        assert_eq!(s.p50, Duration::from_millis(51));
+        assert_eq!(s.p99, Duration::from_millis(99));
+        assert_eq!(s.avg, Duration::from_micros(50_500));
+        stats.reset();
+        assert_eq!(stats.summary(), None);
+    }
+
+    #[test]
+    fn display() {
+        let mut stats = LatencyStats::default();
+        stats.record(Duration::from_micros(1500));
+        assert_eq!(
+            stats.summary().unwrap().to_string(),
+            "n=1 min=1.50ms avg=1.50ms p50=1.50ms p99=1.50ms max=1.50ms"
+        );
+    }
+}
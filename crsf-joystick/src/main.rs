@@ -14,15 +14,87 @@
 //! This matches the SA-switch handoff convention used elsewhere in the
 //! workspace; the simulator-side bridges (e.g. `liftoff-input`) don't
 //! see RC channels at all — they only handle telemetry.
-use std::time::Duration;
+//!
+//! With `--record`, every frame applied to the joystick is also appended
+//! to an [`rclog`](crsf_joystick::rclog) file for later analysis or replay.
+//! With `--replay`, no Zenoh session is opened: the frames of a recording
+//! are fed to the joystick with their original timing (scaled by
+//! `--speed`), after which the process exits.
+//!
+//! Additional manual radios can be attached with `--rc-source` (e.g. a
+//! student radio next to the instructor's in a buddy-box setup). All
+//! manual sources are first combined by the [`mux`](crsf_joystick::mux)
+//! according to `--rc-policy`; the result takes the place of the manual
+//! frame in the rules above.
+//!
+//! With `--output-rate`, frames are not applied as they arrive but fed to
+//! a [`Smoother`](crsf_joystick::smooth::Smoother), and the device is
+//! updated at the given rate with the stick channels interpolated between
+//! frames. The latency figures then end at that hand-off.
+//!
+//! With `--latency-report`, the end-to-end latency (publisher timestamp to
+//! uinput emit, see [`latency`](crsf_joystick::latency)) and the local
+//! processing latency are summarized in a log line at the given interval.
+//! Both are also always recorded as metrics histograms.
+//!
+//! The RC topics normally carry CRSF frames; raw SBUS frames (e.g. from a
+//! FrSky or Futaba receiver bridged onto Zenoh) are accepted as well, see
+//! `--rc-format`. CRSF subset RC frames (type 0x17, sent by newer ELRS
+//! firmware) update only the channels they carry; the rest keep their
+//! last value from the same source.
+//!
+//! `--backend uhid-gamepad` replaces the uinput joystick by a generic HID
+//! gamepad ([`Gamepad`](crsf_joystick::gamepad::Gamepad)). Access to the
+//! device node is checked up front (see
+//! [`preflight`](crsf_joystick::preflight)); with `--backend-fallback` the
+//! other backend is used when it is accessible and the selected one isn't.
+//!
+//! On Windows, built with the `vjoy` feature, the only backend is
+//! `--backend vjoy`, a device of the vJoy driver (see
+//! [`vjoy`](crsf_joystick::vjoy)); `--vjoy-device` selects which.
+//!
+//! On Ctrl-C or SIGTERM the virtual device is first put in a neutral state
+//! (sticks centered, throttle low, buttons released) and then destroyed,
+//! and the Zenoh session is closed, so no stale controller is left behind.
+//!
+//! When run as a systemd `Type=notify` service, readiness is reported once
+//! the virtual device exists, and with `WatchdogSec=` set the watchdog is
+//! pinged from the frame loop.
+//!
+//! Raw channel values are normalized through the `--mapping` config. The
+//! `calibrate` subcommand learns that config interactively from the
+//! manual RC topic. If the config defines mapping profiles, the device is
+//! recreated whenever the profile switch selects a profile that maps
+//! differently, and the profile name is published as CRSF flight mode.
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
 
-use clap::Parser;
-use crsf_joystick::{AXIS_MAX, AXIS_MID, Joystick};
+use clap::{Parser, Subcommand, ValueEnum};
+#[cfg(target_os = "linux")]
+use crsf_joystick::Joystick;
+use crsf_joystick::calibrate::Calibrator;
+#[cfg(target_os = "linux")]
+use crsf_joystick::gamepad::Gamepad;
+use crsf_joystick::latency::LatencyStats;
+use crsf_joystick::mapping::{MappingConfig, NUM_CHANNELS};
+use crsf_joystick::mux::{Mux, Policy};
+#[cfg(target_os = "linux")]
+use crsf_joystick::preflight;
+use crsf_joystick::rclog::{self, RcLogWriter};
+use crsf_joystick::smooth::Smoother;
+#[cfg(feature = "vjoy")]
+use crsf_joystick::vjoy::VJoy;
+use crsf_joystick::{AXIS_MAX, AXIS_MID, Output};
 use log::{error, info, trace, warn};
-use metrics::{Unit, counter, describe_counter};
+use metrics::{Unit, counter, describe_counter, describe_histogram, histogram};
+use metrics_exporter_prometheus::PrometheusBuilder;
 use metrics_exporter_tcp::TcpBuilder;
 use telemetry_lib::crsf::{self, CrsfPacket};
+use telemetry_lib::crsf_tx;
+use telemetry_lib::sbus;
+use telemetry_lib::systemd;
 use telemetry_lib::topics;
+use tokio::io::{AsyncBufReadExt, BufReader};
 use zenoh::Config;
 
 const MANUAL_TIMEOUT: Duration = Duration::from_millis(500);
@@ -30,6 +102,27 @@ const MANUAL_TIMEOUT: Duration = Duration::from_millis(500);
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Channel mapping config (JSON). Written by `calibrate`.
+    #[arg(long, global = true)]
+    mapping: Option<PathBuf>,
+
+    /// Virtual device to drive.
+    #[arg(long, value_enum, default_value_t = Backend::default())]
+    backend: Backend,
+
+    /// vJoy device to drive with `--backend vjoy`, 1 to 16.
+    #[cfg(feature = "vjoy")]
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=16))]
+    vjoy_device: u32,
+
+    /// Use the other backend if the selected one's device node can't be
+    /// opened.
+    #[arg(long, default_value_t = false)]
+    backend_fallback: bool,
+
     /// Zenoh connect endpoint (e.g. tcp/192.168.1.1:7447). Omit for peer discovery.
     #[arg(long)]
     zenoh_connect: Option<String>,
@@ -49,6 +142,468 @@ struct Args {
     /// Bind address for metrics-rs-tcp-exporter.
     #[arg(long, default_value = "127.0.0.1:5004")]
     metrics_tcp_bind: std::net::SocketAddr,
+
+    /// Serve metrics for Prometheus to scrape on this address (e.g.
+    /// 127.0.0.1:9000).
+    #[arg(long, conflicts_with = "metrics_tcp")]
+    metrics_prometheus: Option<std::net::SocketAddr>,
+
+    /// Record the RC frames applied to the joystick to this file (JSON lines).
+    #[arg(long)]
+    record: Option<PathBuf>,
+
+    /// Rotate the recording once the active file reaches this many bytes.
+    #[arg(long, requires = "record")]
+    record_max_bytes: Option<u64>,
+
+    /// Rotate the recording once the active file is this many seconds old.
+    #[arg(long, requires = "record")]
+    record_max_secs: Option<u64>,
+
+    /// Replay a recording (including its rotated segments) into the
+    /// joystick instead of listening to Zenoh.
+    #[arg(long, conflicts_with = "record")]
+    replay: Option<PathBuf>,
+
+    /// Replay speed factor; 2.0 plays back twice as fast.
+    #[arg(long, default_value_t = 1.0, requires = "replay")]
+    speed: f64,
+
+    /// Additional manual RC source as NAME=TOPIC (topic relative to the
+    /// prefix). Repeatable; earlier sources have higher priority, all
+    /// below the default `manual` source.
+    #[arg(long, value_parser = parse_assignment)]
+    rc_source: Vec<(String, String)>,
+
+    /// How multiple manual RC sources are combined.
+    #[arg(long, value_enum, default_value_t = RcPolicy::Priority)]
+    rc_policy: RcPolicy,
+
+    /// For `last-active`: how long the active source must be idle before
+    /// another one can take over.
+    #[arg(long, default_value_t = 1000)]
+    rc_hysteresis_ms: u64,
+
+    /// For `merge`: take channel CH from source NAME as CH=NAME.
+    /// Repeatable.
+    #[arg(long, value_parser = parse_assignment)]
+    rc_merge: Vec<(String, String)>,
+
+    /// Log a latency summary every this many seconds.
+    #[arg(long)]
+    latency_report: Option<u64>,
+
+    /// Update the device at this fixed rate (Hz), interpolating the sticks
+    /// between received frames, instead of once per frame.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=1000))]
+    output_rate: Option<u32>,
+
+    /// Encoding of the frames on the RC topics.
+    #[arg(long, value_enum, default_value_t = RcFormat::Auto)]
+    rc_format: RcFormat,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Backend {
+    /// uinput joystick (`/dev/uinput`).
+    #[cfg(target_os = "linux")]
+    Joystick,
+    /// Generic HID gamepad (`/dev/uhid`).
+    #[cfg(target_os = "linux")]
+    UhidGamepad,
+    /// vJoy device (Windows).
+    #[cfg(feature = "vjoy")]
+    Vjoy,
+}
+
+#[cfg(not(any(target_os = "linux", feature = "vjoy")))]
+compile_error!("crsf-joystick needs Linux, or the `vjoy` feature on Windows");
+
+impl Default for Backend {
+    fn default() -> Self {
+        #[cfg(target_os = "linux")]
+        return Backend::Joystick;
+        #[cfg(not(target_os = "linux"))]
+        return Backend::Vjoy;
+    }
+}
+
+impl Backend {
+    /// Check up front that the device can be created.
+    fn check(self) -> Result<(), String> {
+        match self {
+            #[cfg(target_os = "linux")]
+            Backend::Joystick => preflight::check(&preflight::UINPUT),
+            #[cfg(target_os = "linux")]
+            Backend::UhidGamepad => preflight::check(&preflight::UHID),
+            // vJoy tells what is wrong when the device is acquired.
+            #[cfg(feature = "vjoy")]
+            Backend::Vjoy => Ok(()),
+        }
+    }
+
+    /// The backend for `--backend-fallback`, if any.
+    fn other(self) -> Option<Backend> {
+        match self {
+            #[cfg(target_os = "linux")]
+            Backend::Joystick => Some(Backend::UhidGamepad),
+            #[cfg(target_os = "linux")]
+            Backend::UhidGamepad => Some(Backend::Joystick),
+            #[cfg(feature = "vjoy")]
+            Backend::Vjoy => None,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum RcPolicy {
+    /// Highest-priority source with fresh frames wins.
+    Priority,
+    /// Source whose sticks moved last wins, with hysteresis.
+    LastActive,
+    /// Per-channel merge as given by `--rc-merge`.
+    Merge,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum RcFormat {
+    /// SBUS if the frame starts with the SBUS start byte, CRSF otherwise.
+    Auto,
+    /// CRSF RC_CHANNELS_PACKED frames.
+    Crsf,
+    /// Raw 25-byte SBUS frames.
+    Sbus,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Interactively learn each channel's endpoints and switch positions
+    /// from the radio and store them in the `--mapping` config.
+    Calibrate,
+}
+
+fn parse_assignment(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .ok_or_else(|| format!("expected KEY=VALUE, got `{}`", s))
+}
+
+/// Build the manual source mux from the command line. `names` are the
+/// manual source names in priority order.
+fn build_mux(args: &Args, names: &[String]) -> Result<Mux, String> {
+    let policy = match args.rc_policy {
+        RcPolicy::Priority => Policy::Priority,
+        RcPolicy::LastActive => Policy::LastActive {
+            hysteresis: Duration::from_millis(args.rc_hysteresis_ms),
+        },
+        RcPolicy::Merge => {
+            let mut assign = vec![None; NUM_CHANNELS];
+            for (ch, name) in &args.rc_merge {
+                let ch: usize = ch
+                    .parse()
+                    .ok()
+                    .filter(|&ch| ch < NUM_CHANNELS)
+                    .ok_or_else(|| format!("--rc-merge: invalid channel `{}`", ch))?;
+                let idx = names
+                    .iter()
+                    .position(|n| n == name)
+                    .ok_or_else(|| format!("--rc-merge: unknown source `{}`", name))?;
+                assign[ch] = Some(idx);
+            }
+            Policy::Merge { assign }
+        }
+    };
+    Ok(Mux::new(names.len(), policy, MANUAL_TIMEOUT))
+}
+
+/// Decode an RC frame in the given format into `state`, the last known
+/// channels of its source, and return the updated channels. CRSF subset
+/// frames only overwrite the channels they carry. SBUS frames sent while
+/// the receiver is in failsafe are dropped. Channels out of range are
+/// returned but not kept, so that they don't spoil the subset frames after
+/// them.
+fn decode_rc(format: RcFormat, payload: &[u8], state: &mut [u16; 16]) -> Option<[u16; 16]> {
+    let is_sbus = match format {
+        RcFormat::Auto => sbus::is_sbus_frame(payload),
+        RcFormat::Crsf => false,
+        RcFormat::Sbus => true,
+    };
+    let channels = if is_sbus {
+        let frame = sbus::parse_frame(payload)?;
+        counter!("joystick.sbus.rx").increment(1);
+        if frame.failsafe {
+            counter!("joystick.sbus.failsafe").increment(1);
+            return None;
+        }
+        frame.channels
+    } else {
+        match crsf::parse_packet_check(payload)? {
+            CrsfPacket::RcChannelsPacked(rc) => rc.channels,
+            CrsfPacket::RcChannelsSubset(subset) => {
+                counter!("joystick.crsf.rx_rc_subset").increment(1);
+                let mut channels = *state;
+                subset.merge_into(&mut channels);
+                channels
+            }
+            _ => return None,
+        }
+    };
+    if channels.iter().all(|&c| c <= AXIS_MAX) {
+        *state = channels;
+    }
+    Some(channels)
+}
+
+/// Wall-clock time the publisher stamped on `sample`, if any.
+fn sample_time(sample: &zenoh::sample::Sample) -> Option<SystemTime> {
+    sample.timestamp().map(|ts| ts.get_time().to_system_time())
+}
+
+/// Create the virtual device selected by `--backend`, falling back to the
+/// other backend with `--backend-fallback`. Returns the backend used.
+fn open_output(
+    args: &Args,
+    mapping: MappingConfig,
+) -> Result<(Backend, Box<dyn Output>), Box<dyn std::error::Error + Send + Sync>> {
+    // /dev/uinput and /dev/uhid require write permission — typically
+    // achieved via udev rule or running as a member of the `input` group.
+    let mut backend = args.backend;
+    if let Err(diag) = backend.check() {
+        let Some(other) = backend.other().filter(|_| args.backend_fallback) else {
+            error!("{}", diag);
+            return Err(format!("cannot create the {:?} device", backend).into());
+        };
+        if let Err(other_diag) = other.check() {
+            error!("{}", diag);
+            error!("{}", other_diag);
+            return Err(format!("cannot create the {:?} or {:?} device", backend, other).into());
+        }
+        warn!("{}", diag);
+        warn!("Falling back to the {:?} backend", other);
+        backend = other;
+    }
+    let output: Box<dyn Output> = match backend {
+        #[cfg(target_os = "linux")]
+        Backend::Joystick => Box::new(Joystick::with_mapping(mapping)?),
+        #[cfg(target_os = "linux")]
+        Backend::UhidGamepad => Box::new(Gamepad::with_mapping(mapping)?),
+        #[cfg(feature = "vjoy")]
+        Backend::Vjoy => Box::new(VJoy::with_mapping(mapping, args.vjoy_device)?),
+    };
+    Ok((backend, output))
+}
+
+/// The virtual device, created for the mapping of the selected profile.
+struct Device {
+    backend: Backend,
+    output: Box<dyn Output>,
+    /// Effective mapping the device was created with.
+    mapping: MappingConfig,
+    profile: Option<usize>,
+}
+
+impl Device {
+    fn open(
+        args: &Args,
+        config: &MappingConfig,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mapping = config.profile(None);
+        let (backend, output) = open_output(args, mapping.clone())?;
+        Ok(Self {
+            backend,
+            output,
+            mapping,
+            profile: None,
+        })
+    }
+
+    /// Follow the profile switch in the raw frame `channels`, recreating
+    /// the device if the newly selected profile maps differently. The new
+    /// device is created before the old one is removed, so on failure the
+    /// old one stays in place. Returns the profile if the selection
+    /// changed.
+    fn select_profile(
+        &mut self,
+        args: &Args,
+        config: &MappingConfig,
+        channels: &[u16; NUM_CHANNELS],
+    ) -> Result<Option<usize>, Box<dyn std::error::Error + Send + Sync>> {
+        let selected = config.select_profile(channels, self.profile);
+        if selected == self.profile {
+            return Ok(None);
+        }
+        let mapping = config.profile(selected);
+        if mapping != self.mapping {
+            let (backend, output) = open_output(args, mapping.clone())?;
+            if let Err(e) = self.output.neutralize() {
+                error!("Failed to neutralize {:?} output: {}", self.backend, e);
+            }
+            self.backend = backend;
+            self.output = output;
+            self.mapping = mapping;
+        }
+        self.profile = selected;
+        Ok(selected)
+    }
+}
+
+/// Notify systemd of a state change, logging failures.
+fn sd_notify(state: &str) {
+    if let Err(e) = systemd::notify(state) {
+        warn!("sd_notify {} failed: {}", state, e);
+    }
+}
+
+/// Resolves on Ctrl-C or SIGTERM.
+#[cfg(unix)]
+async fn shutdown_signal() {
+    let mut term = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+        .expect("failed to install SIGTERM handler");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = term.recv() => {}
+    }
+}
+
+/// Resolves on Ctrl-C.
+#[cfg(not(unix))]
+async fn shutdown_signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+async fn open_session(
+    args: &Args,
+) -> Result<zenoh::Session, Box<dyn std::error::Error + Send + Sync>> {
+    let mut config = Config::default();
+    config.insert_json5("mode", &format!(r#""{}""#, args.zenoh_mode))?;
+    if let Some(ref endpoint) = args.zenoh_connect {
+        config.insert_json5("connect/endpoints", &format!(r#"["{}"]"#, endpoint))?;
+    }
+    zenoh::open(config).await
+}
+
+/// Interactive calibration against live frames on `rc_topic`. The learned
+/// endpoints are merged into the mapping config at `path` (created if it
+/// doesn't exist yet), leaving channels that weren't moved untouched.
+async fn calibrate(
+    session: &zenoh::Session,
+    rc_topic: &str,
+    format: RcFormat,
+    path: &Path,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut config = if path.exists() {
+        MappingConfig::load(path)?
+    } else {
+        MappingConfig::default()
+    };
+    let subscriber = session.declare_subscriber(rc_topic).await?;
+    let mut stdin = BufReader::new(tokio::io::stdin()).lines();
+    let mut calibrator = Calibrator::new();
+    let mut latest: Option<[u16; 16]> = None;
+    let mut state = [AXIS_MID; NUM_CHANNELS];
+    let mut sweeping = false;
+
+    println!("Listening for RC frames on {}.", rc_topic);
+    println!(
+        "Center all sticks (throttle too), put every switch in its rest position, then press Enter."
+    );
+    loop {
+        tokio::select! {
+            line = stdin.next_line() => {
+                if line?.is_none() {
+                    return Err("stdin closed before calibration finished".into());
+                }
+                if sweeping {
+                    break;
+                }
+                let Some(ref channels) = latest else {
+                    println!(
+                        "No RC frames received yet; check the radio link and press Enter again."
+                    );
+                    continue;
+                };
+                calibrator.capture_center(channels);
+                sweeping = true;
+                println!(
+                    "Move every stick and pot to its full extent and flip every switch \
+                     through all of its positions, then press Enter."
+                );
+            }
+            result = subscriber.recv_async() => {
+                let sample = result?;
+                let payload = sample.payload().to_bytes();
+                if let Some(channels) = decode_rc(format, &payload, &mut state) {
+                    latest = Some(channels);
+                    if sweeping {
+                        calibrator.observe(&channels);
+                    }
+                }
+            }
+        }
+    }
+
+    let updated = calibrator.apply(&mut config);
+    if updated.is_empty() {
+        return Err("no channel moved during calibration; nothing written".into());
+    }
+    for ch in updated {
+        let cfg = config.channel(ch);
+        let positions = calibrator
+            .result(ch)
+            .map(|res| res.positions)
+            .unwrap_or_default();
+        match &cfg.switch {
+            _ if positions.is_empty() => println!(
+                "ch{:<2} min {:4} center {:4} max {:4}",
+                ch, cfg.min, cfg.center, cfg.max
+            ),
+            Some(switch) => println!(
+                "ch{:<2} switch positions {:?}, thresholds {:?}",
+                ch, positions, switch.thresholds
+            ),
+            None => println!(
+                "ch{:<2} switch positions {:?}, unused without a switch entry",
+                ch, positions
+            ),
+        }
+    }
+    config.save(path)?;
+    println!("Wrote {}", path.display());
+    Ok(())
+}
+
+/// Feed recorded frames into the device, sleeping between them so that
+/// the original inter-frame timing is reproduced at `speed`×.
+async fn replay(
+    args: &Args,
+    config: &MappingConfig,
+    device: &mut Device,
+    entries: &[rclog::RcLogEntry],
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let Some(first) = entries.first() else {
+        return Ok(());
+    };
+    let start = tokio::time::Instant::now();
+    for entry in entries {
+        let offset = entry.t_us.saturating_sub(first.t_us) as f64 / 1e6 / args.speed;
+        // Too far ahead for a clock, at a tiny speed.
+        let at = Duration::try_from_secs_f64(offset)
+            .ok()
+            .and_then(|offset| start.checked_add(offset))
+            .ok_or_else(|| {
+                format!(
+                    "frame at {} µs is out of reach at --speed {}",
+                    entry.t_us, args.speed
+                )
+            })?;
+        tokio::time::sleep_until(at).await;
+        trace!("replay {} {:?}", entry.source, entry.channels);
+        if let Some(idx) = device.select_profile(args, config, &entry.channels)? {
+            info!("Mapping profile switched to {}", config.profile_name(idx));
+        }
+        device.output.update(entry.channels)?;
+    }
+    Ok(())
 }
 
 #[tokio::main]
@@ -64,73 +619,311 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
             .install()
             .expect("failed to install metrics TCP exporter");
     }
+    if let Some(addr) = args.metrics_prometheus {
+        PrometheusBuilder::new()
+            .with_http_listener(addr)
+            .install()
+            .expect("failed to install metrics Prometheus exporter");
+    }
 
     describe_counter!("joystick.crsf.rx", Unit::Count, "CRSF frames received");
     describe_counter!(
         "joystick.crsf.rx_rc_channels",
         Unit::Count,
-        "CRSF RC_CHANNELS frames received"
+        "RC channel frames received (CRSF or SBUS)"
+    );
+    describe_counter!(
+        "joystick.crsf.rx_rc_subset",
+        Unit::Count,
+        "CRSF subset RC channel frames received"
+    );
+    describe_counter!("joystick.sbus.rx", Unit::Count, "SBUS frames received");
+    describe_counter!(
+        "joystick.sbus.failsafe",
+        Unit::Count,
+        "SBUS frames dropped because the receiver was in failsafe"
     );
     describe_counter!(
         "joystick.uinput.update",
         Unit::Count,
         "Updates to virtual input device"
     );
+    describe_counter!(
+        "joystick.uhid.update",
+        Unit::Count,
+        "Reports sent to the virtual HID gamepad"
+    );
+    describe_counter!(
+        "joystick.rc.applied",
+        Unit::Count,
+        "RC frames applied to the virtual device"
+    );
+    describe_counter!(
+        "joystick.output.error",
+        Unit::Count,
+        "Failed updates of the virtual device"
+    );
+    describe_counter!(
+        "joystick.telemetry.tx",
+        Unit::Count,
+        "Flight mode frames published for mapping profile changes"
+    );
+    describe_counter!(
+        "joystick.record.error",
+        Unit::Count,
+        "Failed writes to the RC recording"
+    );
+    describe_histogram!(
+        "joystick.rc.interval",
+        Unit::Microseconds,
+        "Time between RC frames from the same source"
+    );
+    describe_histogram!(
+        "joystick.latency.e2e",
+        Unit::Microseconds,
+        "Publisher timestamp to uinput emit"
+    );
+    describe_histogram!(
+        "joystick.latency.local",
+        Unit::Microseconds,
+        "Sample receive to uinput emit"
+    );
 
-    let mut config = Config::default();
-    config.insert_json5("mode", &format!(r#""{}""#, args.zenoh_mode))?;
-    if let Some(ref endpoint) = args.zenoh_connect {
-        config.insert_json5("connect/endpoints", &format!(r#"["{}"]"#, endpoint))?;
+    if let Some(Command::Calibrate) = args.command {
+        let Some(ref path) = args.mapping else {
+            return Err("calibrate needs --mapping <path> to write to".into());
+        };
+        let session = open_session(&args).await?;
+        let rc_topic = topics::topic(&args.zenoh_prefix, topics::CRSF_RC);
+        let result = calibrate(&session, &rc_topic, args.rc_format, path).await;
+        session.close().await?;
+        return result;
+    }
+
+    let mapping = match args.mapping {
+        Some(ref path) => {
+            info!("Loading channel mapping from {}", path.display());
+            MappingConfig::load(path)?
+        }
+        None => MappingConfig::default(),
+    };
+    // The mux decides on the normalized SA switch position, like the
+    // joystick mapping itself.
+    let sa_switch = mapping.channel(7);
+
+    if let Some(ref path) = args.replay {
+        if args.speed.is_nan() || args.speed <= 0.0 {
+            return Err("--speed must be positive".into());
+        }
+        let entries = rclog::read_session(path)?;
+        info!(
+            "Replaying {} RC frames from {} at {}x",
+            entries.len(),
+            path.display(),
+            args.speed
+        );
+        let mut device = Device::open(&args, &mapping)?;
+        replay(&args, &mapping, &mut device, &entries).await?;
+        info!("Replay finished");
+        return Ok(());
     }
-    let session = zenoh::open(config).await?;
 
-    let crsf_rc_topic = topics::topic(&args.zenoh_prefix, topics::CRSF_RC);
+    let session = open_session(&args).await?;
+
+    // Manual sources in priority order: the default RC topic first, then
+    // any `--rc-source`s.
+    let mut names = vec!["manual".to_string()];
+    let mut manual_topics = vec![topics::topic(&args.zenoh_prefix, topics::CRSF_RC)];
+    for (name, topic) in &args.rc_source {
+        if names.contains(name) || name == "autopilot" {
+            return Err(format!("duplicate RC source name `{}`", name).into());
+        }
+        names.push(name.clone());
+        manual_topics.push(topics::topic(&args.zenoh_prefix, topic));
+    }
+    let mut mux = build_mux(&args, &names)?;
+    if names.len() > 1 {
+        info!(
+            "Combining manual RC sources with {:?} policy",
+            args.rc_policy
+        );
+    }
+
+    // Each manual subscriber forwards its frames, tagged with the source
+    // index and publisher timestamp, into one channel so the mux loop can
+    // wait on all of them.
+    let (manual_tx, mut manual_rx) =
+        tokio::sync::mpsc::channel::<(usize, Vec<u8>, Option<SystemTime>)>(64);
+    for (idx, topic) in manual_topics.iter().enumerate() {
+        info!("Subscribing to: {} ({})", topic, names[idx]);
+        let subscriber = session.declare_subscriber(topic).await?;
+        let tx = manual_tx.clone();
+        let name = names[idx].clone();
+        tokio::spawn(async move {
+            loop {
+                match subscriber.recv_async().await {
+                    Ok(sample) => {
+                        let payload = sample.payload().to_bytes().to_vec();
+                        let sent = sample_time(&sample);
+                        if tx.send((idx, payload, sent)).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        error!("RC subscriber error ({}): {}", name, e);
+                        break;
+                    }
+                }
+            }
+        });
+    }
+    drop(manual_tx);
+
     let crsf_rc_ap_topic = topics::topic(&args.zenoh_prefix, topics::CRSF_RC_AUTOPILOT);
-    info!("Subscribing to: {} (manual)", crsf_rc_topic);
     info!("Subscribing to: {} (autopilot)", crsf_rc_ap_topic);
-
-    let rc_subscriber = session.declare_subscriber(&crsf_rc_topic).await?;
     let rc_ap_subscriber = session.declare_subscriber(&crsf_rc_ap_topic).await?;
 
-    // /dev/uinput requires write permission — typically achieved via udev
-    // rule or running as a member of the `input` group.
-    let mut joystick = Joystick::new()?;
+    let mut device = Device::open(&args, &mapping)?;
+
+    // The active mapping profile is shown on the radio as flight mode.
+    let crsf_tel_publisher = match mapping.profile_switch {
+        Some(_) => {
+            let topic = topics::topic(&args.zenoh_prefix, topics::CRSF_TELEMETRY);
+            Some(session.declare_publisher(topic).await?)
+        }
+        None => None,
+    };
+
+    let mut recorder = match args.record {
+        Some(ref path) => {
+            let rotation = rclog::Rotation {
+                max_bytes: args.record_max_bytes,
+                max_age: args.record_max_secs.map(Duration::from_secs),
+            };
+            info!("Recording RC frames to {}", path.display());
+            Some(RcLogWriter::create(path, rotation)?)
+        }
+        None => None,
+    };
 
     // Mux state: track manual radio presence and the SA switch position.
     let mut last_manual_time: Option<tokio::time::Instant> = None;
     let mut last_manual_ch7: u16 = 0; // SA switch, low = manual
     let mut active_source = "none";
+    let mut manual_selected = None;
+
+    // Last known channels per manual source, then the autopilot. Channels
+    // not yet seen in a subset frame read as centered.
+    let mut rc_state = vec![[AXIS_MID; NUM_CHANNELS]; names.len() + 1];
+    // Receive time of the previous frame, in the same order.
+    let mut last_rx: Vec<Option<Instant>> = vec![None; names.len() + 1];
+
+    let mut e2e_latency = LatencyStats::default();
+    let mut local_latency = LatencyStats::default();
+    let report_period = Duration::from_secs(args.latency_report.unwrap_or(60).max(1));
+    let mut report_timer =
+        tokio::time::interval_at(tokio::time::Instant::now() + report_period, report_period);
+
+    let mut smoother = Smoother::new();
+    let output_period = Duration::from_secs(1) / args.output_rate.unwrap_or(1);
+    let mut output_timer = tokio::time::interval(output_period);
+    output_timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    if let Some(rate) = args.output_rate {
+        info!("Updating the device at {} Hz", rate);
+    }
+
+    // Pinged from the frame loop itself, so that a loop stuck in a hung
+    // output device stops the pings and systemd restarts the service.
+    let watchdog = systemd::watchdog_interval();
+    let mut watchdog_timer = tokio::time::interval(watchdog.unwrap_or(Duration::from_secs(1)));
+    if let Some(interval) = watchdog {
+        info!("systemd watchdog enabled, pinging every {:?}", interval);
+    }
+    sd_notify("READY=1");
+
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
 
     loop {
-        let (payload, source) = tokio::select! {
-            result = rc_subscriber.recv_async() => match result {
-                Ok(sample) => (sample.payload().to_bytes().to_vec(), "manual"),
-                Err(e) => { error!("RC subscriber error: {}", e); break; }
+        // `None` is the autopilot, `Some(idx)` manual source `idx`.
+        let (payload, source, sent) = tokio::select! {
+            _ = &mut shutdown => {
+                info!("Shutdown signal received");
+                break;
+            }
+            msg = manual_rx.recv() => match msg {
+                Some((idx, payload, sent)) => (payload, Some(idx), sent),
+                None => { error!("All manual RC subscribers closed"); break; }
             },
             result = rc_ap_subscriber.recv_async() => match result {
-                Ok(sample) => (sample.payload().to_bytes().to_vec(), "autopilot"),
+                Ok(sample) => (sample.payload().to_bytes().to_vec(), None, sample_time(&sample)),
                 Err(e) => { error!("RC autopilot subscriber error: {}", e); break; }
             },
+            _ = report_timer.tick(), if args.latency_report.is_some() => {
+                match e2e_latency.summary() {
+                    Some(s) => info!("Latency end-to-end: {}", s),
+                    None => info!("Latency end-to-end: no timestamped frames"),
+                }
+                if let Some(s) = local_latency.summary() {
+                    info!("Latency local: {}", s);
+                }
+                e2e_latency.reset();
+                local_latency.reset();
+                continue;
+            }
+            _ = output_timer.tick(), if args.output_rate.is_some() => {
+                if let Some(frame) = smoother.sample(Instant::now())
+                    && let Err(e) = device.output.update(frame)
+                {
+                    counter!("joystick.output.error").increment(1);
+                    error!("Failed to update {:?} output: {}", device.backend, e);
+                }
+                continue;
+            }
+            _ = watchdog_timer.tick(), if watchdog.is_some() => {
+                sd_notify("WATCHDOG=1");
+                continue;
+            }
         };
+        let received = Instant::now();
+        let source_name = source.map_or("autopilot", |idx| names[idx].as_str());
 
-        trace!("rx crsf ({}) {:02x?}", source, &*payload);
+        trace!("rx crsf ({}) {:02x?}", source_name, &*payload);
         counter!("joystick.crsf.rx").increment(1);
 
-        let Some(CrsfPacket::RcChannelsPacked(channels)) =
-            crsf::parse_packet_check(&payload)
-        else {
+        let slot = source.unwrap_or(names.len());
+        let Some(channels) = decode_rc(args.rc_format, &payload, &mut rc_state[slot]) else {
             continue;
         };
         counter!("joystick.crsf.rx_rc_channels").increment(1);
-        if channels.channels.iter().any(|&c| c > AXIS_MAX) {
-            warn!("Channel out of range: {:?}", channels.channels);
+        if let Some(prev) = last_rx[slot].replace(received) {
+            histogram!("joystick.rc.interval", "source" => source_name.to_string())
+                .record((received - prev).as_micros() as f64);
+        }
+        if channels.iter().any(|&c| c > AXIS_MAX) {
+            warn!("Channel out of range: {:?}", channels);
             continue;
         }
 
-        if source == "manual" {
-            last_manual_time = Some(tokio::time::Instant::now());
-            last_manual_ch7 = channels.channels[7];
-        }
+        let (channels, kind) = match source {
+            Some(idx) => {
+                let Some(frame) = mux.input(idx, channels, received) else {
+                    continue;
+                };
+                if mux.selected() != manual_selected {
+                    manual_selected = mux.selected();
+                    if let Some(sel) = manual_selected
+                        && names.len() > 1
+                    {
+                        info!("Manual RC source switched to {}", names[sel]);
+                    }
+                }
+                last_manual_time = Some(tokio::time::Instant::now());
+                last_manual_ch7 = sa_switch.normalize(frame[7]);
+                (frame, "manual")
+            }
+            None => (channels, "autopilot"),
+        };
 
         let manual_active = last_manual_time
             .map(|t| t.elapsed() < MANUAL_TIMEOUT)
@@ -146,12 +939,64 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
             active_source = selected;
         }
 
-        if source == selected {
-            if let Err(e) = joystick.update(channels.channels) {
-                error!("Failed to update uinput: {}", e);
+        if kind != selected {
+            continue;
+        }
+        counter!("joystick.rc.applied").increment(1);
+        match device.select_profile(&args, &mapping, &channels) {
+            Ok(Some(idx)) => {
+                let name = mapping.profile_name(idx);
+                info!("Mapping profile switched to {}", name);
+                if let Some(ref publisher) = crsf_tel_publisher
+                    && let Some(frame) = crsf_tx::build_flight_mode_packet(name)
+                {
+                    match publisher.put(frame).await {
+                        Ok(()) => counter!("joystick.telemetry.tx").increment(1),
+                        Err(e) => warn!("Failed to publish mapping profile: {}", e),
+                    }
+                }
             }
+            Ok(None) => {}
+            Err(e) => error!("Failed to switch mapping profile: {}", e),
         }
+        if args.output_rate.is_some() {
+            smoother.push(channels, received);
+        } else if let Err(e) = device.output.update(channels) {
+            counter!("joystick.output.error").increment(1);
+            error!("Failed to update {:?} output: {}", device.backend, e);
+        }
+        let local = received.elapsed();
+        histogram!("joystick.latency.local").record(local.as_micros() as f64);
+        // The windows are only emptied by the report.
+        let report = args.latency_report.is_some();
+        if report {
+            local_latency.record(local);
+        }
+        // Only a publisher clock ahead of ours makes this fail; skip those.
+        if let Some(e2e) = sent.and_then(|t| SystemTime::now().duration_since(t).ok()) {
+            histogram!("joystick.latency.e2e").record(e2e.as_micros() as f64);
+            if report {
+                e2e_latency.record(e2e);
+            }
+        }
+        let record_name = match manual_selected {
+            Some(idx) if kind == "manual" => names[idx].as_str(),
+            _ => kind,
+        };
+        if let Some(ref mut rec) = recorder
+            && let Err(e) = rec.write(record_name, &channels)
+        {
+            counter!("joystick.record.error").increment(1);
+            warn!("Failed to write RC recording: {}", e);
+        }
+    }
+
+    sd_notify("STOPPING=1");
+    if let Err(e) = device.output.neutralize() {
+        error!("Failed to neutralize {:?} output: {}", device.backend, e);
     }
+    drop(device);
+    info!("Virtual device removed");
 
     session.close().await?;
     Ok(())
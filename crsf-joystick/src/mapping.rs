@@ -0,0 +1,633 @@
+//! Per-radio channel mapping configuration.
+//!
+//! Loaded from a JSON file (`--mapping`). Every field is optional; a
+//! missing file section or channel entry falls back to the defaults,
+//! which reproduce the uncalibrated behaviour: raw CRSF values
+//! `0..=AXIS_MAX` with `AXIS_MID` as the center.
+//!
+//! ```json
+//! {
+//!   "channels": [
+//!     { "min": 172, "center": 992, "max": 1811 },
+//!     { "min": 174, "center": 990, "max": 1809, "reverse": true },
+//!     { "min": 172, "center": 992, "max": 1811, "offset": -12 },
+//!     {},
+//!     {},
+//!     {},
+//!     {},
+//!     {},
+//!     { "switch": { "thresholds": [400, 1000, 1600], "output": "axis" } }
+//!   ]
+//! }
+//! ```
+//!
+//! An `axis` entry sets the range and `AbsInfo` parameters the channel is
+//! reported with (see [`AxisConfig`]), e.g. `"axis": { "max": 65535 }`
+//! for full 16-bit resolution.
+//!
+//! A `switch` entry turns the channel into a discrete N-position input
+//! (see [`SwitchConfig`]), replacing the fixed mapping of that channel in
+//! the uinput joystick.
+//!
+//! Optional `profiles` override the settings of some channels, e.g. to
+//! turn the sticks into menu navigation buttons. The profile is selected
+//! at runtime by the position of the `profile_switch` channel:
+//!
+//! ```json
+//! {
+//!   "channels": [ ... ],
+//!   "profiles": [
+//!     { "name": "liftoff" },
+//!     { "name": "menu", "channels": [{ "switch": {} }, { "switch": {} }] }
+//!   ],
+//!   "profile_switch": { "channel": 9 }
+//! }
+//! ```
+
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{AXIS_3POS_LEFT, AXIS_3POS_RIGHT, AXIS_MAX, AXIS_MID};
+
+/// Number of CRSF RC channels.
+pub const NUM_CHANNELS: usize = 16;
+
+/// Calibration and options for a single RC channel.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChannelConfig {
+    /// Raw value at the low endpoint.
+    pub min: u16,
+    /// Raw value at rest (sticks) or the middle position (switches).
+    pub center: u16,
+    /// Raw value at the high endpoint.
+    pub max: u16,
+    /// Invert the channel (`0` ↔ `AXIS_MAX`).
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub reverse: bool,
+    /// Static offset (subtrim) added to the normalized value, after
+    /// inversion.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub offset: i16,
+    /// Output range of the axis the channel is mapped to.
+    #[serde(skip_serializing_if = "is_default")]
+    pub axis: AxisConfig,
+    /// Emit the channel as discrete positions instead of its fixed mapping.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub switch: Option<SwitchConfig>,
+}
+
+fn is_zero(v: &i16) -> bool {
+    *v == 0
+}
+
+fn is_default<T: Default + PartialEq>(v: &T) -> bool {
+    *v == T::default()
+}
+
+impl Default for ChannelConfig {
+    fn default() -> Self {
+        Self {
+            min: 0,
+            center: AXIS_MID,
+            max: AXIS_MAX,
+            reverse: false,
+            offset: 0,
+            axis: AxisConfig::default(),
+            switch: None,
+        }
+    }
+}
+
+impl ChannelConfig {
+    /// Endpoints are ordered `min <= center <= max`.
+    pub fn is_valid(&self) -> bool {
+        self.min <= self.center && self.center <= self.max
+    }
+
+    /// Map a raw channel value onto the canonical `0..=AXIS_MAX` range, with
+    /// `min`, `center` and `max` landing on `0`, `AXIS_MID` and `AXIS_MAX`,
+    /// then apply `reverse` and `offset`. Values beyond the endpoints are
+    /// clamped.
+    pub fn normalize(&self, raw: u16) -> u16 {
+        let mut out = self.scale_endpoints(raw);
+        if self.reverse {
+            out = AXIS_MAX - out;
+        }
+        (out as i32 + self.offset as i32).clamp(0, AXIS_MAX as i32) as u16
+    }
+
+    fn scale_endpoints(&self, raw: u16) -> u16 {
+        if !self.is_valid() {
+            return raw.min(AXIS_MAX);
+        }
+        let raw = raw.clamp(self.min, self.max);
+        let out = if raw <= self.center {
+            scale(raw - self.min, self.center - self.min, AXIS_MID)
+        } else {
+            AXIS_MID
+                + scale(
+                    raw - self.center,
+                    self.max - self.center,
+                    AXIS_MAX - AXIS_MID,
+                )
+        };
+        out.min(AXIS_MAX)
+    }
+}
+
+/// `value / span * out_span`, rounded; a zero span maps everything to
+/// `out_span`.
+fn scale(value: u16, span: u16, out_span: u16) -> u16 {
+    if span == 0 {
+        return out_span;
+    }
+    ((value as u32 * out_span as u32 + span as u32 / 2) / span as u32) as u16
+}
+
+/// Range and `AbsInfo` parameters of an output axis. The normalized
+/// `0..=AXIS_MAX` value is scaled linearly onto `min..=max`; `fuzz` and
+/// `flat` are in output units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AxisConfig {
+    pub min: i32,
+    pub max: i32,
+    pub fuzz: i32,
+    pub flat: i32,
+}
+
+impl Default for AxisConfig {
+    fn default() -> Self {
+        Self {
+            min: 0,
+            max: AXIS_MAX as i32,
+            fuzz: 7,
+            flat: 127,
+        }
+    }
+}
+
+impl AxisConfig {
+    pub fn is_valid(&self) -> bool {
+        self.min < self.max && self.fuzz >= 0 && self.flat >= 0
+    }
+
+    /// Scale a normalized channel value onto the output range.
+    pub fn scale(&self, value: u16) -> i32 {
+        let span = self.max as i64 - self.min as i64;
+        let value = value.min(AXIS_MAX) as i64;
+        (self.min as i64 + (value * span + AXIS_MAX as i64 / 2) / AXIS_MAX as i64) as i32
+    }
+}
+
+/// How the positions of a [`SwitchConfig`] channel are emitted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SwitchOutput {
+    /// One button per position, the one for the current position pressed.
+    #[default]
+    Buttons,
+    /// A hat axis reporting the position index `0..N`.
+    Axis,
+}
+
+/// A channel split into N discrete positions.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SwitchConfig {
+    /// Normalized values separating adjacent positions, strictly
+    /// ascending; N-1 thresholds give N positions.
+    pub thresholds: Vec<u16>,
+    /// How far past a threshold the value has to move before the position
+    /// changes, to keep a noisy channel from chattering.
+    pub hysteresis: u16,
+    pub output: SwitchOutput,
+}
+
+impl Default for SwitchConfig {
+    /// A 3-position switch with the same thresholds as the fixed trim
+    /// mapping.
+    fn default() -> Self {
+        Self {
+            thresholds: vec![AXIS_3POS_LEFT, AXIS_3POS_RIGHT],
+            hysteresis: 16,
+            output: SwitchOutput::Buttons,
+        }
+    }
+}
+
+impl SwitchConfig {
+    /// Thresholds are non-empty and strictly ascending.
+    pub fn is_valid(&self) -> bool {
+        !self.thresholds.is_empty() && self.thresholds.windows(2).all(|w| w[0] < w[1])
+    }
+
+    /// Number of positions.
+    pub fn positions(&self) -> usize {
+        self.thresholds.len() + 1
+    }
+
+    /// Position for the normalized `value`, given the previous position
+    /// (`None` on the first frame). Leaving `prev` requires moving
+    /// `hysteresis` past each threshold that is crossed.
+    pub fn position(&self, value: u16, prev: Option<usize>) -> usize {
+        let plain = self.thresholds.iter().filter(|&&t| value >= t).count();
+        let Some(prev) = prev.filter(|&p| p < self.positions()) else {
+            return plain;
+        };
+        let h = self.hysteresis;
+        if plain > prev {
+            prev + self.thresholds[prev..]
+                .iter()
+                .take_while(|&&t| value >= t.saturating_add(h))
+                .count()
+        } else if plain < prev {
+            prev - self.thresholds[..prev]
+                .iter()
+                .rev()
+                .take_while(|&&t| value.saturating_add(h) < t)
+                .count()
+        } else {
+            prev
+        }
+    }
+}
+
+/// A named set of channel settings that replaces the base settings while
+/// it is selected.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub name: String,
+    /// Per-channel overrides; `null` entries and channels beyond the end
+    /// of the list keep the base settings.
+    pub channels: Vec<Option<ChannelConfig>>,
+}
+
+/// The channel selecting the active [`Profile`]: position N of the switch
+/// selects the Nth profile.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProfileSwitch {
+    pub channel: usize,
+    /// Normalized values separating adjacent positions, as for
+    /// [`SwitchConfig`]. Defaults to a 2-position switch.
+    #[serde(default = "ProfileSwitch::default_thresholds")]
+    pub thresholds: Vec<u16>,
+    #[serde(default = "ProfileSwitch::default_hysteresis")]
+    pub hysteresis: u16,
+}
+
+impl ProfileSwitch {
+    fn default_thresholds() -> Vec<u16> {
+        vec![AXIS_MID]
+    }
+
+    fn default_hysteresis() -> u16 {
+        SwitchConfig::default().hysteresis
+    }
+
+    fn switch(&self) -> SwitchConfig {
+        SwitchConfig {
+            thresholds: self.thresholds.clone(),
+            hysteresis: self.hysteresis,
+            output: SwitchOutput::default(),
+        }
+    }
+}
+
+/// The full mapping configuration.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MappingConfig {
+    /// Per-channel settings, in channel order. Channels beyond the end of
+    /// the list use [`ChannelConfig::default`].
+    pub channels: Vec<ChannelConfig>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub profiles: Vec<Profile>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile_switch: Option<ProfileSwitch>,
+}
+
+/// Check a list of channel settings, naming the first invalid channel.
+fn validate_channels(channels: &[ChannelConfig]) -> Result<(), String> {
+    if let Some(ch) = channels.iter().position(|c| !c.is_valid()) {
+        return Err(format!("channel {}: expected min <= center <= max", ch));
+    }
+    if let Some(ch) = channels
+        .iter()
+        .position(|c| c.switch.as_ref().is_some_and(|s| !s.is_valid()))
+    {
+        return Err(format!(
+            "channel {}: switch thresholds must be strictly ascending",
+            ch
+        ));
+    }
+    if let Some(ch) = channels.iter().position(|c| !c.axis.is_valid()) {
+        return Err(format!(
+            "channel {}: expected axis min < max and non-negative fuzz/flat",
+            ch
+        ));
+    }
+    Ok(())
+}
+
+impl MappingConfig {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let data = std::fs::read_to_string(path)?;
+        let config: Self = serde_json::from_str(&data)?;
+        config
+            .validate()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        validate_channels(&self.channels)?;
+        for (idx, profile) in self.profiles.iter().enumerate() {
+            validate_channels(&self.profile(Some(idx)).channels)
+                .map_err(|e| format!("profile {}: {}", profile.name, e))?;
+        }
+        if let Some(ref sw) = self.profile_switch {
+            if sw.channel >= NUM_CHANNELS {
+                return Err(format!("profile_switch: invalid channel {}", sw.channel));
+            }
+            if !sw.switch().is_valid() {
+                return Err("profile_switch: thresholds must be strictly ascending".into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Profile selected by the raw frame `raw`, given the previous
+    /// selection, or `None` without a `profile_switch`. Positions without
+    /// a profile select the base settings.
+    pub fn select_profile(&self, raw: &[u16; NUM_CHANNELS], prev: Option<usize>) -> Option<usize> {
+        let sw = self.profile_switch.as_ref()?;
+        let value = self.channel(sw.channel).normalize(raw[sw.channel]);
+        Some(sw.switch().position(value, prev))
+    }
+
+    /// Name of profile `idx`, `"default"` for the base settings.
+    pub fn profile_name(&self, idx: usize) -> &str {
+        self.profiles
+            .get(idx)
+            .map_or("default", |p| p.name.as_str())
+    }
+
+    /// The effective configuration while profile `idx` is selected: the
+    /// base channel settings with the profile's overrides applied. `None`
+    /// gives the base settings alone.
+    pub fn profile(&self, idx: Option<usize>) -> MappingConfig {
+        let mut channels = self.channels.clone();
+        if let Some(profile) = idx.and_then(|idx| self.profiles.get(idx)) {
+            for (ch, cfg) in profile.channels.iter().enumerate().take(NUM_CHANNELS) {
+                if let Some(cfg) = cfg {
+                    if channels.len() <= ch {
+                        channels.resize(ch + 1, ChannelConfig::default());
+                    }
+                    channels[ch] = cfg.clone();
+                }
+            }
+        }
+        MappingConfig {
+            channels,
+            ..Default::default()
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut data = serde_json::to_string_pretty(self)?;
+        data.push('\n');
+        std::fs::write(path, data)
+    }
+
+    /// Settings for channel `ch`, defaulted if not configured.
+    pub fn channel(&self, ch: usize) -> ChannelConfig {
+        self.channels.get(ch).cloned().unwrap_or_default()
+    }
+
+    /// Mutable settings for channel `ch`, extending the list with defaults
+    /// as needed.
+    pub fn channel_mut(&mut self, ch: usize) -> &mut ChannelConfig {
+        if self.channels.len() <= ch {
+            self.channels.resize(ch + 1, ChannelConfig::default());
+        }
+        &mut self.channels[ch]
+    }
+
+    /// Normalize a raw 16-channel frame using the per-channel calibration.
+    pub fn apply(&self, raw: [u16; NUM_CHANNELS]) -> [u16; NUM_CHANNELS] {
+        let mut out = raw;
+        for (ch, cfg) in self.channels.iter().enumerate().take(NUM_CHANNELS) {
+            out[ch] = cfg.normalize(raw[ch]);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_is_identity() {
+        let cfg = ChannelConfig::default();
+        for raw in [0, 1, 500, AXIS_MID, 1500, AXIS_MAX] {
+            assert_eq!(cfg.normalize(raw), raw);
+        }
+    }
+
+    #[test]
+    fn normalize_elrs_range() {
+        let cfg = ChannelConfig {
+            min: 172,
+            center: 992,
+            max: 1811,
+            ..Default::default()
+        };
+        assert_eq!(cfg.normalize(172), 0);
+        assert_eq!(cfg.normalize(992), AXIS_MID);
+        assert_eq!(cfg.normalize(1811), AXIS_MAX);
+        // Clamped outside the endpoints.
+        assert_eq!(cfg.normalize(0), 0);
+        assert_eq!(cfg.normalize(2047), AXIS_MAX);
+        // Halfway between center and max.
+        let half = cfg.normalize(992 + (1811 - 992) / 2);
+        assert!(half.abs_diff(AXIS_MID + (AXIS_MAX - AXIS_MID) / 2) <= 1);
+    }
+
+    #[test]
+    fn reverse_and_offset() {
+        let cfg = ChannelConfig {
+            reverse: true,
+            ..Default::default()
+        };
+        assert_eq!(cfg.normalize(0), AXIS_MAX);
+        assert_eq!(cfg.normalize(AXIS_MAX), 0);
+        assert_eq!(cfg.normalize(AXIS_MID), AXIS_MAX - AXIS_MID);
+
+        let cfg = ChannelConfig {
+            offset: -20,
+            ..Default::default()
+        };
+        assert_eq!(cfg.normalize(AXIS_MID), AXIS_MID - 20);
+        assert_eq!(cfg.normalize(10), 0);
+
+        let cfg = ChannelConfig {
+            reverse: true,
+            offset: 20,
+            ..Default::default()
+        };
+        assert_eq!(cfg.normalize(AXIS_MAX), 20);
+        assert_eq!(cfg.normalize(0), AXIS_MAX);
+    }
+
+    #[test]
+    fn axis_scaling() {
+        let default = AxisConfig::default();
+        for v in [0, 1, AXIS_MID, AXIS_MAX] {
+            assert_eq!(default.scale(v), v as i32);
+        }
+        let wide = AxisConfig {
+            min: -32768,
+            max: 32767,
+            ..Default::default()
+        };
+        assert_eq!(wide.scale(0), -32768);
+        assert_eq!(wide.scale(AXIS_MAX), 32767);
+        assert!(wide.scale(AXIS_MID).abs() <= 16);
+        let full = AxisConfig {
+            max: 65535,
+            ..Default::default()
+        };
+        assert_eq!(full.scale(AXIS_MAX), 65535);
+        assert!(
+            !AxisConfig {
+                max: 0,
+                ..Default::default()
+            }
+            .is_valid()
+        );
+    }
+
+    #[test]
+    fn switch_positions_with_hysteresis() {
+        let sw = SwitchConfig {
+            thresholds: vec![500, 1000, 1500],
+            hysteresis: 20,
+            output: SwitchOutput::Axis,
+        };
+        assert!(sw.is_valid());
+        assert_eq!(sw.positions(), 4);
+        assert_eq!(sw.position(0, None), 0);
+        assert_eq!(sw.position(500, None), 1);
+        assert_eq!(sw.position(AXIS_MAX, None), 3);
+        // Just past the threshold isn't enough to leave position 0...
+        assert_eq!(sw.position(510, Some(0)), 0);
+        // ...but clearing it by the hysteresis is.
+        assert_eq!(sw.position(520, Some(0)), 1);
+        // Likewise on the way down.
+        assert_eq!(sw.position(490, Some(1)), 1);
+        assert_eq!(sw.position(479, Some(1)), 0);
+        // Jumping several positions at once.
+        assert_eq!(sw.position(1600, Some(0)), 3);
+        assert_eq!(sw.position(1490, Some(3)), 3);
+        assert_eq!(sw.position(1470, Some(3)), 2);
+        assert_eq!(sw.position(100, Some(3)), 0);
+
+        assert!(
+            !SwitchConfig {
+                thresholds: vec![],
+                ..Default::default()
+            }
+            .is_valid()
+        );
+        assert!(
+            !SwitchConfig {
+                thresholds: vec![5, 5],
+                ..Default::default()
+            }
+            .is_valid()
+        );
+    }
+
+    #[test]
+    fn profiles() {
+        let cfg: MappingConfig = serde_json::from_str(
+            r#"{
+                "channels": [{"min": 172, "max": 1811}, {"reverse": true}],
+                "profiles": [
+                    {"name": "liftoff"},
+                    {"name": "menu", "channels": [null, {"switch": {}}, {"offset": 5}]}
+                ],
+                "profile_switch": {"channel": 9, "thresholds": [600, 1400]}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(cfg.validate(), Ok(()));
+
+        let mut raw = [AXIS_MID; NUM_CHANNELS];
+        raw[9] = 100;
+        assert_eq!(cfg.select_profile(&raw, None), Some(0));
+        raw[9] = 1000;
+        assert_eq!(cfg.select_profile(&raw, Some(0)), Some(1));
+        raw[9] = 1900;
+        assert_eq!(cfg.select_profile(&raw, Some(1)), Some(2));
+        assert_eq!(cfg.profile_name(1), "menu");
+        assert_eq!(cfg.profile_name(2), "default");
+
+        let liftoff = cfg.profile(Some(0));
+        assert_eq!(liftoff.channels, cfg.channels);
+        assert!(liftoff.profiles.is_empty() && liftoff.profile_switch.is_none());
+        let menu = cfg.profile(Some(1));
+        assert_eq!(menu.channel(0), cfg.channel(0));
+        assert!(menu.channel(1).switch.is_some() && !menu.channel(1).reverse);
+        assert_eq!(menu.channel(2).offset, 5);
+        assert_eq!(cfg.profile(Some(2)).channels, cfg.channels);
+        assert_eq!(cfg.profile(None).channels, cfg.channels);
+
+        // Without a profile switch, there is nothing to select.
+        assert_eq!(MappingConfig::default().select_profile(&raw, None), None);
+
+        let mut bad = cfg.clone();
+        bad.profiles[1].channels[2] = Some(ChannelConfig {
+            min: 1000,
+            max: 100,
+            ..Default::default()
+        });
+        assert_eq!(
+            bad.validate(),
+            Err("profile menu: channel 2: expected min <= center <= max".into())
+        );
+        let mut bad = cfg.clone();
+        bad.profile_switch.as_mut().unwrap().channel = NUM_CHANNELS;
+        assert!(bad.validate().is_err());
+    }
+
+    #[test]
+    fn parse_partial_config() {
+        let cfg: MappingConfig = serde_json::from_str(r#"{"channels":[{"min":100},{}]}"#).unwrap();
+        assert_eq!(cfg.channel(0).min, 100);
+        assert_eq!(cfg.channel(0).max, AXIS_MAX);
+        assert_eq!(cfg.channel(1), ChannelConfig::default());
+        assert_eq!(cfg.channel(15), ChannelConfig::default());
+
+        let cfg: MappingConfig =
+            serde_json::from_str(r#"{"channels":[{"reverse":true,"offset":-5}]}"#).unwrap();
+        assert!(cfg.channel(0).reverse);
+        assert_eq!(cfg.channel(0).offset, -5);
+        assert_eq!(cfg.channel(0).switch, None);
+
+        let cfg: MappingConfig =
+            serde_json::from_str(r#"{"channels":[{"switch":{"output":"axis"}}]}"#).unwrap();
+        let sw = cfg.channel(0).switch.unwrap();
+        assert_eq!(sw.thresholds, vec![AXIS_3POS_LEFT, AXIS_3POS_RIGHT]);
+        assert_eq!(sw.output, SwitchOutput::Axis);
+        // Defaults are left out when saving.
+        let json = serde_json::to_string(&MappingConfig::default().channel(0)).unwrap();
+        assert!(!json.contains("reverse") && !json.contains("offset"));
+    }
+}
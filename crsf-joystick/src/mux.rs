@@ -0,0 +1,200 @@
+//! Combining several manual RC sources (e.g. instructor and student
+//! radios in a buddy-box setup) into one stream of frames.
+//!
+//! Sources are identified by their index, which is also their priority:
+//! index 0 is the highest. A source is *fresh* while its last frame is
+//! younger than the mux timeout; stale sources never drive the output.
+//!
+//! The [`Policy`] decides which source drives the output:
+//!
+//! - [`Policy::Priority`]: the highest-priority fresh source.
+//! - [`Policy::LastActive`]: the source whose sticks moved most recently.
+//!   Control is only handed over once the current source has been idle
+//!   for the hysteresis period (or went stale), so two pilots nudging
+//!   their sticks at the same time don't make the output flap.
+//! - [`Policy::Merge`]: the output is built per channel. Each channel is
+//!   taken from its assigned source while that source is fresh; all other
+//!   channels come from the highest-priority fresh source.
+
+use std::time::{Duration, Instant};
+
+use crate::mapping::NUM_CHANNELS;
+
+/// A change larger than this (in raw channel units) counts as stick
+/// activity for [`Policy::LastActive`].
+pub const ACTIVITY_DEADBAND: u16 = 8;
+
+/// How the sources are combined.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Policy {
+    Priority,
+    LastActive {
+        hysteresis: Duration,
+    },
+    /// `assign[ch]` is the index of the source channel `ch` is taken
+    /// from.
+    Merge {
+        assign: Vec<Option<usize>>,
+    },
+}
+
+#[derive(Debug, Clone, Default)]
+struct Source {
+    frame: Option<[u16; NUM_CHANNELS]>,
+    last_frame: Option<Instant>,
+    last_active: Option<Instant>,
+}
+
+/// Mux state for a fixed set of sources.
+#[derive(Debug, Clone)]
+pub struct Mux {
+    policy: Policy,
+    timeout: Duration,
+    sources: Vec<Source>,
+    selected: Option<usize>,
+}
+
+impl Mux {
+    pub fn new(num_sources: usize, policy: Policy, timeout: Duration) -> Self {
+        Self {
+            policy,
+            timeout,
+            sources: vec![Source::default(); num_sources],
+            selected: None,
+        }
+    }
+
+    /// Source currently driving the output (the base source for
+    /// [`Policy::Merge`]), if any.
+    pub fn selected(&self) -> Option<usize> {
+        self.selected
+    }
+
+    fn is_fresh(&self, idx: usize, now: Instant) -> bool {
+        self.sources[idx]
+            .last_frame
+            .is_some_and(|t| now.saturating_duration_since(t) < self.timeout)
+    }
+
+    fn first_fresh(&self, now: Instant) -> Option<usize> {
+        (0..self.sources.len()).find(|&i| self.is_fresh(i, now))
+    }
+
+    /// Feed a frame from source `idx` received at `now`. Returns the frame
+    /// to apply, or `None` if this source doesn't currently drive the
+    /// output.
+    pub fn input(
+        &mut self,
+        idx: usize,
+        channels: [u16; NUM_CHANNELS],
+        now: Instant,
+    ) -> Option<[u16; NUM_CHANNELS]> {
+        let src = self.sources.get_mut(idx)?;
+        let moved = match src.frame {
+            Some(prev) => prev
+                .iter()
+                .zip(&channels)
+                .any(|(a, b)| a.abs_diff(*b) > ACTIVITY_DEADBAND),
+            None => true,
+        };
+        src.frame = Some(channels);
+        src.last_frame = Some(now);
+        if moved {
+            src.last_active = Some(now);
+        }
+
+        self.selected = match self.policy {
+            Policy::Priority | Policy::Merge { .. } => self.first_fresh(now),
+            Policy::LastActive { hysteresis } => match self.selected {
+                Some(cur) if cur != idx && self.is_fresh(cur, now) => {
+                    let cur_idle = self.sources[cur]
+                        .last_active
+                        .is_none_or(|t| now.saturating_duration_since(t) >= hysteresis);
+                    if moved && cur_idle {
+                        Some(idx)
+                    } else {
+                        Some(cur)
+                    }
+                }
+                _ => Some(idx),
+            },
+        };
+
+        match self.policy {
+            Policy::Merge { ref assign } => {
+                let base = self.sources[self.selected?].frame?;
+                let mut out = base;
+                for (ch, owner) in assign.iter().enumerate().take(NUM_CHANNELS) {
+                    if let Some(owner) = *owner
+                        && owner < self.sources.len()
+                        && self.is_fresh(owner, now)
+                        && let Some(frame) = self.sources[owner].frame
+                    {
+                        out[ch] = frame[ch];
+                    }
+                }
+                Some(out)
+            }
+            _ if self.selected == Some(idx) => Some(channels),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_millis(500);
+
+    fn frame(v: u16) -> [u16; NUM_CHANNELS] {
+        [v; NUM_CHANNELS]
+    }
+
+    #[test]
+    fn priority_prefers_first_fresh_source() {
+        let t0 = Instant::now();
+        let mut mux = Mux::new(2, Policy::Priority, TIMEOUT);
+        assert_eq!(mux.input(1, frame(100), t0), Some(frame(100)));
+        assert_eq!(mux.input(0, frame(200), t0), Some(frame(200)));
+        assert_eq!(mux.input(1, frame(100), t0), None);
+        // Source 0 goes stale, source 1 takes over.
+        let t1 = t0 + TIMEOUT;
+        assert_eq!(mux.input(1, frame(100), t1), Some(frame(100)));
+        assert_eq!(mux.selected(), Some(1));
+    }
+
+    #[test]
+    fn last_active_with_hysteresis() {
+        let hysteresis = Duration::from_millis(200);
+        let t0 = Instant::now();
+        let ms = |n| t0 + Duration::from_millis(n);
+        let mut mux = Mux::new(2, Policy::LastActive { hysteresis }, TIMEOUT);
+        assert!(mux.input(0, frame(992), ms(0)).is_some());
+        assert_eq!(mux.input(1, frame(992), ms(10)), None);
+        // Source 0 is still moving: no handover.
+        assert!(mux.input(0, frame(1100), ms(100)).is_some());
+        assert_eq!(mux.input(1, frame(1200), ms(150)), None);
+        // Source 0 holds still (within the deadband) past the hysteresis.
+        assert!(mux.input(0, frame(1104), ms(250)).is_some());
+        assert_eq!(mux.input(1, frame(1300), ms(320)), Some(frame(1300)));
+        assert_eq!(mux.selected(), Some(1));
+        // Source 1 idle but fresh: small jitter on 0 doesn't take it back.
+        assert_eq!(mux.input(0, frame(1105), ms(600)), None);
+    }
+
+    #[test]
+    fn merge_takes_assigned_channels() {
+        let mut assign = vec![None; NUM_CHANNELS];
+        assign[2] = Some(1); // throttle from the student radio
+        let t0 = Instant::now();
+        let mut mux = Mux::new(2, Policy::Merge { assign }, TIMEOUT);
+        assert_eq!(mux.input(0, frame(992), t0), Some(frame(992)));
+        let out = mux.input(1, frame(172), t0).unwrap();
+        assert_eq!(out[2], 172);
+        assert_eq!(out[0], 992);
+        // Student radio goes stale: instructor's throttle again.
+        let out = mux.input(0, frame(992), t0 + TIMEOUT).unwrap();
+        assert_eq!(out, frame(992));
+    }
+}
@@ -0,0 +1,212 @@
+//! Access checks for the virtual device nodes, run before creating the
+//! device so that a missing module or permission problem is reported with
+//! concrete remediation steps instead of a bare `EPERM`.
+
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::os::unix::fs::MetadataExt;
+
+/// A character device a backend creates its virtual device through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceNode {
+    pub path: &'static str,
+    /// Kernel name, for udev rules and `modprobe`.
+    pub kernel: &'static str,
+}
+
+pub const UINPUT: DeviceNode = DeviceNode {
+    path: "/dev/uinput",
+    kernel: "uinput",
+};
+
+pub const UHID: DeviceNode = DeviceNode {
+    path: "/dev/uhid",
+    kernel: "uhid",
+};
+
+/// Group the udev rule hands the device nodes to.
+const INPUT_GROUP: &str = "input";
+
+/// What is known about a device node the process can't open.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct NodeInfo {
+    /// Owning group name and whether it has write permission.
+    group: Option<(String, bool)>,
+    mode: u32,
+    /// The current process has the owning group.
+    process_in_group: bool,
+    /// The user is listed as a member of the owning group in /etc/group.
+    user_in_group: bool,
+}
+
+/// Check that `node` can be opened for writing. On failure, returns a
+/// multi-line description of the problem and how to fix it.
+pub fn check(node: &DeviceNode) -> Result<(), String> {
+    let err = match OpenOptions::new().write(true).open(node.path) {
+        Ok(_) => return Ok(()),
+        Err(e) => e,
+    };
+    let info = fs::metadata(node.path)
+        .map(|meta| node_info(&meta))
+        .unwrap_or_default();
+    Err(diagnose(node, &err, &info))
+}
+
+fn node_info(meta: &fs::Metadata) -> NodeInfo {
+    let etc_group = fs::read_to_string("/etc/group").unwrap_or_default();
+    let status = fs::read_to_string("/proc/self/status").unwrap_or_default();
+    let user = std::env::var("USER").unwrap_or_default();
+    let group = parse_group(&etc_group, meta.gid());
+    NodeInfo {
+        user_in_group: group
+            .as_ref()
+            .is_some_and(|(_, members)| members.contains(&user)),
+        group: group.map(|(name, _)| (name, meta.mode() & 0o020 != 0)),
+        mode: meta.mode() & 0o777,
+        process_in_group: process_groups(&status).contains(&meta.gid()),
+    }
+}
+
+/// Name and members of group `gid` from `/etc/group` contents.
+fn parse_group(etc_group: &str, gid: u32) -> Option<(String, Vec<String>)> {
+    etc_group.lines().find_map(|line| {
+        let mut fields = line.split(':');
+        let name = fields.next()?;
+        let _password = fields.next()?;
+        if fields.next()?.parse::<u32>().ok()? != gid {
+            return None;
+        }
+        let members = fields
+            .next()
+            .unwrap_or("")
+            .split(',')
+            .filter(|m| !m.is_empty())
+            .map(str::to_string)
+            .collect();
+        Some((name.to_string(), members))
+    })
+}
+
+/// Effective and supplementary group IDs from `/proc/self/status`.
+fn process_groups(status: &str) -> Vec<u32> {
+    let mut groups = Vec::new();
+    for line in status.lines() {
+        if let Some(ids) = line.strip_prefix("Groups:") {
+            groups.extend(ids.split_whitespace().filter_map(|g| g.parse::<u32>().ok()));
+        } else if let Some(ids) = line.strip_prefix("Gid:") {
+            // Real, effective, saved, filesystem.
+            groups.extend(
+                ids.split_whitespace()
+                    .nth(1)
+                    .and_then(|g| g.parse::<u32>().ok()),
+            );
+        }
+    }
+    groups
+}
+
+fn udev_rule(node: &DeviceNode) -> String {
+    format!(
+        "KERNEL==\"{}\", SUBSYSTEM==\"misc\", GROUP=\"{}\", MODE=\"0660\", OPTIONS+=\"static_node={}\"",
+        node.kernel, INPUT_GROUP, node.kernel
+    )
+}
+
+fn diagnose(node: &DeviceNode, err: &io::Error, info: &NodeInfo) -> String {
+    match err.kind() {
+        io::ErrorKind::NotFound => format!(
+            "{path} does not exist. Load the kernel module with\n\
+             \x20   sudo modprobe {kernel}\n\
+             and make it load at boot with\n\
+             \x20   echo {kernel} | sudo tee /etc/modules-load.d/{kernel}.conf",
+            path = node.path,
+            kernel = node.kernel
+        ),
+        io::ErrorKind::PermissionDenied => {
+            let mut msg = format!("No write access to {}", node.path);
+            let writable_group = match info.group {
+                Some((ref name, writable)) => {
+                    msg += &format!(" (group {}, mode {:04o}).", name, info.mode);
+                    (writable && name != "root").then_some(name.as_str())
+                }
+                None => {
+                    msg += ".";
+                    None
+                }
+            };
+            match writable_group {
+                Some(group) if info.user_in_group && !info.process_in_group => {
+                    msg += &format!(
+                        "\nYour user is in group `{}`, but this process isn't; log out and \
+                         back in (or restart the systemd user manager) to pick it up.",
+                        group
+                    );
+                }
+                Some(group) => {
+                    msg += &format!(
+                        "\nAdd your user to group `{}` and log in again:\n\
+                         \x20   sudo usermod -aG {} $USER",
+                        group, group
+                    );
+                }
+                None => {
+                    msg += &format!(
+                        "\nGive group `{group}` write access with a udev rule in \
+                         /etc/udev/rules.d/99-{kernel}.rules:\n\
+                         \x20   {rule}\n\
+                         then reload it and add your user to the group:\n\
+                         \x20   sudo udevadm control --reload-rules && sudo udevadm trigger\n\
+                         \x20   sudo usermod -aG {group} $USER",
+                        group = INPUT_GROUP,
+                        kernel = node.kernel,
+                        rule = udev_rule(node)
+                    );
+                }
+            }
+            msg
+        }
+        _ => format!("Cannot open {}: {}", node.path, err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_group_and_status() {
+        let etc_group = "root:x:0:\ninput:x:104:alice,bob\nplugdev:x:46:\n";
+        assert_eq!(
+            parse_group(etc_group, 104),
+            Some(("input".into(), vec!["alice".into(), "bob".into()]))
+        );
+        assert_eq!(parse_group(etc_group, 46), Some(("plugdev".into(), vec![])));
+        assert_eq!(parse_group(etc_group, 5), None);
+
+        let status = "Name:\tcrsf-joystick\nGid:\t1000\t1001\t1000\t1001\nGroups:\t24 27 104 \n";
+        assert_eq!(process_groups(status), vec![1001, 24, 27, 104]);
+    }
+
+    #[test]
+    fn diagnostics() {
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+        let mut info = NodeInfo {
+            group: Some(("root".into(), false)),
+            mode: 0o600,
+            ..Default::default()
+        };
+        let msg = diagnose(&UINPUT, &denied, &info);
+        assert!(msg.starts_with("No write access to /dev/uinput (group root, mode 0600)."));
+        assert!(msg.contains(&udev_rule(&UINPUT)));
+
+        info.group = Some(("input".into(), true));
+        info.mode = 0o660;
+        assert!(diagnose(&UINPUT, &denied, &info).contains("sudo usermod -aG input $USER"));
+
+        info.user_in_group = true;
+        assert!(diagnose(&UINPUT, &denied, &info).contains("log out and back in"));
+
+        let missing = io::Error::from(io::ErrorKind::NotFound);
+        assert!(diagnose(&UHID, &missing, &info).contains("sudo modprobe uhid"));
+    }
+}
@@ -0,0 +1,260 @@
+//! RC channel log: timestamped 16-channel frames, one JSON object per line.
+//!
+//! Each line is an [`RcLogEntry`]. Timestamps are microseconds since the
+//! recording was started and keep counting across rotated segments, so
+//! the segments of one session can be concatenated and replayed as a
+//! whole.
+//!
+//! Rotation renames the active file to `<path>.<n>` (first free `n`,
+//! counting up from 1) and reopens `<path>`, so the oldest data of a
+//! session ends up in `<path>.1` and the newest in `<path>` itself.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, LineWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// A single recorded RC frame.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RcLogEntry {
+    /// Microseconds since the start of the recording.
+    pub t_us: u64,
+    /// Mux source the frame was taken from (`manual`, `autopilot` or an
+    /// `--rc-source` name).
+    pub source: String,
+    pub channels: [u16; 16],
+}
+
+/// Size/age limits after which the active segment is rotated.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Rotation {
+    pub max_bytes: Option<u64>,
+    pub max_age: Option<Duration>,
+}
+
+/// Appending writer for RC logs with size/time based rotation.
+pub struct RcLogWriter {
+    path: PathBuf,
+    file: LineWriter<File>,
+    rotation: Rotation,
+    start: Instant,
+    segment_start: Instant,
+    segment_bytes: u64,
+}
+
+impl RcLogWriter {
+    /// Create (truncate) the log file at `path`, and remove the rotated
+    /// segments of an earlier session there, which would otherwise be read
+    /// as part of this one.
+    pub fn create(path: impl Into<PathBuf>, rotation: Rotation) -> io::Result<Self> {
+        let path = path.into();
+        for n in 1.. {
+            match std::fs::remove_file(segment_path(&path, n)) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::NotFound => break,
+                Err(e) => return Err(e),
+            }
+        }
+        let file = LineWriter::new(File::create(&path)?);
+        let now = Instant::now();
+        Ok(Self {
+            path,
+            file,
+            rotation,
+            start: now,
+            segment_start: now,
+            segment_bytes: 0,
+        })
+    }
+
+    /// Append a frame, stamped with the time since the recording started.
+    pub fn write(&mut self, source: &str, channels: &[u16; 16]) -> io::Result<()> {
+        let entry = RcLogEntry {
+            t_us: self.start.elapsed().as_micros() as u64,
+            source: source.to_string(),
+            channels: *channels,
+        };
+        self.write_entry(&entry)
+    }
+
+    /// Append a pre-stamped entry.
+    pub fn write_entry(&mut self, entry: &RcLogEntry) -> io::Result<()> {
+        if self.should_rotate() {
+            self.rotate()?;
+        }
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.segment_bytes += line.len() as u64;
+        Ok(())
+    }
+
+    fn should_rotate(&self) -> bool {
+        if self.segment_bytes == 0 {
+            return false;
+        }
+        let too_big = self
+            .rotation
+            .max_bytes
+            .is_some_and(|max| self.segment_bytes >= max);
+        let too_old = self
+            .rotation
+            .max_age
+            .is_some_and(|max| self.segment_start.elapsed() >= max);
+        too_big || too_old
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let rotated = next_segment_path(&self.path);
+        std::fs::rename(&self.path, &rotated)?;
+        self.file = LineWriter::new(
+            OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&self.path)?,
+        );
+        self.segment_start = Instant::now();
+        self.segment_bytes = 0;
+        Ok(())
+    }
+}
+
+/// Path of rotated segment `n`: `<path>.<n>`.
+fn segment_path(path: &Path, n: u32) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+/// First `<path>.<n>` (n ≥ 1) that doesn't exist yet.
+fn next_segment_path(path: &Path) -> PathBuf {
+    (1..)
+        .map(|n| segment_path(path, n))
+        .find(|p| !p.exists())
+        .expect("unbounded range")
+}
+
+/// Read all entries of a single log file. Blank lines are skipped;
+/// malformed lines are an error.
+pub fn read_log(path: impl AsRef<Path>) -> io::Result<Vec<RcLogEntry>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut entries = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        entries.push(serde_json::from_str(&line)?);
+    }
+    Ok(entries)
+}
+
+/// Read a whole recording session: the rotated segments `<path>.1`,
+/// `<path>.2`, … in order, followed by `<path>` itself.
+pub fn read_session(path: impl AsRef<Path>) -> io::Result<Vec<RcLogEntry>> {
+    let path = path.as_ref();
+    let mut entries = Vec::new();
+    for n in 1.. {
+        let segment = segment_path(path, n);
+        if !segment.exists() {
+            break;
+        }
+        entries.extend(read_log(&segment)?);
+    }
+    entries.extend(read_log(path)?);
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rclog-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("rc.log")
+    }
+
+    #[test]
+    fn roundtrip() {
+        let path = temp_path("roundtrip");
+        let mut writer = RcLogWriter::create(&path, Rotation::default()).unwrap();
+        let mut channels = [992u16; 16];
+        writer.write("manual", &channels).unwrap();
+        channels[2] = 172;
+        writer.write("autopilot", &channels).unwrap();
+        drop(writer);
+
+        let entries = read_log(&path).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].source, "manual");
+        assert_eq!(entries[0].channels, [992; 16]);
+        assert_eq!(entries[1].source, "autopilot");
+        assert_eq!(entries[1].channels[2], 172);
+        assert!(entries[1].t_us >= entries[0].t_us);
+    }
+
+    #[test]
+    fn rotates_by_size() {
+        let path = temp_path("rotate");
+        let rotation = Rotation {
+            max_bytes: Some(1),
+            max_age: None,
+        };
+        let mut writer = RcLogWriter::create(&path, rotation).unwrap();
+        for i in 0..3 {
+            writer
+                .write_entry(&RcLogEntry {
+                    t_us: i,
+                    source: "manual".to_string(),
+                    channels: [i as u16; 16],
+                })
+                .unwrap();
+        }
+        drop(writer);
+
+        let first = read_log(path.with_extension("log.1")).unwrap();
+        let second = read_log(path.with_extension("log.2")).unwrap();
+        let current = read_log(&path).unwrap();
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].t_us, 0);
+        assert_eq!(second[0].t_us, 1);
+        assert_eq!(current[0].t_us, 2);
+
+        let session = read_session(&path).unwrap();
+        let stamps: Vec<u64> = session.iter().map(|e| e.t_us).collect();
+        assert_eq!(stamps, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn create_again() {
+        let path = temp_path("again");
+        let rotation = Rotation {
+            max_bytes: Some(1),
+            max_age: None,
+        };
+        // The first session rotates twice, the second once.
+        for stamps in [10..13, 20..22] {
+            let mut writer = RcLogWriter::create(&path, rotation).unwrap();
+            for t_us in stamps {
+                writer
+                    .write_entry(&RcLogEntry {
+                        t_us,
+                        source: "manual".to_string(),
+                        channels: [992; 16],
+                    })
+                    .unwrap();
+            }
+        }
+
+        let session = read_session(&path).unwrap();
+        let stamps: Vec<u64> = session.iter().map(|e| e.t_us).collect();
+        assert_eq!(stamps, vec![20, 21]);
+        assert!(!path.with_extension("log.2").exists());
+    }
+}
@@ -0,0 +1,127 @@
+//! Interpolation between RC frames for constant-rate output
+//! (`--output-rate`).
+//!
+//! Each received frame becomes the new target, approached linearly from
+//! the current output over the (smoothed) frame interval. The output thus
+//! trails the radio by about one frame interval, but moves in small steps
+//! instead of jumping once per frame, which helps with 50 Hz links or
+//! bursty network delivery.
+//!
+//! Only the stick channels are interpolated: intermediate values on a
+//! switch channel would pass through positions the switch never was in.
+
+use std::time::{Duration, Instant};
+
+use crate::mapping::NUM_CHANNELS;
+
+/// Channels 0-3 (AIL, ELE, THR, RUD).
+pub const SMOOTH_CHANNELS: usize = 4;
+
+/// A gap between frames longer than this restarts the interval estimate,
+/// and the next frame is applied without interpolation.
+pub const MAX_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Weight of a new interval sample in the running estimate, as 1/N.
+const INTERVAL_SMOOTHING: u32 = 8;
+
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    from: [u16; NUM_CHANNELS],
+    to: [u16; NUM_CHANNELS],
+    start: Instant,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Smoother {
+    segment: Option<Segment>,
+    /// Estimated interval between frames; zero disables interpolation.
+    interval: Duration,
+}
+
+impl Smoother {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start moving towards `frame`, received at `now`.
+    pub fn push(&mut self, frame: [u16; NUM_CHANNELS], now: Instant) {
+        let from = self.sample(now).unwrap_or(frame);
+        let gap = self.segment.map(|s| now.saturating_duration_since(s.start));
+        self.interval = match gap {
+            Some(gap) if gap <= MAX_INTERVAL && !self.interval.is_zero() => {
+                (self.interval * (INTERVAL_SMOOTHING - 1) + gap) / INTERVAL_SMOOTHING
+            }
+            Some(gap) if gap <= MAX_INTERVAL => gap,
+            _ => Duration::ZERO,
+        };
+        self.segment = Some(Segment {
+            from,
+            to: frame,
+            start: now,
+        });
+    }
+
+    /// The interpolated frame at `now`, or `None` before the first frame.
+    pub fn sample(&self, now: Instant) -> Option<[u16; NUM_CHANNELS]> {
+        let seg = self.segment?;
+        let elapsed = now.saturating_duration_since(seg.start);
+        if self.interval.is_zero() || elapsed >= self.interval {
+            return Some(seg.to);
+        }
+        let frac = elapsed.as_secs_f64() / self.interval.as_secs_f64();
+        let mut out = seg.to;
+        for (out, &from) in out.iter_mut().zip(&seg.from).take(SMOOTH_CHANNELS) {
+            let (from, to) = (from as f64, *out as f64);
+            *out = (from + (to - from) * frac).round() as u16;
+        }
+        Some(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(stick: u16, switch: u16) -> [u16; NUM_CHANNELS] {
+        let mut f = [switch; NUM_CHANNELS];
+        f[..SMOOTH_CHANNELS].fill(stick);
+        f
+    }
+
+    #[test]
+    fn interpolates_sticks_only() {
+        let t0 = Instant::now();
+        let ms = |n| t0 + Duration::from_millis(n);
+        let mut s = Smoother::new();
+        assert_eq!(s.sample(t0), None);
+        // First frame and a frame after a long gap are applied directly.
+        s.push(frame(1000, 172), ms(0));
+        assert_eq!(s.sample(ms(0)), Some(frame(1000, 172)));
+
+        s.push(frame(1200, 1811), ms(20));
+        assert_eq!(s.sample(ms(20)), Some(frame(1000, 1811)));
+        assert_eq!(s.sample(ms(30)), Some(frame(1100, 1811)));
+        assert_eq!(s.sample(ms(40)), Some(frame(1200, 1811)));
+        assert_eq!(s.sample(ms(100)), Some(frame(1200, 1811)));
+
+        s.push(frame(172, 172), ms(500));
+        assert_eq!(s.sample(ms(500)), Some(frame(172, 172)));
+    }
+
+    #[test]
+    fn retargets_from_current_output() {
+        let t0 = Instant::now();
+        let ms = |n| t0 + Duration::from_millis(n);
+        let mut s = Smoother::new();
+        s.push(frame(1000, 992), ms(0));
+        s.push(frame(1400, 992), ms(20));
+        // Halfway there, a frame arrives early (bursty delivery): the
+        // interval estimate shrinks a little, and the new segment starts
+        // at the current output rather than jumping.
+        s.push(frame(1000, 992), ms(30));
+        assert_eq!(s.sample(ms(30)), Some(frame(1200, 992)));
+        let out = s.sample(ms(40)).unwrap();
+        assert!(out[0] < 1200 && out[0] > 1000);
+        assert_eq!(s.sample(ms(60)), Some(frame(1000, 992)));
+    }
+}
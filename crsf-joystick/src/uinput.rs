@@ -0,0 +1,480 @@
+//! The uinput [`Joystick`](crate::Joystick): a `CRSF Joystick` evdev
+//! device created through `/dev/uinput`. Linux only.
+
+use evdev::uinput::VirtualDevice;
+use evdev::{AbsoluteAxisCode, AttributeSet, InputId, KeyCode, MiscCode, UinputAbsSetup};
+use metrics::counter;
+
+use crate::mapping::{AxisConfig, MappingConfig, NUM_CHANNELS, SwitchConfig, SwitchOutput};
+use crate::{AXIS_3POS_LEFT, AXIS_3POS_RIGHT, AXIS_MID, Output};
+
+/// All buttons of the virtual joystick.
+const BUTTONS: [KeyCode; 13] = [
+    KeyCode::BTN_TRIGGER,
+    KeyCode::BTN_THUMB,
+    KeyCode::BTN_THUMB2,
+    KeyCode::BTN_TOP,
+    KeyCode::BTN_TOP2,
+    KeyCode::BTN_PINKIE,
+    KeyCode::BTN_BASE,
+    KeyCode::BTN_BASE2,
+    KeyCode::BTN_BASE3,
+    KeyCode::BTN_BASE4,
+    KeyCode::BTN_BASE5,
+    KeyCode::BTN_BASE6,
+    KeyCode::new(KeyCode::BTN_BASE6.code() + 1), // 0x12d
+];
+
+/// Buttons available to [`SwitchOutput::Buttons`] channels, allocated in
+/// channel order: `BTN_TRIGGER_HAPPY1` to `BTN_TRIGGER_HAPPY40`.
+const SWITCH_BUTTONS: std::ops::Range<u16> =
+    KeyCode::BTN_TRIGGER_HAPPY1.code()..KeyCode::BTN_TRIGGER_HAPPY40.code() + 1;
+
+/// Axes available to [`SwitchOutput::Axis`] channels, allocated in channel
+/// order.
+const SWITCH_AXES: [AbsoluteAxisCode; 8] = [
+    AbsoluteAxisCode::ABS_HAT0X,
+    AbsoluteAxisCode::ABS_HAT0Y,
+    AbsoluteAxisCode::ABS_HAT1X,
+    AbsoluteAxisCode::ABS_HAT1Y,
+    AbsoluteAxisCode::ABS_HAT2X,
+    AbsoluteAxisCode::ABS_HAT2Y,
+    AbsoluteAxisCode::ABS_HAT3X,
+    AbsoluteAxisCode::ABS_HAT3Y,
+];
+
+enum SwitchTarget {
+    Buttons(Vec<KeyCode>),
+    Axis(AbsoluteAxisCode),
+}
+
+/// A channel mapped as an N-position switch.
+struct Switch {
+    ch: usize,
+    config: SwitchConfig,
+    target: SwitchTarget,
+    position: Option<usize>,
+}
+
+impl Switch {
+    /// Events reporting `position`.
+    fn events(&self, position: usize) -> Vec<evdev::InputEvent> {
+        match self.target {
+            SwitchTarget::Buttons(ref keys) => keys
+                .iter()
+                .enumerate()
+                .map(|(i, key)| {
+                    evdev::InputEvent::new(evdev::EventType::KEY.0, key.0, (i == position) as i32)
+                })
+                .collect(),
+            SwitchTarget::Axis(axis) => vec![evdev::InputEvent::new(
+                evdev::EventType::ABSOLUTE.0,
+                axis.0,
+                position as i32,
+            )],
+        }
+    }
+}
+
+/// Allocate buttons and axes for the switch channels in `mapping`.
+fn allocate_switches(mapping: &MappingConfig) -> std::io::Result<Vec<Switch>> {
+    let mut switches = Vec::new();
+    let mut buttons = SWITCH_BUTTONS;
+    let mut axes = SWITCH_AXES.iter();
+    for (ch, cfg) in mapping.channels.iter().enumerate().take(NUM_CHANNELS) {
+        let Some(ref config) = cfg.switch else {
+            continue;
+        };
+        let kind = match config.output {
+            SwitchOutput::Buttons => "buttons",
+            SwitchOutput::Axis => "axes",
+        };
+        let exhausted = || {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("channel {}: no free switch {}", ch, kind),
+            )
+        };
+        let target = match config.output {
+            SwitchOutput::Buttons => {
+                let keys: Vec<KeyCode> = buttons
+                    .by_ref()
+                    .take(config.positions())
+                    .map(KeyCode::new)
+                    .collect();
+                if keys.len() < config.positions() {
+                    return Err(exhausted());
+                }
+                SwitchTarget::Buttons(keys)
+            }
+            SwitchOutput::Axis => SwitchTarget::Axis(*axes.next().ok_or_else(exhausted)?),
+        };
+        switches.push(Switch {
+            ch,
+            config: config.clone(),
+            target,
+            position: None,
+        });
+    }
+    Ok(switches)
+}
+
+/// A virtual joystick driven by 16-channel CRSF RC frames.
+pub struct Joystick {
+    old_channels: [u16; 16],
+    device: VirtualDevice,
+    mapping: MappingConfig,
+    axes: [AxisConfig; NUM_CHANNELS],
+    switches: Vec<Switch>,
+}
+
+fn abs_info(axis: &AxisConfig) -> evdev::AbsInfo {
+    evdev::AbsInfo::new(axis.min, axis.min, axis.max, axis.fuzz, axis.flat, 0)
+}
+
+impl Joystick {
+    /// Create the virtual device with the default (uncalibrated) mapping.
+    /// Requires write access to `/dev/uinput`.
+    pub fn new() -> std::io::Result<Self> {
+        Self::with_mapping(MappingConfig::default())
+    }
+
+    /// Create the virtual device using the given channel mapping.
+    /// Requires write access to `/dev/uinput`.
+    pub fn with_mapping(mapping: MappingConfig) -> std::io::Result<Self> {
+        let switches = allocate_switches(&mapping)?;
+        let axes: [AxisConfig; NUM_CHANNELS] = std::array::from_fn(|ch| mapping.channel(ch).axis);
+
+        let mut keys = AttributeSet::<KeyCode>::new();
+        for k in BUTTONS {
+            keys.insert(k);
+        }
+        let mut switch_axes = Vec::new();
+        for sw in &switches {
+            match sw.target {
+                SwitchTarget::Buttons(ref codes) => {
+                    for &k in codes {
+                        keys.insert(k);
+                    }
+                }
+                SwitchTarget::Axis(axis) => switch_axes.push(UinputAbsSetup::new(
+                    axis,
+                    evdev::AbsInfo::new(0, 0, sw.config.positions() as i32 - 1, 0, 0, 0),
+                )),
+            }
+        }
+
+        let abs_setup = UinputAbsSetup::new(AbsoluteAxisCode::ABS_X, abs_info(&axes[0]));
+        let abs_y = UinputAbsSetup::new(AbsoluteAxisCode::ABS_Y, abs_info(&axes[1]));
+        let abs_z = UinputAbsSetup::new(AbsoluteAxisCode::ABS_Z, abs_info(&axes[2]));
+        let abs_rx = UinputAbsSetup::new(AbsoluteAxisCode::ABS_RX, abs_info(&axes[3]));
+        let abs_throttle = UinputAbsSetup::new(AbsoluteAxisCode::ABS_THROTTLE, abs_info(&axes[4]));
+        let abs_rudder = UinputAbsSetup::new(AbsoluteAxisCode::ABS_RUDDER, abs_info(&axes[6]));
+        let abs_wheel = UinputAbsSetup::new(AbsoluteAxisCode::ABS_WHEEL, abs_info(&axes[7]));
+
+        let mut msc_set = AttributeSet::<MiscCode>::new();
+        msc_set.insert(MiscCode::MSC_SCAN);
+
+        let mut builder = VirtualDevice::builder()?
+            .name("CRSF Joystick")
+            .input_id(InputId::new(evdev::BusType::BUS_USB, 0x1209, 0x4f54, 0)) // Radiomaster Pocket vendor/product
+            .with_keys(&keys)?
+            .with_absolute_axis(&abs_setup)?
+            .with_absolute_axis(&abs_y)?
+            .with_absolute_axis(&abs_z)?
+            .with_absolute_axis(&abs_rx)?
+            .with_absolute_axis(&abs_throttle)?
+            .with_absolute_axis(&abs_rudder)?
+            .with_absolute_axis(&abs_wheel)?;
+        for setup in &switch_axes {
+            builder = builder.with_absolute_axis(setup)?;
+        }
+        let device = builder.with_msc(&msc_set)?.build()?;
+
+        Ok(Self {
+            old_channels: [0xffff; 16], // Different initial value to force update
+            device,
+            mapping,
+            axes,
+            switches,
+        })
+    }
+
+    /// Update the virtual joystick from a raw 16-channel CRSF RC frame.
+    /// Only channels whose normalized value differs from the previous call
+    /// generate InputEvents. All events of a frame are written with a single
+    /// `emit`, which appends one `SYN_REPORT`, so readers see the whole frame
+    /// as one atomic update.
+    pub fn update(&mut self, channels: [u16; 16]) -> std::io::Result<()> {
+        let mut channels = self.mapping.apply(channels);
+        let mut events = Vec::<evdev::InputEvent>::new();
+        let dev = &mut self.device;
+        let old = self.old_channels;
+        let axes = &self.axes;
+
+        // Switch channels: emit position changes, then pin the channel to
+        // its old value so the fixed mapping below never fires for it.
+        for sw in &mut self.switches {
+            let position = sw.config.position(channels[sw.ch], sw.position);
+            if sw.position != Some(position) {
+                events.extend(sw.events(position));
+                sw.position = Some(position);
+            }
+            channels[sw.ch] = old[sw.ch];
+        }
+
+        // 0 AIL (ABS_X)
+        if channels[0] != old[0] {
+            events.extend(&[evdev::InputEvent::new(
+                evdev::EventType::ABSOLUTE.0,
+                AbsoluteAxisCode::ABS_X.0,
+                axes[0].scale(channels[0]),
+            )]);
+        }
+        // 1 ELE (ABS_Y)
+        if channels[1] != old[1] {
+            events.extend(&[evdev::InputEvent::new(
+                evdev::EventType::ABSOLUTE.0,
+                AbsoluteAxisCode::ABS_Y.0,
+                axes[1].scale(channels[1]),
+            )]);
+        }
+        // 2 THR (ABS_Z)
+        if channels[2] != old[2] {
+            events.extend(&[evdev::InputEvent::new(
+                evdev::EventType::ABSOLUTE.0,
+                AbsoluteAxisCode::ABS_Z.0,
+                axes[2].scale(channels[2]),
+            )]);
+        }
+        // 3 RUD (ABS_RX)
+        if channels[3] != old[3] {
+            events.extend(&[evdev::InputEvent::new(
+                evdev::EventType::ABSOLUTE.0,
+                AbsoluteAxisCode::ABS_RX.0,
+                axes[3].scale(channels[3]),
+            )]);
+        }
+
+        // 4 SD disarm/arm button(s) + ABS_THROTTLE
+        if channels[4] != old[4] {
+            let val = axes[4].scale(channels[4]);
+            events.extend(&[
+                evdev::InputEvent::new(
+                    evdev::EventType::KEY.0,
+                    KeyCode::BTN_TRIGGER.0,
+                    if channels[4] < AXIS_MID { 1 } else { 0 },
+                ),
+                evdev::InputEvent::new(
+                    evdev::EventType::KEY.0,
+                    KeyCode::BTN_THUMB.0,
+                    if channels[4] >= AXIS_MID { 1 } else { 0 },
+                ),
+                evdev::InputEvent::new(
+                    evdev::EventType::ABSOLUTE.0,
+                    AbsoluteAxisCode::ABS_THROTTLE.0,
+                    val,
+                ),
+            ]);
+        }
+
+        // 5 button SE (2POS, momentary) -> BTN_THUMB2
+        if channels[5] != old[5] {
+            events.extend(&[evdev::InputEvent::new(
+                evdev::EventType::KEY.0,
+                KeyCode::BTN_THUMB2.0,
+                if channels[5] >= AXIS_MID { 1 } else { 0 },
+            )]);
+        }
+
+        // 6 S1-pot -> ABS_RUDDER
+        if channels[6] != old[6] {
+            events.extend(&[evdev::InputEvent::new(
+                evdev::EventType::ABSOLUTE.0,
+                AbsoluteAxisCode::ABS_RUDDER.0,
+                axes[6].scale(channels[6]),
+            )]);
+        }
+
+        // 7 button SA (2POS, fixed) -> BTN_BASE6 / BTN_BASE6+1 + ABS_WHEEL
+        if channels[7] != old[7] {
+            let val = axes[7].scale(channels[7]);
+            events.extend(&[
+                evdev::InputEvent::new(
+                    evdev::EventType::KEY.0,
+                    KeyCode::BTN_BASE6.0,
+                    if channels[7] < AXIS_MID { 1 } else { 0 },
+                ),
+                evdev::InputEvent::new(
+                    evdev::EventType::KEY.0,
+                    KeyCode::new(KeyCode::BTN_BASE6.code() + 1).0,
+                    if channels[7] >= AXIS_MID { 1 } else { 0 },
+                ),
+                evdev::InputEvent::new(
+                    evdev::EventType::ABSOLUTE.0,
+                    AbsoluteAxisCode::ABS_WHEEL.0,
+                    val,
+                ),
+            ]);
+        }
+
+        // 8: RUD trim
+        if channels[8] != old[8] {
+            events.extend(&[
+                evdev::InputEvent::new(
+                    evdev::EventType::KEY.0,
+                    KeyCode::BTN_TOP.0,
+                    if channels[8] <= AXIS_3POS_LEFT { 1 } else { 0 },
+                ),
+                evdev::InputEvent::new(
+                    evdev::EventType::KEY.0,
+                    KeyCode::BTN_TOP2.0,
+                    if channels[8] >= AXIS_3POS_RIGHT { 1 } else { 0 },
+                ),
+            ]);
+        }
+        // 9: ELE trim
+        if channels[9] != old[9] {
+            events.extend(&[
+                evdev::InputEvent::new(
+                    evdev::EventType::KEY.0,
+                    KeyCode::BTN_PINKIE.0,
+                    if channels[9] <= AXIS_3POS_LEFT { 1 } else { 0 },
+                ),
+                evdev::InputEvent::new(
+                    evdev::EventType::KEY.0,
+                    KeyCode::BTN_BASE.0,
+                    if channels[9] >= AXIS_3POS_RIGHT { 1 } else { 0 },
+                ),
+            ]);
+        }
+        // 10: THR trim
+        if channels[10] != old[10] {
+            events.extend(&[
+                evdev::InputEvent::new(
+                    evdev::EventType::KEY.0,
+                    KeyCode::BTN_BASE2.0,
+                    if channels[10] <= AXIS_3POS_LEFT { 1 } else { 0 },
+                ),
+                evdev::InputEvent::new(
+                    evdev::EventType::KEY.0,
+                    KeyCode::BTN_BASE3.0,
+                    if channels[10] >= AXIS_3POS_RIGHT { 1 } else { 0 },
+                ),
+            ]);
+        }
+        // 11: AIL trim
+        if channels[11] != old[11] {
+            events.extend(&[
+                evdev::InputEvent::new(
+                    evdev::EventType::KEY.0,
+                    KeyCode::BTN_BASE4.0,
+                    if channels[11] <= AXIS_3POS_LEFT { 1 } else { 0 },
+                ),
+                evdev::InputEvent::new(
+                    evdev::EventType::KEY.0,
+                    KeyCode::BTN_BASE5.0,
+                    if channels[11] >= AXIS_3POS_RIGHT { 1 } else { 0 },
+                ),
+            ]);
+        }
+
+        self.old_channels = channels;
+
+        if !events.is_empty() {
+            counter!("joystick.uinput.update").increment(1);
+            dev.emit(&events)?;
+        }
+        Ok(())
+    }
+}
+
+impl Output for Joystick {
+    fn update(&mut self, channels: [u16; 16]) -> std::io::Result<()> {
+        Joystick::update(self, channels)
+    }
+
+    fn neutralize(&mut self) -> std::io::Result<()> {
+        let axes = [
+            (AbsoluteAxisCode::ABS_X, 0, AXIS_MID),
+            (AbsoluteAxisCode::ABS_Y, 1, AXIS_MID),
+            (AbsoluteAxisCode::ABS_Z, 2, 0),
+            (AbsoluteAxisCode::ABS_RX, 3, AXIS_MID),
+            (AbsoluteAxisCode::ABS_THROTTLE, 4, 0),
+            (AbsoluteAxisCode::ABS_RUDDER, 6, AXIS_MID),
+            (AbsoluteAxisCode::ABS_WHEEL, 7, 0),
+        ];
+        let mut events: Vec<evdev::InputEvent> = axes
+            .iter()
+            .map(|&(axis, ch, val)| {
+                evdev::InputEvent::new(
+                    evdev::EventType::ABSOLUTE.0,
+                    axis.0,
+                    self.axes[ch].scale(val),
+                )
+            })
+            .collect();
+        events.extend(
+            BUTTONS
+                .iter()
+                .map(|key| evdev::InputEvent::new(evdev::EventType::KEY.0, key.0, 0)),
+        );
+        for sw in &mut self.switches {
+            // Position 0 on an axis; no button pressed.
+            let released = match sw.target {
+                SwitchTarget::Buttons(_) => usize::MAX,
+                SwitchTarget::Axis(_) => 0,
+            };
+            events.extend(sw.events(released));
+            sw.position = None;
+        }
+        self.old_channels = [0xffff; 16];
+        self.device.emit(&events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapping::ChannelConfig;
+
+    fn switch_channel(output: SwitchOutput, positions: usize) -> ChannelConfig {
+        ChannelConfig {
+            switch: Some(SwitchConfig {
+                thresholds: (1..positions as u16).map(|i| i * 100).collect(),
+                output,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn switch_allocation() {
+        let mut mapping = MappingConfig::default();
+        *mapping.channel_mut(8) = switch_channel(SwitchOutput::Buttons, 3);
+        *mapping.channel_mut(9) = switch_channel(SwitchOutput::Axis, 5);
+        *mapping.channel_mut(10) = switch_channel(SwitchOutput::Buttons, 2);
+        let switches = allocate_switches(&mapping).unwrap();
+        assert_eq!(switches.len(), 3);
+        let SwitchTarget::Buttons(ref keys) = switches[0].target else {
+            panic!("expected buttons");
+        };
+        assert_eq!(keys[0], KeyCode::BTN_TRIGGER_HAPPY1);
+        assert_eq!(keys.len(), 3);
+        assert!(matches!(
+            switches[1].target,
+            SwitchTarget::Axis(AbsoluteAxisCode::ABS_HAT0X)
+        ));
+        let SwitchTarget::Buttons(ref keys) = switches[2].target else {
+            panic!("expected buttons");
+        };
+        assert_eq!(keys[0], KeyCode::BTN_TRIGGER_HAPPY4);
+
+        // 40 buttons in total.
+        for ch in 0..14 {
+            *mapping.channel_mut(ch) = switch_channel(SwitchOutput::Buttons, 3);
+        }
+        assert!(allocate_switches(&mapping).is_err());
+    }
+}
@@ -0,0 +1,300 @@
+//! CRSF RC channels → vJoy virtual joystick, for Windows (`vjoy` feature).
+//!
+//! The counterpart of the uinput joystick where there is no uinput: a
+//! device of the [vJoy](https://github.com/BrunnerInnovation/vJoy) driver,
+//! driven through `vJoyInterface.dll`, which is loaded when the device is
+//! opened. The device has to be enabled in *Configure vJoy* first, with the
+//! axes below and at least 13 buttons.
+//!
+//! The layout is that of the uinput joystick, so that the same binds work:
+//!
+//! | vJoy          | Channel                       |
+//! |---------------|-------------------------------|
+//! | X, Y, Z, Rx   | 0 AIL, 1 ELE, 2 THR, 3 RUD    |
+//! | Ry            | 4 SD (arm switch, as an axis) |
+//! | Rz            | 6 S1 pot                      |
+//! | Slider        | 7 SA (as an axis)             |
+//! | buttons 1–2   | 4 SD low/high                 |
+//! | button 3      | 5 SE high                     |
+//! | buttons 4–11  | trims 8–11, low/high          |
+//! | buttons 12–13 | 7 SA low/high                 |
+//!
+//! Channels configured as switches are not emitted separately.
+
+use std::io;
+
+use libloading::Library;
+use metrics::counter;
+
+use crate::mapping::MappingConfig;
+use crate::{AXIS_3POS_LEFT, AXIS_3POS_RIGHT, AXIS_MAX, AXIS_MID, Output};
+
+/// The DLL, on the search path or where the vJoy installer puts it.
+const DLL: [&str; 2] = [
+    "vJoyInterface.dll",
+    r"C:\Program Files\vJoy\x64\vJoyInterface.dll",
+];
+
+/// HID usages of the axes, in [`Report::axes`] order.
+const AXES: [u32; 7] = [0x30, 0x31, 0x32, 0x33, 0x34, 0x35, 0x36];
+const BUTTONS: u8 = 13;
+
+/// Axis range of a vJoy device.
+const VJOY_MIN: i32 = 0x1;
+const VJOY_MAX: i32 = 0x8000;
+
+/// `VjdStat` values.
+const VJD_STAT_OWN: i32 = 0;
+const VJD_STAT_FREE: i32 = 1;
+const VJD_STAT_BUSY: i32 = 2;
+
+/// Axis values and buttons of the device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Report {
+    pub axes: [i32; AXES.len()],
+    /// Bit `n` is button `n + 1`.
+    pub buttons: u16,
+}
+
+fn axis(value: u16) -> i32 {
+    let value = i32::from(value.min(AXIS_MAX));
+    VJOY_MIN + (value * (VJOY_MAX - VJOY_MIN) + i32::from(AXIS_MAX) / 2) / i32::from(AXIS_MAX)
+}
+
+/// Build the report for a normalized 16-channel frame.
+pub fn report(channels: &[u16; 16]) -> Report {
+    let axes = [0, 1, 2, 3, 4, 6, 7].map(|ch| axis(channels[ch]));
+    let pressed = [
+        channels[4] < AXIS_MID,
+        channels[4] >= AXIS_MID,
+        channels[5] >= AXIS_MID,
+        channels[8] <= AXIS_3POS_LEFT,
+        channels[8] >= AXIS_3POS_RIGHT,
+        channels[9] <= AXIS_3POS_LEFT,
+        channels[9] >= AXIS_3POS_RIGHT,
+        channels[10] <= AXIS_3POS_LEFT,
+        channels[10] >= AXIS_3POS_RIGHT,
+        channels[11] <= AXIS_3POS_LEFT,
+        channels[11] >= AXIS_3POS_RIGHT,
+        channels[7] < AXIS_MID,
+        channels[7] >= AXIS_MID,
+    ];
+    let buttons = pressed
+        .iter()
+        .enumerate()
+        .filter(|(_, on)| **on)
+        .fold(0u16, |acc, (bit, _)| acc | (1 << bit));
+    Report { axes, buttons }
+}
+
+/// The functions of `vJoyInterface.dll` that are used.
+struct Api {
+    get_status: unsafe extern "C" fn(u32) -> i32,
+    acquire: unsafe extern "C" fn(u32) -> i32,
+    relinquish: unsafe extern "C" fn(u32),
+    reset: unsafe extern "C" fn(u32) -> i32,
+    axis_exists: unsafe extern "C" fn(u32, u32) -> i32,
+    button_count: unsafe extern "C" fn(u32) -> i32,
+    set_axis: unsafe extern "C" fn(i32, u32, u32) -> i32,
+    set_button: unsafe extern "C" fn(i32, u32, u8) -> i32,
+    /// Keeps the functions loaded.
+    _library: Library,
+}
+
+fn error(msg: String) -> io::Error {
+    io::Error::other(msg)
+}
+
+impl Api {
+    fn load() -> io::Result<Self> {
+        let mut last_error = None;
+        for path in DLL {
+            // SAFETY: the DLL is vJoy's, which has no initialization
+            // routines with preconditions.
+            match unsafe { Library::new(path) } {
+                Ok(library) => return Self::with(library),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(error(format!(
+            "cannot load vJoyInterface.dll, is vJoy installed? ({})",
+            last_error.map(|e| e.to_string()).unwrap_or_default()
+        )))
+    }
+
+    fn with(library: Library) -> io::Result<Self> {
+        let enabled: unsafe extern "C" fn() -> i32 = *symbol(&library, "vJoyEnabled")?;
+        // SAFETY: the signatures are those of vJoyInterface.h.
+        unsafe {
+            if enabled() == 0 {
+                return Err(error("the vJoy driver is not enabled".into()));
+            }
+            Ok(Self {
+                get_status: *symbol(&library, "GetVJDStatus")?,
+                acquire: *symbol(&library, "AcquireVJD")?,
+                relinquish: *symbol(&library, "RelinquishVJD")?,
+                reset: *symbol(&library, "ResetVJD")?,
+                axis_exists: *symbol(&library, "GetVJDAxisExist")?,
+                button_count: *symbol(&library, "GetVJDButtonNumber")?,
+                set_axis: *symbol(&library, "SetAxis")?,
+                set_button: *symbol(&library, "SetBtn")?,
+                _library: library,
+            })
+        }
+    }
+}
+
+fn symbol<'a, T>(library: &'a Library, name: &str) -> io::Result<libloading::Symbol<'a, T>> {
+    // SAFETY: `T` is the type of the function by that name, as the
+    // callers declare it.
+    unsafe { library.get(name.as_bytes()) }
+        .map_err(|e| error(format!("vJoyInterface.dll: {}: {}", name, e)))
+}
+
+/// A vJoy device driven by 16-channel CRSF RC frames.
+pub struct VJoy {
+    api: Api,
+    device: u32,
+    mapping: MappingConfig,
+    last_report: Option<Report>,
+}
+
+impl VJoy {
+    /// Acquire vJoy device `device` (1 to 16) using the given channel
+    /// mapping.
+    pub fn with_mapping(mapping: MappingConfig, device: u32) -> io::Result<Self> {
+        let api = Api::load()?;
+        // SAFETY: calls into vJoyInterface.dll with a device number, which
+        // it checks.
+        unsafe {
+            match (api.get_status)(device) {
+                VJD_STAT_OWN | VJD_STAT_FREE => {}
+                status => {
+                    return Err(error(format!(
+                        "vJoy device {} is {}",
+                        device,
+                        if status == VJD_STAT_BUSY {
+                            "in use by another program"
+                        } else {
+                            "not configured"
+                        }
+                    )));
+                }
+            }
+            if let Some(&usage) = AXES
+                .iter()
+                .find(|&&usage| (api.axis_exists)(device, usage) == 0)
+            {
+                return Err(error(format!(
+                    "vJoy device {} lacks axis 0x{:02x}; enable X, Y, Z, Rx, Ry, Rz and Slider",
+                    device, usage
+                )));
+            }
+            if (api.button_count)(device) < i32::from(BUTTONS) {
+                return Err(error(format!(
+                    "vJoy device {} has fewer than {} buttons",
+                    device, BUTTONS
+                )));
+            }
+            if (api.acquire)(device) == 0 {
+                return Err(error(format!("cannot acquire vJoy device {}", device)));
+            }
+            (api.reset)(device);
+        }
+        Ok(Self {
+            api,
+            device,
+            mapping,
+            last_report: None,
+        })
+    }
+
+    /// Update the device from a raw 16-channel CRSF RC frame. Only the
+    /// axes and buttons that changed are set.
+    pub fn update(&mut self, channels: [u16; 16]) -> io::Result<()> {
+        let report = report(&self.mapping.apply(channels));
+        self.send(report)
+    }
+
+    fn send(&mut self, report: Report) -> io::Result<()> {
+        let last = self.last_report;
+        if last == Some(report) {
+            return Ok(());
+        }
+        let mut ok = true;
+        // SAFETY: calls into vJoyInterface.dll for the acquired device.
+        unsafe {
+            for (i, &value) in report.axes.iter().enumerate() {
+                if last.is_none_or(|last| last.axes[i] != value) {
+                    ok &= (self.api.set_axis)(value, self.device, AXES[i]) != 0;
+                }
+            }
+            for button in 0..BUTTONS {
+                let pressed = report.buttons & (1 << button) != 0;
+                if last.is_none_or(|last| (last.buttons & (1 << button) != 0) != pressed) {
+                    ok &= (self.api.set_button)(pressed as i32, self.device, button + 1) != 0;
+                }
+            }
+        }
+        if !ok {
+            self.last_report = None;
+            return Err(error(format!("cannot update vJoy device {}", self.device)));
+        }
+        self.last_report = Some(report);
+        counter!("joystick.vjoy.update").increment(1);
+        Ok(())
+    }
+}
+
+impl Output for VJoy {
+    fn update(&mut self, channels: [u16; 16]) -> io::Result<()> {
+        VJoy::update(self, channels)
+    }
+
+    fn neutralize(&mut self) -> io::Result<()> {
+        let mut channels = [AXIS_MID; 16];
+        for ch in [2, 4, 7] {
+            channels[ch] = 0;
+        }
+        let mut neutral = report(&channels);
+        neutral.buttons = 0;
+        self.send(neutral)?;
+        self.last_report = None;
+        Ok(())
+    }
+}
+
+impl Drop for VJoy {
+    fn drop(&mut self) {
+        // SAFETY: the device was acquired in `with_mapping`.
+        unsafe { (self.api.relinquish)(self.device) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout() {
+        let mut channels = [AXIS_MID; 16];
+        channels[2] = 0;
+        channels[4] = AXIS_MAX;
+        channels[5] = 0;
+        channels[8] = 0;
+        channels[11] = AXIS_MAX;
+        channels[7] = 0;
+        let r = report(&channels);
+        assert_eq!(r.axes[0], 0x4009);
+        assert_eq!(r.axes[2], VJOY_MIN);
+        assert_eq!(r.axes[4], VJOY_MAX);
+        assert_eq!(r.axes[6], VJOY_MIN);
+        // SD high, RUD trim low, AIL trim high, SA low.
+        assert_eq!(r.buttons, 0b0_1100_0000_1010);
+
+        channels[4] = 0;
+        channels[5] = AXIS_MAX;
+        channels[7] = AXIS_MAX;
+        assert_eq!(report(&channels).buttons, 0b1_0100_0000_1101);
+    }
+}
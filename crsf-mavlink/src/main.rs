@@ -0,0 +1,310 @@
+use clap::Parser;
+use log::{debug, info, warn};
+use mavlink::MavHeader;
+use mavlink::common::MavMessage;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use telemetry_lib::crsf;
+use telemetry_lib::topics;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::{Duration, MissedTickBehavior, interval};
+use zenoh::Config;
+
+mod vehicle;
+
+use vehicle::Vehicle;
+
+/// `MAV_COMP_ID_AUTOPILOT1`.
+const COMPONENT_ID: u8 = 1;
+/// Where ground stations listen by default.
+const DEFAULT_UDP: &str = "127.0.0.1:14550";
+/// Frames queued for each TCP client before it misses some.
+const CLIENT_QUEUE: usize = 64;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Send MAVLink over UDP to this address, where a ground station such
+    /// as QGroundControl or Mission Planner listens. Can be given more than
+    /// once. Without it and --tcp-bind, 127.0.0.1:14550.
+    #[arg(long)]
+    udp: Vec<SocketAddr>,
+
+    /// Serve MAVLink over TCP on this address (e.g. 0.0.0.0:5760), for
+    /// ground stations that connect to the vehicle.
+    #[arg(long)]
+    tcp_bind: Option<SocketAddr>,
+
+    /// Rate of the attitude, position, HUD and RC channel messages, Hz.
+    /// The heartbeat, system status and GPS fix are sent once a second.
+    #[arg(long, default_value_t = 10.0, value_parser = parse_rate)]
+    rate: f64,
+
+    /// Also send the RC channels from the RC topic, as RC_CHANNELS.
+    #[arg(long)]
+    rc_channels: bool,
+
+    /// MAVLink system ID. The MAVLink interface of the autopilot is system
+    /// 1 too; give another one to see both in the ground station.
+    #[arg(long, default_value_t = 1)]
+    system_id: u8,
+
+    /// Telemetry older than this is not reported, s.
+    #[arg(long, default_value_t = 2.0)]
+    stale_timeout: f64,
+
+    /// Zenoh connect endpoint (e.g. tcp/192.168.1.1:7447). Omit for peer discovery.
+    #[arg(long)]
+    zenoh_connect: Option<String>,
+
+    /// Zenoh mode (peer or client).
+    #[arg(long, default_value = "client")]
+    zenoh_mode: String,
+
+    /// Zenoh topic prefix.
+    #[arg(long, default_value = topics::DEFAULT_PREFIX)]
+    zenoh_prefix: String,
+}
+
+/// Parse a message rate, for use as a clap value parser.
+fn parse_rate(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(hz) if hz > 0.0 && hz <= 100.0 => Ok(hz),
+        _ => Err(format!(
+            "invalid rate `{}`, must be above 0 and up to 100 Hz",
+            s
+        )),
+    }
+}
+
+/// Frames messages as MAVLink 2, from one system and component.
+struct Encoder {
+    header: MavHeader,
+}
+
+impl Encoder {
+    fn new(system_id: u8) -> Self {
+        Self {
+            header: MavHeader {
+                system_id,
+                component_id: COMPONENT_ID,
+                sequence: 0,
+            },
+        }
+    }
+
+    fn encode(&mut self, message: &MavMessage) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(280);
+        // Writing to a Vec doesn't fail.
+        mavlink::write_v2_msg(&mut frame, self.header, message).ok();
+        self.header.sequence = self.header.sequence.wrapping_add(1);
+        frame
+    }
+}
+
+/// Where the MAVLink frames go.
+struct Outputs {
+    udp: Option<(UdpSocket, Vec<SocketAddr>)>,
+    tcp: broadcast::Sender<Arc<[u8]>>,
+}
+
+impl Outputs {
+    async fn send(&self, frame: Vec<u8>) {
+        if let Some((socket, addrs)) = &self.udp {
+            for addr in addrs {
+                // Refused while no ground station listens.
+                if let Err(e) = socket.send_to(&frame, addr).await {
+                    debug!("MAVLink UDP send to {} failed: {}", addr, e);
+                }
+            }
+        }
+        // Fails only without clients.
+        let _ = self.tcp.send(frame.into());
+    }
+}
+
+/// Send the frames to a TCP client, and discard what it sends, until it
+/// disconnects.
+async fn tcp_client(
+    mut stream: TcpStream,
+    addr: SocketAddr,
+    mut frames: broadcast::Receiver<Arc<[u8]>>,
+) {
+    if let Err(e) = stream.set_nodelay(true) {
+        debug!("TCP_NODELAY for {}: {}", addr, e);
+    }
+    let (mut reader, mut writer) = stream.split();
+    let mut discard = [0u8; 512];
+    loop {
+        tokio::select! {
+            frame = frames.recv() => match frame {
+                Ok(frame) => {
+                    if writer.write_all(&frame).await.is_err() {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(n)) => debug!("{} missed {} frames", addr, n),
+                Err(RecvError::Closed) => break,
+            },
+            read = reader.read(&mut discard) => {
+                if !matches!(read, Ok(n) if n > 0) {
+                    break;
+                }
+            }
+        }
+    }
+    info!("Ground station disconnected: {}", addr);
+}
+
+async fn serve_tcp(listener: TcpListener, frames: broadcast::Sender<Arc<[u8]>>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, addr)) => {
+                info!("Ground station connected: {}", addr);
+                tokio::spawn(tcp_client(stream, addr, frames.subscribe()));
+            }
+            Err(e) => warn!("MAVLink TCP accept error: {}", e),
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    env_logger::init();
+    let mut args = Args::parse();
+
+    info!("Starting crsf-mavlink");
+
+    // Zenoh session
+    let mut config = Config::default();
+    config.insert_json5("mode", &format!(r#""{}""#, args.zenoh_mode))?;
+    if let Some(ref endpoint) = args.zenoh_connect {
+        config.insert_json5("connect/endpoints", &format!(r#"["{}"]"#, endpoint))?;
+    }
+
+    let session = zenoh::open(config).await?;
+    let vehicle = Arc::new(Mutex::new(Vehicle::new(Duration::from_secs_f64(
+        args.stale_timeout,
+    ))));
+
+    let crsf_tel_topic = topics::topic(&args.zenoh_prefix, topics::CRSF_TELEMETRY);
+    info!("Subscribing to: {}", crsf_tel_topic);
+    let crsf_tel_subscriber = session.declare_subscriber(&crsf_tel_topic).await?;
+    let tx = vehicle.clone();
+    tokio::spawn(async move {
+        loop {
+            match crsf_tel_subscriber.recv_async().await {
+                Ok(sample) => {
+                    let payload = sample.payload().to_bytes();
+                    if let Some(packet) = crsf::parse_packet_check(&payload)
+                        && let Ok(mut lock) = tx.lock()
+                    {
+                        lock.telemetry(Instant::now(), packet);
+                    }
+                }
+                Err(e) => {
+                    warn!("CRSF telemetry subscriber error: {}", e);
+                    break;
+                }
+            }
+        }
+    });
+
+    if args.rc_channels {
+        let rc_topic = topics::topic(&args.zenoh_prefix, topics::CRSF_RC);
+        info!("Subscribing to: {}", rc_topic);
+        let rc_subscriber = session.declare_subscriber(&rc_topic).await?;
+        let tx = vehicle.clone();
+        tokio::spawn(async move {
+            loop {
+                match rc_subscriber.recv_async().await {
+                    Ok(sample) => {
+                        let payload = sample.payload().to_bytes();
+                        if let Ok(mut lock) = tx.lock() {
+                            lock.rc(Instant::now(), &payload);
+                        }
+                    }
+                    Err(e) => {
+                        warn!("RC subscriber error: {}", e);
+                        break;
+                    }
+                }
+            }
+        });
+    }
+
+    if args.udp.is_empty() && args.tcp_bind.is_none() {
+        args.udp.push(DEFAULT_UDP.parse()?);
+    }
+    let udp = if args.udp.is_empty() {
+        None
+    } else {
+        for addr in &args.udp {
+            info!("Sending MAVLink to udp://{}", addr);
+        }
+        Some((UdpSocket::bind("0.0.0.0:0").await?, args.udp.clone()))
+    };
+    let (tcp, _) = broadcast::channel(CLIENT_QUEUE);
+    if let Some(addr) = args.tcp_bind {
+        let listener = TcpListener::bind(addr).await?;
+        info!("Serving MAVLink on tcp://{}", addr);
+        tokio::spawn(serve_tcp(listener, tcp.clone()));
+    }
+    let outputs = Outputs { udp, tcp };
+
+    let mut encoder = Encoder::new(args.system_id);
+    let boot = Instant::now();
+    let mut fast = interval(Duration::from_secs_f64(1.0 / args.rate));
+    fast.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut slow = interval(Duration::from_secs(1));
+    slow.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    info!("Sending as system {} at {} Hz", args.system_id, args.rate);
+    loop {
+        let messages = tokio::select! {
+            _ = fast.tick() => {
+                let boot_ms = boot.elapsed().as_millis() as u32;
+                vehicle.lock().map(|v| v.fast(Instant::now(), boot_ms))
+            }
+            _ = slow.tick() => vehicle.lock().map(|v| v.slow(Instant::now())),
+            _ = tokio::signal::ctrl_c() => break,
+        };
+        for message in messages.unwrap_or_default() {
+            outputs.send(encoder.encode(&message)).await;
+        }
+    }
+    info!("Shutdown signal received, exiting.");
+
+    session.close().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mavlink::peek_reader::PeekReader;
+    use std::io::Cursor;
+
+    #[test]
+    fn encoding() {
+        assert_eq!(parse_rate("10"), Ok(10.0));
+        assert!(parse_rate("0").is_err());
+        assert!(parse_rate("fast").is_err());
+
+        let vehicle = Vehicle::new(Duration::from_secs(1));
+        let mut encoder = Encoder::new(42);
+        let mut stream = Vec::new();
+        for message in vehicle.slow(Instant::now()) {
+            stream.extend(encoder.encode(&message));
+        }
+        let mut reader = PeekReader::new(Cursor::new(stream));
+        let (header, message) = mavlink::read_v2_msg::<MavMessage, _>(&mut reader).unwrap();
+        assert_eq!((header.system_id, header.sequence), (42, 0));
+        assert!(matches!(message, MavMessage::HEARTBEAT(_)));
+        let (header, message) = mavlink::read_v2_msg::<MavMessage, _>(&mut reader).unwrap();
+        assert_eq!(header.sequence, 1);
+        assert!(matches!(message, MavMessage::SYS_STATUS(_)));
+    }
+}
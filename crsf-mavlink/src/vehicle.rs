@@ -0,0 +1,376 @@
+//! The state of the drone from its CRSF telemetry, as MAVLink messages.
+//!
+//! Every value is kept with the time it was received, and left out of the
+//! messages once it is older than the stale timeout. MAVLink units: angles
+//! in radians or centidegrees, altitudes in mm, speeds in cm/s or m/s.
+
+use std::time::{Duration, Instant};
+
+use mavlink::common::*;
+use telemetry_lib::crsf::{self, Airspeed, Attitude, Battery, CrsfPacket, Gps, LinkStatistics};
+
+const NUM_CHANNELS: usize = 16;
+/// The center of a CRSF channel, 1500 µs.
+const CHANNEL_MID: u16 = 992;
+
+#[derive(Debug)]
+pub struct Vehicle {
+    stale_timeout: Duration,
+    attitude: Option<(Instant, Attitude)>,
+    gps: Option<(Instant, Gps)>,
+    /// Altitude of the first GPS fix, m, that the relative altitude is
+    /// counted from.
+    home_alt: Option<f64>,
+    /// Barometric altitude, m.
+    baro_alt: Option<(Instant, f64)>,
+    /// Vertical speed, m/s, up.
+    climb: Option<(Instant, f64)>,
+    airspeed: Option<(Instant, Airspeed)>,
+    battery: Option<(Instant, Battery)>,
+    link: Option<(Instant, LinkStatistics)>,
+    rc: Option<(Instant, [u16; NUM_CHANNELS])>,
+}
+
+/// `value` if it was received within `timeout` of `now`.
+fn fresh<T>(value: &Option<(Instant, T)>, now: Instant, timeout: Duration) -> Option<&T> {
+    value
+        .as_ref()
+        .filter(|(at, _)| now.saturating_duration_since(*at) < timeout)
+        .map(|(_, value)| value)
+}
+
+impl Vehicle {
+    pub fn new(stale_timeout: Duration) -> Self {
+        Self {
+            stale_timeout,
+            attitude: None,
+            gps: None,
+            home_alt: None,
+            baro_alt: None,
+            climb: None,
+            airspeed: None,
+            battery: None,
+            link: None,
+            rc: None,
+        }
+    }
+
+    /// A telemetry packet was received at `now`.
+    pub fn telemetry(&mut self, now: Instant, packet: CrsfPacket) {
+        match packet {
+            CrsfPacket::Attitude(attitude) => self.attitude = Some((now, attitude)),
+            CrsfPacket::Gps(gps) => {
+                self.home_alt.get_or_insert(gps.alt_m());
+                self.gps = Some((now, gps));
+            }
+            CrsfPacket::BaroAlt(baro) => {
+                self.baro_alt = Some((now, baro.alt_m()));
+                self.climb = Some((now, baro.vertical_speed_ms()));
+            }
+            CrsfPacket::Vario(vario) => self.climb = Some((now, vario.vertical_speed_ms())),
+            CrsfPacket::Airspeed(airspeed) => self.airspeed = Some((now, airspeed)),
+            CrsfPacket::Battery(battery) => self.battery = Some((now, battery)),
+            CrsfPacket::LinkStatistics(link) => self.link = Some((now, link)),
+            _ => {}
+        }
+    }
+
+    /// An RC frame was received at `now`. Subset frames update the last
+    /// channels in part.
+    pub fn rc(&mut self, now: Instant, frame: &[u8]) {
+        let mut channels = self.rc.map_or([CHANNEL_MID; NUM_CHANNELS], |(_, c)| c);
+        match crsf::parse_packet_check(frame) {
+            Some(CrsfPacket::RcChannelsPacked(rc)) => channels = rc.channels,
+            Some(CrsfPacket::RcChannelsSubset(subset)) => subset.merge_into(&mut channels),
+            _ => return,
+        }
+        self.rc = Some((now, channels));
+    }
+
+    fn fresh<'a, T>(&self, value: &'a Option<(Instant, T)>, now: Instant) -> Option<&'a T> {
+        fresh(value, now, self.stale_timeout)
+    }
+
+    /// Ground speed, m/s, and course, degrees.
+    fn velocity(&self, now: Instant) -> Option<(f64, f64)> {
+        let gps = self.fresh(&self.gps, now)?;
+        Some((gps.speed_kmh() / 3.6, gps.heading_deg()))
+    }
+
+    /// The messages to send at the fast rate: the attitude, position,
+    /// HUD and RC channels, as far as they are known.
+    pub fn fast(&self, now: Instant, boot_ms: u32) -> Vec<MavMessage> {
+        [
+            self.attitude(now, boot_ms),
+            self.global_position(now, boot_ms),
+            self.vfr_hud(now),
+            self.rc_channels(now, boot_ms),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    /// The messages to send once a second: the heartbeat, the system
+    /// status and the GPS fix.
+    pub fn slow(&self, now: Instant) -> Vec<MavMessage> {
+        [
+            Some(self.heartbeat(now)),
+            Some(self.sys_status(now)),
+            self.gps_raw(now),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    fn heartbeat(&self, now: Instant) -> MavMessage {
+        let active =
+            self.fresh(&self.attitude, now).is_some() || self.fresh(&self.gps, now).is_some();
+        MavMessage::HEARTBEAT(HEARTBEAT_DATA {
+            custom_mode: 0,
+            mavtype: MavType::MAV_TYPE_QUADROTOR,
+            autopilot: MavAutopilot::MAV_AUTOPILOT_GENERIC,
+            base_mode: MavModeFlag::MAV_MODE_FLAG_MANUAL_INPUT_ENABLED,
+            system_status: if active {
+                MavState::MAV_STATE_ACTIVE
+            } else {
+                MavState::MAV_STATE_STANDBY
+            },
+            mavlink_version: 0x03,
+        })
+    }
+
+    fn attitude(&self, now: Instant, boot_ms: u32) -> Option<MavMessage> {
+        let attitude = self.fresh(&self.attitude, now)?;
+        Some(MavMessage::ATTITUDE(ATTITUDE_DATA {
+            time_boot_ms: boot_ms,
+            roll: attitude.roll as f32 / 1e4,
+            pitch: attitude.pitch as f32 / 1e4,
+            yaw: attitude.yaw as f32 / 1e4,
+            rollspeed: 0.0,
+            pitchspeed: 0.0,
+            yawspeed: 0.0,
+        }))
+    }
+
+    fn global_position(&self, now: Instant, boot_ms: u32) -> Option<MavMessage> {
+        let gps = self.fresh(&self.gps, now)?;
+        let (speed, course) = self.velocity(now)?;
+        let climb = self.fresh(&self.climb, now).copied().unwrap_or(0.0);
+        let cm = |v: f64| (v * 100.0) as i16;
+        Some(MavMessage::GLOBAL_POSITION_INT(GLOBAL_POSITION_INT_DATA {
+            time_boot_ms: boot_ms,
+            lat: gps.lat,
+            lon: gps.lon,
+            alt: (gps.alt_m() * 1000.0) as i32,
+            relative_alt: ((gps.alt_m() - self.home_alt.unwrap_or(0.0)) * 1000.0) as i32,
+            vx: cm(speed * course.to_radians().cos()),
+            vy: cm(speed * course.to_radians().sin()),
+            vz: cm(-climb),
+            hdg: gps.heading % 36000,
+        }))
+    }
+
+    fn gps_raw(&self, now: Instant) -> Option<MavMessage> {
+        let gps = self.fresh(&self.gps, now)?;
+        Some(MavMessage::GPS_RAW_INT(GPS_RAW_INT_DATA {
+            time_usec: 0,
+            lat: gps.lat,
+            lon: gps.lon,
+            alt: (gps.alt_m() * 1000.0) as i32,
+            eph: u16::MAX,
+            epv: u16::MAX,
+            vel: (gps.speed_kmh() / 3.6 * 100.0) as u16,
+            cog: gps.heading % 36000,
+            fix_type: if gps.sats >= 4 {
+                GpsFixType::GPS_FIX_TYPE_3D_FIX
+            } else {
+                GpsFixType::GPS_FIX_TYPE_NO_FIX
+            },
+            satellites_visible: gps.sats,
+        }))
+    }
+
+    fn vfr_hud(&self, now: Instant) -> Option<MavMessage> {
+        let attitude = self.fresh(&self.attitude, now);
+        let gps = self.fresh(&self.gps, now);
+        let alt = match (gps, self.fresh(&self.baro_alt, now)) {
+            (Some(gps), _) => gps.alt_m(),
+            (None, Some(&baro)) => baro,
+            (None, None) if attitude.is_some() => 0.0,
+            (None, None) => return None,
+        };
+        let (groundspeed, course) = self.velocity(now).unwrap_or((0.0, 0.0));
+        let heading = match attitude {
+            Some(attitude) => (attitude.yaw as f64 / 1e4).to_degrees(),
+            None => course,
+        };
+        let airspeed = self
+            .fresh(&self.airspeed, now)
+            .map_or(groundspeed, |a| a.speed_kmh() / 3.6);
+        // Channel 3 is the throttle.
+        let throttle = self.fresh(&self.rc, now).map_or(0, |rc| {
+            ((crsf::ticks_to_us(rc[2]) as i32 - 1000) / 10).clamp(0, 100) as u16
+        });
+        Some(MavMessage::VFR_HUD(VFR_HUD_DATA {
+            airspeed: airspeed as f32,
+            groundspeed: groundspeed as f32,
+            alt: alt as f32,
+            climb: self.fresh(&self.climb, now).copied().unwrap_or(0.0) as f32,
+            heading: heading.rem_euclid(360.0) as i16,
+            throttle,
+        }))
+    }
+
+    fn sys_status(&self, now: Instant) -> MavMessage {
+        let mut sensors = MavSysStatusSensor::empty();
+        if self.fresh(&self.attitude, now).is_some() {
+            sensors |= MavSysStatusSensor::MAV_SYS_STATUS_SENSOR_3D_GYRO
+                | MavSysStatusSensor::MAV_SYS_STATUS_SENSOR_3D_ACCEL
+                | MavSysStatusSensor::MAV_SYS_STATUS_AHRS;
+        }
+        if self.fresh(&self.gps, now).is_some() {
+            sensors |= MavSysStatusSensor::MAV_SYS_STATUS_SENSOR_GPS;
+        }
+        let battery = self.fresh(&self.battery, now);
+        MavMessage::SYS_STATUS(SYS_STATUS_DATA {
+            onboard_control_sensors_present: sensors,
+            onboard_control_sensors_enabled: sensors,
+            onboard_control_sensors_health: sensors,
+            load: 0,
+            // dV to mV, dA to cA; all ones is unknown.
+            voltage_battery: battery.map_or(u16::MAX, |b| b.voltage.saturating_mul(100)),
+            current_battery: battery.map_or(-1, |b| {
+                i16::try_from(b.current as u32 * 10).unwrap_or(i16::MAX)
+            }),
+            battery_remaining: battery.map_or(-1, |b| b.remaining.min(100) as i8),
+            // The uplink loss, in centipercent.
+            drop_rate_comm: self
+                .fresh(&self.link, now)
+                .map_or(0, |l| 100u16.saturating_sub(l.lq as u16) * 100),
+            errors_comm: 0,
+            errors_count1: 0,
+            errors_count2: 0,
+            errors_count3: 0,
+            errors_count4: 0,
+        })
+    }
+
+    fn rc_channels(&self, now: Instant, boot_ms: u32) -> Option<MavMessage> {
+        let rc = self.fresh(&self.rc, now)?;
+        let us = rc.map(crsf::ticks_to_us);
+        Some(MavMessage::RC_CHANNELS(RC_CHANNELS_DATA {
+            time_boot_ms: boot_ms,
+            chan1_raw: us[0],
+            chan2_raw: us[1],
+            chan3_raw: us[2],
+            chan4_raw: us[3],
+            chan5_raw: us[4],
+            chan6_raw: us[5],
+            chan7_raw: us[6],
+            chan8_raw: us[7],
+            chan9_raw: us[8],
+            chan10_raw: us[9],
+            chan11_raw: us[10],
+            chan12_raw: us[11],
+            chan13_raw: us[12],
+            chan14_raw: us[13],
+            chan15_raw: us[14],
+            chan16_raw: us[15],
+            chan17_raw: u16::MAX,
+            chan18_raw: u16::MAX,
+            chancount: NUM_CHANNELS as u8,
+            // Scaled to 0-254, 255 is unknown.
+            rssi: self
+                .fresh(&self.link, now)
+                .map_or(255, |l| (l.lq.min(100) as u16 * 254 / 100) as u8),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use telemetry_lib::crsf::{RcChannelsPacked, Vario, device_address};
+
+    #[test]
+    fn messages() {
+        let t0 = Instant::now();
+        let mut vehicle = Vehicle::new(Duration::from_secs(2));
+        let Some(MavMessage::HEARTBEAT(heartbeat)) = vehicle.slow(t0).first().cloned() else {
+            panic!("no heartbeat");
+        };
+        assert_eq!(heartbeat.system_status, MavState::MAV_STATE_STANDBY);
+        assert!(vehicle.fast(t0, 0).is_empty());
+
+        // Heading east at 36 km/h, climbing at 2 m/s.
+        let gps = Gps::from_values(52.0, 4.5, 10.0, 36.0, 90.0, 12).unwrap();
+        vehicle.telemetry(t0, CrsfPacket::Gps(gps));
+        let gps = Gps::from_values(52.0, 4.5, 15.0, 36.0, 90.0, 12).unwrap();
+        vehicle.telemetry(t0, CrsfPacket::Gps(gps));
+        vehicle.telemetry(t0, CrsfPacket::Vario(Vario::from_ms(2.0).unwrap()));
+        vehicle.telemetry(
+            t0,
+            CrsfPacket::Attitude(Attitude::from_radians(0.1, -0.2, 1.5).unwrap()),
+        );
+        vehicle.telemetry(
+            t0,
+            CrsfPacket::Battery(Battery {
+                voltage: 168,
+                current: 123,
+                capacity: 0,
+                remaining: 80,
+            }),
+        );
+        let mut channels = [CHANNEL_MID; NUM_CHANNELS];
+        channels[2] = 1811;
+        let frame = crsf::build_packet(
+            device_address::FLIGHT_CONTROLLER,
+            &CrsfPacket::RcChannelsPacked(RcChannelsPacked { channels }),
+        )
+        .unwrap();
+        vehicle.rc(t0, &frame);
+
+        let fast = vehicle.fast(t0, 1000);
+        assert_eq!(fast.len(), 4);
+        let MavMessage::ATTITUDE(ref attitude) = fast[0] else {
+            panic!("no attitude");
+        };
+        assert!((attitude.roll + 0.2).abs() < 1e-4);
+        assert!((attitude.yaw - 1.5).abs() < 1e-4);
+        let MavMessage::GLOBAL_POSITION_INT(ref position) = fast[1] else {
+            panic!("no position");
+        };
+        assert_eq!((position.lat, position.lon), (520_000_000, 45_000_000));
+        assert_eq!((position.alt, position.relative_alt), (15_000, 5_000));
+        assert!(position.vx.abs() <= 1);
+        assert_eq!(position.vy, 1000);
+        assert!((position.vz + 200).abs() <= 2);
+        assert_eq!(position.hdg, 9000);
+        let MavMessage::VFR_HUD(ref hud) = fast[2] else {
+            panic!("no HUD");
+        };
+        assert_eq!(hud.groundspeed, 10.0);
+        assert_eq!(hud.heading, 85);
+        assert_eq!(hud.throttle, 100);
+        let MavMessage::RC_CHANNELS(ref rc) = fast[3] else {
+            panic!("no RC channels");
+        };
+        assert_eq!((rc.chan1_raw, rc.chan3_raw, rc.rssi), (1500, 2011, 255));
+
+        let slow = vehicle.slow(t0);
+        let MavMessage::SYS_STATUS(ref status) = slow[1] else {
+            panic!("no status");
+        };
+        assert_eq!(status.voltage_battery, 16800);
+        assert_eq!(status.current_battery, 1230);
+        assert_eq!(status.battery_remaining, 80);
+        assert!(matches!(slow[2], MavMessage::GPS_RAW_INT(_)));
+
+        // Everything goes stale.
+        let later = t0 + Duration::from_secs(3);
+        assert!(vehicle.fast(later, 4000).is_empty());
+        assert_eq!(vehicle.slow(later).len(), 2);
+    }
+}
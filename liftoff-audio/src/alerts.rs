@@ -0,0 +1,137 @@
+//! The alerts: low battery, the laps and gates of liftoff-race, and the
+//! events from liftoff-input.
+
+use telemetry_lib::events;
+use telemetry_lib::race::Event;
+
+use crate::synth::Tone;
+
+/// When the battery is low.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LowBattery {
+    /// Charge left, from 0 to 1.
+    pub charge: f64,
+    /// Pack voltage, V, if given.
+    pub voltage: Option<f64>,
+    /// Time between the alerts while it is low, s.
+    pub repeat: f64,
+}
+
+impl LowBattery {
+    pub fn is_low(&self, charge: f64, voltage: f64) -> bool {
+        charge <= self.charge || self.voltage.is_some_and(|low| voltage <= low)
+    }
+}
+
+/// Tells when to sound the low battery alert.
+pub struct BatteryAlert {
+    settings: LowBattery,
+    /// Time of the last alert, s.
+    last: Option<f64>,
+}
+
+impl BatteryAlert {
+    pub fn new(settings: LowBattery) -> Self {
+        Self {
+            settings,
+            last: None,
+        }
+    }
+
+    /// The alert, if due at `time` (s) with `charge` and `voltage`.
+    pub fn update(&mut self, time: f64, charge: f64, voltage: f64) -> Option<Vec<Tone>> {
+        if !self.settings.is_low(charge, voltage) {
+            self.last = None;
+            return None;
+        }
+        if self
+            .last
+            .is_some_and(|last| (0.0..self.settings.repeat).contains(&(time - last)))
+        {
+            return None;
+        }
+        self.last = Some(time);
+        Some(low_battery())
+    }
+}
+
+/// Three short low beeps.
+pub fn low_battery() -> Vec<Tone> {
+    (0..3)
+        .flat_map(|_| [Tone::new(880.0, 0.12), Tone::pause(0.08)])
+        .collect()
+}
+
+/// The beeps for a race event.
+pub fn race(event: &Event) -> Vec<Tone> {
+    match event {
+        // Two high beeps, rising if the best lap.
+        Event::Lap { best: true, .. } => vec![
+            Tone::new(1500.0, 0.1),
+            Tone::pause(0.05),
+            Tone::new(2000.0, 0.25),
+        ],
+        Event::Lap { .. } => vec![
+            Tone::new(1500.0, 0.1),
+            Tone::pause(0.05),
+            Tone::new(1500.0, 0.1),
+        ],
+        Event::Split { .. } => vec![Tone::new(1200.0, 0.06)],
+        Event::Start { missed: 0 } => vec![Tone::new(1000.0, 0.15)],
+        // A missed gate, low.
+        Event::Start { .. } => vec![Tone::new(400.0, 0.3)],
+    }
+}
+
+/// The beeps for an event, by the name of its rule.
+pub fn event(event: &events::Event) -> Vec<Tone> {
+    match event.name.as_str() {
+        // Falling, low.
+        "crash" => vec![Tone::new(600.0, 0.15), Tone::new(300.0, 0.4)],
+        // Rising, quick.
+        "punch" => vec![Tone::new(800.0, 0.05), Tone::new(1600.0, 0.08)],
+        "sag" => vec![
+            Tone::new(500.0, 0.08),
+            Tone::pause(0.05),
+            Tone::new(500.0, 0.08),
+        ],
+        _ => vec![Tone::new(700.0, 0.1)],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn battery() {
+        let mut alert = BatteryAlert::new(LowBattery {
+            charge: 0.2,
+            voltage: Some(14.0),
+            repeat: 10.0,
+        });
+        assert_eq!(alert.update(0.0, 0.5, 15.0), None);
+        assert_eq!(alert.update(1.0, 0.2, 15.0), Some(low_battery()));
+        assert_eq!(alert.update(5.0, 0.1, 15.0), None);
+        assert_eq!(alert.update(11.0, 0.1, 15.0), Some(low_battery()));
+        // Back up, as after a battery swap, and down again.
+        assert_eq!(alert.update(12.0, 1.0, 16.0), None);
+        assert!(alert.update(13.0, 1.0, 13.9).is_some());
+        // Sim time going back.
+        assert!(alert.update(2.0, 0.1, 13.9).is_some());
+        assert_eq!(race(&Event::Start { missed: 0 }).len(), 1);
+        let crash = events::Event {
+            name: "crash".into(),
+            time: 1.0,
+            metric: "deceleration".into(),
+            value: 80.0,
+        };
+        assert_ne!(
+            event(&crash),
+            event(&events::Event {
+                name: "x".into(),
+                ..crash.clone()
+            })
+        );
+    }
+}
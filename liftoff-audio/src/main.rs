@@ -0,0 +1,273 @@
+use clap::{Parser, ValueEnum};
+use log::{debug, info, warn};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Instant;
+use telemetry_lib::events;
+use telemetry_lib::race::Event;
+use telemetry_lib::telemetry;
+use telemetry_lib::topics;
+use tokio::time::{Duration, timeout};
+use zenoh::Config;
+
+mod alerts;
+mod synth;
+
+use alerts::{BatteryAlert, LowBattery};
+use synth::{SAMPLE_RATE, Synth, Vario};
+
+/// Samples written to the player at a time.
+const CHUNK: usize = SAMPLE_RATE as usize / 50;
+/// The vario goes quiet when the telemetry stops for this long.
+const TELEMETRY_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Play through this sound system.
+    #[arg(long, value_enum, default_value_t = Backend::Alsa)]
+    backend: Backend,
+
+    /// Volume, from 0 to 1.
+    #[arg(long, default_value_t = 0.5, value_parser = parse_fraction)]
+    volume: f64,
+
+    /// No vario.
+    #[arg(long, default_value_t = false)]
+    no_vario: bool,
+
+    /// Climb from which the vario beeps, m/s.
+    #[arg(long, default_value_t = 0.5)]
+    vario_climb: f64,
+
+    /// Sink from which the vario sounds, m/s.
+    #[arg(long, default_value_t = 2.0)]
+    vario_sink: f64,
+
+    /// Vertical speed of the vario's highest and lowest tones, m/s.
+    #[arg(long, default_value_t = 10.0)]
+    vario_max: f64,
+
+    /// Alert when the charge left is down to this, from 0 to 1.
+    #[arg(long, default_value_t = 0.2, value_parser = parse_fraction)]
+    low_battery: f64,
+
+    /// Also alert when the pack voltage is down to this, V.
+    #[arg(long)]
+    low_voltage: Option<f64>,
+
+    /// Time between the low battery alerts while it is low, s.
+    #[arg(long, default_value_t = 10.0)]
+    battery_repeat: f64,
+
+    /// No alerts for the events from liftoff-input (crash, punch, sag).
+    #[arg(long, default_value_t = false)]
+    no_events: bool,
+
+    /// Zenoh connect endpoint (e.g. tcp/192.168.1.1:7447). Omit for peer discovery.
+    #[arg(long)]
+    zenoh_connect: Option<String>,
+
+    /// Zenoh mode (peer or client).
+    #[arg(long, default_value = "client")]
+    zenoh_mode: String,
+
+    /// Zenoh topic prefix.
+    #[arg(long, default_value = topics::DEFAULT_PREFIX)]
+    zenoh_prefix: String,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Backend {
+    /// ALSA, through `aplay`.
+    Alsa,
+    /// PulseAudio or PipeWire, through `pacat`.
+    Pulse,
+}
+
+impl Backend {
+    /// The player, taking 16-bit mono samples on its standard input.
+    fn command(self) -> Command {
+        let mut command;
+        match self {
+            Backend::Alsa => {
+                command = Command::new("aplay");
+                command.args(["-q", "-t", "raw", "-f", "S16_LE", "-c", "1", "-B", "50000"]);
+                command.arg(format!("-r{}", SAMPLE_RATE));
+            }
+            Backend::Pulse => {
+                command = Command::new("pacat");
+                command.args([
+                    "--raw",
+                    "--format=s16le",
+                    "--channels=1",
+                    "--latency-msec=50",
+                ]);
+                command.arg(format!("--rate={}", SAMPLE_RATE));
+            }
+        }
+        command
+    }
+}
+
+/// Parse a value from 0 to 1, for use as a clap value parser.
+fn parse_fraction(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(v) if (0.0..=1.0).contains(&v) => Ok(v),
+        _ => Err(format!("invalid value `{}`, must be from 0 to 1", s)),
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    env_logger::init();
+    let args = Args::parse();
+
+    info!("Starting liftoff-audio");
+
+    let vario = (!args.no_vario).then_some(Vario {
+        climb: args.vario_climb,
+        sink: args.vario_sink,
+        max: args.vario_max,
+    });
+    let synth = Arc::new(Mutex::new(Synth::new(vario, args.volume)));
+    let mut battery = BatteryAlert::new(LowBattery {
+        charge: args.low_battery,
+        voltage: args.low_voltage,
+        repeat: args.battery_repeat,
+    });
+
+    let mut command = args.backend.command();
+    let mut child = command
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("{}: {}", command.get_program().to_string_lossy(), e))?;
+    let mut stdin = child.stdin.take().expect("piped stdin");
+    info!(
+        "Playing through {}",
+        command.get_program().to_string_lossy()
+    );
+    // Writing blocks while the player's buffer is full, which keeps the
+    // synth in step with it.
+    let mut player = tokio::task::spawn_blocking({
+        let synth = synth.clone();
+        move || -> std::io::Result<()> {
+            let mut samples = [0i16; CHUNK];
+            loop {
+                synth
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .fill(&mut samples);
+                let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+                stdin.write_all(&bytes)?;
+            }
+        }
+    });
+
+    // Zenoh session
+    let mut config = Config::default();
+    config.insert_json5("mode", &format!(r#""{}""#, args.zenoh_mode))?;
+    if let Some(ref endpoint) = args.zenoh_connect {
+        config.insert_json5("connect/endpoints", &format!(r#"["{}"]"#, endpoint))?;
+    }
+
+    let session = zenoh::open(config).await?;
+
+    let tel_topic = topics::topic(&args.zenoh_prefix, topics::TELEMETRY);
+    let race_topic = topics::topic(&args.zenoh_prefix, topics::RACE);
+    let events_topic = topics::topic(&args.zenoh_prefix, topics::EVENTS);
+    info!("Subscribing to: {}", tel_topic);
+    info!("Subscribing to: {}", race_topic);
+    info!("Subscribing to: {}", events_topic);
+    let tel_subscriber = session.declare_subscriber(&tel_topic).await?;
+    let race_subscriber = session.declare_subscriber(&race_topic).await?;
+    let events_subscriber = session.declare_subscriber(&events_topic).await?;
+
+    // Telemetry format config
+    // We assume default configuration for now
+    let format = telemetry::default_stream_format();
+    let epoch = Instant::now();
+    let result: Result<(), Box<dyn std::error::Error + Send + Sync>> = loop {
+        tokio::select! {
+            sample = timeout(TELEMETRY_TIMEOUT, tel_subscriber.recv_async()) => match sample {
+                Ok(Ok(sample)) => {
+                    let payload = sample.payload().to_bytes();
+                    let packet = match telemetry::parse_packet(&payload, &format) {
+                        Ok(packet) => packet,
+                        Err(e) => {
+                            debug!("Sim telemetry: {}", e);
+                            continue;
+                        }
+                    };
+                    let alert = packet.battery.and_then(|[charge, voltage]| {
+                        let time = epoch.elapsed().as_secs_f64();
+                        battery.update(time, f64::from(charge), f64::from(voltage))
+                    });
+                    let mut synth = synth.lock().unwrap_or_else(PoisonError::into_inner);
+                    synth.set_vertical_speed(packet.velocity.map(|v| f64::from(v[1])));
+                    if let Some(alert) = alert {
+                        info!("Low battery");
+                        synth.play(alert);
+                    }
+                }
+                Ok(Err(e)) => break Err(format!("sim telemetry subscriber error: {}", e).into()),
+                Err(_) => synth
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .set_vertical_speed(None),
+            },
+            sample = race_subscriber.recv_async() => match sample {
+                Ok(sample) => match serde_json::from_slice::<Event>(&sample.payload().to_bytes()) {
+                    Ok(event) => synth
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .play(alerts::race(&event)),
+                    Err(e) => warn!("Race event: {}", e),
+                },
+                Err(e) => break Err(format!("race subscriber error: {}", e).into()),
+            },
+            sample = events_subscriber.recv_async() => match sample {
+                Ok(sample) => {
+                    match serde_json::from_slice::<events::Event>(&sample.payload().to_bytes()) {
+                        Ok(event) if !args.no_events => synth
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .play(alerts::event(&event)),
+                        Ok(_) => {}
+                        Err(e) => warn!("Event: {}", e),
+                    }
+                }
+                Err(e) => break Err(format!("events subscriber error: {}", e).into()),
+            },
+            result = &mut player => {
+                break Err(match result {
+                    Ok(Err(e)) => format!("player stopped: {}", e),
+                    Ok(Ok(())) => "player stopped".to_string(),
+                    Err(e) => format!("player: {}", e),
+                }.into());
+            }
+            _ = tokio::signal::ctrl_c() => {
+                info!("Shutdown signal received, exiting.");
+                break Ok(());
+            }
+        }
+    };
+    child.kill().ok();
+
+    session.close().await?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fraction() {
+        assert_eq!(parse_fraction("0.25"), Ok(0.25));
+        assert!(parse_fraction("1.5").is_err());
+        assert!(parse_fraction("-0.1").is_err());
+        assert!(parse_fraction("half").is_err());
+    }
+}
@@ -0,0 +1,194 @@
+//! The sound: the vario, and the beeps of the alerts over it.
+//!
+//! Like the vario of a radio, climbing beeps, faster and higher the faster
+//! the climb, and sinking is a steady tone, lower the faster the sink. In
+//! between, it is quiet. An alert silences the vario while it plays.
+
+use std::collections::VecDeque;
+use std::f64::consts::TAU;
+
+/// Samples per second.
+pub const SAMPLE_RATE: u32 = 22050;
+/// Fade in and out of a tone, s, so it doesn't click.
+const FADE: f64 = 0.005;
+
+/// A tone of `freq` Hz, or a pause if 0, for `duration` s.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tone {
+    pub freq: f64,
+    pub duration: f64,
+}
+
+impl Tone {
+    pub fn new(freq: f64, duration: f64) -> Self {
+        Self { freq, duration }
+    }
+
+    pub fn pause(duration: f64) -> Self {
+        Self {
+            freq: 0.0,
+            duration,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vario {
+    /// Climb from which it beeps, m/s.
+    pub climb: f64,
+    /// Sink from which it sounds, m/s.
+    pub sink: f64,
+    /// Vertical speed of the highest and lowest tones, m/s.
+    pub max: f64,
+}
+
+impl Vario {
+    /// The tone at vertical speed `vs` (m/s), and for a climb, the time
+    /// between beeps, s.
+    pub fn tone(&self, vs: f64) -> Option<(f64, Option<f64>)> {
+        let x = (vs.abs() / self.max).min(1.0);
+        if vs >= self.climb {
+            Some((700.0 + 1000.0 * x, Some(0.6 - 0.45 * x)))
+        } else if vs <= -self.sink {
+            Some((450.0 - 250.0 * x, None))
+        } else {
+            None
+        }
+    }
+}
+
+pub struct Synth {
+    vario: Option<Vario>,
+    /// Peak amplitude.
+    amplitude: f64,
+    /// Vertical speed, m/s.
+    vertical_speed: Option<f64>,
+    queue: VecDeque<Tone>,
+    /// Of the tone at the front of the queue, s.
+    elapsed: f64,
+    /// Of the vario beeps, s.
+    vario_time: f64,
+    /// Of the sine, rad.
+    phase: f64,
+}
+
+impl Synth {
+    /// A synth at `volume`, from 0 to 1, with `vario` if any.
+    pub fn new(vario: Option<Vario>, volume: f64) -> Self {
+        Self {
+            vario,
+            amplitude: volume.clamp(0.0, 1.0) * f64::from(i16::MAX),
+            vertical_speed: None,
+            queue: VecDeque::new(),
+            elapsed: 0.0,
+            vario_time: 0.0,
+            phase: 0.0,
+        }
+    }
+
+    pub fn set_vertical_speed(&mut self, vs: Option<f64>) {
+        self.vertical_speed = vs;
+    }
+
+    /// Play `tones` after the ones queued.
+    pub fn play(&mut self, tones: impl IntoIterator<Item = Tone>) {
+        self.queue.extend(tones);
+    }
+
+    /// The tone now, its time in and its length, advancing by `dt` s.
+    fn next(&mut self, dt: f64) -> Option<(f64, f64, f64)> {
+        while let Some(tone) = self.queue.front() {
+            if self.elapsed < tone.duration {
+                let current = (tone.freq, self.elapsed, tone.duration);
+                self.elapsed += dt;
+                return Some(current);
+            }
+            self.queue.pop_front();
+            self.elapsed = 0.0;
+        }
+        let vs = self.vertical_speed?;
+        let (freq, period) = self.vario.as_ref()?.tone(vs)?;
+        self.vario_time += dt;
+        match period {
+            Some(period) => {
+                let t = self.vario_time % period;
+                let beep = period / 2.0;
+                (t < beep).then_some((freq, t, beep))
+            }
+            None => Some((freq, self.vario_time, f64::INFINITY)),
+        }
+    }
+
+    /// Fill `buf` with the next samples.
+    pub fn fill(&mut self, buf: &mut [i16]) {
+        let dt = 1.0 / f64::from(SAMPLE_RATE);
+        for sample in buf {
+            *sample = match self.next(dt) {
+                Some((freq, t, duration)) if freq > 0.0 => {
+                    let envelope = (t / FADE).min((duration - t) / FADE).clamp(0.0, 1.0);
+                    self.phase = (self.phase + TAU * freq * dt) % TAU;
+                    (self.amplitude * envelope * self.phase.sin()).round() as i16
+                }
+                _ => {
+                    self.phase = 0.0;
+                    0
+                }
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Frequency of `samples` by the zero crossings, Hz.
+    fn frequency(samples: &[i16]) -> f64 {
+        let crossings = samples
+            .windows(2)
+            .filter(|w| (w[0] < 0) != (w[1] < 0))
+            .count();
+        crossings as f64 / 2.0 * f64::from(SAMPLE_RATE) / samples.len() as f64
+    }
+
+    #[test]
+    fn sounds() {
+        let vario = Vario {
+            climb: 0.5,
+            sink: 2.0,
+            max: 10.0,
+        };
+        let mut synth = Synth::new(Some(vario), 0.5);
+        let mut buf = vec![0i16; SAMPLE_RATE as usize / 10];
+        synth.fill(&mut buf);
+        assert!(buf.iter().all(|&s| s == 0));
+
+        // Sinking: a steady tone.
+        synth.set_vertical_speed(Some(-5.0));
+        synth.fill(&mut buf);
+        assert!((frequency(&buf[200..]) - 325.0).abs() < 15.0);
+        assert!(buf.iter().all(|&s| s.abs() <= i16::MAX / 2 + 1));
+        synth.set_vertical_speed(Some(1.0));
+        synth.fill(&mut buf);
+        // Climbing: beeps, 0.28 s on and off.
+        let mut second = vec![0i16; SAMPLE_RATE as usize];
+        synth.fill(&mut second);
+        let quiet = second
+            .chunks(441)
+            .filter(|c| c.iter().all(|&s| s == 0))
+            .count();
+        assert!((20..=30).contains(&quiet), "{}", quiet);
+
+        // An alert, then the vario again.
+        synth.set_vertical_speed(Some(0.0));
+        synth.play([Tone::new(1000.0, 0.04), Tone::pause(0.06)]);
+        let mut alert = vec![0i16; SAMPLE_RATE as usize / 20];
+        synth.fill(&mut alert);
+        assert!((frequency(&alert[200..680]) - 1000.0).abs() < 50.0);
+        assert!(alert[900..].iter().all(|&s| s == 0));
+        synth.fill(&mut alert);
+        assert!(alert.iter().all(|&s| s == 0));
+        synth.fill(&mut alert);
+        assert!(alert.iter().all(|&s| s == 0));
+    }
+}
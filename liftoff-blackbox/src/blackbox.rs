@@ -0,0 +1,283 @@
+//! Writer of Betaflight blackbox logs (`.bbl`), as read by Blackbox
+//! Explorer and PIDtoolbox.
+//!
+//! A log is a header of `H name:value` lines, then frames: intra frames
+//! (`I`) with the values themselves, every [`I_INTERVAL`] iterations, and
+//! inter frames (`P`) with the differences from the frame before, all as
+//! variable-length integers. It ends with an end-of-log event (`E`). A file
+//! can hold several logs, one after the other.
+//!
+//! The fields are those Betaflight 4.4 logs for the sticks, setpoints,
+//! gyro, battery voltage and motor RPM, in the units it logs them in, so
+//! the tools show them as they would a log from a quad.
+
+use std::io::{self, Write};
+
+/// Iterations per intra frame.
+pub const I_INTERVAL: u32 = 32;
+
+/// Field encodings.
+const SIGNED_VB: u8 = 0;
+const UNSIGNED_VB: u8 = 1;
+const NULL: u8 = 9;
+
+/// Field predictors.
+const ZERO: u8 = 0;
+const PREVIOUS: u8 = 1;
+const INC: u8 = 6;
+
+/// `FLIGHT_LOG_EVENT_LOG_END`.
+const LOG_END: u8 = 255;
+
+struct Field {
+    name: &'static str,
+    signed: bool,
+}
+
+const fn field(name: &'static str, signed: bool) -> Field {
+    Field { name, signed }
+}
+
+/// The fields of a frame, in order.
+const FIELDS: [Field; 18] = [
+    field("loopIteration", false),
+    field("time", false),
+    field("rcCommand[0]", true),
+    field("rcCommand[1]", true),
+    field("rcCommand[2]", true),
+    field("rcCommand[3]", false),
+    field("setpoint[0]", true),
+    field("setpoint[1]", true),
+    field("setpoint[2]", true),
+    field("setpoint[3]", true),
+    field("gyroADC[0]", true),
+    field("gyroADC[1]", true),
+    field("gyroADC[2]", true),
+    field("vbatLatest", false),
+    field("eRPM[0]", false),
+    field("eRPM[1]", false),
+    field("eRPM[2]", false),
+    field("eRPM[3]", false),
+];
+
+/// A frame, in Betaflight's units and axis order (roll, pitch, yaw,
+/// throttle) and motor order (rear right, front right, rear left, front
+/// left).
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Frame {
+    /// Since the start of the log, µs.
+    pub time: u32,
+    /// Sticks, -500 to 500, and throttle from 1000 to 2000.
+    pub rc_command: [i32; 4],
+    /// Rotation rates, deg/s, and throttle from 0 to 1000.
+    pub setpoint: [i32; 4],
+    /// Rotation rates, deg/s.
+    pub gyro: [i32; 3],
+    /// Battery voltage, 0.01 V.
+    pub vbat: u32,
+    /// Electrical RPM, in hundreds.
+    pub erpm: [u32; 4],
+}
+
+impl Frame {
+    fn values(&self, iteration: u32) -> [i32; FIELDS.len()] {
+        let mut values = [0; FIELDS.len()];
+        values[0] = iteration as i32;
+        values[1] = self.time as i32;
+        values[2..6].copy_from_slice(&self.rc_command);
+        values[6..10].copy_from_slice(&self.setpoint);
+        values[10..13].copy_from_slice(&self.gyro);
+        values[13] = self.vbat as i32;
+        for (value, erpm) in values[14..].iter_mut().zip(self.erpm) {
+            *value = erpm as i32;
+        }
+        values
+    }
+}
+
+/// What the header tells about the craft.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    /// Time between frames, µs.
+    pub looptime: u32,
+    pub motor_poles: u8,
+    pub craft_name: String,
+    /// As `2024-01-31T12:00:00.000+00:00`.
+    pub start: String,
+}
+
+fn unsigned_vb(out: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn signed_vb(out: &mut Vec<u8>, value: i32) {
+    // Zigzag, so small negative numbers are short too.
+    unsigned_vb(out, ((value << 1) ^ (value >> 31)) as u32);
+}
+
+fn list(values: impl Iterator<Item = impl ToString>) -> String {
+    values.map(|v| v.to_string()).collect::<Vec<_>>().join(",")
+}
+
+/// The state of a log being written: the frames so far.
+#[derive(Debug, Default)]
+pub struct Log {
+    iteration: u32,
+    previous: [i32; FIELDS.len()],
+}
+
+impl Log {
+    /// Start a log in `out`.
+    pub fn start(out: &mut impl Write, header: &Header) -> io::Result<Self> {
+        let fields = FIELDS.len();
+        let i_encoding = FIELDS
+            .iter()
+            .map(|f| if f.signed { SIGNED_VB } else { UNSIGNED_VB });
+        let mut p_predictor = vec![PREVIOUS; fields];
+        let mut p_encoding = vec![SIGNED_VB; fields];
+        // The iteration counts up by one each frame.
+        p_predictor[0] = INC;
+        p_encoding[0] = NULL;
+
+        let lines = [
+            (
+                "Product",
+                "Blackbox flight data recorder by Nicholas Sherlock".into(),
+            ),
+            ("Data version", "2".into()),
+            ("I interval", I_INTERVAL.to_string()),
+            ("P interval", "1".into()),
+            ("P ratio", I_INTERVAL.to_string()),
+            ("Field I name", list(FIELDS.iter().map(|f| f.name))),
+            (
+                "Field I signed",
+                list(FIELDS.iter().map(|f| f.signed as u8)),
+            ),
+            ("Field I predictor", list(std::iter::repeat_n(ZERO, fields))),
+            ("Field I encoding", list(i_encoding)),
+            ("Field P predictor", list(p_predictor.iter())),
+            ("Field P encoding", list(p_encoding.iter())),
+            ("Firmware type", "Cleanflight".into()),
+            (
+                "Firmware revision",
+                format!(
+                    "Betaflight 4.4.0 (liftoff-blackbox {}) LIFTOFF",
+                    env!("CARGO_PKG_VERSION")
+                ),
+            ),
+            ("Board information", "LIFT LIFTOFF".into()),
+            ("Log start datetime", header.start.clone()),
+            ("Craft name", header.craft_name.clone()),
+            ("looptime", header.looptime.to_string()),
+            ("gyro_sync_denom", "1".into()),
+            ("pid_process_denom", "1".into()),
+            ("minthrottle", "1000".into()),
+            ("maxthrottle", "2000".into()),
+            // 1.0 as float bits: the gyro in deg/s.
+            ("gyro_scale", "0x3f800000".into()),
+            ("motorOutput", "0,2047".into()),
+            ("motor_poles", header.motor_poles.to_string()),
+        ];
+        for (name, value) in lines {
+            writeln!(out, "H {}:{}", name, value)?;
+        }
+        Ok(Self::default())
+    }
+
+    /// Write a frame.
+    pub fn frame(&mut self, out: &mut impl Write, frame: &Frame) -> io::Result<()> {
+        let values = frame.values(self.iteration);
+        let mut data = Vec::with_capacity(64);
+        if self.iteration.is_multiple_of(I_INTERVAL) {
+            data.push(b'I');
+            for (field, &value) in FIELDS.iter().zip(&values) {
+                if field.signed {
+                    signed_vb(&mut data, value);
+                } else {
+                    unsigned_vb(&mut data, value as u32);
+                }
+            }
+        } else {
+            data.push(b'P');
+            // The iteration is predicted, not written.
+            for (value, previous) in values.iter().zip(&self.previous).skip(1) {
+                signed_vb(&mut data, value.wrapping_sub(*previous));
+            }
+        }
+        out.write_all(&data)?;
+        self.previous = values;
+        self.iteration += 1;
+        Ok(())
+    }
+
+    /// End the log.
+    pub fn end(self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(&[b'E', LOG_END])?;
+        out.write_all(b"End of log\0")?;
+        out.flush()
+    }
+
+    /// Frames written.
+    pub fn frames(&self) -> u32 {
+        self.iteration
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoding() {
+        let mut data = Vec::new();
+        unsigned_vb(&mut data, 1);
+        unsigned_vb(&mut data, 300);
+        signed_vb(&mut data, -1);
+        signed_vb(&mut data, 1);
+        assert_eq!(data, [0x01, 0xac, 0x02, 0x01, 0x02]);
+
+        let header = Header {
+            looptime: 2000,
+            motor_poles: 14,
+            craft_name: "quad".into(),
+            start: "2024-01-31T12:00:00.000+00:00".into(),
+        };
+        let mut out = Vec::new();
+        let mut log = Log::start(&mut out, &header).unwrap();
+        let text = String::from_utf8(out.clone()).unwrap();
+        assert!(text.starts_with("H Product:Blackbox flight data recorder"));
+        assert!(text.contains("\nH Field I name:loopIteration,time,rcCommand[0],"));
+        assert!(text.contains("\nH Field P predictor:6,1,1,"));
+        assert!(text.contains("\nH looptime:2000\n"));
+
+        let start = out.len();
+        let mut frame = Frame {
+            rc_command: [0, -1, 0, 1000],
+            ..Default::default()
+        };
+        log.frame(&mut out, &frame).unwrap();
+        assert_eq!(out[start], b'I');
+        assert_eq!(out[start + 1..start + 4], [0, 0, 0]);
+        assert_eq!(out[start + 4..start + 8], [0x01, 0x00, 0xe8, 0x07]);
+        assert_eq!(out.len(), start + 20);
+
+        let start = out.len();
+        frame.time = 2000;
+        frame.gyro[2] = -3;
+        log.frame(&mut out, &frame).unwrap();
+        let mut expected = vec![b'P', 0xa0, 0x1f];
+        expected.extend([0; 8]);
+        expected.extend([0, 0, 0x05]);
+        expected.extend([0; 5]);
+        assert_eq!(out[start..], expected);
+        assert_eq!(log.frames(), 2);
+
+        let start = out.len();
+        log.end(&mut out).unwrap();
+        assert_eq!(out[start..], *b"E\xffEnd of log\0");
+    }
+}
@@ -0,0 +1,129 @@
+use chrono::{SecondsFormat, Utc};
+use clap::Parser;
+use log::{debug, info, warn};
+use std::fs::OpenOptions;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::time::Instant;
+use telemetry_lib::telemetry;
+use telemetry_lib::topics;
+use tokio::time::{Duration, timeout};
+use zenoh::Config;
+
+mod blackbox;
+mod recorder;
+
+use recorder::{Recorder, Settings};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Blackbox log file. Each flight is a log, appended to it.
+    #[arg(long, default_value = "liftoff.bbl")]
+    output: PathBuf,
+
+    /// Rotation rate at full stick deflection, for the setpoints, deg/s.
+    #[arg(long, default_value_t = 670.0)]
+    rate: f32,
+
+    /// Magnets in the motors, to log the motor RPM as eRPM.
+    #[arg(long, default_value_t = 14)]
+    motor_poles: u8,
+
+    /// Craft name in the log header.
+    #[arg(long, default_value = "")]
+    craft_name: String,
+
+    /// A log ends when the telemetry stops for this long, s.
+    #[arg(long, default_value_t = 1.0)]
+    gap: f64,
+
+    /// Zenoh connect endpoint (e.g. tcp/192.168.1.1:7447). Omit for peer discovery.
+    #[arg(long)]
+    zenoh_connect: Option<String>,
+
+    /// Zenoh mode (peer or client).
+    #[arg(long, default_value = "client")]
+    zenoh_mode: String,
+
+    /// Zenoh topic prefix.
+    #[arg(long, default_value = topics::DEFAULT_PREFIX)]
+    zenoh_prefix: String,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    env_logger::init();
+    let args = Args::parse();
+
+    info!("Starting liftoff-blackbox");
+
+    // Zenoh session
+    let mut config = Config::default();
+    config.insert_json5("mode", &format!(r#""{}""#, args.zenoh_mode))?;
+    if let Some(ref endpoint) = args.zenoh_connect {
+        config.insert_json5("connect/endpoints", &format!(r#"["{}"]"#, endpoint))?;
+    }
+
+    let session = zenoh::open(config).await?;
+
+    let tel_topic = topics::topic(&args.zenoh_prefix, topics::TELEMETRY);
+    info!("Subscribing to: {}", tel_topic);
+    let tel_subscriber = session.declare_subscriber(&tel_topic).await?;
+
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&args.output)?;
+    info!("Logging to {}", args.output.display());
+    let settings = Settings {
+        rate: args.rate,
+        motor_poles: args.motor_poles,
+        craft_name: args.craft_name.clone(),
+    };
+    let mut recorder = Recorder::new(BufWriter::new(file), settings);
+
+    // Telemetry format config
+    // We assume default configuration for now
+    let format = telemetry::default_stream_format();
+    let gap = Duration::from_secs_f64(args.gap);
+    // For telemetry without a timestamp.
+    let epoch = Instant::now();
+    let datetime = || Utc::now().to_rfc3339_opts(SecondsFormat::Millis, false);
+    loop {
+        tokio::select! {
+            sample = timeout(gap, tel_subscriber.recv_async()) => match sample {
+                Ok(Ok(sample)) => {
+                    let payload = sample.payload().to_bytes();
+                    match telemetry::parse_packet(&payload, &format) {
+                        Ok(packet) => {
+                            let time = match packet.timestamp {
+                                Some(timestamp) => f64::from(timestamp),
+                                None => epoch.elapsed().as_secs_f64(),
+                            };
+                            recorder.record(time, datetime, &packet)?;
+                        }
+                        Err(e) => debug!("Sim telemetry: {}", e),
+                    }
+                }
+                Ok(Err(e)) => {
+                    warn!("Sim telemetry subscriber error: {}", e);
+                    break;
+                }
+                Err(_) if recorder.logging() => {
+                    info!("No telemetry for {} s", args.gap);
+                    recorder.end()?;
+                }
+                Err(_) => {}
+            },
+            _ = tokio::signal::ctrl_c() => {
+                info!("Shutdown signal received, exiting.");
+                break;
+            }
+        }
+    }
+    recorder.end()?;
+
+    session.close().await?;
+    Ok(())
+}
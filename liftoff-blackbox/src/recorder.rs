@@ -0,0 +1,229 @@
+//! Recording of the sim telemetry into blackbox logs.
+//!
+//! A log starts with the first telemetry, and ends when it stops
+//! ([`Recorder::end`]) or when the sim time goes back, as it does when a
+//! flight restarts. The header gives the time between frames, so it is
+//! written once the first frames are in.
+
+use std::io::{self, Write};
+
+use log::info;
+use telemetry_lib::telemetry::TelemetryPacket;
+
+use crate::blackbox::{Frame, Header, I_INTERVAL, Log};
+
+/// Frames held back before the header, to measure the frame rate.
+const PENDING_FRAMES: usize = 4 * I_INTERVAL as usize;
+
+/// For each Betaflight motor, the motor in the sim telemetry (left front,
+/// right front, left back, right back).
+const MOTOR_ORDER: [usize; 4] = [3, 1, 2, 0];
+
+/// How to convert the telemetry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    /// Rotation rate at full stick deflection, deg/s.
+    pub rate: f32,
+    pub motor_poles: u8,
+    pub craft_name: String,
+}
+
+impl Settings {
+    /// The frame for `packet`, `time` µs into the log.
+    pub fn frame(&self, time: u32, packet: &TelemetryPacket) -> Frame {
+        let mut frame = Frame {
+            time,
+            ..Default::default()
+        };
+        // Throttle, yaw, pitch, roll, from -1 to 1.
+        if let Some([throttle, yaw, pitch, roll]) = packet.input {
+            let throttle = ((throttle + 1.0) / 2.0).clamp(0.0, 1.0);
+            for (axis, stick) in [roll, pitch, yaw].into_iter().enumerate() {
+                let stick = stick.clamp(-1.0, 1.0);
+                frame.rc_command[axis] = (stick * 500.0).round() as i32;
+                frame.setpoint[axis] = (stick * self.rate).round() as i32;
+            }
+            frame.rc_command[3] = 1000 + (throttle * 1000.0).round() as i32;
+            frame.setpoint[3] = (throttle * 1000.0).round() as i32;
+        }
+        // Pitch, roll, yaw.
+        if let Some([pitch, roll, yaw]) = packet.gyro {
+            frame.gyro = [roll, pitch, yaw].map(|rate| rate.round() as i32);
+        }
+        if let Some([_, voltage]) = packet.battery {
+            frame.vbat = (voltage.max(0.0) * 100.0).round() as u32;
+        }
+        if let Some(rpm) = &packet.motor_rpm {
+            let pole_pairs = f32::from(self.motor_poles) / 2.0;
+            for (erpm, &motor) in frame.erpm.iter_mut().zip(&MOTOR_ORDER) {
+                let rpm = rpm.get(motor).copied().unwrap_or_default().abs();
+                *erpm = (rpm * pole_pairs / 100.0).round() as u32;
+            }
+        }
+        frame
+    }
+}
+
+/// Writes the logs to `out`.
+pub struct Recorder<W: Write> {
+    out: W,
+    settings: Settings,
+    /// Sim time at the start of the log, and of the last frame, s.
+    times: Option<(f64, f64)>,
+    /// As `2024-01-31T12:00:00.000+00:00`.
+    start: String,
+    pending: Vec<Frame>,
+    log: Option<Log>,
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(out: W, settings: Settings) -> Self {
+        Self {
+            out,
+            settings,
+            times: None,
+            start: String::new(),
+            pending: Vec::new(),
+            log: None,
+        }
+    }
+
+    /// Record `packet`, from `time` in the sim, s; a log started at
+    /// `datetime` if none is running.
+    pub fn record(
+        &mut self,
+        time: f64,
+        datetime: impl FnOnce() -> String,
+        packet: &TelemetryPacket,
+    ) -> io::Result<()> {
+        if let Some((_, last)) = self.times
+            && time < last
+        {
+            info!("Sim time went back, starting a new log");
+            self.end()?;
+        }
+        let start = match self.times {
+            Some((start, _)) => start,
+            None => {
+                info!("Log started");
+                self.start = datetime();
+                time
+            }
+        };
+        self.times = Some((start, time));
+        let frame = self
+            .settings
+            .frame(((time - start) * 1e6).round() as u32, packet);
+        match &mut self.log {
+            Some(log) => log.frame(&mut self.out, &frame),
+            None => {
+                self.pending.push(frame);
+                if self.pending.len() < PENDING_FRAMES {
+                    return Ok(());
+                }
+                self.header()
+            }
+        }
+    }
+
+    /// Write the header and the frames held back.
+    fn header(&mut self) -> io::Result<()> {
+        let looptime = match (self.pending.first(), self.pending.last()) {
+            (Some(first), Some(last)) if self.pending.len() > 1 => {
+                (last.time - first.time) / (self.pending.len() as u32 - 1)
+            }
+            _ => 0,
+        };
+        let header = Header {
+            looptime: looptime.max(1),
+            motor_poles: self.settings.motor_poles,
+            craft_name: self.settings.craft_name.clone(),
+            start: self.start.clone(),
+        };
+        let mut log = Log::start(&mut self.out, &header)?;
+        for frame in self.pending.drain(..) {
+            log.frame(&mut self.out, &frame)?;
+        }
+        self.log = Some(log);
+        Ok(())
+    }
+
+    /// End the log, if one is running.
+    pub fn end(&mut self) -> io::Result<()> {
+        if self.times.take().is_none() {
+            return Ok(());
+        }
+        if self.log.is_none() {
+            self.header()?;
+        }
+        if let Some(log) = self.log.take() {
+            info!("Log ended after {} frames", log.frames());
+            log.end(&mut self.out)?;
+        }
+        Ok(())
+    }
+
+    pub fn logging(&self) -> bool {
+        self.times.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(throttle: f32, roll: f32) -> TelemetryPacket {
+        TelemetryPacket {
+            timestamp: None,
+            position: None,
+            attitude: None,
+            velocity: None,
+            gyro: Some([10.4, -20.6, 0.0]),
+            input: Some([throttle, 0.0, 0.0, roll]),
+            battery: Some([0.9, 16.8]),
+            motor_rpm: Some(vec![1000.0, 2000.0, 3000.0, 4000.0]),
+        }
+    }
+
+    #[test]
+    fn recording() {
+        let settings = Settings {
+            rate: 670.0,
+            motor_poles: 14,
+            craft_name: String::new(),
+        };
+        let frame = settings.frame(500, &packet(-1.0, -0.5));
+        assert_eq!(frame.time, 500);
+        assert_eq!(frame.rc_command, [-250, 0, 0, 1000]);
+        assert_eq!(frame.setpoint, [-335, 0, 0, 0]);
+        assert_eq!(frame.gyro, [-21, 10, 0]);
+        assert_eq!(frame.vbat, 1680);
+        assert_eq!(frame.erpm, [280, 140, 210, 70]);
+        let frame = settings.frame(0, &packet(1.0, 2.0));
+        assert_eq!(frame.rc_command, [500, 0, 0, 2000]);
+        assert_eq!(frame.setpoint, [670, 0, 0, 1000]);
+
+        let datetime = || "2024-01-31T12:00:00.000+00:00".to_string();
+        let mut recorder = Recorder::new(Vec::new(), settings);
+        assert!(!recorder.logging());
+        for i in 0..10 {
+            let time = 3.0 + i as f64 * 0.002;
+            recorder.record(time, datetime, &packet(0.0, 0.0)).unwrap();
+        }
+        assert!(recorder.logging());
+        // Held back until the frame rate is known.
+        assert!(recorder.out.is_empty());
+        // The flight restarts.
+        recorder.record(1.0, datetime, &packet(0.0, 0.0)).unwrap();
+        recorder.end().unwrap();
+        recorder.end().unwrap();
+        assert!(!recorder.logging());
+
+        let text = String::from_utf8_lossy(&recorder.out);
+        assert_eq!(text.matches("H Product:").count(), 2);
+        assert_eq!(text.matches("End of log").count(), 2);
+        assert!(text.contains("H looptime:2000\n"));
+        assert!(text.contains("H looptime:1\n"));
+        assert!(text.contains("H Log start datetime:2024-01-31T12:00:00.000+00:00\n"));
+    }
+}
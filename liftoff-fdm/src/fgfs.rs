@@ -0,0 +1,166 @@
+//! FlightGear's native FDM protocol, `FGNetFDM` version 24: a fixed
+//! 408-byte structure in network byte order, which FlightGear takes with
+//! `--fdm=null --native-fdm=socket,in,RATE,,PORT,udp`.
+
+use crate::flight::{Flight, G};
+
+pub const VERSION: u32 = 24;
+
+const MAX_ENGINES: usize = 4;
+const MAX_TANKS: usize = 4;
+const MAX_WHEELS: usize = 3;
+
+/// `eng_state` of a turning motor, and of a stopped one.
+const ENGINE_RUNNING: u32 = 2;
+const ENGINE_OFF: u32 = 0;
+
+const FEET: f64 = 3.28084;
+const KNOTS: f64 = 1.943844;
+
+/// Visibility reported, m.
+const VISIBILITY: f64 = 20000.0;
+
+#[derive(Default)]
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn u32(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn f32(&mut self, value: f64) {
+        self.buf.extend_from_slice(&(value as f32).to_be_bytes());
+    }
+
+    fn f64(&mut self, value: f64) {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+    }
+
+    /// `len` of `values`, then as many zeros as there are slots left.
+    fn f32_slots(&mut self, values: impl Iterator<Item = f64>, len: usize) {
+        let mut count = 0;
+        for value in values.take(len) {
+            self.f32(value);
+            count += 1;
+        }
+        for _ in count..len {
+            self.f32(0.0);
+        }
+    }
+}
+
+/// The `FGNetFDM` of `flight`, at `time` (s since the epoch).
+pub fn encode(flight: &Flight, time: u32) -> Vec<u8> {
+    let mut w = Writer::default();
+    w.u32(VERSION);
+    w.u32(0);
+
+    // Position, radians and m.
+    w.f64(flight.longitude.to_radians());
+    w.f64(flight.latitude.to_radians());
+    w.f64(flight.altitude);
+    w.f32(flight.height);
+
+    // Attitude, and the angles of attack and sideslip.
+    w.f32(flight.roll);
+    w.f32(flight.pitch);
+    w.f32(flight.heading);
+    let [u, v, wv] = flight.body_velocity;
+    let airspeed = flight.airspeed();
+    w.f32(wv.atan2(u));
+    w.f32(if airspeed > 0.0 {
+        (v / airspeed).asin()
+    } else {
+        0.0
+    });
+
+    // Rates, velocities in ft/s and accelerations in ft/s².
+    for rate in flight.rates {
+        w.f32(rate);
+    }
+    w.f32(airspeed * KNOTS);
+    w.f32(-flight.velocity[2] * FEET);
+    for value in flight.velocity.into_iter().chain(flight.body_velocity) {
+        w.f32(value * FEET);
+    }
+    for value in flight.acceleration.unwrap_or([0.0, 0.0, -G]) {
+        w.f32(value * FEET);
+    }
+    // Stall warning, slip.
+    w.f32(0.0);
+    w.f32(0.0);
+
+    // Engines: the motors, of which only the state and RPM are known.
+    let engines = flight.rpm.len().min(MAX_ENGINES);
+    w.u32(engines as u32);
+    for i in 0..MAX_ENGINES {
+        let turning = flight.rpm.get(i).is_some_and(|&rpm| rpm > 0.0);
+        w.u32(if turning { ENGINE_RUNNING } else { ENGINE_OFF });
+    }
+    w.f32_slots(flight.rpm.iter().map(|&rpm| f64::from(rpm)), MAX_ENGINES);
+    // Fuel flow, fuel pressure, EGT, CHT, manifold pressure, TIT, oil
+    // temperature and oil pressure.
+    for _ in 0..8 {
+        w.f32_slots(std::iter::empty(), MAX_ENGINES);
+    }
+
+    // No tanks, no wheels.
+    w.u32(0);
+    w.f32_slots(std::iter::empty(), MAX_TANKS);
+    w.u32(0);
+    for _ in 0..MAX_WHEELS {
+        w.u32(0);
+    }
+    for _ in 0..3 {
+        w.f32_slots(std::iter::empty(), MAX_WHEELS);
+    }
+
+    // Time, warp and visibility.
+    w.u32(time);
+    w.u32(0);
+    w.f32(VISIBILITY);
+
+    // Control surfaces: elevator, elevator trim, flaps, ailerons, rudder,
+    // nose wheel, speedbrake and spoilers.
+    w.f32_slots(std::iter::empty(), 10);
+    w.buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn native_fdm() {
+        let flight = Flight {
+            latitude: 52.0,
+            longitude: 4.0,
+            altitude: 120.0,
+            height: 20.0,
+            roll: 0.1,
+            pitch: 0.2,
+            heading: 0.3,
+            rates: [0.0; 3],
+            velocity: [10.0, 0.0, -1.0],
+            body_velocity: [10.0, 0.0, 0.0],
+            acceleration: None,
+            rpm: vec![10000.0, 0.0],
+        };
+        let data = encode(&flight, 1_700_000_000);
+        assert_eq!(data.len(), 408);
+        assert_eq!(data[..4], 24u32.to_be_bytes());
+        assert_eq!(data[8..16], 4f64.to_radians().to_be_bytes());
+        assert_eq!(data[16..24], 52f64.to_radians().to_be_bytes());
+        assert_eq!(data[24..32], 120f64.to_be_bytes());
+        assert_eq!(data[36..40], 0.1f32.to_be_bytes());
+        // Climb rate, ft/s.
+        assert_eq!(data[72..76], (FEET as f32).to_be_bytes());
+        // Two engines, one of them running at 10000 RPM.
+        assert_eq!(data[120..124], 2u32.to_be_bytes());
+        assert_eq!(data[124..132], [0, 0, 0, 2, 0, 0, 0, 0]);
+        assert_eq!(data[140..144], 10000f32.to_be_bytes());
+        assert_eq!(data[356..360], 1_700_000_000u32.to_be_bytes());
+    }
+}
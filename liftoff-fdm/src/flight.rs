@@ -0,0 +1,194 @@
+//! The state of the quad, in the terms of flight simulators: position on
+//! the globe, Euler angles, body rates and velocities, and the specific
+//! force felt by the pilot.
+//!
+//! Liftoff's world is left-handed, X east, Y up and Z north; the quad's
+//! body is right, up, forward. The gyro gives the rates about the right,
+//! forward and up axes, in deg/s. The acceleration, which the sim doesn't
+//! give, is the change of velocity between packets.
+
+use telemetry_lib::geo;
+use telemetry_lib::telemetry::TelemetryPacket;
+
+pub const G: f64 = 9.80665;
+
+/// Vector `v` turned by the quaternion `q` (x, y, z, w).
+fn rotate([x, y, z, w]: [f64; 4], v: [f64; 3]) -> [f64; 3] {
+    let u = [x, y, z];
+    let cross = |a: [f64; 3], b: [f64; 3]| {
+        [
+            a[1] * b[2] - a[2] * b[1],
+            a[2] * b[0] - a[0] * b[2],
+            a[0] * b[1] - a[1] * b[0],
+        ]
+    };
+    let t = cross(u, v).map(|c| 2.0 * c);
+    let s = cross(u, t);
+    [0, 1, 2].map(|i| v[i] + w * t[i] + s[i])
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Flight {
+    /// Degrees.
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Above mean sea level, m.
+    pub altitude: f64,
+    /// Above the scene origin, m.
+    pub height: f64,
+    /// Roll (right wing down), pitch (nose up) and true heading, radians.
+    pub roll: f64,
+    pub pitch: f64,
+    pub heading: f64,
+    /// Roll, pitch and yaw rates (p, q, r), rad/s.
+    pub rates: [f64; 3],
+    /// North, east, down, m/s.
+    pub velocity: [f64; 3],
+    /// Forward, right, down (u, v, w), m/s.
+    pub body_velocity: [f64; 3],
+    /// Specific force along forward, right, down, m/s²: about -G down when
+    /// hovering. `None` until there are two packets to tell.
+    pub acceleration: Option<[f64; 3]>,
+    /// Motor RPM.
+    pub rpm: Vec<f32>,
+}
+
+impl Flight {
+    /// Speed through the air, which is still in Liftoff, m/s.
+    pub fn airspeed(&self) -> f64 {
+        dot(self.velocity, self.velocity).sqrt()
+    }
+
+    /// Horizontal speed, m/s.
+    pub fn ground_speed(&self) -> f64 {
+        self.velocity[0].hypot(self.velocity[1])
+    }
+}
+
+pub struct Tracker {
+    /// Latitude and longitude of the scene origin, degrees, and its
+    /// altitude above mean sea level, m.
+    home: (f64, f64, f64),
+    /// Time (s) and world velocity of the last packet.
+    last: Option<(f64, [f64; 3])>,
+}
+
+impl Tracker {
+    pub fn new(home: (f64, f64, f64)) -> Self {
+        Self { home, last: None }
+    }
+
+    /// The flight state from `packet`, if it has the position, attitude
+    /// and velocity.
+    pub fn update(&mut self, packet: &TelemetryPacket) -> Option<Flight> {
+        let position = packet.position?.map(f64::from);
+        let attitude = packet.attitude?.map(f64::from);
+        let velocity = packet.velocity?.map(f64::from);
+        let gyro = packet.gyro.unwrap_or_default().map(f64::from);
+
+        let right = rotate(attitude, [1.0, 0.0, 0.0]);
+        let up = rotate(attitude, [0.0, 1.0, 0.0]);
+        let forward = rotate(attitude, [0.0, 0.0, 1.0]);
+
+        // The world acceleration since the last packet, less gravity.
+        let mut acceleration = None;
+        if let Some(time) = packet.timestamp.map(f64::from) {
+            if let Some((last_time, last_velocity)) = self.last
+                && time > last_time
+            {
+                let dt = time - last_time;
+                let a = [0, 1, 2].map(|i| (velocity[i] - last_velocity[i]) / dt);
+                let force = [a[0], a[1] + G, a[2]];
+                acceleration = Some([dot(force, forward), dot(force, right), -dot(force, up)]);
+            }
+            self.last = Some((time, velocity));
+        }
+
+        let (lat, lon, alt) = self.home;
+        let (longitude, latitude, height) = geo::gps_from_coord(&position, (lon, lat));
+        Some(Flight {
+            latitude,
+            longitude,
+            altitude: alt + height,
+            height,
+            roll: (-right[1]).atan2(up[1]),
+            pitch: forward[1].clamp(-1.0, 1.0).asin(),
+            heading: forward[0]
+                .atan2(forward[2])
+                .rem_euclid(std::f64::consts::TAU),
+            // Liftoff's rates turn the other way about the right and
+            // forward axes: nose down and right wing up.
+            rates: [-gyro[1], -gyro[0], gyro[2]].map(f64::to_radians),
+            velocity: [velocity[2], velocity[0], -velocity[1]],
+            body_velocity: [
+                dot(velocity, forward),
+                dot(velocity, right),
+                -dot(velocity, up),
+            ],
+            acceleration,
+            rpm: packet.motor_rpm.clone().unwrap_or_default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::{FRAC_1_SQRT_2, FRAC_PI_2};
+
+    fn close(a: &[f64], b: &[f64]) -> bool {
+        a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-4)
+    }
+
+    fn packet(time: f32, attitude: [f32; 4], speed: f32) -> TelemetryPacket {
+        TelemetryPacket {
+            timestamp: Some(time),
+            position: Some([0.0, 20.0, 111.111]),
+            attitude: Some(attitude),
+            velocity: Some([0.0, 1.0, speed]),
+            gyro: Some([10.0, 20.0, 30.0]),
+            input: None,
+            battery: None,
+            motor_rpm: Some(vec![10000.0; 4]),
+        }
+    }
+
+    #[test]
+    fn flight() {
+        let mut tracker = Tracker::new((0.0, 0.0, 100.0));
+        let level = [0.0, 0.0, 0.0, 1.0];
+        let flight = tracker.update(&packet(1.0, level, 5.0)).unwrap();
+        assert!((flight.latitude - 0.001).abs() < 1e-6);
+        assert_eq!((flight.height, flight.altitude), (20.0, 120.0));
+        assert!(close(
+            &[flight.roll, flight.pitch, flight.heading],
+            &[0.0; 3]
+        ));
+        let rates = [-20.0, -10.0, 30.0].map(f64::to_radians);
+        assert!(close(&flight.rates, &rates));
+        assert!(close(&flight.velocity, &[5.0, 0.0, -1.0]));
+        assert!(close(&flight.body_velocity, &[5.0, 0.0, -1.0]));
+        assert_eq!(flight.acceleration, None);
+
+        // Facing east: a quarter turn right about up, in Liftoff; flying
+        // north is flying left.
+        let east = [0.0, FRAC_1_SQRT_2 as f32, 0.0, FRAC_1_SQRT_2 as f32];
+        let flight = tracker.update(&packet(1.5, east, 6.0)).unwrap();
+        assert!((flight.heading - FRAC_PI_2).abs() < 1e-4);
+        assert!(close(&flight.body_velocity, &[0.0, -6.0, -1.0]));
+        let acceleration = flight.acceleration.unwrap();
+        assert!(close(&acceleration, &[0.0, -2.0, -G]));
+
+        // Nose up, and rolled right.
+        let pitched = [-0.258819, 0.0, 0.0, 0.965926];
+        let flight = tracker.update(&packet(2.0, pitched, 0.0)).unwrap();
+        assert!((flight.pitch - 30f64.to_radians()).abs() < 1e-4);
+        let rolled = [0.0, 0.0, -0.258819, 0.965926];
+        let flight = tracker.update(&packet(2.5, rolled, 0.0)).unwrap();
+        assert!((flight.roll - 30f64.to_radians()).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,160 @@
+use clap::Parser;
+use log::{debug, info, warn};
+use std::net::SocketAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use telemetry_lib::geo;
+use telemetry_lib::telemetry;
+use telemetry_lib::topics;
+use tokio::net::UdpSocket;
+use zenoh::Config;
+
+mod fgfs;
+mod flight;
+mod xplane;
+
+use flight::Tracker;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Send FlightGear native FDM to this address (e.g. 127.0.0.1:5502),
+    /// for `fgfs --fdm=null --native-fdm=socket,in,60,,5502,udp`. Can be
+    /// given more than once.
+    #[arg(long)]
+    fgfs: Vec<SocketAddr>,
+
+    /// Send X-Plane data output to this address (e.g. 192.168.1.10:49003),
+    /// as X-Plane would for the speeds, G-load, angular velocities,
+    /// attitude, position and engine RPM rows. Can be given more than once.
+    #[arg(long)]
+    xplane: Vec<SocketAddr>,
+
+    /// Latitude of the scene origin, degrees. Liftoff's scene is placed at
+    /// 0°N 0°E otherwise.
+    #[arg(long, allow_negative_numbers = true, requires = "home_lon")]
+    home_lat: Option<f64>,
+
+    /// Longitude of the scene origin, degrees.
+    #[arg(long, allow_negative_numbers = true, requires = "home_lat")]
+    home_lon: Option<f64>,
+
+    /// Altitude of the scene origin above mean sea level, m.
+    #[arg(long, allow_negative_numbers = true, default_value_t = 0.0)]
+    home_alt: f64,
+
+    /// Zenoh connect endpoint (e.g. tcp/192.168.1.1:7447). Omit for peer discovery.
+    #[arg(long)]
+    zenoh_connect: Option<String>,
+
+    /// Zenoh mode (peer or client).
+    #[arg(long, default_value = "client")]
+    zenoh_mode: String,
+
+    /// Zenoh topic prefix.
+    #[arg(long, default_value = topics::DEFAULT_PREFIX)]
+    zenoh_prefix: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    FlightGear,
+    XPlane,
+}
+
+/// A socket to send from to `addr`.
+async fn socket_for(addr: SocketAddr) -> std::io::Result<UdpSocket> {
+    let bind: SocketAddr = if addr.is_ipv4() {
+        ([0, 0, 0, 0], 0).into()
+    } else {
+        ([0u16; 8], 0).into()
+    };
+    UdpSocket::bind(bind).await
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    env_logger::init();
+    let args = Args::parse();
+
+    if args.fgfs.is_empty() && args.xplane.is_empty() {
+        return Err("give --fgfs, --xplane or both".into());
+    }
+
+    info!("Starting liftoff-fdm");
+
+    let mut outputs = Vec::new();
+    let targets = (args.fgfs.iter().map(|&addr| (Format::FlightGear, addr)))
+        .chain(args.xplane.iter().map(|&addr| (Format::XPlane, addr)));
+    for (format, addr) in targets {
+        info!("Sending {:?} to {}", format, addr);
+        outputs.push((format, addr, socket_for(addr).await?));
+    }
+
+    // Zenoh session
+    let mut config = Config::default();
+    config.insert_json5("mode", &format!(r#""{}""#, args.zenoh_mode))?;
+    if let Some(ref endpoint) = args.zenoh_connect {
+        config.insert_json5("connect/endpoints", &format!(r#"["{}"]"#, endpoint))?;
+    }
+
+    let session = zenoh::open(config).await?;
+
+    let tel_topic = topics::topic(&args.zenoh_prefix, topics::TELEMETRY);
+    info!("Subscribing to: {}", tel_topic);
+    let tel_subscriber = session.declare_subscriber(&tel_topic).await?;
+
+    // Telemetry format config
+    // We assume default configuration for now
+    let format = telemetry::default_stream_format();
+    let home = (
+        args.home_lat.unwrap_or(0.0),
+        args.home_lon.unwrap_or(0.0),
+        args.home_alt,
+    );
+    let declination = geo::magnetic_declination(home.0, home.1);
+    let mut tracker = Tracker::new(home);
+    loop {
+        let sample = tokio::select! {
+            sample = tel_subscriber.recv_async() => match sample {
+                Ok(sample) => sample,
+                Err(e) => {
+                    warn!("Sim telemetry subscriber error: {}", e);
+                    break;
+                }
+            },
+            _ = tokio::signal::ctrl_c() => {
+                info!("Shutdown signal received, exiting.");
+                break;
+            }
+        };
+        let packet = match telemetry::parse_packet(&sample.payload().to_bytes(), &format) {
+            Ok(packet) => packet,
+            Err(e) => {
+                debug!("Sim telemetry: {}", e);
+                continue;
+            }
+        };
+        let Some(flight) = tracker.update(&packet) else {
+            debug!("Sim telemetry without position, attitude or velocity");
+            continue;
+        };
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as u32;
+        let fgfs = fgfs::encode(&flight, time);
+        let xplane = xplane::encode(&flight, declination);
+        for (format, addr, socket) in &outputs {
+            let data = match format {
+                Format::FlightGear => &fgfs,
+                Format::XPlane => &xplane,
+            };
+            if let Err(e) = socket.send_to(data, addr).await {
+                warn!("Failed to send to {}: {}", addr, e);
+            }
+        }
+    }
+
+    session.close().await?;
+    Ok(())
+}
@@ -0,0 +1,150 @@
+//! X-Plane's UDP data output, as X-Plane 11 sends it for the rows ticked
+//! in its Data Output screen: `DATA`, a byte for X-Plane's own use, then
+//! for each row its index (i32) and eight values (f32), little-endian.
+//! Values a row doesn't have are -999.
+
+use crate::flight::{Flight, G};
+
+const HEADER: &[u8; 5] = b"DATA*";
+
+const UNUSED: f32 = -999.0;
+
+/// The rows sent.
+const SPEEDS: i32 = 3;
+const MACH_VVI_GLOAD: i32 = 4;
+const ANGULAR_VELOCITIES: i32 = 16;
+const PITCH_ROLL_HEADINGS: i32 = 17;
+const LAT_LON_ALTITUDE: i32 = 20;
+const ENGINE_RPM: i32 = 37;
+
+const FEET: f64 = 3.28084;
+const KNOTS: f64 = 1.943844;
+const MPH: f64 = 2.236936;
+/// Speed of sound at sea level, m/s.
+const SPEED_OF_SOUND: f64 = 340.29;
+
+fn row(buf: &mut Vec<u8>, index: i32, values: [f32; 8]) {
+    buf.extend_from_slice(&index.to_le_bytes());
+    for value in values {
+        buf.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+/// The data output of `flight`. `declination` is the magnetic declination,
+/// degrees east, for the magnetic heading.
+pub fn encode(flight: &Flight, declination: f64) -> Vec<u8> {
+    let mut buf = HEADER.to_vec();
+    // There is no wind in Liftoff, so airspeed is true airspeed, and
+    // indicated at sea level.
+    let airspeed = flight.airspeed();
+    let ground_speed = flight.ground_speed();
+    row(
+        &mut buf,
+        SPEEDS,
+        [
+            (airspeed * KNOTS) as f32,
+            (airspeed * KNOTS) as f32,
+            (airspeed * KNOTS) as f32,
+            (ground_speed * KNOTS) as f32,
+            UNUSED,
+            (airspeed * MPH) as f32,
+            (airspeed * MPH) as f32,
+            (ground_speed * MPH) as f32,
+        ],
+    );
+    let [forward, right, down] = flight.acceleration.unwrap_or([0.0, 0.0, -G]);
+    row(
+        &mut buf,
+        MACH_VVI_GLOAD,
+        [
+            (airspeed / SPEED_OF_SOUND) as f32,
+            UNUSED,
+            (-flight.velocity[2] * FEET * 60.0) as f32,
+            UNUSED,
+            (-down / G) as f32,
+            (forward / G) as f32,
+            (right / G) as f32,
+            UNUSED,
+        ],
+    );
+    let [p, q, r] = flight.rates.map(|rate| rate as f32);
+    row(
+        &mut buf,
+        ANGULAR_VELOCITIES,
+        [q, p, r, UNUSED, UNUSED, UNUSED, UNUSED, UNUSED],
+    );
+    let heading = flight.heading.to_degrees();
+    row(
+        &mut buf,
+        PITCH_ROLL_HEADINGS,
+        [
+            flight.pitch.to_degrees() as f32,
+            flight.roll.to_degrees() as f32,
+            heading as f32,
+            (heading - declination).rem_euclid(360.0) as f32,
+            UNUSED,
+            UNUSED,
+            UNUSED,
+            UNUSED,
+        ],
+    );
+    row(
+        &mut buf,
+        LAT_LON_ALTITUDE,
+        [
+            flight.latitude as f32,
+            flight.longitude as f32,
+            (flight.altitude * FEET) as f32,
+            (flight.height * FEET) as f32,
+            0.0,
+            (flight.altitude * FEET) as f32,
+            UNUSED,
+            UNUSED,
+        ],
+    );
+    if !flight.rpm.is_empty() {
+        let mut rpm = [UNUSED; 8];
+        for (slot, &value) in rpm.iter_mut().zip(&flight.rpm) {
+            *slot = value;
+        }
+        row(&mut buf, ENGINE_RPM, rpm);
+    }
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_output() {
+        let flight = Flight {
+            latitude: 52.0,
+            longitude: 4.0,
+            altitude: 120.0,
+            height: 20.0,
+            roll: 0.0,
+            pitch: 0.0,
+            heading: 0.0,
+            rates: [0.1, 0.2, 0.3],
+            velocity: [10.0, 0.0, -1.0],
+            body_velocity: [10.0, 0.0, 0.0],
+            acceleration: None,
+            rpm: vec![],
+        };
+        let data = encode(&flight, 2.0);
+        assert_eq!(&data[..5], b"DATA*");
+        assert_eq!(data.len(), 5 + 5 * 36);
+        let value = |row: usize, i: usize| {
+            let at = 5 + row * 36 + 4 + i * 4;
+            f32::from_le_bytes(data[at..at + 4].try_into().unwrap())
+        };
+        assert_eq!(data[5..9], 3i32.to_le_bytes());
+        // Pitch rate first, hovering at 1 G, heading 358° magnetic.
+        assert_eq!((value(2, 0), value(2, 1)), (0.2, 0.1));
+        assert_eq!(value(1, 4), 1.0);
+        assert_eq!(value(3, 3), 358.0);
+        assert_eq!(value(4, 0), 52.0);
+        assert_eq!(value(4, 2), (120.0 * FEET) as f32);
+    }
+}
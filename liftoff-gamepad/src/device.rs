@@ -0,0 +1,106 @@
+//! Finding the gamepad, and reading it into a [`ChannelMapper`].
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use evdev::{AbsoluteAxisCode, Device, EventSummary, KeyCode};
+use log::{info, warn};
+use tokio::sync::oneshot;
+
+use crate::mapping::ChannelMapper;
+
+/// Name of the uinput device of `crsf-joystick` and `crsf-forward
+/// --joystick`, which is never taken for a gamepad.
+const CRSF_JOYSTICK: &str = "CRSF Joystick";
+
+/// Whether `device` looks like a gamepad or joystick: it has sticks and
+/// gamepad or joystick buttons.
+fn is_gamepad(device: &Device) -> bool {
+    let has_sticks = device
+        .supported_absolute_axes()
+        .is_some_and(|axes| axes.contains(AbsoluteAxisCode::ABS_X));
+    let has_buttons = device.supported_keys().is_some_and(|keys| {
+        keys.contains(KeyCode::BTN_SOUTH) || keys.contains(KeyCode::BTN_TRIGGER)
+    });
+    has_sticks && has_buttons && device.name() != Some(CRSF_JOYSTICK)
+}
+
+/// The gamepads, with their paths.
+pub fn list() -> Vec<(PathBuf, Device)> {
+    let mut devices: Vec<_> = evdev::enumerate()
+        .filter(|(_, device)| is_gamepad(device))
+        .collect();
+    devices.sort_by(|a, b| a.0.cmp(&b.0));
+    devices
+}
+
+/// The device at `name` if it is a path, the one of that name otherwise,
+/// or the first gamepad if there is no name.
+pub fn find(name: Option<&str>) -> Result<(PathBuf, Device), String> {
+    match name {
+        Some(path) if path.starts_with('/') => Device::open(path)
+            .map(|device| (PathBuf::from(path), device))
+            .map_err(|e| format!("{}: {}", path, e)),
+        Some(name) => evdev::enumerate()
+            .find(|(_, device)| device.name() == Some(name))
+            .ok_or_else(|| format!("no input device named {:?}", name)),
+        None => list()
+            .into_iter()
+            .next()
+            .ok_or_else(|| "no gamepad found; see --list".to_string()),
+    }
+}
+
+/// Read `device` into `mapper` on a thread of its own, as reading the
+/// device blocks. The receiver gets the error that stops it.
+pub fn spawn(
+    path: PathBuf,
+    mut device: Device,
+    mapper: Arc<Mutex<ChannelMapper>>,
+) -> std::io::Result<oneshot::Receiver<std::io::Error>> {
+    {
+        let mut mapper = mapper.lock().unwrap_or_else(PoisonError::into_inner);
+        let absinfo: Vec<_> = device.get_absinfo()?.collect();
+        let axes: Vec<_> = mapper.axes().collect();
+        for code in axes {
+            match absinfo.iter().find(|(axis, _)| *axis == code) {
+                Some((_, info)) => {
+                    mapper.set_range(code, info.minimum(), info.maximum());
+                    mapper.axis(code, info.value());
+                }
+                None => warn!("{} has no axis {:?}", path.display(), code),
+            }
+        }
+        let keys = device.get_key_state()?;
+        for key in keys.iter() {
+            mapper.button(key, true);
+        }
+    }
+    info!(
+        "Reading {} ({})",
+        device.name().unwrap_or("?"),
+        path.display()
+    );
+
+    let (tx, rx) = oneshot::channel();
+    std::thread::spawn(move || {
+        loop {
+            let events = match device.fetch_events() {
+                Ok(events) => events,
+                Err(e) => {
+                    tx.send(e).ok();
+                    return;
+                }
+            };
+            let mut mapper = mapper.lock().unwrap_or_else(PoisonError::into_inner);
+            for event in events {
+                match event.destructure() {
+                    EventSummary::AbsoluteAxis(_, code, value) => mapper.axis(code, value),
+                    EventSummary::Key(_, code, value) => mapper.button(code, value != 0),
+                    _ => {}
+                }
+            }
+        }
+    });
+    Ok(rx)
+}
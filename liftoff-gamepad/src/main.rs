@@ -0,0 +1,149 @@
+//! A local gamepad or joystick → CRSF RC frames, as a radio sends them.
+//!
+//! Reads the gamepad through evdev, maps its axes and buttons to the 16
+//! RC channels (see [`mapping`]), and sends RcChannelsPacked frames at a
+//! steady rate over UDP, a serial port, or both. Without a radio, this
+//! drives `crsf-forward` (through a serial port or a pty pair) and
+//! everything after it.
+
+use clap::Parser;
+use log::info;
+use std::path::PathBuf;
+
+#[cfg(target_os = "linux")]
+mod device;
+#[cfg(target_os = "linux")]
+mod mapping;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Gamepad to read: its name, or a /dev/input/event* path. By default
+    /// the first one found.
+    #[arg(long)]
+    device: Option<String>,
+
+    /// List the gamepads and exit.
+    #[arg(long, default_value_t = false)]
+    list: bool,
+
+    /// Mapping of the axes and buttons to channels (JSON). By default that
+    /// of a mode 2 gamepad.
+    #[arg(long)]
+    mapping: Option<PathBuf>,
+
+    /// Send the RC frames to this address, one per datagram.
+    #[arg(long)]
+    udp: Option<std::net::SocketAddr>,
+
+    /// Write the RC frames to this serial port.
+    #[arg(long)]
+    serial: Option<String>,
+
+    /// Serial port speed.
+    #[arg(long, default_value_t = 420000)]
+    baud: u32,
+
+    /// RC frames per second.
+    #[arg(long, default_value_t = 150)]
+    rate: u32,
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    Err("liftoff-gamepad is only supported on Linux".into())
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    use log::warn;
+    use mapping::{ChannelMapper, MappingConfig};
+    use std::sync::{Arc, Mutex, PoisonError};
+    use std::time::Duration;
+    use telemetry_lib::crsf::{CrsfPacket, RcChannelsPacked, build_packet, device_address};
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::UdpSocket;
+    use tokio_serial::SerialPortBuilderExt;
+
+    env_logger::init();
+    let args = Args::parse();
+
+    if args.list {
+        for (path, device) in device::list() {
+            println!("{}: {}", path.display(), device.name().unwrap_or("?"));
+        }
+        return Ok(());
+    }
+
+    info!("Starting liftoff-gamepad");
+
+    if args.udp.is_none() && args.serial.is_none() {
+        return Err("nothing to send to: give --udp, --serial or both".into());
+    }
+
+    let config = match args.mapping {
+        Some(ref path) => {
+            info!("Loading mapping from {}", path.display());
+            MappingConfig::load(path).map_err(|e| format!("{}: {}", path.display(), e))?
+        }
+        None => MappingConfig::default(),
+    };
+    let mapper = Arc::new(Mutex::new(ChannelMapper::new(&config)?));
+
+    let (path, gamepad) = device::find(args.device.as_deref())?;
+    let mut stopped = device::spawn(path.clone(), gamepad, mapper.clone())?;
+
+    let udp = match args.udp {
+        Some(addr) => {
+            let bind: std::net::SocketAddr = if addr.is_ipv4() {
+                ([0, 0, 0, 0], 0).into()
+            } else {
+                ([0u16; 8], 0).into()
+            };
+            let socket = UdpSocket::bind(bind).await?;
+            socket.connect(addr).await?;
+            info!("Sending to {}", addr);
+            Some(socket)
+        }
+        None => None,
+    };
+    let mut serial = match args.serial {
+        Some(ref port) => {
+            let stream = tokio_serial::new(port, args.baud)
+                .open_native_async()
+                .map_err(|e| format!("{}: {}", port, e))?;
+            info!("Writing to {} at {} baud", port, args.baud);
+            Some(stream)
+        }
+        None => None,
+    };
+
+    let mut interval = tokio::time::interval(Duration::from_secs(1) / args.rate.max(1));
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                let channels = mapper.lock().unwrap_or_else(PoisonError::into_inner).channels();
+                let packet = CrsfPacket::RcChannelsPacked(RcChannelsPacked { channels });
+                let frame = build_packet(device_address::FLIGHT_CONTROLLER, &packet)
+                    .expect("RC frame");
+                if let Some(ref socket) = udp
+                    && let Err(e) = socket.send(&frame).await
+                {
+                    warn!("UDP send error: {}", e);
+                }
+                if let Some(ref mut port) = serial {
+                    port.write_all(&frame).await?;
+                }
+            }
+            e = &mut stopped => {
+                let e = e.map_or_else(|_| "stopped".to_string(), |e| e.to_string());
+                return Err(format!("{}: {}", path.display(), e).into());
+            }
+            _ = tokio::signal::ctrl_c() => {
+                info!("Shutdown signal received, exiting.");
+                return Ok(());
+            }
+        }
+    }
+}
@@ -0,0 +1,307 @@
+//! Gamepad axes and buttons → 16 CRSF RC channels.
+//!
+//! Loaded from a JSON file (`--mapping`). Axes and buttons are named as
+//! evdev names them (`evtest` lists them); each drives one channel. A
+//! button is high while pressed, or with `toggle`, flips the channel on
+//! each press. Channels nothing drives stay centered if they are sticks
+//! (0-3), and low otherwise.
+//!
+//! ```json
+//! {
+//!   "axes": [
+//!     { "axis": "ABS_RX", "channel": 0, "deadband": 0.05 },
+//!     { "axis": "ABS_RY", "channel": 1, "reverse": true, "deadband": 0.05 },
+//!     { "axis": "ABS_Y", "channel": 2, "reverse": true },
+//!     { "axis": "ABS_X", "channel": 3, "deadband": 0.05 }
+//!   ],
+//!   "buttons": [
+//!     { "button": "BTN_START", "channel": 4, "toggle": true }
+//!   ]
+//! }
+//! ```
+//!
+//! Without a file, the mapping is that of a mode 2 gamepad, as above, with
+//! the right trigger on channel 6, and BTN_SELECT (toggled) and BTN_SOUTH
+//! on channels 5 and 7.
+
+use std::io;
+use std::path::Path;
+
+use evdev::{AbsoluteAxisCode, KeyCode};
+use serde::{Deserialize, Serialize};
+use telemetry_lib::crsf;
+
+/// Number of CRSF RC channels.
+pub const NUM_CHANNELS: usize = 16;
+/// Channels that are sticks, and rest centered.
+const STICKS: usize = 4;
+
+const LOW_US: u16 = 1000;
+const MID_US: u16 = 1500;
+const HIGH_US: u16 = 2000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AxisMapping {
+    /// Name of the axis, e.g. `ABS_X`.
+    pub axis: String,
+    pub channel: usize,
+    /// Low end of the axis to the high end of the channel.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub reverse: bool,
+    /// Part of the range on either side of the center that counts as
+    /// centered, from 0 to 1.
+    #[serde(default)]
+    pub deadband: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ButtonMapping {
+    /// Name of the button, e.g. `BTN_SOUTH`.
+    pub button: String,
+    pub channel: usize,
+    /// Flip the channel on each press, instead of holding it high while
+    /// pressed.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub toggle: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MappingConfig {
+    pub axes: Vec<AxisMapping>,
+    pub buttons: Vec<ButtonMapping>,
+}
+
+impl Default for MappingConfig {
+    fn default() -> Self {
+        let axis = |axis: &str, channel, reverse, deadband| AxisMapping {
+            axis: axis.to_string(),
+            channel,
+            reverse,
+            deadband,
+        };
+        let button = |button: &str, channel, toggle| ButtonMapping {
+            button: button.to_string(),
+            channel,
+            toggle,
+        };
+        Self {
+            axes: vec![
+                axis("ABS_RX", 0, false, 0.05),
+                axis("ABS_RY", 1, true, 0.05),
+                axis("ABS_Y", 2, true, 0.0),
+                axis("ABS_X", 3, false, 0.05),
+                axis("ABS_RZ", 6, false, 0.0),
+            ],
+            buttons: vec![
+                button("BTN_START", 4, true),
+                button("BTN_SELECT", 5, true),
+                button("BTN_SOUTH", 7, false),
+            ],
+        }
+    }
+}
+
+impl MappingConfig {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let data = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Axis {
+    code: AbsoluteAxisCode,
+    mapping: AxisMapping,
+    min: i32,
+    max: i32,
+}
+
+#[derive(Debug, Clone)]
+struct Button {
+    code: KeyCode,
+    mapping: ButtonMapping,
+    pressed: bool,
+}
+
+/// The channels, as the axes and buttons of a [`MappingConfig`] move.
+#[derive(Debug, Clone)]
+pub struct ChannelMapper {
+    axes: Vec<Axis>,
+    buttons: Vec<Button>,
+    /// µs.
+    channels: [u16; NUM_CHANNELS],
+}
+
+fn check_channel(channel: usize, what: &str) -> Result<(), String> {
+    if channel >= NUM_CHANNELS {
+        return Err(format!("{}: invalid channel {}", what, channel));
+    }
+    Ok(())
+}
+
+impl ChannelMapper {
+    pub fn new(config: &MappingConfig) -> Result<Self, String> {
+        let mut axes = Vec::new();
+        for mapping in &config.axes {
+            let code = mapping
+                .axis
+                .parse()
+                .map_err(|_| format!("unknown axis {:?}", mapping.axis))?;
+            check_channel(mapping.channel, &mapping.axis)?;
+            if !(0.0..1.0).contains(&mapping.deadband) {
+                return Err(format!("{}: deadband must be from 0 to 1", mapping.axis));
+            }
+            axes.push(Axis {
+                code,
+                mapping: mapping.clone(),
+                min: 0,
+                max: 0,
+            });
+        }
+        let mut buttons = Vec::new();
+        for mapping in &config.buttons {
+            let code = mapping
+                .button
+                .parse()
+                .map_err(|_| format!("unknown button {:?}", mapping.button))?;
+            check_channel(mapping.channel, &mapping.button)?;
+            buttons.push(Button {
+                code,
+                mapping: mapping.clone(),
+                pressed: false,
+            });
+        }
+        let mut channels = [LOW_US; NUM_CHANNELS];
+        channels[..STICKS].fill(MID_US);
+        Ok(Self {
+            axes,
+            buttons,
+            channels,
+        })
+    }
+
+    /// Axes of the mapping.
+    pub fn axes(&self) -> impl Iterator<Item = AbsoluteAxisCode> + '_ {
+        self.axes.iter().map(|axis| axis.code)
+    }
+
+    /// Set the range of an axis, as the device reports it. Axes without
+    /// one stay centered.
+    pub fn set_range(&mut self, code: AbsoluteAxisCode, min: i32, max: i32) {
+        for axis in self.axes.iter_mut().filter(|axis| axis.code == code) {
+            axis.min = min;
+            axis.max = max;
+        }
+    }
+
+    /// The axis `code` moved to `value`.
+    pub fn axis(&mut self, code: AbsoluteAxisCode, value: i32) {
+        for axis in self.axes.iter().filter(|axis| axis.code == code) {
+            if axis.max <= axis.min {
+                continue;
+            }
+            // From -1 to 1.
+            let span = f64::from(axis.max) - f64::from(axis.min);
+            let mut x = (f64::from(value.clamp(axis.min, axis.max)) - f64::from(axis.min)) / span
+                * 2.0
+                - 1.0;
+            if axis.mapping.reverse {
+                x = -x;
+            }
+            let deadband = axis.mapping.deadband;
+            x = if x.abs() <= deadband {
+                0.0
+            } else {
+                x.signum() * (x.abs() - deadband) / (1.0 - deadband)
+            };
+            self.channels[axis.mapping.channel] =
+                (f64::from(MID_US) + x * f64::from(HIGH_US - MID_US)).round() as u16;
+        }
+    }
+
+    /// The button `code` was pressed or released.
+    pub fn button(&mut self, code: KeyCode, pressed: bool) {
+        for button in self.buttons.iter_mut().filter(|button| button.code == code) {
+            let channel = &mut self.channels[button.mapping.channel];
+            if !button.mapping.toggle {
+                *channel = if pressed { HIGH_US } else { LOW_US };
+            } else if pressed && !button.pressed {
+                *channel = if *channel > MID_US { LOW_US } else { HIGH_US };
+            }
+            button.pressed = pressed;
+        }
+    }
+
+    /// The channels, in CRSF ticks.
+    pub fn channels(&self) -> [u16; NUM_CHANNELS] {
+        self.channels.map(crsf::us_to_ticks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mapper() {
+        let config: MappingConfig = serde_json::from_str(
+            r#"{
+                "axes": [
+                    { "axis": "ABS_X", "channel": 0, "deadband": 0.1 },
+                    { "axis": "ABS_Y", "channel": 2, "reverse": true }
+                ],
+                "buttons": [
+                    { "button": "BTN_START", "channel": 4, "toggle": true },
+                    { "button": "BTN_SOUTH", "channel": 7 }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let mut mapper = ChannelMapper::new(&config).unwrap();
+        assert_eq!(
+            mapper.channels[..8],
+            [1500, 1500, 1500, 1500, 1000, 1000, 1000, 1000]
+        );
+
+        // No range yet.
+        mapper.axis(AbsoluteAxisCode::ABS_X, 100);
+        assert_eq!(mapper.channels[0], 1500);
+
+        mapper.set_range(AbsoluteAxisCode::ABS_X, -100, 100);
+        mapper.set_range(AbsoluteAxisCode::ABS_Y, 0, 255);
+        mapper.axis(AbsoluteAxisCode::ABS_X, 100);
+        assert_eq!(mapper.channels[0], 2000);
+        mapper.axis(AbsoluteAxisCode::ABS_X, -5);
+        assert_eq!(mapper.channels[0], 1500);
+        mapper.axis(AbsoluteAxisCode::ABS_X, -55);
+        assert_eq!(mapper.channels[0], 1250);
+        mapper.axis(AbsoluteAxisCode::ABS_Y, 0);
+        assert_eq!(mapper.channels[2], 2000);
+        mapper.axis(AbsoluteAxisCode::ABS_Y, 255);
+        assert_eq!(mapper.channels[2], 1000);
+
+        mapper.button(KeyCode::BTN_START, true);
+        mapper.button(KeyCode::BTN_START, true);
+        mapper.button(KeyCode::BTN_SOUTH, true);
+        assert_eq!((mapper.channels[4], mapper.channels[7]), (2000, 2000));
+        mapper.button(KeyCode::BTN_START, false);
+        mapper.button(KeyCode::BTN_SOUTH, false);
+        assert_eq!((mapper.channels[4], mapper.channels[7]), (2000, 1000));
+        mapper.button(KeyCode::BTN_START, true);
+        assert_eq!(mapper.channels[4], 1000);
+        assert_eq!(mapper.channels()[4], crsf::us_to_ticks(1000));
+
+        assert!(ChannelMapper::new(&MappingConfig::default()).is_ok());
+        let bad = MappingConfig {
+            axes: vec![AxisMapping {
+                axis: "ABS_NOPE".into(),
+                channel: 0,
+                reverse: false,
+                deadband: 0.0,
+            }],
+            buttons: Vec::new(),
+        };
+        assert!(ChannelMapper::new(&bad).is_err());
+    }
+}
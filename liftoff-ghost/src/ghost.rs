@@ -0,0 +1,218 @@
+//! The state of a pilot's quad, as exchanged between the pilots.
+//!
+//! A datagram is `LFGH`, the version, the pilot name as a length byte and
+//! UTF-8, then a u32 sequence number and the sim time, position, attitude
+//! and velocity, as in the sim telemetry (f32). All little-endian. With a
+//! room key, the HMAC-SHA256 of all that by the key follows.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use ring::hmac;
+
+use telemetry_lib::telemetry::TelemetryPacket;
+
+const MAGIC: &[u8; 4] = b"LFGH";
+const VERSION: u8 = 1;
+/// Longest pilot name, bytes.
+pub const MAX_NAME: usize = 32;
+/// A pilot quiet for this long may start over with any sequence number,
+/// as after a restart.
+const RESTART: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ghost {
+    pub name: String,
+    /// Counts up with each state a pilot sends, to drop the ones that
+    /// come late.
+    pub sequence: u32,
+    pub time: f32,
+    pub position: [f32; 3],
+    pub attitude: [f32; 4],
+    pub velocity: [f32; 3],
+}
+
+/// Whether `name` is a pilot name: letters, digits, `-` and `_`, as it
+/// goes in a topic.
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+impl Ghost {
+    /// The state in `packet`, if it has a position.
+    pub fn from_packet(name: &str, sequence: u32, packet: &TelemetryPacket) -> Option<Self> {
+        Some(Self {
+            name: name.to_string(),
+            sequence,
+            time: packet.timestamp.unwrap_or_default(),
+            position: packet.position?,
+            attitude: packet.attitude.unwrap_or([0.0, 0.0, 0.0, 1.0]),
+            velocity: packet.velocity.unwrap_or_default(),
+        })
+    }
+
+    /// The state as sim telemetry.
+    pub fn packet(&self) -> TelemetryPacket {
+        TelemetryPacket {
+            timestamp: Some(self.time),
+            position: Some(self.position),
+            attitude: Some(self.attitude),
+            velocity: Some(self.velocity),
+            gyro: None,
+            input: None,
+            battery: None,
+            motor_rpm: None,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(6 + self.name.len() + 48);
+        data.extend_from_slice(MAGIC);
+        data.push(VERSION);
+        data.push(self.name.len() as u8);
+        data.extend_from_slice(self.name.as_bytes());
+        data.extend_from_slice(&self.sequence.to_le_bytes());
+        let floats = std::iter::once(&self.time)
+            .chain(&self.position)
+            .chain(&self.attitude)
+            .chain(&self.velocity);
+        for value in floats {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let rest = data.strip_prefix(MAGIC)?.strip_prefix(&[VERSION])?;
+        let (&len, rest) = rest.split_first()?;
+        let (name, rest) = rest.split_at_checked(len as usize)?;
+        let name = std::str::from_utf8(name).ok().filter(|n| valid_name(n))?;
+        let (sequence, rest) = rest.split_first_chunk::<4>()?;
+        if rest.len() != 11 * 4 {
+            return None;
+        }
+        let mut floats = rest
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+        let mut next = || floats.next().unwrap_or_default();
+        Some(Self {
+            name: name.to_string(),
+            sequence: u32::from_le_bytes(*sequence),
+            time: next(),
+            position: [next(), next(), next()],
+            attitude: [next(), next(), next(), next()],
+            velocity: [next(), next(), next()],
+        })
+    }
+}
+
+/// Shortest room key, bytes.
+pub const MIN_KEY: usize = 32;
+/// Length of the HMAC after a datagram.
+const TAG: usize = 32;
+
+/// The key the pilots of a room, and its relay, share.
+pub struct RoomKey(hmac::Key);
+
+impl RoomKey {
+    pub fn new(key: &[u8]) -> Self {
+        Self(hmac::Key::new(hmac::HMAC_SHA256, key))
+    }
+
+    /// `data` with its HMAC after it.
+    pub fn sign(&self, mut data: Vec<u8>) -> Vec<u8> {
+        let tag = hmac::sign(&self.0, &data);
+        data.extend_from_slice(tag.as_ref());
+        data
+    }
+
+    /// The datagram in `data`, if its HMAC is right.
+    pub fn verify<'a>(&self, data: &'a [u8]) -> Option<&'a [u8]> {
+        let (data, tag) = data.split_at_checked(data.len().checked_sub(TAG)?)?;
+        hmac::verify(&self.0, data, tag).ok()?;
+        Some(data)
+    }
+}
+
+/// The latest state of each pilot, to drop the states that come late.
+#[derive(Default)]
+pub struct Latest {
+    /// The sequence number of each pilot, and when it came.
+    pilots: HashMap<String, (u32, Instant)>,
+}
+
+impl Latest {
+    /// Whether `ghost`, come at `now`, is newer than the last state of its
+    /// pilot.
+    pub fn accept(&mut self, ghost: &Ghost, now: Instant) -> bool {
+        if let Some(&(sequence, last)) = self.pilots.get(&ghost.name)
+            && (ghost.sequence.wrapping_sub(sequence) as i32) <= 0
+            && now.saturating_duration_since(last) < RESTART
+        {
+            return false;
+        }
+        self.pilots
+            .insert(ghost.name.clone(), (ghost.sequence, now));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let ghost = Ghost {
+            name: "pilot_2".into(),
+            sequence: 7,
+            time: 12.5,
+            position: [1.0, 2.0, 3.0],
+            attitude: [0.0, 0.5, 0.0, 0.866],
+            velocity: [4.0, 0.0, -4.0],
+        };
+        let data = ghost.encode();
+        assert_eq!(data.len(), 6 + 7 + 4 + 44);
+        assert_eq!(Ghost::decode(&data), Some(ghost.clone()));
+        assert_eq!(Ghost::decode(&data[..data.len() - 1]), None);
+        let mut other = data.clone();
+        other[4] = 2;
+        assert_eq!(Ghost::decode(&other), None);
+
+        let packet = ghost.packet();
+        assert_eq!(
+            Ghost::from_packet("pilot_2", 7, &packet),
+            Some(ghost.clone())
+        );
+        assert!(valid_name("a-b_9"));
+        assert!(!valid_name("a/b"));
+        assert!(!valid_name("*"));
+        assert!(!valid_name(""));
+        assert!(!valid_name(&"x".repeat(MAX_NAME + 1)));
+
+        let mut latest = Latest::default();
+        let now = Instant::now();
+        let state = |sequence: u32| Ghost {
+            sequence,
+            ..ghost.clone()
+        };
+        assert!(latest.accept(&state(7), now));
+        assert!(!latest.accept(&state(6), now));
+        assert!(!latest.accept(&state(7), now));
+        assert!(latest.accept(&state(8), now));
+        assert!(latest.accept(&state(0), now + RESTART));
+        assert!(latest.accept(&state(u32::MAX), now + 2 * RESTART));
+        assert!(latest.accept(&state(0), now + 2 * RESTART));
+
+        let key = RoomKey::new(&[1; MIN_KEY]);
+        let signed = key.sign(data.clone());
+        assert_eq!(key.verify(&signed), Some(&data[..]));
+        assert_eq!(key.verify(&data), None);
+        assert_eq!(RoomKey::new(&[2; MIN_KEY]).verify(&signed), None);
+        assert_eq!(key.verify(&signed[..TAG - 1]), None);
+    }
+}
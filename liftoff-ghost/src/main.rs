@@ -0,0 +1,320 @@
+use clap::{Parser, Subcommand};
+use log::{debug, info, warn};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use telemetry_lib::telemetry;
+use telemetry_lib::topics;
+use tokio::net::UdpSocket;
+use tokio::time::Duration;
+use zenoh::Config;
+
+mod ghost;
+mod relay;
+
+use ghost::{Ghost, Latest, RoomKey};
+use relay::Relay;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+
+    /// File with the room key the pilots and the relay share, which the
+    /// states are signed with: a random key of at least 32 bytes, e.g.
+    /// from `openssl rand -hex 32`. The relay needs it.
+    #[arg(long, global = true)]
+    room_key_file: Option<PathBuf>,
+
+    /// Zenoh connect endpoint (e.g. tcp/192.168.1.1:7447). Omit for peer discovery.
+    #[arg(long, global = true)]
+    zenoh_connect: Option<String>,
+
+    /// Zenoh mode (peer or client).
+    #[arg(long, global = true, default_value = "client")]
+    zenoh_mode: String,
+
+    /// Zenoh topic prefix.
+    #[arg(long, global = true, default_value = topics::DEFAULT_PREFIX)]
+    zenoh_prefix: String,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Send the state of the quad to the other pilots, and publish theirs
+    /// as telemetry on `ghost/NAME`, until interrupted.
+    Pilot {
+        /// The pilot's name: letters, digits, `-` and `_`.
+        #[arg(long, value_parser = parse_name)]
+        name: String,
+
+        /// Send to and receive from this address.
+        #[arg(long, default_value = "0.0.0.0:9030")]
+        bind: SocketAddr,
+
+        /// Send the state to this pilot, or relay. Can be given more than
+        /// once.
+        #[arg(long)]
+        peer: Vec<SocketAddr>,
+
+        /// States sent per second, at most.
+        #[arg(long, default_value_t = 30.0, value_parser = parse_rate)]
+        rate: f64,
+    },
+    /// Pass the states of the pilots that send to it on to the others,
+    /// for pilots that can't reach each other directly.
+    Relay {
+        #[arg(long, default_value = "0.0.0.0:9030")]
+        bind: SocketAddr,
+
+        /// Pilots at a time, at most.
+        #[arg(long, default_value_t = 16)]
+        max_pilots: usize,
+    },
+}
+
+/// Parse a pilot name, for use as a clap value parser.
+fn parse_name(s: &str) -> Result<String, String> {
+    if ghost::valid_name(s) {
+        Ok(s.to_string())
+    } else {
+        Err(format!(
+            "invalid name `{}`, must be up to {} letters, digits, `-` and `_`",
+            s,
+            ghost::MAX_NAME
+        ))
+    }
+}
+
+/// Parse a send rate, for use as a clap value parser.
+fn parse_rate(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(hz) if hz > 0.0 && hz <= 1000.0 => Ok(hz),
+        _ => Err(format!(
+            "invalid rate `{}`, must be above 0 and up to 1000 Hz",
+            s
+        )),
+    }
+}
+
+/// Read the room key from `path`.
+fn read_key(path: &Path) -> Result<RoomKey, String> {
+    let key = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let key = key.trim().as_bytes();
+    if key.len() < ghost::MIN_KEY {
+        return Err(format!(
+            "{}: the room key is shorter than {} bytes; use a random key, e.g. from `openssl rand -hex 32`",
+            path.display(),
+            ghost::MIN_KEY
+        ));
+    }
+    Ok(RoomKey::new(key))
+}
+
+/// The ghost state in the datagram `data`, if it is signed by `key`.
+fn open(key: Option<&RoomKey>, data: &[u8]) -> Option<Ghost> {
+    match key {
+        Some(key) => Ghost::decode(key.verify(data)?),
+        None => Ghost::decode(data),
+    }
+}
+
+async fn pilot(
+    args: &Args,
+    key: Option<&RoomKey>,
+    name: &str,
+    socket: UdpSocket,
+    peers: &[SocketAddr],
+    rate: f64,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Zenoh session
+    let mut config = Config::default();
+    config.insert_json5("mode", &format!(r#""{}""#, args.zenoh_mode))?;
+    if let Some(ref endpoint) = args.zenoh_connect {
+        config.insert_json5("connect/endpoints", &format!(r#"["{}"]"#, endpoint))?;
+    }
+
+    let session = zenoh::open(config).await?;
+
+    let tel_topic = topics::topic(&args.zenoh_prefix, topics::TELEMETRY);
+    info!("Subscribing to: {}", tel_topic);
+    let tel_subscriber = session.declare_subscriber(&tel_topic).await?;
+    for peer in peers {
+        info!("Sending to {}", peer);
+    }
+
+    // Telemetry format config
+    // We assume default configuration for now
+    let format = telemetry::default_stream_format();
+    let interval = Duration::from_secs_f64(1.0 / rate);
+    let mut last_sent: Option<Instant> = None;
+    let mut sequence = 0u32;
+    let mut latest = Latest::default();
+    let mut publishers = HashMap::new();
+    let mut buf = [0u8; 1024];
+    loop {
+        tokio::select! {
+            sample = tel_subscriber.recv_async() => {
+                let sample = match sample {
+                    Ok(sample) => sample,
+                    Err(e) => {
+                        warn!("Sim telemetry subscriber error: {}", e);
+                        break;
+                    }
+                };
+                if last_sent.is_some_and(|last| last.elapsed() < interval) {
+                    continue;
+                }
+                let packet = match telemetry::parse_packet(&sample.payload().to_bytes(), &format) {
+                    Ok(packet) => packet,
+                    Err(e) => {
+                        debug!("Sim telemetry: {}", e);
+                        continue;
+                    }
+                };
+                let Some(state) = Ghost::from_packet(name, sequence, &packet) else {
+                    continue;
+                };
+                last_sent = Some(Instant::now());
+                sequence = sequence.wrapping_add(1);
+                let mut data = state.encode();
+                if let Some(key) = key {
+                    data = key.sign(data);
+                }
+                for peer in peers {
+                    if let Err(e) = socket.send_to(&data, peer).await {
+                        debug!("Send to {}: {}", peer, e);
+                    }
+                }
+            }
+            result = socket.recv_from(&mut buf) => {
+                // A send to a peer that is not up can come back as an
+                // error here.
+                let (len, from) = match result {
+                    Ok(received) => received,
+                    Err(e) => {
+                        debug!("Receive: {}", e);
+                        continue;
+                    }
+                };
+                let Some(state) = open(key, &buf[..len]) else {
+                    debug!("Not a ghost state of the room from {}: {} bytes", from, len);
+                    continue;
+                };
+                if state.name == name || !latest.accept(&state, Instant::now()) {
+                    continue;
+                }
+                if !publishers.contains_key(&state.name) {
+                    let topic = topics::topic(
+                        &args.zenoh_prefix,
+                        &format!("{}/{}", topics::GHOST, state.name),
+                    );
+                    info!("Pilot {} from {}, publishing on: {}", state.name, from, topic);
+                    let publisher = session.declare_publisher(topic).await?;
+                    publishers.insert(state.name.clone(), publisher);
+                }
+                let payload = telemetry::encode_packet(&state.packet(), &format)?;
+                if let Err(e) = publishers[&state.name].put(payload).await {
+                    warn!("Failed to publish ghost {}: {}", state.name, e);
+                }
+            }
+            _ = tokio::signal::ctrl_c() => {
+                info!("Shutdown signal received, exiting.");
+                break;
+            }
+        }
+    }
+
+    session.close().await?;
+    Ok(())
+}
+
+async fn relay(
+    key: &RoomKey,
+    socket: UdpSocket,
+    max_pilots: usize,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut relay = Relay::new(max_pilots);
+    // Not to pass on a state again, as from someone replaying it.
+    let mut latest = Latest::default();
+    let mut buf = [0u8; 1024];
+    loop {
+        tokio::select! {
+            result = socket.recv_from(&mut buf) => {
+                // A send to a peer that is not up can come back as an
+                // error here.
+                let (len, from) = match result {
+                    Ok(received) => received,
+                    Err(e) => {
+                        debug!("Receive: {}", e);
+                        continue;
+                    }
+                };
+                let Some(state) = open(Some(key), &buf[..len]) else {
+                    debug!("Not a ghost state of the room from {}: {} bytes", from, len);
+                    continue;
+                };
+                let now = Instant::now();
+                if !latest.accept(&state, now) {
+                    continue;
+                }
+                for to in relay.receive(from, now) {
+                    if let Err(e) = socket.send_to(&buf[..len], to).await {
+                        debug!("Send to {}: {}", to, e);
+                    }
+                }
+            }
+            _ = tokio::signal::ctrl_c() => {
+                info!("Shutdown signal received, exiting.");
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    env_logger::init();
+    let args = Args::parse();
+
+    info!("Starting liftoff-ghost");
+
+    let key = args.room_key_file.as_deref().map(read_key).transpose()?;
+
+    match args.command {
+        Command::Pilot {
+            ref name,
+            bind,
+            ref peer,
+            rate,
+        } => {
+            let socket = UdpSocket::bind(bind).await?;
+            info!("Pilot {} on {}", name, bind);
+            pilot(&args, key.as_ref(), name, socket, peer, rate).await?;
+        }
+        Command::Relay { bind, max_pilots } => {
+            let key = key.ok_or("give --room-key-file, with the key the pilots share")?;
+            let socket = UdpSocket::bind(bind).await?;
+            info!("Relaying on {}", bind);
+            relay(&key, socket, max_pilots).await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name() {
+        assert_eq!(parse_name("pilot-1"), Ok("pilot-1".to_string()));
+        assert!(parse_name("pilot 1").is_err());
+        assert!(parse_name("liftoff/**").is_err());
+        assert_eq!(parse_rate("10"), Ok(10.0));
+        assert!(parse_rate("0").is_err());
+    }
+}
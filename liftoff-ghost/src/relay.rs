@@ -0,0 +1,75 @@
+//! The relay: pilots that can't reach each other directly, behind NAT,
+//! send their states to it, and it passes each on to the other pilots.
+//! A pilot is known from its first state until it goes quiet; there are
+//! at most so many at a time.
+
+use log::{info, warn};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// A pilot is dropped when it sends nothing for this long.
+pub const PILOT_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct Relay {
+    /// When each pilot last sent something.
+    pilots: HashMap<SocketAddr, Instant>,
+    max_pilots: usize,
+}
+
+impl Relay {
+    pub fn new(max_pilots: usize) -> Self {
+        Self {
+            pilots: HashMap::new(),
+            max_pilots,
+        }
+    }
+
+    /// Take a state from `from` at `now`, and return where to pass it on:
+    /// nowhere for a new pilot while there are as many as there can be.
+    pub fn receive(&mut self, from: SocketAddr, now: Instant) -> Vec<SocketAddr> {
+        self.pilots
+            .retain(|_, &mut last| now.saturating_duration_since(last) < PILOT_TIMEOUT);
+        if !self.pilots.contains_key(&from) {
+            if self.pilots.len() >= self.max_pilots {
+                warn!("Pilot {} refused, {} already", from, self.pilots.len());
+                return Vec::new();
+            }
+            info!("Pilot {} joined, {} now", from, self.pilots.len() + 1);
+        }
+        self.pilots.insert(from, now);
+        self.pilots
+            .keys()
+            .copied()
+            .filter(|&addr| addr != from)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pass_on() {
+        let mut relay = Relay::new(3);
+        let addr = |port: u16| SocketAddr::from(([10, 0, 0, 1], port));
+        let start = Instant::now();
+        assert_eq!(relay.receive(addr(1), start), []);
+        assert_eq!(relay.receive(addr(2), start), [addr(1)]);
+        let mut targets = relay.receive(addr(3), start + Duration::from_secs(5));
+        targets.sort();
+        assert_eq!(targets, [addr(1), addr(2)]);
+        // 1 and 2 went quiet.
+        assert_eq!(relay.receive(addr(3), start + Duration::from_secs(12)), []);
+        assert_eq!(
+            relay.receive(addr(1), start + Duration::from_secs(13)),
+            [addr(3)]
+        );
+        // Full.
+        let later = start + Duration::from_secs(14);
+        assert_eq!(relay.receive(addr(2), later).len(), 2);
+        assert_eq!(relay.receive(addr(4), later), []);
+        assert_eq!(relay.receive(addr(3), later).len(), 2);
+    }
+}
@@ -0,0 +1,166 @@
+use clap::Parser;
+use log::{debug, info, warn};
+use std::net::SocketAddr;
+use telemetry_lib::crsf::{self, CrsfPacket};
+use telemetry_lib::topics;
+use tokio::net::UdpSocket;
+use zenoh::Config;
+
+mod opentrack;
+
+/// Channel value at the center, and how far it goes either way.
+const CENTER: f64 = 992.0;
+const HALF_SPAN: f64 = 820.0;
+const MIN: f64 = 172.0;
+const MAX: f64 = 1811.0;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Receive opentrack's "UDP over network" output on this address.
+    #[arg(long, default_value = "127.0.0.1:4242")]
+    bind: SocketAddr,
+
+    /// Channel (from 0) for the pan, turning the head left and right.
+    #[arg(long, default_value_t = 6, value_parser = parse_channel)]
+    pan_channel: usize,
+
+    /// Channel (from 0) for the tilt, looking up and down.
+    #[arg(long, default_value_t = 7, value_parser = parse_channel)]
+    tilt_channel: usize,
+
+    /// Pan at full deflection of the channel, degrees.
+    #[arg(long, default_value_t = 90.0)]
+    pan_range: f64,
+
+    /// Tilt at full deflection of the channel, degrees.
+    #[arg(long, default_value_t = 45.0)]
+    tilt_range: f64,
+
+    /// Reverse the pan channel.
+    #[arg(long, default_value_t = false)]
+    pan_reverse: bool,
+
+    /// Reverse the tilt channel.
+    #[arg(long, default_value_t = false)]
+    tilt_reverse: bool,
+
+    /// Zenoh connect endpoint (e.g. tcp/192.168.1.1:7447). Omit for peer discovery.
+    #[arg(long)]
+    zenoh_connect: Option<String>,
+
+    /// Zenoh mode (peer or client).
+    #[arg(long, default_value = "client")]
+    zenoh_mode: String,
+
+    /// Zenoh topic prefix.
+    #[arg(long, default_value = topics::DEFAULT_PREFIX)]
+    zenoh_prefix: String,
+}
+
+/// Parse an RC channel, for use as a clap value parser.
+fn parse_channel(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(ch) if ch < 16 => Ok(ch),
+        _ => Err(format!("invalid channel `{}`, must be 0 to 15", s)),
+    }
+}
+
+/// Channel value for `angle`, with `range` at full deflection.
+fn channel_value(angle: f64, range: f64, reverse: bool) -> u16 {
+    let deflection = if reverse { -angle } else { angle } / range;
+    (CENTER + deflection * HALF_SPAN).round().clamp(MIN, MAX) as u16
+}
+
+/// The RC channels for `pose`: the pan and tilt, and the rest centered.
+fn channels(args: &Args, pose: &opentrack::Pose) -> [u16; 16] {
+    let mut channels = [CENTER as u16; 16];
+    channels[args.pan_channel] = channel_value(pose.yaw, args.pan_range, args.pan_reverse);
+    channels[args.tilt_channel] = channel_value(pose.pitch, args.tilt_range, args.tilt_reverse);
+    channels
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    env_logger::init();
+    let args = Args::parse();
+
+    info!("Starting liftoff-headtracker");
+
+    if args.pan_channel == args.tilt_channel {
+        return Err("--pan-channel and --tilt-channel must differ".into());
+    }
+    if args.pan_range <= 0.0 || args.tilt_range <= 0.0 {
+        return Err("--pan-range and --tilt-range must be positive".into());
+    }
+
+    // Zenoh session
+    let mut config = Config::default();
+    config.insert_json5("mode", &format!(r#""{}""#, args.zenoh_mode))?;
+    if let Some(ref endpoint) = args.zenoh_connect {
+        config.insert_json5("connect/endpoints", &format!(r#"["{}"]"#, endpoint))?;
+    }
+
+    let session = zenoh::open(config).await?;
+
+    let rc_topic = topics::topic(&args.zenoh_prefix, topics::CRSF_RC_HEADTRACKER);
+    info!("Publishing on: {}", rc_topic);
+    let rc_publisher = session.declare_publisher(rc_topic).await?;
+
+    let socket = UdpSocket::bind(args.bind).await?;
+    info!("Listening for opentrack on {}", args.bind);
+    info!(
+        "Pan on channel {}, tilt on channel {}",
+        args.pan_channel, args.tilt_channel
+    );
+
+    let mut buf = [0u8; 1024];
+    loop {
+        let len = tokio::select! {
+            result = socket.recv(&mut buf) => result?,
+            _ = tokio::signal::ctrl_c() => break,
+        };
+        let Some(pose) = opentrack::parse_pose(&buf[..len]) else {
+            debug!("Not an opentrack pose: {} bytes", len);
+            continue;
+        };
+        let channels = channels(&args, &pose);
+        let rc_packet = CrsfPacket::RcChannelsPacked(crsf::RcChannelsPacked { channels });
+        let frame = crsf::build_packet(crsf::device_address::FLIGHT_CONTROLLER, &rc_packet)
+            .expect("channel values out of range");
+        if let Err(e) = rc_publisher.put(frame).await {
+            warn!("Publish error: {}", e);
+        }
+    }
+    info!("Shutdown signal received, exiting.");
+
+    session.close().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pan_tilt() {
+        assert_eq!(channel_value(0.0, 90.0, false), 992);
+        assert_eq!(channel_value(45.0, 90.0, false), 1402);
+        assert_eq!(channel_value(45.0, 90.0, true), 582);
+        assert_eq!(channel_value(180.0, 90.0, false), 1811);
+        assert_eq!(channel_value(-180.0, 90.0, false), 172);
+
+        let args = Args::parse_from(["liftoff-headtracker", "--tilt-channel", "9"]);
+        let pose = opentrack::Pose {
+            position: [0.0; 3],
+            yaw: -90.0,
+            pitch: 45.0,
+            roll: 10.0,
+        };
+        let channels = channels(&args, &pose);
+        assert_eq!(channels[6], 172);
+        assert_eq!(channels[9], 1811);
+        assert_eq!(channels[7], 992);
+        assert!(parse_channel("16").is_err());
+    }
+}
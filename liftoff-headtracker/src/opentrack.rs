@@ -0,0 +1,58 @@
+//! opentrack's "UDP over network" output: a pose per datagram, as six
+//! little-endian doubles, x, y and z (cm) and yaw, pitch and roll
+//! (degrees), sometimes followed by a frame number.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pose {
+    /// Position, cm.
+    pub position: [f64; 3],
+    /// Turned right, degrees.
+    pub yaw: f64,
+    /// Looking up, degrees.
+    pub pitch: f64,
+    /// Tilted right, degrees.
+    pub roll: f64,
+}
+
+/// Length of a pose, bytes.
+pub const POSE_LEN: usize = 48;
+
+pub fn parse_pose(data: &[u8]) -> Option<Pose> {
+    let data = data.get(..POSE_LEN)?;
+    let mut values = data
+        .chunks_exact(8)
+        .map(|b| f64::from_le_bytes(b.try_into().unwrap()));
+    let mut next = || values.next().filter(|v| v.is_finite());
+    Some(Pose {
+        position: [next()?, next()?, next()?],
+        yaw: next()?,
+        pitch: next()?,
+        roll: next()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pose() {
+        let mut data: Vec<u8> = [1.0f64, 2.0, 3.0, -30.0, 10.0, 0.5]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let pose = Pose {
+            position: [1.0, 2.0, 3.0],
+            yaw: -30.0,
+            pitch: 10.0,
+            roll: 0.5,
+        };
+        assert_eq!(parse_pose(&data), Some(pose));
+        // With a frame number.
+        data.extend_from_slice(&7u32.to_le_bytes());
+        assert_eq!(parse_pose(&data), Some(pose));
+        assert_eq!(parse_pose(&data[..40]), None);
+        data[24..32].copy_from_slice(&f64::NAN.to_le_bytes());
+        assert_eq!(parse_pose(&data), None);
+    }
+}
@@ -9,14 +9,26 @@
 //! No RC channel handling — the virtual joystick lives in `crsf-joystick/`
 //! now. This binary is purely Liftoff → Zenoh; for Velocidrone or
 //! Uncrashed, run their respective `*-input` crate instead.
+//!
+//! CRSF frames are only generated while sim telemetry arrives. When none
+//! has been received for `--telemetry-timeout-ms` (game closed, race
+//! ended), the damage frames stop as well, so the radio's sensors time out
+//! and show telemetry as lost. With `--no-data-mode`, a FlightMode frame
+//! with that text is sent once per second instead, until telemetry
+//! resumes.
+//!
+//! The sim telemetry also goes through the event rules (`--event-rule`),
+//! and the events they find, a crash for one, are published for the
+//! logger, audio, OSD and overlay to mark.
 use clap::Parser;
 use log::{error, info, trace, warn};
 use metrics::{Unit, counter, describe_counter};
 use metrics_exporter_tcp::TcpBuilder;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use telemetry_lib::crsf_custom;
 use telemetry_lib::crsf_tx;
+use telemetry_lib::events::{self, Detector, Rule};
 use telemetry_lib::simstate::{self, BatteryPacket, DamagePacket, SimstatePacket};
 use telemetry_lib::telemetry::{self};
 use telemetry_lib::topics;
@@ -55,10 +67,33 @@ struct Args {
     /// Bind address for metrics-rs-tcp-exporter.
     #[arg(long, default_value = "127.0.0.1:5002")]
     metrics_tcp_bind: std::net::SocketAddr,
+
+    /// Consider sim telemetry lost after this many milliseconds without
+    /// a packet.
+    #[arg(long, default_value_t = 1000)]
+    telemetry_timeout_ms: u64,
+
+    /// Flight mode text (e.g. "!TEL") to send while sim telemetry is lost.
+    #[arg(long)]
+    no_data_mode: Option<String>,
+
+    /// Rule of an event to publish, as name=metric>value or
+    /// name=metric<value, with more conditions after a comma; e.g.
+    /// "crash=deceleration>60". Can be given more than once. Metrics:
+    /// speed (m/s), deceleration (m/s²), altitude (m), throttle (0-1),
+    /// throttle_rate (/s), voltage (V), sag (0-1 below the voltage at rest)
+    /// and charge (0-1). Without it: crash, punch and sag.
+    #[arg(long = "event-rule", value_name = "RULE", value_parser = Rule::parse)]
+    event_rules: Vec<Rule>,
+
+    /// Don't look for events.
+    #[arg(long, default_value_t = false, conflicts_with = "event_rules")]
+    no_events: bool,
 }
 
 const TELEMETRY_INTERVAL: Duration = Duration::from_millis(100);
 const DAMAGE_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+const NO_DATA_INTERVAL: Duration = Duration::from_secs(1);
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
@@ -104,6 +139,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         Unit::Count,
         "Battery UDP packets received from simstate-bridge"
     );
+    describe_counter!(
+        "input.telemetry.lost",
+        Unit::Count,
+        "Times sim telemetry timed out"
+    );
+    describe_counter!("input.events.tx", Unit::Count, "Events published");
     describe_counter!(
         "simstate.parse_error",
         Unit::Count,
@@ -225,16 +266,62 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 
     // Telemetry format config
     // We assume default configuration for now
-    let config_format = vec![
-        "Timestamp".to_string(),
-        "Position".to_string(),
-        "Attitude".to_string(),
-        "Velocity".to_string(),
-        "Gyro".to_string(),
-        "Input".to_string(),
-        "Battery".to_string(),
-        "MotorRPM".to_string(),
-    ];
+    let config_format = telemetry::default_stream_format();
+
+    // Task: Look for events in every telemetry packet, and publish them.
+    if !args.no_events {
+        let rules = if args.event_rules.is_empty() {
+            events::DEFAULT_RULES
+                .iter()
+                .map(|rule| Rule::parse(rule))
+                .collect::<Result<Vec<_>, String>>()?
+        } else {
+            args.event_rules.clone()
+        };
+        let events_topic = topics::topic(&args.zenoh_prefix, topics::EVENTS);
+        info!("Publishing on: {}", events_topic);
+        let events_publisher = session.declare_publisher(events_topic).await?;
+        let events_subscriber = session.declare_subscriber(&tel_topic).await?;
+        let format = config_format.clone();
+        tokio::spawn(async move {
+            let mut detector = Detector::new(rules);
+            // For telemetry without a timestamp.
+            let epoch = Instant::now();
+            loop {
+                match events_subscriber.recv_async().await {
+                    Ok(sample) => {
+                        let payload = sample.payload().to_bytes();
+                        let Ok(packet) = telemetry::parse_packet(&payload, &format) else {
+                            continue;
+                        };
+                        let time = match packet.timestamp {
+                            Some(timestamp) => f64::from(timestamp),
+                            None => epoch.elapsed().as_secs_f64(),
+                        };
+                        for event in detector.update(time, &packet) {
+                            info!("Event {} at {:.2} s", event.name, event.time);
+                            let json = match serde_json::to_vec(&event) {
+                                Ok(json) => json,
+                                Err(e) => {
+                                    warn!("Failed to encode event: {}", e);
+                                    continue;
+                                }
+                            };
+                            if let Err(e) = events_publisher.put(json).await {
+                                warn!("Failed to publish event: {}", e);
+                            } else {
+                                counter!("input.events.tx").increment(1);
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        warn!("Events telemetry subscriber error: {}", e);
+                        break;
+                    }
+                }
+            }
+        });
+    }
 
     // Task: Receive raw telemetry from bridge, convert to CRSF, publish.
     // Also listens for damage-change notifications to send an immediate
@@ -243,10 +330,24 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let crsf_battery_state = battery_state.clone();
     let crsf_damage_state = damage_state.clone();
     let crsf_damage_notify = damage_notify.clone();
+    let telemetry_timeout = Duration::from_millis(args.telemetry_timeout_ms);
+    let no_data_frame = match args.no_data_mode {
+        Some(ref mode) => {
+            Some(crsf_tx::build_flight_mode_packet(mode).ok_or("--no-data-mode text too long")?)
+        }
+        None => None,
+    };
     let crsf_task = tokio::spawn(async move {
         let mut next_send = tokio::time::Instant::now();
         let mut next_damage_heartbeat = tokio::time::Instant::now();
 
+        // Staleness watchdog. Telemetry counts as lost until the first
+        // packet arrives.
+        let mut last_telemetry: Option<tokio::time::Instant> = None;
+        let mut telemetry_lost = true;
+        let mut watchdog = tokio::time::interval(TELEMETRY_INTERVAL);
+        let mut next_no_data = tokio::time::Instant::now();
+
         /// Publish a single CRSF frame, logging and counting on success.
         async fn send_frame(
             pub_: &zenoh::pubsub::Publisher<'_>,
@@ -270,6 +371,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
                             trace!("rx tel {} bytes", payload.len());
                             counter!("input.telemetry.rx").increment(1);
                             let now = tokio::time::Instant::now();
+                            last_telemetry = Some(now);
+                            if telemetry_lost {
+                                info!("Sim telemetry received");
+                                telemetry_lost = false;
+                            }
                             if now >= next_send {
                                 if let Ok(packet) =
                                     telemetry::parse_packet(&payload, &config_format)
@@ -304,6 +410,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 
                 // Immediate damage path: fires when damage state changes.
                 _ = crsf_damage_notify.notified() => {
+                    if telemetry_lost {
+                        continue;
+                    }
                     let dmg_snapshot = crsf_damage_state.lock().await.clone();
                     if let Some(frame) = dmg_snapshot.and_then(|d| crsf_custom::build_damage_packet(&d)) {
                         send_frame(&crsf_tel_pub, &frame).await;
@@ -311,6 +420,25 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
                     // Reset heartbeat timer so we don't double-send.
                     next_damage_heartbeat = tokio::time::Instant::now() + DAMAGE_HEARTBEAT_INTERVAL;
                 }
+
+                // Staleness watchdog.
+                _ = watchdog.tick() => {
+                    if !telemetry_lost
+                        && last_telemetry.is_some_and(|t| t.elapsed() >= telemetry_timeout)
+                    {
+                        warn!("Sim telemetry lost (no packet for {:?})", telemetry_timeout);
+                        counter!("input.telemetry.lost").increment(1);
+                        telemetry_lost = true;
+                    }
+                    let now = tokio::time::Instant::now();
+                    if telemetry_lost
+                        && now >= next_no_data
+                        && let Some(ref frame) = no_data_frame
+                    {
+                        send_frame(&crsf_tel_pub, frame).await;
+                        next_no_data = now + NO_DATA_INTERVAL;
+                    }
+                }
             }
         }
     });
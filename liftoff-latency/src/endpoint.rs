@@ -0,0 +1,133 @@
+//! Where the marker frames go in (`--inject`) and where they are looked
+//! for coming out (`--observe`).
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+use telemetry_lib::topics;
+
+/// Name `crsf-joystick` gives its uinput device.
+pub const JOYSTICK_NAME: &str = "CRSF Joystick";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inject {
+    /// Publish CRSF RC frames on this topic, as `crsf-forward` does.
+    Zenoh(String),
+    /// Write CRSF RC frames to this serial port, as a receiver does.
+    Serial(String),
+    /// Send each CRSF RC frame as a datagram to this address.
+    Udp(SocketAddr),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Observe {
+    /// The uinput joystick of this name.
+    Joystick(String),
+    /// CRSF RC frames on this topic.
+    Zenoh(String),
+    /// MAVLink RC_CHANNELS received on this address, as `crsf-mavlink
+    /// --rc-channels` sends them.
+    Udp(SocketAddr),
+}
+
+#[derive(Debug)]
+pub struct ParseError(String);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// `kind` and the rest of `s` after a `:`, if any.
+fn split(s: &str) -> (&str, Option<&str>) {
+    match s.split_once(':') {
+        Some((kind, rest)) => (kind, Some(rest)),
+        None => (s, None),
+    }
+}
+
+fn addr(s: Option<&str>, what: &str) -> Result<SocketAddr, ParseError> {
+    let s = s.ok_or_else(|| {
+        ParseError(format!(
+            "{} needs an address, e.g. {}:127.0.0.1:9000",
+            what, what
+        ))
+    })?;
+    s.parse().map_err(|e| ParseError(format!("{}: {}", s, e)))
+}
+
+impl FromStr for Inject {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match split(s) {
+            ("zenoh", topic) => Ok(Inject::Zenoh(topic.unwrap_or(topics::CRSF_RC).to_string())),
+            ("serial", Some(port)) if !port.is_empty() => Ok(Inject::Serial(port.to_string())),
+            ("serial", _) => Err(ParseError(
+                "serial needs a port, e.g. serial:/dev/ttyUSB0".into(),
+            )),
+            ("udp", rest) => Ok(Inject::Udp(addr(rest, "udp")?)),
+            _ => Err(ParseError(format!(
+                "{}: expected zenoh[:TOPIC], serial:PORT or udp:ADDR",
+                s
+            ))),
+        }
+    }
+}
+
+impl FromStr for Observe {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match split(s) {
+            ("joystick", name) => Ok(Observe::Joystick(name.unwrap_or(JOYSTICK_NAME).to_string())),
+            ("zenoh", topic) => Ok(Observe::Zenoh(topic.unwrap_or(topics::CRSF_RC).to_string())),
+            ("udp", rest) => Ok(Observe::Udp(addr(rest, "udp")?)),
+            _ => Err(ParseError(format!(
+                "{}: expected joystick[:NAME], zenoh[:TOPIC] or udp:ADDR",
+                s
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            "zenoh".parse::<Inject>().unwrap(),
+            Inject::Zenoh("crsf/rc".into())
+        );
+        assert_eq!(
+            "serial:/dev/ttyUSB0".parse::<Inject>().unwrap(),
+            Inject::Serial("/dev/ttyUSB0".into())
+        );
+        assert_eq!(
+            "udp:127.0.0.1:9000".parse::<Inject>().unwrap(),
+            Inject::Udp(([127, 0, 0, 1], 9000).into())
+        );
+        assert!("serial".parse::<Inject>().is_err());
+        assert!("udp:nowhere".parse::<Inject>().is_err());
+        assert!("joystick".parse::<Inject>().is_err());
+
+        assert_eq!(
+            "joystick".parse::<Observe>().unwrap(),
+            Observe::Joystick(JOYSTICK_NAME.into())
+        );
+        assert_eq!(
+            "zenoh:crsf/rc/autopilot".parse::<Observe>().unwrap(),
+            Observe::Zenoh("crsf/rc/autopilot".into())
+        );
+        assert_eq!(
+            "udp:[::]:14550".parse::<Observe>().unwrap(),
+            Observe::Udp("[::]:14550".parse().unwrap())
+        );
+    }
+}
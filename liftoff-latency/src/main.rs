@@ -0,0 +1,228 @@
+//! End-to-end latency of the RC path. Sends CRSF RC frames into one end of
+//! the pipeline, flips a marker channel in them every so often, and times
+//! how long each flip takes to come out of the other end: as an event of
+//! the `crsf-joystick` uinput device, as a frame on a Zenoh topic, or as a
+//! MAVLink RC_CHANNELS message over UDP. Prints the percentiles at the
+//! end.
+//!
+//! Joystick events carry the kernel's timestamp; the other outputs are
+//! timed when they are received. The clocks of both ends must agree when
+//! injecting on another machine.
+
+use clap::Parser;
+use log::{debug, info, warn};
+use std::time::{Duration, SystemTime};
+use telemetry_lib::crsf::{self, CrsfPacket, RcChannelsPacked, build_packet, device_address};
+use telemetry_lib::topics;
+use tokio::io::AsyncWriteExt;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
+use zenoh::Config;
+
+mod endpoint;
+mod observe;
+mod probe;
+
+use endpoint::{Inject, Observe};
+use probe::Probes;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Where to send the RC frames: `zenoh` or `zenoh:TOPIC` publishes them
+    /// (by default on crsf/rc, as crsf-forward does), `serial:PORT` writes
+    /// them to a serial port, as a receiver does, and `udp:ADDR` sends them
+    /// as datagrams.
+    #[arg(long, default_value = "zenoh")]
+    inject: Inject,
+
+    /// Where to look for them: `joystick` or `joystick:NAME` watches the
+    /// crsf-joystick device, `zenoh` or `zenoh:TOPIC` the frames on a topic,
+    /// and `udp:ADDR` the RC_CHANNELS of `crsf-mavlink --rc-channels` sent
+    /// to that address.
+    #[arg(long, default_value = "joystick")]
+    observe: Observe,
+
+    /// RC channel of the marker, from 0. The joystick reports channels 0-4,
+    /// 6 and 7 as axes.
+    #[arg(long, default_value_t = 6, value_parser = clap::value_parser!(u8).range(0..16))]
+    channel: u8,
+
+    /// RC frames per second.
+    #[arg(long, default_value_t = 100)]
+    rate: u32,
+
+    /// Time between flips of the marker, ms. Flips not seen by the next
+    /// one count as lost.
+    #[arg(long, default_value_t = 200)]
+    interval: u64,
+
+    /// Number of flips; 0 to go on until interrupted.
+    #[arg(long, default_value_t = 100)]
+    count: usize,
+
+    /// Serial port speed, for `--inject serial:PORT`.
+    #[arg(long, default_value_t = 420000)]
+    baud: u32,
+
+    /// Zenoh connect endpoint (e.g. tcp/192.168.1.1:7447). Omit for peer discovery.
+    #[arg(long)]
+    zenoh_connect: Option<String>,
+
+    /// Zenoh mode (peer or client).
+    #[arg(long, default_value = "client")]
+    zenoh_mode: String,
+
+    /// Zenoh topic prefix.
+    #[arg(long, default_value = topics::DEFAULT_PREFIX)]
+    zenoh_prefix: String,
+}
+
+/// Where the frames go.
+enum Sink {
+    Zenoh(zenoh::pubsub::Publisher<'static>),
+    Serial(SerialStream),
+    Udp(UdpSocket),
+}
+
+impl Sink {
+    async fn send(&mut self, frame: &[u8]) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        match self {
+            Sink::Zenoh(publisher) => publisher.put(frame.to_vec()).await?,
+            Sink::Serial(port) => port.write_all(frame).await?,
+            Sink::Udp(socket) => {
+                socket.send(frame).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// RC frame with the marker in `channel` at `level`. The sticks are
+/// centered, and the throttle and the arm switch (channel 4) low.
+fn rc_frame(channel: usize, level: bool) -> Vec<u8> {
+    let mut channels = [crsf::us_to_ticks(1500); 16];
+    channels[2] = crsf::us_to_ticks(1000);
+    channels[4] = crsf::us_to_ticks(1000);
+    channels[channel] = crsf::us_to_ticks(if level { 2000 } else { 1000 });
+    let packet = CrsfPacket::RcChannelsPacked(RcChannelsPacked { channels });
+    build_packet(device_address::FLIGHT_CONTROLLER, &packet).expect("RC frame")
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    env_logger::init();
+    let args = Args::parse();
+    let channel = usize::from(args.channel);
+
+    info!("Starting liftoff-latency");
+
+    // Zenoh session, if either end needs one
+    let session =
+        if matches!(args.inject, Inject::Zenoh(_)) || matches!(args.observe, Observe::Zenoh(_)) {
+            let mut config = Config::default();
+            config.insert_json5("mode", &format!(r#""{}""#, args.zenoh_mode))?;
+            if let Some(ref endpoint) = args.zenoh_connect {
+                config.insert_json5("connect/endpoints", &format!(r#"["{}"]"#, endpoint))?;
+            }
+            Some(zenoh::open(config).await?)
+        } else {
+            None
+        };
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    match args.observe {
+        Observe::Joystick(ref name) => observe::joystick(name, channel, tx)?,
+        Observe::Zenoh(ref topic) => {
+            let topic = topics::topic(&args.zenoh_prefix, topic);
+            info!("Subscribing to: {}", topic);
+            let subscriber = session.as_ref().unwrap().declare_subscriber(&topic).await?;
+            tokio::spawn(async move {
+                while let Ok(sample) = subscriber.recv_async().await {
+                    let now = SystemTime::now();
+                    if let Some(level) = observe::crsf_level(&sample.payload().to_bytes(), channel)
+                        && tx.send((now, level)).is_err()
+                    {
+                        break;
+                    }
+                }
+            });
+        }
+        Observe::Udp(addr) => {
+            let socket = UdpSocket::bind(addr).await?;
+            info!("Listening for MAVLink on {}", addr);
+            tokio::spawn(observe::udp(socket, channel, tx));
+        }
+    }
+
+    let mut sink = match args.inject {
+        Inject::Zenoh(ref topic) => {
+            let topic = topics::topic(&args.zenoh_prefix, topic);
+            info!("Publishing to: {}", topic);
+            Sink::Zenoh(session.as_ref().unwrap().declare_publisher(topic).await?)
+        }
+        Inject::Serial(ref path) => {
+            let port = tokio_serial::new(path, args.baud)
+                .open_native_async()
+                .map_err(|e| format!("{}: {}", path, e))?;
+            info!("Writing to {} at {} baud", path, args.baud);
+            Sink::Serial(port)
+        }
+        Inject::Udp(addr) => {
+            let bind: std::net::SocketAddr = if addr.is_ipv4() {
+                ([0, 0, 0, 0], 0).into()
+            } else {
+                ([0u16; 8], 0).into()
+            };
+            let socket = UdpSocket::bind(bind).await?;
+            socket.connect(addr).await?;
+            info!("Sending to {}", addr);
+            Sink::Udp(socket)
+        }
+    };
+
+    let mut probes = Probes::default();
+    let mut frames = tokio::time::interval(Duration::from_secs(1) / args.rate.max(1));
+    frames.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let interval = Duration::from_millis(args.interval.max(1));
+    // Let the pipeline see the marker low before the first flip.
+    let mut next_flip = tokio::time::Instant::now() + Duration::from_secs(1);
+    loop {
+        tokio::select! {
+            _ = frames.tick() => {
+                let now = tokio::time::Instant::now();
+                if now >= next_flip {
+                    if args.count > 0 && probes.sent() >= args.count {
+                        // Give the last flip the same time as the others.
+                        break;
+                    }
+                    probes.flip(SystemTime::now());
+                    next_flip = now + interval;
+                }
+                if let Err(e) = sink.send(&rc_frame(channel, probes.level())).await {
+                    warn!("Failed to send RC frame: {}", e);
+                }
+            }
+            sighting = rx.recv() => {
+                let Some((at, level)) = sighting else {
+                    warn!("Stopped observing");
+                    break;
+                };
+                if let Some(latency) = probes.observe(at, level) {
+                    debug!("Probe {}: {:?}", probes.sent(), latency);
+                }
+            }
+            _ = tokio::signal::ctrl_c() => {
+                info!("Shutdown signal received, exiting.");
+                break;
+            }
+        }
+    }
+
+    print!("{}", probes.report());
+    if let Some(session) = session {
+        session.close().await?;
+    }
+    Ok(())
+}
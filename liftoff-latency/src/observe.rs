@@ -0,0 +1,185 @@
+//! Watching the output of the pipeline for the marker channel, and
+//! reporting its level each time it is seen, with the time it was seen.
+
+use std::io::Cursor;
+use std::time::SystemTime;
+
+use log::{debug, info, warn};
+use mavlink::common::MavMessage;
+use mavlink::peek_reader::PeekReader;
+use telemetry_lib::crsf::{self, CrsfPacket};
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// When and at which level the marker was seen.
+pub type Sighting = (SystemTime, bool);
+
+/// Level of the marker in the RC channels of a CRSF frame, `None` if it
+/// has none.
+pub fn crsf_level(frame: &[u8], channel: usize) -> Option<bool> {
+    match crsf::parse_packet_check(frame)? {
+        CrsfPacket::RcChannelsPacked(rc) => Some(rc.channels[channel] > crsf::us_to_ticks(1500)),
+        _ => None,
+    }
+}
+
+/// Level of the marker in the RC_CHANNELS messages of a MAVLink datagram,
+/// `None` if it has none.
+pub fn mavlink_level(datagram: &[u8], channel: usize) -> Option<bool> {
+    let mut reader = PeekReader::new(Cursor::new(datagram));
+    let mut level = None;
+    while let Ok((_, message)) = mavlink::read_v2_msg::<MavMessage, _>(&mut reader) {
+        if let MavMessage::RC_CHANNELS(rc) = message {
+            let raw = [
+                rc.chan1_raw,
+                rc.chan2_raw,
+                rc.chan3_raw,
+                rc.chan4_raw,
+                rc.chan5_raw,
+                rc.chan6_raw,
+                rc.chan7_raw,
+                rc.chan8_raw,
+                rc.chan9_raw,
+                rc.chan10_raw,
+                rc.chan11_raw,
+                rc.chan12_raw,
+                rc.chan13_raw,
+                rc.chan14_raw,
+                rc.chan15_raw,
+                rc.chan16_raw,
+            ];
+            level = Some(raw[channel] > 1500);
+        }
+    }
+    level
+}
+
+/// Report the marker in the MAVLink datagrams received on `socket`.
+pub async fn udp(socket: UdpSocket, channel: usize, tx: UnboundedSender<Sighting>) {
+    let mut buf = [0u8; 2048];
+    loop {
+        let len = match socket.recv(&mut buf).await {
+            Ok(len) => len,
+            Err(e) => {
+                warn!("UDP receive error: {}", e);
+                continue;
+            }
+        };
+        let now = SystemTime::now();
+        if let Some(level) = mavlink_level(&buf[..len], channel)
+            && tx.send((now, level)).is_err()
+        {
+            break;
+        }
+    }
+}
+
+/// The joystick axis `crsf-joystick` reports RC channel `channel` on.
+#[cfg(target_os = "linux")]
+fn joystick_axis(channel: usize) -> Option<evdev::AbsoluteAxisCode> {
+    use evdev::AbsoluteAxisCode as A;
+    [
+        Some(A::ABS_X),
+        Some(A::ABS_Y),
+        Some(A::ABS_Z),
+        Some(A::ABS_RX),
+        Some(A::ABS_THROTTLE),
+        None,
+        Some(A::ABS_RUDDER),
+        Some(A::ABS_WHEEL),
+    ]
+    .get(channel)
+    .copied()
+    .flatten()
+}
+
+/// Report the marker on the axis of the input device `name`, with the
+/// kernel's timestamps of the events. Runs on a thread of its own, as
+/// reading the device blocks.
+#[cfg(target_os = "linux")]
+pub fn joystick(
+    name: &str,
+    channel: usize,
+    tx: UnboundedSender<Sighting>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    use evdev::EventSummary;
+
+    let axis = joystick_axis(channel)
+        .ok_or_else(|| format!("channel {} has no joystick axis", channel))?;
+    let (path, mut device) = evdev::enumerate()
+        .find(|(_, device)| device.name() == Some(name))
+        .ok_or_else(|| {
+            format!(
+                "no input device named {:?}; is crsf-joystick running?",
+                name
+            )
+        })?;
+    let (_, info) = device
+        .get_absinfo()?
+        .find(|&(code, _)| code == axis)
+        .ok_or_else(|| format!("{} has no axis {:?}", path.display(), axis))?;
+    let mid = (info.minimum() + info.maximum()) / 2;
+    info!("Watching {:?} of {} ({})", axis, name, path.display());
+
+    std::thread::spawn(move || {
+        loop {
+            let events = match device.fetch_events() {
+                Ok(events) => events,
+                Err(e) => {
+                    warn!("{}: {}", path.display(), e);
+                    return;
+                }
+            };
+            for event in events {
+                if let EventSummary::AbsoluteAxis(_, code, value) = event.destructure()
+                    && code == axis
+                {
+                    debug!("{:?} = {}", code, value);
+                    if tx.send((event.timestamp(), value > mid)).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn joystick(
+    _name: &str,
+    _channel: usize,
+    _tx: UnboundedSender<Sighting>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    Err("watching a joystick is only supported on Linux".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mavlink::common::RC_CHANNELS_DATA;
+    use mavlink::{MavHeader, MavlinkVersion};
+    use telemetry_lib::crsf::{RcChannelsPacked, build_packet, device_address};
+
+    #[test]
+    fn levels() {
+        let mut channels = [crsf::us_to_ticks(1500); 16];
+        channels[6] = crsf::us_to_ticks(2000);
+        let packet = CrsfPacket::RcChannelsPacked(RcChannelsPacked { channels });
+        let frame = build_packet(device_address::FLIGHT_CONTROLLER, &packet).unwrap();
+        assert_eq!(crsf_level(&frame, 6), Some(true));
+        assert_eq!(crsf_level(&frame, 5), Some(false));
+
+        let mut datagram = Vec::new();
+        let rc = MavMessage::RC_CHANNELS(RC_CHANNELS_DATA {
+            chan7_raw: 1000,
+            chan8_raw: 2000,
+            ..Default::default()
+        });
+        mavlink::write_versioned_msg(&mut datagram, MavlinkVersion::V2, MavHeader::default(), &rc)
+            .unwrap();
+        assert_eq!(mavlink_level(&datagram, 6), Some(false));
+        assert_eq!(mavlink_level(&datagram, 7), Some(true));
+        assert_eq!(mavlink_level(&frame, 6), None);
+    }
+}
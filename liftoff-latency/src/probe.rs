@@ -0,0 +1,172 @@
+//! Matching the marker flips seen at the output to the ones injected.
+//!
+//! Each probe flips the marker channel to the other level. The first
+//! observation of that level afterwards ends the probe, and its latency
+//! is the time in between. A probe that is still waiting when the next
+//! one starts is lost; so is one whose level was seen before it was sent,
+//! which can't be told apart from a stale one.
+
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, Clone, Copy)]
+struct Pending {
+    level: bool,
+    sent: SystemTime,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Probes {
+    level: bool,
+    pending: Option<Pending>,
+    sent: usize,
+    lost: usize,
+    latencies: Vec<Duration>,
+}
+
+impl Probes {
+    /// Level of the marker in the frames being sent.
+    pub fn level(&self) -> bool {
+        self.level
+    }
+
+    /// Flip the marker, injected at `now`.
+    pub fn flip(&mut self, now: SystemTime) {
+        if self.pending.is_some() {
+            self.lost += 1;
+        }
+        self.level = !self.level;
+        self.sent += 1;
+        self.pending = Some(Pending {
+            level: self.level,
+            sent: now,
+        });
+    }
+
+    /// The marker was seen at `level` at time `at`. Returns the latency
+    /// of the probe this ends, if any.
+    pub fn observe(&mut self, at: SystemTime, level: bool) -> Option<Duration> {
+        let pending = self.pending.filter(|p| p.level == level)?;
+        self.pending = None;
+        // Seen before it was sent: clocks that don't agree, or a stale
+        // observation.
+        let Ok(latency) = at.duration_since(pending.sent) else {
+            self.lost += 1;
+            return None;
+        };
+        self.latencies.push(latency);
+        Some(latency)
+    }
+
+    /// Number of probes sent.
+    pub fn sent(&self) -> usize {
+        self.sent
+    }
+
+    pub fn report(&self) -> Report {
+        let mut sorted = self.latencies.clone();
+        sorted.sort_unstable();
+        Report {
+            sent: self.sent,
+            // The probe still waiting is neither seen nor lost.
+            lost: self.lost,
+            latencies: sorted,
+        }
+    }
+}
+
+/// Percentiles of the latencies of the probes seen.
+#[derive(Debug, Clone)]
+pub struct Report {
+    sent: usize,
+    lost: usize,
+    /// Sorted.
+    latencies: Vec<Duration>,
+}
+
+impl Report {
+    /// Latency below which `p` percent of the probes seen came out.
+    pub fn percentile(&self, p: usize) -> Option<Duration> {
+        let n = self.latencies.len();
+        (n > 0).then(|| self.latencies[((n - 1) * p + 50) / 100])
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        let n = self.latencies.len();
+        (n > 0).then(|| self.latencies.iter().sum::<Duration>() / n as u32)
+    }
+}
+
+fn ms(d: Duration) -> f64 {
+    d.as_secs_f64() * 1e3
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} probes, {} seen, {} lost",
+            self.sent,
+            self.latencies.len(),
+            self.lost
+        )?;
+        let (Some(mean), Some(min), Some(max)) =
+            (self.mean(), self.percentile(0), self.percentile(100))
+        else {
+            return Ok(());
+        };
+        write!(f, "latency ms: min {:.2}", ms(min))?;
+        for p in [50, 90, 95, 99] {
+            write!(f, "  p{} {:.2}", p, ms(self.percentile(p).unwrap_or(max)))?;
+        }
+        writeln!(f, "  max {:.2}  mean {:.2}", ms(max), ms(mean))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn probes() {
+        let t0 = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+        let ms = |n: u64| Duration::from_millis(n);
+        let mut probes = Probes::default();
+        assert!(!probes.level());
+
+        // Seen after 5 ms; the observation after that is a repeat.
+        probes.flip(t0);
+        assert!(probes.level());
+        assert_eq!(probes.observe(t0 + ms(3), false), None);
+        assert_eq!(probes.observe(t0 + ms(5), true), Some(ms(5)));
+        assert_eq!(probes.observe(t0 + ms(6), true), None);
+
+        // Never seen.
+        probes.flip(t0 + ms(100));
+        probes.flip(t0 + ms(200));
+        assert_eq!(probes.observe(t0 + ms(202), true), Some(ms(2)));
+
+        // Seen before it was sent.
+        probes.flip(t0 + ms(300));
+        assert_eq!(probes.observe(t0 + ms(299), false), None);
+
+        for i in 0..10 {
+            probes.flip(t0 + ms(400 + 100 * i));
+            probes.observe(t0 + ms(410 + 101 * i), probes.level());
+        }
+
+        let report = probes.report();
+        assert_eq!(
+            (probes.sent(), report.lost, report.latencies.len()),
+            (14, 2, 12)
+        );
+        assert_eq!(report.percentile(0), Some(ms(2)));
+        assert_eq!(report.percentile(50), Some(ms(14)));
+        assert_eq!(report.percentile(100), Some(ms(19)));
+        assert_eq!(
+            report.to_string(),
+            "14 probes, 12 seen, 2 lost\n\
+             latency ms: min 2.00  p50 14.00  p90 18.00  p95 18.00  p99 19.00  max 19.00  mean 12.67\n"
+        );
+    }
+}
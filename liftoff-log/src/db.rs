@@ -0,0 +1,362 @@
+//! The flight log, an SQLite database.
+//!
+//! A session is a flight: it starts with the first telemetry, and ends
+//! when the telemetry stops ([`Logger::end`]) or the sim time goes back,
+//! as it does when a flight restarts. The tables:
+//!
+//! - `sessions`: `id`, `start` (RFC 3339), `duration` (s), `samples`,
+//!   `distance` flown (m), `max_speed` (m/s), `max_altitude` (m) and
+//!   `min_voltage` (V).
+//! - `samples`: `session`, then the [`SAMPLE_COLUMNS`], indexed by
+//!   session and time.
+//! - `events`: `session`, `time` (sim time, s), `name`, and the `metric`
+//!   and its `value`, of the events published by liftoff-input.
+
+use log::info;
+use rusqlite::{Connection, ToSql, params, params_from_iter};
+use telemetry_lib::events::Event;
+use telemetry_lib::telemetry::TelemetryPacket;
+
+/// The columns of `samples` after `session`. `time` is the sim time, s;
+/// position `x`, `y` (up) and `z` in m; the attitude quaternion;
+/// velocity in m/s; gyro in deg/s; the sticks from -1 to 1; `battery`
+/// charge from 0 to 1 and `voltage` in V; motor RPM; and the derived
+/// `speed` (m/s) and `distance` flown so far (m).
+pub const SAMPLE_COLUMNS: [&str; 26] = [
+    "time",
+    "x",
+    "y",
+    "z",
+    "qx",
+    "qy",
+    "qz",
+    "qw",
+    "vx",
+    "vy",
+    "vz",
+    "gyro_pitch",
+    "gyro_roll",
+    "gyro_yaw",
+    "throttle",
+    "yaw",
+    "pitch",
+    "roll",
+    "battery",
+    "voltage",
+    "rpm_lf",
+    "rpm_rf",
+    "rpm_lb",
+    "rpm_rb",
+    "speed",
+    "distance",
+];
+
+/// Samples written at a time.
+const BATCH: usize = 256;
+
+pub type Row = [Option<f64>; SAMPLE_COLUMNS.len()];
+
+/// Create the tables if they are not there yet.
+pub fn init(conn: &Connection) -> rusqlite::Result<()> {
+    let columns: Vec<String> = SAMPLE_COLUMNS
+        .iter()
+        .map(|c| format!("{} REAL", c))
+        .collect();
+    conn.execute_batch(&format!(
+        "CREATE TABLE IF NOT EXISTS sessions (
+             id INTEGER PRIMARY KEY,
+             start TEXT NOT NULL,
+             duration REAL NOT NULL DEFAULT 0,
+             samples INTEGER NOT NULL DEFAULT 0,
+             distance REAL NOT NULL DEFAULT 0,
+             max_speed REAL,
+             max_altitude REAL,
+             min_voltage REAL
+         );
+         CREATE TABLE IF NOT EXISTS samples (
+             session INTEGER NOT NULL REFERENCES sessions(id),
+             {}
+         );
+         CREATE INDEX IF NOT EXISTS samples_time ON samples (session, time);
+         CREATE TABLE IF NOT EXISTS events (
+             session INTEGER NOT NULL REFERENCES sessions(id),
+             time REAL NOT NULL,
+             name TEXT NOT NULL,
+             metric TEXT NOT NULL,
+             value REAL NOT NULL
+         );",
+        columns.join(",\n")
+    ))
+}
+
+fn max(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    a.into_iter().chain(b).reduce(f64::max)
+}
+
+fn min(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    a.into_iter().chain(b).reduce(f64::min)
+}
+
+struct Session {
+    id: i64,
+    /// Sim time at the start, and of the last sample, s.
+    start: f64,
+    last: f64,
+    position: Option<[f64; 3]>,
+    samples: u64,
+    distance: f64,
+    max_speed: Option<f64>,
+    max_altitude: Option<f64>,
+    min_voltage: Option<f64>,
+    pending: Vec<Row>,
+}
+
+impl Session {
+    fn row(&mut self, time: f64, packet: &TelemetryPacket) -> Row {
+        let f = |v: f32| Some(f64::from(v));
+        let mut row = [None; SAMPLE_COLUMNS.len()];
+        row[0] = Some(time);
+        let position = packet.position.map(|p| p.map(f64::from));
+        if let (Some(from), Some(to)) = (self.position, position) {
+            self.distance += (0..3)
+                .map(|i| (to[i] - from[i]).powi(2))
+                .sum::<f64>()
+                .sqrt();
+        }
+        if position.is_some() {
+            self.position = position;
+        }
+        if let Some(p) = position {
+            row[1..4].copy_from_slice(&p.map(Some));
+            self.max_altitude = max(self.max_altitude, Some(p[1]));
+        }
+        if let Some(q) = packet.attitude {
+            row[4..8].copy_from_slice(&q.map(f));
+        }
+        if let Some(v) = packet.velocity {
+            row[8..11].copy_from_slice(&v.map(f));
+            let speed = v.iter().map(|&v| f64::from(v).powi(2)).sum::<f64>().sqrt();
+            row[24] = Some(speed);
+            self.max_speed = max(self.max_speed, Some(speed));
+        }
+        if let Some(g) = packet.gyro {
+            row[11..14].copy_from_slice(&g.map(f));
+        }
+        if let Some(input) = packet.input {
+            row[14..18].copy_from_slice(&input.map(f));
+        }
+        if let Some(b) = packet.battery {
+            row[18..20].copy_from_slice(&b.map(f));
+            self.min_voltage = min(self.min_voltage, Some(f64::from(b[1])));
+        }
+        if let Some(rpm) = &packet.motor_rpm {
+            for (dst, &rpm) in row[20..24].iter_mut().zip(rpm) {
+                *dst = f(rpm);
+            }
+        }
+        row[25] = Some(self.distance);
+        row
+    }
+}
+
+/// Writes the telemetry into the database, a session per flight.
+pub struct Logger {
+    conn: Connection,
+    session: Option<Session>,
+}
+
+impl Logger {
+    pub fn new(conn: Connection) -> rusqlite::Result<Self> {
+        init(&conn)?;
+        Ok(Self {
+            conn,
+            session: None,
+        })
+    }
+
+    /// Whether a session is under way.
+    pub fn logging(&self) -> bool {
+        self.session.is_some()
+    }
+
+    /// Log `packet` at sim `time`, s. `datetime` gives the date and time
+    /// a new session starts.
+    pub fn record(
+        &mut self,
+        time: f64,
+        datetime: impl FnOnce() -> String,
+        packet: &TelemetryPacket,
+    ) -> rusqlite::Result<()> {
+        if self.session.as_ref().is_some_and(|s| time < s.last) {
+            self.end()?;
+        }
+        let session = match &mut self.session {
+            Some(session) => session,
+            None => {
+                self.conn
+                    .execute("INSERT INTO sessions (start) VALUES (?1)", [datetime()])?;
+                let id = self.conn.last_insert_rowid();
+                info!("Session {} started", id);
+                self.session.insert(Session {
+                    id,
+                    start: time,
+                    last: time,
+                    position: None,
+                    samples: 0,
+                    distance: 0.0,
+                    max_speed: None,
+                    max_altitude: None,
+                    min_voltage: None,
+                    pending: Vec::new(),
+                })
+            }
+        };
+        session.last = time;
+        session.samples += 1;
+        let row = session.row(time, packet);
+        session.pending.push(row);
+        if session.pending.len() >= BATCH {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Log `event` in the session under way. Without one, it is dropped.
+    pub fn event(&mut self, event: &Event) -> rusqlite::Result<()> {
+        let Some(session) = &self.session else {
+            return Ok(());
+        };
+        self.conn.execute(
+            "INSERT INTO events (session, time, name, metric, value) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                session.id,
+                event.time,
+                event.name,
+                event.metric,
+                event.value
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Write the pending samples, and the summary of the session so far.
+    fn flush(&mut self) -> rusqlite::Result<()> {
+        let Some(session) = &mut self.session else {
+            return Ok(());
+        };
+        let tx = self.conn.transaction()?;
+        {
+            let placeholders = vec!["?"; SAMPLE_COLUMNS.len() + 1].join(", ");
+            let mut insert = tx.prepare_cached(&format!(
+                "INSERT INTO samples (session, {}) VALUES ({})",
+                SAMPLE_COLUMNS.join(", "),
+                placeholders
+            ))?;
+            for row in session.pending.drain(..) {
+                let values = row.iter().map(|v| v as &dyn ToSql);
+                insert.execute(params_from_iter(
+                    std::iter::once(&session.id as &dyn ToSql).chain(values),
+                ))?;
+            }
+        }
+        tx.execute(
+            "UPDATE sessions SET duration = ?2, samples = ?3, distance = ?4, max_speed = ?5,
+             max_altitude = ?6, min_voltage = ?7 WHERE id = ?1",
+            params![
+                session.id,
+                session.last - session.start,
+                session.samples,
+                session.distance,
+                session.max_speed,
+                session.max_altitude,
+                session.min_voltage
+            ],
+        )?;
+        tx.commit()
+    }
+
+    /// End the session, if one is under way.
+    pub fn end(&mut self) -> rusqlite::Result<()> {
+        self.flush()?;
+        if let Some(session) = self.session.take() {
+            info!(
+                "Session {} ended: {} samples, {:.1} s",
+                session.id,
+                session.samples,
+                session.last - session.start
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sessions() {
+        let mut logger = Logger::new(Connection::open_in_memory().unwrap()).unwrap();
+        let packet = |x: f32, voltage: f32| TelemetryPacket {
+            timestamp: None,
+            position: Some([x, 10.0, 0.0]),
+            attitude: None,
+            velocity: Some([3.0, 4.0, 0.0]),
+            gyro: None,
+            input: None,
+            battery: Some([0.5, voltage]),
+            motor_rpm: Some(vec![1000.0; 4]),
+        };
+        let datetime = || "2026-01-01T00:00:00.000+00:00".to_string();
+        for i in 0..300 {
+            let x = i as f32;
+            logger
+                .record(i as f64 * 0.1, datetime, &packet(x, 16.0 - x / 100.0))
+                .unwrap();
+        }
+        let crash = Event {
+            name: "crash".into(),
+            time: 29.9,
+            metric: "deceleration".into(),
+            value: 80.0,
+        };
+        logger.event(&crash).unwrap();
+        // A restart.
+        logger.record(0.0, datetime, &packet(0.0, 16.0)).unwrap();
+        assert!(logger.logging());
+        logger.end().unwrap();
+        assert!(!logger.logging());
+
+        let conn = &logger.conn;
+        let (samples, duration, distance, max_speed, min_voltage): (i64, f64, f64, f64, f64) = conn
+            .query_row(
+                "SELECT samples, duration, distance, max_speed, min_voltage FROM sessions
+                 WHERE id = 1",
+                [],
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?)),
+            )
+            .unwrap();
+        assert_eq!(samples, 300);
+        assert!((duration - 29.9).abs() < 1e-9);
+        assert_eq!(distance, 299.0);
+        assert_eq!(max_speed, 5.0);
+        assert!((min_voltage - 13.01).abs() < 1e-3);
+        let count = |sql: &str| conn.query_row(sql, [], |r| r.get::<_, i64>(0)).unwrap();
+        assert_eq!(count("SELECT COUNT(*) FROM sessions"), 2);
+        assert_eq!(count("SELECT COUNT(*) FROM samples WHERE session = 1"), 300);
+        assert_eq!(count("SELECT COUNT(*) FROM samples WHERE session = 2"), 1);
+        assert_eq!(
+            count("SELECT COUNT(*) FROM events WHERE session = 1 AND name = 'crash'"),
+            1
+        );
+        let (distance, rpm, gyro): (f64, f64, Option<f64>) = conn
+            .query_row(
+                "SELECT distance, rpm_rb, gyro_yaw FROM samples WHERE session = 1 AND time > 1.95
+                 ORDER BY time LIMIT 1",
+                [],
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!((distance, rpm, gyro), (20.0, 1000.0, None));
+    }
+}
@@ -0,0 +1,157 @@
+//! Export of the samples, as CSV or Parquet, for the usual data tools.
+
+use std::io::Write;
+use std::sync::Arc;
+
+use arrow_array::{ArrayRef, Float64Array, Int64Array, RecordBatch};
+use arrow_schema::{DataType, Field, Schema};
+use clap::ValueEnum;
+use parquet::arrow::ArrowWriter;
+use rusqlite::Connection;
+
+use crate::db::{Row, SAMPLE_COLUMNS};
+
+/// Rows in a Parquet row group.
+const ROW_GROUP: usize = 8192;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Parquet,
+}
+
+/// The samples of `session`, or of all sessions, in order.
+fn samples(
+    conn: &Connection,
+    session: Option<i64>,
+    mut f: impl FnMut(i64, Row) -> Result<(), Box<dyn std::error::Error + Send + Sync>>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT session, {} FROM samples WHERE ?1 IS NULL OR session = ?1 ORDER BY session, time",
+        SAMPLE_COLUMNS.join(", ")
+    ))?;
+    let mut rows = stmt.query([session])?;
+    while let Some(r) = rows.next()? {
+        let mut row = [None; SAMPLE_COLUMNS.len()];
+        for (i, value) in row.iter_mut().enumerate() {
+            *value = r.get(i + 1)?;
+        }
+        f(r.get(0)?, row)?;
+    }
+    Ok(())
+}
+
+/// Write the samples of `session`, or of all sessions, to `out`. Returns
+/// the number of samples.
+pub fn export<W: Write + Send>(
+    conn: &Connection,
+    session: Option<i64>,
+    format: Format,
+    mut out: W,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let mut count = 0;
+    match format {
+        Format::Csv => {
+            writeln!(out, "session,{}", SAMPLE_COLUMNS.join(","))?;
+            samples(conn, session, |session, row| {
+                let values: Vec<String> = row
+                    .iter()
+                    .map(|v| v.map(|v| v.to_string()).unwrap_or_default())
+                    .collect();
+                writeln!(out, "{},{}", session, values.join(","))?;
+                count += 1;
+                Ok(())
+            })?;
+            out.flush()?;
+        }
+        Format::Parquet => {
+            let fields: Vec<Field> = std::iter::once(Field::new("session", DataType::Int64, false))
+                .chain(
+                    SAMPLE_COLUMNS
+                        .iter()
+                        .map(|c| Field::new(*c, DataType::Float64, true)),
+                )
+                .collect();
+            let schema = Arc::new(Schema::new(fields));
+            let mut writer = ArrowWriter::try_new(out, schema.clone(), None)?;
+            let mut sessions = Vec::new();
+            let mut rows = Vec::new();
+            let mut write = |sessions: &mut Vec<i64>, rows: &mut Vec<Row>| {
+                let columns: Vec<ArrayRef> =
+                    std::iter::once(Arc::new(Int64Array::from(std::mem::take(sessions))) as _)
+                        .chain((0..SAMPLE_COLUMNS.len()).map(|i| {
+                            let column: Float64Array = rows.iter().map(|row| row[i]).collect();
+                            Arc::new(column) as _
+                        }))
+                        .collect();
+                rows.clear();
+                writer.write(&RecordBatch::try_new(schema.clone(), columns)?)
+            };
+            samples(conn, session, |session, row| {
+                sessions.push(session);
+                rows.push(row);
+                count += 1;
+                if rows.len() == ROW_GROUP {
+                    write(&mut sessions, &mut rows)?;
+                }
+                Ok(())
+            })?;
+            if !rows.is_empty() {
+                write(&mut sessions, &mut rows)?;
+            }
+            writer.close()?;
+        }
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    #[test]
+    fn formats() {
+        let conn = Connection::open_in_memory().unwrap();
+        crate::db::init(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO sessions (start) VALUES ('a'), ('b');
+             INSERT INTO samples (session, time, x, speed) VALUES
+                 (1, 0.5, 1.0, NULL), (1, 0.0, 2.0, 3.0), (2, 0.0, 4.0, NULL);",
+        )
+        .unwrap();
+
+        let mut csv = Vec::new();
+        assert_eq!(export(&conn, Some(1), Format::Csv, &mut csv).unwrap(), 2);
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("session,time,x,y,"));
+        assert!(lines[1].starts_with("1,0,2,,"), "{}", lines[1]);
+        assert!(lines[1].ends_with(",3,"), "{}", lines[1]);
+        assert!(lines[2].starts_with("1,0.5,1,,"), "{}", lines[2]);
+
+        let path = std::env::temp_dir().join(format!("liftoff-log-{}.parquet", std::process::id()));
+        let file = std::fs::File::create(&path).unwrap();
+        assert_eq!(export(&conn, None, Format::Parquet, file).unwrap(), 3);
+        let reader = ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches: Vec<RecordBatch> = reader.map(Result::unwrap).collect();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        assert_eq!(batch.num_columns(), SAMPLE_COLUMNS.len() + 1);
+        let sessions = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!(sessions.values(), &[1, 1, 2]);
+        let x = batch.column_by_name("x").unwrap();
+        let x = x.as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(x.values(), &[2.0, 1.0, 4.0]);
+        assert!(batch.column_by_name("y").unwrap().is_null(0));
+    }
+}
@@ -0,0 +1,228 @@
+use chrono::{SecondsFormat, Utc};
+use clap::{Parser, Subcommand};
+use log::{debug, info, warn};
+use rusqlite::Connection;
+use rusqlite::types::ValueRef;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::time::Instant;
+use telemetry_lib::events::Event;
+use telemetry_lib::telemetry;
+use telemetry_lib::topics;
+use tokio::time::{Duration, timeout};
+use zenoh::Config;
+
+mod db;
+mod export;
+
+use db::Logger;
+use export::Format;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+
+    /// The flight log, an SQLite database.
+    #[arg(long, global = true, default_value = "liftoff.db")]
+    db: PathBuf,
+
+    /// Zenoh connect endpoint (e.g. tcp/192.168.1.1:7447). Omit for peer discovery.
+    #[arg(long, global = true)]
+    zenoh_connect: Option<String>,
+
+    /// Zenoh mode (peer or client).
+    #[arg(long, global = true, default_value = "client")]
+    zenoh_mode: String,
+
+    /// Zenoh topic prefix.
+    #[arg(long, global = true, default_value = topics::DEFAULT_PREFIX)]
+    zenoh_prefix: String,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Log the sim telemetry, and the events from liftoff-input, a session
+    /// per flight, until interrupted.
+    Record {
+        /// A session ends when the telemetry stops for this long, s.
+        #[arg(long, default_value_t = 1.0)]
+        gap: f64,
+    },
+    /// Run an SQL query on the log, and print the result, tab separated.
+    Query {
+        /// E.g. `SELECT * FROM sessions`.
+        sql: String,
+    },
+    /// Export the samples, of a session or all of them.
+    Export {
+        /// File to write.
+        output: PathBuf,
+
+        /// Session to export. Without it, all of them.
+        #[arg(long)]
+        session: Option<i64>,
+
+        #[arg(long, value_enum, default_value_t = Format::Csv)]
+        format: Format,
+    },
+}
+
+async fn record(
+    args: &Args,
+    mut logger: Logger,
+    gap: f64,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Zenoh session
+    let mut config = Config::default();
+    config.insert_json5("mode", &format!(r#""{}""#, args.zenoh_mode))?;
+    if let Some(ref endpoint) = args.zenoh_connect {
+        config.insert_json5("connect/endpoints", &format!(r#"["{}"]"#, endpoint))?;
+    }
+
+    let session = zenoh::open(config).await?;
+
+    let tel_topic = topics::topic(&args.zenoh_prefix, topics::TELEMETRY);
+    info!("Subscribing to: {}", tel_topic);
+    let tel_subscriber = session.declare_subscriber(&tel_topic).await?;
+    let events_topic = topics::topic(&args.zenoh_prefix, topics::EVENTS);
+    info!("Subscribing to: {}", events_topic);
+    let events_subscriber = session.declare_subscriber(&events_topic).await?;
+    info!("Logging to {}", args.db.display());
+
+    // Telemetry format config
+    // We assume default configuration for now
+    let format = telemetry::default_stream_format();
+    let gap_duration = Duration::from_secs_f64(gap);
+    // For telemetry without a timestamp.
+    let epoch = Instant::now();
+    let datetime = || Utc::now().to_rfc3339_opts(SecondsFormat::Millis, false);
+    loop {
+        tokio::select! {
+            sample = timeout(gap_duration, tel_subscriber.recv_async()) => match sample {
+                Ok(Ok(sample)) => {
+                    let payload = sample.payload().to_bytes();
+                    match telemetry::parse_packet(&payload, &format) {
+                        Ok(packet) => {
+                            let time = match packet.timestamp {
+                                Some(timestamp) => f64::from(timestamp),
+                                None => epoch.elapsed().as_secs_f64(),
+                            };
+                            logger.record(time, datetime, &packet)?;
+                        }
+                        Err(e) => debug!("Sim telemetry: {}", e),
+                    }
+                }
+                Ok(Err(e)) => {
+                    warn!("Sim telemetry subscriber error: {}", e);
+                    break;
+                }
+                Err(_) if logger.logging() => {
+                    info!("No telemetry for {} s", gap);
+                    logger.end()?;
+                }
+                Err(_) => {}
+            },
+            sample = events_subscriber.recv_async() => match sample {
+                Ok(sample) => match serde_json::from_slice::<Event>(&sample.payload().to_bytes()) {
+                    Ok(event) => logger.event(&event)?,
+                    Err(e) => warn!("Event: {}", e),
+                },
+                Err(e) => {
+                    warn!("Events subscriber error: {}", e);
+                    break;
+                }
+            },
+            _ = tokio::signal::ctrl_c() => {
+                info!("Shutdown signal received, exiting.");
+                break;
+            }
+        }
+    }
+    logger.end()?;
+
+    session.close().await?;
+    Ok(())
+}
+
+/// Run `sql`, and write the result to `out`: a line with the column names,
+/// then a line per row, tab separated, with NULL as nothing.
+fn query(
+    conn: &Connection,
+    sql: &str,
+    mut out: impl Write,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut stmt = conn.prepare(sql)?;
+    writeln!(out, "{}", stmt.column_names().join("\t"))?;
+    let columns = stmt.column_count();
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let mut values = Vec::with_capacity(columns);
+        for i in 0..columns {
+            values.push(match row.get_ref(i)? {
+                ValueRef::Null => String::new(),
+                ValueRef::Integer(v) => v.to_string(),
+                ValueRef::Real(v) => v.to_string(),
+                ValueRef::Text(v) => String::from_utf8_lossy(v).into_owned(),
+                ValueRef::Blob(v) => format!("<{} bytes>", v.len()),
+            });
+        }
+        writeln!(out, "{}", values.join("\t"))?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    env_logger::init();
+    let args = Args::parse();
+
+    info!("Starting liftoff-log");
+
+    let conn = Connection::open(&args.db)?;
+    db::init(&conn)?;
+    match args.command {
+        Command::Record { gap } => record(&args, Logger::new(conn)?, gap).await?,
+        Command::Query { ref sql } => query(&conn, sql, io::stdout().lock())?,
+        Command::Export {
+            ref output,
+            session,
+            format,
+        } => {
+            let out = BufWriter::new(File::create(output)?);
+            let count = export::export(&conn, session, format, out)?;
+            info!("Exported {} samples to {}", count, output.display());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_output() {
+        let conn = Connection::open_in_memory().unwrap();
+        db::init(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO sessions (start, distance, max_speed) VALUES ('2026-01-01', 12.5, NULL)",
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        query(
+            &conn,
+            "SELECT id, start, distance, max_speed FROM sessions",
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "id\tstart\tdistance\tmax_speed\n1\t2026-01-01\t12.5\t\n"
+        );
+        assert!(query(&conn, "SELECT nothing FROM nowhere", io::sink()).is_err());
+    }
+}
@@ -0,0 +1,214 @@
+//! The metrics of the pipeline, in one live view, without a Prometheus
+//! stack: connects to the metrics-exporter-tcp endpoints of the services
+//! (`--metrics-tcp`), adds up their counters, gauges and histograms, and
+//! shows them together. They can also be written to a CSV file, every
+//! interval.
+
+use clap::Parser;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use log::info;
+use ratatui::DefaultTerminal;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+mod proto;
+mod source;
+mod store;
+mod ui;
+
+use store::Store;
+
+/// The services' default `--metrics-tcp-bind` addresses.
+const DEFAULT_SOURCES: &[(&str, &str)] = &[
+    ("forward", "127.0.0.1:5000"),
+    ("input", "127.0.0.1:5002"),
+    ("gpsd", "127.0.0.1:5003"),
+    ("joystick", "127.0.0.1:5004"),
+];
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Exporter to connect to, as name=host:port. Can be given more than
+    /// once. Without it, the default addresses of crsf-forward (forward),
+    /// liftoff-input (input), crsf-gpsd (gpsd) and crsf-joystick
+    /// (joystick).
+    #[arg(long = "source", value_name = "NAME=HOST:PORT", value_parser = parse_source)]
+    sources: Vec<(String, String)>,
+
+    /// Time between updates of the rates and rows of the CSV file, in
+    /// milliseconds.
+    #[arg(long, default_value_t = 1000)]
+    interval: u64,
+
+    /// Append the metrics to this CSV file, every interval.
+    #[arg(long)]
+    csv: Option<PathBuf>,
+
+    /// Don't show the live view, only write the CSV file.
+    #[arg(long, requires = "csv")]
+    headless: bool,
+}
+
+fn parse_source(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((name, addr)) if !name.is_empty() && !addr.is_empty() => {
+            Ok((name.to_string(), addr.to_string()))
+        }
+        _ => Err(format!("expected name=host:port, got {}", s)),
+    }
+}
+
+/// Appends the rows of the store to a CSV file.
+struct Csv(BufWriter<File>);
+
+impl Csv {
+    fn create(path: &PathBuf) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let file = File::options()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        let empty = file.metadata()?.len() == 0;
+        let mut out = BufWriter::new(file);
+        if empty {
+            writeln!(out, "{}", store::CSV_HEADER)?;
+        }
+        Ok(Self(out))
+    }
+
+    fn write(&mut self, store: &Store) -> std::io::Result<()> {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        for row in store.rows() {
+            writeln!(self.0, "{}", row.csv(time))?;
+        }
+        self.0.flush()
+    }
+}
+
+/// Every interval: work out the rates, and write the CSV rows.
+fn tick(
+    store: &Mutex<Store>,
+    csv: &mut Option<Csv>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut store = store.lock().unwrap();
+    store.tick(Instant::now());
+    if let Some(csv) = csv {
+        csv.write(&store)?;
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    env_logger::init();
+    let args = Args::parse();
+
+    let sources = if args.sources.is_empty() {
+        DEFAULT_SOURCES
+            .iter()
+            .map(|&(name, addr)| (name.to_string(), addr.to_string()))
+            .collect()
+    } else {
+        args.sources
+    };
+    let store = Arc::new(Mutex::new(Store::new(
+        sources.iter().map(|(name, _)| name.clone()),
+    )));
+    for (name, addr) in sources {
+        info!("Connecting to {} at {}", name, addr);
+        source::spawn(name, addr, store.clone());
+    }
+
+    let mut csv = args.csv.as_ref().map(Csv::create).transpose()?;
+    let interval = Duration::from_millis(args.interval.max(1));
+
+    if args.headless {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await;
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => tick(&store, &mut csv)?,
+                _ = tokio::signal::ctrl_c() => {
+                    info!("Shutdown signal received, exiting.");
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    // Terminal UI
+    let mut terminal = ratatui::init();
+    let result = run_tui(&mut terminal, store, interval, csv);
+    ratatui::restore();
+    result
+}
+
+fn run_tui(
+    terminal: &mut DefaultTerminal,
+    store: Arc<Mutex<Store>>,
+    interval: Duration,
+    mut csv: Option<Csv>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let frame = Duration::from_millis(100); // ~10 Hz refresh
+    let mut next_tick = Instant::now() + interval;
+    let mut offset = 0usize;
+
+    loop {
+        if Instant::now() >= next_tick {
+            tick(&store, &mut csv)?;
+            next_tick += interval;
+        }
+
+        let page = ui::visible_rows(terminal.size()?.height).max(1);
+        {
+            let lock = store.lock().unwrap();
+            offset = offset.min(lock.rows().len().saturating_sub(page));
+            terminal.draw(|f| ui::draw(f, &lock, offset))?;
+        }
+
+        if event::poll(frame)?
+            && let Event::Key(key) = event::read()?
+        {
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('q') => return Ok(()),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(());
+                }
+                KeyCode::Char('l') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    terminal.clear()?;
+                }
+                KeyCode::Up => offset = offset.saturating_sub(1),
+                KeyCode::Down => offset += 1,
+                KeyCode::PageUp => offset = offset.saturating_sub(page),
+                KeyCode::PageDown => offset += page,
+                KeyCode::Home => offset = 0,
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sources() {
+        assert_eq!(
+            parse_source("forward=127.0.0.1:5000"),
+            Ok(("forward".to_string(), "127.0.0.1:5000".to_string()))
+        );
+        assert!(parse_source("127.0.0.1:5000").is_err());
+        assert!(parse_source("=127.0.0.1:5000").is_err());
+    }
+}
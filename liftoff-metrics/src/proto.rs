@@ -0,0 +1,119 @@
+//! The events of metrics-exporter-tcp: protobuf messages (its
+//! `event.proto`), each preceded by its length as a varint.
+//!
+//! The oneofs of single fields are plain optional fields here, which is
+//! the same on the wire. The timestamp of a metric is left out; the time
+//! it is received is close enough.
+
+use bytes::{Buf, BytesMut};
+use prost::Message;
+use std::collections::HashMap;
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Event {
+    #[prost(oneof = "Kind", tags = "1, 2")]
+    pub kind: Option<Kind>,
+}
+
+#[derive(Clone, PartialEq, prost::Oneof)]
+pub enum Kind {
+    #[prost(message, tag = "1")]
+    Metadata(Metadata),
+    #[prost(message, tag = "2")]
+    Metric(Metric),
+}
+
+/// Description of a metric, sent once per connection.
+#[derive(Clone, PartialEq, Message)]
+pub struct Metadata {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, optional, tag = "3")]
+    pub unit: Option<String>,
+    #[prost(string, optional, tag = "4")]
+    pub description: Option<String>,
+}
+
+/// An update of a metric.
+#[derive(Clone, PartialEq, Message)]
+pub struct Metric {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(map = "string, string", tag = "3")]
+    pub labels: HashMap<String, String>,
+    #[prost(oneof = "Operation", tags = "4, 5, 6, 7, 8, 9")]
+    pub operation: Option<Operation>,
+}
+
+#[derive(Clone, Copy, PartialEq, prost::Oneof)]
+pub enum Operation {
+    #[prost(uint64, tag = "4")]
+    IncrementCounter(u64),
+    #[prost(uint64, tag = "5")]
+    SetCounter(u64),
+    #[prost(double, tag = "6")]
+    IncrementGauge(f64),
+    #[prost(double, tag = "7")]
+    DecrementGauge(f64),
+    #[prost(double, tag = "8")]
+    SetGauge(f64),
+    #[prost(double, tag = "9")]
+    RecordHistogram(f64),
+}
+
+/// Take the next whole event from the front of `buf`; `None` if it hasn't
+/// all arrived yet.
+pub fn next(buf: &mut BytesMut) -> Result<Option<Event>, prost::DecodeError> {
+    let mut peek = &buf[..];
+    let len = match prost::decode_length_delimiter(&mut peek) {
+        Ok(len) => len,
+        // A varint is at most 10 bytes; shorter may be cut off.
+        Err(_) if buf.len() < 10 => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let start = buf.len() - peek.len();
+    if peek.len() < len {
+        return Ok(None);
+    }
+    buf.advance(start);
+    let event = Event::decode(buf.split_to(len))?;
+    Ok(Some(event))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events() {
+        let metric = Event {
+            kind: Some(Kind::Metric(Metric {
+                name: "crsf.rx.frames".into(),
+                labels: HashMap::from([("kind".into(), "rc".into())]),
+                operation: Some(Operation::IncrementCounter(3)),
+            })),
+        };
+        let metadata = Event {
+            kind: Some(Kind::Metadata(Metadata {
+                name: "crsf.rx.count".into(),
+                unit: Some("count".into()),
+                description: None,
+            })),
+        };
+        let mut stream = BytesMut::new();
+        stream.extend_from_slice(&metric.encode_length_delimited_to_vec());
+        stream.extend_from_slice(&metadata.encode_length_delimited_to_vec());
+
+        // Arriving a byte at a time.
+        let mut buf = BytesMut::new();
+        let mut events = Vec::new();
+        for byte in stream {
+            buf.extend_from_slice(&[byte]);
+            while let Some(event) = next(&mut buf).unwrap() {
+                events.push(event);
+            }
+        }
+        assert_eq!(events, [metric, metadata]);
+        assert!(buf.is_empty());
+    }
+}
@@ -0,0 +1,56 @@
+//! Connections to the exporters, kept up while the viewer runs.
+
+use bytes::BytesMut;
+use log::{debug, info, warn};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+
+use crate::proto;
+use crate::store::Store;
+
+/// Wait before connecting again, to a source that isn't running or went
+/// away.
+const RETRY: Duration = Duration::from_secs(2);
+
+/// Read the events of `stream` into `store` until it closes.
+async fn read(
+    name: &str,
+    mut stream: TcpStream,
+    store: &Mutex<Store>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut buf = BytesMut::with_capacity(4096);
+    loop {
+        if stream.read_buf(&mut buf).await? == 0 {
+            return Ok(());
+        }
+        let mut store = store.lock().unwrap();
+        while let Some(event) = proto::next(&mut buf)? {
+            store.apply(name, event);
+        }
+    }
+}
+
+/// Keep a connection to the exporter of source `name` at `addr`, adding
+/// its events to `store`.
+pub fn spawn(name: String, addr: String, store: Arc<Mutex<Store>>) {
+    tokio::spawn(async move {
+        loop {
+            match TcpStream::connect(&addr).await {
+                Ok(stream) => {
+                    info!("{}: connected to {}", name, addr);
+                    store.lock().unwrap().set_connected(&name, true);
+                    let result = read(&name, stream, &store).await;
+                    store.lock().unwrap().set_connected(&name, false);
+                    match result {
+                        Ok(()) => info!("{}: connection closed", name),
+                        Err(e) => warn!("{}: {}", name, e),
+                    }
+                }
+                Err(e) => debug!("{}: {}: {}", name, addr, e),
+            }
+            tokio::time::sleep(RETRY).await;
+        }
+    });
+}
@@ -0,0 +1,386 @@
+//! The metrics of all the sources, added up from their events.
+//!
+//! The exporter only sends what happens while a client is connected, so
+//! counters count from when the connection was made. Histograms keep their
+//! latest [`WINDOW`] samples, which the percentiles are taken over.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::Instant;
+
+use crate::proto::{Event, Kind, Operation};
+
+/// Samples of a histogram kept for its percentiles.
+pub const WINDOW: usize = 1024;
+
+/// A metric of a source. Labels are `key=value`, sorted, joined with `;`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Key {
+    pub source: String,
+    pub name: String,
+    pub labels: String,
+}
+
+#[derive(Debug)]
+enum Value {
+    Counter {
+        total: u64,
+        /// Total at the last [`Store::tick`], and when that was; `None`
+        /// until the first, so that the first rate covers a whole interval.
+        last: Option<(Instant, u64)>,
+        rate: Option<f64>,
+    },
+    Gauge(f64),
+    Histogram {
+        count: u64,
+        samples: VecDeque<f64>,
+    },
+}
+
+/// The latest samples of a histogram.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    /// Samples ever recorded.
+    pub count: u64,
+    pub mean: f64,
+    pub p50: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reading {
+    Counter { total: u64, rate: Option<f64> },
+    Gauge(f64),
+    Histogram(Summary),
+}
+
+impl Reading {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Reading::Counter { .. } => "counter",
+            Reading::Gauge(_) => "gauge",
+            Reading::Histogram(_) => "histogram",
+        }
+    }
+}
+
+pub struct Row<'a> {
+    pub key: &'a Key,
+    pub unit: Option<&'a str>,
+    pub reading: Reading,
+}
+
+pub const CSV_HEADER: &str =
+    "time,source,metric,labels,unit,type,value,rate,count,mean,p50,p99,max";
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+impl Row<'_> {
+    /// The row as a line of CSV, at `time` (seconds since the epoch).
+    pub fn csv(&self, time: f64) -> String {
+        let empty = String::new;
+        let (value, rate, summary) = match self.reading {
+            Reading::Counter { total, rate } => (
+                total.to_string(),
+                rate.map(|r| format!("{:.3}", r)).unwrap_or_else(empty),
+                None,
+            ),
+            Reading::Gauge(value) => (value.to_string(), empty(), None),
+            Reading::Histogram(summary) => (empty(), empty(), Some(summary)),
+        };
+        let summary = summary.map_or_else(
+            || ",,,,".to_string(),
+            |s| format!("{},{},{},{},{}", s.count, s.mean, s.p50, s.p99, s.max),
+        );
+        format!(
+            "{:.3},{},{},{},{},{},{},{},{}",
+            time,
+            csv_field(&self.key.source),
+            csv_field(&self.key.name),
+            csv_field(&self.key.labels),
+            csv_field(self.unit.unwrap_or_default()),
+            self.reading.kind(),
+            value,
+            rate,
+            summary
+        )
+    }
+}
+
+/// Percentile `p` (0 to 1) of `sorted`, which isn't empty.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let index = ((sorted.len() - 1) as f64 * p).round() as usize;
+    sorted[index]
+}
+
+fn summary(count: u64, samples: &VecDeque<f64>) -> Summary {
+    let mut sorted: Vec<f64> = samples.iter().copied().collect();
+    sorted.sort_by(f64::total_cmp);
+    if sorted.is_empty() {
+        return Summary {
+            count,
+            mean: 0.0,
+            p50: 0.0,
+            p99: 0.0,
+            max: 0.0,
+        };
+    }
+    Summary {
+        count,
+        mean: sorted.iter().sum::<f64>() / sorted.len() as f64,
+        p50: percentile(&sorted, 0.5),
+        p99: percentile(&sorted, 0.99),
+        max: sorted[sorted.len() - 1],
+    }
+}
+
+pub struct Store {
+    /// Sources in the order given, and whether each is connected.
+    sources: Vec<(String, bool)>,
+    metrics: BTreeMap<Key, Value>,
+    /// Units of the metrics of each source, by name.
+    units: HashMap<(String, String), String>,
+}
+
+impl Store {
+    pub fn new(sources: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            sources: sources.into_iter().map(|s| (s, false)).collect(),
+            metrics: BTreeMap::new(),
+            units: HashMap::new(),
+        }
+    }
+
+    pub fn sources(&self) -> &[(String, bool)] {
+        &self.sources
+    }
+
+    pub fn set_connected(&mut self, source: &str, connected: bool) {
+        if let Some(s) = self.sources.iter_mut().find(|(name, _)| name == source) {
+            s.1 = connected;
+        }
+    }
+
+    /// Add `event` from `source`.
+    pub fn apply(&mut self, source: &str, event: Event) {
+        let metric = match event.kind {
+            Some(Kind::Metadata(metadata)) => {
+                if let Some(unit) = metadata.unit {
+                    self.units.insert((source.to_string(), metadata.name), unit);
+                }
+                return;
+            }
+            Some(Kind::Metric(metric)) => metric,
+            None => return,
+        };
+        let Some(operation) = metric.operation else {
+            return;
+        };
+        let mut labels: Vec<String> = metric
+            .labels
+            .into_iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect();
+        labels.sort();
+        let key = Key {
+            source: source.to_string(),
+            name: metric.name,
+            labels: labels.join(";"),
+        };
+        let value = self.metrics.entry(key).or_insert_with(|| match operation {
+            Operation::IncrementCounter(_) | Operation::SetCounter(_) => Value::Counter {
+                total: 0,
+                last: None,
+                rate: None,
+            },
+            Operation::IncrementGauge(_)
+            | Operation::DecrementGauge(_)
+            | Operation::SetGauge(_) => Value::Gauge(0.0),
+            Operation::RecordHistogram(_) => Value::Histogram {
+                count: 0,
+                samples: VecDeque::with_capacity(WINDOW),
+            },
+        });
+        match (value, operation) {
+            (Value::Counter { total, .. }, Operation::IncrementCounter(n)) => {
+                *total = total.saturating_add(n);
+            }
+            (Value::Counter { total, .. }, Operation::SetCounter(n)) => *total = n,
+            (Value::Gauge(value), Operation::IncrementGauge(n)) => *value += n,
+            (Value::Gauge(value), Operation::DecrementGauge(n)) => *value -= n,
+            (Value::Gauge(value), Operation::SetGauge(n)) => *value = n,
+            (Value::Histogram { count, samples }, Operation::RecordHistogram(n)) => {
+                *count += 1;
+                if samples.len() == WINDOW {
+                    samples.pop_front();
+                }
+                samples.push_back(n);
+            }
+            // Another kind under the same name; the first one stays.
+            _ => {}
+        }
+    }
+
+    /// Work out the rates of the counters since the last tick.
+    pub fn tick(&mut self, now: Instant) {
+        for value in self.metrics.values_mut() {
+            if let Value::Counter { total, last, rate } = value {
+                match *last {
+                    Some((then, before)) => {
+                        let elapsed = now.duration_since(then).as_secs_f64();
+                        if elapsed > 0.0 {
+                            *rate = Some(total.saturating_sub(before) as f64 / elapsed);
+                            *last = Some((now, *total));
+                        }
+                    }
+                    None => *last = Some((now, *total)),
+                }
+            }
+        }
+    }
+
+    /// All metrics, by source, name and labels.
+    pub fn rows(&self) -> Vec<Row<'_>> {
+        self.metrics
+            .iter()
+            .map(|(key, value)| Row {
+                key,
+                unit: self
+                    .units
+                    .get(&(key.source.clone(), key.name.clone()))
+                    .map(String::as_str),
+                reading: match value {
+                    Value::Counter { total, rate, .. } => Reading::Counter {
+                        total: *total,
+                        rate: *rate,
+                    },
+                    Value::Gauge(value) => Reading::Gauge(*value),
+                    Value::Histogram { count, samples } => {
+                        Reading::Histogram(summary(*count, samples))
+                    }
+                },
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::{Metadata, Metric};
+    use std::time::Duration;
+
+    fn metric(name: &str, labels: &[(&str, &str)], operation: Operation) -> Event {
+        Event {
+            kind: Some(Kind::Metric(Metric {
+                name: name.into(),
+                labels: labels.iter().map(|&(k, v)| (k.into(), v.into())).collect(),
+                operation: Some(operation),
+            })),
+        }
+    }
+
+    #[test]
+    fn store() {
+        let start = Instant::now();
+        let mut store = Store::new(["forward".to_string()]);
+        store.apply(
+            "forward",
+            Event {
+                kind: Some(Kind::Metadata(Metadata {
+                    name: "crsf.rx.count".into(),
+                    unit: Some("count".into()),
+                    description: None,
+                })),
+            },
+        );
+        let count = metric("crsf.rx.count", &[], Operation::IncrementCounter(2));
+        for _ in 0..10 {
+            store.apply("forward", count.clone());
+        }
+        // No rate until a whole interval has passed.
+        store.tick(start);
+        assert_eq!(
+            store.rows()[0].reading,
+            Reading::Counter {
+                total: 20,
+                rate: None
+            }
+        );
+        for _ in 0..10 {
+            store.apply("forward", count.clone());
+        }
+        store.apply(
+            "forward",
+            metric(
+                "crsf.tx.queue_depth",
+                &[("queue", "reply")],
+                Operation::SetGauge(3.0),
+            ),
+        );
+        store.apply(
+            "forward",
+            metric(
+                "crsf.tx.queue_depth",
+                &[("queue", "reply")],
+                Operation::DecrementGauge(1.0),
+            ),
+        );
+        for i in 1..=WINDOW + 100 {
+            store.apply(
+                "input",
+                metric(
+                    "crsf.rx.rc_interval",
+                    &[],
+                    Operation::RecordHistogram(i as f64),
+                ),
+            );
+        }
+        store.tick(start + Duration::from_secs(2));
+
+        let rows = store.rows();
+        let names: Vec<_> = rows
+            .iter()
+            .map(|r| (&*r.key.source, &*r.key.name))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("forward", "crsf.rx.count"),
+                ("forward", "crsf.tx.queue_depth"),
+                ("input", "crsf.rx.rc_interval")
+            ]
+        );
+        assert_eq!(rows[0].unit, Some("count"));
+        assert_eq!(
+            rows[0].reading,
+            Reading::Counter {
+                total: 40,
+                rate: Some(10.0)
+            }
+        );
+        assert_eq!(rows[1].key.labels, "queue=reply");
+        assert_eq!(rows[1].reading, Reading::Gauge(2.0));
+        // Only the latest WINDOW samples: 101 to 1124.
+        let Reading::Histogram(summary) = rows[2].reading else {
+            panic!("not a histogram");
+        };
+        assert_eq!(summary.count, (WINDOW + 100) as u64);
+        assert_eq!((summary.p50, summary.max), (613.0, 1124.0));
+
+        assert_eq!(
+            rows[0].csv(1.5),
+            "1.500,forward,crsf.rx.count,,count,counter,40,10.000,,,,,"
+        );
+        assert_eq!(
+            rows[2].csv(1.5),
+            "1.500,input,crsf.rx.rc_interval,,,histogram,,,1124,612.5,613,1114,1124"
+        );
+    }
+}
@@ -0,0 +1,165 @@
+//! Drawing: the sources and whether they are connected on top, all their
+//! metrics in one table below, in the panel style of telemetry-dashboard.
+
+use ratatui::Frame;
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Cell, Padding, Paragraph, Row, Table};
+
+use crate::store::{self, Reading};
+
+/// The colours of telemetry-dashboard.
+mod theme {
+    use ratatui::style::Color;
+
+    pub const BG_PANEL: Color = Color::Rgb(0x1c, 0x17, 0x1f);
+    pub const TEXT: Color = Color::Rgb(0xdf, 0xb0, 0xff);
+    pub const TEXT_MUTED: Color = Color::Rgb(0x88, 0x6f, 0x99);
+    pub const PRIMARY: Color = Color::Rgb(0xa2, 0x9d, 0xf0);
+    pub const SUCCESS: Color = Color::Rgb(0xa7, 0xda, 0x1e);
+    pub const ERROR: Color = Color::Rgb(0xe6, 0x1f, 0x44);
+}
+
+fn panel_block(title: String) -> Block<'static> {
+    let title_line = Line::from(vec![
+        Span::styled(" ▎", Style::default().fg(theme::PRIMARY)),
+        Span::styled(
+            format!(" {title} "),
+            Style::default()
+                .fg(theme::PRIMARY)
+                .add_modifier(Modifier::BOLD),
+        ),
+    ]);
+    Block::default()
+        .borders(Borders::NONE)
+        .style(Style::default().bg(theme::BG_PANEL).fg(theme::TEXT))
+        .title(title_line)
+        .padding(Padding::new(1, 2, 1, 1))
+}
+
+/// `value` short enough for a column: whole numbers as they are, others
+/// to three significant decimals or so.
+fn number(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e12 {
+        format!("{:.0}", value)
+    } else if value.abs() >= 100.0 {
+        format!("{:.1}", value)
+    } else {
+        format!("{:.3}", value)
+    }
+}
+
+/// Rows of the table that fit, from `offset` on.
+pub fn visible_rows(height: u16) -> usize {
+    // Sources panel, then the table's padding, title and header.
+    usize::from(height.saturating_sub(5 + 4))
+}
+
+pub fn draw(f: &mut Frame, store: &store::Store, offset: usize) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(5), Constraint::Min(0)])
+        .split(f.area());
+
+    let mut sources = Vec::new();
+    for (name, connected) in store.sources() {
+        let (mark, color) = if *connected {
+            ("●", theme::SUCCESS)
+        } else {
+            ("○", theme::ERROR)
+        };
+        sources.push(Span::styled(
+            format!("{} ", mark),
+            Style::default().fg(color),
+        ));
+        sources.push(Span::raw(format!("{}   ", name)));
+    }
+    let help = Line::from(Span::styled(
+        "↑/↓ PgUp/PgDn scroll, q quit",
+        Style::default().fg(theme::TEXT_MUTED),
+    ));
+    f.render_widget(
+        Paragraph::new(vec![Line::from(sources), help]).block(panel_block("Sources".into())),
+        chunks[0],
+    );
+
+    let rows = store.rows();
+    let total = rows.len();
+    let table_rows: Vec<Row> = rows
+        .into_iter()
+        .skip(offset)
+        .map(|row| {
+            let (value, rate, p50, p99, max) = match row.reading {
+                Reading::Counter { total, rate } => (
+                    total.to_string(),
+                    rate.map(number).unwrap_or_default(),
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                ),
+                Reading::Gauge(value) => (
+                    number(value),
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                ),
+                Reading::Histogram(s) => (
+                    number(s.mean),
+                    String::new(),
+                    number(s.p50),
+                    number(s.p99),
+                    number(s.max),
+                ),
+            };
+            Row::new(vec![
+                Cell::from(row.key.source.clone()).style(Style::default().fg(theme::TEXT_MUTED)),
+                Cell::from(row.key.name.clone()),
+                Cell::from(row.key.labels.clone()).style(Style::default().fg(theme::TEXT_MUTED)),
+                Cell::from(value),
+                Cell::from(row.unit.unwrap_or_default().to_string())
+                    .style(Style::default().fg(theme::TEXT_MUTED)),
+                Cell::from(rate),
+                Cell::from(p50),
+                Cell::from(p99),
+                Cell::from(max),
+            ])
+        })
+        .collect();
+    let header = Row::new([
+        "Source", "Metric", "Labels", "Value", "Unit", "Rate/s", "p50", "p99", "Max",
+    ])
+    .style(
+        Style::default()
+            .fg(theme::PRIMARY)
+            .add_modifier(Modifier::BOLD),
+    );
+    let widths = [
+        Constraint::Length(10),
+        Constraint::Min(24),
+        Constraint::Length(18),
+        Constraint::Length(12),
+        Constraint::Length(12),
+        Constraint::Length(10),
+        Constraint::Length(10),
+        Constraint::Length(10),
+        Constraint::Length(10),
+    ];
+    let title = if total == 0 {
+        "Metrics".to_string()
+    } else {
+        format!(
+            "Metrics ({}-{} of {})",
+            offset.min(total - 1) + 1,
+            total.min(offset + visible_rows(f.area().height)),
+            total
+        )
+    };
+    f.render_widget(
+        Table::new(table_rows, widths)
+            .header(header)
+            .block(panel_block(title)),
+        chunks[1],
+    );
+}
@@ -0,0 +1,375 @@
+//! Decoding CRSF frames and sim telemetry into messages, printed as a line
+//! of text or JSON.
+//!
+//! The values are in the units of the radio: degrees, meters, km/h, volts,
+//! and RC channels in µs.
+
+use clap::ValueEnum;
+use serde_json::{Map, Value, json};
+use telemetry_lib::crsf::{self, CrsfPacket, PacketType};
+use telemetry_lib::telemetry::TelemetryPacket;
+
+/// What a message is, for `--type`.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// RC channels.
+    Rc,
+    /// Link statistics.
+    Link,
+    Attitude,
+    Gps,
+    Battery,
+    Vario,
+    /// Barometric altitude.
+    Baro,
+    Airspeed,
+    Rpm,
+    /// Cell voltages.
+    Voltages,
+    /// Flight mode.
+    Mode,
+    /// Damage of the quad in the sim.
+    Damage,
+    /// Frames with addresses: device ping and info, parameters, commands.
+    Device,
+    /// Other CRSF frames.
+    Other,
+    /// Sim telemetry.
+    Telemetry,
+    /// Bytes that are not part of a frame.
+    Garbage,
+}
+
+impl Kind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Rc => "rc",
+            Kind::Link => "link",
+            Kind::Attitude => "attitude",
+            Kind::Gps => "gps",
+            Kind::Battery => "battery",
+            Kind::Vario => "vario",
+            Kind::Baro => "baro",
+            Kind::Airspeed => "airspeed",
+            Kind::Rpm => "rpm",
+            Kind::Voltages => "voltages",
+            Kind::Mode => "mode",
+            Kind::Damage => "damage",
+            Kind::Device => "device",
+            Kind::Other => "other",
+            Kind::Telemetry => "telemetry",
+            Kind::Garbage => "garbage",
+        }
+    }
+
+    fn of(packet_type: Option<PacketType>) -> Self {
+        match packet_type {
+            Some(PacketType::RcChannelsPacked | PacketType::RcChannelsSubset) => Kind::Rc,
+            Some(
+                PacketType::LinkStatistics
+                | PacketType::LinkStatisticsRx
+                | PacketType::LinkStatisticsTx,
+            ) => Kind::Link,
+            Some(PacketType::Attitude) => Kind::Attitude,
+            Some(PacketType::Gps) => Kind::Gps,
+            Some(PacketType::BatterySensor) => Kind::Battery,
+            Some(PacketType::Vario) => Kind::Vario,
+            Some(PacketType::BaroAlt) => Kind::Baro,
+            Some(PacketType::Airspeed) => Kind::Airspeed,
+            Some(PacketType::Rpm) => Kind::Rpm,
+            Some(PacketType::Voltages) => Kind::Voltages,
+            Some(PacketType::FlightMode) => Kind::Mode,
+            Some(PacketType::Damage) => Kind::Damage,
+            Some(
+                PacketType::DevicePing
+                | PacketType::DeviceInfo
+                | PacketType::ConfigRead
+                | PacketType::ConfigWrite
+                | PacketType::Command,
+            ) => Kind::Device,
+            _ => Kind::Other,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub kind: Kind,
+    /// Address of a CRSF frame.
+    pub address: Option<u8>,
+    /// What is wrong with it, if it couldn't be decoded.
+    pub error: Option<&'static str>,
+    /// Names and values, in order.
+    pub fields: Vec<(&'static str, Value)>,
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// `value` rounded to `decimals`.
+fn round(value: f64, decimals: i32) -> Value {
+    let scale = 10f64.powi(decimals);
+    json!((value * scale).round() / scale)
+}
+
+/// `value` as short as it prints as f32.
+fn float(value: f32) -> Value {
+    value
+        .to_string()
+        .parse::<f64>()
+        .map_or(Value::Null, Value::from)
+}
+
+fn floats(values: &[f32]) -> Value {
+    values.iter().copied().map(float).collect()
+}
+
+fn packet_fields(packet: &CrsfPacket) -> Vec<(&'static str, Value)> {
+    match packet {
+        CrsfPacket::Attitude(a) => {
+            let (pitch, roll, yaw) = a.as_radians();
+            vec![
+                ("pitch", round(pitch.to_degrees(), 1)),
+                ("roll", round(roll.to_degrees(), 1)),
+                ("yaw", round(yaw.to_degrees(), 1)),
+            ]
+        }
+        CrsfPacket::Gps(g) => vec![
+            ("lat", json!(g.lat_deg())),
+            ("lon", json!(g.lon_deg())),
+            ("alt", json!(g.alt_m())),
+            ("speed", json!(g.speed_kmh())),
+            ("heading", json!(g.heading_deg())),
+            ("sats", json!(g.sats)),
+        ],
+        CrsfPacket::Battery(b) => vec![
+            ("voltage", json!(b.voltage_v())),
+            ("current", json!(b.current_a())),
+            ("capacity", json!(b.capacity)),
+            ("remaining", json!(b.remaining)),
+        ],
+        CrsfPacket::Vario(v) => vec![("vertical_speed", json!(v.vertical_speed_ms()))],
+        CrsfPacket::FlightMode(m) => vec![("mode", json!(m.mode))],
+        CrsfPacket::BaroAlt(b) => vec![
+            ("alt", json!(b.alt_m())),
+            ("vertical_speed", round(b.vertical_speed_ms(), 2)),
+        ],
+        CrsfPacket::Airspeed(a) => vec![("speed", json!(a.speed_kmh()))],
+        CrsfPacket::Rpm(r) => vec![("source", json!(r.source_id)), ("rpm", json!(r.rpms))],
+        CrsfPacket::Voltages(v) => vec![
+            ("source", json!(v.source_id)),
+            (
+                "cells",
+                v.voltages_mv
+                    .iter()
+                    .map(|&mv| json!(f64::from(mv) / 1000.0))
+                    .collect(),
+            ),
+        ],
+        CrsfPacket::RcChannelsPacked(rc) => vec![(
+            "channels",
+            rc.channels.iter().map(|&t| crsf::ticks_to_us(t)).collect(),
+        )],
+        CrsfPacket::RcChannelsSubset(rc) => vec![
+            ("first", json!(rc.first_channel)),
+            ("resolution", json!(rc.resolution)),
+            (
+                "channels",
+                rc.channels().into_iter().map(crsf::ticks_to_us).collect(),
+            ),
+        ],
+        CrsfPacket::LinkStatistics(l) => vec![
+            ("rssi", json!(l.rssi)),
+            ("lq", json!(l.lq)),
+            ("snr", json!(l.snr)),
+            ("rf_mode", json!(l.rf_mode)),
+            ("tx_power", json!(l.tx_power)),
+            ("tx_auc", json!(l.tx_auc)),
+            ("rx_auc", json!(l.rx_auc)),
+            ("rssi_rx", json!(l.rssi_rx)),
+            ("lq_rx", json!(l.lq_rx)),
+            ("snr_rx", json!(l.snr_rx)),
+        ],
+        CrsfPacket::Damage(d) => vec![
+            ("flags", json!(d.flags)),
+            (
+                "health",
+                d.health
+                    .iter()
+                    .map(|&h| json!(f64::from(h) / 100.0))
+                    .collect(),
+            ),
+        ],
+        CrsfPacket::Unknown(_) => Vec::new(),
+    }
+}
+
+/// Decode a CRSF frame, `valid` unless its checksum is wrong.
+pub fn frame(data: &[u8], valid: bool) -> Message {
+    let type_byte = data.get(2).copied();
+    let packet_type = type_byte.and_then(|t| PacketType::try_from(t).ok());
+    let packet = valid.then(|| crsf::parse_packet(data)).flatten();
+    let fields = match &packet {
+        Some(CrsfPacket::Unknown(_)) | None => {
+            let name = match packet_type {
+                Some(t) => format!("{:?}", t),
+                None => format!("0x{:02x}", type_byte.unwrap_or_default()),
+            };
+            let data = hex(data.get(3..).unwrap_or_default());
+            vec![("type", json!(name)), ("data", json!(data))]
+        }
+        Some(packet) => packet_fields(packet),
+    };
+    let error = if !valid {
+        Some("bad CRC")
+    } else if packet.is_none() && packet_type.is_some() {
+        Some("malformed")
+    } else {
+        None
+    };
+    Message {
+        kind: Kind::of(packet_type),
+        address: data.first().copied(),
+        error,
+        fields,
+    }
+}
+
+/// Decode a sim telemetry packet.
+pub fn telemetry(packet: &TelemetryPacket) -> Message {
+    let values = [
+        ("time", packet.timestamp.map(float)),
+        ("position", packet.position.map(|v| floats(&v))),
+        ("attitude", packet.attitude.map(|v| floats(&v))),
+        ("velocity", packet.velocity.map(|v| floats(&v))),
+        ("gyro", packet.gyro.map(|v| floats(&v))),
+        ("input", packet.input.map(|v| floats(&v))),
+        ("battery", packet.battery.map(|v| floats(&v))),
+        ("rpm", packet.motor_rpm.as_deref().map(floats)),
+    ];
+    Message {
+        kind: Kind::Telemetry,
+        address: None,
+        error: None,
+        fields: values
+            .into_iter()
+            .filter_map(|(name, value)| Some((name, value?)))
+            .collect(),
+    }
+}
+
+pub fn garbage(data: &[u8]) -> Message {
+    Message {
+        kind: Kind::Garbage,
+        address: None,
+        error: None,
+        fields: vec![("data", json!(hex(data)))],
+    }
+}
+
+impl Message {
+    /// A line of text, at `time` s.
+    pub fn text(&self, time: f64) -> String {
+        let mut line = format!("{:12.6} {:<9}", time, self.kind.name());
+        if let Some(address) = self.address {
+            line += &format!(" [{:02x}]", address);
+        }
+        if let Some(error) = self.error {
+            line += &format!(" ({})", error);
+        }
+        for (name, value) in &self.fields {
+            match value {
+                Value::String(s) => line += &format!(" {}={}", name, s),
+                _ => line += &format!(" {}={}", name, value),
+            }
+        }
+        line
+    }
+
+    /// A JSON object, at `time` s.
+    pub fn json(&self, time: f64) -> Value {
+        let mut object = Map::new();
+        object.insert("time".into(), round(time, 6));
+        object.insert("kind".into(), json!(self.kind.name()));
+        if let Some(address) = self.address {
+            object.insert("address".into(), json!(address));
+        }
+        if let Some(error) = self.error {
+            object.insert("error".into(), json!(error));
+        }
+        for (name, value) in &self.fields {
+            object.insert(name.to_string(), value.clone());
+        }
+        Value::Object(object)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use telemetry_lib::crsf::{Battery, device_address};
+
+    #[test]
+    fn messages() {
+        let battery = crsf::build_packet(
+            device_address::FLIGHT_CONTROLLER,
+            &CrsfPacket::Battery(Battery {
+                voltage: 162,
+                current: 35,
+                capacity: 650,
+                remaining: 80,
+            }),
+        )
+        .unwrap();
+        let message = frame(&battery, true);
+        assert_eq!(message.kind, Kind::Battery);
+        assert_eq!(
+            message.text(1.5),
+            "    1.500000 battery   [c8] voltage=16.2 current=3.5 capacity=650 remaining=80"
+        );
+        assert_eq!(
+            message.json(1.5),
+            json!({
+                "time": 1.5,
+                "kind": "battery",
+                "address": 0xc8,
+                "voltage": 16.2,
+                "current": 3.5,
+                "capacity": 650,
+                "remaining": 80,
+            })
+        );
+
+        let mut bad = battery.clone();
+        *bad.last_mut().unwrap() ^= 1;
+        assert_eq!(
+            frame(&bad, false).text(0.0),
+            "    0.000000 battery   [c8] (bad CRC) type=BatterySensor data=00a2002300028a5012"
+        );
+        let ping = [0xc8, 4, 0x28, 0x00, 0xea, 0x54];
+        let message = frame(&ping, true);
+        assert_eq!(message.kind, Kind::Device);
+        assert_eq!(message.error, None);
+        assert_eq!(message.fields[0], ("type", json!("DevicePing")));
+
+        let packet = TelemetryPacket {
+            timestamp: Some(2.1),
+            position: Some([1.0, 2.0, 0.1]),
+            attitude: None,
+            velocity: None,
+            gyro: None,
+            input: None,
+            battery: Some([0.9, 16.4]),
+            motor_rpm: None,
+        };
+        assert_eq!(
+            telemetry(&packet).text(3.0),
+            "    3.000000 telemetry time=2.1 position=[1.0,2.0,0.1] battery=[0.9,16.4]"
+        );
+        assert_eq!(
+            garbage(&[0x55, 0xaa]).json(0.25),
+            json!({"time": 0.25, "kind": "garbage", "data": "55aa"})
+        );
+    }
+}
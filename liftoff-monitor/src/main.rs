@@ -0,0 +1,284 @@
+use clap::{Parser, Subcommand};
+use log::{debug, info};
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Instant;
+use telemetry_lib::framing::{self, Chunk, Deframer};
+use telemetry_lib::pcap::{self, Payload};
+use telemetry_lib::telemetry;
+use tokio::io::AsyncReadExt;
+use tokio::net::UdpSocket;
+use tokio_serial::SerialPortBuilderExt;
+
+mod decode;
+
+use decode::{Kind, Message};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+
+    /// Only show these kinds of messages. Can be given more than once, or
+    /// as a comma-separated list.
+    #[arg(
+        long = "type",
+        value_name = "KIND",
+        global = true,
+        value_enum,
+        value_delimiter = ','
+    )]
+    types: Vec<Kind>,
+
+    /// Print a JSON object per message, instead of a line of text.
+    #[arg(long, global = true, default_value_t = false)]
+    json: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Show the CRSF frames on a serial port, until interrupted.
+    Serial {
+        /// The serial port.
+        path: String,
+
+        /// Serial port speed.
+        #[arg(long, default_value_t = 420000)]
+        baud: u32,
+    },
+    /// Show the UDP datagrams received on an address, CRSF frames or sim
+    /// telemetry, until interrupted.
+    Udp {
+        /// Address to receive on (e.g. 127.0.0.1:9001 for Liftoff's
+        /// telemetry).
+        bind: SocketAddr,
+    },
+    /// Show the CRSF frames and sim telemetry in a pcap or pcapng file:
+    /// UDP datagrams, or the serial traffic captured by `crsf-forward
+    /// --capture`.
+    Pcap {
+        file: PathBuf,
+
+        /// Only the UDP datagrams from or to this port.
+        #[arg(long)]
+        port: Option<u16>,
+    },
+}
+
+struct Printer {
+    json: bool,
+    /// Kinds to show; all if empty.
+    types: Vec<Kind>,
+}
+
+impl Printer {
+    /// Print `message`, received `time` s after the start.
+    fn print(&self, time: f64, message: &Message) -> io::Result<()> {
+        if !self.types.is_empty() && !self.types.contains(&message.kind) {
+            return Ok(());
+        }
+        let line = if self.json {
+            message.json(time).to_string()
+        } else {
+            message.text(time)
+        };
+        writeln!(io::stdout().lock(), "{}", line)
+    }
+}
+
+fn chunk(chunk: &Chunk) -> Message {
+    match chunk {
+        Chunk::Frame { data, valid } => decode::frame(data, *valid),
+        Chunk::Garbage(data) => decode::garbage(data),
+    }
+}
+
+/// The messages in a UDP datagram, if it is CRSF frames or a sim
+/// telemetry packet in `format`.
+fn datagram(data: &[u8], format: &[String]) -> Option<Vec<Message>> {
+    if let Some(chunks) = framing::split(data) {
+        return Some(chunks.iter().map(chunk).collect());
+    }
+    let packet = telemetry::parse_packet(data, format).ok()?;
+    // All of it, as any bytes would do for its start.
+    let len = telemetry::encode_packet(&packet, format).ok()?.len();
+    (len == data.len()).then(|| vec![decode::telemetry(&packet)])
+}
+
+async fn serial(
+    printer: &Printer,
+    path: &str,
+    baud: u32,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut port = tokio_serial::new(path, baud)
+        .open_native_async()
+        .map_err(|e| format!("{}: {}", path, e))?;
+    info!("Reading {} at {} baud", path, baud);
+    let start = Instant::now();
+    let mut deframer = Deframer::default();
+    let mut buf = [0u8; 1024];
+    loop {
+        tokio::select! {
+            result = port.read(&mut buf) => {
+                let len = result?;
+                if len == 0 {
+                    info!("Serial port closed");
+                    break;
+                }
+                let time = start.elapsed().as_secs_f64();
+                for c in deframer.push(&buf[..len]) {
+                    printer.print(time, &chunk(&c))?;
+                }
+            }
+            _ = tokio::signal::ctrl_c() => {
+                info!("Shutdown signal received, exiting.");
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn udp(
+    printer: &Printer,
+    bind: SocketAddr,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let socket = UdpSocket::bind(bind).await?;
+    info!("Receiving on {}", bind);
+    // Telemetry format config
+    // We assume default configuration for now
+    let format = telemetry::default_stream_format();
+    let start = Instant::now();
+    let mut buf = [0u8; 2048];
+    loop {
+        tokio::select! {
+            result = socket.recv_from(&mut buf) => {
+                let (len, from) = result?;
+                let time = start.elapsed().as_secs_f64();
+                let Some(messages) = datagram(&buf[..len], &format) else {
+                    debug!("Not CRSF or telemetry from {}: {} bytes", from, len);
+                    continue;
+                };
+                for message in &messages {
+                    printer.print(time, message)?;
+                }
+            }
+            _ = tokio::signal::ctrl_c() => {
+                info!("Shutdown signal received, exiting.");
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn pcap(
+    printer: &Printer,
+    file: &PathBuf,
+    port: Option<u16>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let data = std::fs::read(file).map_err(|e| format!("{}: {}", file.display(), e))?;
+    let packets = pcap::packets(&data).map_err(|e| format!("{}: {}", file.display(), e))?;
+    info!("{} packets in {}", packets.len(), file.display());
+    // Telemetry format config
+    // We assume default configuration for now
+    let format = telemetry::default_stream_format();
+    let start = packets.first().map_or(0.0, |p| p.time);
+    for packet in &packets {
+        let time = packet.time - start;
+        let messages = match pcap::payload(packet) {
+            Some(Payload::Crsf(data)) => {
+                // A frame or garbage each.
+                let mut deframer = Deframer::default();
+                let mut chunks = deframer.push(data);
+                chunks.extend(deframer.finish());
+                chunks.iter().map(chunk).collect()
+            }
+            Some(Payload::Udp { from, to, data }) => {
+                if port.is_some_and(|port| from.port() != port && to.port() != port) {
+                    continue;
+                }
+                match datagram(data, &format) {
+                    Some(messages) => messages,
+                    None => {
+                        debug!("Not CRSF or telemetry from {}: {} bytes", from, data.len());
+                        continue;
+                    }
+                }
+            }
+            None => continue,
+        };
+        for message in &messages {
+            printer.print(time, message)?;
+        }
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    env_logger::init();
+    let args = Args::parse();
+
+    let printer = Printer {
+        json: args.json,
+        types: args.types,
+    };
+    let result = match args.command {
+        Command::Serial { ref path, baud } => serial(&printer, path, baud).await,
+        Command::Udp { bind } => udp(&printer, bind).await,
+        Command::Pcap { ref file, port } => pcap(&printer, file, port),
+    };
+    match result {
+        // Piped into a program that has exited, as `head`.
+        Err(e)
+            if e.downcast_ref::<io::Error>()
+                .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe) =>
+        {
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use telemetry_lib::crsf::{self, CrsfPacket, Vario, device_address};
+    use telemetry_lib::telemetry::TelemetryPacket;
+
+    #[test]
+    fn datagrams() {
+        let format = telemetry::default_stream_format();
+        let vario = crsf::build_packet(
+            device_address::FLIGHT_CONTROLLER,
+            &CrsfPacket::Vario(Vario {
+                vertical_speed: -150,
+            }),
+        )
+        .unwrap();
+        let messages = datagram(&[vario.clone(), vario].concat(), &format).unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].kind, Kind::Vario);
+
+        let packet = TelemetryPacket {
+            timestamp: Some(1.0),
+            position: Some([0.0; 3]),
+            attitude: Some([0.0, 0.0, 0.0, 1.0]),
+            velocity: Some([0.0; 3]),
+            gyro: Some([0.0; 3]),
+            input: Some([0.0; 4]),
+            battery: Some([1.0, 16.8]),
+            motor_rpm: Some(vec![0.0; 4]),
+        };
+        let data = telemetry::encode_packet(&packet, &format).unwrap();
+        let messages = datagram(&data, &format).unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].kind, Kind::Telemetry);
+        assert_eq!(datagram(&data[..data.len() - 1], &format), None);
+        assert_eq!(datagram(&[data.clone(), data].concat(), &format), None);
+        assert_eq!(datagram(b"hello", &format), None);
+    }
+}
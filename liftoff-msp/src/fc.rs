@@ -0,0 +1,471 @@
+//! The flight controller that is emulated: the state of the drone from its
+//! CRSF telemetry and the RC channels, as the payloads of Betaflight's MSP
+//! responses.
+//!
+//! Every value is kept with the time it was received, and reported as
+//! unknown once it is older than the stale timeout. MSP units: angles in
+//! decidegrees, altitudes in cm (or m), speeds in cm/s, voltages in 10 mV
+//! or 100 mV, currents in 10 mA.
+
+use std::time::{Duration, Instant};
+
+use telemetry_lib::crsf::{self, Attitude, Battery, CrsfPacket, Gps, LinkStatistics};
+use telemetry_lib::events::Event;
+
+const MSP_API_VERSION: u16 = 1;
+const MSP_FC_VARIANT: u16 = 2;
+const MSP_FC_VERSION: u16 = 3;
+const MSP_BOARD_INFO: u16 = 4;
+const MSP_BUILD_INFO: u16 = 5;
+const MSP_NAME: u16 = 10;
+const MSP_STATUS: u16 = 101;
+const MSP_RC: u16 = 105;
+const MSP_RAW_GPS: u16 = 106;
+const MSP_COMP_GPS: u16 = 107;
+const MSP_ATTITUDE: u16 = 108;
+const MSP_ALTITUDE: u16 = 109;
+const MSP_ANALOG: u16 = 110;
+const MSP_BOXNAMES: u16 = 116;
+const MSP_BOXIDS: u16 = 119;
+const MSP_BATTERY_STATE: u16 = 130;
+const MSP_STATUS_EX: u16 = 150;
+const MSP_UID: u16 = 160;
+
+/// Betaflight 4.5, MSP API 1.46.
+const API_VERSION: [u8; 3] = [0, 1, 46];
+const FC_VERSION: [u8; 3] = [4, 5, 0];
+/// As Betaflight's simulator target.
+const BOARD: &[u8; 4] = b"SITL";
+const MCU_TYPE_SIMULATOR: u8 = 0;
+const CONFIGURATION_STATE_CONFIGURED: u8 = 1;
+/// The PID loop, µs, and the gyro rate, Hz, reported.
+const CYCLE_TIME: u16 = 125;
+const GYRO_RATE: u16 = 8000;
+
+const SENSOR_ACC: u16 = 1 << 0;
+const SENSOR_BARO: u16 = 1 << 1;
+const SENSOR_GPS: u16 = 1 << 3;
+const SENSOR_GYRO: u16 = 1 << 5;
+
+/// The only mode box, `ARM`, as the first: bit 0 of the flight mode flags.
+const BOXNAMES: &[u8] = b"ARM;";
+const BOXID_ARM: u8 = 0;
+
+const BATTERY_OK: u8 = 0;
+const BATTERY_WARNING: u8 = 1;
+const BATTERY_CRITICAL: u8 = 2;
+const BATTERY_NOT_PRESENT: u8 = 3;
+/// Cell voltages of the battery states, V, and the highest of a charged
+/// cell, from which the cells are counted.
+const CELL_WARNING: f64 = 3.5;
+const CELL_CRITICAL: f64 = 3.3;
+const CELL_MAX: f64 = 4.35;
+
+const NUM_CHANNELS: usize = 16;
+/// The center of a CRSF channel, 1500 µs.
+const CHANNEL_MID: u16 = 992;
+
+#[derive(Debug)]
+pub struct Fc {
+    name: String,
+    /// Channel of the arm switch, from 0.
+    arm_channel: usize,
+    stale_timeout: Duration,
+    /// How long an event is shown as the craft name.
+    event_time: Duration,
+    /// Name of the last event.
+    event: Option<(Instant, String)>,
+    attitude: Option<(Instant, Attitude)>,
+    gps: Option<(Instant, Gps)>,
+    /// Position of the first GPS fix, that the distance and direction home
+    /// are from.
+    home: Option<(f64, f64)>,
+    /// Barometric altitude, m.
+    baro_alt: Option<(Instant, f64)>,
+    /// Vertical speed, m/s, up.
+    climb: Option<(Instant, f64)>,
+    battery: Option<(Instant, Battery)>,
+    link: Option<(Instant, LinkStatistics)>,
+    rc: Option<(Instant, [u16; NUM_CHANNELS])>,
+    /// Toggled by each GPS fix, for MSP_COMP_GPS.
+    gps_heartbeat: bool,
+}
+
+/// `value` if it was received within `timeout` of `now`.
+fn fresh<T>(value: &Option<(Instant, T)>, now: Instant, timeout: Duration) -> Option<&T> {
+    value
+        .as_ref()
+        .filter(|(at, _)| now.saturating_duration_since(*at) < timeout)
+        .map(|(_, value)| value)
+}
+
+/// Distance, m, and direction, degrees, from `from` to `to`, both latitude
+/// and longitude in degrees. Flat earth, which is near enough for a flight.
+fn distance_direction(from: (f64, f64), to: (f64, f64)) -> (f64, f64) {
+    const EARTH_RADIUS: f64 = 6_371_000.0;
+    let north = (to.0 - from.0).to_radians() * EARTH_RADIUS;
+    let east = (to.1 - from.1).to_radians() * EARTH_RADIUS * from.0.to_radians().cos();
+    (
+        north.hypot(east),
+        east.atan2(north).to_degrees().rem_euclid(360.0),
+    )
+}
+
+/// Length-prefixed string, as in MSP_BOARD_INFO.
+fn string(out: &mut Vec<u8>, s: &[u8]) {
+    out.push(s.len() as u8);
+    out.extend_from_slice(s);
+}
+
+impl Fc {
+    pub fn new(
+        name: String,
+        arm_channel: usize,
+        stale_timeout: Duration,
+        event_time: Duration,
+    ) -> Self {
+        Self {
+            name,
+            arm_channel,
+            stale_timeout,
+            event_time,
+            event: None,
+            attitude: None,
+            gps: None,
+            home: None,
+            baro_alt: None,
+            climb: None,
+            battery: None,
+            link: None,
+            rc: None,
+            gps_heartbeat: false,
+        }
+    }
+
+    /// A telemetry packet was received at `now`.
+    pub fn telemetry(&mut self, now: Instant, packet: CrsfPacket) {
+        match packet {
+            CrsfPacket::Attitude(attitude) => self.attitude = Some((now, attitude)),
+            CrsfPacket::Gps(gps) => {
+                self.home.get_or_insert((gps.lat_deg(), gps.lon_deg()));
+                self.gps = Some((now, gps));
+                self.gps_heartbeat = !self.gps_heartbeat;
+            }
+            CrsfPacket::BaroAlt(baro) => {
+                self.baro_alt = Some((now, baro.alt_m()));
+                self.climb = Some((now, baro.vertical_speed_ms()));
+            }
+            CrsfPacket::Vario(vario) => self.climb = Some((now, vario.vertical_speed_ms())),
+            CrsfPacket::Battery(battery) => self.battery = Some((now, battery)),
+            CrsfPacket::LinkStatistics(link) => self.link = Some((now, link)),
+            _ => {}
+        }
+    }
+
+    /// An RC frame was received at `now`. Subset frames update the last
+    /// channels in part.
+    pub fn rc(&mut self, now: Instant, frame: &[u8]) {
+        let mut channels = self.rc.map_or([CHANNEL_MID; NUM_CHANNELS], |(_, c)| c);
+        match crsf::parse_packet_check(frame) {
+            Some(CrsfPacket::RcChannelsPacked(rc)) => channels = rc.channels,
+            Some(CrsfPacket::RcChannelsSubset(subset)) => subset.merge_into(&mut channels),
+            _ => return,
+        }
+        self.rc = Some((now, channels));
+    }
+
+    /// An event from liftoff-input was received at `now`.
+    pub fn event(&mut self, now: Instant, event: &Event) {
+        self.event = Some((now, event.name.to_ascii_uppercase()));
+    }
+
+    fn fresh<'a, T>(&self, value: &'a Option<(Instant, T)>, now: Instant) -> Option<&'a T> {
+        fresh(value, now, self.stale_timeout)
+    }
+
+    fn armed(&self, now: Instant) -> bool {
+        self.fresh(&self.rc, now)
+            .is_some_and(|rc| rc[self.arm_channel] > CHANNEL_MID)
+    }
+
+    /// Altitude, m: GPS, or barometric without a fix.
+    fn altitude(&self, now: Instant) -> Option<f64> {
+        match (self.fresh(&self.gps, now), self.fresh(&self.baro_alt, now)) {
+            (Some(gps), _) => Some(gps.alt_m()),
+            (None, Some(&baro)) => Some(baro),
+            (None, None) => None,
+        }
+    }
+
+    /// The number of cells of the battery, and its voltage, V.
+    fn cells(&self, now: Instant) -> Option<(u8, f64)> {
+        let battery = self.fresh(&self.battery, now)?;
+        let voltage = battery.voltage_v();
+        let cells = (voltage / CELL_MAX).ceil().max(1.0) as u8;
+        Some((cells, voltage))
+    }
+
+    /// The payload of the response to `command`, if it is supported.
+    /// Requests with a payload set something, which none of the supported
+    /// commands do.
+    pub fn respond(&self, now: Instant, command: u16, payload: &[u8]) -> Option<Vec<u8>> {
+        if !payload.is_empty() {
+            return None;
+        }
+        let mut out = Vec::new();
+        match command {
+            MSP_API_VERSION => out.extend_from_slice(&API_VERSION),
+            MSP_FC_VARIANT => out.extend_from_slice(b"BTFL"),
+            MSP_FC_VERSION => out.extend_from_slice(&FC_VERSION),
+            MSP_BOARD_INFO => self.board_info(&mut out),
+            MSP_BUILD_INFO => {
+                // Date, time and short git revision.
+                out.extend_from_slice(b"Jan  1 2025");
+                out.extend_from_slice(b"00:00:00");
+                out.extend_from_slice(b"liftoff");
+            }
+            MSP_NAME => {
+                let event = fresh(&self.event, now, self.event_time);
+                out.extend_from_slice(event.unwrap_or(&self.name).as_bytes());
+            }
+            MSP_STATUS | MSP_STATUS_EX => self.status(now, command == MSP_STATUS_EX, &mut out),
+            MSP_RC => {
+                let rc = self.fresh(&self.rc, now).copied();
+                for channel in rc.unwrap_or([CHANNEL_MID; NUM_CHANNELS]) {
+                    out.extend_from_slice(&crsf::ticks_to_us(channel).to_le_bytes());
+                }
+            }
+            MSP_RAW_GPS => self.raw_gps(now, &mut out),
+            MSP_COMP_GPS => {
+                let gps = self.fresh(&self.gps, now);
+                let (distance, direction) = match (gps, self.home) {
+                    (Some(gps), Some(home)) => {
+                        distance_direction((gps.lat_deg(), gps.lon_deg()), home)
+                    }
+                    _ => (0.0, 0.0),
+                };
+                out.extend_from_slice(&(distance.min(65535.0) as u16).to_le_bytes());
+                out.extend_from_slice(&(direction.round() as i16 % 360).to_le_bytes());
+                out.push(u8::from(self.gps_heartbeat));
+            }
+            MSP_ATTITUDE => {
+                let (pitch, roll, yaw) = self
+                    .fresh(&self.attitude, now)
+                    .map_or((0.0, 0.0, 0.0), Attitude::as_radians);
+                let decidegrees = |angle: f64| (angle.to_degrees() * 10.0).round() as i16;
+                out.extend_from_slice(&decidegrees(roll).to_le_bytes());
+                out.extend_from_slice(&decidegrees(pitch).to_le_bytes());
+                let heading = yaw.to_degrees().rem_euclid(360.0).round() as i16 % 360;
+                out.extend_from_slice(&heading.to_le_bytes());
+            }
+            MSP_ALTITUDE => {
+                let altitude = self.altitude(now).unwrap_or(0.0);
+                let climb = self.fresh(&self.climb, now).copied().unwrap_or(0.0);
+                out.extend_from_slice(&((altitude * 100.0).round() as i32).to_le_bytes());
+                out.extend_from_slice(&((climb * 100.0).round() as i16).to_le_bytes());
+            }
+            MSP_ANALOG => {
+                let battery = self.fresh(&self.battery, now);
+                let voltage = battery.map_or(0.0, Battery::voltage_v);
+                let current = battery.map_or(0.0, Battery::current_a);
+                let drawn = battery.map_or(0, |b| b.capacity);
+                out.push((voltage * 10.0).round().min(255.0) as u8);
+                out.extend_from_slice(&(drawn.min(65535) as u16).to_le_bytes());
+                out.extend_from_slice(&self.rssi(now).to_le_bytes());
+                out.extend_from_slice(&((current * 100.0).round() as i16).to_le_bytes());
+                out.extend_from_slice(&((voltage * 100.0).round() as u16).to_le_bytes());
+            }
+            MSP_BOXNAMES => out.extend_from_slice(BOXNAMES),
+            MSP_BOXIDS => out.push(BOXID_ARM),
+            MSP_BATTERY_STATE => self.battery_state(now, &mut out),
+            MSP_UID => out.extend_from_slice(b"LIFTOFF-MSP\0"),
+            _ => return None,
+        }
+        Some(out)
+    }
+
+    fn board_info(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(BOARD);
+        // Hardware revision, and a flight controller without OSD.
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out.push(0);
+        // Target capabilities.
+        out.push(0);
+        string(out, BOARD);
+        string(out, b"LIFTOFF");
+        // Manufacturer.
+        string(out, b"");
+        // Signature.
+        out.extend_from_slice(&[0; 32]);
+        out.push(MCU_TYPE_SIMULATOR);
+        out.push(CONFIGURATION_STATE_CONFIGURED);
+        out.extend_from_slice(&GYRO_RATE.to_le_bytes());
+        // Configuration problems.
+        out.extend_from_slice(&0u32.to_le_bytes());
+        // SPI and I2C devices.
+        out.extend_from_slice(&[0, 0]);
+    }
+
+    fn status(&self, now: Instant, extended: bool, out: &mut Vec<u8>) {
+        let mut sensors = SENSOR_ACC | SENSOR_GYRO;
+        if self.fresh(&self.baro_alt, now).is_some() {
+            sensors |= SENSOR_BARO;
+        }
+        if self.fresh(&self.gps, now).is_some() {
+            sensors |= SENSOR_GPS;
+        }
+        out.extend_from_slice(&CYCLE_TIME.to_le_bytes());
+        // I2C errors.
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out.extend_from_slice(&sensors.to_le_bytes());
+        let modes = u32::from(self.armed(now)) << BOXID_ARM;
+        out.extend_from_slice(&modes.to_le_bytes());
+        // PID profile.
+        out.push(0);
+        // System load.
+        out.extend_from_slice(&0u16.to_le_bytes());
+        if extended {
+            // PID profiles, and the rate profile.
+            out.extend_from_slice(&[1, 0]);
+        } else {
+            // Gyro cycle time.
+            out.extend_from_slice(&0u16.to_le_bytes());
+        }
+        // Flight mode flags beyond the first 32.
+        out.push(0);
+        // Arming disable flags: their count, and those set.
+        out.push(0);
+        out.extend_from_slice(&0u32.to_le_bytes());
+        // Reboot required.
+        out.push(0);
+        // CPU temperature.
+        out.extend_from_slice(&0u16.to_le_bytes());
+    }
+
+    fn raw_gps(&self, now: Instant, out: &mut Vec<u8>) {
+        let Some(gps) = self.fresh(&self.gps, now) else {
+            out.extend_from_slice(&[0; 18]);
+            return;
+        };
+        // 3D fix.
+        out.push(if gps.sats >= 4 { 2 } else { 0 });
+        out.push(gps.sats);
+        out.extend_from_slice(&gps.lat.to_le_bytes());
+        out.extend_from_slice(&gps.lon.to_le_bytes());
+        out.extend_from_slice(&(gps.alt_m().max(0.0) as u16).to_le_bytes());
+        out.extend_from_slice(&((gps.speed_kmh() / 3.6 * 100.0).round() as u16).to_le_bytes());
+        out.extend_from_slice(&((gps.heading % 36000) / 10).to_le_bytes());
+        // HDOP, as a good fix.
+        out.extend_from_slice(&100u16.to_le_bytes());
+    }
+
+    fn battery_state(&self, now: Instant, out: &mut Vec<u8>) {
+        let battery = self.fresh(&self.battery, now);
+        let (cells, voltage) = self.cells(now).unwrap_or((0, 0.0));
+        let state = match self.cells(now) {
+            None => BATTERY_NOT_PRESENT,
+            Some((cells, voltage)) if voltage / f64::from(cells) < CELL_CRITICAL => {
+                BATTERY_CRITICAL
+            }
+            Some((cells, voltage)) if voltage / f64::from(cells) < CELL_WARNING => BATTERY_WARNING,
+            Some(_) => BATTERY_OK,
+        };
+        let drawn = battery.map_or(0, |b| b.capacity);
+        let current = battery.map_or(0.0, Battery::current_a);
+        out.push(cells);
+        // Capacity, unknown.
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out.push((voltage * 10.0).round().min(255.0) as u8);
+        out.extend_from_slice(&(drawn.min(65535) as u16).to_le_bytes());
+        out.extend_from_slice(&((current * 100.0).round() as u16).to_le_bytes());
+        out.push(state);
+        out.extend_from_slice(&((voltage * 100.0).round() as u16).to_le_bytes());
+    }
+
+    /// RSSI from 0 to 1023, the link quality of the receiver.
+    fn rssi(&self, now: Instant) -> u16 {
+        self.fresh(&self.link, now)
+            .map_or(0, |link| u16::from(link.lq.min(100)) * 1023 / 100)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use telemetry_lib::crsf::{RcChannelsPacked, device_address};
+
+    #[test]
+    fn responses() {
+        let now = Instant::now();
+        let mut fc = Fc::new(
+            "QUAD".into(),
+            4,
+            Duration::from_secs(1),
+            Duration::from_secs(3),
+        );
+        assert_eq!(fc.respond(now, MSP_API_VERSION, &[]), Some(vec![0, 1, 46]));
+        assert_eq!(fc.respond(now, MSP_NAME, &[]), Some(b"QUAD".to_vec()));
+        assert_eq!(fc.respond(now, 0x1234, &[]), None);
+        assert_eq!(fc.respond(now, MSP_NAME, b"NEW"), None);
+        assert_eq!(fc.respond(now, MSP_BOARD_INFO, &[]).unwrap().len(), 64);
+        assert_eq!(fc.respond(now, MSP_STATUS_EX, &[]).unwrap().len(), 24);
+
+        fc.telemetry(
+            now,
+            CrsfPacket::Attitude(Attitude::from_radians(-0.1, 0.2, -0.5).unwrap()),
+        );
+        fc.telemetry(
+            now,
+            CrsfPacket::Battery(Battery {
+                voltage: 148,
+                current: 125,
+                capacity: 420,
+                remaining: 80,
+            }),
+        );
+        let mut channels = [CHANNEL_MID; NUM_CHANNELS];
+        channels[4] = 1811;
+        let frame = crsf::build_packet(
+            device_address::FLIGHT_CONTROLLER,
+            &CrsfPacket::RcChannelsPacked(RcChannelsPacked { channels }),
+        )
+        .unwrap();
+        fc.rc(now, &frame);
+
+        let attitude = fc.respond(now, MSP_ATTITUDE, &[]).unwrap();
+        // Roll 11.5°, pitch -5.7°, heading 331°.
+        assert_eq!(attitude, [115, 0, 0xc7, 0xff, 0x4b, 0x01]);
+        let battery = fc.respond(now, MSP_BATTERY_STATE, &[]).unwrap();
+        // 4 cells at 3.7 V, 420 mAh drawn, 12.5 A.
+        assert_eq!(
+            battery,
+            [4, 0, 0, 148, 0xa4, 0x01, 0xe2, 0x04, BATTERY_OK, 0xc8, 0x05]
+        );
+        let status = fc.respond(now, MSP_STATUS, &[]).unwrap();
+        assert_eq!(status[6..10], [1, 0, 0, 0]);
+        let rc = fc.respond(now, MSP_RC, &[]).unwrap();
+        assert_eq!(rc[8..10], 2011u16.to_le_bytes());
+
+        fc.event(
+            now,
+            &Event {
+                name: "crash".into(),
+                time: 12.0,
+                metric: "deceleration".into(),
+                value: 80.0,
+            },
+        );
+        assert_eq!(fc.respond(now, MSP_NAME, &[]), Some(b"CRASH".to_vec()));
+
+        // Stale.
+        let later = now + Duration::from_secs(2);
+        let status = fc.respond(later, MSP_STATUS, &[]).unwrap();
+        assert_eq!(status[6..10], [0, 0, 0, 0]);
+        assert_eq!(
+            fc.respond(later, MSP_BATTERY_STATE, &[]).unwrap()[8],
+            BATTERY_NOT_PRESENT
+        );
+        let later = now + Duration::from_secs(3);
+        assert_eq!(fc.respond(later, MSP_NAME, &[]), Some(b"QUAD".to_vec()));
+    }
+}
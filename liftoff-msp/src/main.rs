@@ -0,0 +1,239 @@
+use clap::Parser;
+use log::{debug, info, warn};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use telemetry_lib::crsf;
+use telemetry_lib::events::Event;
+use telemetry_lib::topics;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::time::Duration;
+use tokio_serial::SerialPortBuilderExt;
+use zenoh::Config;
+
+mod fc;
+mod msp;
+
+use fc::Fc;
+use msp::Decoder;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Serve MSP over TCP on this address (e.g. 127.0.0.1:5761), for
+    /// Betaflight Configurator's manual connection (tcp://ADDR).
+    #[arg(long)]
+    tcp_bind: Option<SocketAddr>,
+
+    /// Serve MSP on this serial port, for an OSD or a configurator on the
+    /// other end of a serial link or a pseudo-terminal pair.
+    #[arg(long)]
+    serial: Option<String>,
+
+    /// Serial port speed.
+    #[arg(long, default_value_t = 115200)]
+    baud: u32,
+
+    /// Craft name, as shown by the OSD.
+    #[arg(long, default_value = "LIFTOFF")]
+    name: String,
+
+    /// Show each event from liftoff-input (e.g. CRASH) as the craft name
+    /// for this long, s. 0 for never.
+    #[arg(long, default_value_t = 3.0)]
+    event_time: f64,
+
+    /// Channel of the arm switch, from 0. The FC is armed while it is high.
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u8).range(0..16))]
+    arm_channel: u8,
+
+    /// Telemetry older than this is not reported, s.
+    #[arg(long, default_value_t = 2.0)]
+    stale_timeout: f64,
+
+    /// Zenoh connect endpoint (e.g. tcp/192.168.1.1:7447). Omit for peer discovery.
+    #[arg(long)]
+    zenoh_connect: Option<String>,
+
+    /// Zenoh mode (peer or client).
+    #[arg(long, default_value = "client")]
+    zenoh_mode: String,
+
+    /// Zenoh topic prefix.
+    #[arg(long, default_value = topics::DEFAULT_PREFIX)]
+    zenoh_prefix: String,
+}
+
+/// Answer the requests on `stream`, until it is closed.
+async fn serve<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    fc: &Mutex<Fc>,
+) -> std::io::Result<()> {
+    let mut decoder = Decoder::default();
+    let mut buf = [0u8; 512];
+    loop {
+        let len = stream.read(&mut buf).await?;
+        if len == 0 {
+            return Ok(());
+        }
+        for request in decoder.push(&buf[..len]) {
+            let payload = fc
+                .lock()
+                .ok()
+                .and_then(|fc| fc.respond(Instant::now(), request.command, &request.payload));
+            if payload.is_none() {
+                debug!("Unsupported MSP command {}", request.command);
+            }
+            let response = msp::response(request.version, request.command, payload.as_deref());
+            stream.write_all(&response).await?;
+        }
+    }
+}
+
+async fn serve_tcp(listener: TcpListener, fc: Arc<Mutex<Fc>>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, addr)) => {
+                info!("MSP client connected: {}", addr);
+                if let Err(e) = stream.set_nodelay(true) {
+                    debug!("TCP_NODELAY for {}: {}", addr, e);
+                }
+                let fc = fc.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = serve(stream, &fc).await {
+                        debug!("MSP client {}: {}", addr, e);
+                    }
+                    info!("MSP client disconnected: {}", addr);
+                });
+            }
+            Err(e) => warn!("MSP TCP accept error: {}", e),
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    env_logger::init();
+    let args = Args::parse();
+
+    if args.tcp_bind.is_none() && args.serial.is_none() {
+        return Err("give --tcp-bind, --serial or both".into());
+    }
+
+    info!("Starting liftoff-msp");
+
+    // Zenoh session
+    let mut config = Config::default();
+    config.insert_json5("mode", &format!(r#""{}""#, args.zenoh_mode))?;
+    if let Some(ref endpoint) = args.zenoh_connect {
+        config.insert_json5("connect/endpoints", &format!(r#"["{}"]"#, endpoint))?;
+    }
+
+    let session = zenoh::open(config).await?;
+    let fc = Arc::new(Mutex::new(Fc::new(
+        args.name.clone(),
+        usize::from(args.arm_channel),
+        Duration::from_secs_f64(args.stale_timeout),
+        Duration::from_secs_f64(args.event_time),
+    )));
+
+    let crsf_tel_topic = topics::topic(&args.zenoh_prefix, topics::CRSF_TELEMETRY);
+    info!("Subscribing to: {}", crsf_tel_topic);
+    let crsf_tel_subscriber = session.declare_subscriber(&crsf_tel_topic).await?;
+    let tx = fc.clone();
+    tokio::spawn(async move {
+        loop {
+            match crsf_tel_subscriber.recv_async().await {
+                Ok(sample) => {
+                    let payload = sample.payload().to_bytes();
+                    if let Some(packet) = crsf::parse_packet_check(&payload)
+                        && let Ok(mut lock) = tx.lock()
+                    {
+                        lock.telemetry(Instant::now(), packet);
+                    }
+                }
+                Err(e) => {
+                    warn!("CRSF telemetry subscriber error: {}", e);
+                    break;
+                }
+            }
+        }
+    });
+
+    let rc_topic = topics::topic(&args.zenoh_prefix, topics::CRSF_RC);
+    info!("Subscribing to: {}", rc_topic);
+    let rc_subscriber = session.declare_subscriber(&rc_topic).await?;
+    let tx = fc.clone();
+    tokio::spawn(async move {
+        loop {
+            match rc_subscriber.recv_async().await {
+                Ok(sample) => {
+                    let payload = sample.payload().to_bytes();
+                    if let Ok(mut lock) = tx.lock() {
+                        lock.rc(Instant::now(), &payload);
+                    }
+                }
+                Err(e) => {
+                    warn!("RC subscriber error: {}", e);
+                    break;
+                }
+            }
+        }
+    });
+
+    let events_topic = topics::topic(&args.zenoh_prefix, topics::EVENTS);
+    info!("Subscribing to: {}", events_topic);
+    let events_subscriber = session.declare_subscriber(&events_topic).await?;
+    let tx = fc.clone();
+    tokio::spawn(async move {
+        loop {
+            match events_subscriber.recv_async().await {
+                Ok(sample) => {
+                    let payload = sample.payload().to_bytes();
+                    match serde_json::from_slice::<Event>(&payload) {
+                        Ok(event) => {
+                            if let Ok(mut lock) = tx.lock() {
+                                lock.event(Instant::now(), &event);
+                            }
+                        }
+                        Err(e) => warn!("Event: {}", e),
+                    }
+                }
+                Err(e) => {
+                    warn!("Events subscriber error: {}", e);
+                    break;
+                }
+            }
+        }
+    });
+
+    if let Some(addr) = args.tcp_bind {
+        let listener = TcpListener::bind(addr).await?;
+        info!("Serving MSP on tcp://{}", addr);
+        tokio::spawn(serve_tcp(listener, fc.clone()));
+    }
+
+    match args.serial {
+        Some(ref path) => {
+            let port = tokio_serial::new(path, args.baud)
+                .open_native_async()
+                .map_err(|e| format!("{}: {}", path, e))?;
+            info!("Serving MSP on {} at {} baud", path, args.baud);
+            tokio::select! {
+                result = serve(port, &fc) => {
+                    result.map_err(|e| format!("{}: {}", path, e))?;
+                    info!("Serial port closed");
+                }
+                _ = tokio::signal::ctrl_c() => info!("Shutdown signal received, exiting."),
+            }
+        }
+        None => {
+            tokio::signal::ctrl_c().await?;
+            info!("Shutdown signal received, exiting.");
+        }
+    }
+
+    session.close().await?;
+    Ok(())
+}
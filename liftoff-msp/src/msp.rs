@@ -0,0 +1,189 @@
+//! MSP, the MultiWii Serial Protocol that Betaflight speaks to its
+//! configurator and OSDs, as the flight controller side.
+//!
+//! A request is `$M<`, the payload size (u8), the command (u8), the payload
+//! and an XOR of size, command and payload (MSP v1), or `$X<`, a flag
+//! byte, the command and the payload size (u16 each, little-endian), the
+//! payload and a CRC-8/DVB-S2 of all from the flag (MSP v2). The response
+//! is the same with `>`, or `!` for a command that is not supported.
+
+use telemetry_lib::crsf;
+
+/// Largest MSP v2 payload accepted. Larger ones are taken for garbage.
+const MAX_V2_PAYLOAD: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    V1,
+    V2,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    /// The response is in the same version.
+    pub version: Version,
+    pub command: u16,
+    pub payload: Vec<u8>,
+}
+
+/// Whether a buffer starts with a request, and how long it is.
+enum Found {
+    Request(Request, usize),
+    NotARequest,
+    Incomplete,
+}
+
+fn xor(data: &[u8]) -> u8 {
+    data.iter().fold(0, |acc, b| acc ^ b)
+}
+
+fn request(buf: &[u8]) -> Found {
+    if buf[0] != b'$' {
+        return Found::NotARequest;
+    }
+    if buf.len() < 3 {
+        return Found::Incomplete;
+    }
+    let (version, header) = match &buf[1..3] {
+        b"M<" => (Version::V1, 5),
+        b"X<" => (Version::V2, 8),
+        _ => return Found::NotARequest,
+    };
+    if buf.len() < header {
+        return Found::Incomplete;
+    }
+    let (command, size) = match version {
+        Version::V1 => (u16::from(buf[4]), usize::from(buf[3])),
+        Version::V2 => (
+            u16::from_le_bytes([buf[4], buf[5]]),
+            usize::from(u16::from_le_bytes([buf[6], buf[7]])),
+        ),
+    };
+    if size > MAX_V2_PAYLOAD {
+        return Found::NotARequest;
+    }
+    let total = header + size + 1;
+    if buf.len() < total {
+        return Found::Incomplete;
+    }
+    let checked = &buf[3..total - 1];
+    let check = match version {
+        Version::V1 => xor(checked),
+        Version::V2 => crsf::calc_crc8(checked),
+    };
+    if check != buf[total - 1] {
+        return Found::NotARequest;
+    }
+    Found::Request(
+        Request {
+            version,
+            command,
+            payload: buf[header..total - 1].to_vec(),
+        },
+        total,
+    )
+}
+
+/// Splits the bytes from the configurator or OSD, which come in pieces,
+/// into requests. Anything else is skipped.
+#[derive(Default)]
+pub struct Decoder {
+    buf: Vec<u8>,
+}
+
+impl Decoder {
+    /// The requests completed by `data`.
+    pub fn push(&mut self, data: &[u8]) -> Vec<Request> {
+        self.buf.extend_from_slice(data);
+        let mut requests = Vec::new();
+        let mut at = 0;
+        while at < self.buf.len() {
+            match request(&self.buf[at..]) {
+                Found::Request(request, len) => {
+                    requests.push(request);
+                    at += len;
+                }
+                Found::NotARequest => at += 1,
+                Found::Incomplete => break,
+            }
+        }
+        self.buf.drain(..at);
+        requests
+    }
+}
+
+/// The response to `command` with `payload`, or the error response if
+/// there is none.
+pub fn response(version: Version, command: u16, payload: Option<&[u8]>) -> Vec<u8> {
+    let direction = if payload.is_some() { b'>' } else { b'!' };
+    let payload = payload.unwrap_or_default();
+    let mut frame = Vec::with_capacity(payload.len() + 9);
+    match version {
+        Version::V1 => {
+            frame.extend_from_slice(&[b'$', b'M', direction]);
+            // Responses to MSP v1 requests fit.
+            frame.push(payload.len() as u8);
+            frame.push(command as u8);
+            frame.extend_from_slice(payload);
+            frame.push(xor(&frame[3..]));
+        }
+        Version::V2 => {
+            frame.extend_from_slice(&[b'$', b'X', direction, 0]);
+            frame.extend_from_slice(&command.to_le_bytes());
+            frame.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+            frame.extend_from_slice(payload);
+            frame.push(crsf::calc_crc8(&frame[3..]));
+        }
+    }
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn framing() {
+        // MSP_API_VERSION, as the configurator asks for it.
+        let v1 = b"$M<\x00\x01\x01";
+        let v2 = {
+            let mut v2 = b"$X<\x00\x6c\x00\x02\x00\xaa\xbb".to_vec();
+            v2.push(crsf::calc_crc8(&v2[3..]));
+            v2
+        };
+        let mut stream = b"junk$".to_vec();
+        stream.extend_from_slice(v1);
+        stream.extend_from_slice(b"$M<\x00\x01\x00");
+        stream.extend_from_slice(&v2);
+
+        let mut decoder = Decoder::default();
+        let mut requests = decoder.push(&stream[..12]);
+        assert_eq!(
+            requests,
+            [Request {
+                version: Version::V1,
+                command: 1,
+                payload: Vec::new(),
+            }]
+        );
+        requests = decoder.push(&stream[12..]);
+        assert_eq!(
+            requests,
+            [Request {
+                version: Version::V2,
+                command: 108,
+                payload: vec![0xaa, 0xbb],
+            }]
+        );
+        assert_eq!(decoder.push(b"$X"), []);
+
+        assert_eq!(
+            response(Version::V1, 1, Some(&[0, 1, 46])),
+            b"$M>\x03\x01\x00\x01\x2e\x2d"
+        );
+        assert_eq!(response(Version::V1, 250, None), b"$M!\x00\xfa\xfa");
+        let frame = response(Version::V2, 0x1001, Some(&[7]));
+        assert_eq!(frame[..9], *b"$X>\x00\x01\x10\x01\x00\x07");
+        assert_eq!(frame[9], crsf::calc_crc8(&frame[3..9]));
+    }
+}
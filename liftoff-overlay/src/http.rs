@@ -0,0 +1,97 @@
+//! The overlay for a browser source (`--http-bind`): the page at `GET /`,
+//! and what it shows as JSON at `GET /overlay.json`, which the page polls.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use log::{debug, warn};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+
+/// Longest request header accepted.
+const MAX_REQUEST: usize = 8192;
+
+/// The page served without `--page`.
+pub const PAGE: &str = include_str!("page.html");
+
+/// The path of an HTTP GET request.
+fn parse_request(req: &str) -> Option<&str> {
+    let mut first = req.lines().next()?.split_whitespace();
+    if first.next()? != "GET" {
+        return None;
+    }
+    first.next()
+}
+
+async fn handle(
+    mut socket: TcpStream,
+    addr: SocketAddr,
+    page: Arc<str>,
+    overlay: watch::Receiver<String>,
+) -> std::io::Result<()> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = socket.read(&mut chunk).await?;
+        if n == 0 || buf.len() > MAX_REQUEST {
+            return Ok(());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    let req = String::from_utf8_lossy(&buf);
+    let (status, content_type, body) = match parse_request(&req) {
+        Some("/") => ("200 OK", "text/html; charset=utf-8", page.to_string()),
+        Some("/overlay.json") => ("200 OK", "application/json", overlay.borrow().clone()),
+        Some(_) => ("404 Not Found", "text/plain", String::new()),
+        None => ("400 Bad Request", "text/plain", String::new()),
+    };
+    debug!("Overlay {} from {}", status, addr);
+    // Pages opened from a file may fetch the JSON too.
+    let response = format!(
+        "HTTP/1.1 {}\r\n\
+         Content-Type: {}\r\n\
+         Content-Length: {}\r\n\
+         Cache-Control: no-store\r\n\
+         Access-Control-Allow-Origin: *\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    socket.write_all(response.as_bytes()).await?;
+    socket.shutdown().await
+}
+
+/// Serve `page` and the overlay on `listener`.
+pub async fn serve(listener: TcpListener, page: Arc<str>, overlay: watch::Receiver<String>) {
+    loop {
+        match listener.accept().await {
+            Ok((socket, addr)) => {
+                let (page, overlay) = (page.clone(), overlay.clone());
+                tokio::spawn(async move {
+                    if let Err(e) = handle(socket, addr, page, overlay).await {
+                        debug!("Overlay client {}: {}", addr, e);
+                    }
+                });
+            }
+            Err(e) => warn!("Overlay server accept: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request() {
+        assert_eq!(
+            parse_request("GET /overlay.json HTTP/1.1\r\nHost: x\r\n\r\n"),
+            Some("/overlay.json")
+        );
+        assert_eq!(parse_request("POST / HTTP/1.1\r\n\r\n"), None);
+        assert_eq!(parse_request(""), None);
+    }
+}
@@ -0,0 +1,329 @@
+//! An overlay for streaming the sim: speed, battery, lap times and link
+//! statistics, kept up to date from Zenoh, for OBS and the like.
+//!
+//! Each line of the overlay is a template (`--template`), written as a
+//! text file for a text source (`--text-dir`), shown on a page for a
+//! browser source (`--http-bind`), and sent as JSON over WebSocket
+//! (`--ws-bind`).
+
+use clap::Parser;
+use log::{info, warn};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use telemetry_lib::events;
+use telemetry_lib::race::Event;
+use telemetry_lib::telemetry;
+use telemetry_lib::topics;
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use zenoh::Config;
+
+mod http;
+mod state;
+mod template;
+mod ws;
+
+use state::State;
+use template::Template;
+
+/// The lines without `--template`.
+const DEFAULT_TEMPLATES: &[(&str, &str)] = &[
+    ("speed", "{speed_kmh} km/h"),
+    ("battery", "{voltage} V  {remaining}%"),
+    ("lap", "Lap {lap}  {lap_time}"),
+    ("last_lap", "Last {last_lap}"),
+    ("best_lap", "Best {best_lap}"),
+    ("link", "LQ {lq}%  RSSI {rssi} dBm"),
+];
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// A line of the overlay, as name=template, where the template has
+    /// fields in braces, e.g. "speed={speed_kmh} km/h". Can be given more
+    /// than once. Fields: speed_kmh, speed_ms, altitude, voltage, current,
+    /// used, remaining, lap, lap_time, last_lap, best_lap, laps, lq, rssi,
+    /// snr, tx_power, event. Without it: speed, battery, lap, last_lap, best_lap
+    /// and link.
+    #[arg(long = "template", value_name = "NAME=TEMPLATE", value_parser = parse_template)]
+    templates: Vec<(String, Template)>,
+
+    /// Write each line to NAME.txt in this directory, for text sources.
+    #[arg(long)]
+    text_dir: Option<PathBuf>,
+
+    /// Serve the overlay page at http://ADDR/, for a browser source, and
+    /// the lines and fields as JSON at http://ADDR/overlay.json.
+    #[arg(long)]
+    http_bind: Option<SocketAddr>,
+
+    /// Serve this page instead of the built-in one.
+    #[arg(long, requires = "http_bind")]
+    page: Option<PathBuf>,
+
+    /// Send the JSON of /overlay.json to WebSocket clients on this address
+    /// whenever it changes.
+    #[arg(long)]
+    ws_bind: Option<SocketAddr>,
+
+    /// Updates per second.
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..=100))]
+    rate: u32,
+
+    /// Zenoh connect endpoint (e.g. tcp/192.168.1.1:7447). Omit for peer discovery.
+    #[arg(long)]
+    zenoh_connect: Option<String>,
+
+    /// Zenoh mode (peer or client).
+    #[arg(long, default_value = "client")]
+    zenoh_mode: String,
+
+    /// Zenoh topic prefix.
+    #[arg(long, default_value = topics::DEFAULT_PREFIX)]
+    zenoh_prefix: String,
+}
+
+fn parse_template(s: &str) -> Result<(String, Template), String> {
+    let (name, template) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected name=template, got {}", s))?;
+    // The name is also a file name.
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(format!(
+            "{}: a name has only letters, digits, _ and -",
+            name
+        ));
+    }
+    Ok((name.to_string(), Template::parse(template, state::FIELDS)?))
+}
+
+/// Replace `dir`/`name`.txt with `text`, so that a text source never reads
+/// it half-written.
+fn write_text(dir: &Path, name: &str, text: &str) -> std::io::Result<()> {
+    let path = dir.join(format!("{}.txt", name));
+    let tmp = dir.join(format!(".{}.txt.tmp", name));
+    std::fs::write(&tmp, text)?;
+    std::fs::rename(&tmp, &path)
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    env_logger::init();
+    let args = Args::parse();
+
+    info!("Starting liftoff-overlay");
+
+    if args.text_dir.is_none() && args.http_bind.is_none() && args.ws_bind.is_none() {
+        return Err("nothing to write to: give --text-dir, --http-bind or --ws-bind".into());
+    }
+    let templates = if args.templates.is_empty() {
+        DEFAULT_TEMPLATES
+            .iter()
+            .map(|&(name, template)| {
+                Ok((name.to_string(), Template::parse(template, state::FIELDS)?))
+            })
+            .collect::<Result<Vec<_>, String>>()?
+    } else {
+        args.templates
+    };
+
+    // Zenoh session
+    let mut config = Config::default();
+    config.insert_json5("mode", &format!(r#""{}""#, args.zenoh_mode))?;
+    if let Some(ref endpoint) = args.zenoh_connect {
+        config.insert_json5("connect/endpoints", &format!(r#"["{}"]"#, endpoint))?;
+    }
+    let session = zenoh::open(config).await?;
+
+    let state = Arc::new(Mutex::new(State::default()));
+
+    let tel_topic = topics::topic(&args.zenoh_prefix, topics::TELEMETRY);
+    info!("Subscribing to: {}", tel_topic);
+    let tel_subscriber = session.declare_subscriber(&tel_topic).await?;
+    let tx = state.clone();
+    tokio::spawn(async move {
+        // We assume the default telemetry format for now
+        let format = telemetry::default_stream_format();
+        loop {
+            match tel_subscriber.recv_async().await {
+                Ok(sample) => {
+                    let payload = sample.payload().to_bytes();
+                    if let Ok(packet) = telemetry::parse_packet(&payload, &format) {
+                        tx.lock().unwrap().telemetry(Instant::now(), &packet);
+                    }
+                }
+                Err(e) => {
+                    warn!("Sim telemetry subscriber error: {}", e);
+                    break;
+                }
+            }
+        }
+    });
+
+    // The link statistics come with the RC from crsf-forward.
+    for suffix in [topics::CRSF_TELEMETRY, topics::CRSF_RC] {
+        let topic = topics::topic(&args.zenoh_prefix, suffix);
+        info!("Subscribing to: {}", topic);
+        let subscriber = session.declare_subscriber(&topic).await?;
+        let tx = state.clone();
+        tokio::spawn(async move {
+            loop {
+                match subscriber.recv_async().await {
+                    Ok(sample) => {
+                        let payload = sample.payload().to_bytes();
+                        tx.lock().unwrap().crsf(Instant::now(), &payload);
+                    }
+                    Err(e) => {
+                        warn!("{} subscriber error: {}", topic, e);
+                        break;
+                    }
+                }
+            }
+        });
+    }
+
+    let race_topic = topics::topic(&args.zenoh_prefix, topics::RACE);
+    info!("Subscribing to: {}", race_topic);
+    let race_subscriber = session.declare_subscriber(&race_topic).await?;
+    let tx = state.clone();
+    tokio::spawn(async move {
+        loop {
+            match race_subscriber.recv_async().await {
+                Ok(sample) => {
+                    let payload = sample.payload().to_bytes();
+                    match serde_json::from_slice::<Event>(&payload) {
+                        Ok(event) => tx.lock().unwrap().race(Instant::now(), &event),
+                        Err(e) => warn!("Bad race event: {}", e),
+                    }
+                }
+                Err(e) => {
+                    warn!("Race subscriber error: {}", e);
+                    break;
+                }
+            }
+        }
+    });
+
+    let events_topic = topics::topic(&args.zenoh_prefix, topics::EVENTS);
+    info!("Subscribing to: {}", events_topic);
+    let events_subscriber = session.declare_subscriber(&events_topic).await?;
+    let tx = state.clone();
+    tokio::spawn(async move {
+        loop {
+            match events_subscriber.recv_async().await {
+                Ok(sample) => {
+                    let payload = sample.payload().to_bytes();
+                    match serde_json::from_slice::<events::Event>(&payload) {
+                        Ok(event) => tx.lock().unwrap().event(Instant::now(), &event),
+                        Err(e) => warn!("Bad event: {}", e),
+                    }
+                }
+                Err(e) => {
+                    warn!("Events subscriber error: {}", e);
+                    break;
+                }
+            }
+        }
+    });
+
+    // Outputs
+    let (overlay_tx, overlay_rx) = watch::channel(String::from("{}"));
+    if let Some(dir) = &args.text_dir {
+        std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        info!("Writing text files to {}", dir.display());
+    }
+    if let Some(addr) = args.http_bind {
+        let page: Arc<str> = match &args.page {
+            Some(path) => std::fs::read_to_string(path)
+                .map_err(|e| format!("{}: {}", path.display(), e))?
+                .into(),
+            None => http::PAGE.into(),
+        };
+        let listener = TcpListener::bind(addr).await?;
+        info!("Serving the overlay at http://{}/", listener.local_addr()?);
+        tokio::spawn(http::serve(listener, page, overlay_rx.clone()));
+    }
+    if let Some(addr) = args.ws_bind {
+        let listener = TcpListener::bind(addr).await?;
+        info!(
+            "Serving the overlay over WebSocket on {}",
+            listener.local_addr()?
+        );
+        tokio::spawn(ws::serve(listener, overlay_rx.clone()));
+    }
+
+    let mut ticker = tokio::time::interval(Duration::from_secs_f64(1.0 / f64::from(args.rate)));
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    let mut written: Vec<Option<String>> = vec![None; templates.len()];
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                let fields = state.lock().unwrap().fields(Instant::now());
+                let lines: Vec<String> = templates
+                    .iter()
+                    .map(|(_, template)| template.render(&fields))
+                    .collect();
+
+                if let Some(dir) = &args.text_dir {
+                    for ((name, _), (text, last)) in
+                        templates.iter().zip(lines.iter().zip(written.iter_mut()))
+                    {
+                        if last.as_ref() == Some(text) {
+                            continue;
+                        }
+                        match write_text(dir, name, text) {
+                            Ok(()) => *last = Some(text.clone()),
+                            Err(e) => warn!("{}: {}", dir.join(name).display(), e),
+                        }
+                    }
+                }
+
+                let json = serde_json::json!({
+                    "lines": templates
+                        .iter()
+                        .zip(&lines)
+                        .map(|((name, _), text)| serde_json::json!({"name": name, "text": text}))
+                        .collect::<Vec<_>>(),
+                    "values": fields,
+                })
+                .to_string();
+                overlay_tx.send_if_modified(|current| {
+                    if *current == json {
+                        return false;
+                    }
+                    *current = json;
+                    true
+                });
+            }
+            _ = tokio::signal::ctrl_c() => {
+                info!("Shutdown signal received, exiting.");
+                break;
+            }
+        }
+    }
+
+    session.close().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates() {
+        let (name, template) = parse_template("speed={speed_kmh} km/h").unwrap();
+        assert_eq!(name, "speed");
+        let fields = [("speed_kmh", "42".to_string())].into_iter().collect();
+        assert_eq!(template.render(&fields), "42 km/h");
+        assert!(parse_template("{speed_kmh}").is_err());
+        assert!(parse_template("../speed={speed_kmh}").is_err());
+        assert!(parse_template("speed={speed}").is_err());
+    }
+}
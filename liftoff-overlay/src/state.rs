@@ -0,0 +1,278 @@
+//! What the overlay shows, gathered by the subscriber tasks: speed and
+//! altitude from the sim telemetry (the speed from the GPS without it),
+//! battery and link statistics from the CRSF telemetry, the laps from
+//! liftoff-race, and the events from liftoff-input.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use telemetry_lib::crsf::{self, Battery, CrsfPacket, LinkStatistics};
+use telemetry_lib::events;
+use telemetry_lib::race::Event;
+use telemetry_lib::telemetry::TelemetryPacket;
+
+/// Values older than this are shown as unknown.
+const STALE: Duration = Duration::from_secs(2);
+
+/// How long an event is shown.
+const EVENT_SHOWN: Duration = Duration::from_secs(3);
+
+/// Shown for a value that isn't known.
+pub const UNKNOWN: &str = "-";
+
+/// The fields templates can use: the speed in km/h and m/s, the height
+/// above the scene origin (m), the battery voltage (V), current (A), charge
+/// used (mAh) and remaining (%), the number and time of the lap under way,
+/// the times of the last and best laps, the laps done, and the uplink
+/// quality (%), RSSI (dBm), SNR (dB) and transmit power (dBm), and the
+/// name of the last event, for a few seconds after it.
+pub const FIELDS: &[&str] = &[
+    "speed_kmh",
+    "speed_ms",
+    "altitude",
+    "voltage",
+    "current",
+    "used",
+    "remaining",
+    "lap",
+    "lap_time",
+    "last_lap",
+    "best_lap",
+    "laps",
+    "lq",
+    "rssi",
+    "snr",
+    "tx_power",
+    "event",
+];
+
+/// A value and when it arrived.
+type Timed<T> = Option<(Instant, T)>;
+
+fn fresh<T>(value: &Timed<T>, now: Instant) -> Option<&T> {
+    value
+        .as_ref()
+        .filter(|(at, _)| now.saturating_duration_since(*at) < STALE)
+        .map(|(_, value)| value)
+}
+
+/// `seconds` as a lap time: 12.345, or 1:02.345 from a minute.
+pub fn lap_time(seconds: f64) -> String {
+    let millis = (seconds * 1000.0).round() as u64;
+    let (minutes, millis) = (millis / 60_000, millis % 60_000);
+    if minutes > 0 {
+        format!("{}:{:02}.{:03}", minutes, millis / 1000, millis % 1000)
+    } else {
+        format!("{}.{:03}", millis / 1000, millis % 1000)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct State {
+    /// m/s.
+    speed: Timed<f64>,
+    /// Ground speed from the GPS, m/s.
+    gps_speed: Timed<f64>,
+    /// m.
+    altitude: Timed<f64>,
+    battery: Timed<Battery>,
+    link: Timed<LinkStatistics>,
+    /// Times of the laps done, s.
+    laps: Vec<f64>,
+    best: Option<f64>,
+    /// When the lap under way started.
+    lap_start: Option<Instant>,
+    /// Name of the last event.
+    event: Option<(Instant, String)>,
+}
+
+impl State {
+    pub fn telemetry(&mut self, now: Instant, packet: &TelemetryPacket) {
+        if let Some(velocity) = packet.velocity {
+            let speed = velocity.iter().map(|v| v * v).sum::<f32>().sqrt();
+            self.speed = Some((now, f64::from(speed)));
+        }
+        if let Some([_, y, _]) = packet.position {
+            self.altitude = Some((now, f64::from(y)));
+        }
+    }
+
+    /// A CRSF frame, of the telemetry or the RC (which may carry the link
+    /// statistics).
+    pub fn crsf(&mut self, now: Instant, frame: &[u8]) {
+        match crsf::parse_packet(frame) {
+            Some(CrsfPacket::Battery(battery)) => self.battery = Some((now, battery)),
+            Some(CrsfPacket::LinkStatistics(link)) => self.link = Some((now, link)),
+            Some(CrsfPacket::Gps(gps)) => self.gps_speed = Some((now, gps.speed_kmh() / 3.6)),
+            _ => {}
+        }
+    }
+
+    pub fn race(&mut self, now: Instant, event: &Event) {
+        match *event {
+            Event::Start { .. } => self.lap_start = Some(now),
+            Event::Split { .. } => {}
+            Event::Lap { time, .. } => {
+                self.laps.push(time);
+                self.best = Some(self.best.map_or(time, |best| best.min(time)));
+                self.lap_start = Some(now);
+            }
+        }
+    }
+
+    pub fn event(&mut self, now: Instant, event: &events::Event) {
+        self.event = Some((now, event.name.clone()));
+    }
+
+    /// The value of each of [`FIELDS`], formatted.
+    pub fn fields(&self, now: Instant) -> BTreeMap<&'static str, String> {
+        let unknown = || UNKNOWN.to_string();
+        let mut fields = BTreeMap::new();
+        let speed = fresh(&self.speed, now).or(fresh(&self.gps_speed, now));
+        fields.insert(
+            "speed_kmh",
+            speed.map_or_else(unknown, |s| format!("{:.0}", s * 3.6)),
+        );
+        fields.insert(
+            "speed_ms",
+            speed.map_or_else(unknown, |s| format!("{:.1}", s)),
+        );
+        fields.insert(
+            "altitude",
+            fresh(&self.altitude, now).map_or_else(unknown, |a| format!("{:.0}", a)),
+        );
+
+        let battery = fresh(&self.battery, now);
+        fields.insert(
+            "voltage",
+            battery.map_or_else(unknown, |b| format!("{:.1}", b.voltage_v())),
+        );
+        fields.insert(
+            "current",
+            battery.map_or_else(unknown, |b| format!("{:.1}", b.current_a())),
+        );
+        fields.insert(
+            "used",
+            battery.map_or_else(unknown, |b| b.capacity.to_string()),
+        );
+        fields.insert(
+            "remaining",
+            battery.map_or_else(unknown, |b| b.remaining.to_string()),
+        );
+
+        let lap = self.lap_start.map(|start| (self.laps.len() + 1, start));
+        fields.insert("lap", lap.map_or_else(unknown, |(lap, _)| lap.to_string()));
+        fields.insert(
+            "lap_time",
+            lap.map_or_else(unknown, |(_, start)| {
+                lap_time(now.saturating_duration_since(start).as_secs_f64())
+            }),
+        );
+        fields.insert(
+            "last_lap",
+            self.laps.last().map_or_else(unknown, |&t| lap_time(t)),
+        );
+        fields.insert("best_lap", self.best.map_or_else(unknown, lap_time));
+        fields.insert("laps", self.laps.len().to_string());
+
+        let link = fresh(&self.link, now);
+        fields.insert("lq", link.map_or_else(unknown, |l| l.lq.to_string()));
+        fields.insert(
+            "rssi",
+            link.map_or_else(unknown, |l| format!("-{}", l.rssi)),
+        );
+        fields.insert(
+            "snr",
+            link.map_or_else(unknown, |l| (l.snr as i8).to_string()),
+        );
+        fields.insert(
+            "tx_power",
+            link.map_or_else(unknown, |l| l.tx_power.to_string()),
+        );
+        let event = self
+            .event
+            .as_ref()
+            .filter(|(at, _)| now.saturating_duration_since(*at) < EVENT_SHOWN);
+        fields.insert(
+            "event",
+            event.map_or_else(String::new, |(_, name)| name.clone()),
+        );
+        fields
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use telemetry_lib::crsf::{build_packet, device_address};
+
+    #[test]
+    fn state() {
+        let t0 = Instant::now();
+        let mut state = State::default();
+        let fields = state.fields(t0);
+        assert_eq!(fields.len(), FIELDS.len());
+        assert!(FIELDS.iter().all(|name| fields.contains_key(name)));
+        assert_eq!((&*fields["speed_kmh"], &*fields["laps"]), (UNKNOWN, "0"));
+
+        let packet = TelemetryPacket {
+            timestamp: None,
+            position: Some([0.0, 12.0, 0.0]),
+            attitude: None,
+            velocity: Some([3.0, 0.0, 4.0]),
+            gyro: None,
+            input: None,
+            battery: None,
+            motor_rpm: None,
+        };
+        state.telemetry(t0, &packet);
+        let battery = CrsfPacket::Battery(Battery {
+            voltage: 152,
+            current: 215,
+            capacity: 650,
+            remaining: 80,
+        });
+        state.crsf(
+            t0,
+            &build_packet(device_address::FLIGHT_CONTROLLER, &battery).unwrap(),
+        );
+        state.race(t0, &Event::Start { missed: 0 });
+        state.event(
+            t0,
+            &events::Event {
+                name: "crash".into(),
+                time: 1.0,
+                metric: "deceleration".into(),
+                value: 80.0,
+            },
+        );
+        state.race(
+            t0 + Duration::from_secs(65),
+            &Event::Lap {
+                lap: 1,
+                time: 62.5,
+                best: true,
+            },
+        );
+
+        let fields = state.fields(t0 + Duration::from_millis(500));
+        assert_eq!(fields["speed_kmh"], "18");
+        assert_eq!(fields["speed_ms"], "5.0");
+        assert_eq!(fields["altitude"], "12");
+        assert_eq!(fields["voltage"], "15.2");
+        assert_eq!(fields["current"], "21.5");
+        assert_eq!(fields["remaining"], "80");
+        assert_eq!(fields["lq"], UNKNOWN);
+        assert_eq!(fields["event"], "crash");
+
+        let now = t0 + Duration::from_millis(66_250);
+        let fields = state.fields(now);
+        assert_eq!(fields["speed_kmh"], UNKNOWN);
+        assert_eq!(fields["lap"], "2");
+        assert_eq!(fields["lap_time"], "1.250");
+        assert_eq!(fields["last_lap"], "1:02.500");
+        assert_eq!(fields["best_lap"], "1:02.500");
+        assert_eq!(fields["laps"], "1");
+        assert_eq!(fields["event"], "");
+    }
+}
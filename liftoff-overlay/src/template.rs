@@ -0,0 +1,89 @@
+//! Templates of the overlay's lines: text with fields in braces, e.g.
+//! `{speed_kmh} km/h`. `{{` and `}}` are a brace.
+
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Field(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template(Vec<Part>);
+
+impl Template {
+    /// Parse `s`, whose fields must be among `fields`.
+    pub fn parse(s: &str, fields: &[&str]) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(format!("unclosed {{{}", name)),
+                        }
+                    }
+                    if !fields.contains(&name.as_str()) {
+                        return Err(format!(
+                            "unknown field {{{}}}, expected one of: {}",
+                            name,
+                            fields.join(", ")
+                        ));
+                    }
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Field(name));
+                }
+                '}' => return Err("unmatched }; write }} for a brace".to_string()),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Self(parts))
+    }
+
+    pub fn render(&self, values: &BTreeMap<&str, String>) -> String {
+        self.0
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.as_str(),
+                Part::Field(name) => values.get(name.as_str()).map_or("", String::as_str),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates() {
+        let fields = ["speed_kmh", "lq"];
+        let values = BTreeMap::from([("speed_kmh", "42".to_string()), ("lq", "100".to_string())]);
+        let template = Template::parse("{speed_kmh} km/h, LQ {lq}% {{x}}", &fields).unwrap();
+        assert_eq!(template.render(&values), "42 km/h, LQ 100% {x}");
+        assert_eq!(Template::parse("", &fields).unwrap().render(&values), "");
+
+        assert!(Template::parse("{speed}", &fields).is_err());
+        assert!(Template::parse("{speed_kmh", &fields).is_err());
+        assert!(Template::parse("a } b", &fields).is_err());
+    }
+}
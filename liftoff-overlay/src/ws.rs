@@ -0,0 +1,54 @@
+//! The overlay over WebSocket (`--ws-bind`): each client gets the JSON of
+//! `/overlay.json` as a text message whenever it changes, for overlays
+//! that would rather be told than poll.
+
+use futures_util::{SinkExt, StreamExt};
+use log::{debug, info, warn};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+async fn handle(
+    socket: TcpStream,
+    mut overlay: watch::Receiver<String>,
+) -> Result<(), tokio_tungstenite::tungstenite::Error> {
+    let mut ws = tokio_tungstenite::accept_async(socket).await?;
+    let current = overlay.borrow_and_update().clone();
+    ws.send(Message::text(current)).await?;
+    loop {
+        tokio::select! {
+            changed = overlay.changed() => {
+                if changed.is_err() {
+                    return Ok(());
+                }
+                let current = overlay.borrow_and_update().clone();
+                ws.send(Message::text(current)).await?;
+            }
+            // Only there to see the client go; what it sends is ignored.
+            message = ws.next() => match message {
+                Some(Ok(Message::Close(_))) | None => return Ok(()),
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e),
+            },
+        }
+    }
+}
+
+/// Send the overlay to the WebSocket clients that connect on `listener`.
+pub async fn serve(listener: TcpListener, overlay: watch::Receiver<String>) {
+    loop {
+        match listener.accept().await {
+            Ok((socket, addr)) => {
+                let overlay = overlay.clone();
+                tokio::spawn(async move {
+                    info!("WebSocket client {} connected", addr);
+                    match handle(socket, overlay).await {
+                        Ok(()) => info!("WebSocket client {} disconnected", addr),
+                        Err(e) => debug!("WebSocket client {}: {}", addr, e),
+                    }
+                });
+            }
+            Err(e) => warn!("WebSocket server accept: {}", e),
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! The course file, in TOML: the start/finish line, and the gates in the
+//! order they are to be passed.
+//!
+//! ```toml
+//! [start]
+//! ends = [[-5.0, 0.0], [5.0, 0.0]]
+//!
+//! [[gate]]
+//! name = "Tower"
+//! ends = [[40.0, 60.0], [44.0, 60.0]]
+//! heights = [0.0, 4.0]
+//! ```
+//!
+//! `ends` are the points on the ground the gate is between, `[x, z]` in
+//! Liftoff coordinates, m. `heights` limits it to heights from a bottom to
+//! a top; without it, any height passes. `name` defaults to `Gate N`.
+
+use telemetry_lib::race::{Course, Gate};
+use toml_edit::{Document, Item, Table, Value};
+
+fn number(value: &Value, what: &str) -> Result<f64, String> {
+    value
+        .as_float()
+        .or_else(|| value.as_integer().map(|i| i as f64))
+        .ok_or_else(|| format!("{} is not a number", what))
+}
+
+/// A pair of values of `what`, read by `f`.
+fn pair<T>(
+    value: &Value,
+    what: &str,
+    f: impl Fn(&Value, &str) -> Result<T, String>,
+) -> Result<[T; 2], String> {
+    let values: Vec<&Value> = value.as_array().into_iter().flatten().collect();
+    match values[..] {
+        [a, b] => Ok([f(a, what)?, f(b, what)?]),
+        _ => Err(format!("{} is not a pair", what)),
+    }
+}
+
+fn gate(table: &Table, default_name: String) -> Result<Gate, String> {
+    let name = match table.get("name") {
+        Some(item) => item
+            .as_str()
+            .ok_or_else(|| format!("name of {} is not a string", default_name))?
+            .to_string(),
+        None => default_name,
+    };
+    let ends = table
+        .get("ends")
+        .and_then(Item::as_value)
+        .ok_or_else(|| format!("{} has no ends", name))?;
+    let ends = pair(ends, &format!("ends of {}", name), |item, what| {
+        pair(item, what, number)
+    })?;
+    let heights = table
+        .get("heights")
+        .and_then(Item::as_value)
+        .map(|value| pair(value, &format!("heights of {}", name), number))
+        .transpose()?;
+    Ok(Gate {
+        name,
+        ends,
+        heights,
+    })
+}
+
+/// Parse a course file.
+pub fn parse(text: &str) -> Result<Course, String> {
+    let document = Document::parse(text).map_err(|e| e.to_string())?;
+    let start = document
+        .get("start")
+        .and_then(Item::as_table)
+        .ok_or("no [start] line")?;
+    let start = gate(start, "start".into())?;
+    let gates = match document.get("gate") {
+        Some(item) => item
+            .as_array_of_tables()
+            .ok_or("gate is not an array of tables, [[gate]]")?
+            .iter()
+            .enumerate()
+            .map(|(i, table)| gate(table, format!("Gate {}", i + 1)))
+            .collect::<Result<_, _>>()?,
+        None => Vec::new(),
+    };
+    Ok(Course { start, gates })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn course() {
+        let course = parse(
+            "[start]\n\
+             ends = [[-5, 0], [5.0, 0]]\n\
+             [[gate]]\n\
+             name = \"Tower\"\n\
+             ends = [[40.0, 60.0], [44.0, 60.0]]\n\
+             heights = [0.0, 4.5]\n\
+             [[gate]]\n\
+             ends = [[0.0, 100.0], [0.0, 110.0]]\n",
+        )
+        .unwrap();
+        assert_eq!(course.start.ends, [[-5.0, 0.0], [5.0, 0.0]]);
+        assert_eq!(course.start.heights, None);
+        assert_eq!(course.gates.len(), 2);
+        assert_eq!(course.gates[0].name, "Tower");
+        assert_eq!(course.gates[0].heights, Some([0.0, 4.5]));
+        assert_eq!(course.gates[1].name, "Gate 2");
+
+        assert!(parse("").unwrap_err().contains("[start]"));
+        assert!(parse("[start]\n").unwrap_err().contains("no ends"));
+        let err = parse("[start]\nends = [[0, 0]]\n").unwrap_err();
+        assert!(err.contains("not a pair"), "{}", err);
+        let err = parse("[start]\nends = [[0, 0], [0, \"x\"]]\n").unwrap_err();
+        assert!(err.contains("not a number"), "{}", err);
+        assert!(parse("[start\n").is_err());
+    }
+}
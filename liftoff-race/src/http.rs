@@ -0,0 +1,88 @@
+//! The standings as JSON, at `GET /race` (`--http-bind`).
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use log::{debug, warn};
+use telemetry_lib::race::Standings;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Longest request header accepted.
+const MAX_REQUEST: usize = 8192;
+
+pub type Shared = Arc<Mutex<Standings>>;
+
+/// The path of an HTTP GET request.
+fn parse_request(req: &str) -> Option<&str> {
+    let mut first = req.lines().next()?.split_whitespace();
+    if first.next()? != "GET" {
+        return None;
+    }
+    first.next()
+}
+
+async fn handle(mut socket: TcpStream, addr: SocketAddr, standings: Shared) -> std::io::Result<()> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = socket.read(&mut chunk).await?;
+        if n == 0 || buf.len() > MAX_REQUEST {
+            return Ok(());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    let req = String::from_utf8_lossy(&buf);
+    let (status, body) = match parse_request(&req) {
+        Some("/race") => {
+            let standings = standings.lock().unwrap_or_else(PoisonError::into_inner);
+            ("200 OK", serde_json::to_string(&*standings)?)
+        }
+        Some(_) => ("404 Not Found", String::new()),
+        None => ("400 Bad Request", String::new()),
+    };
+    debug!("Race {} from {}", status, addr);
+    let response = format!(
+        "HTTP/1.1 {}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    socket.write_all(response.as_bytes()).await?;
+    socket.shutdown().await
+}
+
+/// Serve /race on `listener`.
+pub async fn serve(listener: TcpListener, standings: Shared) {
+    loop {
+        match listener.accept().await {
+            Ok((socket, addr)) => {
+                let standings = standings.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle(socket, addr, standings).await {
+                        debug!("Race client {}: {}", addr, e);
+                    }
+                });
+            }
+            Err(e) => warn!("Race server accept: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request() {
+        assert_eq!(
+            parse_request("GET /race HTTP/1.1\r\nHost: x\r\n\r\n"),
+            Some("/race")
+        );
+        assert_eq!(parse_request("POST /race HTTP/1.1\r\n\r\n"), None);
+        assert_eq!(parse_request(""), None);
+    }
+}
@@ -0,0 +1,182 @@
+use clap::Parser;
+use log::{debug, info, warn};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Instant;
+use telemetry_lib::crsf_tx;
+use telemetry_lib::race::{Event, Timer};
+use telemetry_lib::telemetry;
+use telemetry_lib::topics;
+use tokio::net::TcpListener;
+use zenoh::Config;
+
+mod course;
+mod http;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Course file (TOML): the start/finish line and the gates.
+    course: PathBuf,
+
+    /// Serve the laps at http://ADDR/race, as JSON.
+    #[arg(long)]
+    http_bind: Option<SocketAddr>,
+
+    /// Zenoh connect endpoint (e.g. tcp/192.168.1.1:7447). Omit for peer discovery.
+    #[arg(long)]
+    zenoh_connect: Option<String>,
+
+    /// Zenoh mode (peer or client).
+    #[arg(long, default_value = "client")]
+    zenoh_mode: String,
+
+    /// Zenoh topic prefix.
+    #[arg(long, default_value = topics::DEFAULT_PREFIX)]
+    zenoh_prefix: String,
+}
+
+/// The event, for the console.
+fn describe(event: &Event) -> String {
+    match event {
+        Event::Start { missed: 0 } => "Started".to_string(),
+        Event::Start { missed } => format!("Missed {} gates, started over", missed),
+        Event::Split { name, time, .. } => format!("{}: {:.3} s", name, time),
+        Event::Lap { lap, time, best } => format!(
+            "Lap {}: {:.3} s{}",
+            lap,
+            time,
+            if *best { " (best)" } else { "" }
+        ),
+    }
+}
+
+/// The event, as a flight mode on the radio.
+fn flight_mode(event: &Event) -> String {
+    match event {
+        Event::Start { missed: 0 } => "START".to_string(),
+        Event::Start { missed } => format!("MISS {}", missed),
+        Event::Split { gate, time, .. } => format!("G{} {:.2}", gate + 1, time),
+        Event::Lap { lap, time, best } => {
+            format!("L{} {:.2}{}", lap, time, if *best { "*" } else { "" })
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    env_logger::init();
+    let args = Args::parse();
+
+    info!("Starting liftoff-race");
+
+    let text = std::fs::read_to_string(&args.course)?;
+    let course = course::parse(&text).map_err(|e| format!("{}: {}", args.course.display(), e))?;
+    info!("Course with {} gates", course.gates.len());
+    let mut timer = Timer::new(course);
+    let standings: http::Shared = Arc::new(Mutex::new(timer.standings()));
+
+    if let Some(addr) = args.http_bind {
+        let listener = TcpListener::bind(addr).await?;
+        info!("Serving laps on http://{}/race", addr);
+        tokio::spawn(http::serve(listener, standings.clone()));
+    }
+
+    // Zenoh session
+    let mut config = Config::default();
+    config.insert_json5("mode", &format!(r#""{}""#, args.zenoh_mode))?;
+    if let Some(ref endpoint) = args.zenoh_connect {
+        config.insert_json5("connect/endpoints", &format!(r#"["{}"]"#, endpoint))?;
+    }
+
+    let session = zenoh::open(config).await?;
+
+    let tel_topic = topics::topic(&args.zenoh_prefix, topics::TELEMETRY);
+    let race_topic = topics::topic(&args.zenoh_prefix, topics::RACE);
+    let crsf_tel_topic = topics::topic(&args.zenoh_prefix, topics::CRSF_TELEMETRY);
+    info!("Subscribing to: {}", tel_topic);
+    info!("Publishing on: {}", race_topic);
+    info!("Publishing on: {} (flight mode)", crsf_tel_topic);
+    let tel_subscriber = session.declare_subscriber(&tel_topic).await?;
+    let race_publisher = session.declare_publisher(race_topic).await?;
+    let crsf_tel_publisher = session.declare_publisher(crsf_tel_topic).await?;
+
+    // Telemetry format config
+    // We assume default configuration for now
+    let format = telemetry::default_stream_format();
+    // For telemetry without a timestamp.
+    let epoch = Instant::now();
+    loop {
+        let sample = tokio::select! {
+            sample = tel_subscriber.recv_async() => sample,
+            _ = tokio::signal::ctrl_c() => break,
+        };
+        let sample = match sample {
+            Ok(sample) => sample,
+            Err(e) => {
+                warn!("Sim telemetry subscriber error: {}", e);
+                break;
+            }
+        };
+        let packet = match telemetry::parse_packet(&sample.payload().to_bytes(), &format) {
+            Ok(packet) => packet,
+            Err(e) => {
+                debug!("Sim telemetry: {}", e);
+                continue;
+            }
+        };
+        let Some(position) = packet.position else {
+            continue;
+        };
+        let time = match packet.timestamp {
+            Some(timestamp) => f64::from(timestamp),
+            None => epoch.elapsed().as_secs_f64(),
+        };
+        let events = timer.update(time, position.map(f64::from));
+        *standings.lock().unwrap_or_else(PoisonError::into_inner) = timer.standings();
+        for event in events {
+            println!("{}", describe(&event));
+            if let Err(e) = race_publisher.put(serde_json::to_vec(&event)?).await {
+                warn!("Failed to publish race event: {}", e);
+            }
+            if let Some(frame) = crsf_tx::build_flight_mode_packet(&flight_mode(&event))
+                && let Err(e) = crsf_tel_publisher.put(frame).await
+            {
+                warn!("Failed to publish flight mode: {}", e);
+            }
+        }
+    }
+    info!("Shutdown signal received, exiting.");
+
+    session.close().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text() {
+        let lap = Event::Lap {
+            lap: 3,
+            time: 23.4567,
+            best: true,
+        };
+        assert_eq!(describe(&lap), "Lap 3: 23.457 s (best)");
+        assert_eq!(flight_mode(&lap), "L3 23.46*");
+        let split = Event::Split {
+            gate: 1,
+            name: "Tower".into(),
+            time: 9.5,
+        };
+        assert_eq!(describe(&split), "Tower: 9.500 s");
+        assert_eq!(flight_mode(&split), "G2 9.50");
+        assert_eq!(
+            describe(&Event::Start { missed: 2 }),
+            "Missed 2 gates, started over"
+        );
+        assert_eq!(flight_mode(&Event::Start { missed: 0 }), "START");
+    }
+}
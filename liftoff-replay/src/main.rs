@@ -0,0 +1,334 @@
+use clap::{Parser, Subcommand};
+use log::{info, warn};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read};
+use std::path::{Path, PathBuf};
+use telemetry_lib::topics;
+use tokio::sync::mpsc;
+use tokio::time::{Duration, Instant, sleep_until};
+use zenoh::{Config, Session};
+
+mod recording;
+mod subtitles;
+
+use recording::{Reader, Record, Writer};
+use subtitles::{Format, Item};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+
+    /// Zenoh connect endpoint (e.g. tcp/192.168.1.1:7447). Omit for peer discovery.
+    #[arg(long, global = true)]
+    zenoh_connect: Option<String>,
+
+    /// Zenoh mode (peer or client).
+    #[arg(long, global = true, default_value = "client")]
+    zenoh_mode: String,
+
+    /// Zenoh topic prefix.
+    #[arg(long, global = true, default_value = topics::DEFAULT_PREFIX)]
+    zenoh_prefix: String,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Record the telemetry to a file, until interrupted.
+    Record {
+        file: PathBuf,
+
+        /// Topic to record, without the prefix. Can be given more than
+        /// once. Without it, the sim telemetry, damage and battery, from
+        /// which the rest is made.
+        #[arg(long)]
+        topic: Vec<String>,
+    },
+    /// Publish a recording again, on the topics it was recorded from, with
+    /// its timing.
+    Play {
+        file: PathBuf,
+
+        /// Play this many times as fast (e.g. 0.5 for half speed).
+        #[arg(long, default_value_t = 1.0, value_parser = parse_speed)]
+        speed: f64,
+
+        /// Start over at the end, until interrupted.
+        #[arg(long = "loop")]
+        repeat: bool,
+    },
+    /// Write the telemetry of a recording as subtitles, to overlay on a
+    /// video of the flight.
+    Subtitles {
+        file: PathBuf,
+
+        /// Subtitle file to write.
+        output: PathBuf,
+
+        #[arg(long, value_enum, default_value_t = Format::Srt)]
+        format: Format,
+
+        /// Field to show, and where: `FIELD[=POSITION]`, with FIELD one of
+        /// speed, altitude, battery and lap, and POSITION one of top-left,
+        /// top, top-right, left, center, right, bottom-left, bottom and
+        /// bottom-right. Can be given more than once, or as a
+        /// comma-separated list. Without it, all fields, where they are by
+        /// default: the speed and altitude bottom left, the battery bottom
+        /// right and the lap top right.
+        #[arg(long, value_name = "FIELD[=POSITION]", value_parser = subtitles::parse_item, value_delimiter = ',')]
+        show: Vec<Item>,
+
+        /// Time in the video at which the recording starts, s. Negative if
+        /// the video starts after the recording.
+        #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+        offset: f64,
+
+        /// Update the subtitles this often, s.
+        #[arg(long, default_value_t = 0.2, value_parser = parse_interval)]
+        interval: f64,
+    },
+}
+
+/// Parse a playback speed, for use as a clap value parser.
+fn parse_speed(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(speed) if speed > 0.0 && speed.is_finite() => Ok(speed),
+        _ => Err(format!("invalid speed `{}`, must be above 0", s)),
+    }
+}
+
+/// Parse a subtitle interval, for use as a clap value parser.
+fn parse_interval(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(interval) if interval > 0.0 && interval.is_finite() => Ok(interval),
+        _ => Err(format!("invalid interval `{}`, must be above 0", s)),
+    }
+}
+
+async fn record(
+    session: &Session,
+    prefix: &str,
+    path: &Path,
+    mut suffixes: Vec<String>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if suffixes.is_empty() {
+        suffixes = [topics::TELEMETRY, topics::DAMAGE, topics::BATTERY]
+            .map(String::from)
+            .to_vec();
+    }
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    for suffix in suffixes {
+        let topic = topics::topic(prefix, &suffix);
+        info!("Subscribing to: {}", topic);
+        let subscriber = session.declare_subscriber(&topic).await?;
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            loop {
+                match subscriber.recv_async().await {
+                    Ok(sample) => {
+                        let payload = sample.payload().to_bytes().into_owned();
+                        if tx.send((Instant::now(), suffix.clone(), payload)).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        warn!("{} subscriber error: {}", topic, e);
+                        break;
+                    }
+                }
+            }
+        });
+    }
+    drop(tx);
+
+    let mut writer = Writer::new(BufWriter::new(File::create(path)?))?;
+    info!("Recording to {}", path.display());
+    let mut start = None;
+    let mut count = 0u64;
+    loop {
+        tokio::select! {
+            sample = rx.recv() => {
+                let Some((received, topic, payload)) = sample else {
+                    break;
+                };
+                let start = *start.get_or_insert(received);
+                writer.write(&Record {
+                    time: received - start,
+                    topic,
+                    payload,
+                })?;
+                count += 1;
+            }
+            _ = tokio::signal::ctrl_c() => {
+                info!("Shutdown signal received, exiting.");
+                break;
+            }
+        }
+    }
+    writer.flush()?;
+    info!("Recorded {} samples", count);
+    Ok(())
+}
+
+async fn play(
+    session: &Session,
+    prefix: &str,
+    path: &Path,
+    speed: f64,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut reader = Reader::new(BufReader::new(File::open(path)?))?;
+    info!("Playing {} at {}x", path.display(), speed);
+    let mut publishers = HashMap::new();
+    let start = Instant::now();
+    let mut count = 0u64;
+    loop {
+        let record = match reader.next_record() {
+            Ok(Some(record)) => record,
+            Ok(None) => break,
+            Err(e) => {
+                warn!("Recording ends early: {}", e);
+                break;
+            }
+        };
+        if !publishers.contains_key(&record.topic) {
+            let topic = topics::topic(prefix, &record.topic);
+            info!("Publishing on: {}", topic);
+            let publisher = session.declare_publisher(topic).await?;
+            publishers.insert(record.topic.clone(), publisher);
+        }
+        // Too far ahead for a clock, at a tiny speed.
+        let at = Duration::try_from_secs_f64(record.time.as_secs_f64() / speed)
+            .ok()
+            .and_then(|offset| start.checked_add(offset))
+            .ok_or_else(|| {
+                format!(
+                    "sample at {:.3} s is out of reach at --speed {}",
+                    record.time.as_secs_f64(),
+                    speed
+                )
+            })?;
+        sleep_until(at).await;
+        if let Err(e) = publishers[&record.topic].put(record.payload).await {
+            warn!("Failed to publish {}: {}", record.topic, e);
+        }
+        count += 1;
+    }
+    info!(
+        "Played {} samples in {:.1} s",
+        count,
+        start.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
+
+/// The records of the recording at `path`, up to where it ends early, if
+/// it does.
+fn read_records<R: Read>(path: &Path, input: R) -> std::io::Result<Vec<Record>> {
+    let mut reader = Reader::new(input)?;
+    let mut records = Vec::new();
+    loop {
+        match reader.next_record() {
+            Ok(Some(record)) => records.push(record),
+            Ok(None) => break,
+            Err(e) => {
+                warn!("{}: recording ends early: {}", path.display(), e);
+                break;
+            }
+        }
+    }
+    Ok(records)
+}
+
+fn write_subtitles(
+    path: &Path,
+    output: &Path,
+    format: Format,
+    mut layout: Vec<Item>,
+    offset: f64,
+    interval: f64,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if layout.is_empty() {
+        layout = subtitles::DEFAULT_LAYOUT.to_vec();
+    }
+    let records = read_records(path, BufReader::new(File::open(path)?))?;
+    let cues = subtitles::cues(records, &layout, interval, offset);
+    let text = match format {
+        Format::Srt => subtitles::srt(&cues),
+        Format::Ass => subtitles::ass(&cues),
+    };
+    std::fs::write(output, text)?;
+    info!("Wrote {} subtitles to {}", cues.len(), output.display());
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    env_logger::init();
+    let args = Args::parse();
+
+    info!("Starting liftoff-replay");
+
+    if let Command::Subtitles {
+        ref file,
+        ref output,
+        format,
+        ref show,
+        offset,
+        interval,
+    } = args.command
+    {
+        return write_subtitles(file, output, format, show.clone(), offset, interval);
+    }
+
+    // Zenoh session
+    let mut config = Config::default();
+    config.insert_json5("mode", &format!(r#""{}""#, args.zenoh_mode))?;
+    if let Some(ref endpoint) = args.zenoh_connect {
+        config.insert_json5("connect/endpoints", &format!(r#"["{}"]"#, endpoint))?;
+    }
+
+    let session = zenoh::open(config).await?;
+
+    match args.command {
+        Command::Record { file, topic } => {
+            record(&session, &args.zenoh_prefix, &file, topic).await?;
+        }
+        Command::Play {
+            file,
+            speed,
+            repeat,
+        } => loop {
+            tokio::select! {
+                result = play(&session, &args.zenoh_prefix, &file, speed) => result?,
+                _ = tokio::signal::ctrl_c() => {
+                    info!("Shutdown signal received, exiting.");
+                    break;
+                }
+            }
+            if !repeat {
+                break;
+            }
+        },
+        Command::Subtitles { .. } => unreachable!(),
+    }
+
+    session.close().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn speed() {
+        assert_eq!(parse_speed("0.5"), Ok(0.5));
+        assert!(parse_speed("0").is_err());
+        assert!(parse_speed("inf").is_err());
+        assert!(parse_speed("fast").is_err());
+        assert_eq!(parse_interval("0.1"), Ok(0.1));
+        assert!(parse_interval("-1").is_err());
+    }
+}
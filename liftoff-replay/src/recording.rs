@@ -0,0 +1,137 @@
+//! The recording file format.
+//!
+//! The magic `LFRP` and a version byte, then a record per sample: the time
+//! since the start of the recording in µs (u64), the topic without its
+//! prefix (u8 length, then the bytes), and the payload (u32 length, then
+//! the bytes), all little-endian.
+
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+const MAGIC: &[u8; 4] = b"LFRP";
+const VERSION: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// Since the start of the recording.
+    pub time: Duration,
+    /// Topic, without the prefix (e.g. `telemetry`).
+    pub topic: String,
+    pub payload: Vec<u8>,
+}
+
+pub struct Writer<W: Write> {
+    out: W,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(mut out: W) -> io::Result<Self> {
+        out.write_all(MAGIC)?;
+        out.write_all(&[VERSION])?;
+        Ok(Self { out })
+    }
+
+    pub fn write(&mut self, record: &Record) -> io::Result<()> {
+        let topic = record.topic.as_bytes();
+        let topic_len = u8::try_from(topic.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "topic too long"))?;
+        let mut data = Vec::with_capacity(13 + topic.len() + record.payload.len());
+        data.extend_from_slice(&(record.time.as_micros() as u64).to_le_bytes());
+        data.push(topic_len);
+        data.extend_from_slice(topic);
+        data.extend_from_slice(&(record.payload.len() as u32).to_le_bytes());
+        data.extend_from_slice(&record.payload);
+        self.out.write_all(&data)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+pub struct Reader<R: Read> {
+    input: R,
+}
+
+impl<R: Read> Reader<R> {
+    pub fn new(mut input: R) -> io::Result<Self> {
+        let mut header = [0u8; 5];
+        input.read_exact(&mut header)?;
+        if header[..4] != MAGIC[..] {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a liftoff-replay recording",
+            ));
+        }
+        if header[4] != VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported recording version {}", header[4]),
+            ));
+        }
+        Ok(Self { input })
+    }
+
+    /// The next record, or `None` at the end of the recording.
+    pub fn next_record(&mut self) -> io::Result<Option<Record>> {
+        let mut time = [0u8; 8];
+        // The end, unless it is in the middle of a record.
+        let read = self.input.read(&mut time)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        self.input.read_exact(&mut time[read..])?;
+        let mut topic_len = [0u8; 1];
+        self.input.read_exact(&mut topic_len)?;
+        let mut topic = vec![0u8; topic_len[0] as usize];
+        self.input.read_exact(&mut topic)?;
+        let mut payload_len = [0u8; 4];
+        self.input.read_exact(&mut payload_len)?;
+        let mut payload = vec![0u8; u32::from_le_bytes(payload_len) as usize];
+        self.input.read_exact(&mut payload)?;
+        Ok(Some(Record {
+            time: Duration::from_micros(u64::from_le_bytes(time)),
+            topic: String::from_utf8(topic)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            payload,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let records = [
+            Record {
+                time: Duration::ZERO,
+                topic: "telemetry".into(),
+                payload: vec![1, 2, 3],
+            },
+            Record {
+                time: Duration::from_micros(1_500_250),
+                topic: "battery".into(),
+                payload: Vec::new(),
+            },
+        ];
+        let mut writer = Writer::new(Vec::new()).unwrap();
+        for record in &records {
+            writer.write(record).unwrap();
+        }
+        let data = writer.out;
+        assert_eq!(data[..5], *b"LFRP\x01");
+
+        let mut reader = Reader::new(&data[..]).unwrap();
+        assert_eq!(reader.next_record().unwrap().as_ref(), Some(&records[0]));
+        assert_eq!(reader.next_record().unwrap().as_ref(), Some(&records[1]));
+        assert_eq!(reader.next_record().unwrap(), None);
+
+        let mut truncated = Reader::new(&data[..data.len() - 1]).unwrap();
+        truncated.next_record().unwrap();
+        assert!(truncated.next_record().is_err());
+        assert!(Reader::new(&b"PCAP\x01"[..]).is_err());
+        assert!(Reader::new(&b"LFRP\x02"[..]).is_err());
+    }
+}
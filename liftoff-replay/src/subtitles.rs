@@ -0,0 +1,465 @@
+//! Telemetry overlays for videos of a recorded flight, as SRT or ASS
+//! subtitles.
+//!
+//! The recording is sampled every interval, and each field is shown at its
+//! place on the screen: the speed, the altitude and the battery from the
+//! sim telemetry, and the lap timing from the race events, when those were
+//! recorded too (`--topic race`). Fields at the same place are shown one
+//! below the other. A subtitle lasts as long as its text stays the same.
+//!
+//! The places are the numpad alignments of ASS, `{\anN}`, which most
+//! players also honor in SRT.
+
+use std::fmt::Write;
+
+use clap::ValueEnum;
+use telemetry_lib::race::Event;
+use telemetry_lib::telemetry;
+use telemetry_lib::topics;
+
+use crate::recording::Record;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Srt,
+    Ass,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    /// Ground speed, km/h.
+    Speed,
+    /// Height, m.
+    Altitude,
+    /// Voltage and charge left.
+    Battery,
+    /// The lap under way, and the last and best laps.
+    Lap,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Position {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Position {
+    /// The ASS alignment, as on a numpad.
+    fn alignment(self) -> u8 {
+        match self {
+            Position::BottomLeft => 1,
+            Position::Bottom => 2,
+            Position::BottomRight => 3,
+            Position::Left => 4,
+            Position::Center => 5,
+            Position::Right => 6,
+            Position::TopLeft => 7,
+            Position::Top => 8,
+            Position::TopRight => 9,
+        }
+    }
+}
+
+/// A field, and where it is shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Item {
+    pub field: Field,
+    pub position: Position,
+}
+
+/// The fields shown without `--show`.
+pub const DEFAULT_LAYOUT: [Item; 4] = [
+    Item {
+        field: Field::Speed,
+        position: Position::BottomLeft,
+    },
+    Item {
+        field: Field::Altitude,
+        position: Position::BottomLeft,
+    },
+    Item {
+        field: Field::Battery,
+        position: Position::BottomRight,
+    },
+    Item {
+        field: Field::Lap,
+        position: Position::TopRight,
+    },
+];
+
+/// Parse `FIELD=POSITION` (e.g. `speed=top-left`), for use as a clap value
+/// parser. Without a position, the field is where it is by default.
+pub fn parse_item(s: &str) -> Result<Item, String> {
+    let (field, position) = match s.split_once('=') {
+        Some((field, position)) => (field, Some(position)),
+        None => (s, None),
+    };
+    let field = Field::from_str(field, true)?;
+    let position = match position {
+        Some(position) => Position::from_str(position, true)?,
+        None => {
+            DEFAULT_LAYOUT
+                .iter()
+                .find(|item| item.field == field)
+                .unwrap()
+                .position
+        }
+    };
+    Ok(Item { field, position })
+}
+
+/// A subtitle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cue {
+    /// Times in the video, s.
+    pub start: f64,
+    pub end: f64,
+    pub position: Position,
+    pub lines: Vec<String>,
+}
+
+/// What is known at a time in the recording.
+#[derive(Default)]
+struct State {
+    /// m/s.
+    speed: Option<f64>,
+    altitude: Option<f64>,
+    /// Fraction left, and V.
+    battery: Option<[f64; 2]>,
+    /// The lap under way, and when it started.
+    lap: Option<(usize, f64)>,
+    last_lap: Option<f64>,
+    best_lap: Option<f64>,
+}
+
+impl State {
+    fn update(&mut self, time: f64, record: &Record, format: &[String]) {
+        if record.topic == topics::TELEMETRY {
+            let Ok(packet) = telemetry::parse_packet(&record.payload, format) else {
+                return;
+            };
+            if let Some(v) = packet.velocity {
+                self.speed = Some(v.map(f64::from).iter().map(|v| v * v).sum::<f64>().sqrt());
+            }
+            if let Some(p) = packet.position {
+                self.altitude = Some(f64::from(p[1]));
+            }
+            if let Some(b) = packet.battery {
+                self.battery = Some(b.map(f64::from));
+            }
+        } else if record.topic == topics::RACE {
+            match serde_json::from_slice(&record.payload) {
+                Ok(Event::Start { .. }) => {
+                    let lap = self.lap.map_or(1, |(lap, _)| lap);
+                    self.lap = Some((lap, time));
+                }
+                Ok(Event::Lap {
+                    lap,
+                    time: lap_time,
+                    ..
+                }) => {
+                    self.last_lap = Some(lap_time);
+                    self.best_lap = Some(self.best_lap.map_or(lap_time, |t| t.min(lap_time)));
+                    self.lap = Some((lap + 1, time));
+                }
+                Ok(Event::Split { .. }) | Err(_) => {}
+            }
+        }
+    }
+
+    /// The lines of `field` at `time`, none if it is not known.
+    fn lines(&self, field: Field, time: f64) -> Vec<String> {
+        match field {
+            Field::Speed => self
+                .speed
+                .map(|speed| format!("SPD {:.0} km/h", speed * 3.6))
+                .into_iter()
+                .collect(),
+            Field::Altitude => self
+                .altitude
+                .map(|altitude| format!("ALT {:.0} m", altitude))
+                .into_iter()
+                .collect(),
+            Field::Battery => self
+                .battery
+                .map(|[fraction, voltage]| format!("BAT {:.1} V {:.0}%", voltage, fraction * 100.0))
+                .into_iter()
+                .collect(),
+            Field::Lap => {
+                let mut lines = Vec::new();
+                if let Some((lap, start)) = self.lap {
+                    lines.push(format!("LAP {} {:.1} s", lap, time - start));
+                }
+                if let (Some(last), Some(best)) = (self.last_lap, self.best_lap) {
+                    lines.push(format!("LAST {:.2} s BEST {:.2} s", last, best));
+                }
+                lines
+            }
+        }
+    }
+}
+
+/// The subtitles for the records of a recording, in order, sampled every
+/// `interval` s. The recording starts `offset` s into the video; what
+/// would be shown before the start of the video is left out.
+pub fn cues(
+    records: impl IntoIterator<Item = Record>,
+    layout: &[Item],
+    interval: f64,
+    offset: f64,
+) -> Vec<Cue> {
+    // Telemetry format config
+    // We assume default configuration for now
+    let format = telemetry::default_stream_format();
+    let mut positions: Vec<Position> = Vec::new();
+    for item in layout {
+        if !positions.contains(&item.position) {
+            positions.push(item.position);
+        }
+    }
+    let sample = |state: &State, time: f64| -> Vec<Vec<String>> {
+        positions
+            .iter()
+            .map(|&position| {
+                layout
+                    .iter()
+                    .filter(|item| item.position == position)
+                    .flat_map(|item| state.lines(item.field, time))
+                    .collect()
+            })
+            .collect()
+    };
+
+    // The lines at each position, every interval.
+    let mut samples = Vec::new();
+    let mut state = State::default();
+    let mut end = 0.0;
+    for record in records {
+        let time = record.time.as_secs_f64();
+        // Those before the record, with the records before it.
+        while (samples.len() as f64) * interval < time {
+            let at = samples.len() as f64 * interval;
+            samples.push(sample(&state, at));
+        }
+        state.update(time, &record, &format);
+        end = time;
+    }
+    while (samples.len() as f64) * interval <= end {
+        let at = samples.len() as f64 * interval;
+        samples.push(sample(&state, at));
+    }
+
+    let mut cues: Vec<Cue> = Vec::new();
+    for (i, &position) in positions.iter().enumerate() {
+        let mut open: Option<Cue> = None;
+        for (n, lines) in samples.iter().map(|sample| &sample[i]).enumerate() {
+            let start = n as f64 * interval + offset;
+            match open {
+                Some(ref mut cue) if cue.lines == *lines => cue.end = start + interval,
+                _ => {
+                    cues.extend(open.take());
+                    if !lines.is_empty() {
+                        open = Some(Cue {
+                            start,
+                            end: start + interval,
+                            position,
+                            lines: lines.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        cues.extend(open);
+    }
+    cues.retain(|cue| cue.end > 0.0);
+    for cue in &mut cues {
+        cue.start = cue.start.max(0.0);
+    }
+    cues.sort_by(|a, b| a.start.total_cmp(&b.start));
+    cues
+}
+
+/// `time` (s) as hours, minutes, seconds and the rest in `digits` digits.
+fn timestamp(time: f64, digits: u32) -> (u64, u64, u64, u64) {
+    let scale = 10u64.pow(digits);
+    let total = (time * scale as f64).round() as u64;
+    let seconds = total / scale;
+    (
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        total % scale,
+    )
+}
+
+/// The subtitles as SRT.
+pub fn srt(cues: &[Cue]) -> String {
+    let srt_time = |time| {
+        let (h, m, s, ms) = timestamp(time, 3);
+        format!("{:02}:{:02}:{:02},{:03}", h, m, s, ms)
+    };
+    let mut out = String::new();
+    for (n, cue) in cues.iter().enumerate() {
+        let _ = write!(
+            out,
+            "{}\n{} --> {}\n{{\\an{}}}{}\n\n",
+            n + 1,
+            srt_time(cue.start),
+            srt_time(cue.end),
+            cue.position.alignment(),
+            cue.lines.join("\n")
+        );
+    }
+    out
+}
+
+/// The subtitles as ASS, for a 1920×1080 video (players scale it).
+pub fn ass(cues: &[Cue]) -> String {
+    let ass_time = |time| {
+        let (h, m, s, cs) = timestamp(time, 2);
+        format!("{}:{:02}:{:02}.{:02}", h, m, s, cs)
+    };
+    let mut out = String::from(
+        "[Script Info]\n\
+         ScriptType: v4.00+\n\
+         PlayResX: 1920\n\
+         PlayResY: 1080\n\
+         ScaledBorderAndShadow: yes\n\
+         \n\
+         [V4+ Styles]\n\
+         Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, \
+         BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, \
+         BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\n\
+         Style: Default,Monospace,44,&H00FFFFFF,&H000000FF,&H00000000,&H80000000,\
+         -1,0,0,0,100,100,0,0,1,3,0,2,40,40,40,1\n\
+         \n\
+         [Events]\n\
+         Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n",
+    );
+    for cue in cues {
+        let _ = writeln!(
+            out,
+            "Dialogue: 0,{},{},Default,,0,0,0,,{{\\an{}}}{}",
+            ass_time(cue.start),
+            ass_time(cue.end),
+            cue.position.alignment(),
+            cue.lines.join("\\N")
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use telemetry_lib::telemetry::TelemetryPacket;
+
+    fn telemetry(time: f64, speed: f32) -> Record {
+        let packet = TelemetryPacket {
+            timestamp: Some(time as f32),
+            position: Some([0.0, 12.0, 0.0]),
+            attitude: Some([0.0, 0.0, 0.0, 1.0]),
+            velocity: Some([speed, 0.0, 0.0]),
+            gyro: Some([0.0; 3]),
+            input: Some([0.0; 4]),
+            battery: Some([0.75, 15.84]),
+            motor_rpm: Some(vec![0.0; 4]),
+        };
+        Record {
+            time: Duration::from_secs_f64(time),
+            topic: topics::TELEMETRY.into(),
+            payload: telemetry::encode_packet(&packet, &telemetry::default_stream_format())
+                .unwrap(),
+        }
+    }
+
+    fn race(time: f64, event: Event) -> Record {
+        Record {
+            time: Duration::from_secs_f64(time),
+            topic: topics::RACE.into(),
+            payload: serde_json::to_vec(&event).unwrap(),
+        }
+    }
+
+    #[test]
+    fn overlay() {
+        assert_eq!(
+            parse_item("SPEED=top"),
+            Ok(Item {
+                field: Field::Speed,
+                position: Position::Top
+            })
+        );
+        assert_eq!(parse_item("lap"), Ok(DEFAULT_LAYOUT[3]));
+        assert!(parse_item("speed=middle").is_err());
+
+        let records = vec![
+            telemetry(0.5, 10.0),
+            race(1.5, Event::Start { missed: 0 }),
+            telemetry(2.5, 20.0),
+            race(
+                3.5,
+                Event::Lap {
+                    lap: 1,
+                    time: 2.0,
+                    best: true,
+                },
+            ),
+            telemetry(4.5, 20.0),
+        ];
+        let layout = [
+            parse_item("speed").unwrap(),
+            parse_item("battery").unwrap(),
+            parse_item("lap=bottom-left").unwrap(),
+        ];
+        // The video starts 1.5 s into the recording.
+        let cues = cues(records, &layout, 1.0, -1.5);
+        let texts: Vec<_> = cues
+            .iter()
+            .map(|cue| (cue.start, cue.end, cue.position, cue.lines.join("|")))
+            .collect();
+        assert_eq!(
+            texts,
+            [
+                (0.0, 0.5, Position::BottomLeft, "SPD 36 km/h".into()),
+                (0.0, 3.5, Position::BottomRight, "BAT 15.8 V 75%".into()),
+                (
+                    0.5,
+                    1.5,
+                    Position::BottomLeft,
+                    "SPD 36 km/h|LAP 1 0.5 s".into()
+                ),
+                (
+                    1.5,
+                    2.5,
+                    Position::BottomLeft,
+                    "SPD 72 km/h|LAP 1 1.5 s".into()
+                ),
+                (
+                    2.5,
+                    3.5,
+                    Position::BottomLeft,
+                    "SPD 72 km/h|LAP 2 0.5 s|LAST 2.00 s BEST 2.00 s".into()
+                ),
+            ]
+        );
+
+        let srt = srt(&cues[..1]);
+        assert_eq!(
+            srt,
+            "1\n00:00:00,000 --> 00:00:00,500\n{\\an1}SPD 36 km/h\n\n"
+        );
+        let ass = ass(&cues[..1]);
+        assert!(
+            ass.ends_with("Dialogue: 0,0:00:00.00,0:00:00.50,Default,,0,0,0,,{\\an1}SPD 36 km/h\n")
+        );
+    }
+}
@@ -0,0 +1,140 @@
+//! CDR, the serialization of ROS 2 messages on the wire: little-endian,
+//! after a 4-byte encapsulation header, with each value aligned to its
+//! size from the end of the header. Strings are a u32 length including the
+//! terminating NUL, then the bytes and the NUL; sequences a u32 length,
+//! then the elements; fixed-size arrays just the elements.
+
+/// `CDR_LE`, no options.
+const HEADER: [u8; 4] = [0x00, 0x01, 0x00, 0x00];
+
+pub struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Default for Writer {
+    fn default() -> Self {
+        Self {
+            buf: HEADER.to_vec(),
+        }
+    }
+}
+
+impl Writer {
+    fn align(&mut self, size: usize) {
+        while !(self.buf.len() - HEADER.len()).is_multiple_of(size) {
+            self.buf.push(0);
+        }
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    pub fn i8(&mut self, value: i8) {
+        self.buf.push(value as u8);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.buf.push(u8::from(value));
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.align(2);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn i32(&mut self, value: i32) {
+        self.align(4);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.align(4);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn f32(&mut self, value: f32) {
+        self.align(4);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn f64(&mut self, value: f64) {
+        self.align(8);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32 + 1);
+        self.buf.extend_from_slice(value.as_bytes());
+        self.buf.push(0);
+    }
+
+    /// A sequence of `float32`.
+    pub fn f32_sequence(&mut self, values: &[f32]) {
+        self.u32(values.len() as u32);
+        for &value in values {
+            self.f32(value);
+        }
+    }
+
+    /// A fixed-size array of `float64`.
+    pub fn f64_array(&mut self, values: &[f64]) {
+        for &value in values {
+            self.f64(value);
+        }
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+pub struct Reader<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    /// `None` unless `data` is little-endian CDR.
+    pub fn new(data: &'a [u8]) -> Option<Self> {
+        (*data.get(..2)? == HEADER[..2]).then_some(Self {
+            data,
+            at: HEADER.len(),
+        })
+    }
+
+    pub fn u16(&mut self) -> Option<u16> {
+        self.at += (self.at - HEADER.len()) % 2;
+        let bytes = self.data.get(self.at..self.at + 2)?;
+        self.at += 2;
+        Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoding() {
+        let mut writer = Writer::default();
+        writer.u8(1);
+        writer.f64(2.0);
+        writer.string("ab");
+        writer.u16(3);
+        writer.f32_sequence(&[4.0]);
+        let data = writer.finish();
+        let mut expected = vec![0, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0];
+        expected.extend_from_slice(&2.0f64.to_le_bytes());
+        expected.extend_from_slice(&[3, 0, 0, 0, b'a', b'b', 0]);
+        expected.extend_from_slice(&[0, 3, 0, 0, 0, 1, 0, 0, 0]);
+        expected.extend_from_slice(&4.0f32.to_le_bytes());
+        assert_eq!(data, expected);
+
+        let mut reader = Reader::new(&[0, 1, 0, 0, 7, 0, 8, 0, 9]).unwrap();
+        assert_eq!(reader.u16(), Some(7));
+        assert_eq!(reader.u16(), Some(8));
+        assert_eq!(reader.u16(), None);
+        assert!(Reader::new(&[0, 0, 0, 0]).is_none());
+    }
+}
@@ -0,0 +1,201 @@
+//! The sim telemetry as ROS 2 messages, in the ROS frames (REP 103 and
+//! 105).
+//!
+//! Liftoff's world is left-handed, X east, Y up and Z north; the ROS world
+//! (`odom`) is east, north, up. The quad's body is right, up, forward in
+//! Liftoff; `base_link` is forward, left, up. The gyro gives the rates
+//! about the right, forward and up axes, in deg/s. The acceleration, which
+//! the sim doesn't give, is the change of velocity between packets, and
+//! gravity, as an accelerometer measures it.
+
+use std::f64::consts::FRAC_1_SQRT_2;
+use std::time::SystemTime;
+
+use telemetry_lib::geo;
+use telemetry_lib::telemetry::TelemetryPacket;
+
+use crate::msgs::{BatteryState, Header, Imu, NavSatFix, Odometry};
+
+pub const WORLD_FRAME: &str = "odom";
+pub const BODY_FRAME: &str = "base_link";
+
+const G: f64 = 9.80665;
+
+/// The body axes of Liftoff turned to those of ROS: a quarter turn left
+/// about up.
+const BODY_TURN: [f64; 4] = [0.0, 0.0, FRAC_1_SQRT_2, FRAC_1_SQRT_2];
+
+/// A Liftoff position or velocity, in the ROS world.
+fn world([x, y, z]: [f32; 3]) -> [f64; 3] {
+    [x, z, y].map(f64::from)
+}
+
+/// Quaternion product, x, y, z, w.
+fn mul([ax, ay, az, aw]: [f64; 4], [bx, by, bz, bw]: [f64; 4]) -> [f64; 4] {
+    [
+        aw * bx + ax * bw + ay * bz - az * by,
+        aw * by - ax * bz + ay * bw + az * bx,
+        aw * bz + ax * by - ay * bx + az * bw,
+        aw * bw - ax * bx - ay * by - az * bz,
+    ]
+}
+
+/// `v` in the world turned into the frame that `q` turns the world to.
+fn to_body(q: [f64; 4], [vx, vy, vz]: [f64; 3]) -> [f64; 3] {
+    let [x, y, z, w] = q;
+    let conjugate = [-x, -y, -z, w];
+    let [x, y, z, _] = mul(mul(conjugate, [vx, vy, vz, 0.0]), q);
+    [x, y, z]
+}
+
+/// The attitude of the quad, as the orientation of `base_link` in the ROS
+/// world.
+fn orientation([x, y, z, w]: [f32; 4]) -> [f64; 4] {
+    // Swapping Y and Z mirrors the world, which negates the axis.
+    let world = [-x, -z, -y, w].map(f64::from);
+    mul(world, BODY_TURN)
+}
+
+/// The gyro, as the angular velocity about the `base_link` axes, rad/s.
+fn angular_velocity([right, forward, up]: [f32; 3]) -> [f64; 3] {
+    // Liftoff's rates turn the other way, its world being left-handed; and
+    // right is minus left.
+    [-forward, right, -up].map(|rate| f64::from(rate).to_radians())
+}
+
+pub struct Messages {
+    pub odometry: Option<Odometry>,
+    pub imu: Option<Imu>,
+    pub battery: Option<BatteryState>,
+    pub fix: Option<NavSatFix>,
+}
+
+pub struct Converter {
+    /// Latitude and longitude of the scene origin, degrees.
+    home: (f64, f64),
+    /// Time (s) and world velocity of the last packet.
+    last: Option<(f64, [f64; 3])>,
+}
+
+impl Converter {
+    pub fn new(home: (f64, f64)) -> Self {
+        Self { home, last: None }
+    }
+
+    /// The messages from `packet`, received at `stamp`, as far as it has
+    /// what they need.
+    pub fn convert(&mut self, packet: &TelemetryPacket, stamp: SystemTime) -> Messages {
+        let header = |frame_id: &str| Header {
+            stamp,
+            frame_id: frame_id.to_string(),
+        };
+        let orientation = packet.attitude.map(orientation);
+        let velocity = packet.velocity.map(world);
+
+        // The world acceleration since the last packet, and gravity.
+        let mut acceleration = None;
+        if let (Some(time), Some(velocity)) = (packet.timestamp.map(f64::from), velocity) {
+            if let Some((last_time, last_velocity)) = self.last
+                && time > last_time
+            {
+                let dt = time - last_time;
+                let a = [0, 1, 2].map(|i| (velocity[i] - last_velocity[i]) / dt);
+                acceleration = Some([a[0], a[1], a[2] + G]);
+            }
+            self.last = Some((time, velocity));
+        }
+
+        let odometry = match (packet.position, orientation, velocity, packet.gyro) {
+            (Some(position), Some(orientation), Some(velocity), Some(gyro)) => Some(Odometry {
+                header: header(WORLD_FRAME),
+                child_frame_id: BODY_FRAME.to_string(),
+                position: world(position),
+                orientation,
+                linear: to_body(orientation, velocity),
+                angular: angular_velocity(gyro),
+            }),
+            _ => None,
+        };
+        let imu = match (orientation, packet.gyro) {
+            (Some(orientation), Some(gyro)) => Some(Imu {
+                header: header(BODY_FRAME),
+                orientation,
+                angular_velocity: angular_velocity(gyro),
+                linear_acceleration: acceleration.map(|a| to_body(orientation, a)),
+            }),
+            _ => None,
+        };
+        let battery = packet.battery.map(|[fraction, voltage]| BatteryState {
+            header: header(BODY_FRAME),
+            voltage,
+            percentage: fraction,
+        });
+        let fix = packet.position.map(|position| {
+            let (lon, lat, alt) =
+                geo::gps_from_coord(&position.map(f64::from), (self.home.1, self.home.0));
+            NavSatFix {
+                header: header(BODY_FRAME),
+                latitude: lat,
+                longitude: lon,
+                // The scene origin at mean sea level.
+                altitude: alt + geo::geoid_separation(lat, lon),
+            }
+        });
+        Messages {
+            odometry,
+            imu,
+            battery,
+            fix,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: &[f64], b: &[f64]) -> bool {
+        a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-6)
+    }
+
+    fn packet(time: f32, attitude: [f32; 4], speed: f32) -> TelemetryPacket {
+        TelemetryPacket {
+            timestamp: Some(time),
+            position: Some([3.0, 2.0, 111.111]),
+            attitude: Some(attitude),
+            velocity: Some([0.0, 0.0, speed]),
+            gyro: Some([10.0, 0.0, 0.0]),
+            input: None,
+            battery: Some([0.5, 15.2]),
+            motor_rpm: None,
+        }
+    }
+
+    #[test]
+    fn frames() {
+        let mut converter = Converter::new((0.0, 0.0));
+        let level = [0.0, 0.0, 0.0, 1.0];
+        let messages = converter.convert(&packet(1.0, level, 5.0), SystemTime::UNIX_EPOCH);
+        let odometry = messages.odometry.unwrap();
+        // Facing north, which is a quarter turn left of east; flying
+        // forward, and pitching down.
+        assert_eq!(odometry.position, [3.0, 111.111f32 as f64, 2.0]);
+        assert!(close(&odometry.orientation, &BODY_TURN));
+        assert!(close(&odometry.linear, &[5.0, 0.0, 0.0]));
+        assert!(close(&odometry.angular, &[0.0, 10f64.to_radians(), 0.0]));
+        assert_eq!(messages.imu.unwrap().linear_acceleration, None);
+        let fix = messages.fix.unwrap();
+        assert!((fix.latitude - 0.001).abs() < 1e-6);
+        assert_eq!(messages.battery.unwrap().voltage, 15.2);
+
+        // Facing east: a quarter turn right about up, in Liftoff.
+        let east = [0.0, FRAC_1_SQRT_2 as f32, 0.0, FRAC_1_SQRT_2 as f32];
+        let messages = converter.convert(&packet(1.5, east, 6.0), SystemTime::UNIX_EPOCH);
+        let odometry = messages.odometry.unwrap();
+        assert!(close(&odometry.orientation, &[0.0, 0.0, 0.0, 1.0]));
+        // Flying north is flying left.
+        assert!(close(&odometry.linear, &[0.0, 6.0, 0.0]));
+        let acceleration = messages.imu.unwrap().linear_acceleration.unwrap();
+        assert!(close(&acceleration, &[0.0, 2.0, G]));
+    }
+}
@@ -0,0 +1,206 @@
+use clap::Parser;
+use log::{debug, info, warn};
+use std::time::SystemTime;
+use telemetry_lib::crsf::{self, CrsfPacket};
+use telemetry_lib::telemetry;
+use telemetry_lib::topics;
+use zenoh::Config;
+
+mod cdr;
+mod convert;
+mod msgs;
+
+use convert::Converter;
+
+/// The center of a CRSF channel, 1500 µs.
+const CHANNEL_MID: u16 = 992;
+/// The range of an overridden channel, µs.
+const OVERRIDE_MIN: u16 = 988;
+const OVERRIDE_MAX: u16 = 2012;
+/// `mavros_msgs/OverrideRCIn` values that release a channel, and that
+/// leave it as it is.
+const CHAN_RELEASE: u16 = 0;
+const CHAN_NOCHANGE: u16 = 65535;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// ROS namespace of the topics, as zenoh-bridge-ros2dds maps them to
+    /// Zenoh keys: odom, imu, battery_state, fix and rc/override under it.
+    #[arg(long, default_value = "liftoff")]
+    ros_namespace: String,
+
+    /// Receive mavros_msgs/OverrideRCIn on rc/override, and publish the
+    /// channels it overrides on the `crsf/rc/ros` topic, for crsf-joystick
+    /// (`--rc-source ros=crsf/rc/ros`).
+    #[arg(long, default_value_t = false)]
+    rc_override: bool,
+
+    /// Latitude of the scene origin, degrees. Liftoff's scene is placed at
+    /// 0°N 0°E otherwise.
+    #[arg(long, allow_negative_numbers = true, requires = "home_lon")]
+    home_lat: Option<f64>,
+
+    /// Longitude of the scene origin, degrees.
+    #[arg(long, allow_negative_numbers = true, requires = "home_lat")]
+    home_lon: Option<f64>,
+
+    /// Zenoh connect endpoint (e.g. tcp/192.168.1.1:7447). Omit for peer discovery.
+    #[arg(long)]
+    zenoh_connect: Option<String>,
+
+    /// Zenoh mode (peer or client).
+    #[arg(long, default_value = "client")]
+    zenoh_mode: String,
+
+    /// Zenoh topic prefix.
+    #[arg(long, default_value = topics::DEFAULT_PREFIX)]
+    zenoh_prefix: String,
+}
+
+/// Apply the RC override `channels` (µs) to the CRSF `rc` channels. Released
+/// channels go back to the center. Whether any channel is overridden.
+fn apply_override(rc: &mut [u16; 16], channels: &[u16; 18]) -> bool {
+    let mut overridden = false;
+    for (value, &us) in rc.iter_mut().zip(channels) {
+        match us {
+            CHAN_NOCHANGE => overridden |= *value != CHANNEL_MID,
+            CHAN_RELEASE => *value = CHANNEL_MID,
+            us => {
+                *value = crsf::us_to_ticks(us.clamp(OVERRIDE_MIN, OVERRIDE_MAX));
+                overridden = true;
+            }
+        }
+    }
+    overridden
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    env_logger::init();
+    let args = Args::parse();
+
+    info!("Starting liftoff-ros2");
+
+    // Zenoh session
+    let mut config = Config::default();
+    config.insert_json5("mode", &format!(r#""{}""#, args.zenoh_mode))?;
+    if let Some(ref endpoint) = args.zenoh_connect {
+        config.insert_json5("connect/endpoints", &format!(r#"["{}"]"#, endpoint))?;
+    }
+
+    let session = zenoh::open(config).await?;
+
+    let ros_topic = |name: &str| topics::topic(&args.ros_namespace, name);
+    let mut publishers = Vec::new();
+    for name in ["odom", "imu", "battery_state", "fix"] {
+        let topic = ros_topic(name);
+        info!("Publishing on: {}", topic);
+        publishers.push(session.declare_publisher(topic).await?);
+    }
+
+    if args.rc_override {
+        let override_topic = ros_topic("rc/override");
+        let rc_topic = topics::topic(&args.zenoh_prefix, topics::CRSF_RC_ROS);
+        info!("Subscribing to: {}", override_topic);
+        info!("Publishing on: {}", rc_topic);
+        let override_subscriber = session.declare_subscriber(&override_topic).await?;
+        let rc_publisher = session.declare_publisher(rc_topic).await?;
+        tokio::spawn(async move {
+            let mut channels = [CHANNEL_MID; 16];
+            loop {
+                let sample = match override_subscriber.recv_async().await {
+                    Ok(sample) => sample,
+                    Err(e) => {
+                        warn!("RC override subscriber error: {}", e);
+                        break;
+                    }
+                };
+                let Some(values) = msgs::override_rc_in(&sample.payload().to_bytes()) else {
+                    debug!("Not an OverrideRCIn");
+                    continue;
+                };
+                // Nothing overridden: leave it to the other sources.
+                if !apply_override(&mut channels, &values) {
+                    continue;
+                }
+                let rc_packet = CrsfPacket::RcChannelsPacked(crsf::RcChannelsPacked { channels });
+                let frame = crsf::build_packet(crsf::device_address::FLIGHT_CONTROLLER, &rc_packet)
+                    .expect("channel values out of range");
+                if let Err(e) = rc_publisher.put(frame).await {
+                    warn!("Publish error: {}", e);
+                }
+            }
+        });
+    }
+
+    let tel_topic = topics::topic(&args.zenoh_prefix, topics::TELEMETRY);
+    info!("Subscribing to: {}", tel_topic);
+    let tel_subscriber = session.declare_subscriber(&tel_topic).await?;
+
+    // Telemetry format config
+    // We assume default configuration for now
+    let format = telemetry::default_stream_format();
+    let home = (args.home_lat.unwrap_or(0.0), args.home_lon.unwrap_or(0.0));
+    let mut converter = Converter::new(home);
+    loop {
+        let sample = tokio::select! {
+            sample = tel_subscriber.recv_async() => match sample {
+                Ok(sample) => sample,
+                Err(e) => {
+                    warn!("Sim telemetry subscriber error: {}", e);
+                    break;
+                }
+            },
+            _ = tokio::signal::ctrl_c() => {
+                info!("Shutdown signal received, exiting.");
+                break;
+            }
+        };
+        let packet = match telemetry::parse_packet(&sample.payload().to_bytes(), &format) {
+            Ok(packet) => packet,
+            Err(e) => {
+                debug!("Sim telemetry: {}", e);
+                continue;
+            }
+        };
+        let messages = converter.convert(&packet, SystemTime::now());
+        let payloads = [
+            messages.odometry.map(|m| m.encode()),
+            messages.imu.map(|m| m.encode()),
+            messages.battery.map(|m| m.encode()),
+            messages.fix.map(|m| m.encode()),
+        ];
+        for (publisher, payload) in publishers.iter().zip(payloads) {
+            if let Some(payload) = payload
+                && let Err(e) = publisher.put(payload).await
+            {
+                warn!("Failed to publish {}: {}", publisher.key_expr(), e);
+            }
+        }
+    }
+
+    session.close().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rc_override() {
+        let mut rc = [CHANNEL_MID; 16];
+        let mut values = [CHAN_NOCHANGE; 18];
+        assert!(!apply_override(&mut rc, &values));
+        values[2] = 1000;
+        values[4] = 2500;
+        assert!(apply_override(&mut rc, &values));
+        assert_eq!((rc[2], rc[4]), (crsf::us_to_ticks(1000), 1811));
+        values[4] = CHAN_NOCHANGE;
+        values[2] = CHAN_RELEASE;
+        assert!(apply_override(&mut rc, &values));
+        assert_eq!((rc[2], rc[4]), (CHANNEL_MID, 1811));
+        assert!(!apply_override(&mut rc, &[CHAN_RELEASE; 18]));
+    }
+}
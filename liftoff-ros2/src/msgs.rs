@@ -0,0 +1,218 @@
+//! The ROS 2 messages that are published, and the RC override that is
+//! received, in CDR.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::cdr::{Reader, Writer};
+
+/// `std_msgs/Header`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    pub stamp: SystemTime,
+    pub frame_id: String,
+}
+
+impl Header {
+    fn write(&self, w: &mut Writer) {
+        let since_epoch = self.stamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+        w.i32(since_epoch.as_secs() as i32);
+        w.u32(since_epoch.subsec_nanos());
+        w.string(&self.frame_id);
+    }
+}
+
+/// Covariance matrices all zero, as unknown.
+const UNKNOWN_3X3: [f64; 9] = [0.0; 9];
+const UNKNOWN_6X6: [f64; 36] = [0.0; 36];
+
+/// `nav_msgs/Odometry`: the pose in the header's frame, the twist in the
+/// child frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Odometry {
+    pub header: Header,
+    pub child_frame_id: String,
+    pub position: [f64; 3],
+    /// x, y, z, w.
+    pub orientation: [f64; 4],
+    pub linear: [f64; 3],
+    pub angular: [f64; 3],
+}
+
+impl Odometry {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        self.header.write(&mut w);
+        w.string(&self.child_frame_id);
+        w.f64_array(&self.position);
+        w.f64_array(&self.orientation);
+        w.f64_array(&UNKNOWN_6X6);
+        w.f64_array(&self.linear);
+        w.f64_array(&self.angular);
+        w.f64_array(&UNKNOWN_6X6);
+        w.finish()
+    }
+}
+
+/// `sensor_msgs/Imu`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Imu {
+    pub header: Header,
+    /// x, y, z, w.
+    pub orientation: [f64; 4],
+    /// rad/s.
+    pub angular_velocity: [f64; 3],
+    /// m/s², `None` if not known yet.
+    pub linear_acceleration: Option<[f64; 3]>,
+}
+
+impl Imu {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        self.header.write(&mut w);
+        w.f64_array(&self.orientation);
+        w.f64_array(&UNKNOWN_3X3);
+        w.f64_array(&self.angular_velocity);
+        w.f64_array(&UNKNOWN_3X3);
+        w.f64_array(&self.linear_acceleration.unwrap_or_default());
+        // A covariance starting with -1 marks the value as not given.
+        let mut covariance = UNKNOWN_3X3;
+        if self.linear_acceleration.is_none() {
+            covariance[0] = -1.0;
+        }
+        w.f64_array(&covariance);
+        w.finish()
+    }
+}
+
+const POWER_SUPPLY_STATUS_DISCHARGING: u8 = 2;
+const POWER_SUPPLY_HEALTH_UNKNOWN: u8 = 0;
+const POWER_SUPPLY_TECHNOLOGY_LIPO: u8 = 3;
+
+/// `sensor_msgs/BatteryState`, of what the sim tells: the voltage and the
+/// charge left. The rest is NaN, as unknown.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatteryState {
+    pub header: Header,
+    /// V.
+    pub voltage: f32,
+    /// From 0 to 1.
+    pub percentage: f32,
+}
+
+impl BatteryState {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        self.header.write(&mut w);
+        w.f32(self.voltage);
+        // Temperature, current, charge, capacity and design capacity.
+        for _ in 0..5 {
+            w.f32(f32::NAN);
+        }
+        w.f32(self.percentage);
+        w.u8(POWER_SUPPLY_STATUS_DISCHARGING);
+        w.u8(POWER_SUPPLY_HEALTH_UNKNOWN);
+        w.u8(POWER_SUPPLY_TECHNOLOGY_LIPO);
+        // Present.
+        w.bool(true);
+        // Cell voltages and temperatures.
+        w.f32_sequence(&[]);
+        w.f32_sequence(&[]);
+        // Location and serial number.
+        w.string("");
+        w.string("");
+        w.finish()
+    }
+}
+
+const STATUS_FIX: i8 = 0;
+const SERVICE_GPS: u16 = 1;
+const COVARIANCE_TYPE_UNKNOWN: u8 = 0;
+
+/// `sensor_msgs/NavSatFix`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NavSatFix {
+    pub header: Header,
+    /// Degrees.
+    pub latitude: f64,
+    pub longitude: f64,
+    /// m, above the WGS84 ellipsoid.
+    pub altitude: f64,
+}
+
+impl NavSatFix {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        self.header.write(&mut w);
+        w.i8(STATUS_FIX);
+        w.u16(SERVICE_GPS);
+        w.f64(self.latitude);
+        w.f64(self.longitude);
+        w.f64(self.altitude);
+        w.f64_array(&UNKNOWN_3X3);
+        w.u8(COVARIANCE_TYPE_UNKNOWN);
+        w.finish()
+    }
+}
+
+/// The channels of a `mavros_msgs/OverrideRCIn`, µs; 0 releases a channel,
+/// 65535 leaves it as it is.
+pub fn override_rc_in(data: &[u8]) -> Option<[u16; 18]> {
+    let mut r = Reader::new(data)?;
+    let mut channels = [0; 18];
+    for channel in &mut channels {
+        *channel = r.u16()?;
+    }
+    Some(channels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn messages() {
+        let header = Header {
+            stamp: UNIX_EPOCH + Duration::new(1_700_000_000, 5),
+            frame_id: "base_link".into(),
+        };
+        let imu = Imu {
+            header: header.clone(),
+            orientation: [0.0, 0.0, 0.0, 1.0],
+            angular_velocity: [0.1, 0.2, 0.3],
+            linear_acceleration: None,
+        }
+        .encode();
+        // Header: stamp, then "base_link" and its NUL, padded to 8 for the
+        // orientation.
+        assert_eq!(imu[4..8], 1_700_000_000i32.to_le_bytes());
+        assert_eq!(imu[8..12], 5u32.to_le_bytes());
+        assert_eq!(imu[12..16], 10u32.to_le_bytes());
+        assert_eq!(&imu[16..26], b"base_link\0");
+        // 2 bytes of padding, then 4 + 9 + 3 + 9 + 3 + 9 doubles, the
+        // acceleration covariance from the 28th.
+        assert_eq!(imu.len(), 28 + 37 * 8);
+        assert_eq!(imu[28..36], 0.0f64.to_le_bytes());
+        assert_eq!(imu[28 + 28 * 8..][..8], (-1.0f64).to_le_bytes());
+
+        let fix = NavSatFix {
+            header,
+            latitude: 1.0,
+            longitude: 2.0,
+            altitude: 3.0,
+        }
+        .encode();
+        // Status, then service at 2, latitude at 8, after the header.
+        assert_eq!(fix[26..32], [0, 0, 1, 0, 0, 0]);
+        assert_eq!(fix[36..44], 1.0f64.to_le_bytes());
+        assert_eq!(fix.len(), 36 + 12 * 8 + 1);
+
+        let mut rc = vec![0, 1, 0, 0];
+        for channel in 0..18u16 {
+            rc.extend_from_slice(&(1000 + channel).to_le_bytes());
+        }
+        let channels = override_rc_in(&rc).unwrap();
+        assert_eq!((channels[0], channels[17]), (1000, 1017));
+        assert_eq!(override_rc_in(&rc[..rc.len() - 1]), None);
+    }
+}
@@ -0,0 +1,385 @@
+//! The session file, in TOML: the services to run, and how.
+//!
+//! ```toml
+//! [zenoh]
+//! connect = "udp/127.0.0.1:7447"
+//!
+//! [router]
+//! listen = "udp/0.0.0.0:7447"
+//!
+//! [input]
+//!
+//! [forward]
+//! port = "/dev/ttyACM0"
+//!
+//! [joystick]
+//! mapping = "mapping.json"
+//!
+//! [gpsd]
+//! args = ["--gpsd-bind", "127.0.0.1:2947"]
+//!
+//! [[service]]
+//! name = "race"
+//! command = "liftoff-race"
+//! args = ["course.toml"]
+//! zenoh = true
+//! ```
+//!
+//! `[router]` is zenohd, `[input]` liftoff-input, `[forward]` crsf-forward
+//! (`port` is its `--port`), `[joystick]` crsf-joystick (`mapping` is its
+//! `--mapping`) and `[gpsd]` crsf-gpsd; each runs if its table is there.
+//! They start in that order, then the `[[service]]`s in theirs. `[zenoh]`
+//! (`connect`, `mode`, `prefix`) is passed to all of them but the router,
+//! and to the others that have `zenoh = true`.
+//!
+//! Any service can have `command` (the program, found next to
+//! liftoff-session or on the PATH), `args`, `env` (a table of variables),
+//! `restart` (`always`, `on-failure` or `never`; `on-failure` by default),
+//! `restart_delay` (s, 3 by default) and `delay` (s to wait before starting
+//! the next; 1 for the router, so that it listens before the others
+//! connect, and 0 for the others).
+
+use std::time::Duration;
+
+use toml_edit::{Document, Item, Table};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Restart {
+    Always,
+    OnFailure,
+    Never,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Service {
+    pub name: String,
+    pub command: String,
+    pub args: Vec<String>,
+    pub env: Vec<(String, String)>,
+    pub restart: Restart,
+    pub restart_delay: Duration,
+    /// Wait after starting it, before starting the next.
+    pub delay: Duration,
+}
+
+/// A built-in service: its table, its command, and the key that is an
+/// option of the command.
+struct BuiltIn {
+    table: &'static str,
+    command: &'static str,
+    option: Option<(&'static str, &'static str)>,
+}
+
+/// The built-in services, in the order they start.
+const BUILT_IN: &[BuiltIn] = &[
+    BuiltIn {
+        table: "router",
+        command: "zenohd",
+        option: Some(("listen", "--listen")),
+    },
+    BuiltIn {
+        table: "input",
+        command: "liftoff-input",
+        option: None,
+    },
+    BuiltIn {
+        table: "forward",
+        command: "crsf-forward",
+        option: Some(("port", "--port")),
+    },
+    BuiltIn {
+        table: "joystick",
+        command: "crsf-joystick",
+        option: Some(("mapping", "--mapping")),
+    },
+    BuiltIn {
+        table: "gpsd",
+        command: "crsf-gpsd",
+        option: None,
+    },
+];
+
+/// Keys every service may have.
+const COMMON_KEYS: &[&str] = &[
+    "command",
+    "args",
+    "env",
+    "restart",
+    "restart_delay",
+    "delay",
+];
+
+const DEFAULT_RESTART_DELAY: Duration = Duration::from_secs(3);
+const ROUTER_DELAY: Duration = Duration::from_secs(1);
+
+fn string(item: &Item, what: &str) -> Result<String, String> {
+    item.as_str()
+        .map(str::to_string)
+        .ok_or_else(|| format!("{} is not a string", what))
+}
+
+/// A string, or an array of them.
+fn strings(item: &Item, what: &str) -> Result<Vec<String>, String> {
+    if let Some(s) = item.as_str() {
+        return Ok(vec![s.to_string()]);
+    }
+    item.as_array()
+        .ok_or_else(|| format!("{} is not an array of strings", what))?
+        .iter()
+        .map(|value| {
+            value
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| format!("{} is not an array of strings", what))
+        })
+        .collect()
+}
+
+fn seconds(item: &Item, what: &str) -> Result<Duration, String> {
+    item.as_float()
+        .or_else(|| item.as_integer().map(|i| i as f64))
+        .and_then(|s| Duration::try_from_secs_f64(s).ok())
+        .ok_or_else(|| format!("{} is not a number of seconds", what))
+}
+
+/// The service of `table`, which is called `name` and runs `command`
+/// unless it says otherwise. Its keys must be among `keys` and
+/// [`COMMON_KEYS`].
+fn service(
+    table: &Table,
+    name: String,
+    command: Option<&str>,
+    keys: &[&str],
+) -> Result<Service, String> {
+    for (key, _) in table.iter() {
+        if !keys.contains(&key) && !COMMON_KEYS.contains(&key) {
+            return Err(format!("{}: unknown key {}", name, key));
+        }
+    }
+    let command = match (table.get("command"), command) {
+        (Some(item), _) => string(item, &format!("command of {}", name))?,
+        (None, Some(command)) => command.to_string(),
+        (None, None) => return Err(format!("{} has no command", name)),
+    };
+    let args = match table.get("args") {
+        Some(item) => strings(item, &format!("args of {}", name))?,
+        None => Vec::new(),
+    };
+    let env = match table.get("env") {
+        Some(item) => item
+            .as_table_like()
+            .ok_or_else(|| format!("env of {} is not a table", name))?
+            .iter()
+            .map(|(key, value)| Ok((key.to_string(), string(value, &format!("env {}", key))?)))
+            .collect::<Result<_, String>>()?,
+        None => Vec::new(),
+    };
+    let restart = match table.get("restart").map(|item| item.as_str()) {
+        None => Restart::OnFailure,
+        Some(Some("always")) => Restart::Always,
+        Some(Some("on-failure")) => Restart::OnFailure,
+        Some(Some("never")) => Restart::Never,
+        Some(_) => {
+            return Err(format!(
+                "restart of {} is not always, on-failure or never",
+                name
+            ));
+        }
+    };
+    let restart_delay = match table.get("restart_delay") {
+        Some(item) => seconds(item, &format!("restart_delay of {}", name))?,
+        None => DEFAULT_RESTART_DELAY,
+    };
+    let delay = match table.get("delay") {
+        Some(item) => seconds(item, &format!("delay of {}", name))?,
+        None => Duration::ZERO,
+    };
+    Ok(Service {
+        name,
+        command,
+        args,
+        env,
+        restart,
+        restart_delay,
+        delay,
+    })
+}
+
+/// The options that `[zenoh]` passes on.
+fn zenoh_args(document: &Table) -> Result<Vec<String>, String> {
+    let Some(item) = document.get("zenoh") else {
+        return Ok(Vec::new());
+    };
+    let table = item.as_table().ok_or("zenoh is not a table")?;
+    let mut args = Vec::new();
+    for (key, item) in table.iter() {
+        let option = match key {
+            "connect" => "--zenoh-connect",
+            "mode" => "--zenoh-mode",
+            "prefix" => "--zenoh-prefix",
+            _ => return Err(format!("zenoh: unknown key {}", key)),
+        };
+        args.push(option.to_string());
+        args.push(string(item, &format!("zenoh {}", key))?);
+    }
+    Ok(args)
+}
+
+/// Parse a session file into its services, in the order they start.
+pub fn parse(text: &str) -> Result<Vec<Service>, String> {
+    let document = Document::parse(text).map_err(|e| e.to_string())?;
+    for (key, _) in document.iter() {
+        if key != "zenoh" && key != "service" && !BUILT_IN.iter().any(|b| b.table == key) {
+            return Err(format!("unknown table [{}]", key));
+        }
+    }
+    let zenoh = zenoh_args(document.as_table())?;
+
+    let mut services = Vec::new();
+    for &BuiltIn {
+        table: name,
+        command,
+        option,
+    } in BUILT_IN
+    {
+        let Some(item) = document.get(name) else {
+            continue;
+        };
+        let table = item
+            .as_table()
+            .ok_or_else(|| format!("{} is not a table, [{}]", name, name))?;
+        let keys: Vec<&str> = option.iter().map(|(key, _)| *key).collect();
+        let mut service = service(table, name.to_string(), Some(command), &keys)?;
+        let mut args = Vec::new();
+        if let Some((key, flag)) = option
+            && let Some(item) = table.get(key)
+        {
+            for value in strings(item, &format!("{} of {}", key, name))? {
+                args.push(flag.to_string());
+                args.push(value);
+            }
+        }
+        if name == "router" {
+            if table.get("delay").is_none() {
+                service.delay = ROUTER_DELAY;
+            }
+        } else {
+            args.extend(zenoh.iter().cloned());
+        }
+        args.append(&mut service.args);
+        service.args = args;
+        services.push(service);
+    }
+
+    if let Some(item) = document.get("service") {
+        let tables = item
+            .as_array_of_tables()
+            .ok_or("service is not an array of tables, [[service]]")?;
+        for (i, table) in tables.iter().enumerate() {
+            let name = match table.get("name") {
+                Some(item) => string(item, &format!("name of service {}", i + 1))?,
+                None => format!("service {}", i + 1),
+            };
+            let mut service = service(table, name, None, &["name", "zenoh"])?;
+            if let Some(item) = table.get("zenoh")
+                && item
+                    .as_bool()
+                    .ok_or_else(|| format!("zenoh of {} is not true or false", service.name))?
+            {
+                service.args.extend(zenoh.iter().cloned());
+            }
+            services.push(service);
+        }
+    }
+
+    let mut names: Vec<&str> = services.iter().map(|s| s.name.as_str()).collect();
+    names.sort_unstable();
+    if let Some(name) = names.windows(2).find(|w| w[0] == w[1]) {
+        return Err(format!("two services are called {}", name[0]));
+    }
+    Ok(services)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session() {
+        let services = parse(
+            "[zenoh]\n\
+             connect = \"udp/127.0.0.1:7447\"\n\
+             [gpsd]\n\
+             restart = \"always\"\n\
+             [forward]\n\
+             port = \"/dev/ttyACM0\"\n\
+             args = [\"--baud\", \"420000\"]\n\
+             env = { RUST_LOG = \"debug\" }\n\
+             [router]\n\
+             listen = [\"udp/0.0.0.0:7447\", \"tcp/0.0.0.0:7447\"]\n\
+             [[service]]\n\
+             name = \"race\"\n\
+             command = \"liftoff-race\"\n\
+             args = [\"course.toml\"]\n\
+             zenoh = true\n\
+             restart_delay = 0.5\n\
+             [[service]]\n\
+             command = \"./my-tool\"\n",
+        )
+        .unwrap();
+        let names: Vec<&str> = services.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["router", "forward", "gpsd", "race", "service 2"]);
+
+        let router = &services[0];
+        assert_eq!(router.command, "zenohd");
+        assert_eq!(
+            router.args,
+            [
+                "--listen",
+                "udp/0.0.0.0:7447",
+                "--listen",
+                "tcp/0.0.0.0:7447"
+            ]
+        );
+        assert_eq!(router.delay, ROUTER_DELAY);
+
+        let forward = &services[1];
+        assert_eq!(
+            forward.args,
+            [
+                "--port",
+                "/dev/ttyACM0",
+                "--zenoh-connect",
+                "udp/127.0.0.1:7447",
+                "--baud",
+                "420000"
+            ]
+        );
+        assert_eq!(forward.env, [("RUST_LOG".into(), "debug".into())]);
+        assert_eq!(forward.restart, Restart::OnFailure);
+        assert_eq!(services[2].restart, Restart::Always);
+
+        let race = &services[3];
+        assert_eq!(
+            race.args,
+            ["course.toml", "--zenoh-connect", "udp/127.0.0.1:7447"]
+        );
+        assert_eq!(race.restart_delay, Duration::from_millis(500));
+        assert_eq!(services[4].args, Vec::<String>::new());
+
+        assert!(
+            parse("[router]\nport = \"x\"\n")
+                .unwrap_err()
+                .contains("unknown key port")
+        );
+        assert!(parse("[routr]\n").unwrap_err().contains("unknown table"));
+        assert!(
+            parse("[[service]]\nname = \"x\"\n")
+                .unwrap_err()
+                .contains("no command")
+        );
+        assert!(parse("[input]\nrestart = \"sometimes\"\n").is_err());
+        let err = parse("[input]\n[[service]]\nname = \"input\"\ncommand = \"x\"\n").unwrap_err();
+        assert!(err.contains("two services"), "{}", err);
+    }
+}
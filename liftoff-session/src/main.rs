@@ -0,0 +1,156 @@
+//! Runs a whole setup from one session file: the Zenoh router and the
+//! services, started in order, restarted when they fail, with their output
+//! in one log. Stopping the session stops them, the last started first.
+
+use clap::Parser;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::{mpsc, watch};
+
+mod config;
+mod supervisor;
+
+use supervisor::Log;
+
+/// Name of the session's own lines in the log.
+const SESSION: &str = "session";
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Session file (TOML): the services to run.
+    session: PathBuf,
+
+    /// Also append the log to this file.
+    #[arg(long)]
+    log: Option<PathBuf>,
+
+    /// Only show the commands that would run.
+    #[arg(long)]
+    dry_run: bool,
+}
+
+/// Wait for Ctrl-C, or SIGTERM from a service manager.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = term.recv() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let args = Args::parse();
+
+    let text = std::fs::read_to_string(&args.session)
+        .map_err(|e| format!("{}: {}", args.session.display(), e))?;
+    let services =
+        config::parse(&text).map_err(|e| format!("{}: {}", args.session.display(), e))?;
+    if services.is_empty() {
+        return Err(format!("{}: no services", args.session.display()).into());
+    }
+    let exe_dir = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf));
+
+    if args.dry_run {
+        for service in &services {
+            let program = supervisor::resolve(&service.command, exe_dir.as_deref());
+            let env: Vec<String> = service
+                .env
+                .iter()
+                .map(|(k, v)| format!("{}={} ", k, v))
+                .collect();
+            println!(
+                "{}: {}{} {}",
+                service.name,
+                env.concat(),
+                program.display(),
+                service.args.join(" ")
+            );
+        }
+        return Ok(());
+    }
+
+    let file = args
+        .log
+        .as_ref()
+        .map(|path| {
+            File::options()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| format!("{}: {}", path.display(), e))
+        })
+        .transpose()?;
+    let width = services
+        .iter()
+        .map(|s| s.name.len())
+        .chain([SESSION.len()])
+        .max()
+        .unwrap_or_default();
+    let log = Arc::new(Log::new(width, file));
+    let names: Vec<&str> = services.iter().map(|s| s.name.as_str()).collect();
+    log.line(SESSION, &format!("starting {}", names.join(", ")));
+
+    // Each service is stopped through its own sender, so that they stop in
+    // turn. `done` closes when all of them are done.
+    let (done_tx, mut done_rx) = mpsc::channel::<()>(1);
+    let mut running = Vec::new();
+    let mut stopping = false;
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+    for service in services {
+        let program = supervisor::resolve(&service.command, exe_dir.as_deref());
+        let delay = service.delay;
+        let (stop_tx, stop_rx) = watch::channel(false);
+        let (log, done) = (log.clone(), done_tx.clone());
+        let handle = tokio::spawn(async move {
+            supervisor::supervise(service, program, log, stop_rx).await;
+            drop(done);
+        });
+        running.push((stop_tx, handle));
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = &mut shutdown => {
+                stopping = true;
+                break;
+            }
+        }
+    }
+    drop(done_tx);
+
+    if !stopping {
+        tokio::select! {
+            _ = &mut shutdown => {}
+            _ = done_rx.recv() => {
+                log.line(SESSION, "all services are done");
+                return Ok(());
+            }
+        }
+    }
+
+    log.line(SESSION, "shutdown signal received, stopping the services");
+    for (stop, handle) in running.into_iter().rev() {
+        let _ = stop.send(true);
+        let _ = handle.await;
+    }
+    log.line(SESSION, "stopped");
+    Ok(())
+}
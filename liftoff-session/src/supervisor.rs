@@ -0,0 +1,206 @@
+//! Running a service: starting its process, passing on its output, and
+//! starting it again when it ends, as its restart policy says.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::config::{Restart, Service};
+
+/// Time a service has to exit after SIGTERM, before it is killed.
+const STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Time to wait for the rest of the output of a service that has exited.
+const OUTPUT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The output of all services, and of the session, a line at a time, each
+/// after the name of whose it is. Goes to stdout, and to a file if given.
+pub struct Log {
+    width: usize,
+    file: Option<Mutex<File>>,
+}
+
+impl Log {
+    pub fn new(width: usize, file: Option<File>) -> Self {
+        Self {
+            width,
+            file: file.map(Mutex::new),
+        }
+    }
+
+    pub fn line(&self, name: &str, line: &str) {
+        let line = format!("{:>width$} | {}", name, line, width = self.width);
+        println!("{}", line);
+        if let Some(file) = &self.file {
+            let mut file = file.lock().unwrap();
+            // Nowhere to report it to but stdout, which has the line already.
+            let _ = writeln!(file, "{}", line);
+        }
+    }
+}
+
+/// The program to run for `command`: as it is if it is a path, next to
+/// liftoff-session if it is there, otherwise from the PATH.
+pub fn resolve(command: &str, exe_dir: Option<&Path>) -> PathBuf {
+    if command.contains(std::path::MAIN_SEPARATOR) {
+        return command.into();
+    }
+    match exe_dir.map(|dir| dir.join(command)) {
+        Some(path) if path.is_file() => path,
+        _ => command.into(),
+    }
+}
+
+fn forward(
+    name: String,
+    output: impl AsyncRead + Unpin + Send + 'static,
+    log: Arc<Log>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(output).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            log.line(&name, &line);
+        }
+    })
+}
+
+/// Start `service`, with the tasks that pass on its output.
+fn spawn(
+    service: &Service,
+    program: &Path,
+    log: &Arc<Log>,
+) -> std::io::Result<(Child, Vec<JoinHandle<()>>)> {
+    let mut child = Command::new(program)
+        .args(&service.args)
+        .envs(service.env.iter().cloned())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let mut output = Vec::new();
+    if let Some(stdout) = child.stdout.take() {
+        output.push(forward(service.name.clone(), stdout, log.clone()));
+    }
+    if let Some(stderr) = child.stderr.take() {
+        output.push(forward(service.name.clone(), stderr, log.clone()));
+    }
+    Ok((child, output))
+}
+
+/// Ask `child` to exit, and kill it if it doesn't in time.
+async fn stop(child: &mut Child) -> std::io::Result<ExitStatus> {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        // SAFETY: plain kill(2); the child hasn't been waited for yet, so
+        // `pid` is still its own.
+        unsafe {
+            libc::kill(pid as libc::pid_t, libc::SIGTERM);
+        }
+    }
+    #[cfg(not(unix))]
+    child.start_kill()?;
+    match tokio::time::timeout(STOP_TIMEOUT, child.wait()).await {
+        Ok(status) => status,
+        Err(_) => {
+            child.kill().await?;
+            child.wait().await
+        }
+    }
+}
+
+/// Wait until `shutdown` turns true.
+async fn stopped(shutdown: &mut watch::Receiver<bool>) {
+    // An error means the session is gone, which is as good as true.
+    let _ = shutdown.wait_for(|&done| done).await;
+}
+
+/// Run `service` until its restart policy says it is done, or until
+/// `shutdown` turns true, when it is stopped.
+pub async fn supervise(
+    service: Service,
+    program: PathBuf,
+    log: Arc<Log>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let name = service.name.as_str();
+    let command_line = std::iter::once(program.display().to_string())
+        .chain(service.args.iter().cloned())
+        .collect::<Vec<_>>()
+        .join(" ");
+    loop {
+        log.line(name, &format!("starting {}", command_line));
+        let succeeded = match spawn(&service, &program, &log) {
+            Ok((mut child, output)) => {
+                let (status, stopping) = tokio::select! {
+                    status = child.wait() => (status, false),
+                    _ = stopped(&mut shutdown) => (stop(&mut child).await, true),
+                };
+                // The rest of its output first, unless something it left
+                // running holds on to it.
+                let deadline = tokio::time::Instant::now() + OUTPUT_TIMEOUT;
+                for task in output {
+                    let _ = tokio::time::timeout_at(deadline, task).await;
+                }
+                let (done, fail) = if stopping {
+                    ("stopped", "stop")
+                } else {
+                    ("exited", "wait for")
+                };
+                match &status {
+                    Ok(status) => log.line(name, &format!("{}, {}", done, status)),
+                    Err(e) => log.line(name, &format!("can't {} it: {}", fail, e)),
+                }
+                if stopping {
+                    return;
+                }
+                status.is_ok_and(|status| status.success())
+            }
+            Err(e) => {
+                log.line(name, &format!("can't start {}: {}", program.display(), e));
+                false
+            }
+        };
+        let again = match service.restart {
+            Restart::Always => true,
+            Restart::OnFailure => !succeeded,
+            Restart::Never => false,
+        };
+        if !again {
+            return;
+        }
+        log.line(name, &format!("restarting in {:?}", service.restart_delay));
+        tokio::select! {
+            _ = tokio::time::sleep(service.restart_delay) => {}
+            _ = stopped(&mut shutdown) => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn programs() {
+        let dir = std::env::temp_dir().join(format!("liftoff-session-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("crsf-gpsd"), "").unwrap();
+
+        assert_eq!(resolve("crsf-gpsd", Some(&dir)), dir.join("crsf-gpsd"));
+        assert_eq!(resolve("zenohd", Some(&dir)), PathBuf::from("zenohd"));
+        assert_eq!(resolve("zenohd", None), PathBuf::from("zenohd"));
+        let path = format!(".{}my-tool", std::path::MAIN_SEPARATOR);
+        assert_eq!(resolve(&path, Some(&dir)), PathBuf::from(&path));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
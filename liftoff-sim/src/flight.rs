@@ -0,0 +1,336 @@
+//! The synthetic flight.
+//!
+//! The quad flies round a path at a constant speed, bobbing up and down by
+//! the climb, facing where it goes and banked into the turns with the
+//! thrust that takes. The battery voltage follows a LiPo discharge curve
+//! and sags with the current, which grows with the thrust; the motor RPM
+//! goes with the square root of the thrust.
+//!
+//! Coordinates are Liftoff's: x east, y up and z north, m.
+
+use clap::ValueEnum;
+use telemetry_lib::telemetry::TelemetryPacket;
+
+const G: f64 = 9.81;
+/// Rest voltage of a LiPo cell by the charge left, from empty to full in
+/// steps of 10%, V.
+const CELL_CURVE: [f64; 11] = [
+    3.27, 3.61, 3.69, 3.71, 3.73, 3.77, 3.79, 3.82, 3.87, 3.96, 4.20,
+];
+/// Internal resistance of a cell, Ω.
+const CELL_RESISTANCE: f64 = 0.006;
+/// Throttle to hover, from 0 to 1.
+const HOVER_THROTTLE: f64 = 0.3;
+/// Rotation rate at full stick deflection, deg/s.
+const STICK_RATE: f64 = 670.0;
+/// Time step of the derivatives, s.
+const STEP: f64 = 0.01;
+/// Segments to measure the length of the path with.
+const SEGMENTS: usize = 1000;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shape {
+    /// A circle, clockwise.
+    Circle,
+    /// A figure eight, lying east to west.
+    FigureEight,
+}
+
+impl Shape {
+    /// The point at `angle` of a path of `radius`, east and north.
+    fn point(self, radius: f64, angle: f64) -> (f64, f64) {
+        match self {
+            Shape::Circle => (radius * angle.sin(), radius * angle.cos()),
+            Shape::FigureEight => (radius * angle.sin(), radius * angle.sin() * angle.cos()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub shape: Shape,
+    /// Size of the path, m.
+    pub radius: f64,
+    /// Speed, m/s.
+    pub speed: f64,
+    /// Height of the path, m.
+    pub altitude: f64,
+    /// How far it goes up and down along the path, m.
+    pub climb: f64,
+    pub cells: u8,
+    /// Battery capacity, mAh.
+    pub capacity: f64,
+    /// Current to hover, A.
+    pub hover_current: f64,
+    /// Motor RPM to hover.
+    pub hover_rpm: f64,
+}
+
+type Matrix = [[f64; 3]; 3];
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn scale(a: [f64; 3], k: f64) -> [f64; 3] {
+    a.map(|v| v * k)
+}
+
+fn normalize(a: [f64; 3]) -> [f64; 3] {
+    scale(a, 1.0 / dot(a, a).sqrt())
+}
+
+/// Quaternion (x, y, z, w) of the rotation `m`.
+fn quaternion(m: &Matrix) -> [f64; 4] {
+    let trace = m[0][0] + m[1][1] + m[2][2];
+    if trace > 0.0 {
+        let s = 2.0 * (trace + 1.0).sqrt();
+        [
+            (m[2][1] - m[1][2]) / s,
+            (m[0][2] - m[2][0]) / s,
+            (m[1][0] - m[0][1]) / s,
+            s / 4.0,
+        ]
+    } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+        let s = 2.0 * (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt();
+        [
+            s / 4.0,
+            (m[0][1] + m[1][0]) / s,
+            (m[0][2] + m[2][0]) / s,
+            (m[2][1] - m[1][2]) / s,
+        ]
+    } else if m[1][1] > m[2][2] {
+        let s = 2.0 * (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt();
+        [
+            (m[0][1] + m[1][0]) / s,
+            s / 4.0,
+            (m[1][2] + m[2][1]) / s,
+            (m[0][2] - m[2][0]) / s,
+        ]
+    } else {
+        let s = 2.0 * (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt();
+        [
+            (m[0][2] + m[2][0]) / s,
+            (m[1][2] + m[2][1]) / s,
+            s / 4.0,
+            (m[1][0] - m[0][1]) / s,
+        ]
+    }
+}
+
+/// Rest voltage of a cell with `charge` left, from 0 to 1.
+fn cell_voltage(charge: f64) -> f64 {
+    let x = charge.clamp(0.0, 1.0) * (CELL_CURVE.len() - 1) as f64;
+    let i = (x as usize).min(CELL_CURVE.len() - 2);
+    let f = x - i as f64;
+    CELL_CURVE[i] * (1.0 - f) + CELL_CURVE[i + 1] * f
+}
+
+pub struct Flight {
+    settings: Settings,
+    /// Of the path, rad/s.
+    angular_rate: f64,
+    /// Charge drawn from the battery, mAh.
+    drawn: f64,
+    /// Time of the last packet, s.
+    last: Option<f64>,
+}
+
+impl Flight {
+    pub fn new(settings: Settings) -> Self {
+        let length: f64 = (0..SEGMENTS)
+            .map(|i| {
+                let angle = |i: usize| i as f64 * std::f64::consts::TAU / SEGMENTS as f64;
+                let (x0, z0) = settings.shape.point(settings.radius, angle(i));
+                let (x1, z1) = settings.shape.point(settings.radius, angle(i + 1));
+                (x1 - x0).hypot(z1 - z0)
+            })
+            .sum();
+        let angular_rate = if length > 0.0 {
+            settings.speed * std::f64::consts::TAU / length
+        } else {
+            0.0
+        };
+        Self {
+            settings,
+            angular_rate,
+            drawn: 0.0,
+            last: None,
+        }
+    }
+
+    fn position(&self, time: f64) -> [f64; 3] {
+        let angle = self.angular_rate * time;
+        let (x, z) = self.settings.shape.point(self.settings.radius, angle);
+        let y = self.settings.altitude + self.settings.climb * angle.sin();
+        [x, y, z]
+    }
+
+    fn velocity(&self, time: f64) -> [f64; 3] {
+        let delta = sub(self.position(time + STEP), self.position(time - STEP));
+        scale(delta, 0.5 / STEP)
+    }
+
+    /// Thrust per mass, m/s².
+    fn thrust(&self, time: f64) -> [f64; 3] {
+        let before = sub(self.position(time), self.position(time - STEP));
+        let after = sub(self.position(time + STEP), self.position(time));
+        let mut thrust = scale(sub(after, before), 1.0 / (STEP * STEP));
+        thrust[1] += G;
+        thrust
+    }
+
+    /// The rotation from the body (right, up, forward) to the world.
+    fn rotation(&self, time: f64) -> Matrix {
+        let up = normalize(self.thrust(time));
+        let velocity = self.velocity(time);
+        let heading = velocity[0].atan2(velocity[2]);
+        let level = [heading.sin(), 0.0, heading.cos()];
+        let forward = normalize(sub(level, scale(up, dot(level, up))));
+        let right = cross(up, forward);
+        let mut m = [[0.0; 3]; 3];
+        for (column, axis) in [right, up, forward].into_iter().enumerate() {
+            for row in 0..3 {
+                m[row][column] = axis[row];
+            }
+        }
+        m
+    }
+
+    /// Rotation rates about the right, up and forward axes, deg/s.
+    fn rates(&self, time: f64) -> [f64; 3] {
+        let (a, b) = (self.rotation(time - STEP), self.rotation(time + STEP));
+        // a^T b, the rotation in the body frame.
+        let r = |i: usize, j: usize| (0..3).map(|k| a[k][i] * b[k][j]).sum::<f64>();
+        let k = 0.5 / (2.0 * STEP);
+        [
+            (r(2, 1) - r(1, 2)) * k,
+            (r(0, 2) - r(2, 0)) * k,
+            (r(1, 0) - r(0, 1)) * k,
+        ]
+        .map(f64::to_degrees)
+    }
+
+    /// The telemetry at `time` into the flight, s.
+    pub fn packet(&mut self, time: f64) -> TelemetryPacket {
+        let settings = &self.settings;
+        let thrust = dot(self.thrust(time), self.thrust(time)).sqrt() / G;
+        let current = settings.hover_current * thrust.powf(1.5);
+        let dt = self.last.map_or(0.0, |last| (time - last).max(0.0));
+        self.last = Some(time);
+        // 1 mAh is 3.6 As.
+        self.drawn += current * dt / 3.6;
+        let charge = (1.0 - self.drawn / settings.capacity).clamp(0.0, 1.0);
+        let voltage =
+            f64::from(settings.cells) * (cell_voltage(charge) - current * CELL_RESISTANCE);
+
+        let [right, up, forward] = self.rates(time);
+        let stick = |rate: f64| (rate / STICK_RATE).clamp(-1.0, 1.0) as f32;
+        let throttle = (HOVER_THROTTLE * thrust).clamp(0.0, 1.0);
+        let rpm = (settings.hover_rpm * thrust.sqrt()) as f32;
+
+        TelemetryPacket {
+            timestamp: Some(time as f32),
+            position: Some(self.position(time).map(|v| v as f32)),
+            attitude: Some(quaternion(&self.rotation(time)).map(|v| v as f32)),
+            velocity: Some(self.velocity(time).map(|v| v as f32)),
+            gyro: Some([right, forward, up].map(|v| v as f32)),
+            input: Some([
+                (2.0 * throttle - 1.0) as f32,
+                stick(up),
+                stick(right),
+                stick(forward),
+            ]),
+            battery: Some([charge as f32, voltage.max(0.0) as f32]),
+            motor_rpm: Some(vec![rpm; 4]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use telemetry_lib::geo;
+
+    #[test]
+    fn circle() {
+        let settings = Settings {
+            shape: Shape::Circle,
+            radius: 50.0,
+            speed: 20.0,
+            altitude: 10.0,
+            climb: 0.0,
+            cells: 4,
+            capacity: 1500.0,
+            hover_current: 10.0,
+            hover_rpm: 10000.0,
+        };
+        let mut flight = Flight::new(settings.clone());
+        let packet = flight.packet(0.0);
+        assert_eq!(packet.position, Some([0.0, 10.0, 50.0]));
+        let [vx, vy, vz] = packet.velocity.unwrap();
+        assert!((vx - 20.0).abs() < 0.01 && vy.abs() < 0.01 && vz.abs() < 0.01);
+
+        // Heading east, banked right into the turn.
+        let [qx, qy, qz, qw] = packet.attitude.unwrap().map(f64::from);
+        let heading = geo::quat2heading(qx, qy, qz, qw).to_degrees();
+        assert!((heading - 90.0).abs() < 0.1, "{}", heading);
+        let (pitch, roll, _) = geo::quat2eulers(qx, qy, qz, qw);
+        let bank = (20.0f64.powi(2) / 50.0 / G).atan();
+        assert!((roll - bank).abs() < 0.01, "{} {}", roll, bank);
+        assert!(pitch.abs() < 0.01);
+
+        // A turn a lap.
+        let rate = (20.0f64 / 50.0).to_degrees();
+        let gyro = packet.gyro.unwrap().map(f64::from);
+        assert!((dot(gyro, gyro).sqrt() - rate).abs() < 0.1);
+        let thrust = 1.0 / bank.cos();
+        let rpm = packet.motor_rpm.unwrap()[0] as f64;
+        assert!((rpm - 10000.0 * thrust.sqrt()).abs() < 1.0);
+
+        // The battery, full at the start, and drawn for a minute.
+        let [charge, voltage] = packet.battery.unwrap();
+        let current = 10.0 * thrust.powf(1.5);
+        assert_eq!(charge, 1.0);
+        assert!((voltage as f64 - 4.0 * (4.2 - current * CELL_RESISTANCE)).abs() < 0.01);
+        for i in 1..=60 {
+            flight.packet(i as f64);
+        }
+        let [charge, voltage_later] = flight.packet(60.0).battery.unwrap();
+        let drawn = current * 60.0 / 3.6;
+        assert!((charge as f64 - (1.0 - drawn / 1500.0)).abs() < 1e-3);
+        assert!(voltage_later < voltage);
+
+        let figure_eight = Flight::new(Settings {
+            shape: Shape::FigureEight,
+            ..settings
+        });
+        let [vx, _, vz] = figure_eight.velocity(0.0);
+        assert!(vx > 0.0 && vz > 0.0);
+        // At the speed on average.
+        let lap = std::f64::consts::TAU / figure_eight.angular_rate;
+        let distance: f64 = (0..1000)
+            .map(|i| {
+                let time = |i: usize| i as f64 * lap / 1000.0;
+                let delta = sub(
+                    figure_eight.position(time(i + 1)),
+                    figure_eight.position(time(i)),
+                );
+                dot(delta, delta).sqrt()
+            })
+            .sum();
+        assert!((distance / lap - 20.0).abs() < 0.01);
+    }
+}
@@ -0,0 +1,127 @@
+use clap::Parser;
+use log::{debug, info};
+use std::net::SocketAddr;
+use telemetry_lib::telemetry;
+use tokio::net::UdpSocket;
+use tokio::time::{Duration, Instant, MissedTickBehavior, interval};
+
+mod flight;
+
+use flight::{Flight, Settings, Shape};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Send the telemetry to this address, where liftoff-input listens for
+    /// the sim.
+    #[arg(long, default_value = "127.0.0.1:9001")]
+    target: SocketAddr,
+
+    /// Telemetry packets per second.
+    #[arg(long, default_value_t = 100.0, value_parser = parse_rate)]
+    rate: f64,
+
+    /// Path of the flight.
+    #[arg(long, value_enum, default_value_t = Shape::Circle)]
+    shape: Shape,
+
+    /// Radius of the circle, or half the length of the figure eight, m.
+    #[arg(long, default_value_t = 50.0)]
+    radius: f64,
+
+    /// Speed, m/s.
+    #[arg(long, default_value_t = 15.0)]
+    speed: f64,
+
+    /// Height of the path, m.
+    #[arg(long, default_value_t = 20.0)]
+    altitude: f64,
+
+    /// How far the quad goes up and down along the path, m.
+    #[arg(long, default_value_t = 2.0)]
+    climb: f64,
+
+    /// LiPo cells in the battery.
+    #[arg(long, default_value_t = 4)]
+    cells: u8,
+
+    /// Battery capacity, mAh.
+    #[arg(long, default_value_t = 1500.0)]
+    capacity: f64,
+
+    /// Current to hover, A. It grows with the thrust in the turns.
+    #[arg(long, default_value_t = 15.0)]
+    hover_current: f64,
+
+    /// Motor RPM to hover.
+    #[arg(long, default_value_t = 12000.0)]
+    hover_rpm: f64,
+}
+
+/// Parse a packet rate, for use as a clap value parser.
+fn parse_rate(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(hz) if hz > 0.0 && hz <= 1000.0 => Ok(hz),
+        _ => Err(format!(
+            "invalid rate `{}`, must be above 0 and up to 1000 Hz",
+            s
+        )),
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    env_logger::init();
+    let args = Args::parse();
+
+    info!("Starting liftoff-sim");
+
+    let mut flight = Flight::new(Settings {
+        shape: args.shape,
+        radius: args.radius,
+        speed: args.speed,
+        altitude: args.altitude,
+        climb: args.climb,
+        cells: args.cells,
+        capacity: args.capacity,
+        hover_current: args.hover_current,
+        hover_rpm: args.hover_rpm,
+    });
+    let format = telemetry::default_stream_format();
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    info!(
+        "Sending telemetry to udp://{} at {} Hz",
+        args.target, args.rate
+    );
+
+    let start = Instant::now();
+    let mut ticks = interval(Duration::from_secs_f64(1.0 / args.rate));
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => {
+                let packet = flight.packet(start.elapsed().as_secs_f64());
+                let data = telemetry::encode_packet(&packet, &format)?;
+                // Refused while nothing listens.
+                if let Err(e) = socket.send_to(&data, args.target).await {
+                    debug!("Send to {} failed: {}", args.target, e);
+                }
+            }
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+    info!("Shutdown signal received, exiting.");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate() {
+        assert_eq!(parse_rate("100"), Ok(100.0));
+        assert!(parse_rate("0").is_err());
+        assert!(parse_rate("often").is_err());
+    }
+}
@@ -0,0 +1,71 @@
+//! The trainer frames EdgeTX radios exchange over their Bluetooth module,
+//! as the student radio sends them.
+//!
+//! A frame is the trainer frame type and eight channels of 12 bits, µs,
+//! two channels to three bytes, followed by the XOR of those bytes. It
+//! starts and ends with `0x7E`; a `0x7E` or `0x7D` inside it is sent as
+//! `0x7D` and the byte XOR `0x20`.
+
+/// Channels in a frame.
+pub const CHANNELS: usize = 8;
+
+const START_STOP: u8 = 0x7e;
+const BYTE_STUFF: u8 = 0x7d;
+const STUFF_MASK: u8 = 0x20;
+const TRAINER_FRAME: u8 = 0x80;
+
+fn push(frame: &mut Vec<u8>, byte: u8) {
+    if byte == START_STOP || byte == BYTE_STUFF {
+        frame.extend([BYTE_STUFF, byte ^ STUFF_MASK]);
+    } else {
+        frame.push(byte);
+    }
+}
+
+/// Frame of `channels`, µs.
+pub fn frame(channels: &[u16; CHANNELS]) -> Vec<u8> {
+    let mut bytes = vec![TRAINER_FRAME];
+    for pair in channels.chunks(2) {
+        let (a, b) = (pair[0].min(0xfff), pair[1].min(0xfff));
+        bytes.extend([
+            a as u8,
+            ((a >> 4) & 0xf0) as u8 | ((b >> 4) & 0x0f) as u8,
+            ((b << 4) & 0xf0) as u8 | (b >> 8) as u8,
+        ]);
+    }
+    let crc = bytes.iter().fold(0, |crc, b| crc ^ b);
+    bytes.push(crc);
+
+    let mut frame = vec![START_STOP];
+    for byte in bytes {
+        push(&mut frame, byte);
+    }
+    frame.push(START_STOP);
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames() {
+        let mut expected = vec![0x7e, 0x80];
+        for _ in 0..4 {
+            expected.extend([0xdc, 0x5d, 0xc5]);
+        }
+        expected.extend([0x80, 0x7e]);
+        assert_eq!(frame(&[1500; CHANNELS]), expected);
+
+        // 1406 is 0x57e.
+        let mut channels = [1000; CHANNELS];
+        channels[0] = 1406;
+        assert_eq!(
+            frame(&channels),
+            [
+                0x7e, 0x80, 0x7d, 0x5e, 0x5e, 0x83, 0xe8, 0x3e, 0x83, 0xe8, 0x3e, 0x83, 0xe8, 0x3e,
+                0x83, 0x76, 0x7e
+            ]
+        );
+    }
+}
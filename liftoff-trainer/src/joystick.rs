@@ -0,0 +1,157 @@
+//! Channels from a local joystick or gamepad, read through evdev.
+//!
+//! The axes come first, in the order of their codes (X, Y, Z, RX, ...),
+//! scaled from their range to 1000-2000 µs, then the buttons, at 1000 µs
+//! released and 2000 µs pressed, up to 16 channels.
+
+use std::path::PathBuf;
+
+use evdev::{AbsoluteAxisCode, Device, EventSummary, KeyCode};
+use log::{info, warn};
+
+use crate::{Channels, NUM_CHANNELS};
+
+#[derive(Debug, Clone, Copy)]
+struct Axis {
+    code: AbsoluteAxisCode,
+    min: i32,
+    max: i32,
+}
+
+/// Channel value of an axis at `value`, µs.
+fn axis_us(value: i32, min: i32, max: i32) -> u16 {
+    if max <= min {
+        return 1500;
+    }
+    let value = value.clamp(min, max);
+    (1000 + i64::from(value - min) * 1000 / i64::from(max - min)) as u16
+}
+
+fn button_us(pressed: bool) -> u16 {
+    if pressed { 2000 } else { 1000 }
+}
+
+/// The device at `name`, if it is a path, or else the one of that name.
+fn find(name: &str) -> std::io::Result<(PathBuf, Device)> {
+    if name.starts_with('/') {
+        return Ok((PathBuf::from(name), Device::open(name)?));
+    }
+    evdev::enumerate()
+        .find(|(_, device)| device.name() == Some(name))
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("no input device named {:?}", name),
+            )
+        })
+}
+
+/// Read the channels of the joystick `name` into `channels`, on a thread
+/// of its own, as reading the device blocks. When reading fails the
+/// channels go away.
+pub fn spawn(
+    name: &str,
+    channels: Channels,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (path, mut device) = find(name)?;
+    let mut absinfo: Vec<_> = device.get_absinfo()?.collect();
+    absinfo.sort_by_key(|(code, _)| code.0);
+    let axes: Vec<Axis> = absinfo
+        .iter()
+        .map(|(code, info)| Axis {
+            code: *code,
+            min: info.minimum(),
+            max: info.maximum(),
+        })
+        .collect();
+    let buttons: Vec<KeyCode> = device
+        .supported_keys()
+        .map(|keys| keys.iter().collect())
+        .unwrap_or_default();
+    if axes.is_empty() && buttons.is_empty() {
+        return Err(format!("{} has no axes or buttons", path.display()).into());
+    }
+    if axes.len() + buttons.len() > NUM_CHANNELS {
+        warn!(
+            "{}: {} axes and {} buttons, only the first {} are used",
+            path.display(),
+            axes.len(),
+            buttons.len(),
+            NUM_CHANNELS
+        );
+    }
+    info!(
+        "Reading {} ({}): {} axes, {} buttons",
+        device.name().unwrap_or("?"),
+        path.display(),
+        axes.len(),
+        buttons.len()
+    );
+
+    // Channels of the inputs, in order; the others stay centered.
+    let mut values = [1500u16; NUM_CHANNELS];
+    for (ch, (_, info)) in absinfo.iter().enumerate().take(NUM_CHANNELS) {
+        values[ch] = axis_us(info.value(), info.minimum(), info.maximum());
+    }
+    let keys = device.get_key_state()?;
+    for (i, button) in buttons.iter().enumerate() {
+        if let Some(value) = values.get_mut(axes.len() + i) {
+            *value = button_us(keys.contains(*button));
+        }
+    }
+    channels.set(Some(values));
+
+    std::thread::spawn(move || {
+        loop {
+            let events = match device.fetch_events() {
+                Ok(events) => events,
+                Err(e) => {
+                    warn!("{}: {}", path.display(), e);
+                    channels.set(None);
+                    return;
+                }
+            };
+            let mut changed = false;
+            for event in events {
+                match event.destructure() {
+                    EventSummary::AbsoluteAxis(_, code, value) => {
+                        if let Some(ch) = axes.iter().position(|axis| axis.code == code)
+                            && ch < NUM_CHANNELS
+                        {
+                            values[ch] = axis_us(value, axes[ch].min, axes[ch].max);
+                            changed = true;
+                        }
+                    }
+                    EventSummary::Key(_, code, value) => {
+                        if let Some(i) = buttons.iter().position(|&button| button == code)
+                            && let Some(slot) = values.get_mut(axes.len() + i)
+                        {
+                            *slot = button_us(value != 0);
+                            changed = true;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            if changed {
+                channels.set(Some(values));
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scale() {
+        assert_eq!(axis_us(-32768, -32768, 32767), 1000);
+        assert_eq!(axis_us(32767, -32768, 32767), 2000);
+        assert_eq!(axis_us(0, -32768, 32767), 1500);
+        assert_eq!(axis_us(128, 0, 255), 1501);
+        assert_eq!(axis_us(300, 0, 255), 2000);
+        assert_eq!(axis_us(5, 0, 0), 1500);
+    }
+}
@@ -0,0 +1,321 @@
+//! RC channels → the trainer input of an EdgeTX radio, so that a PC or a
+//! gamepad can be the student of a real radio that flies the sim.
+//!
+//! The channels come from the CRSF RC frames on Zenoh, or from a local
+//! joystick. They go out as PPM, played through a sound card into the
+//! trainer jack, or as the frames of the Bluetooth trainer, written to a
+//! BLE serial module the radio connects to.
+
+use clap::{Parser, ValueEnum};
+use log::info;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use telemetry_lib::crsf::{self, CrsfPacket};
+use telemetry_lib::topics;
+use tokio::io::AsyncWriteExt;
+use tokio_serial::SerialPortBuilderExt;
+use zenoh::Config;
+
+mod bluetooth;
+#[cfg(target_os = "linux")]
+mod joystick;
+mod ppm;
+
+use ppm::Ppm;
+
+/// Number of CRSF RC channels.
+const NUM_CHANNELS: usize = 16;
+/// The channels are dropped when the RC frames stop for this long.
+const RC_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Trainer signal to produce.
+    #[arg(long, value_enum, default_value_t = Output::Ppm)]
+    output: Output,
+
+    /// Read the channels from this joystick or gamepad (a name, or a
+    /// /dev/input/event* path) instead of the RC frames on Zenoh. Its axes
+    /// are the first channels, then its buttons.
+    #[arg(long)]
+    joystick: Option<String>,
+
+    /// Channels in a PPM frame, from the first.
+    #[arg(long, default_value_t = 8, value_parser = clap::value_parser!(u8).range(1..=16))]
+    channels: u8,
+
+    /// Length of a PPM frame, µs.
+    #[arg(long, default_value_t = 22500)]
+    frame: u32,
+
+    /// Sample rate of the PPM audio. Higher rates time the pulses more
+    /// finely, where the sound card supports them.
+    #[arg(long, default_value_t = 48000)]
+    sample_rate: u32,
+
+    /// Level of the PPM audio, from 0 to 1.
+    #[arg(long, default_value_t = 0.8, value_parser = parse_fraction)]
+    volume: f64,
+
+    /// Play the PPM audio through this sound system.
+    #[arg(long, value_enum, default_value_t = Backend::Alsa)]
+    backend: Backend,
+
+    /// Serial port of the BLE module, for the Bluetooth trainer.
+    #[arg(long)]
+    serial: Option<String>,
+
+    /// Serial port speed.
+    #[arg(long, default_value_t = 115200)]
+    baud: u32,
+
+    /// Bluetooth trainer frames per second.
+    #[arg(long, default_value_t = 50)]
+    rate: u32,
+
+    /// Zenoh connect endpoint (e.g. tcp/192.168.1.1:7447). Omit for peer discovery.
+    #[arg(long)]
+    zenoh_connect: Option<String>,
+
+    /// Zenoh mode (peer or client).
+    #[arg(long, default_value = "client")]
+    zenoh_mode: String,
+
+    /// Zenoh topic prefix.
+    #[arg(long, default_value = topics::DEFAULT_PREFIX)]
+    zenoh_prefix: String,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum Output {
+    /// PPM over audio, into the trainer jack.
+    Ppm,
+    /// EdgeTX Bluetooth trainer frames, to a serial port.
+    Bluetooth,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Backend {
+    /// ALSA, through `aplay`.
+    Alsa,
+    /// PulseAudio or PipeWire, through `pacat`.
+    Pulse,
+}
+
+impl Backend {
+    /// The player, taking 16-bit mono samples at `rate` on its standard
+    /// input.
+    fn command(self, rate: u32) -> Command {
+        let mut command;
+        match self {
+            Backend::Alsa => {
+                command = Command::new("aplay");
+                command.args(["-q", "-t", "raw", "-f", "S16_LE", "-c", "1", "-B", "40000"]);
+                command.arg(format!("-r{}", rate));
+            }
+            Backend::Pulse => {
+                command = Command::new("pacat");
+                command.args([
+                    "--raw",
+                    "--format=s16le",
+                    "--channels=1",
+                    "--latency-msec=40",
+                ]);
+                command.arg(format!("--rate={}", rate));
+            }
+        }
+        command
+    }
+}
+
+/// Parse a value from 0 to 1, for use as a clap value parser.
+fn parse_fraction(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(v) if (0.0..=1.0).contains(&v) => Ok(v),
+        _ => Err(format!("invalid value `{}`, must be from 0 to 1", s)),
+    }
+}
+
+/// Channels, µs, and when they came.
+type Timed = (Instant, [u16; NUM_CHANNELS]);
+
+/// The latest channels, shared between the input and the output.
+#[derive(Clone)]
+struct Channels {
+    latest: Arc<Mutex<Option<Timed>>>,
+    /// Age from which they are dropped, if any.
+    timeout: Option<Duration>,
+}
+
+impl Channels {
+    fn new(timeout: Option<Duration>) -> Self {
+        Self {
+            latest: Arc::new(Mutex::new(None)),
+            timeout,
+        }
+    }
+
+    fn set(&self, channels: Option<[u16; NUM_CHANNELS]>) {
+        *self.latest.lock().unwrap_or_else(PoisonError::into_inner) =
+            channels.map(|channels| (Instant::now(), channels));
+    }
+
+    fn get(&self) -> Option<[u16; NUM_CHANNELS]> {
+        let latest = *self.latest.lock().unwrap_or_else(PoisonError::into_inner);
+        latest
+            .filter(|(at, _)| self.timeout.is_none_or(|timeout| at.elapsed() < timeout))
+            .map(|(_, channels)| channels)
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    env_logger::init();
+    let args = Args::parse();
+
+    info!("Starting liftoff-trainer");
+
+    if args.output == Output::Bluetooth && args.serial.is_none() {
+        return Err("--output bluetooth needs --serial".into());
+    }
+
+    let mut session = None;
+    let channels = match args.joystick {
+        #[cfg(target_os = "linux")]
+        Some(ref name) => {
+            let channels = Channels::new(None);
+            joystick::spawn(name, channels.clone())?;
+            channels
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => return Err("--joystick is only supported on Linux".into()),
+        None => {
+            let channels = Channels::new(Some(RC_TIMEOUT));
+            // Zenoh session
+            let mut config = Config::default();
+            config.insert_json5("mode", &format!(r#""{}""#, args.zenoh_mode))?;
+            if let Some(ref endpoint) = args.zenoh_connect {
+                config.insert_json5("connect/endpoints", &format!(r#"["{}"]"#, endpoint))?;
+            }
+            let zenoh = zenoh::open(config).await?;
+
+            let rc_topic = topics::topic(&args.zenoh_prefix, topics::CRSF_RC);
+            info!("Subscribing to: {}", rc_topic);
+            let subscriber = zenoh.declare_subscriber(&rc_topic).await?;
+            let latest = channels.clone();
+            tokio::spawn(async move {
+                while let Ok(sample) = subscriber.recv_async().await {
+                    if let Some(CrsfPacket::RcChannelsPacked(rc)) =
+                        crsf::parse_packet_check(&sample.payload().to_bytes())
+                    {
+                        latest.set(Some(rc.channels.map(crsf::ticks_to_us)));
+                    }
+                }
+            });
+            session = Some(zenoh);
+            channels
+        }
+    };
+
+    let mut child = None;
+    let mut output = match args.output {
+        Output::Ppm => {
+            let ppm = Ppm {
+                sample_rate: args.sample_rate,
+                frame_us: f64::from(args.frame),
+                amplitude: (args.volume * f64::from(i16::MAX)) as i16,
+            };
+            let count = usize::from(args.channels);
+            let mut command = args.backend.command(args.sample_rate);
+            let mut player = command
+                .stdin(Stdio::piped())
+                .spawn()
+                .map_err(|e| format!("{}: {}", command.get_program().to_string_lossy(), e))?;
+            let mut stdin = player.stdin.take().expect("piped stdin");
+            child = Some(player);
+            info!(
+                "Playing {} channels of PPM through {}",
+                count,
+                command.get_program().to_string_lossy()
+            );
+            // Writing blocks while the player's buffer is full, which
+            // keeps the frames in step with it.
+            tokio::task::spawn_blocking(move || -> std::io::Result<()> {
+                loop {
+                    let samples = ppm.frame(channels.get().as_ref().map(|c| &c[..count]));
+                    let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+                    stdin.write_all(&bytes)?;
+                }
+            })
+        }
+        Output::Bluetooth => {
+            let path = args.serial.clone().expect("checked above");
+            let mut port = tokio_serial::new(&path, args.baud)
+                .open_native_async()
+                .map_err(|e| format!("{}: {}", path, e))?;
+            info!("Writing Bluetooth trainer frames to {}", path);
+            let period = Duration::from_secs(1) / args.rate.max(1);
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(period);
+                loop {
+                    interval.tick().await;
+                    // Nothing is sent without channels, so the radio sees
+                    // the student go away.
+                    if let Some(channels) = channels.get() {
+                        let frame = bluetooth::frame(
+                            channels[..bluetooth::CHANNELS]
+                                .try_into()
+                                .expect("8 channels"),
+                        );
+                        AsyncWriteExt::write_all(&mut port, &frame).await?;
+                    }
+                }
+            })
+        }
+    };
+
+    let result: Result<(), Box<dyn std::error::Error + Send + Sync>> = tokio::select! {
+        result = &mut output => Err(match result {
+            Ok(Err(e)) => format!("output stopped: {}", e),
+            Ok(Ok(())) => "output stopped".to_string(),
+            Err(e) => format!("output: {}", e),
+        }.into()),
+        _ = tokio::signal::ctrl_c() => {
+            info!("Shutdown signal received, exiting.");
+            Ok(())
+        }
+    };
+    if let Some(mut child) = child {
+        child.kill().ok();
+    }
+
+    if let Some(session) = session {
+        session.close().await?;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channels() {
+        let channels = Channels::new(Some(Duration::from_millis(50)));
+        assert_eq!(channels.get(), None);
+        channels.set(Some([1500; NUM_CHANNELS]));
+        assert_eq!(channels.get(), Some([1500; NUM_CHANNELS]));
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(channels.get(), None);
+
+        let channels = Channels::new(None);
+        channels.set(Some([1000; NUM_CHANNELS]));
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(channels.get(), Some([1000; NUM_CHANNELS]));
+        channels.set(None);
+        assert_eq!(channels.get(), None);
+    }
+}
@@ -0,0 +1,77 @@
+//! PPM, as the trainer port of a radio takes it, in audio samples.
+//!
+//! A frame has a short pulse before each channel and one after the last;
+//! the time from the start of one pulse to the next is the channel value.
+//! The gap after the last pulse, up to the end of the frame, is what marks
+//! the start of the next frame.
+
+/// Length of a pulse, µs.
+pub const PULSE_US: f64 = 300.0;
+/// Shortest gap at the end of a frame, µs, so that it can't be taken for a
+/// channel.
+const SYNC_US: f64 = 4000.0;
+
+#[derive(Debug, Clone)]
+pub struct Ppm {
+    /// Samples per second.
+    pub sample_rate: u32,
+    /// Length of a frame, µs; longer if the channels don't fit.
+    pub frame_us: f64,
+    /// Level of the pulses; the gaps are at the opposite level.
+    pub amplitude: i16,
+}
+
+impl Ppm {
+    /// Samples of one frame of `channels`, µs. Silence for a frame when
+    /// there are none, which the radio takes as a lost signal.
+    pub fn frame(&self, channels: Option<&[u16]>) -> Vec<i16> {
+        let sample = |us: f64| (us * f64::from(self.sample_rate) / 1e6).round() as usize;
+        let Some(channels) = channels else {
+            return vec![0; sample(self.frame_us)];
+        };
+        let total: f64 = channels.iter().map(|&us| f64::from(us)).sum();
+        let mut samples =
+            vec![-self.amplitude; sample(self.frame_us.max(total + PULSE_US + SYNC_US))];
+        let mut start = 0.0;
+        for us in channels.iter().map(|&us| f64::from(us)).chain([0.0]) {
+            samples[sample(start)..sample(start + PULSE_US)].fill(self.amplitude);
+            start += us;
+        }
+        samples
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame() {
+        // One sample per µs.
+        let ppm = Ppm {
+            sample_rate: 1_000_000,
+            frame_us: 22500.0,
+            amplitude: 1000,
+        };
+        let samples = ppm.frame(Some(&[1000, 1500, 2000]));
+        assert_eq!(samples.len(), 22500);
+        let rising: Vec<usize> = (1..samples.len())
+            .filter(|&i| samples[i] > samples[i - 1])
+            .collect();
+        assert_eq!(rising, [1000, 2500, 4500]);
+        assert_eq!(samples[0], 1000);
+        assert_eq!(samples[299], 1000);
+        assert_eq!(samples[300], -1000);
+        assert_eq!(samples.iter().filter(|&&s| s > 0).count(), 4 * 300);
+
+        // Too many channels for the frame.
+        assert_eq!(ppm.frame(Some(&[2000; 10])).len(), 24300);
+
+        let silence = Ppm {
+            sample_rate: 48000,
+            ..ppm
+        }
+        .frame(None);
+        assert_eq!(silence, vec![0; 1080]);
+    }
+}
@@ -0,0 +1,166 @@
+use clap::Parser;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use log::{info, warn};
+use ratatui::DefaultTerminal;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use telemetry_lib::telemetry;
+use telemetry_lib::topics;
+use zenoh::Config;
+
+mod state;
+mod ui;
+
+use state::State;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// RC channels shown.
+    #[arg(long, default_value_t = 8, value_parser = clap::value_parser!(u8).range(1..=16))]
+    channels: u8,
+
+    /// Positions kept for the flight path, at the telemetry rate.
+    #[arg(long, default_value_t = 3000)]
+    trace_length: usize,
+
+    /// Zenoh connect endpoint (e.g. tcp/192.168.1.1:7447). Omit for peer discovery.
+    #[arg(long)]
+    zenoh_connect: Option<String>,
+
+    /// Zenoh mode (peer or client).
+    #[arg(long, default_value = "client")]
+    zenoh_mode: String,
+
+    /// Zenoh topic prefix.
+    #[arg(long, default_value = topics::DEFAULT_PREFIX)]
+    zenoh_prefix: String,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    env_logger::init();
+    let args = Args::parse();
+
+    // Zenoh session
+    let mut config = Config::default();
+    config.insert_json5("mode", &format!(r#""{}""#, args.zenoh_mode))?;
+    if let Some(ref endpoint) = args.zenoh_connect {
+        config.insert_json5("connect/endpoints", &format!(r#"["{}"]"#, endpoint))?;
+    }
+    let session = zenoh::open(config).await?;
+
+    let state = Arc::new(Mutex::new(State::new(args.trace_length)));
+
+    let tel_topic = topics::topic(&args.zenoh_prefix, topics::TELEMETRY);
+    info!("Subscribing to: {}", tel_topic);
+    let tel_subscriber = session.declare_subscriber(&tel_topic).await?;
+    let tx = state.clone();
+    tokio::spawn(async move {
+        // Telemetry format config
+        // We assume default configuration for now
+        let format = telemetry::default_stream_format();
+        loop {
+            match tel_subscriber.recv_async().await {
+                Ok(sample) => {
+                    let payload = sample.payload().to_bytes();
+                    if let Ok(packet) = telemetry::parse_packet(&payload, &format)
+                        && let Ok(mut lock) = tx.lock()
+                    {
+                        lock.telemetry(Instant::now(), &packet);
+                    }
+                }
+                Err(e) => {
+                    warn!("Sim telemetry subscriber error: {}", e);
+                    break;
+                }
+            }
+        }
+    });
+
+    let crsf_tel_topic = topics::topic(&args.zenoh_prefix, topics::CRSF_TELEMETRY);
+    info!("Subscribing to: {}", crsf_tel_topic);
+    let crsf_tel_subscriber = session.declare_subscriber(&crsf_tel_topic).await?;
+    let tx = state.clone();
+    tokio::spawn(async move {
+        loop {
+            match crsf_tel_subscriber.recv_async().await {
+                Ok(sample) => {
+                    let payload = sample.payload().to_bytes();
+                    if let Ok(mut lock) = tx.lock() {
+                        lock.crsf_telemetry(Instant::now(), &payload);
+                    }
+                }
+                Err(e) => {
+                    warn!("CRSF telemetry subscriber error: {}", e);
+                    break;
+                }
+            }
+        }
+    });
+
+    let rc_topic = topics::topic(&args.zenoh_prefix, topics::CRSF_RC);
+    info!("Subscribing to: {}", rc_topic);
+    let rc_subscriber = session.declare_subscriber(&rc_topic).await?;
+    let tx = state.clone();
+    tokio::spawn(async move {
+        loop {
+            match rc_subscriber.recv_async().await {
+                Ok(sample) => {
+                    let payload = sample.payload().to_bytes();
+                    if let Ok(mut lock) = tx.lock() {
+                        lock.rc(Instant::now(), &payload);
+                    }
+                }
+                Err(e) => {
+                    warn!("RC subscriber error: {}", e);
+                    break;
+                }
+            }
+        }
+    });
+
+    // Terminal UI
+    let mut terminal = ratatui::init();
+    let result = run_tui(&mut terminal, state, usize::from(args.channels));
+    ratatui::restore();
+    result
+}
+
+fn run_tui(
+    terminal: &mut DefaultTerminal,
+    state: Arc<Mutex<State>>,
+    channels: usize,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let tick = Duration::from_millis(100); // ~10 Hz refresh
+
+    loop {
+        let now = Instant::now();
+        let current = {
+            let mut lock = state.lock().unwrap();
+            lock.expire(now);
+            lock.clone()
+        };
+
+        terminal.draw(|f| ui::draw(f, &current, channels, now))?;
+
+        if event::poll(tick)?
+            && let Event::Key(key) = event::read()?
+        {
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('q') => return Ok(()),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(());
+                }
+                KeyCode::Char('l') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    terminal.clear()?;
+                }
+                KeyCode::Char('c') => state.lock().unwrap().clear_trace(),
+                _ => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//! What the dashboard shows, gathered by the subscriber tasks: the flight
+//! path from the sim telemetry; attitude, battery and link statistics from
+//! the CRSF telemetry; the RC channels; and how often each of those
+//! arrives.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use telemetry_lib::crsf::{self, CrsfPacket, LinkStatistics};
+use telemetry_lib::telemetry::TelemetryPacket;
+
+/// Window over which the rate of a stream is counted.
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Arrivals of one stream of packets.
+#[derive(Debug, Clone, Default)]
+pub struct Stream {
+    /// Arrival times within the last [`RATE_WINDOW`].
+    recent: VecDeque<Instant>,
+    last: Option<Instant>,
+}
+
+impl Stream {
+    fn arrive(&mut self, now: Instant) {
+        self.recent.push_back(now);
+        self.last = Some(now);
+    }
+
+    /// Packets per second, over the last [`RATE_WINDOW`].
+    pub fn rate(&self, now: Instant) -> f64 {
+        let since = now.checked_sub(RATE_WINDOW);
+        let count = self
+            .recent
+            .iter()
+            .filter(|&&t| since.is_none_or(|since| t > since))
+            .count();
+        count as f64 / RATE_WINDOW.as_secs_f64()
+    }
+
+    /// Time since the last packet, `None` if there was none.
+    pub fn age(&self, now: Instant) -> Option<Duration> {
+        self.last.map(|last| now.saturating_duration_since(last))
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some(&t) = self.recent.front()
+            && now.saturating_duration_since(t) > RATE_WINDOW
+        {
+            self.recent.pop_front();
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct State {
+    /// Pitch, roll and yaw, degrees.
+    pub attitude: Option<(f64, f64, f64)>,
+    /// Voltage (V) and remaining charge (%).
+    pub battery: Option<(f64, u8)>,
+    pub link: Option<LinkStatistics>,
+    /// RC channels, µs.
+    pub rc: Option<[u16; 16]>,
+    /// Flight path seen from above, east and north, m, the oldest first.
+    pub trace: VecDeque<(f64, f64)>,
+    trace_len: usize,
+    /// Height above the scene origin, m.
+    pub altitude: Option<f64>,
+    /// m/s.
+    pub speed: Option<f64>,
+    pub telemetry: Stream,
+    pub crsf_telemetry: Stream,
+    pub rc_stream: Stream,
+}
+
+impl State {
+    /// `trace_len` is the number of positions kept for the flight path.
+    pub fn new(trace_len: usize) -> Self {
+        Self {
+            attitude: None,
+            battery: None,
+            link: None,
+            rc: None,
+            trace: VecDeque::with_capacity(trace_len),
+            trace_len,
+            altitude: None,
+            speed: None,
+            telemetry: Stream::default(),
+            crsf_telemetry: Stream::default(),
+            rc_stream: Stream::default(),
+        }
+    }
+
+    pub fn telemetry(&mut self, now: Instant, packet: &TelemetryPacket) {
+        self.telemetry.arrive(now);
+        if let Some([x, y, z]) = packet.position {
+            if self.trace.len() >= self.trace_len {
+                self.trace.pop_front();
+            }
+            self.trace.push_back((f64::from(x), f64::from(z)));
+            self.altitude = Some(f64::from(y));
+        }
+        if let Some(velocity) = packet.velocity {
+            self.speed = Some(f64::from(
+                velocity.iter().map(|v| v * v).sum::<f32>().sqrt(),
+            ));
+        }
+    }
+
+    pub fn crsf_telemetry(&mut self, now: Instant, frame: &[u8]) {
+        let Some(packet) = crsf::parse_packet(frame) else {
+            return;
+        };
+        self.crsf_telemetry.arrive(now);
+        match packet {
+            CrsfPacket::Attitude(attitude) => {
+                let (pitch, roll, yaw) = attitude.as_radians();
+                self.attitude = Some((pitch.to_degrees(), roll.to_degrees(), yaw.to_degrees()));
+            }
+            CrsfPacket::Battery(battery) => {
+                self.battery = Some((battery.voltage_v(), battery.remaining));
+            }
+            CrsfPacket::LinkStatistics(link) => self.link = Some(link),
+            _ => {}
+        }
+    }
+
+    pub fn rc(&mut self, now: Instant, frame: &[u8]) {
+        match crsf::parse_packet(frame) {
+            Some(CrsfPacket::RcChannelsPacked(rc)) => {
+                self.rc = Some(rc.channels.map(crsf::ticks_to_us));
+            }
+            Some(CrsfPacket::LinkStatistics(link)) => {
+                self.link = Some(link);
+                return;
+            }
+            _ => return,
+        }
+        self.rc_stream.arrive(now);
+    }
+
+    /// Forget the flight path.
+    pub fn clear_trace(&mut self) {
+        self.trace.clear();
+    }
+
+    /// Drop the arrivals that no longer count towards the rates.
+    pub fn expire(&mut self, now: Instant) {
+        self.telemetry.expire(now);
+        self.crsf_telemetry.expire(now);
+        self.rc_stream.expire(now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use telemetry_lib::crsf::{RcChannelsPacked, build_packet, device_address};
+
+    #[test]
+    fn state() {
+        let t0 = Instant::now();
+        let mut state = State::new(2);
+        for i in 0..3 {
+            let packet = TelemetryPacket {
+                timestamp: None,
+                position: Some([i as f32, 5.0, 2.0 * i as f32]),
+                attitude: None,
+                velocity: Some([3.0, 0.0, 4.0]),
+                gyro: None,
+                input: None,
+                battery: None,
+                motor_rpm: None,
+            };
+            state.telemetry(t0 + Duration::from_millis(400 * i), &packet);
+        }
+        assert_eq!(state.trace, [(1.0, 2.0), (2.0, 4.0)]);
+        assert_eq!((state.altitude, state.speed), (Some(5.0), Some(5.0)));
+
+        let now = t0 + Duration::from_millis(1000);
+        state.expire(now);
+        assert_eq!(state.telemetry.rate(now), 2.0);
+        assert_eq!(state.telemetry.age(now), Some(Duration::from_millis(200)));
+        assert_eq!(state.rc_stream.age(now), None);
+
+        let mut channels = [992; 16];
+        channels[2] = 172;
+        let packet = CrsfPacket::RcChannelsPacked(RcChannelsPacked { channels });
+        let frame = build_packet(device_address::FLIGHT_CONTROLLER, &packet).unwrap();
+        state.rc(now, &frame);
+        let rc = state.rc.unwrap();
+        assert_eq!((rc[0], rc[2]), (1500, 988));
+        assert_eq!(state.rc_stream.rate(now), 1.0);
+    }
+}
@@ -0,0 +1,331 @@
+//! Drawing: attitude and flight path on top, RC channels, battery and link
+//! health below, in the panel style of telemetry-dashboard.
+
+use ratatui::Frame;
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::symbols::Marker;
+use ratatui::text::{Line, Span};
+use ratatui::widgets::canvas::{self, Canvas, Points};
+use ratatui::widgets::{Block, Borders, Gauge, Padding, Paragraph};
+use std::time::{Duration, Instant};
+
+use crate::state::{State, Stream};
+
+/// The colours of telemetry-dashboard.
+mod theme {
+    use ratatui::style::Color;
+
+    pub const BG_PANEL: Color = Color::Rgb(0x1c, 0x17, 0x1f);
+    pub const TEXT: Color = Color::Rgb(0xdf, 0xb0, 0xff);
+    pub const TEXT_MUTED: Color = Color::Rgb(0x88, 0x6f, 0x99);
+    pub const TEXT_DIM: Color = Color::Rgb(0x77, 0x63, 0x86);
+    pub const PRIMARY: Color = Color::Rgb(0xa2, 0x9d, 0xf0);
+    pub const ACCENT: Color = Color::Rgb(0xb6, 0x57, 0xff);
+    pub const SUCCESS: Color = Color::Rgb(0xa7, 0xda, 0x1e);
+    pub const WARNING: Color = Color::Rgb(0xf7, 0xb8, 0x3d);
+    pub const ERROR: Color = Color::Rgb(0xe6, 0x1f, 0x44);
+}
+
+/// A stream is shown as lost after this long without a packet.
+const STALE: Duration = Duration::from_secs(1);
+
+/// Pitch that moves the horizon from the center to the edge, degrees.
+const PITCH_RANGE: f64 = 45.0;
+
+/// Range of the RC channel bars, µs.
+const RC_MIN: u16 = 988;
+const RC_MAX: u16 = 2012;
+
+fn panel_block(title: String) -> Block<'static> {
+    let title_line = Line::from(vec![
+        Span::styled(" ▎", Style::default().fg(theme::PRIMARY)),
+        Span::styled(
+            format!(" {title} "),
+            Style::default()
+                .fg(theme::PRIMARY)
+                .add_modifier(Modifier::BOLD),
+        ),
+    ]);
+    Block::default()
+        .borders(Borders::NONE)
+        .style(Style::default().bg(theme::BG_PANEL).fg(theme::TEXT))
+        .title(title_line)
+        .padding(Padding::new(1, 2, 1, 1))
+}
+
+/// The part within the canvas, from -`width` to `width` and from -1 to 1,
+/// of the line from `center` `half` along `direction` either way. The
+/// canvas leaves out lines that don't fit whole.
+fn clip(
+    width: f64,
+    center: (f64, f64),
+    direction: (f64, f64),
+    half: f64,
+    color: Color,
+) -> Option<canvas::Line> {
+    let (mut t0, mut t1) = (-half, half);
+    for (c, d, max) in [(center.0, direction.0, width), (center.1, direction.1, 1.0)] {
+        if d.abs() < 1e-9 {
+            if c.abs() > max {
+                return None;
+            }
+            continue;
+        }
+        let (a, b) = ((-max - c) / d, (max - c) / d);
+        t0 = t0.max(a.min(b));
+        t1 = t1.min(a.max(b));
+    }
+    (t0 < t1).then_some(canvas::Line {
+        x1: center.0 + direction.0 * t0,
+        y1: center.1 + direction.1 * t0,
+        x2: center.0 + direction.0 * t1,
+        y2: center.1 + direction.1 * t1,
+        color,
+    })
+}
+
+fn draw_attitude(f: &mut Frame, area: Rect, state: &State) {
+    let title = match state.attitude {
+        Some((pitch, roll, yaw)) => format!(
+            "Attitude  P {:.0}°  R {:.0}°  Y {:.0}°",
+            pitch,
+            roll,
+            yaw.rem_euclid(360.0)
+        ),
+        None => "Attitude".to_string(),
+    };
+    let attitude = state.attitude;
+    let block = panel_block(title);
+    // As wide as the panel is, for a cell about twice as tall as it is
+    // wide.
+    let inner = block.inner(area);
+    let width = f64::from(inner.width.max(1)) / f64::from(inner.height.max(1) * 2);
+    let horizon = Canvas::default()
+        .block(block)
+        .background_color(theme::BG_PANEL)
+        .marker(Marker::Braille)
+        .x_bounds([-width, width])
+        .y_bounds([-1.0, 1.0])
+        .paint(move |ctx| {
+            if let Some((pitch, roll, _)) = attitude {
+                // Along the horizon, and up from it on the screen.
+                let (sin, cos) = roll.to_radians().sin_cos();
+                let (along, up) = ((cos, sin), (-sin, cos));
+                // The horizon, and the pitch ladder every 10°.
+                for step in -2..=2 {
+                    let offset = (f64::from(step) * 10.0 - pitch) / PITCH_RANGE;
+                    let half = if step == 0 { 2.0 * width + 2.0 } else { 0.2 };
+                    let center = (up.0 * offset, up.1 * offset);
+                    let color = if step == 0 {
+                        theme::PRIMARY
+                    } else {
+                        theme::TEXT_DIM
+                    };
+                    if let Some(line) = clip(width, center, along, half, color) {
+                        ctx.draw(&line);
+                    }
+                }
+            }
+            // The quad, fixed in the middle.
+            for (x1, y1, x2, y2) in [(-0.4, 0.0, -0.1, 0.0), (0.1, 0.0, 0.4, 0.0)] {
+                ctx.draw(&canvas::Line {
+                    x1,
+                    y1,
+                    x2,
+                    y2,
+                    color: theme::ACCENT,
+                });
+            }
+        });
+    f.render_widget(horizon, area);
+}
+
+fn draw_trace(f: &mut Frame, area: Rect, state: &State) {
+    let mut title = "Position".to_string();
+    if let Some(altitude) = state.altitude {
+        title += &format!("  alt {:.1} m", altitude);
+    }
+    if let Some(speed) = state.speed {
+        title += &format!("  {:.1} m/s", speed);
+    }
+    let block = panel_block(title);
+    let inner = block.inner(area);
+
+    // Fit the path, north up, at the same scale both ways: a cell is about
+    // twice as tall as it is wide.
+    let (mut x0, mut x1, mut y0, mut y1) = (f64::MAX, f64::MIN, f64::MAX, f64::MIN);
+    for &(x, y) in &state.trace {
+        (x0, x1, y0, y1) = (x0.min(x), x1.max(x), y0.min(y), y1.max(y));
+    }
+    if state.trace.is_empty() {
+        (x0, x1, y0, y1) = (0.0, 0.0, 0.0, 0.0);
+    }
+    let aspect = f64::from(inner.width.max(1)) / f64::from(inner.height.max(1) * 2);
+    let span_y = ((x1 - x0) / aspect).max(y1 - y0).max(10.0) * 1.1;
+    let span_x = span_y * aspect;
+    let (cx, cy) = ((x0 + x1) / 2.0, (y0 + y1) / 2.0);
+
+    let trace: Vec<(f64, f64)> = state.trace.iter().copied().collect();
+    let map = Canvas::default()
+        .block(block)
+        .background_color(theme::BG_PANEL)
+        .marker(Marker::Braille)
+        .x_bounds([cx - span_x / 2.0, cx + span_x / 2.0])
+        .y_bounds([cy - span_y / 2.0, cy + span_y / 2.0])
+        .paint(move |ctx| {
+            ctx.draw(&Points {
+                coords: &trace,
+                color: theme::PRIMARY,
+            });
+            if let Some(last) = trace.last() {
+                ctx.layer();
+                ctx.draw(&Points {
+                    coords: &[*last],
+                    color: theme::ACCENT,
+                });
+            }
+        });
+    f.render_widget(map, area);
+}
+
+/// A bar of `width` cells, filled for `us` between [`RC_MIN`] and
+/// [`RC_MAX`].
+fn rc_bar(us: u16, width: usize) -> String {
+    let fraction = f64::from(us.clamp(RC_MIN, RC_MAX) - RC_MIN) / f64::from(RC_MAX - RC_MIN);
+    let filled = (fraction * width as f64).round() as usize;
+    "█".repeat(filled) + &"░".repeat(width - filled)
+}
+
+fn draw_rc(f: &mut Frame, area: Rect, state: &State, channels: usize) {
+    let block = panel_block("RC channels".to_string());
+    let width = usize::from(block.inner(area).width).saturating_sub(11);
+    let lines: Vec<Line> = match state.rc {
+        Some(rc) => rc
+            .iter()
+            .take(channels)
+            .enumerate()
+            .map(|(i, &us)| {
+                Line::from(vec![
+                    Span::styled(
+                        format!("CH{:<3}{:>5} ", i + 1, us),
+                        Style::default().fg(theme::TEXT_MUTED),
+                    ),
+                    Span::styled(rc_bar(us, width), Style::default().fg(theme::PRIMARY)),
+                ])
+            })
+            .collect(),
+        None => vec![Line::styled("No RC", Style::default().fg(theme::TEXT_DIM))],
+    };
+    f.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+fn draw_battery(f: &mut Frame, area: Rect, state: &State) {
+    let block = panel_block("Battery".to_string());
+    let gauge = match state.battery {
+        Some((voltage, remaining)) => {
+            let color = match remaining {
+                0..20 => theme::ERROR,
+                20..50 => theme::WARNING,
+                _ => theme::SUCCESS,
+            };
+            Gauge::default()
+                .gauge_style(Style::default().fg(color).bg(theme::BG_PANEL))
+                .ratio(f64::from(remaining.min(100)) / 100.0)
+                .label(format!("{:.1} V  {}%", voltage, remaining))
+        }
+        None => Gauge::default()
+            .gauge_style(Style::default().fg(theme::TEXT_DIM).bg(theme::BG_PANEL))
+            .ratio(0.0)
+            .label("No battery"),
+    };
+    f.render_widget(gauge.block(block), area);
+}
+
+fn stream_line(name: &str, stream: &Stream, now: Instant) -> Line<'static> {
+    let (status, color) = match stream.age(now) {
+        Some(age) if age <= STALE => (format!("{:5.1} Hz", stream.rate(now)), theme::SUCCESS),
+        Some(age) => (format!("lost {:.0} s", age.as_secs_f64()), theme::ERROR),
+        None => ("none".to_string(), theme::ERROR),
+    };
+    Line::from(vec![
+        Span::styled(
+            format!("{:<16}", name),
+            Style::default().fg(theme::TEXT_MUTED),
+        ),
+        Span::styled(status, Style::default().fg(color)),
+    ])
+}
+
+fn draw_link(f: &mut Frame, area: Rect, state: &State, now: Instant) {
+    let mut lines = vec![
+        stream_line("Sim telemetry", &state.telemetry, now),
+        stream_line("CRSF telemetry", &state.crsf_telemetry, now),
+        stream_line("RC", &state.rc_stream, now),
+    ];
+    if let Some(link) = &state.link {
+        lines.push(Line::styled(
+            format!(
+                "LQ {}%  RSSI -{} dBm  downlink LQ {}%",
+                link.lq, link.rssi, link.lq_rx
+            ),
+            Style::default().fg(theme::TEXT),
+        ));
+    }
+    f.render_widget(
+        Paragraph::new(lines).block(panel_block("Link".to_string())),
+        area,
+    );
+}
+
+pub fn draw(f: &mut Frame, state: &State, channels: usize, now: Instant) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage(55),
+            Constraint::Min(8),
+            Constraint::Length(1),
+        ])
+        .spacing(1)
+        .split(f.area());
+
+    let top = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .spacing(1)
+        .split(rows[0]);
+    draw_attitude(f, top[0], state);
+    draw_trace(f, top[1], state);
+
+    let bottom = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .spacing(1)
+        .split(rows[1]);
+    draw_rc(f, bottom[0], state, channels);
+    let side = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(5), Constraint::Min(6)])
+        .spacing(1)
+        .split(bottom[1]);
+    draw_battery(f, side[0], state);
+    draw_link(f, side[1], state, now);
+
+    let key = |s: &str| {
+        Span::styled(
+            s.to_string(),
+            Style::default()
+                .fg(theme::ACCENT)
+                .add_modifier(Modifier::BOLD),
+        )
+    };
+    let desc = |s: &str| Span::styled(s.to_string(), Style::default().fg(theme::TEXT_MUTED));
+    let help = Paragraph::new(Line::from(vec![
+        Span::raw(" "),
+        key("c"),
+        desc(" clear path  "),
+        key("q"),
+        desc(" quit"),
+    ]));
+    f.render_widget(help, rows[2]);
+}
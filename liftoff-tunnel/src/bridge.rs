@@ -0,0 +1,120 @@
+//! Forwarding Zenoh samples through the connection, both ways.
+//!
+//! Samples of the forwarded topics go out as datagrams, RC first. The
+//! others are dropped while the datagrams waiting to be sent take more
+//! than half their room, so that they never hold up the RC. Samples that
+//! come in are published on the same topic under the local prefix. Only
+//! samples from other sessions are forwarded, so that a topic forwarded by
+//! both ends doesn't go round in circles.
+
+use std::collections::HashMap;
+
+use bytes::Bytes;
+use log::{debug, info, warn};
+use quinn::SendDatagramError;
+use telemetry_lib::topics;
+use tokio::sync::mpsc;
+use zenoh::Session;
+use zenoh::pubsub::Publisher;
+use zenoh::sample::Locality;
+
+use crate::tls::DATAGRAM_BUFFER;
+use crate::wire::{self, Priority};
+
+/// Samples waiting to go out, of each priority.
+const QUEUE: usize = 64;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+fn send(connection: &quinn::Connection, datagram: Bytes) -> Result<(), Error> {
+    match connection.send_datagram(datagram) {
+        Ok(()) => Ok(()),
+        Err(SendDatagramError::TooLarge) => {
+            warn!("Sample too large for a datagram, dropped");
+            Ok(())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Forward `forward` (topics under `prefix`, which may have wildcards) to
+/// the other end of `connection`, and publish what it forwards, until the
+/// connection is lost.
+pub async fn run(
+    connection: &quinn::Connection,
+    session: &Session,
+    prefix: &str,
+    forward: &[String],
+) -> Result<(), Error> {
+    let (rc_tx, mut rc_rx) = mpsc::channel::<Bytes>(QUEUE);
+    let (tel_tx, mut tel_rx) = mpsc::channel::<Bytes>(QUEUE);
+    let mut subscribers = Vec::new();
+    for topic in forward {
+        let key = topics::topic(prefix, topic);
+        info!("Forwarding: {}", key);
+        let under = format!("{}/", prefix);
+        let (rc_tx, tel_tx) = (rc_tx.clone(), tel_tx.clone());
+        let subscriber = session
+            .declare_subscriber(&key)
+            .allowed_origin(Locality::Remote)
+            .callback(move |sample| {
+                let Some(topic) = sample.key_expr().as_str().strip_prefix(&under) else {
+                    return;
+                };
+                let Some(datagram) = wire::encode(topic, &sample.payload().to_bytes()) else {
+                    return;
+                };
+                let tx = match wire::priority(topic) {
+                    Priority::Rc => &rc_tx,
+                    Priority::Telemetry => &tel_tx,
+                };
+                if tx.try_send(datagram).is_err() {
+                    debug!("Queue full, dropped a sample of {}", topic);
+                }
+            })
+            .await?;
+        subscribers.push(subscriber);
+    }
+
+    let mut publishers: HashMap<String, Publisher<'static>> = HashMap::new();
+    let mut dropped = 0u64;
+    loop {
+        tokio::select! {
+            biased;
+            Some(datagram) = rc_rx.recv() => send(connection, datagram)?,
+            datagram = connection.read_datagram() => {
+                let datagram = datagram?;
+                let Some((topic, payload)) = wire::decode(&datagram) else {
+                    debug!("Malformed datagram");
+                    continue;
+                };
+                if !publishers.contains_key(topic) {
+                    let key = topics::topic(prefix, topic);
+                    match session.declare_publisher(key.clone()).await {
+                        Ok(publisher) => {
+                            info!("Publishing to: {}", key);
+                            publishers.insert(topic.to_string(), publisher);
+                        }
+                        Err(e) => {
+                            warn!("Can't publish to {}: {}", key, e);
+                            continue;
+                        }
+                    }
+                }
+                if let Err(e) = publishers[topic].put(payload.to_vec()).await {
+                    warn!("Failed to publish {}: {}", topic, e);
+                }
+            }
+            Some(datagram) = tel_rx.recv() => {
+                if connection.datagram_send_buffer_space() >= DATAGRAM_BUFFER / 2 {
+                    send(connection, datagram)?;
+                } else {
+                    dropped += 1;
+                    if dropped.is_power_of_two() {
+                        warn!("Link congested, {} samples dropped so far", dropped);
+                    }
+                }
+            }
+        }
+    }
+}
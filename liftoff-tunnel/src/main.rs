@@ -0,0 +1,268 @@
+//! Zenoh topics over the internet, through one QUIC connection, for a
+//! radio and a sim in different places.
+//!
+//! The server runs at one end (usually the sim, reachable on a UDP port)
+//! and the client at the other (the radio, with `crsf-forward`). Each
+//! forwards its local samples of some topics to the other, which publishes
+//! them there: by default the client the RC, and the server the CRSF
+//! telemetry. The ends authenticate each other with a shared secret, and
+//! the client reconnects whenever the connection is lost.
+//!
+//! The server's certificate isn't checked, so a man in the middle can see
+//! the client's proof of the secret, and try secrets against it offline:
+//! the secret is to be a random key, of at least [`MIN_SECRET`] bytes.
+
+use clap::{Parser, Subcommand};
+use log::{info, warn};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use telemetry_lib::topics;
+use tokio::net::lookup_host;
+use tokio::sync::Semaphore;
+use zenoh::{Config, Session};
+
+mod auth;
+mod bridge;
+mod tls;
+mod wire;
+
+use auth::Role;
+
+/// Wait before reconnecting, doubling up to [`MAX_BACKOFF`].
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(16);
+
+/// Shortest secret, bytes.
+const MIN_SECRET: usize = 32;
+/// Time a client has to connect and authenticate.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Connections the server has yet to authenticate at a time; more are
+/// refused.
+const MAX_PENDING: usize = 8;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+
+    /// File with the secret both ends share: a random key of at least 32
+    /// bytes, e.g. from `openssl rand -hex 32`.
+    #[arg(long, global = true)]
+    secret_file: Option<PathBuf>,
+
+    /// Topic to forward to the other end, without the prefix; may have
+    /// wildcards. Can be given more than once. Without it, the client
+    /// forwards the RC (crsf/rc) and the server the CRSF telemetry
+    /// (crsf/telemetry).
+    #[arg(long, global = true)]
+    forward: Vec<String>,
+
+    /// Zenoh connect endpoint (e.g. tcp/192.168.1.1:7447). Omit for peer discovery.
+    #[arg(long, global = true)]
+    zenoh_connect: Option<String>,
+
+    /// Zenoh mode (peer or client).
+    #[arg(long, global = true, default_value = "client")]
+    zenoh_mode: String,
+
+    /// Zenoh topic prefix.
+    #[arg(long, global = true, default_value = topics::DEFAULT_PREFIX)]
+    zenoh_prefix: String,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Wait for the client to connect, e.g. at the sim.
+    Server {
+        /// UDP address to listen on.
+        #[arg(long, default_value = "0.0.0.0:7450")]
+        bind: SocketAddr,
+    },
+    /// Connect to the server, e.g. at the radio.
+    Client {
+        /// Address of the server, as host:port.
+        server: String,
+    },
+}
+
+/// Links between the two ends, all sharing one Zenoh session.
+struct Tunnel {
+    session: Session,
+    secret: Vec<u8>,
+    prefix: String,
+    forward: Vec<String>,
+}
+
+impl Tunnel {
+    /// Forward through `connection` until it is lost.
+    async fn bridge(
+        &self,
+        connection: &quinn::Connection,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        info!("Connected to {}", connection.remote_address());
+        bridge::run(connection, &self.session, &self.prefix, &self.forward).await
+    }
+
+    /// Serve every client that connects. A new one takes over from the
+    /// one before, which is likely gone, once it has authenticated.
+    async fn server(
+        self: Arc<Self>,
+        bind: SocketAddr,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let endpoint = quinn::Endpoint::server(tls::server()?, bind)?;
+        info!("Listening on {}", endpoint.local_addr()?);
+        let current: Arc<Mutex<Option<quinn::Connection>>> = Arc::default();
+        let pending = Arc::new(Semaphore::new(MAX_PENDING));
+        while let Some(incoming) = endpoint.accept().await {
+            let Ok(permit) = pending.clone().try_acquire_owned() else {
+                warn!(
+                    "{}: refused, too many connections authenticating",
+                    incoming.remote_address()
+                );
+                incoming.refuse();
+                continue;
+            };
+            let tunnel = self.clone();
+            let current = current.clone();
+            tokio::spawn(async move {
+                let remote = incoming.remote_address();
+                let connected = tokio::time::timeout(HANDSHAKE_TIMEOUT, async {
+                    let connection = incoming.await?;
+                    let handshake = auth::handshake(&connection, &tunnel.secret, Role::Server);
+                    if let Err(e) = handshake.await {
+                        connection.close(0u32.into(), b"");
+                        return Err(e);
+                    }
+                    Ok::<_, Box<dyn std::error::Error + Send + Sync>>(connection)
+                })
+                .await;
+                drop(permit);
+                let connection = match connected {
+                    Ok(Ok(connection)) => connection,
+                    Ok(Err(e)) => {
+                        warn!("{}: {}", remote, e);
+                        return;
+                    }
+                    Err(_) => {
+                        warn!("{}: no authentication in {:?}", remote, HANDSHAKE_TIMEOUT);
+                        return;
+                    }
+                };
+                let result = async {
+                    let previous = current
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .replace(connection.clone());
+                    if let Some(previous) = previous {
+                        previous.close(0u32.into(), b"replaced");
+                    }
+                    tunnel.bridge(&connection).await
+                }
+                .await;
+                if let Err(e) = result {
+                    warn!("{}: {}", remote, e);
+                }
+                connection.close(0u32.into(), b"");
+            });
+        }
+        Ok(())
+    }
+
+    /// Connect to `server`, and again whenever the connection is lost.
+    async fn client(
+        self: Arc<Self>,
+        server: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut endpoint = quinn::Endpoint::client(([0, 0, 0, 0, 0, 0, 0, 0], 0).into())
+            .or_else(|_| quinn::Endpoint::client(([0, 0, 0, 0], 0).into()))?;
+        endpoint.set_default_client_config(tls::client()?);
+        let mut backoff = MIN_BACKOFF;
+        loop {
+            let result = async {
+                let addr = lookup_host(server)
+                    .await?
+                    .next()
+                    .ok_or_else(|| format!("{}: no address", server))?;
+                info!("Connecting to {}", addr);
+                let connection = endpoint.connect(addr, tls::SERVER_NAME)?.await?;
+                auth::handshake(&connection, &self.secret, Role::Client).await?;
+                backoff = MIN_BACKOFF;
+                self.bridge(&connection).await
+            }
+            .await;
+            if let Err(e) = result {
+                warn!("{}: {}", server, e);
+            }
+            info!("Reconnecting in {:?}", backoff);
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    env_logger::init();
+    let args = Args::parse();
+
+    info!("Starting liftoff-tunnel");
+
+    let path = args
+        .secret_file
+        .ok_or("give --secret-file, with the secret both ends share")?;
+    let secret = std::fs::read_to_string(&path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?
+        .trim()
+        .as_bytes()
+        .to_vec();
+    if secret.len() < MIN_SECRET {
+        return Err(format!(
+            "{}: the secret is shorter than {} bytes; use a random key, e.g. from `openssl rand -hex 32`",
+            path.display(),
+            MIN_SECRET
+        )
+        .into());
+    }
+
+    let forward = if !args.forward.is_empty() {
+        args.forward
+    } else {
+        match args.command {
+            Command::Server { .. } => vec![topics::CRSF_TELEMETRY.to_string()],
+            Command::Client { .. } => vec![topics::CRSF_RC.to_string()],
+        }
+    };
+
+    // Zenoh session
+    let mut config = Config::default();
+    config.insert_json5("mode", &format!(r#""{}""#, args.zenoh_mode))?;
+    if let Some(ref endpoint) = args.zenoh_connect {
+        config.insert_json5("connect/endpoints", &format!(r#"["{}"]"#, endpoint))?;
+    }
+    let session = zenoh::open(config).await?;
+
+    let tunnel = Arc::new(Tunnel {
+        session: session.clone(),
+        secret,
+        prefix: args.zenoh_prefix,
+        forward,
+    });
+    let result = tokio::select! {
+        result = async {
+            match args.command {
+                Command::Server { bind } => tunnel.server(bind).await,
+                Command::Client { ref server } => tunnel.client(server).await,
+            }
+        } => result,
+        _ = tokio::signal::ctrl_c() => {
+            info!("Shutdown signal received, exiting.");
+            Ok(())
+        }
+    };
+
+    session.close().await?;
+    result
+}
@@ -0,0 +1,73 @@
+//! What goes through the tunnel: one Zenoh sample per QUIC datagram, as the
+//! length of its topic (relative to the prefix), the topic, and the
+//! payload.
+
+use bytes::Bytes;
+use telemetry_lib::topics;
+
+/// Which samples go first when the link is short of room.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// RC frames, always sent.
+    Rc,
+    /// Everything else, dropped while the link is congested.
+    Telemetry,
+}
+
+/// Priority of the samples of `topic`.
+pub fn priority(topic: &str) -> Priority {
+    let rc = topic == topics::CRSF_RC
+        || topic
+            .strip_prefix(topics::CRSF_RC)
+            .is_some_and(|rest| rest.starts_with('/'));
+    if rc {
+        Priority::Rc
+    } else {
+        Priority::Telemetry
+    }
+}
+
+/// Datagram of a sample; `None` if the topic is too long.
+pub fn encode(topic: &str, payload: &[u8]) -> Option<Bytes> {
+    let len = u8::try_from(topic.len()).ok()?;
+    let mut datagram = Vec::with_capacity(1 + topic.len() + payload.len());
+    datagram.push(len);
+    datagram.extend_from_slice(topic.as_bytes());
+    datagram.extend_from_slice(payload);
+    Some(datagram.into())
+}
+
+/// Topic and payload of a datagram; `None` if it is malformed.
+pub fn decode(datagram: &[u8]) -> Option<(&str, &[u8])> {
+    let (&len, rest) = datagram.split_first()?;
+    let len = usize::from(len);
+    if len == 0 || rest.len() < len {
+        return None;
+    }
+    let topic = std::str::from_utf8(&rest[..len]).ok()?;
+    Some((topic, &rest[len..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn datagrams() {
+        let datagram = encode("crsf/rc", &[0xc8, 0x18, 0x16]).unwrap();
+        assert_eq!(&datagram[..], b"\x07crsf/rc\xc8\x18\x16");
+        assert_eq!(
+            decode(&datagram),
+            Some(("crsf/rc", &[0xc8, 0x18, 0x16][..]))
+        );
+        assert_eq!(decode(b"\x07crsf/r"), None);
+        assert_eq!(decode(b"\x00data"), None);
+        assert_eq!(decode(b""), None);
+        assert_eq!(encode(&"x".repeat(256), b""), None);
+
+        assert_eq!(priority(topics::CRSF_RC), Priority::Rc);
+        assert_eq!(priority(topics::CRSF_RC_AUTOPILOT), Priority::Rc);
+        assert_eq!(priority("crsf/rcx"), Priority::Telemetry);
+        assert_eq!(priority(topics::CRSF_TELEMETRY), Priority::Telemetry);
+    }
+}
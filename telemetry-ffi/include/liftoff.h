@@ -0,0 +1,295 @@
+#ifndef LIFTOFF_H
+#define LIFTOFF_H
+
+/* Generated by cbindgen from telemetry-ffi; do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Longest CRSF frame, bytes.
+#define LIFTOFF_CRSF_MAX_FRAME_SIZE 64
+
+// Most RPM values in an RPM frame.
+#define LIFTOFF_CRSF_MAX_RPMS 19
+
+// Most cell voltages in a voltages frame.
+#define LIFTOFF_CRSF_MAX_VOLTAGES 29
+
+// Most channels in an RC channels subset frame, at 10 bits.
+#define LIFTOFF_CRSF_MAX_SUBSET_VALUES 47
+
+// Size of the flight mode, including the terminating NUL.
+#define LIFTOFF_CRSF_MAX_FLIGHT_MODE 60
+
+// Most rotors in a damage frame.
+#define LIFTOFF_CRSF_MAX_ROTORS 8
+
+// Frame types.
+#define LIFTOFF_CRSF_GPS 2
+
+#define LIFTOFF_CRSF_VARIO 7
+
+#define LIFTOFF_CRSF_BATTERY 8
+
+#define LIFTOFF_CRSF_BARO_ALT 9
+
+#define LIFTOFF_CRSF_AIRSPEED 10
+
+#define LIFTOFF_CRSF_RPM 12
+
+#define LIFTOFF_CRSF_VOLTAGES 14
+
+#define LIFTOFF_CRSF_LINK_STATISTICS 20
+
+#define LIFTOFF_CRSF_RC_CHANNELS_PACKED 22
+
+#define LIFTOFF_CRSF_RC_CHANNELS_SUBSET 23
+
+#define LIFTOFF_CRSF_ATTITUDE 30
+
+#define LIFTOFF_CRSF_FLIGHT_MODE 33
+
+#define LIFTOFF_CRSF_DAMAGE 66
+
+// Device addresses.
+#define LIFTOFF_CRSF_ADDRESS_FLIGHT_CONTROLLER 200
+
+#define LIFTOFF_CRSF_ADDRESS_RADIO_TRANSMITTER 234
+
+// Most motors in a packet.
+#define LIFTOFF_TELEMETRY_MAX_MOTORS 8
+
+// Bits of `LiftoffTelemetry::fields`, for the fields in the packet.
+#define LIFTOFF_TELEMETRY_TIMESTAMP (1 << 0)
+
+#define LIFTOFF_TELEMETRY_POSITION (1 << 1)
+
+#define LIFTOFF_TELEMETRY_ATTITUDE (1 << 2)
+
+#define LIFTOFF_TELEMETRY_VELOCITY (1 << 3)
+
+#define LIFTOFF_TELEMETRY_GYRO (1 << 4)
+
+#define LIFTOFF_TELEMETRY_INPUT (1 << 5)
+
+#define LIFTOFF_TELEMETRY_BATTERY (1 << 6)
+
+#define LIFTOFF_TELEMETRY_MOTOR_RPM (1 << 7)
+
+// Radians * 1e4.
+typedef struct LiftoffCrsfAttitude {
+  int16_t pitch;
+  int16_t roll;
+  int16_t yaw;
+} LiftoffCrsfAttitude;
+
+typedef struct LiftoffCrsfGps {
+  // Degrees * 1e7.
+  int32_t lat;
+  // Degrees * 1e7.
+  int32_t lon;
+  // km/h * 10.
+  uint16_t speed;
+  // Degrees * 100.
+  uint16_t heading;
+  // m + 1000.
+  uint16_t alt;
+  uint8_t sats;
+} LiftoffCrsfGps;
+
+typedef struct LiftoffCrsfBattery {
+  // dV.
+  uint16_t voltage;
+  // dA.
+  uint16_t current;
+  // mAh, 24 bits.
+  uint32_t capacity;
+  // %.
+  uint8_t remaining;
+} LiftoffCrsfBattery;
+
+typedef struct LiftoffCrsfVario {
+  // cm/s.
+  int16_t vertical_speed;
+} LiftoffCrsfVario;
+
+typedef struct LiftoffCrsfFlightMode {
+  // NUL-terminated.
+  char mode[LIFTOFF_CRSF_MAX_FLIGHT_MODE];
+} LiftoffCrsfFlightMode;
+
+typedef struct LiftoffCrsfBaroAlt {
+  // With the MSB clear dm + 10000, with it set m.
+  uint16_t alt;
+  // Log-scaled cm/s.
+  int8_t vertical_speed;
+} LiftoffCrsfBaroAlt;
+
+typedef struct LiftoffCrsfAirspeed {
+  // km/h * 10.
+  uint16_t speed;
+} LiftoffCrsfAirspeed;
+
+typedef struct LiftoffCrsfRpm {
+  uint8_t source_id;
+  uint8_t count;
+  // 24 bits each.
+  uint32_t rpms[LIFTOFF_CRSF_MAX_RPMS];
+} LiftoffCrsfRpm;
+
+typedef struct LiftoffCrsfVoltages {
+  uint8_t source_id;
+  uint8_t count;
+  uint16_t voltages_mv[LIFTOFF_CRSF_MAX_VOLTAGES];
+} LiftoffCrsfVoltages;
+
+typedef struct LiftoffCrsfRcChannelsPacked {
+  // CRSF ticks, 11 bits each.
+  uint16_t channels[16];
+} LiftoffCrsfRcChannelsPacked;
+
+typedef struct LiftoffCrsfRcChannelsSubset {
+  uint8_t first_channel;
+  // Bits per value, 10 to 13.
+  uint8_t resolution;
+  uint8_t count;
+  uint16_t values[LIFTOFF_CRSF_MAX_SUBSET_VALUES];
+} LiftoffCrsfRcChannelsSubset;
+
+typedef struct LiftoffCrsfLinkStatistics {
+  uint8_t snr;
+  uint8_t rf_mode;
+  uint8_t rssi;
+  uint8_t lq;
+  int8_t tx_power;
+  uint8_t tx_auc;
+  uint8_t rx_auc;
+  uint8_t snr_rx;
+  uint8_t rssi_rx;
+  uint8_t lq_rx;
+} LiftoffCrsfLinkStatistics;
+
+typedef struct LiftoffCrsfDamage {
+  // Bit 0 killed, bit 1 crashed, bit 2 no drone.
+  uint8_t flags;
+  uint8_t count;
+  // Health per rotor, 0 (destroyed) to 10000.
+  uint16_t health[LIFTOFF_CRSF_MAX_ROTORS];
+} LiftoffCrsfDamage;
+
+// The payload of a packet, by its type.
+typedef union LiftoffCrsfPayload {
+  struct LiftoffCrsfAttitude attitude;
+  struct LiftoffCrsfGps gps;
+  struct LiftoffCrsfBattery battery;
+  struct LiftoffCrsfVario vario;
+  struct LiftoffCrsfFlightMode flight_mode;
+  struct LiftoffCrsfBaroAlt baro_alt;
+  struct LiftoffCrsfAirspeed airspeed;
+  struct LiftoffCrsfRpm rpm;
+  struct LiftoffCrsfVoltages voltages;
+  struct LiftoffCrsfRcChannelsPacked rc_channels_packed;
+  struct LiftoffCrsfRcChannelsSubset rc_channels_subset;
+  struct LiftoffCrsfLinkStatistics link_statistics;
+  struct LiftoffCrsfDamage damage;
+} LiftoffCrsfPayload;
+
+// A CRSF packet: its frame type, one of the `LIFTOFF_CRSF_*` types, and
+// the payload of that type. Frames of other known types parse with no
+// payload.
+typedef struct LiftoffCrsfPacket {
+  uint8_t packet_type;
+  union LiftoffCrsfPayload payload;
+} LiftoffCrsfPacket;
+
+// A telemetry packet, in the sim's coordinates and units. Only the fields
+// with their bit set in `fields` were in the packet; the others are zero.
+typedef struct LiftoffTelemetry {
+  uint32_t fields;
+  // s.
+  float timestamp;
+  // X, Y, Z, m.
+  float position[3];
+  // Quaternion X, Y, Z, W.
+  float attitude[4];
+  // X, Y, Z, m/s.
+  float velocity[3];
+  // Pitch, roll, yaw, deg/s.
+  float gyro[3];
+  // Throttle, yaw, pitch, roll.
+  float input[4];
+  // Charge (0 to 1) and voltage (V).
+  float battery[2];
+  uint8_t motor_count;
+  float motor_rpm[LIFTOFF_TELEMETRY_MAX_MOTORS];
+} LiftoffTelemetry;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Parse the CRSF frame of `len` bytes at `frame` into `*out`. With
+// `check_crc`, a frame with the wrong CRC is invalid. Returns whether the
+// frame was valid; `*out` is left alone if it wasn't.
+//
+// # Safety
+//
+// `frame` points to `len` readable bytes, and `out` to a packet.
+bool liftoff_crsf_parse(const uint8_t *frame,
+                        size_t len,
+                        bool check_crc,
+                        struct LiftoffCrsfPacket *out);
+
+// Build the CRSF frame of `*packet`, sent to `address`, into the `len`
+// bytes at `out`; `LIFTOFF_CRSF_MAX_FRAME_SIZE` are always enough.
+// Returns the size of the frame, or 0 if the packet can't be built, or
+// doesn't fit.
+//
+// # Safety
+//
+// `packet` points to a packet, and `out` to `len` writable bytes. Of the
+// payload only the member for the packet type is read, and of its arrays
+// only the values in use and the flight mode up to its NUL, so the rest
+// need not be initialized.
+size_t liftoff_crsf_build(uint8_t address,
+                          const struct LiftoffCrsfPacket *packet,
+                          uint8_t *out,
+                          size_t len);
+
+// CRC-8/DVB-S2 of the `len` bytes at `data`, as over the type and payload
+// of a frame.
+//
+// # Safety
+//
+// `data` points to `len` readable bytes.
+uint8_t liftoff_crsf_crc8(const uint8_t *data, size_t len);
+
+// An RC channel value in µs, from CRSF ticks.
+uint16_t liftoff_crsf_ticks_to_us(uint16_t ticks);
+
+// An RC channel value in CRSF ticks, from µs.
+uint16_t liftoff_crsf_us_to_ticks(uint16_t us);
+
+// Parse the telemetry packet of `len` bytes at `data` into `*out`, in the
+// stream `format`: the comma-separated fields of the `StreamFormat` of
+// Liftoff's configuration, e.g. `"Timestamp,Position,Attitude"`, or NULL
+// for the default. Returns whether the packet was valid; `*out` is left
+// alone if it wasn't.
+//
+// # Safety
+//
+// `data` points to `len` readable bytes, `format` is NULL or a
+// NUL-terminated string, and `out` points to a packet.
+bool liftoff_telemetry_parse(const uint8_t *data,
+                             size_t len,
+                             const char *format,
+                             struct LiftoffTelemetry *out);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* LIFTOFF_H */
@@ -0,0 +1,688 @@
+//! CRSF frames to and from [`LiftoffCrsfPacket`].
+//!
+//! The constants are literals, for cbindgen to see them.
+
+use std::ffi::c_char;
+use std::slice;
+
+use telemetry_lib::crsf::{
+    self, Airspeed, Attitude, BaroAlt, Battery, CrsfPacket, Damage, FlightMode, Gps,
+    LinkStatistics, RcChannelsPacked, RcChannelsSubset, Rpm, Vario, Voltages,
+};
+
+/// Longest CRSF frame, bytes.
+pub const LIFTOFF_CRSF_MAX_FRAME_SIZE: usize = 64;
+/// Most RPM values in an RPM frame.
+pub const LIFTOFF_CRSF_MAX_RPMS: usize = 19;
+/// Most cell voltages in a voltages frame.
+pub const LIFTOFF_CRSF_MAX_VOLTAGES: usize = 29;
+/// Most channels in an RC channels subset frame, at 10 bits.
+pub const LIFTOFF_CRSF_MAX_SUBSET_VALUES: usize = 47;
+/// Size of the flight mode, including the terminating NUL.
+pub const LIFTOFF_CRSF_MAX_FLIGHT_MODE: usize = 60;
+/// Most rotors in a damage frame.
+pub const LIFTOFF_CRSF_MAX_ROTORS: usize = 8;
+
+/// Frame types.
+pub const LIFTOFF_CRSF_GPS: u8 = 0x02;
+pub const LIFTOFF_CRSF_VARIO: u8 = 0x07;
+pub const LIFTOFF_CRSF_BATTERY: u8 = 0x08;
+pub const LIFTOFF_CRSF_BARO_ALT: u8 = 0x09;
+pub const LIFTOFF_CRSF_AIRSPEED: u8 = 0x0A;
+pub const LIFTOFF_CRSF_RPM: u8 = 0x0C;
+pub const LIFTOFF_CRSF_VOLTAGES: u8 = 0x0E;
+pub const LIFTOFF_CRSF_LINK_STATISTICS: u8 = 0x14;
+pub const LIFTOFF_CRSF_RC_CHANNELS_PACKED: u8 = 0x16;
+pub const LIFTOFF_CRSF_RC_CHANNELS_SUBSET: u8 = 0x17;
+pub const LIFTOFF_CRSF_ATTITUDE: u8 = 0x1E;
+pub const LIFTOFF_CRSF_FLIGHT_MODE: u8 = 0x21;
+pub const LIFTOFF_CRSF_DAMAGE: u8 = 0x42;
+
+/// Device addresses.
+pub const LIFTOFF_CRSF_ADDRESS_FLIGHT_CONTROLLER: u8 = 0xC8;
+pub const LIFTOFF_CRSF_ADDRESS_RADIO_TRANSMITTER: u8 = 0xEA;
+
+/// Radians * 1e4.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct LiftoffCrsfAttitude {
+    pub pitch: i16,
+    pub roll: i16,
+    pub yaw: i16,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct LiftoffCrsfGps {
+    /// Degrees * 1e7.
+    pub lat: i32,
+    /// Degrees * 1e7.
+    pub lon: i32,
+    /// km/h * 10.
+    pub speed: u16,
+    /// Degrees * 100.
+    pub heading: u16,
+    /// m + 1000.
+    pub alt: u16,
+    pub sats: u8,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct LiftoffCrsfBattery {
+    /// dV.
+    pub voltage: u16,
+    /// dA.
+    pub current: u16,
+    /// mAh, 24 bits.
+    pub capacity: u32,
+    /// %.
+    pub remaining: u8,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct LiftoffCrsfVario {
+    /// cm/s.
+    pub vertical_speed: i16,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct LiftoffCrsfFlightMode {
+    /// NUL-terminated.
+    pub mode: [c_char; LIFTOFF_CRSF_MAX_FLIGHT_MODE],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct LiftoffCrsfBaroAlt {
+    /// With the MSB clear dm + 10000, with it set m.
+    pub alt: u16,
+    /// Log-scaled cm/s.
+    pub vertical_speed: i8,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct LiftoffCrsfAirspeed {
+    /// km/h * 10.
+    pub speed: u16,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct LiftoffCrsfRpm {
+    pub source_id: u8,
+    pub count: u8,
+    /// 24 bits each.
+    pub rpms: [u32; LIFTOFF_CRSF_MAX_RPMS],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct LiftoffCrsfVoltages {
+    pub source_id: u8,
+    pub count: u8,
+    pub voltages_mv: [u16; LIFTOFF_CRSF_MAX_VOLTAGES],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct LiftoffCrsfRcChannelsPacked {
+    /// CRSF ticks, 11 bits each.
+    pub channels: [u16; 16],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct LiftoffCrsfRcChannelsSubset {
+    pub first_channel: u8,
+    /// Bits per value, 10 to 13.
+    pub resolution: u8,
+    pub count: u8,
+    pub values: [u16; LIFTOFF_CRSF_MAX_SUBSET_VALUES],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct LiftoffCrsfLinkStatistics {
+    pub snr: u8,
+    pub rf_mode: u8,
+    pub rssi: u8,
+    pub lq: u8,
+    pub tx_power: i8,
+    pub tx_auc: u8,
+    pub rx_auc: u8,
+    pub snr_rx: u8,
+    pub rssi_rx: u8,
+    pub lq_rx: u8,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct LiftoffCrsfDamage {
+    /// Bit 0 killed, bit 1 crashed, bit 2 no drone.
+    pub flags: u8,
+    pub count: u8,
+    /// Health per rotor, 0 (destroyed) to 10000.
+    pub health: [u16; LIFTOFF_CRSF_MAX_ROTORS],
+}
+
+/// The payload of a packet, by its type.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub union LiftoffCrsfPayload {
+    pub attitude: LiftoffCrsfAttitude,
+    pub gps: LiftoffCrsfGps,
+    pub battery: LiftoffCrsfBattery,
+    pub vario: LiftoffCrsfVario,
+    pub flight_mode: LiftoffCrsfFlightMode,
+    pub baro_alt: LiftoffCrsfBaroAlt,
+    pub airspeed: LiftoffCrsfAirspeed,
+    pub rpm: LiftoffCrsfRpm,
+    pub voltages: LiftoffCrsfVoltages,
+    pub rc_channels_packed: LiftoffCrsfRcChannelsPacked,
+    pub rc_channels_subset: LiftoffCrsfRcChannelsSubset,
+    pub link_statistics: LiftoffCrsfLinkStatistics,
+    pub damage: LiftoffCrsfDamage,
+}
+
+/// A CRSF packet: its frame type, one of the `LIFTOFF_CRSF_*` types, and
+/// the payload of that type. Frames of other known types parse with no
+/// payload.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct LiftoffCrsfPacket {
+    pub packet_type: u8,
+    pub payload: LiftoffCrsfPayload,
+}
+
+/// Copy `values` into the start of `array`, returning their count.
+fn fill<T: Copy, const N: usize>(array: &mut [T; N], values: &[T]) -> u8 {
+    let n = values.len().min(N);
+    array[..n].copy_from_slice(&values[..n]);
+    n as u8
+}
+
+/// The first `count` of the values at `array`, `None` if there aren't as
+/// many. The values after them are not read.
+///
+/// # Safety
+///
+/// `array` points to an array of which the first `count` are initialized.
+unsafe fn take<T: Copy, const N: usize>(array: *const [T; N], count: u8) -> Option<Vec<T>> {
+    let count = usize::from(count);
+    (count <= N).then(|| unsafe { slice::from_raw_parts(array.cast::<T>(), count) }.to_vec())
+}
+
+fn to_c(packet: &CrsfPacket) -> LiftoffCrsfPacket {
+    // Safety: the payload is plain old data, for which all zeros are valid.
+    let mut out: LiftoffCrsfPacket = unsafe { std::mem::zeroed() };
+    let payload = &mut out.payload;
+    out.packet_type = match packet {
+        CrsfPacket::Attitude(att) => {
+            payload.attitude = LiftoffCrsfAttitude {
+                pitch: att.pitch,
+                roll: att.roll,
+                yaw: att.yaw,
+            };
+            LIFTOFF_CRSF_ATTITUDE
+        }
+        CrsfPacket::Gps(gps) => {
+            payload.gps = LiftoffCrsfGps {
+                lat: gps.lat,
+                lon: gps.lon,
+                speed: gps.speed,
+                heading: gps.heading,
+                alt: gps.alt,
+                sats: gps.sats,
+            };
+            LIFTOFF_CRSF_GPS
+        }
+        CrsfPacket::Battery(bat) => {
+            payload.battery = LiftoffCrsfBattery {
+                voltage: bat.voltage,
+                current: bat.current,
+                capacity: bat.capacity,
+                remaining: bat.remaining,
+            };
+            LIFTOFF_CRSF_BATTERY
+        }
+        CrsfPacket::Vario(vario) => {
+            payload.vario = LiftoffCrsfVario {
+                vertical_speed: vario.vertical_speed,
+            };
+            LIFTOFF_CRSF_VARIO
+        }
+        CrsfPacket::FlightMode(fm) => {
+            let mut mode = [0; LIFTOFF_CRSF_MAX_FLIGHT_MODE];
+            for (c, &b) in mode[..LIFTOFF_CRSF_MAX_FLIGHT_MODE - 1]
+                .iter_mut()
+                .zip(fm.mode.as_bytes())
+            {
+                *c = b as c_char;
+            }
+            payload.flight_mode = LiftoffCrsfFlightMode { mode };
+            LIFTOFF_CRSF_FLIGHT_MODE
+        }
+        CrsfPacket::BaroAlt(baro) => {
+            payload.baro_alt = LiftoffCrsfBaroAlt {
+                alt: baro.alt,
+                vertical_speed: baro.vertical_speed,
+            };
+            LIFTOFF_CRSF_BARO_ALT
+        }
+        CrsfPacket::Airspeed(airspeed) => {
+            payload.airspeed = LiftoffCrsfAirspeed {
+                speed: airspeed.speed,
+            };
+            LIFTOFF_CRSF_AIRSPEED
+        }
+        CrsfPacket::Rpm(rpm) => {
+            let mut rpms = [0; LIFTOFF_CRSF_MAX_RPMS];
+            let count = fill(&mut rpms, &rpm.rpms);
+            payload.rpm = LiftoffCrsfRpm {
+                source_id: rpm.source_id,
+                count,
+                rpms,
+            };
+            LIFTOFF_CRSF_RPM
+        }
+        CrsfPacket::Voltages(volts) => {
+            let mut voltages_mv = [0; LIFTOFF_CRSF_MAX_VOLTAGES];
+            let count = fill(&mut voltages_mv, &volts.voltages_mv);
+            payload.voltages = LiftoffCrsfVoltages {
+                source_id: volts.source_id,
+                count,
+                voltages_mv,
+            };
+            LIFTOFF_CRSF_VOLTAGES
+        }
+        CrsfPacket::RcChannelsPacked(rc) => {
+            payload.rc_channels_packed = LiftoffCrsfRcChannelsPacked {
+                channels: rc.channels,
+            };
+            LIFTOFF_CRSF_RC_CHANNELS_PACKED
+        }
+        CrsfPacket::RcChannelsSubset(subset) => {
+            let mut values = [0; LIFTOFF_CRSF_MAX_SUBSET_VALUES];
+            let count = fill(&mut values, &subset.values);
+            payload.rc_channels_subset = LiftoffCrsfRcChannelsSubset {
+                first_channel: subset.first_channel,
+                resolution: subset.resolution,
+                count,
+                values,
+            };
+            LIFTOFF_CRSF_RC_CHANNELS_SUBSET
+        }
+        CrsfPacket::LinkStatistics(ls) => {
+            payload.link_statistics = LiftoffCrsfLinkStatistics {
+                snr: ls.snr,
+                rf_mode: ls.rf_mode,
+                rssi: ls.rssi,
+                lq: ls.lq,
+                tx_power: ls.tx_power,
+                tx_auc: ls.tx_auc,
+                rx_auc: ls.rx_auc,
+                snr_rx: ls.snr_rx,
+                rssi_rx: ls.rssi_rx,
+                lq_rx: ls.lq_rx,
+            };
+            LIFTOFF_CRSF_LINK_STATISTICS
+        }
+        CrsfPacket::Damage(dmg) => {
+            let mut health = [0; LIFTOFF_CRSF_MAX_ROTORS];
+            let count = fill(&mut health, &dmg.health);
+            payload.damage = LiftoffCrsfDamage {
+                flags: dmg.flags,
+                count,
+                health,
+            };
+            LIFTOFF_CRSF_DAMAGE
+        }
+        CrsfPacket::Unknown(packet_type) => *packet_type as u8,
+    };
+    out
+}
+
+fn from_c(packet: &LiftoffCrsfPacket) -> Option<CrsfPacket> {
+    // Safety: only the member of the union for the packet type is read, and
+    // of its arrays only the values in use and the flight mode up to its
+    // NUL, which is all that `liftoff_crsf_build` asks the caller to initialize.
+    let payload = &packet.payload;
+    Some(unsafe {
+        match packet.packet_type {
+            LIFTOFF_CRSF_ATTITUDE => {
+                let att = payload.attitude;
+                CrsfPacket::Attitude(Attitude {
+                    pitch: att.pitch,
+                    roll: att.roll,
+                    yaw: att.yaw,
+                })
+            }
+            LIFTOFF_CRSF_GPS => {
+                let gps = payload.gps;
+                CrsfPacket::Gps(Gps {
+                    lat: gps.lat,
+                    lon: gps.lon,
+                    speed: gps.speed,
+                    heading: gps.heading,
+                    alt: gps.alt,
+                    sats: gps.sats,
+                })
+            }
+            LIFTOFF_CRSF_BATTERY => {
+                let bat = payload.battery;
+                CrsfPacket::Battery(Battery {
+                    voltage: bat.voltage,
+                    current: bat.current,
+                    capacity: bat.capacity,
+                    remaining: bat.remaining,
+                })
+            }
+            LIFTOFF_CRSF_VARIO => CrsfPacket::Vario(Vario {
+                vertical_speed: payload.vario.vertical_speed,
+            }),
+            LIFTOFF_CRSF_FLIGHT_MODE => {
+                let mode = (&raw const payload.flight_mode.mode).cast::<c_char>();
+                let bytes: Vec<u8> = (0..LIFTOFF_CRSF_MAX_FLIGHT_MODE)
+                    .map(|i| mode.add(i).read() as u8)
+                    .take_while(|&c| c != 0)
+                    .collect();
+                if bytes.len() == LIFTOFF_CRSF_MAX_FLIGHT_MODE {
+                    return None;
+                }
+                CrsfPacket::FlightMode(FlightMode {
+                    mode: String::from_utf8(bytes).ok()?,
+                })
+            }
+            LIFTOFF_CRSF_BARO_ALT => {
+                let baro = payload.baro_alt;
+                CrsfPacket::BaroAlt(BaroAlt {
+                    alt: baro.alt,
+                    vertical_speed: baro.vertical_speed,
+                })
+            }
+            LIFTOFF_CRSF_AIRSPEED => CrsfPacket::Airspeed(Airspeed {
+                speed: payload.airspeed.speed,
+            }),
+            LIFTOFF_CRSF_RPM => CrsfPacket::Rpm(Rpm {
+                source_id: payload.rpm.source_id,
+                rpms: take(&raw const payload.rpm.rpms, payload.rpm.count)?,
+            }),
+            LIFTOFF_CRSF_VOLTAGES => CrsfPacket::Voltages(Voltages {
+                source_id: payload.voltages.source_id,
+                voltages_mv: take(
+                    &raw const payload.voltages.voltages_mv,
+                    payload.voltages.count,
+                )?,
+            }),
+            LIFTOFF_CRSF_RC_CHANNELS_PACKED => CrsfPacket::RcChannelsPacked(RcChannelsPacked {
+                channels: payload.rc_channels_packed.channels,
+            }),
+            LIFTOFF_CRSF_RC_CHANNELS_SUBSET => CrsfPacket::RcChannelsSubset(RcChannelsSubset {
+                first_channel: payload.rc_channels_subset.first_channel,
+                resolution: payload.rc_channels_subset.resolution,
+                values: take(
+                    &raw const payload.rc_channels_subset.values,
+                    payload.rc_channels_subset.count,
+                )?,
+            }),
+            LIFTOFF_CRSF_LINK_STATISTICS => {
+                let ls = payload.link_statistics;
+                CrsfPacket::LinkStatistics(LinkStatistics {
+                    snr: ls.snr,
+                    rf_mode: ls.rf_mode,
+                    rssi: ls.rssi,
+                    lq: ls.lq,
+                    tx_power: ls.tx_power,
+                    tx_auc: ls.tx_auc,
+                    rx_auc: ls.rx_auc,
+                    snr_rx: ls.snr_rx,
+                    rssi_rx: ls.rssi_rx,
+                    lq_rx: ls.lq_rx,
+                })
+            }
+            LIFTOFF_CRSF_DAMAGE => CrsfPacket::Damage(Damage {
+                flags: payload.damage.flags,
+                health: take(&raw const payload.damage.health, payload.damage.count)?,
+            }),
+            _ => return None,
+        }
+    })
+}
+
+/// Parse the CRSF frame of `len` bytes at `frame` into `*out`. With
+/// `check_crc`, a frame with the wrong CRC is invalid. Returns whether the
+/// frame was valid; `*out` is left alone if it wasn't.
+///
+/// # Safety
+///
+/// `frame` points to `len` readable bytes, and `out` to a packet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn liftoff_crsf_parse(
+    frame: *const u8,
+    len: usize,
+    check_crc: bool,
+    out: *mut LiftoffCrsfPacket,
+) -> bool {
+    if frame.is_null() || out.is_null() {
+        return false;
+    }
+    let frame = unsafe { slice::from_raw_parts(frame, len) };
+    let packet = if check_crc {
+        crsf::parse_packet_check(frame)
+    } else {
+        crsf::parse_packet(frame)
+    };
+    match packet {
+        Some(packet) => {
+            unsafe { out.write(to_c(&packet)) };
+            true
+        }
+        None => false,
+    }
+}
+
+/// Build the CRSF frame of `*packet`, sent to `address`, into the `len`
+/// bytes at `out`; `LIFTOFF_CRSF_MAX_FRAME_SIZE` are always enough.
+/// Returns the size of the frame, or 0 if the packet can't be built, or
+/// doesn't fit.
+///
+/// # Safety
+///
+/// `packet` points to a packet, and `out` to `len` writable bytes. Of the
+/// payload only the member for the packet type is read, and of its arrays
+/// only the values in use and the flight mode up to its NUL, so the rest
+/// need not be initialized.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn liftoff_crsf_build(
+    address: u8,
+    packet: *const LiftoffCrsfPacket,
+    out: *mut u8,
+    len: usize,
+) -> usize {
+    if packet.is_null() || out.is_null() {
+        return 0;
+    }
+    let Some(frame) = from_c(unsafe { &*packet }).and_then(|p| crsf::build_packet(address, &p))
+    else {
+        return 0;
+    };
+    if frame.len() > len {
+        return 0;
+    }
+    unsafe { slice::from_raw_parts_mut(out, len)[..frame.len()].copy_from_slice(&frame) };
+    frame.len()
+}
+
+/// CRC-8/DVB-S2 of the `len` bytes at `data`, as over the type and payload
+/// of a frame.
+///
+/// # Safety
+///
+/// `data` points to `len` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn liftoff_crsf_crc8(data: *const u8, len: usize) -> u8 {
+    if data.is_null() {
+        return crsf::calc_crc8(&[]);
+    }
+    crsf::calc_crc8(unsafe { slice::from_raw_parts(data, len) })
+}
+
+/// An RC channel value in µs, from CRSF ticks.
+#[unsafe(no_mangle)]
+pub extern "C" fn liftoff_crsf_ticks_to_us(ticks: u16) -> u16 {
+    crsf::ticks_to_us(ticks)
+}
+
+/// An RC channel value in CRSF ticks, from µs.
+#[unsafe(no_mangle)]
+pub extern "C" fn liftoff_crsf_us_to_ticks(us: u16) -> u16 {
+    crsf::us_to_ticks(us)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem::MaybeUninit;
+
+    #[test]
+    fn roundtrip() {
+        use telemetry_lib::crsf::{PacketType, device_address};
+        assert_eq!(LIFTOFF_CRSF_MAX_FRAME_SIZE, crsf::MAX_FRAME_SIZE);
+        let types = [
+            (LIFTOFF_CRSF_GPS, PacketType::Gps),
+            (LIFTOFF_CRSF_VARIO, PacketType::Vario),
+            (LIFTOFF_CRSF_BATTERY, PacketType::BatterySensor),
+            (LIFTOFF_CRSF_BARO_ALT, PacketType::BaroAlt),
+            (LIFTOFF_CRSF_AIRSPEED, PacketType::Airspeed),
+            (LIFTOFF_CRSF_RPM, PacketType::Rpm),
+            (LIFTOFF_CRSF_VOLTAGES, PacketType::Voltages),
+            (LIFTOFF_CRSF_LINK_STATISTICS, PacketType::LinkStatistics),
+            (
+                LIFTOFF_CRSF_RC_CHANNELS_PACKED,
+                PacketType::RcChannelsPacked,
+            ),
+            (
+                LIFTOFF_CRSF_RC_CHANNELS_SUBSET,
+                PacketType::RcChannelsSubset,
+            ),
+            (LIFTOFF_CRSF_ATTITUDE, PacketType::Attitude),
+            (LIFTOFF_CRSF_FLIGHT_MODE, PacketType::FlightMode),
+            (LIFTOFF_CRSF_DAMAGE, PacketType::Damage),
+        ];
+        for (constant, packet_type) in types {
+            assert_eq!(constant, packet_type as u8);
+        }
+        assert_eq!(
+            LIFTOFF_CRSF_ADDRESS_FLIGHT_CONTROLLER,
+            device_address::FLIGHT_CONTROLLER
+        );
+        assert_eq!(
+            LIFTOFF_CRSF_ADDRESS_RADIO_TRANSMITTER,
+            device_address::RADIO_TRANSMITTER
+        );
+
+        let mut frame = [0u8; LIFTOFF_CRSF_MAX_FRAME_SIZE];
+        let mut packet: LiftoffCrsfPacket = unsafe { std::mem::zeroed() };
+        packet.packet_type = LIFTOFF_CRSF_VOLTAGES;
+        let mut voltages_mv = [0; LIFTOFF_CRSF_MAX_VOLTAGES];
+        voltages_mv[..2].copy_from_slice(&[4200, 4150]);
+        packet.payload.voltages = LiftoffCrsfVoltages {
+            source_id: 1,
+            count: 2,
+            voltages_mv,
+        };
+        let len = unsafe {
+            liftoff_crsf_build(
+                LIFTOFF_CRSF_ADDRESS_FLIGHT_CONTROLLER,
+                &packet,
+                frame.as_mut_ptr(),
+                frame.len(),
+            )
+        };
+        assert_eq!(len, 9);
+        assert_eq!(
+            unsafe { liftoff_crsf_crc8(frame[2..].as_ptr(), len - 3) },
+            frame[len - 1]
+        );
+
+        let mut parsed = MaybeUninit::uninit();
+        assert!(unsafe { liftoff_crsf_parse(frame.as_ptr(), len, true, parsed.as_mut_ptr()) });
+        let parsed = unsafe { parsed.assume_init() };
+        assert_eq!(parsed.packet_type, LIFTOFF_CRSF_VOLTAGES);
+        let voltages = unsafe { parsed.payload.voltages };
+        assert_eq!((voltages.count, voltages.voltages_mv[1]), (2, 4150));
+
+        frame[len - 1] ^= 0xff;
+        let mut out = MaybeUninit::uninit();
+        assert!(!unsafe { liftoff_crsf_parse(frame.as_ptr(), len, true, out.as_mut_ptr()) });
+        assert!(unsafe { liftoff_crsf_parse(frame.as_ptr(), len, false, out.as_mut_ptr()) });
+
+        // Too small a buffer, and more values than there are.
+        assert_eq!(
+            unsafe {
+                liftoff_crsf_build(
+                    LIFTOFF_CRSF_ADDRESS_FLIGHT_CONTROLLER,
+                    &packet,
+                    frame.as_mut_ptr(),
+                    8,
+                )
+            },
+            0
+        );
+        packet.payload.voltages = LiftoffCrsfVoltages {
+            count: LIFTOFF_CRSF_MAX_VOLTAGES as u8 + 1,
+            ..unsafe { packet.payload.voltages }
+        };
+        assert_eq!(
+            unsafe {
+                liftoff_crsf_build(
+                    LIFTOFF_CRSF_ADDRESS_FLIGHT_CONTROLLER,
+                    &packet,
+                    frame.as_mut_ptr(),
+                    frame.len(),
+                )
+            },
+            0
+        );
+
+        let mut packet: LiftoffCrsfPacket = unsafe { std::mem::zeroed() };
+        packet.packet_type = LIFTOFF_CRSF_FLIGHT_MODE;
+        let mut mode = [0; LIFTOFF_CRSF_MAX_FLIGHT_MODE];
+        for (c, &b) in mode.iter_mut().zip(b"ACRO") {
+            *c = b as c_char;
+        }
+        packet.payload.flight_mode = LiftoffCrsfFlightMode { mode };
+        let len = unsafe {
+            liftoff_crsf_build(
+                LIFTOFF_CRSF_ADDRESS_FLIGHT_CONTROLLER,
+                &packet,
+                frame.as_mut_ptr(),
+                frame.len(),
+            )
+        };
+        assert_eq!(&frame[3..len - 1], b"ACRO\0");
+
+        // Only the values in use initialized, as a C caller may leave it.
+        let mut packet = MaybeUninit::<LiftoffCrsfPacket>::uninit();
+        let p = packet.as_mut_ptr();
+        unsafe {
+            (&raw mut (*p).packet_type).write(LIFTOFF_CRSF_RPM);
+            (&raw mut (*p).payload.rpm.source_id).write(2);
+            (&raw mut (*p).payload.rpm.count).write(1);
+            (&raw mut (*p).payload.rpm.rpms).cast::<u32>().write(12000);
+        }
+        let len = unsafe {
+            liftoff_crsf_build(
+                LIFTOFF_CRSF_ADDRESS_FLIGHT_CONTROLLER,
+                packet.as_ptr(),
+                frame.as_mut_ptr(),
+                frame.len(),
+            )
+        };
+        assert_eq!(&frame[3..len - 1], [2, 0x00, 0x2e, 0xe0]);
+    }
+}
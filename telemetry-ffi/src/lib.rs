@@ -0,0 +1,12 @@
+//! C interface to telemetry-lib: CRSF frames parsed into and built from
+//! plain structs, and Liftoff telemetry packets parsed. The header is
+//! `include/liftoff.h`, generated by cbindgen; see the README.
+//!
+//! Nothing is allocated across the interface: the caller owns every
+//! buffer, and variable-length fields are fixed-size arrays with a count.
+
+mod crsf;
+mod telemetry;
+
+pub use crsf::*;
+pub use telemetry::*;
@@ -0,0 +1,146 @@
+//! Liftoff telemetry packets into [`LiftoffTelemetry`].
+
+use std::ffi::{CStr, c_char};
+use std::slice;
+
+use telemetry_lib::telemetry;
+
+/// Most motors in a packet.
+pub const LIFTOFF_TELEMETRY_MAX_MOTORS: usize = 8;
+
+/// Bits of `LiftoffTelemetry::fields`, for the fields in the packet.
+pub const LIFTOFF_TELEMETRY_TIMESTAMP: u32 = 1 << 0;
+pub const LIFTOFF_TELEMETRY_POSITION: u32 = 1 << 1;
+pub const LIFTOFF_TELEMETRY_ATTITUDE: u32 = 1 << 2;
+pub const LIFTOFF_TELEMETRY_VELOCITY: u32 = 1 << 3;
+pub const LIFTOFF_TELEMETRY_GYRO: u32 = 1 << 4;
+pub const LIFTOFF_TELEMETRY_INPUT: u32 = 1 << 5;
+pub const LIFTOFF_TELEMETRY_BATTERY: u32 = 1 << 6;
+pub const LIFTOFF_TELEMETRY_MOTOR_RPM: u32 = 1 << 7;
+
+/// A telemetry packet, in the sim's coordinates and units. Only the fields
+/// with their bit set in `fields` were in the packet; the others are zero.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LiftoffTelemetry {
+    pub fields: u32,
+    /// s.
+    pub timestamp: f32,
+    /// X, Y, Z, m.
+    pub position: [f32; 3],
+    /// Quaternion X, Y, Z, W.
+    pub attitude: [f32; 4],
+    /// X, Y, Z, m/s.
+    pub velocity: [f32; 3],
+    /// Pitch, roll, yaw, deg/s.
+    pub gyro: [f32; 3],
+    /// Throttle, yaw, pitch, roll.
+    pub input: [f32; 4],
+    /// Charge (0 to 1) and voltage (V).
+    pub battery: [f32; 2],
+    pub motor_count: u8,
+    pub motor_rpm: [f32; LIFTOFF_TELEMETRY_MAX_MOTORS],
+}
+
+/// Parse the telemetry packet of `len` bytes at `data` into `*out`, in the
+/// stream `format`: the comma-separated fields of the `StreamFormat` of
+/// Liftoff's configuration, e.g. `"Timestamp,Position,Attitude"`, or NULL
+/// for the default. Returns whether the packet was valid; `*out` is left
+/// alone if it wasn't.
+///
+/// # Safety
+///
+/// `data` points to `len` readable bytes, `format` is NULL or a
+/// NUL-terminated string, and `out` points to a packet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn liftoff_telemetry_parse(
+    data: *const u8,
+    len: usize,
+    format: *const c_char,
+    out: *mut LiftoffTelemetry,
+) -> bool {
+    if data.is_null() || out.is_null() {
+        return false;
+    }
+    let format = if format.is_null() {
+        telemetry::default_stream_format()
+    } else {
+        let Ok(format) = unsafe { CStr::from_ptr(format) }.to_str() else {
+            return false;
+        };
+        format.split(',').map(|f| f.trim().to_string()).collect()
+    };
+    let data = unsafe { slice::from_raw_parts(data, len) };
+    let Ok(packet) = telemetry::parse_packet(data, &format) else {
+        return false;
+    };
+
+    let rpms = packet.motor_rpm.as_deref().unwrap_or_default();
+    if rpms.len() > LIFTOFF_TELEMETRY_MAX_MOTORS {
+        return false;
+    }
+    let mut motor_rpm = [0.0; LIFTOFF_TELEMETRY_MAX_MOTORS];
+    motor_rpm[..rpms.len()].copy_from_slice(rpms);
+
+    let bit = |present: bool, bit: u32| if present { bit } else { 0 };
+    let t = LiftoffTelemetry {
+        fields: bit(packet.timestamp.is_some(), LIFTOFF_TELEMETRY_TIMESTAMP)
+            | bit(packet.position.is_some(), LIFTOFF_TELEMETRY_POSITION)
+            | bit(packet.attitude.is_some(), LIFTOFF_TELEMETRY_ATTITUDE)
+            | bit(packet.velocity.is_some(), LIFTOFF_TELEMETRY_VELOCITY)
+            | bit(packet.gyro.is_some(), LIFTOFF_TELEMETRY_GYRO)
+            | bit(packet.input.is_some(), LIFTOFF_TELEMETRY_INPUT)
+            | bit(packet.battery.is_some(), LIFTOFF_TELEMETRY_BATTERY)
+            | bit(packet.motor_rpm.is_some(), LIFTOFF_TELEMETRY_MOTOR_RPM),
+        timestamp: packet.timestamp.unwrap_or_default(),
+        position: packet.position.unwrap_or_default(),
+        attitude: packet.attitude.unwrap_or_default(),
+        velocity: packet.velocity.unwrap_or_default(),
+        gyro: packet.gyro.unwrap_or_default(),
+        input: packet.input.unwrap_or_default(),
+        battery: packet.battery.unwrap_or_default(),
+        motor_count: rpms.len() as u8,
+        motor_rpm,
+    };
+    unsafe { out.write(t) };
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use telemetry_lib::telemetry::TelemetryPacket;
+
+    #[test]
+    fn parse() {
+        let packet = TelemetryPacket {
+            timestamp: Some(1.5),
+            position: Some([1.0, 2.0, 3.0]),
+            attitude: None,
+            velocity: None,
+            gyro: None,
+            input: None,
+            battery: None,
+            motor_rpm: Some(vec![100.0, 200.0, 300.0, 400.0]),
+        };
+        let format = ["Timestamp", "Position", "MotorRPM"].map(String::from);
+        let data = telemetry::encode_packet(&packet, &format).unwrap();
+
+        let mut t = LiftoffTelemetry::default();
+        let format = c"Timestamp, Position,MotorRPM";
+        assert!(unsafe {
+            liftoff_telemetry_parse(data.as_ptr(), data.len(), format.as_ptr(), &mut t)
+        });
+        assert_eq!(
+            t.fields,
+            LIFTOFF_TELEMETRY_TIMESTAMP | LIFTOFF_TELEMETRY_POSITION | LIFTOFF_TELEMETRY_MOTOR_RPM
+        );
+        assert_eq!((t.timestamp, t.position[2]), (1.5, 3.0));
+        assert_eq!((t.motor_count, t.motor_rpm[3]), (4, 400.0));
+
+        // The default format wants more than there is.
+        assert!(!unsafe {
+            liftoff_telemetry_parse(data.as_ptr(), data.len(), std::ptr::null(), &mut t)
+        });
+    }
+}
@@ -1,6 +1,7 @@
 use crate::crsf_custom;
 use crc::{CRC_8_DVB_S2, Crc};
 use num_enum::TryFromPrimitive;
+use serde::{Deserialize, Serialize};
 
 pub const CRC8_DVB_S2: Crc<u8> = Crc::<u8>::new(&CRC_8_DVB_S2);
 
@@ -8,7 +9,7 @@ pub const CRC8_DVB_S2: Crc<u8> = Crc::<u8>::new(&CRC_8_DVB_S2);
 pub const MAX_FRAME_SIZE: usize = 64;
 
 #[repr(u8)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive, Serialize, Deserialize)]
 pub enum PacketType {
     Gps = 0x02,
     Vario = 0x07,
@@ -22,13 +23,16 @@ pub enum PacketType {
     VideoTransmitter = 0x0F,
     LinkStatistics = 0x14,
     RcChannelsPacked = 0x16,
+    RcChannelsSubset = 0x17,
     LinkStatisticsRx = 0x1C,
     LinkStatisticsTx = 0x1D,
     Attitude = 0x1E,
     FlightMode = 0x21,
+    DevicePing = 0x28,
     DeviceInfo = 0x29,
     ConfigRead = 0x2C,
     ConfigWrite = 0x2D,
+    Command = 0x32,
     RadioId = 0x3A,
     /// Custom extended frame for per-rotor damage telemetry.
     /// Unallocated in the CRSF spec; decoded by a LUA script on EdgeTX.
@@ -45,7 +49,7 @@ pub mod device_address {
     pub const CRSF_TRANSMITTER: u8 = 0xEE;
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Attitude {
     pub pitch: i16, // Radians * 1e4
     pub roll: i16,  // Radians * 1e4
@@ -75,7 +79,7 @@ impl Attitude {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Gps {
     pub lat: i32,     // deg * 1e7
     pub lon: i32,     // deg * 1e7
@@ -128,7 +132,7 @@ impl Gps {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Battery {
     pub voltage: u16,  // dV (spec says 10µV, but real devices use dV)
     pub current: u16,  // dA (spec says 10µA, but real devices use dA)
@@ -146,7 +150,7 @@ impl Battery {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Vario {
     pub vertical_speed: i16, // cm/s
 }
@@ -163,12 +167,12 @@ impl Vario {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FlightMode {
     pub mode: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BaroAlt {
     pub alt: u16,             // MSB=0: decimeters + 10000dm offset; MSB=1: meters
     pub vertical_speed: i8,   // log-scaled cm/s
@@ -222,7 +226,7 @@ impl BaroAlt {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Airspeed {
     pub speed: u16, // km/h * 10
 }
@@ -233,7 +237,7 @@ impl Airspeed {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Rpm {
     pub source_id: u8,
     pub rpms: Vec<u32>,
@@ -241,19 +245,56 @@ pub struct Rpm {
 
 /// CRSF voltage group (per-cell) telemetry packet (type 0x0E).
 /// Reports a list of cell voltages in millivolts.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Voltages {
     pub source_id: u8,
     /// Per-cell voltages in millivolts, in cell order.
     pub voltages_mv: Vec<u16>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RcChannelsPacked {
     pub channels: [u16; 16],
 }
 
-#[derive(Debug, Clone)]
+/// CRSFv3 subset RC channels packet (type 0x17): a run of consecutive
+/// channels starting at `first_channel`, packed at 10 to 13 bits each.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RcChannelsSubset {
+    pub first_channel: u8,
+    /// Bits per channel, 10..=13.
+    pub resolution: u8,
+    /// Raw channel values; `0..2^resolution` spans 988..2012 µs.
+    pub values: Vec<u16>,
+}
+
+impl RcChannelsSubset {
+    /// Channel values converted to the `RcChannelsPacked` scale
+    /// (172..1811 nominal, 992 center).
+    pub fn channels(&self) -> Vec<u16> {
+        // (us - 1500) * 8 / 5 + 992, with us = 988 + v / scale; rounded.
+        let scale = 1i32 << (self.resolution - 10);
+        let div = 5 * scale;
+        self.values
+            .iter()
+            .map(|&v| {
+                let num = (v as i32 - 512 * scale) * 8;
+                ((num + div / 2).div_euclid(div) + 992).clamp(0, 0x7ff) as u16
+            })
+            .collect()
+    }
+
+    /// Overwrite the channels carried by this packet in a full 16-channel
+    /// frame. Channels beyond the 16th are ignored.
+    pub fn merge_into(&self, channels: &mut [u16; 16]) {
+        let first = self.first_channel as usize;
+        for (dst, v) in channels.iter_mut().skip(first).zip(self.channels()) {
+            *dst = v;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LinkStatistics {
     pub snr: u8,
     pub rf_mode: u8,
@@ -270,7 +311,9 @@ pub struct LinkStatistics {
 /// Re-export so the rest of the crate can use `crsf::Damage`.
 pub use crate::crsf_custom::Damage;
 
-#[derive(Debug, Clone)]
+/// Serialized as `{"type": "gps", "data": {...}}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
 pub enum CrsfPacket {
     Attitude(Attitude),
     Gps(Gps),
@@ -282,6 +325,7 @@ pub enum CrsfPacket {
     Rpm(Rpm),
     Voltages(Voltages),
     RcChannelsPacked(RcChannelsPacked),
+    RcChannelsSubset(RcChannelsSubset),
     LinkStatistics(LinkStatistics),
     Damage(Damage),
     Unknown(PacketType), // Keep Unknown for parsing existing unknown packets
@@ -303,7 +347,7 @@ pub fn calc_crc8(data: &[u8]) -> u8 {
 
 /// Unpack CRSF 11-bit channels from a byte buffer.
 /// Expects 22 bytes of channel data (16 channels * 11 bits = 176 bits = 22 bytes).
-fn unpack_channels(data: &[u8]) -> Option<[u16; 16]> {
+pub(crate) fn unpack_channels(data: &[u8]) -> Option<[u16; 16]> {
     let mut channels = [0u16; 16];
     if data.len() < 22 {
         return None;
@@ -333,7 +377,7 @@ fn unpack_channels(data: &[u8]) -> Option<[u16; 16]> {
 }
 
 /// Pack 16x 11-bit channels into 22 bytes.
-fn pack_channels(channels: &[u16; 16]) -> Option<[u8; 22]> {
+pub(crate) fn pack_channels(channels: &[u16; 16]) -> Option<[u8; 22]> {
     let mut buf = [0u8; 22];
     let mut dest_shift = 0;
     let mut ptr = 0;
@@ -364,6 +408,51 @@ fn pack_channels(channels: &[u16; 16]) -> Option<[u8; 22]> {
     Some(buf)
 }
 
+/// Unpack `count` values of `bits` bits each, LSB first.
+fn unpack_bits(data: &[u8], bits: u32, count: usize) -> Option<Vec<u16>> {
+    if data.len() * 8 < count * bits as usize {
+        return None;
+    }
+    let mut values = Vec::with_capacity(count);
+    let mut acc = 0u32;
+    let mut acc_bits = 0;
+    let mut bytes = data.iter();
+    for _ in 0..count {
+        while acc_bits < bits {
+            acc |= (*bytes.next()? as u32) << acc_bits;
+            acc_bits += 8;
+        }
+        values.push((acc & ((1 << bits) - 1)) as u16);
+        acc >>= bits;
+        acc_bits -= bits;
+    }
+    Some(values)
+}
+
+/// Pack values of `bits` bits each, LSB first, padding the last byte with
+/// zeros. Returns `None` if a value doesn't fit.
+fn pack_bits(values: &[u16], bits: u32) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity((values.len() * bits as usize).div_ceil(8));
+    let mut acc = 0u32;
+    let mut acc_bits = 0;
+    for &v in values {
+        if (v as u32) >> bits != 0 {
+            return None;
+        }
+        acc |= (v as u32) << acc_bits;
+        acc_bits += bits;
+        while acc_bits >= 8 {
+            out.push(acc as u8);
+            acc >>= 8;
+            acc_bits -= 8;
+        }
+    }
+    if acc_bits > 0 {
+        out.push(acc as u8);
+    }
+    Some(out)
+}
+
 pub fn build_packet(address: u8, packet: &CrsfPacket) -> Option<Vec<u8>> {
     let mut frame = Vec::with_capacity(MAX_FRAME_SIZE);
     frame.push(address); // Address/sync byte
@@ -437,6 +526,14 @@ pub fn build_packet(address: u8, packet: &CrsfPacket) -> Option<Vec<u8>> {
             frame.push(PacketType::RcChannelsPacked as u8);
             frame.extend_from_slice(&pack_channels(&channels.channels)?);
         }
+        CrsfPacket::RcChannelsSubset(subset) => {
+            if subset.first_channel > 0x1f || !(10..=13).contains(&subset.resolution) {
+                return None;
+            }
+            frame.push(PacketType::RcChannelsSubset as u8);
+            frame.push(subset.first_channel | ((subset.resolution - 10) << 5));
+            frame.extend_from_slice(&pack_bits(&subset.values, subset.resolution as u32)?);
+        }
         CrsfPacket::LinkStatistics(ls) => {
             frame.push(PacketType::LinkStatistics as u8);
             frame.push(ls.snr);
@@ -592,6 +689,22 @@ pub fn parse_packet(frame: &[u8]) -> Option<CrsfPacket> {
             let channels = unpack_channels(data)?;
             Some(CrsfPacket::RcChannelsPacked(RcChannelsPacked { channels }))
         }
+        PacketType::RcChannelsSubset => {
+            // Bits 0-4: first channel, bits 5-6: resolution - 10, bit 7: reserved.
+            let (&config, packed) = data.split_first()?;
+            let resolution = 10 + ((config >> 5) & 0x03);
+            // Padding is always shorter than one channel.
+            let count = packed.len() * 8 / resolution as usize;
+            if count == 0 {
+                return None;
+            }
+            let values = unpack_bits(packed, resolution as u32, count)?;
+            Some(CrsfPacket::RcChannelsSubset(RcChannelsSubset {
+                first_channel: config & 0x1f,
+                resolution,
+                values,
+            }))
+        }
         PacketType::LinkStatistics => {
             if data.len() < 10 {
                 return None;
@@ -1101,6 +1214,70 @@ mod tests {
         assert_eq!(built, None);
     }
 
+    #[test]
+    fn test_rc_channels_subset() {
+        let subset = RcChannelsSubset {
+            first_channel: 4,
+            resolution: 11,
+            values: vec![0, 1024, 2047],
+        };
+        let packet = CrsfPacket::RcChannelsSubset(subset.clone());
+        let built = build_packet(SOURCE_ADDRESS, &packet).unwrap();
+        // Config byte plus 33 bits of channel data.
+        assert_eq!(built.len(), 4 + 1 + 5);
+        assert_eq!(built[2], PacketType::RcChannelsSubset as u8);
+        assert_eq!(built[3], 4 | (1 << 5));
+
+        let Some(CrsfPacket::RcChannelsSubset(parsed)) = parse_packet_check(&built) else {
+            panic!("Round trip failed for RcChannelsSubset");
+        };
+        assert_eq!(parsed, subset);
+        // 988 µs, 1500 µs and 2011.5 µs.
+        assert_eq!(parsed.channels(), vec![173, 992, 1810]);
+
+        let mut channels = [0u16; 16];
+        parsed.merge_into(&mut channels);
+        assert_eq!(channels[3..8], [0, 173, 992, 1810, 0]);
+
+        // 10-bit values and channels running past the 16th.
+        let subset = RcChannelsSubset {
+            first_channel: 14,
+            resolution: 10,
+            values: vec![512, 1023, 0],
+        };
+        let built = build_packet(
+            SOURCE_ADDRESS,
+            &CrsfPacket::RcChannelsSubset(subset.clone()),
+        )
+        .unwrap();
+        let Some(CrsfPacket::RcChannelsSubset(parsed)) = parse_packet_check(&built) else {
+            panic!("Round trip failed for RcChannelsSubset");
+        };
+        assert_eq!(parsed, subset);
+        parsed.merge_into(&mut channels);
+        assert_eq!(channels[14..], [992, 1810]);
+
+        // Value overflow and invalid resolution.
+        let subset = RcChannelsSubset {
+            first_channel: 0,
+            resolution: 10,
+            values: vec![1024],
+        };
+        assert_eq!(
+            build_packet(SOURCE_ADDRESS, &CrsfPacket::RcChannelsSubset(subset)),
+            None
+        );
+        let subset = RcChannelsSubset {
+            first_channel: 0,
+            resolution: 14,
+            values: vec![0],
+        };
+        assert_eq!(
+            build_packet(SOURCE_ADDRESS, &CrsfPacket::RcChannelsSubset(subset)),
+            None
+        );
+    }
+
     #[test]
     fn test_build_packet_link_statistics() {
         let ls = LinkStatistics {
@@ -1137,4 +1314,19 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_serde() {
+        let packet = CrsfPacket::Vario(Vario { vertical_speed: -150 });
+        let json = serde_json::to_string(&packet).unwrap();
+        assert_eq!(json, r#"{"type":"vario","data":{"vertical_speed":-150}}"#);
+
+        let json = r#"{"type":"rc_channels_subset","data":
+            {"first_channel":4,"resolution":11,"values":[1024,2047]}}"#;
+        let packet: CrsfPacket = serde_json::from_str(json).unwrap();
+        let built = build_packet(SOURCE_ADDRESS, &packet).unwrap();
+        assert_eq!(built[2], PacketType::RcChannelsSubset as u8);
+
+        let unknown = serde_json::to_string(&CrsfPacket::Unknown(PacketType::DevicePing)).unwrap();
+        assert_eq!(unknown, r#"{"type":"unknown","data":"DevicePing"}"#);
+    }
 }
@@ -6,6 +6,7 @@
 
 use crate::crsf::{CrsfPacket, build_packet, device_address};
 use crate::simstate::DamagePacket;
+use serde::{Deserialize, Serialize};
 
 const SOURCE_ADDRESS: u8 = device_address::FLIGHT_CONTROLLER;
 
@@ -18,7 +19,7 @@ const SOURCE_ADDRESS: u8 = device_address::FLIGHT_CONTROLLER;
 /// Sent as an extended-header frame with dest=RADIO_TRANSMITTER,
 /// origin=FLIGHT_CONTROLLER so EdgeTX forwards it to the LUA queue
 /// via `crossfireTelemetryPop()`.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Damage {
     /// Status flags (bit 0: killed, bit 1: crashed, bit 2: no drone).
     pub flags: u8,
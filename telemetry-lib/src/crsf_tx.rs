@@ -120,6 +120,15 @@ fn build_rpm_packet(rec: &TelemetryPacket) -> Option<Vec<u8>> {
     build_packet(SOURCE_ADDRESS, &CrsfPacket::Rpm(rpm))
 }
 
+/// Build a FlightMode packet carrying `mode`, e.g. a "no data" marker
+/// shown on the radio while the sim isn't sending telemetry.
+pub fn build_flight_mode_packet(mode: &str) -> Option<Vec<u8>> {
+    let fm = crsf::FlightMode {
+        mode: mode.to_string(),
+    };
+    build_packet(SOURCE_ADDRESS, &CrsfPacket::FlightMode(fm))
+}
+
 /// Build the full CRSF telemetry packet set for a single sample.
 ///
 /// `battery_lfbt`, when provided, takes precedence for the BatterySensor packet
@@ -155,6 +164,15 @@ mod tests {
     use crate::crsf::PacketType;
     use crate::telemetry::TelemetryPacket;
 
+    #[test]
+    fn test_build_flight_mode_packet() {
+        let frame = build_flight_mode_packet("!TEL").unwrap();
+        match crsf::parse_packet_check(&frame) {
+            Some(CrsfPacket::FlightMode(fm)) => assert_eq!(fm.mode, "!TEL"),
+            _ => panic!("Expected FlightMode packet"),
+        }
+    }
+
     #[test]
     fn test_generate_crsf_telemetry_empty() {
         let rec = TelemetryPacket {
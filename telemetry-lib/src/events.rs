@@ -0,0 +1,346 @@
+//! Events: the moments of a flight worth marking, such as a crash, found by
+//! rules over metrics derived from the sim telemetry.
+//!
+//! A rule is `NAME=CONDITION[,CONDITION...]`, a condition `METRIC>VALUE`
+//! or `METRIC<VALUE`, e.g. `punch=throttle_rate>4,throttle>0.8`. It
+//! happens when all its conditions become true, and again only after they
+//! have not all been. The metrics are in [`METRICS`].
+//!
+//! The [`Event`]s are published as JSON on the [`crate::topics::EVENTS`]
+//! topic, so that the tools that show or record them mark the same
+//! moments.
+
+use serde::{Deserialize, Serialize};
+
+use crate::telemetry::TelemetryPacket;
+
+/// The rules used unless others are given: a crash is slowing down at
+/// 60 m/s² (about 6 g), a punch is the throttle going up by its whole
+/// range within 0.25 s, to at least 80%, and a sag is the voltage 15%
+/// below that at rest.
+pub const DEFAULT_RULES: &[&str] = &[
+    "crash=deceleration>60",
+    "punch=throttle_rate>4,throttle>0.8",
+    "sag=sag>0.15",
+];
+
+/// The battery is at rest below this throttle, from 0 to 1.
+const REST_THROTTLE: f64 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    Speed,
+    Deceleration,
+    Altitude,
+    Throttle,
+    ThrottleRate,
+    Voltage,
+    Sag,
+    Charge,
+}
+
+/// The metrics by name: the speed (m/s), how fast it goes down (m/s²,
+/// negative when speeding up), the height above the scene origin (m), the
+/// throttle (from 0 to 1) and how fast it goes up (per s), the battery
+/// voltage (V), how far it is below the voltage at rest (from 0 to 1), and
+/// the charge left (from 0 to 1).
+pub const METRICS: &[(&str, Metric)] = &[
+    ("speed", Metric::Speed),
+    ("deceleration", Metric::Deceleration),
+    ("altitude", Metric::Altitude),
+    ("throttle", Metric::Throttle),
+    ("throttle_rate", Metric::ThrottleRate),
+    ("voltage", Metric::Voltage),
+    ("sag", Metric::Sag),
+    ("charge", Metric::Charge),
+];
+
+impl Metric {
+    pub fn name(self) -> &'static str {
+        METRICS
+            .iter()
+            .find(|&&(_, metric)| metric == self)
+            .map(|&(name, _)| name)
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Condition {
+    pub metric: Metric,
+    /// Whether the metric is to be above the threshold, or below it.
+    pub above: bool,
+    pub threshold: f64,
+}
+
+impl Condition {
+    fn holds(&self, value: f64) -> bool {
+        if self.above {
+            value > self.threshold
+        } else {
+            value < self.threshold
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub name: String,
+    pub conditions: Vec<Condition>,
+}
+
+impl Rule {
+    /// Parse `NAME=CONDITION[,CONDITION...]`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (name, conditions) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected name=condition, got {}", s))?;
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(format!(
+                "{}: a name has only letters, digits, _ and -",
+                name
+            ));
+        }
+        let conditions = conditions
+            .split(',')
+            .map(|condition| {
+                let (metric, above, threshold) = if let Some((m, t)) = condition.split_once('>') {
+                    (m, true, t)
+                } else if let Some((m, t)) = condition.split_once('<') {
+                    (m, false, t)
+                } else {
+                    return Err(format!(
+                        "expected metric>value or metric<value, got {}",
+                        condition
+                    ));
+                };
+                let metric = METRICS
+                    .iter()
+                    .find(|&&(name, _)| name == metric.trim())
+                    .map(|&(_, metric)| metric)
+                    .ok_or_else(|| {
+                        let names: Vec<&str> = METRICS.iter().map(|&(name, _)| name).collect();
+                        format!(
+                            "unknown metric {}, expected one of: {}",
+                            metric,
+                            names.join(", ")
+                        )
+                    })?;
+                let threshold = threshold
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("{}: not a number", threshold))?;
+                Ok(Condition {
+                    metric,
+                    above,
+                    threshold,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self {
+            name: name.to_string(),
+            conditions,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    /// Name of the rule.
+    pub name: String,
+    /// Sim time, s.
+    pub time: f64,
+    /// The first metric of the rule, and its value.
+    pub metric: String,
+    pub value: f64,
+}
+
+/// The metrics of a sample, by [`Metric`], if known.
+#[derive(Debug, Default)]
+struct Metrics([Option<f64>; 8]);
+
+impl Metrics {
+    fn get(&self, metric: Metric) -> Option<f64> {
+        self.0[metric as usize]
+    }
+
+    fn set(&mut self, metric: Metric, value: Option<f64>) {
+        self.0[metric as usize] = value;
+    }
+}
+
+/// Finds the events of the rules in the telemetry.
+pub struct Detector {
+    rules: Vec<Rule>,
+    /// Whether each rule holds.
+    holding: Vec<bool>,
+    /// Time (s), speed and throttle of the last sample.
+    last: Option<(f64, Option<f64>, Option<f64>)>,
+    /// Voltage at rest, V.
+    rest: Option<f64>,
+}
+
+impl Detector {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        Self {
+            holding: vec![false; rules.len()],
+            rules,
+            last: None,
+            rest: None,
+        }
+    }
+
+    fn metrics(&mut self, time: f64, packet: &TelemetryPacket) -> Metrics {
+        let mut metrics = Metrics::default();
+        let speed = packet
+            .velocity
+            .map(|v| v.iter().map(|&v| f64::from(v).powi(2)).sum::<f64>().sqrt());
+        // The stick, from -1 to 1.
+        let throttle = packet.input.map(|input| (f64::from(input[0]) + 1.0) / 2.0);
+        metrics.set(Metric::Speed, speed);
+        metrics.set(Metric::Throttle, throttle);
+        metrics.set(Metric::Altitude, packet.position.map(|p| f64::from(p[1])));
+
+        if let Some((last_time, last_speed, last_throttle)) =
+            self.last.replace((time, speed, throttle))
+        {
+            let dt = time - last_time;
+            if dt > 0.0 {
+                let rise = |from: Option<f64>, to: Option<f64>| Some((to? - from?) / dt);
+                metrics.set(Metric::Deceleration, rise(speed, last_speed));
+                metrics.set(Metric::ThrottleRate, rise(last_throttle, throttle));
+            }
+        }
+
+        if let Some([charge, voltage]) = packet.battery {
+            let voltage = f64::from(voltage);
+            // Also after a battery swap.
+            if throttle.is_some_and(|t| t < REST_THROTTLE) || self.rest.is_none_or(|r| voltage > r)
+            {
+                self.rest = Some(voltage);
+            }
+            metrics.set(Metric::Voltage, Some(voltage));
+            metrics.set(Metric::Charge, Some(f64::from(charge)));
+            metrics.set(
+                Metric::Sag,
+                self.rest
+                    .filter(|&rest| rest > 0.0)
+                    .map(|rest| (rest - voltage) / rest),
+            );
+        }
+        metrics
+    }
+
+    /// Take the telemetry at `time` (s), and tell the events it makes. A
+    /// time before the last one is a new run, which starts over.
+    pub fn update(&mut self, time: f64, packet: &TelemetryPacket) -> Vec<Event> {
+        if self.last.is_some_and(|(last, ..)| time < last) {
+            self.last = None;
+            self.rest = None;
+            self.holding.fill(false);
+        }
+        let metrics = self.metrics(time, packet);
+        let mut events = Vec::new();
+        for (rule, holding) in self.rules.iter().zip(self.holding.iter_mut()) {
+            let holds = rule.conditions.iter().all(|condition| {
+                metrics
+                    .get(condition.metric)
+                    .is_some_and(|value| condition.holds(value))
+            });
+            if holds
+                && !*holding
+                && let Some(first) = rule.conditions.first()
+            {
+                events.push(Event {
+                    name: rule.name.clone(),
+                    time,
+                    metric: first.metric.name().to_string(),
+                    value: metrics.get(first.metric).unwrap_or_default(),
+                });
+            }
+            *holding = holds;
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(speed: f32, throttle: f32, voltage: f32) -> TelemetryPacket {
+        TelemetryPacket {
+            timestamp: None,
+            position: Some([0.0, 5.0, 0.0]),
+            attitude: None,
+            velocity: Some([speed, 0.0, 0.0]),
+            gyro: None,
+            input: Some([throttle * 2.0 - 1.0, 0.0, 0.0, 0.0]),
+            battery: Some([0.8, voltage]),
+            motor_rpm: None,
+        }
+    }
+
+    #[test]
+    fn events() {
+        let rule = Rule::parse("punch=throttle_rate>4,throttle>0.8").unwrap();
+        assert_eq!(rule.conditions.len(), 2);
+        assert_eq!(rule.conditions[0].metric, Metric::ThrottleRate);
+        assert_eq!(
+            Rule::parse("low=voltage<13.2").unwrap().conditions[0],
+            Condition {
+                metric: Metric::Voltage,
+                above: false,
+                threshold: 13.2,
+            }
+        );
+        assert!(Rule::parse("crash").is_err());
+        assert!(Rule::parse("crash=decel>60").is_err());
+        assert!(Rule::parse("crash=deceleration=60").is_err());
+        assert!(Rule::parse("crash=deceleration>lots").is_err());
+        assert!(Rule::parse("a/b=speed>1").is_err());
+
+        let rules = DEFAULT_RULES
+            .iter()
+            .map(|rule| Rule::parse(rule))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let mut detector = Detector::new(rules);
+        let names = |events: Vec<Event>| -> Vec<String> {
+            events.into_iter().map(|event| event.name).collect()
+        };
+        assert_eq!(detector.update(0.0, &packet(0.0, 0.0, 16.8)), []);
+        // Punched out, with the voltage sagging.
+        assert_eq!(
+            names(detector.update(0.125, &packet(5.0, 1.0, 14.0))),
+            ["punch", "sag"]
+        );
+        // Holding isn't happening again.
+        assert_eq!(detector.update(0.25, &packet(20.0, 1.0, 14.0)), []);
+        assert_eq!(detector.update(0.375, &packet(30.0, 1.0, 15.5)), []);
+        // Into a wall.
+        let events = detector.update(0.5, &packet(0.0, 1.0, 15.5));
+        assert_eq!(
+            events,
+            [Event {
+                name: "crash".into(),
+                time: 0.5,
+                metric: "deceleration".into(),
+                value: 240.0,
+            }]
+        );
+        assert_eq!(detector.update(0.625, &packet(0.0, 1.0, 15.5)), []);
+        // Down, and again.
+        assert_eq!(detector.update(0.75, &packet(0.0, 0.0, 16.0)), []);
+        assert_eq!(
+            names(detector.update(0.875, &packet(2.0, 1.0, 16.0))),
+            ["punch"]
+        );
+        // A new run.
+        assert_eq!(detector.update(0.0, &packet(0.0, 1.0, 16.8)), []);
+    }
+}
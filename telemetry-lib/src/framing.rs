@@ -0,0 +1,207 @@
+//! Splitting a serial byte stream, which comes in pieces, into frames.
+//!
+//! A frame starts with a sync byte, which can also turn up inside a frame
+//! or in garbage: a sync byte starts a frame only if what follows it is
+//! one. The protocol tells that from the buffer at the sync byte, with a
+//! function that returns [`Found`].
+//!
+//! A CRSF frame starts with the address of the device it is for, which
+//! doubles as sync byte. [`Deframer`] takes any of the flight controller,
+//! radio, receiver and transmitter module addresses, so that both sides
+//! of a link can be watched.
+
+use crate::crsf::{self, device_address};
+
+/// Whether a sync byte starts a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Found {
+    Frame { len: usize, valid: bool },
+    Incomplete,
+    NotAFrame,
+}
+
+/// What a buffer starts with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parsed {
+    /// A frame, `valid` unless its checksum is wrong.
+    Frame { len: usize, valid: bool },
+    /// Bytes that are not part of a frame.
+    Garbage(usize),
+}
+
+/// What `buf` starts with: a frame, or garbage up to the next one, with
+/// frames starting at one of the `sync` bytes. Returns `None` if more
+/// data is needed.
+pub fn next_frame(buf: &[u8], sync: &[u8], frame: fn(&[u8]) -> Found) -> Option<Parsed> {
+    // Skip the sync bytes that don't start a frame, so that the garbage
+    // before the next frame comes in one piece.
+    let mut at = 0;
+    while let Some(pos) = buf[at..].iter().position(|b| sync.contains(b)) {
+        at += pos;
+        match (frame(&buf[at..]), at) {
+            (Found::NotAFrame, _) => at += 1,
+            (Found::Frame { len, valid }, 0) => return Some(Parsed::Frame { len, valid }),
+            (Found::Incomplete, 0) => return None,
+            _ => return Some(Parsed::Garbage(at)),
+        }
+    }
+    (!buf.is_empty()).then_some(Parsed::Garbage(buf.len()))
+}
+
+/// Give up on the incomplete frame that `buf` starts with: the garbage up
+/// to the next of the `sync` bytes.
+pub fn resync(buf: &[u8], sync: &[u8]) -> usize {
+    buf.iter()
+        .skip(1)
+        .position(|b| sync.contains(b))
+        .map_or(buf.len(), |pos| pos + 1)
+}
+
+/// Whether `buf`, at a sync byte, starts a CRSF frame.
+pub fn crsf_frame(buf: &[u8]) -> Found {
+    if buf.len() < 2 {
+        return Found::Incomplete;
+    }
+    let len = buf[1] as usize; // Length of Type + Payload + CRC
+    let total_len = len + 2; // Sync + Len + Type + Payload + CRC
+    if len < 2 || total_len > crsf::MAX_FRAME_SIZE {
+        // "Each CRSF frame is not longer than 64 bytes (including the Sync and CRC bytes)"
+        return Found::NotAFrame;
+    }
+    if buf.len() < total_len {
+        return Found::Incomplete;
+    }
+    Found::Frame {
+        len: total_len,
+        valid: crsf::frame_check_crc(&buf[..total_len]),
+    }
+}
+
+/// The addresses that start the CRSF frames on either side of a link.
+pub const CRSF_SYNC: [u8; 4] = [
+    device_address::FLIGHT_CONTROLLER,
+    device_address::RADIO_TRANSMITTER,
+    device_address::CRSF_RECEIVER,
+    device_address::CRSF_TRANSMITTER,
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Chunk {
+    /// A frame, `valid` unless its checksum is wrong.
+    Frame { data: Vec<u8>, valid: bool },
+    /// Bytes that are not part of a frame.
+    Garbage(Vec<u8>),
+}
+
+/// Splits a CRSF byte stream, which comes in pieces, into frames.
+#[derive(Default)]
+pub struct Deframer {
+    buf: Vec<u8>,
+}
+
+impl Deframer {
+    /// The frames and garbage completed by `data`.
+    pub fn push(&mut self, data: &[u8]) -> Vec<Chunk> {
+        self.buf.extend_from_slice(data);
+        let mut chunks = Vec::new();
+        let mut at = 0;
+        while let Some(parsed) = next_frame(&self.buf[at..], &CRSF_SYNC, crsf_frame) {
+            let (Parsed::Frame { len, .. } | Parsed::Garbage(len)) = parsed;
+            let data = self.buf[at..at + len].to_vec();
+            chunks.push(match parsed {
+                Parsed::Frame { valid, .. } => Chunk::Frame { data, valid },
+                Parsed::Garbage(_) => Chunk::Garbage(data),
+            });
+            at += len;
+        }
+        self.buf.drain(..at);
+        chunks
+    }
+
+    /// The bytes left over at the end of the stream.
+    pub fn finish(&mut self) -> Option<Chunk> {
+        (!self.buf.is_empty()).then(|| Chunk::Garbage(std::mem::take(&mut self.buf)))
+    }
+}
+
+/// The frames in `data`, if it is nothing but CRSF frames.
+pub fn split(data: &[u8]) -> Option<Vec<Chunk>> {
+    let mut deframer = Deframer::default();
+    let chunks = deframer.push(data);
+    let frames_only = deframer.finish().is_none()
+        && !chunks.is_empty()
+        && chunks.iter().all(|c| matches!(c, Chunk::Frame { .. }));
+    frames_only.then_some(chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crsf::{CrsfPacket, FlightMode};
+
+    #[test]
+    fn deframe() {
+        let mode = crsf::build_packet(
+            device_address::RADIO_TRANSMITTER,
+            &CrsfPacket::FlightMode(FlightMode {
+                mode: "ACRO".into(),
+            }),
+        )
+        .unwrap();
+        let mut stream = vec![0x55, 0xc8, 0xff];
+        stream.extend_from_slice(&mode);
+        let mut bad = mode.clone();
+        *bad.last_mut().unwrap() ^= 1;
+        stream.extend_from_slice(&bad);
+
+        // In pieces, with the last frame split over them.
+        let mut deframer = Deframer::default();
+        let mut chunks = deframer.push(&stream[..10]);
+        chunks.extend(deframer.push(&stream[10..stream.len() - 2]));
+        assert_eq!(
+            chunks,
+            [
+                Chunk::Garbage(vec![0x55, 0xc8, 0xff]),
+                Chunk::Frame {
+                    data: mode.clone(),
+                    valid: true
+                },
+            ]
+        );
+        assert_eq!(
+            deframer.push(&stream[stream.len() - 2..]),
+            [Chunk::Frame {
+                data: bad.clone(),
+                valid: false
+            }]
+        );
+        assert_eq!(deframer.push(&mode[..3]), []);
+        assert_eq!(deframer.finish(), Some(Chunk::Garbage(mode[..3].to_vec())));
+        assert_eq!(deframer.finish(), None);
+
+        assert_eq!(split(&mode).map(|c| c.len()), Some(1));
+        assert_eq!(
+            split(&[mode.clone(), bad].concat()).map(|c| c.len()),
+            Some(2)
+        );
+        assert_eq!(split(&stream), None);
+        assert_eq!(split(&mode[..4]), None);
+        assert_eq!(split(&[]), None);
+
+        // A sync byte with a length that runs into the next frame, which
+        // is found once that is given up on.
+        let stuck = [&[0xc8, 30, 0x01][..], &mode].concat();
+        let sync = [device_address::FLIGHT_CONTROLLER];
+        assert_eq!(next_frame(&stuck, &sync, crsf_frame), None);
+        assert_eq!(resync(&stuck, &CRSF_SYNC), 3);
+        assert_eq!(resync(&stuck, &sync), stuck.len());
+        assert_eq!(
+            next_frame(&stuck[3..], &CRSF_SYNC, crsf_frame),
+            Some(Parsed::Frame {
+                len: mode.len(),
+                valid: true
+            })
+        );
+        assert_eq!(next_frame(&[], &sync, crsf_frame), None);
+    }
+}
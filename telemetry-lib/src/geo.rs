@@ -25,6 +25,125 @@ pub fn coord_from_gps(gps: (f64, f64, f64), base: (f64, f64)) -> [f64; 3] {
     [x, alt, z]
 }
 
+/// Geoid heights on a 30° grid, m: rows from 90°S to 90°N, columns from
+/// 180°W to 180°E.
+const GEOID_GRID: [[f64; 13]; 7] = [
+    [
+        -30.0, -30.0, -30.0, -30.0, -30.0, -30.0, -30.0, -30.0, -30.0, -30.0, -30.0, -30.0, -30.0,
+    ],
+    [
+        -15.0, -30.0, -40.0, -15.0, 0.0, 10.0, 5.0, 20.0, -5.0, -40.0, -30.0, -20.0, -15.0,
+    ],
+    [
+        25.0, -5.0, -10.0, 0.0, 15.0, 20.0, 20.0, 30.0, -15.0, -10.0, -30.0, 22.0, 25.0,
+    ],
+    [
+        20.0, 5.0, -10.0, 5.0, -18.0, 10.0, 17.0, -10.0, -60.0, -70.0, 55.0, 65.0, 20.0,
+    ],
+    [
+        10.0, 0.0, -35.0, -25.0, -40.0, 35.0, 45.0, 15.0, -35.0, -40.0, 10.0, 20.0, 10.0,
+    ],
+    [
+        0.0, 5.0, -15.0, -35.0, -10.0, 60.0, 50.0, 20.0, -5.0, -30.0, -15.0, 10.0, 0.0,
+    ],
+    [
+        14.0, 14.0, 14.0, 14.0, 14.0, 14.0, 14.0, 14.0, 14.0, 14.0, 14.0, 14.0, 14.0,
+    ],
+];
+
+/// Height of the geoid (mean sea level) above the WGS84 ellipsoid at
+/// `lat`, `lon` (degrees), m.
+///
+/// A coarse approximation of EGM96, interpolated from a 30° grid: it has
+/// the large features, such as the Indian Ocean low and the New Guinea
+/// high, but can be off by 10-20 m locally. Good enough for plausible
+/// GPS output, not for surveying.
+pub fn geoid_separation(lat: f64, lon: f64) -> f64 {
+    let y = (lat.clamp(-90.0, 90.0) + 90.0) / 30.0;
+    let x = (lon + 180.0).rem_euclid(360.0) / 30.0;
+    let (row, col) = ((y as usize).min(5), (x as usize).min(11));
+    let (fy, fx) = (y - row as f64, x - col as f64);
+    let at = |r: usize, c: usize| GEOID_GRID[r][c];
+    let south = at(row, col) * (1.0 - fx) + at(row, col + 1) * fx;
+    let north = at(row + 1, col) * (1.0 - fx) + at(row + 1, col + 1) * fx;
+    south * (1.0 - fy) + north * fy
+}
+
+/// Degree of the magnetic field model.
+const FIELD_DEGREE: usize = 4;
+
+/// IGRF-13 main field coefficients for 2020 up to [`FIELD_DEGREE`], nT:
+/// (n, m, g, h), Schmidt semi-normalized.
+const IGRF_2020: [(usize, usize, f64, f64); 14] = [
+    (1, 0, -29404.8, 0.0),
+    (1, 1, -1450.9, 4652.5),
+    (2, 0, -2499.6, 0.0),
+    (2, 1, 2982.0, -2991.6),
+    (2, 2, 1677.0, -734.6),
+    (3, 0, 1363.2, 0.0),
+    (3, 1, -2381.2, -82.1),
+    (3, 2, 1236.2, 241.9),
+    (3, 3, 525.7, -543.4),
+    (4, 0, 903.0, 0.0),
+    (4, 1, 809.5, 281.9),
+    (4, 2, 86.3, -158.4),
+    (4, 3, -309.4, 199.7),
+    (4, 4, 48.0, -349.7),
+];
+
+/// Magnetic declination at `lat`, `lon` (degrees) at the surface, degrees,
+/// positive east.
+///
+/// Evaluates the IGRF-13 field for 2020, truncated to degree 4 and on a
+/// spherical Earth. That gives the large-scale pattern, within a few
+/// degrees of the full model away from the magnetic poles; the secular
+/// variation (some 0.1°/year) is left out.
+pub fn magnetic_declination(lat: f64, lon: f64) -> f64 {
+    // Colatitude, kept off the poles where the declination is undefined.
+    let theta = (90.0 - lat).clamp(1e-6, 180.0 - 1e-6).to_radians();
+    let phi = lon.to_radians();
+    let (cos, sin) = (theta.cos(), theta.sin());
+
+    // Schmidt semi-normalized associated Legendre functions and their
+    // derivatives with respect to theta.
+    const N: usize = FIELD_DEGREE + 1;
+    let mut p = [[0.0; N]; N];
+    let mut dp = [[0.0; N]; N];
+    p[0][0] = 1.0;
+    for n in 1..N {
+        let k = if n == 1 {
+            1.0
+        } else {
+            ((2 * n - 1) as f64 / (2 * n) as f64).sqrt()
+        };
+        p[n][n] = k * sin * p[n - 1][n - 1];
+        dp[n][n] = k * (cos * p[n - 1][n - 1] + sin * dp[n - 1][n - 1]);
+        for m in 0..n {
+            let (p2, dp2) = if n >= 2 {
+                (p[n - 2][m], dp[n - 2][m])
+            } else {
+                (0.0, 0.0)
+            };
+            let a = (2 * n - 1) as f64;
+            let b = (((n - 1) * (n - 1)) as f64 - (m * m) as f64)
+                .max(0.0)
+                .sqrt();
+            let c = ((n * n - m * m) as f64).sqrt();
+            p[n][m] = (a * cos * p[n - 1][m] - b * p2) / c;
+            dp[n][m] = (a * (cos * dp[n - 1][m] - sin * p[n - 1][m]) - b * dp2) / c;
+        }
+    }
+
+    // North and east components of the field.
+    let (mut x, mut y) = (0.0, 0.0);
+    for (n, m, g, h) in IGRF_2020 {
+        let (sin_m, cos_m) = (m as f64 * phi).sin_cos();
+        x += (g * cos_m + h * sin_m) * dp[n][m];
+        y += m as f64 * (g * sin_m - h * cos_m) * p[n][m] / sin;
+    }
+    y.atan2(x).to_degrees()
+}
+
 pub fn quat2heading(q0: f64, q1: f64, q2: f64, q3: f64) -> f64 {
     let y = 2.0 * ((q2 * q0) + (q3 * q1));
     let x = q3.powi(2) + q2.powi(2) - q0.powi(2) - q1.powi(2);
@@ -94,6 +213,38 @@ mod tests {
         assert!((lon - base.0).abs() < 0.01);
     }
 
+    #[test]
+    fn test_geoid_separation() {
+        assert_eq!(geoid_separation(90.0, 123.0), 14.0);
+        assert_eq!(geoid_separation(-90.0, -45.0), -30.0);
+        assert_eq!(geoid_separation(0.0, 0.0), 17.0);
+        // Between grid points, and across the antimeridian.
+        assert_eq!(
+            geoid_separation(15.0, 15.0),
+            (17.0 - 10.0 + 45.0 + 15.0) / 4.0
+        );
+        assert_eq!(geoid_separation(0.0, 180.0), geoid_separation(0.0, -180.0));
+        assert_eq!(geoid_separation(0.0, 195.0), geoid_separation(0.0, -165.0));
+        // The Indian Ocean low.
+        assert!(geoid_separation(0.0, 75.0) < -60.0);
+    }
+
+    #[test]
+    fn test_magnetic_declination() {
+        // Within a few degrees of the full model (WMM2020).
+        for (lat, lon, declination) in [
+            (37.8, -122.5, 13.0),  // San Francisco
+            (40.7, -74.0, -13.0),  // New York
+            (47.37, 8.55, 3.0),    // Zurich
+            (-33.86, 151.2, 12.8), // Sydney
+            (-33.9, 18.4, -25.0),  // Cape Town
+        ] {
+            let d = magnetic_declination(lat, lon);
+            assert!((d - declination).abs() < 3.0, "{} {}: {}", lat, lon, d);
+        }
+        assert!(magnetic_declination(90.0, 0.0).is_finite());
+    }
+
     #[test]
     fn test_quat2eulers_identity() {
         let (roll, pitch, yaw) = quat2eulers(0.0, 0.0, 0.0, 1.0);
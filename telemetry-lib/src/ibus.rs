@@ -0,0 +1,75 @@
+//! FlySky IBUS RC frames (the servo output of the receiver).
+//!
+//! A frame is 32 bytes: the header `0x20 0x40`, 14 channels as
+//! little-endian `u16` in µs, and a little-endian checksum, `0xffff` minus
+//! the sum of all bytes before it. Receivers with more than 14 channels
+//! put the extra ones in the high nibbles of the channel words; those are
+//! not decoded.
+
+pub const FRAME_SIZE: usize = 32;
+pub const HEADER: [u8; 2] = [0x20, 0x40];
+pub const CHANNELS: usize = 14;
+
+fn checksum(data: &[u8]) -> u16 {
+    data.iter()
+        .fold(0xffffu16, |sum, &b| sum.wrapping_sub(b as u16))
+}
+
+/// Whether `data` starts with the IBUS header.
+pub fn is_header(data: &[u8]) -> bool {
+    data.starts_with(&HEADER)
+}
+
+/// Parse a single 32-byte IBUS frame into channel values in µs.
+pub fn parse_frame(data: &[u8]) -> Option<[u16; CHANNELS]> {
+    if data.len() != FRAME_SIZE || !is_header(data) {
+        return None;
+    }
+    let sum = u16::from_le_bytes([data[30], data[31]]);
+    if checksum(&data[..30]) != sum {
+        return None;
+    }
+    let mut channels = [0u16; CHANNELS];
+    for (i, ch) in channels.iter_mut().enumerate() {
+        *ch = u16::from_le_bytes([data[2 + 2 * i], data[3 + 2 * i]]) & 0x0fff;
+    }
+    Some(channels)
+}
+
+/// Build a 32-byte IBUS frame from channel values in µs.
+pub fn build_frame(channels: &[u16; CHANNELS]) -> [u8; FRAME_SIZE] {
+    let mut out = [0u8; FRAME_SIZE];
+    out[..2].copy_from_slice(&HEADER);
+    for (i, ch) in channels.iter().enumerate() {
+        out[2 + 2 * i..4 + 2 * i].copy_from_slice(&ch.to_le_bytes());
+    }
+    let sum = checksum(&out[..30]);
+    out[30..].copy_from_slice(&sum.to_le_bytes());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let mut channels = [1500u16; CHANNELS];
+        channels[0] = 1000;
+        channels[13] = 2000;
+        let data = build_frame(&channels);
+        assert_eq!(data[..4], [0x20, 0x40, 0xe8, 0x03]);
+        assert_eq!(parse_frame(&data), Some(channels));
+    }
+
+    #[test]
+    fn test_reject_malformed() {
+        let mut data = build_frame(&[1500; CHANNELS]);
+        assert!(parse_frame(&data[..31]).is_none());
+        data[5] ^= 1;
+        assert!(parse_frame(&data).is_none());
+        data[5] ^= 1;
+        data[1] = 0x41;
+        assert!(parse_frame(&data).is_none());
+    }
+}
@@ -1,7 +1,17 @@
 pub mod crsf;
 pub mod crsf_custom;
 pub mod crsf_tx;
+pub mod events;
+pub mod framing;
 pub mod geo;
+pub mod ibus;
+pub mod pcap;
+pub mod race;
+pub mod sbus;
 pub mod simstate;
+pub mod sumd;
+pub mod systemd;
 pub mod telemetry;
 pub mod topics;
+#[cfg(feature = "wasm")]
+pub mod wasm;
@@ -0,0 +1,422 @@
+//! Reading pcap and pcapng files.
+//!
+//! Of the packets, the UDP datagrams over Ethernet, raw IP, Linux cooked
+//! capture and loopback are taken, and the packets of the `USER0` link
+//! type (147), which the captures of `crsf-forward --capture` are: CRSF
+//! frames and garbage from the serial port. IP fragments and IPv6
+//! extension headers are not followed.
+//!
+//! The pcapng block types, options and flags are public, for writing such
+//! captures.
+
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+
+const PCAP_MICROS: u32 = 0xa1b2_c3d4;
+const PCAP_NANOS: u32 = 0xa1b2_3c4d;
+pub const SECTION_HEADER: u32 = 0x0a0d_0d0a;
+pub const INTERFACE_DESCRIPTION: u32 = 1;
+pub const ENHANCED_PACKET: u32 = 6;
+pub const BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+
+/// Options.
+pub const OPT_END: u16 = 0;
+pub const OPT_COMMENT: u16 = 1;
+pub const IF_NAME: u16 = 2;
+pub const IF_TSRESOL: u16 = 9;
+pub const EPB_FLAGS: u16 = 2;
+
+/// Enhanced packet flags.
+pub const INBOUND: u32 = 0b01;
+pub const OUTBOUND: u32 = 0b10;
+pub const CRC_ERROR: u32 = 1 << 24;
+
+/// Link types.
+const LINKTYPE_NULL: u32 = 0;
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+pub const LINKTYPE_USER0: u32 = 147;
+const LINKTYPE_IPV4: u32 = 228;
+const LINKTYPE_IPV6: u32 = 229;
+const LINKTYPE_LINUX_SLL2: u32 = 276;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const IPPROTO_UDP: u8 = 17;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Packet<'a> {
+    /// Seconds since the epoch.
+    pub time: f64,
+    pub link_type: u32,
+    pub data: &'a [u8],
+    /// The enhanced packet flags, if given.
+    pub flags: Option<u32>,
+}
+
+impl Packet<'_> {
+    /// Whether the packet was sent rather than received, as far as known.
+    pub fn outbound(&self) -> bool {
+        self.flags
+            .is_some_and(|flags| flags & (INBOUND | OUTBOUND) == OUTBOUND)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Payload<'a> {
+    /// CRSF frames, or garbage.
+    Crsf(&'a [u8]),
+    Udp {
+        from: SocketAddr,
+        to: SocketAddr,
+        data: &'a [u8],
+    },
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Reads the words of a file in its byte order.
+#[derive(Clone, Copy)]
+struct Order {
+    big_endian: bool,
+}
+
+impl Order {
+    const LITTLE: Order = Order { big_endian: false };
+
+    fn u16(self, data: &[u8], at: usize) -> u16 {
+        let bytes = [data[at], data[at + 1]];
+        if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        }
+    }
+
+    fn u32(self, data: &[u8], at: usize) -> u32 {
+        let bytes = [data[at], data[at + 1], data[at + 2], data[at + 3]];
+        if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+}
+
+/// The packets in a pcap or pcapng file.
+pub fn packets(data: &[u8]) -> io::Result<Vec<Packet<'_>>> {
+    if data.len() < 4 {
+        return Err(invalid("not a pcap or pcapng file"));
+    }
+    let magic = Order::LITTLE.u32(data, 0);
+    if magic == SECTION_HEADER {
+        pcapng(data)
+    } else if [PCAP_MICROS, PCAP_NANOS].contains(&magic)
+        || [PCAP_MICROS, PCAP_NANOS].contains(&magic.swap_bytes())
+    {
+        pcap(data)
+    } else {
+        Err(invalid("not a pcap or pcapng file"))
+    }
+}
+
+fn pcap(data: &[u8]) -> io::Result<Vec<Packet<'_>>> {
+    if data.len() < 24 {
+        return Err(invalid("truncated header"));
+    }
+    let magic = Order::LITTLE.u32(data, 0);
+    let order = Order {
+        big_endian: ![PCAP_MICROS, PCAP_NANOS].contains(&magic),
+    };
+    let resolution = if order.u32(data, 0) == PCAP_NANOS {
+        1e-9
+    } else {
+        1e-6
+    };
+    let link_type = order.u32(data, 20) & 0xffff;
+    let mut rest = &data[24..];
+    let mut packets = Vec::new();
+    while !rest.is_empty() {
+        if rest.len() < 16 {
+            return Err(invalid("truncated packet"));
+        }
+        let len = order.u32(rest, 8) as usize;
+        let packet = rest
+            .get(16..16 + len)
+            .ok_or_else(|| invalid("truncated packet"))?;
+        packets.push(Packet {
+            time: f64::from(order.u32(rest, 0)) + f64::from(order.u32(rest, 4)) * resolution,
+            link_type,
+            data: packet,
+            flags: None,
+        });
+        rest = &rest[16 + len..];
+    }
+    Ok(packets)
+}
+
+/// The link type and timestamp resolution of an interface description
+/// block `body`.
+fn interface(order: Order, body: &[u8]) -> io::Result<(u32, f64)> {
+    if body.len() < 8 {
+        return Err(invalid("truncated interface"));
+    }
+    let link_type = u32::from(order.u16(body, 0));
+    let mut resolution = 1e-6;
+    for (code, value) in options(order, &body[8..])? {
+        if code == IF_TSRESOL && value.len() == 1 {
+            let exponent = i32::from(value[0] & 0x7f);
+            resolution = if value[0] & 0x80 == 0 {
+                10f64.powi(-exponent)
+            } else {
+                2f64.powi(-exponent)
+            };
+        }
+    }
+    Ok((link_type, resolution))
+}
+
+/// The options of a block: code and value.
+fn options(order: Order, mut data: &[u8]) -> io::Result<Vec<(u16, &[u8])>> {
+    let mut options = Vec::new();
+    while data.len() >= 4 {
+        let code = order.u16(data, 0);
+        let len = order.u16(data, 2) as usize;
+        if code == OPT_END {
+            break;
+        }
+        let value = data
+            .get(4..4 + len)
+            .ok_or_else(|| invalid("truncated option"))?;
+        options.push((code, value));
+        data = data
+            .get((4 + len).next_multiple_of(4)..)
+            .unwrap_or_default();
+    }
+    Ok(options)
+}
+
+fn pcapng(mut data: &[u8]) -> io::Result<Vec<Packet<'_>>> {
+    let mut order = Order::LITTLE;
+    // Link type and timestamp resolution of the interfaces of the section.
+    let mut interfaces = Vec::new();
+    let mut packets = Vec::new();
+    while !data.is_empty() {
+        if data.len() < 12 {
+            return Err(invalid("truncated block"));
+        }
+        let kind = order.u32(data, 0);
+        if kind == SECTION_HEADER {
+            order.big_endian = match Order::LITTLE.u32(data, 8) {
+                BYTE_ORDER_MAGIC => false,
+                magic if magic.swap_bytes() == BYTE_ORDER_MAGIC => true,
+                _ => return Err(invalid("bad byte order magic")),
+            };
+            interfaces.clear();
+        }
+        let len = order.u32(data, 4) as usize;
+        if len < 12 || !len.is_multiple_of(4) || len > data.len() {
+            return Err(invalid("bad block length"));
+        }
+        let body = &data[8..len - 4];
+        match kind {
+            INTERFACE_DESCRIPTION => interfaces.push(interface(order, body)?),
+            ENHANCED_PACKET => {
+                if body.len() < 20 {
+                    return Err(invalid("truncated packet"));
+                }
+                let &(link_type, resolution) = interfaces
+                    .get(order.u32(body, 0) as usize)
+                    .ok_or_else(|| invalid("packet of an unknown interface"))?;
+                let ticks = u64::from(order.u32(body, 4)) << 32 | u64::from(order.u32(body, 8));
+                let end = 20 + order.u32(body, 12) as usize;
+                let packet = body
+                    .get(20..end)
+                    .ok_or_else(|| invalid("truncated packet"))?;
+                let rest = body.get(end.next_multiple_of(4)..).unwrap_or_default();
+                let flags = options(order, rest)?
+                    .into_iter()
+                    .find(|&(code, value)| code == EPB_FLAGS && value.len() == 4)
+                    .map(|(_, value)| order.u32(value, 0));
+                packets.push(Packet {
+                    time: ticks as f64 * resolution,
+                    link_type,
+                    data: packet,
+                    flags,
+                });
+            }
+            _ => {}
+        }
+        data = &data[len..];
+    }
+    Ok(packets)
+}
+
+fn u16_be(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*data.get(at)?, *data.get(at + 1)?]))
+}
+
+/// The UDP datagram in an IP packet.
+fn ip(packet: &[u8]) -> Option<Payload<'_>> {
+    let (from, to, udp) = match packet.first()? >> 4 {
+        4 => {
+            let header_len = usize::from(packet[0] & 0x0f) * 4;
+            let total_len = usize::from(u16_be(packet, 2)?);
+            // More fragments, or not the first.
+            let fragment = u16_be(packet, 6)? & 0x3fff != 0;
+            if header_len < 20 || *packet.get(9)? != IPPROTO_UDP || fragment {
+                return None;
+            }
+            let from: [u8; 4] = packet.get(12..16)?.try_into().ok()?;
+            let to: [u8; 4] = packet.get(16..20)?.try_into().ok()?;
+            let udp = packet.get(header_len..total_len.min(packet.len()))?;
+            (IpAddr::from(from), IpAddr::from(to), udp)
+        }
+        6 => {
+            if *packet.get(6)? != IPPROTO_UDP {
+                return None;
+            }
+            let from: [u8; 16] = packet.get(8..24)?.try_into().ok()?;
+            let to: [u8; 16] = packet.get(24..40)?.try_into().ok()?;
+            let end = 40 + usize::from(u16_be(packet, 4)?);
+            let udp = packet.get(40..end.min(packet.len()))?;
+            (IpAddr::from(from), IpAddr::from(to), udp)
+        }
+        _ => return None,
+    };
+    let len = usize::from(u16_be(udp, 4)?);
+    Some(Payload::Udp {
+        from: SocketAddr::new(from, u16_be(udp, 0)?),
+        to: SocketAddr::new(to, u16_be(udp, 2)?),
+        data: udp.get(8..len)?,
+    })
+}
+
+/// The payload of `packet`, if it has one to decode.
+pub fn payload<'a>(packet: &Packet<'a>) -> Option<Payload<'a>> {
+    let data = packet.data;
+    match packet.link_type {
+        LINKTYPE_USER0 => Some(Payload::Crsf(data)),
+        LINKTYPE_NULL => ip(data.get(4..)?),
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => ip(data),
+        LINKTYPE_ETHERNET => {
+            let mut at = 12;
+            while u16_be(data, at)? == ETHERTYPE_VLAN {
+                at += 4;
+            }
+            match u16_be(data, at)? {
+                ETHERTYPE_IPV4 | ETHERTYPE_IPV6 => ip(data.get(at + 2..)?),
+                _ => None,
+            }
+        }
+        LINKTYPE_LINUX_SLL => match u16_be(data, 14)? {
+            ETHERTYPE_IPV4 | ETHERTYPE_IPV6 => ip(data.get(16..)?),
+            _ => None,
+        },
+        LINKTYPE_LINUX_SLL2 => match u16_be(data, 0)? {
+            ETHERTYPE_IPV4 | ETHERTYPE_IPV6 => ip(data.get(20..)?),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An Ethernet frame with a UDP datagram from 10.0.0.2:9001 to
+    /// 10.0.0.1:9001.
+    fn ethernet(payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0; 12];
+        frame.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        frame.extend_from_slice(&[0x45, 0]);
+        frame.extend_from_slice(&(28 + payload.len() as u16).to_be_bytes());
+        frame.extend_from_slice(&[0, 0, 0x40, 0, 64, IPPROTO_UDP, 0, 0]);
+        frame.extend_from_slice(&[10, 0, 0, 2, 10, 0, 0, 1]);
+        frame.extend_from_slice(&9001u16.to_be_bytes());
+        frame.extend_from_slice(&9001u16.to_be_bytes());
+        frame.extend_from_slice(&(8 + payload.len() as u16).to_be_bytes());
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(payload);
+        // Ethernet padding.
+        frame.resize(frame.len().max(60), 0);
+        frame
+    }
+
+    fn block(kind: u32, body: &[u8]) -> Vec<u8> {
+        let len = (12 + body.len()) as u32;
+        [
+            &kind.to_be_bytes(),
+            &len.to_be_bytes(),
+            body,
+            &len.to_be_bytes(),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn read() {
+        // pcap, little-endian, µs.
+        let mut file = Vec::new();
+        file.extend_from_slice(&PCAP_MICROS.to_le_bytes());
+        file.extend_from_slice(&[2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 4, 0]);
+        file.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+        let frame = ethernet(b"hello");
+        for word in [1000, 250000, frame.len() as u32, frame.len() as u32] {
+            file.extend_from_slice(&u32::to_le_bytes(word));
+        }
+        file.extend_from_slice(&frame);
+        let packets = packets(&file).unwrap();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].time, 1000.25);
+        assert_eq!(
+            payload(&packets[0]),
+            Some(Payload::Udp {
+                from: "10.0.0.2:9001".parse().unwrap(),
+                to: "10.0.0.1:9001".parse().unwrap(),
+                data: b"hello",
+            })
+        );
+        assert!(super::packets(&file[..file.len() - 1]).is_err());
+
+        // pcapng, big-endian, with a USER0 interface in ms.
+        let mut section = BYTE_ORDER_MAGIC.to_be_bytes().to_vec();
+        section.extend_from_slice(&[0, 1, 0, 0]);
+        section.extend_from_slice(&(-1i64).to_be_bytes());
+        let mut user0 = (LINKTYPE_USER0 as u16).to_be_bytes().to_vec();
+        user0.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+        user0.extend_from_slice(&IF_TSRESOL.to_be_bytes());
+        user0.extend_from_slice(&[0, 1, 3, 0, 0, 0]);
+        user0.extend_from_slice(&[0; 4]);
+        let mut epb = 0u32.to_be_bytes().to_vec();
+        for word in [0, 1500, 3, 3] {
+            epb.extend_from_slice(&u32::to_be_bytes(word));
+        }
+        epb.extend_from_slice(&[0xc8, 0x55, 0xaa, 0]);
+        epb.extend_from_slice(&EPB_FLAGS.to_be_bytes());
+        epb.extend_from_slice(&4u16.to_be_bytes());
+        epb.extend_from_slice(&OUTBOUND.to_be_bytes());
+        epb.extend_from_slice(&[0; 4]);
+        let file = [
+            block(SECTION_HEADER, &section),
+            block(INTERFACE_DESCRIPTION, &user0),
+            block(ENHANCED_PACKET, &epb),
+        ]
+        .concat();
+        let packets = super::packets(&file).unwrap();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].time, 1.5);
+        assert_eq!(packets[0].flags, Some(OUTBOUND));
+        assert!(packets[0].outbound());
+        assert_eq!(
+            payload(&packets[0]),
+            Some(Payload::Crsf(&[0xc8, 0x55, 0xaa]))
+        );
+        assert!(super::packets(b"not a capture").is_err());
+    }
+}
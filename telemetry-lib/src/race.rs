@@ -0,0 +1,273 @@
+//! Lap timing: the quad passing the start/finish line and the gates of a
+//! course, from its positions.
+//!
+//! A gate is a vertical line between two points on the ground, `[x, z]` in
+//! Liftoff coordinates (m), optionally limited to heights from a bottom to
+//! a top. It can be passed either way. A lap starts at the start/finish
+//! line, and counts when the gates are passed in order before crossing it
+//! again; a lap that misses a gate starts over at the line.
+//!
+//! The [`Event`]s are published as JSON on the [`crate::topics::RACE`]
+//! topic.
+
+use serde::{Deserialize, Serialize};
+
+/// Longest distance between positions, m. A longer jump is the quad being
+/// put back at the start, which ends the lap.
+pub const MAX_STEP: f64 = 30.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gate {
+    pub name: String,
+    /// Ends, `[x, z]`.
+    pub ends: [[f64; 2]; 2],
+    /// Bottom and top, if limited.
+    pub heights: Option<[f64; 2]>,
+}
+
+fn cross(a: [f64; 2], b: [f64; 2]) -> f64 {
+    a[0] * b[1] - a[1] * b[0]
+}
+
+impl Gate {
+    /// Where along the way from `from` to `to` (`[x, y, z]`) the gate is
+    /// passed, from 0 (exclusive) to 1.
+    pub fn crossing(&self, from: [f64; 3], to: [f64; 3]) -> Option<f64> {
+        let [a, b] = self.ends;
+        let way = [to[0] - from[0], to[2] - from[2]];
+        let gate = [b[0] - a[0], b[1] - a[1]];
+        let offset = [a[0] - from[0], a[1] - from[2]];
+        let denom = cross(way, gate);
+        if denom == 0.0 {
+            return None;
+        }
+        let along = cross(offset, gate) / denom;
+        let across = cross(offset, way) / denom;
+        if along <= 0.0 || along > 1.0 || !(0.0..=1.0).contains(&across) {
+            return None;
+        }
+        let height = from[1] + along * (to[1] - from[1]);
+        match self.heights {
+            Some([bottom, top]) if height < bottom || height > top => None,
+            _ => Some(along),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Course {
+    pub start: Gate,
+    /// In the order they are to be passed.
+    pub gates: Vec<Gate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// The start/finish line was crossed, starting a lap, after `missed`
+    /// gates of the lap before were not passed.
+    Start { missed: usize },
+    /// Gate `gate` (from 0) was passed `time` into the lap, s.
+    Split {
+        gate: usize,
+        name: String,
+        time: f64,
+    },
+    /// Lap `lap` (from 1) was done in `time`, s, the best lap so far if
+    /// `best`. The next lap starts.
+    Lap { lap: usize, time: f64, best: bool },
+}
+
+/// The laps so far, as served in JSON.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Standings {
+    /// Times of the laps done, s.
+    pub laps: Vec<f64>,
+    pub best: Option<f64>,
+    /// Time into the lap of each gate passed in the current lap, s.
+    pub splits: Vec<f64>,
+    /// The gate to pass next, if a lap is under way.
+    pub next_gate: Option<String>,
+}
+
+pub struct Timer {
+    course: Course,
+    /// Time (s) and position of the last sample.
+    last: Option<(f64, [f64; 3])>,
+    /// Start time of the lap under way.
+    lap_start: Option<f64>,
+    /// The gate to pass next.
+    next: usize,
+    splits: Vec<f64>,
+    laps: Vec<f64>,
+}
+
+impl Timer {
+    pub fn new(course: Course) -> Self {
+        Self {
+            course,
+            last: None,
+            lap_start: None,
+            next: 0,
+            splits: Vec::new(),
+            laps: Vec::new(),
+        }
+    }
+
+    fn abandon(&mut self) {
+        self.lap_start = None;
+        self.next = 0;
+        self.splits.clear();
+    }
+
+    /// Take the position at `time` (s), and tell what the quad passed on
+    /// the way there. A time before the last one is a new run, which
+    /// starts the timing over.
+    pub fn update(&mut self, time: f64, position: [f64; 3]) -> Vec<Event> {
+        let mut events = Vec::new();
+        let Some((last_time, from)) = self.last.replace((time, position)) else {
+            return events;
+        };
+        if time < last_time {
+            self.abandon();
+            self.laps.clear();
+            return events;
+        }
+        let step = (0..3).map(|i| (position[i] - from[i]).powi(2)).sum::<f64>();
+        if step.sqrt() > MAX_STEP {
+            self.abandon();
+            return events;
+        }
+        let at = |along: f64| last_time + along * (time - last_time);
+
+        if let Some(lap_start) = self.lap_start
+            && let Some(gate) = self.course.gates.get(self.next)
+            && let Some(along) = gate.crossing(from, position)
+        {
+            let split = at(along) - lap_start;
+            events.push(Event::Split {
+                gate: self.next,
+                name: gate.name.clone(),
+                time: split,
+            });
+            self.splits.push(split);
+            self.next += 1;
+        }
+
+        if let Some(along) = self.course.start.crossing(from, position) {
+            let now = at(along);
+            match self.lap_start {
+                Some(lap_start) if self.next == self.course.gates.len() => {
+                    let lap = now - lap_start;
+                    let best = self.laps.iter().all(|&l| lap < l);
+                    self.laps.push(lap);
+                    events.push(Event::Lap {
+                        lap: self.laps.len(),
+                        time: lap,
+                        best,
+                    });
+                }
+                Some(_) => events.push(Event::Start {
+                    missed: self.course.gates.len() - self.next,
+                }),
+                None => events.push(Event::Start { missed: 0 }),
+            }
+            self.lap_start = Some(now);
+            self.next = 0;
+            self.splits.clear();
+        }
+        events
+    }
+
+    pub fn standings(&self) -> Standings {
+        Standings {
+            laps: self.laps.clone(),
+            best: self.laps.iter().copied().reduce(f64::min),
+            splits: self.splits.clone(),
+            next_gate: self
+                .lap_start
+                .and(self.course.gates.get(self.next))
+                .map(|gate| gate.name.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gate(name: &str, x: f64) -> Gate {
+        Gate {
+            name: name.into(),
+            ends: [[x, -5.0], [x, 5.0]],
+            heights: Some([0.0, 4.0]),
+        }
+    }
+
+    #[test]
+    fn laps() {
+        let start = gate("start", 0.0);
+        assert_eq!(
+            start.crossing([-1.0, 1.0, 0.0], [3.0, 1.0, 0.0]),
+            Some(0.25)
+        );
+        assert_eq!(
+            start.crossing([3.0, 1.0, 0.0], [-1.0, 1.0, 0.0]),
+            Some(0.75)
+        );
+        // Beside, above, short of and parallel to it.
+        assert_eq!(start.crossing([-1.0, 1.0, 6.0], [1.0, 1.0, 6.0]), None);
+        assert_eq!(start.crossing([-1.0, 5.0, 0.0], [1.0, 5.0, 0.0]), None);
+        assert_eq!(start.crossing([-2.0, 1.0, 0.0], [-1.0, 1.0, 0.0]), None);
+        assert_eq!(start.crossing([0.0, 1.0, -1.0], [0.0, 1.0, 1.0]), None);
+        // Once when a position is on the line.
+        assert_eq!(start.crossing([-1.0, 1.0, 0.0], [0.0, 1.0, 0.0]), Some(1.0));
+        assert_eq!(start.crossing([0.0, 1.0, 0.0], [1.0, 1.0, 0.0]), None);
+
+        let mut timer = Timer::new(Course {
+            start,
+            gates: vec![gate("one", 10.0)],
+        });
+        let split = |time: f64| Event::Split {
+            gate: 0,
+            name: "one".into(),
+            time,
+        };
+        let lap = |lap: usize, time: f64, best: bool| Event::Lap { lap, time, best };
+        let mut fly = |time: f64, x: f64, z: f64| timer.update(time, [x, 1.0, z]);
+        assert_eq!(fly(0.0, -1.0, 0.0), []);
+        assert_eq!(fly(1.0, 1.0, 0.0), [Event::Start { missed: 0 }]);
+        // Back over the line without passing the gate.
+        assert_eq!(fly(2.0, -1.0, 0.0), [Event::Start { missed: 1 }]);
+        assert_eq!(fly(3.0, 1.0, 0.0), [Event::Start { missed: 1 }]);
+        assert_eq!(fly(4.0, 9.0, 0.0), []);
+        assert_eq!(fly(5.0, 11.0, 0.0), [split(2.0)]);
+        // Round the gates, and over the line.
+        assert_eq!(fly(6.0, 11.0, 20.0), []);
+        assert_eq!(fly(7.0, -1.0, 20.0), []);
+        assert_eq!(fly(8.0, -1.0, 0.0), []);
+        assert_eq!(fly(9.0, 1.0, 0.0), [lap(1, 6.0, true)]);
+        assert_eq!(fly(10.0, 19.0, 0.0), [split(1.0)]);
+        // Put back at the start.
+        assert_eq!(fly(11.0, 100.0, 0.0), []);
+        assert_eq!(fly(12.0, -1.0, 0.0), []);
+        assert_eq!(fly(13.0, 1.0, 0.0), [Event::Start { missed: 0 }]);
+        for (lap_start, number, best) in [(12.5, 2, true), (17.5, 3, false)] {
+            let t = lap_start + 0.5;
+            assert_eq!(fly(t + 1.0, 19.0, 0.0), [split(1.0)]);
+            assert_eq!(fly(t + 2.0, 19.0, 20.0), []);
+            assert_eq!(fly(t + 3.0, -1.0, 20.0), []);
+            assert_eq!(fly(t + 4.0, -1.0, 0.0), []);
+            assert_eq!(fly(t + 5.0, 1.0, 0.0), [lap(number, 5.0, best)]);
+        }
+
+        let standings = timer.standings();
+        assert_eq!(standings.laps.len(), 3);
+        assert_eq!(standings.best, Some(5.0));
+        assert_eq!(standings.splits, [0.0; 0]);
+        assert_eq!(standings.next_gate.as_deref(), Some("one"));
+        // A new run.
+        timer.update(0.0, [0.0, 1.0, 0.0]);
+        assert_eq!(timer.standings(), Standings::default());
+    }
+}
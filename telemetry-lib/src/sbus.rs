@@ -0,0 +1,113 @@
+//! SBUS (Futaba/FrSky) RC frames.
+//!
+//! A frame is 25 bytes: the `0x0F` start byte, 16 channels of 11 bits
+//! packed LSB first (the same layout as the CRSF RC channels payload), a
+//! flags byte and an end byte (`0x00`, or `0x04`/`0x14`/`0x24`/`0x34` for
+//! SBUS2). Channel values use the same 172..1811 nominal range as CRSF, so
+//! they can be used interchangeably.
+use crate::crsf::{pack_channels, unpack_channels};
+
+pub const FRAME_SIZE: usize = 25;
+pub const START_BYTE: u8 = 0x0f;
+
+const FLAG_CH17: u8 = 0x01;
+const FLAG_CH18: u8 = 0x02;
+const FLAG_FRAME_LOST: u8 = 0x04;
+const FLAG_FAILSAFE: u8 = 0x08;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SbusFrame {
+    pub channels: [u16; 16],
+    /// Digital channels 17 and 18.
+    pub ch17: bool,
+    pub ch18: bool,
+    /// The receiver missed a frame from the transmitter.
+    pub frame_lost: bool,
+    /// The receiver is in failsafe; the channel values are not live.
+    pub failsafe: bool,
+}
+
+fn is_end_byte(b: u8) -> bool {
+    b == 0x00 || b & 0xcf == 0x04
+}
+
+/// Whether `data` looks like an SBUS frame (start byte and length).
+pub fn is_sbus_frame(data: &[u8]) -> bool {
+    data.len() == FRAME_SIZE && data[0] == START_BYTE
+}
+
+/// Parse a single 25-byte SBUS frame.
+pub fn parse_frame(data: &[u8]) -> Option<SbusFrame> {
+    if !is_sbus_frame(data) || !is_end_byte(data[24]) {
+        return None;
+    }
+    let flags = data[23];
+    Some(SbusFrame {
+        channels: unpack_channels(&data[1..23])?,
+        ch17: flags & FLAG_CH17 != 0,
+        ch18: flags & FLAG_CH18 != 0,
+        frame_lost: flags & FLAG_FRAME_LOST != 0,
+        failsafe: flags & FLAG_FAILSAFE != 0,
+    })
+}
+
+/// Build a 25-byte SBUS frame. Returns `None` if a channel exceeds 11 bits.
+pub fn build_frame(frame: &SbusFrame) -> Option<[u8; FRAME_SIZE]> {
+    let mut out = [0u8; FRAME_SIZE];
+    out[0] = START_BYTE;
+    out[1..23].copy_from_slice(&pack_channels(&frame.channels)?);
+    out[23] = [
+        (frame.ch17, FLAG_CH17),
+        (frame.ch18, FLAG_CH18),
+        (frame.frame_lost, FLAG_FRAME_LOST),
+        (frame.failsafe, FLAG_FAILSAFE),
+    ]
+    .iter()
+    .filter(|(set, _)| *set)
+    .fold(0, |acc, (_, flag)| acc | flag);
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let mut channels = [992u16; 16];
+        channels[0] = 172;
+        channels[15] = 1811;
+        let frame = SbusFrame {
+            channels,
+            ch17: true,
+            ch18: false,
+            frame_lost: false,
+            failsafe: true,
+        };
+        let data = build_frame(&frame).unwrap();
+        assert_eq!(data[0], START_BYTE);
+        assert_eq!(data[23], FLAG_CH17 | FLAG_FAILSAFE);
+        assert_eq!(parse_frame(&data), Some(frame));
+    }
+
+    #[test]
+    fn test_reject_malformed() {
+        let frame = SbusFrame {
+            channels: [992; 16],
+            ch17: false,
+            ch18: false,
+            frame_lost: false,
+            failsafe: false,
+        };
+        let mut data = build_frame(&frame).unwrap();
+        assert!(parse_frame(&data[..24]).is_none());
+        // SBUS2 end bytes are accepted, others aren't.
+        data[24] = 0x14;
+        assert!(parse_frame(&data).is_some());
+        data[24] = 0x55;
+        assert!(parse_frame(&data).is_none());
+        data[24] = 0x00;
+        data[0] = 0xc8;
+        assert!(parse_frame(&data).is_none());
+    }
+}
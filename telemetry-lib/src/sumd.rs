@@ -0,0 +1,124 @@
+//! Graupner HoTT SUMD RC frames.
+//!
+//! A frame is the header byte `0xa8`, a status byte (`0x01` live, `0x81`
+//! failsafe), the number of channels, the channels as big-endian `u16` in
+//! 1/8 µs, and a big-endian CRC-16 (XMODEM) of all bytes before it.
+
+use crc::{CRC_16_XMODEM, Crc};
+
+pub const HEADER: u8 = 0xa8;
+pub const MAX_CHANNELS: usize = 32;
+
+const STATUS_LIVE: u8 = 0x01;
+const STATUS_FAILSAFE: u8 = 0x81;
+
+const CRC16: Crc<u16> = Crc::<u16>::new(&CRC_16_XMODEM);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SumdFrame {
+    /// Channel values in µs.
+    pub channels: Vec<u16>,
+    /// The receiver is in failsafe; the channel values are not live.
+    pub failsafe: bool,
+}
+
+/// The size of the frame that `data` starts with, from its first three
+/// bytes; `None` if they are not a SUMD frame header.
+pub fn frame_len(data: &[u8]) -> Option<usize> {
+    match *data {
+        [HEADER, STATUS_LIVE | STATUS_FAILSAFE, n, ..]
+            if (1..=MAX_CHANNELS).contains(&(n as usize)) =>
+        {
+            Some(3 + 2 * n as usize + 2)
+        }
+        _ => None,
+    }
+}
+
+/// Parse a single SUMD frame.
+pub fn parse_frame(data: &[u8]) -> Option<SumdFrame> {
+    if frame_len(data)? != data.len() {
+        return None;
+    }
+    let (body, crc) = data.split_at(data.len() - 2);
+    if CRC16.checksum(body) != u16::from_be_bytes([crc[0], crc[1]]) {
+        return None;
+    }
+    Some(SumdFrame {
+        channels: body[3..]
+            .chunks_exact(2)
+            .map(|ch| u16::from_be_bytes([ch[0], ch[1]]) / 8)
+            .collect(),
+        failsafe: data[1] == STATUS_FAILSAFE,
+    })
+}
+
+/// Build a SUMD frame. Returns `None` if there are no channels or more
+/// than [`MAX_CHANNELS`], or a value doesn't fit in 1/8 µs.
+pub fn build_frame(frame: &SumdFrame) -> Option<Vec<u8>> {
+    let n = frame.channels.len();
+    if !(1..=MAX_CHANNELS).contains(&n) {
+        return None;
+    }
+    let status = if frame.failsafe {
+        STATUS_FAILSAFE
+    } else {
+        STATUS_LIVE
+    };
+    let mut out = vec![HEADER, status, n as u8];
+    for &ch in &frame.channels {
+        out.extend_from_slice(&ch.checked_mul(8)?.to_be_bytes());
+    }
+    let crc = CRC16.checksum(&out);
+    out.extend_from_slice(&crc.to_be_bytes());
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let frame = SumdFrame {
+            channels: vec![1100, 1500, 1900, 1500, 1000, 2000, 1500, 1500],
+            failsafe: false,
+        };
+        let data = build_frame(&frame).unwrap();
+        assert_eq!(data.len(), 21);
+        assert_eq!(frame_len(&data[..3]), Some(21));
+        // 1100 µs is 0x2260 in 1/8 µs.
+        assert_eq!(data[..5], [HEADER, STATUS_LIVE, 8, 0x22, 0x60]);
+        assert_eq!(parse_frame(&data), Some(frame));
+
+        let failsafe = SumdFrame {
+            channels: vec![1500; 16],
+            failsafe: true,
+        };
+        let data = build_frame(&failsafe).unwrap();
+        assert_eq!(parse_frame(&data), Some(failsafe));
+    }
+
+    #[test]
+    fn test_reject_malformed() {
+        let data = build_frame(&SumdFrame {
+            channels: vec![1500; 4],
+            failsafe: false,
+        })
+        .unwrap();
+        assert!(parse_frame(&data[..data.len() - 1]).is_none());
+        let mut bad = data.clone();
+        bad[4] ^= 1;
+        assert!(parse_frame(&bad).is_none());
+        assert_eq!(frame_len(&[HEADER, 0x02, 4]), None);
+        assert_eq!(frame_len(&[HEADER, STATUS_LIVE, 0]), None);
+        assert_eq!(frame_len(&[HEADER, STATUS_LIVE]), None);
+        assert!(
+            build_frame(&SumdFrame {
+                channels: vec![],
+                failsafe: false,
+            })
+            .is_none()
+        );
+    }
+}
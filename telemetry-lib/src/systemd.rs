@@ -0,0 +1,100 @@
+//! Minimal systemd service notification (`sd_notify(3)`), for services
+//! run with `Type=notify` and optionally `WatchdogSec=`.
+//!
+//! Outside of systemd (no `$NOTIFY_SOCKET`, or not on Linux) every call is
+//! a no-op.
+use std::io;
+#[cfg(target_os = "linux")]
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+#[cfg(target_os = "linux")]
+use std::path::Path;
+use std::time::Duration;
+
+/// Send a state string (e.g. `"READY=1"`) to the service manager. Returns
+/// `Ok(false)` if not running under systemd.
+#[cfg(target_os = "linux")]
+pub fn notify(state: &str) -> io::Result<bool> {
+    let Some(socket) = std::env::var_os("NOTIFY_SOCKET") else {
+        return Ok(false);
+    };
+    notify_to(Path::new(&socket), state)?;
+    Ok(true)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn notify(_state: &str) -> io::Result<bool> {
+    Ok(false)
+}
+
+/// Send a state string to the notification socket at `socket`. A leading
+/// `@` denotes a Linux abstract socket name.
+#[cfg(target_os = "linux")]
+pub fn notify_to(socket: &Path, state: &str) -> io::Result<()> {
+    let addr = match socket.as_os_str().as_encoded_bytes() {
+        [b'@', name @ ..] => {
+            use std::os::linux::net::SocketAddrExt;
+            SocketAddr::from_abstract_name(name)?
+        }
+        _ => SocketAddr::from_pathname(socket)?,
+    };
+    let sock = UnixDatagram::unbound()?;
+    sock.connect_addr(&addr)?;
+    sock.send(state.as_bytes())?;
+    Ok(())
+}
+
+/// Interval at which `WATCHDOG=1` must be sent, or `None` if the watchdog
+/// is not enabled for this process. This is half the configured
+/// `WatchdogSec=`, as recommended by `sd_watchdog_enabled(3)`.
+pub fn watchdog_interval() -> Option<Duration> {
+    parse_watchdog(
+        std::env::var("WATCHDOG_USEC").ok().as_deref(),
+        std::env::var("WATCHDOG_PID").ok().as_deref(),
+        std::process::id(),
+    )
+}
+
+fn parse_watchdog(usec: Option<&str>, pid: Option<&str>, own_pid: u32) -> Option<Duration> {
+    let usec: u64 = usec?.parse().ok().filter(|&u| u > 0)?;
+    // The watchdog applies to a specific PID when WATCHDOG_PID is set.
+    if let Some(pid) = pid
+        && pid.parse::<u32>().ok() != Some(own_pid)
+    {
+        return None;
+    }
+    Some(Duration::from_micros(usec / 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_watchdog() {
+        assert_eq!(parse_watchdog(None, None, 1), None);
+        assert_eq!(
+            parse_watchdog(Some("10000000"), None, 1),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(
+            parse_watchdog(Some("2000000"), Some("42"), 42),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(parse_watchdog(Some("2000000"), Some("43"), 42), None);
+        assert_eq!(parse_watchdog(Some("0"), None, 1), None);
+        assert_eq!(parse_watchdog(Some("soon"), None, 1), None);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_notify_to() {
+        let path = std::env::temp_dir().join(format!("sd-notify-test-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixDatagram::bind(&path).unwrap();
+        notify_to(&path, "READY=1").unwrap();
+        let mut buf = [0u8; 64];
+        let n = listener.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"READY=1");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -21,6 +21,22 @@ pub struct TelemetryDescriptor {
     pub stream_format: Vec<String>,
 }
 
+/// The stream format of Liftoff's default telemetry configuration.
+pub fn default_stream_format() -> Vec<String> {
+    [
+        "Timestamp",
+        "Position",
+        "Attitude",
+        "Velocity",
+        "Gyro",
+        "Input",
+        "Battery",
+        "MotorRPM",
+    ]
+    .map(String::from)
+    .to_vec()
+}
+
 pub fn parse_packet(data: &[u8], format: &[String]) -> Result<TelemetryPacket, &'static str> {
     let mut ptr = 0;
 
@@ -145,6 +161,39 @@ pub fn parse_packet(data: &[u8], format: &[String]) -> Result<TelemetryPacket, &
     })
 }
 
+/// Encode `packet` in the stream `format`, as the sim sends it. Fields the
+/// packet lacks are zeros, and no motors.
+pub fn encode_packet(packet: &TelemetryPacket, format: &[String]) -> Result<Vec<u8>, &'static str> {
+    fn floats(data: &mut Vec<u8>, values: &[f32]) {
+        for value in values {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    let mut data = Vec::new();
+    for field in format {
+        match field.as_str() {
+            "Timestamp" => floats(&mut data, &[packet.timestamp.unwrap_or_default()]),
+            "Position" => floats(&mut data, &packet.position.unwrap_or_default()),
+            "Attitude" => floats(&mut data, &packet.attitude.unwrap_or_default()),
+            "Velocity" => floats(&mut data, &packet.velocity.unwrap_or_default()),
+            "Gyro" => floats(&mut data, &packet.gyro.unwrap_or_default()),
+            "Input" => floats(&mut data, &packet.input.unwrap_or_default()),
+            "Battery" => floats(&mut data, &packet.battery.unwrap_or_default()),
+            "MotorRPM" => {
+                let rpms = packet.motor_rpm.as_deref().unwrap_or_default();
+                let count = u8::try_from(rpms.len()).map_err(|_| "Too many motors")?;
+                data.push(count);
+                floats(&mut data, rpms);
+            }
+            _ => {
+                return Err("Unknown field in stream format");
+            }
+        }
+    }
+    Ok(data)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -186,4 +235,29 @@ mod tests {
         let res = parse_packet(&data, &format);
         assert!(res.is_err());
     }
+
+    #[test]
+    fn test_encode_packet() {
+        let packet = TelemetryPacket {
+            timestamp: Some(123.4),
+            position: Some([1.0, 2.0, 3.0]),
+            attitude: None,
+            velocity: None,
+            gyro: None,
+            input: None,
+            battery: Some([0.5, 15.2]),
+            motor_rpm: Some(vec![1000.0, 2000.0, 3000.0, 4000.0]),
+        };
+        let format = default_stream_format();
+        let data = encode_packet(&packet, &format).unwrap();
+        assert_eq!(data.len(), 4 + 12 + 16 + 12 + 12 + 16 + 8 + 1 + 16);
+        let parsed = parse_packet(&data, &format).unwrap();
+        assert_eq!(parsed.timestamp, packet.timestamp);
+        assert_eq!(parsed.position, packet.position);
+        assert_eq!(parsed.attitude, Some([0.0; 4]));
+        assert_eq!(parsed.battery, packet.battery);
+        assert_eq!(parsed.motor_rpm, packet.motor_rpm);
+        let format = vec!["Unknown".to_string()];
+        assert!(encode_packet(&packet, &format).is_err());
+    }
 }
@@ -3,9 +3,14 @@ pub const TELEMETRY: &str = "telemetry";
 pub const CRSF_TELEMETRY: &str = "crsf/telemetry";
 pub const CRSF_RC: &str = "crsf/rc";
 pub const CRSF_RC_AUTOPILOT: &str = "crsf/rc/autopilot";
+pub const CRSF_RC_HEADTRACKER: &str = "crsf/rc/headtracker";
+pub const CRSF_RC_ROS: &str = "crsf/rc/ros";
 pub const MAVLINK: &str = "mavlink";
 pub const DAMAGE: &str = "damage";
 pub const BATTERY: &str = "battery";
+pub const RACE: &str = "race";
+pub const GHOST: &str = "ghost";
+pub const EVENTS: &str = "events";
 
 pub fn topic(prefix: &str, suffix: &str) -> String {
     format!("{}/{}", prefix, suffix)
@@ -0,0 +1,124 @@
+//! JavaScript bindings of the crsf, telemetry and geo modules, built for
+//! wasm32-unknown-unknown with the `wasm` feature.
+//!
+//! Packets are plain objects, as serde serializes them: a CRSF packet is
+//! `{type: "gps", data: {lat, lon, ...}}` in the units on the wire, and a
+//! telemetry packet has the fields of [`TelemetryPacket`], `null` where
+//! the stream format lacks them. Frames and datagrams are `Uint8Array`s.
+
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::crsf::{self, CrsfPacket};
+use crate::geo;
+use crate::telemetry::{self, TelemetryPacket};
+
+fn to_js<T: Serialize>(value: &T) -> Result<JsValue, JsError> {
+    Ok(value.serialize(&serde_wasm_bindgen::Serializer::json_compatible())?)
+}
+
+/// Parse a CRSF frame; `null` if it isn't a valid frame, or, unless
+/// `checkCrc` is false, its CRC is wrong.
+#[wasm_bindgen(js_name = parseCrsf)]
+pub fn parse_crsf(frame: &[u8], check_crc: Option<bool>) -> Result<JsValue, JsError> {
+    let packet = if check_crc.unwrap_or(true) {
+        crsf::parse_packet_check(frame)
+    } else {
+        crsf::parse_packet(frame)
+    };
+    to_js(&packet)
+}
+
+/// Build the CRSF frame of a packet, sent to `address` (by default the
+/// flight controller).
+#[wasm_bindgen(js_name = buildCrsf)]
+pub fn build_crsf(packet: JsValue, address: Option<u8>) -> Result<Vec<u8>, JsError> {
+    let packet: CrsfPacket = serde_wasm_bindgen::from_value(packet)?;
+    let address = address.unwrap_or(crsf::device_address::FLIGHT_CONTROLLER);
+    crsf::build_packet(address, &packet).ok_or_else(|| JsError::new("packet values out of range"))
+}
+
+/// CRC-8/DVB-S2, as over the type and payload of a frame.
+#[wasm_bindgen(js_name = crc8)]
+pub fn crc8(data: &[u8]) -> u8 {
+    crsf::calc_crc8(data)
+}
+
+/// An RC channel value in µs, from CRSF ticks.
+#[wasm_bindgen(js_name = ticksToUs)]
+pub fn ticks_to_us(ticks: u16) -> u16 {
+    crsf::ticks_to_us(ticks)
+}
+
+/// An RC channel value in CRSF ticks, from µs.
+#[wasm_bindgen(js_name = usToTicks)]
+pub fn us_to_ticks(us: u16) -> u16 {
+    crsf::us_to_ticks(us)
+}
+
+/// The stream format of Liftoff's default telemetry configuration.
+#[wasm_bindgen(js_name = defaultStreamFormat)]
+pub fn default_stream_format() -> Vec<String> {
+    telemetry::default_stream_format()
+}
+
+/// Parse a telemetry datagram in the stream `format` (by default Liftoff's
+/// default).
+#[wasm_bindgen(js_name = parseTelemetry)]
+pub fn parse_telemetry(data: &[u8], format: Option<Vec<String>>) -> Result<JsValue, JsError> {
+    let format = format.unwrap_or_else(telemetry::default_stream_format);
+    to_js(&telemetry::parse_packet(data, &format).map_err(JsError::new)?)
+}
+
+/// Encode a telemetry packet in the stream `format` (by default Liftoff's
+/// default).
+#[wasm_bindgen(js_name = serializeTelemetry)]
+pub fn serialize_telemetry(
+    packet: JsValue,
+    format: Option<Vec<String>>,
+) -> Result<Vec<u8>, JsError> {
+    let packet: TelemetryPacket = serde_wasm_bindgen::from_value(packet)?;
+    let format = format.unwrap_or_else(telemetry::default_stream_format);
+    telemetry::encode_packet(&packet, &format).map_err(JsError::new)
+}
+
+/// `[lon, lat, alt]` of the scene position `(x, y, z)`, with the scene
+/// origin at `(baseLon, baseLat)`.
+#[wasm_bindgen(js_name = gpsFromCoord)]
+pub fn gps_from_coord(x: f64, y: f64, z: f64, base_lon: f64, base_lat: f64) -> Vec<f64> {
+    let (lon, lat, alt) = geo::gps_from_coord(&[x, y, z], (base_lon, base_lat));
+    vec![lon, lat, alt]
+}
+
+/// The scene position `[x, y, z]` of `(lon, lat, alt)`, with the scene
+/// origin at `(baseLon, baseLat)`.
+#[wasm_bindgen(js_name = coordFromGps)]
+pub fn coord_from_gps(lon: f64, lat: f64, alt: f64, base_lon: f64, base_lat: f64) -> Vec<f64> {
+    geo::coord_from_gps((lon, lat, alt), (base_lon, base_lat)).to_vec()
+}
+
+/// Height of the geoid above the WGS84 ellipsoid, m.
+#[wasm_bindgen(js_name = geoidSeparation)]
+pub fn geoid_separation(lat: f64, lon: f64) -> f64 {
+    geo::geoid_separation(lat, lon)
+}
+
+/// Magnetic declination, degrees, positive east.
+#[wasm_bindgen(js_name = magneticDeclination)]
+pub fn magnetic_declination(lat: f64, lon: f64) -> f64 {
+    geo::magnetic_declination(lat, lon)
+}
+
+/// Heading of a sim attitude quaternion, radians.
+#[wasm_bindgen(js_name = quat2heading)]
+pub fn quat2heading(x: f64, y: f64, z: f64, w: f64) -> f64 {
+    geo::quat2heading(x, y, z, w)
+}
+
+/// Euler angles of a sim attitude quaternion, radians, as the CRSF
+/// attitude is built from them.
+#[wasm_bindgen(js_name = quat2eulers)]
+pub fn quat2eulers(x: f64, y: f64, z: f64, w: f64) -> Vec<f64> {
+    let (a, b, c) = geo::quat2eulers(x, y, z, w);
+    vec![a, b, c]
+}
@@ -0,0 +1,248 @@
+//! `liftoff.crsf`: CRSF frames to and from dicts.
+//!
+//! A packet is a dict with its `type` (`"gps"`, `"rc_channels_packed"`,
+//! ...) and the fields of the telemetry-lib struct, in the units on the
+//! wire, so that building a parsed packet gives the same frame.
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+use telemetry_lib::crsf::{
+    self, Airspeed, Attitude, BaroAlt, Battery, CrsfPacket, Damage, FlightMode, Gps,
+    LinkStatistics, RcChannelsPacked, RcChannelsSubset, Rpm, Vario, Voltages, device_address,
+};
+
+fn to_dict<'py>(py: Python<'py>, packet: &CrsfPacket) -> PyResult<Bound<'py, PyDict>> {
+    let d = PyDict::new(py);
+    match packet {
+        CrsfPacket::Attitude(att) => {
+            d.set_item("type", "attitude")?;
+            d.set_item("pitch", att.pitch)?;
+            d.set_item("roll", att.roll)?;
+            d.set_item("yaw", att.yaw)?;
+        }
+        CrsfPacket::Gps(gps) => {
+            d.set_item("type", "gps")?;
+            d.set_item("lat", gps.lat)?;
+            d.set_item("lon", gps.lon)?;
+            d.set_item("speed", gps.speed)?;
+            d.set_item("heading", gps.heading)?;
+            d.set_item("alt", gps.alt)?;
+            d.set_item("sats", gps.sats)?;
+        }
+        CrsfPacket::Battery(bat) => {
+            d.set_item("type", "battery")?;
+            d.set_item("voltage", bat.voltage)?;
+            d.set_item("current", bat.current)?;
+            d.set_item("capacity", bat.capacity)?;
+            d.set_item("remaining", bat.remaining)?;
+        }
+        CrsfPacket::Vario(vario) => {
+            d.set_item("type", "vario")?;
+            d.set_item("vertical_speed", vario.vertical_speed)?;
+        }
+        CrsfPacket::FlightMode(fm) => {
+            d.set_item("type", "flight_mode")?;
+            d.set_item("mode", &fm.mode)?;
+        }
+        CrsfPacket::BaroAlt(baro) => {
+            d.set_item("type", "baro_alt")?;
+            d.set_item("alt", baro.alt)?;
+            d.set_item("vertical_speed", baro.vertical_speed)?;
+        }
+        CrsfPacket::Airspeed(airspeed) => {
+            d.set_item("type", "airspeed")?;
+            d.set_item("speed", airspeed.speed)?;
+        }
+        CrsfPacket::Rpm(rpm) => {
+            d.set_item("type", "rpm")?;
+            d.set_item("source_id", rpm.source_id)?;
+            d.set_item("rpms", &rpm.rpms)?;
+        }
+        CrsfPacket::Voltages(volts) => {
+            d.set_item("type", "voltages")?;
+            d.set_item("source_id", volts.source_id)?;
+            d.set_item("voltages_mv", &volts.voltages_mv)?;
+        }
+        CrsfPacket::RcChannelsPacked(rc) => {
+            d.set_item("type", "rc_channels_packed")?;
+            d.set_item("channels", rc.channels)?;
+        }
+        CrsfPacket::RcChannelsSubset(subset) => {
+            d.set_item("type", "rc_channels_subset")?;
+            d.set_item("first_channel", subset.first_channel)?;
+            d.set_item("resolution", subset.resolution)?;
+            d.set_item("values", &subset.values)?;
+        }
+        CrsfPacket::LinkStatistics(ls) => {
+            d.set_item("type", "link_statistics")?;
+            d.set_item("snr", ls.snr)?;
+            d.set_item("rf_mode", ls.rf_mode)?;
+            d.set_item("rssi", ls.rssi)?;
+            d.set_item("lq", ls.lq)?;
+            d.set_item("tx_power", ls.tx_power)?;
+            d.set_item("tx_auc", ls.tx_auc)?;
+            d.set_item("rx_auc", ls.rx_auc)?;
+            d.set_item("snr_rx", ls.snr_rx)?;
+            d.set_item("rssi_rx", ls.rssi_rx)?;
+            d.set_item("lq_rx", ls.lq_rx)?;
+        }
+        CrsfPacket::Damage(dmg) => {
+            d.set_item("type", "damage")?;
+            d.set_item("flags", dmg.flags)?;
+            d.set_item("health", &dmg.health)?;
+        }
+        CrsfPacket::Unknown(packet_type) => {
+            d.set_item("type", "unknown")?;
+            d.set_item("packet_type", *packet_type as u8)?;
+        }
+    }
+    Ok(d)
+}
+
+/// Field `key` of the packet `d`.
+fn field<'py, T: for<'a> FromPyObject<'a, 'py>>(d: &Bound<'py, PyDict>, key: &str) -> PyResult<T> {
+    let value = d
+        .get_item(key)?
+        .ok_or_else(|| PyValueError::new_err(format!("packet has no {:?}", key)))?;
+    value
+        .extract()
+        .map_err(|_| PyValueError::new_err(format!("bad {:?}", key)))
+}
+
+fn from_dict(d: &Bound<'_, PyDict>) -> PyResult<CrsfPacket> {
+    let packet_type: String = field(d, "type")?;
+    Ok(match packet_type.as_str() {
+        "attitude" => CrsfPacket::Attitude(Attitude {
+            pitch: field(d, "pitch")?,
+            roll: field(d, "roll")?,
+            yaw: field(d, "yaw")?,
+        }),
+        "gps" => CrsfPacket::Gps(Gps {
+            lat: field(d, "lat")?,
+            lon: field(d, "lon")?,
+            speed: field(d, "speed")?,
+            heading: field(d, "heading")?,
+            alt: field(d, "alt")?,
+            sats: field(d, "sats")?,
+        }),
+        "battery" => CrsfPacket::Battery(Battery {
+            voltage: field(d, "voltage")?,
+            current: field(d, "current")?,
+            capacity: field(d, "capacity")?,
+            remaining: field(d, "remaining")?,
+        }),
+        "vario" => CrsfPacket::Vario(Vario {
+            vertical_speed: field(d, "vertical_speed")?,
+        }),
+        "flight_mode" => CrsfPacket::FlightMode(FlightMode {
+            mode: field(d, "mode")?,
+        }),
+        "baro_alt" => CrsfPacket::BaroAlt(BaroAlt {
+            alt: field(d, "alt")?,
+            vertical_speed: field(d, "vertical_speed")?,
+        }),
+        "airspeed" => CrsfPacket::Airspeed(Airspeed {
+            speed: field(d, "speed")?,
+        }),
+        "rpm" => CrsfPacket::Rpm(Rpm {
+            source_id: field(d, "source_id")?,
+            rpms: field(d, "rpms")?,
+        }),
+        "voltages" => CrsfPacket::Voltages(Voltages {
+            source_id: field(d, "source_id")?,
+            voltages_mv: field(d, "voltages_mv")?,
+        }),
+        "rc_channels_packed" => CrsfPacket::RcChannelsPacked(RcChannelsPacked {
+            channels: field(d, "channels")?,
+        }),
+        "rc_channels_subset" => CrsfPacket::RcChannelsSubset(RcChannelsSubset {
+            first_channel: field(d, "first_channel")?,
+            resolution: field(d, "resolution")?,
+            values: field(d, "values")?,
+        }),
+        "link_statistics" => CrsfPacket::LinkStatistics(LinkStatistics {
+            snr: field(d, "snr")?,
+            rf_mode: field(d, "rf_mode")?,
+            rssi: field(d, "rssi")?,
+            lq: field(d, "lq")?,
+            tx_power: field(d, "tx_power")?,
+            tx_auc: field(d, "tx_auc")?,
+            rx_auc: field(d, "rx_auc")?,
+            snr_rx: field(d, "snr_rx")?,
+            rssi_rx: field(d, "rssi_rx")?,
+            lq_rx: field(d, "lq_rx")?,
+        }),
+        "damage" => CrsfPacket::Damage(Damage {
+            flags: field(d, "flags")?,
+            health: field(d, "health")?,
+        }),
+        other => {
+            return Err(PyValueError::new_err(format!(
+                "cannot build a {:?} packet",
+                other
+            )));
+        }
+    })
+}
+
+/// Parse a CRSF frame into a packet dict; `None` if it isn't a valid frame,
+/// or, with `check_crc`, its CRC is wrong.
+#[pyfunction]
+#[pyo3(signature = (frame, check_crc = true))]
+fn parse<'py>(
+    py: Python<'py>,
+    frame: &[u8],
+    check_crc: bool,
+) -> PyResult<Option<Bound<'py, PyDict>>> {
+    let packet = if check_crc {
+        crsf::parse_packet_check(frame)
+    } else {
+        crsf::parse_packet(frame)
+    };
+    packet.map(|packet| to_dict(py, &packet)).transpose()
+}
+
+/// Build the CRSF frame of a packet dict, sent to `address` (by default
+/// the flight controller).
+#[pyfunction]
+#[pyo3(signature = (packet, address = device_address::FLIGHT_CONTROLLER))]
+fn build<'py>(
+    py: Python<'py>,
+    packet: &Bound<'py, PyDict>,
+    address: u8,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let frame = crsf::build_packet(address, &from_dict(packet)?)
+        .ok_or_else(|| PyValueError::new_err("packet values out of range"))?;
+    Ok(PyBytes::new(py, &frame))
+}
+
+/// CRC-8/DVB-S2 of `data`, as over the type and payload of a frame.
+#[pyfunction]
+fn crc8(data: &[u8]) -> u8 {
+    crsf::calc_crc8(data)
+}
+
+/// An RC channel value in µs, from CRSF ticks.
+#[pyfunction]
+fn ticks_to_us(ticks: u16) -> u16 {
+    crsf::ticks_to_us(ticks)
+}
+
+/// An RC channel value in CRSF ticks, from µs.
+#[pyfunction]
+fn us_to_ticks(us: u16) -> u16 {
+    crsf::us_to_ticks(us)
+}
+
+pub fn module(py: Python<'_>) -> PyResult<Bound<'_, PyModule>> {
+    let m = PyModule::new(py, "crsf")?;
+    m.add_function(wrap_pyfunction!(parse, &m)?)?;
+    m.add_function(wrap_pyfunction!(build, &m)?)?;
+    m.add_function(wrap_pyfunction!(crc8, &m)?)?;
+    m.add_function(wrap_pyfunction!(ticks_to_us, &m)?)?;
+    m.add_function(wrap_pyfunction!(us_to_ticks, &m)?)?;
+    m.add("FLIGHT_CONTROLLER", device_address::FLIGHT_CONTROLLER)?;
+    m.add("RADIO_TRANSMITTER", device_address::RADIO_TRANSMITTER)?;
+    Ok(m)
+}
@@ -0,0 +1,56 @@
+//! `liftoff.geo`: scene coordinates, GPS and attitude helpers.
+
+use pyo3::prelude::*;
+use telemetry_lib::geo;
+
+/// Longitude, latitude and altitude of a scene position `(x, y, z)`, with
+/// the scene origin at `base` `(lon, lat)`.
+#[pyfunction]
+#[pyo3(signature = (coord, base = (0.0, 0.0)))]
+fn gps_from_coord(coord: [f64; 3], base: (f64, f64)) -> (f64, f64, f64) {
+    geo::gps_from_coord(&coord, base)
+}
+
+/// The scene position `(x, y, z)` of `gps` `(lon, lat, alt)`, with the
+/// scene origin at `base` `(lon, lat)`.
+#[pyfunction]
+#[pyo3(signature = (gps, base = (0.0, 0.0)))]
+fn coord_from_gps(gps: (f64, f64, f64), base: (f64, f64)) -> [f64; 3] {
+    geo::coord_from_gps(gps, base)
+}
+
+/// Height of the geoid above the WGS84 ellipsoid, m.
+#[pyfunction]
+fn geoid_separation(lat: f64, lon: f64) -> f64 {
+    geo::geoid_separation(lat, lon)
+}
+
+/// Magnetic declination, degrees, positive east.
+#[pyfunction]
+fn magnetic_declination(lat: f64, lon: f64) -> f64 {
+    geo::magnetic_declination(lat, lon)
+}
+
+/// Heading of a sim attitude quaternion `(x, y, z, w)`, radians.
+#[pyfunction]
+fn quat2heading(q: [f64; 4]) -> f64 {
+    geo::quat2heading(q[0], q[1], q[2], q[3])
+}
+
+/// Euler angles of a sim attitude quaternion `(x, y, z, w)`, radians, as
+/// the CRSF attitude is built from them.
+#[pyfunction]
+fn quat2eulers(q: [f64; 4]) -> (f64, f64, f64) {
+    geo::quat2eulers(q[0], q[1], q[2], q[3])
+}
+
+pub fn module(py: Python<'_>) -> PyResult<Bound<'_, PyModule>> {
+    let m = PyModule::new(py, "geo")?;
+    m.add_function(wrap_pyfunction!(gps_from_coord, &m)?)?;
+    m.add_function(wrap_pyfunction!(coord_from_gps, &m)?)?;
+    m.add_function(wrap_pyfunction!(geoid_separation, &m)?)?;
+    m.add_function(wrap_pyfunction!(magnetic_declination, &m)?)?;
+    m.add_function(wrap_pyfunction!(quat2heading, &m)?)?;
+    m.add_function(wrap_pyfunction!(quat2eulers, &m)?)?;
+    Ok(m)
+}
@@ -0,0 +1,20 @@
+//! Python bindings of telemetry-lib: the `liftoff` module, with `crsf`,
+//! `telemetry` and `geo` submodules. Built with maturin; see the README.
+
+use pyo3::prelude::*;
+
+mod crsf;
+mod geo;
+mod telemetry;
+
+#[pymodule]
+fn liftoff(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    let modules = py.import("sys")?.getattr("modules")?;
+    for sub in [crsf::module(py)?, telemetry::module(py)?, geo::module(py)?] {
+        m.add_submodule(&sub)?;
+        // So that `import liftoff.crsf` works too.
+        modules.set_item(format!("liftoff.{}", sub.name()?), &sub)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,93 @@
+//! `liftoff.telemetry`: the sim's telemetry stream to and from dicts.
+//!
+//! A packet is a dict of the fields of `TelemetryPacket`: `timestamp`,
+//! `position`, `attitude`, `velocity`, `gyro`, `input`, `battery` and
+//! `motor_rpm`, each `None` if the stream format doesn't have it.
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+use telemetry_lib::telemetry::{self, TelemetryPacket};
+
+fn to_dict<'py>(py: Python<'py>, packet: &TelemetryPacket) -> PyResult<Bound<'py, PyDict>> {
+    let d = PyDict::new(py);
+    d.set_item("timestamp", packet.timestamp)?;
+    d.set_item("position", packet.position)?;
+    d.set_item("attitude", packet.attitude)?;
+    d.set_item("velocity", packet.velocity)?;
+    d.set_item("gyro", packet.gyro)?;
+    d.set_item("input", packet.input)?;
+    d.set_item("battery", packet.battery)?;
+    d.set_item("motor_rpm", &packet.motor_rpm)?;
+    Ok(d)
+}
+
+/// Field `key` of the packet `d`, `None` if it is missing.
+fn field<'py, T: for<'a> FromPyObject<'a, 'py>>(
+    d: &Bound<'py, PyDict>,
+    key: &str,
+) -> PyResult<Option<T>> {
+    match d.get_item(key)? {
+        Some(value) if !value.is_none() => value
+            .extract()
+            .map(Some)
+            .map_err(|_| PyValueError::new_err(format!("bad {:?}", key))),
+        _ => Ok(None),
+    }
+}
+
+fn from_dict(d: &Bound<'_, PyDict>) -> PyResult<TelemetryPacket> {
+    Ok(TelemetryPacket {
+        timestamp: field(d, "timestamp")?,
+        position: field(d, "position")?,
+        attitude: field(d, "attitude")?,
+        velocity: field(d, "velocity")?,
+        gyro: field(d, "gyro")?,
+        input: field(d, "input")?,
+        battery: field(d, "battery")?,
+        motor_rpm: field(d, "motor_rpm")?,
+    })
+}
+
+/// The stream format of Liftoff's default telemetry configuration.
+#[pyfunction]
+fn default_stream_format() -> Vec<String> {
+    telemetry::default_stream_format()
+}
+
+/// Parse a telemetry datagram into a packet dict, in the stream `format`
+/// (by default Liftoff's default).
+#[pyfunction]
+#[pyo3(signature = (data, format = None))]
+fn parse<'py>(
+    py: Python<'py>,
+    data: &[u8],
+    format: Option<Vec<String>>,
+) -> PyResult<Bound<'py, PyDict>> {
+    let format = format.unwrap_or_else(telemetry::default_stream_format);
+    let packet = telemetry::parse_packet(data, &format).map_err(PyValueError::new_err)?;
+    to_dict(py, &packet)
+}
+
+/// Encode a packet dict as a telemetry datagram, in the stream `format` (by
+/// default Liftoff's default). Missing fields are zeros.
+#[pyfunction]
+#[pyo3(signature = (packet, format = None))]
+fn serialize<'py>(
+    py: Python<'py>,
+    packet: &Bound<'py, PyDict>,
+    format: Option<Vec<String>>,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let format = format.unwrap_or_else(telemetry::default_stream_format);
+    let data =
+        telemetry::encode_packet(&from_dict(packet)?, &format).map_err(PyValueError::new_err)?;
+    Ok(PyBytes::new(py, &data))
+}
+
+pub fn module(py: Python<'_>) -> PyResult<Bound<'_, PyModule>> {
+    let m = PyModule::new(py, "telemetry")?;
+    m.add_function(wrap_pyfunction!(default_stream_format, &m)?)?;
+    m.add_function(wrap_pyfunction!(parse, &m)?)?;
+    m.add_function(wrap_pyfunction!(serialize, &m)?)?;
+    Ok(m)
+}
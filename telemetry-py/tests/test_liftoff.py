@@ -0,0 +1,82 @@
+"""Tests of the liftoff module; run after `maturin develop` with
+`python -m unittest discover telemetry-py/tests`."""
+
+import unittest
+
+import liftoff.crsf as crsf
+from liftoff import geo, telemetry
+
+
+class CrsfTest(unittest.TestCase):
+    def test_roundtrip(self):
+        gps = {
+            "type": "gps",
+            "lat": 473977420,
+            "lon": 85455940,
+            "speed": 120,
+            "heading": 9000,
+            "alt": 1500,
+            "sats": 12,
+        }
+        frame = crsf.build(gps)
+        self.assertEqual(frame[0], crsf.FLIGHT_CONTROLLER)
+        self.assertEqual(frame[-1], crsf.crc8(frame[2:-1]))
+        self.assertEqual(crsf.parse(frame), gps)
+
+        corrupt = frame[:-1] + bytes([frame[-1] ^ 0xFF])
+        self.assertIsNone(crsf.parse(corrupt))
+        self.assertEqual(crsf.parse(corrupt, check_crc=False), gps)
+
+        channels = [crsf.us_to_ticks(1500)] * 16
+        frame = crsf.build(
+            {"type": "rc_channels_packed", "channels": channels},
+            address=crsf.RADIO_TRANSMITTER,
+        )
+        parsed = crsf.parse(frame)
+        self.assertEqual(crsf.ticks_to_us(parsed["channels"][0]), 1500)
+
+        with self.assertRaises(ValueError):
+            crsf.build({"type": "gps", "lat": 0})
+        with self.assertRaises(ValueError):
+            crsf.build({"type": "flight_mode", "mode": "X" * 100})
+
+
+class TelemetryTest(unittest.TestCase):
+    def test_roundtrip(self):
+        packet = {
+            "timestamp": 1.5,
+            "position": [1.0, 2.0, 3.0],
+            "attitude": [0.0, 0.0, 0.0, 1.0],
+            "velocity": [0.0, -1.0, 0.0],
+            "gyro": [10.0, 0.0, 0.0],
+            "input": [0.5, 0.0, 0.0, 0.0],
+            "battery": [0.5, 16.0],
+            "motor_rpm": [1000.0, 2000.0, 3000.0, 4000.0],
+        }
+        data = telemetry.serialize(packet)
+        self.assertEqual(telemetry.parse(data), packet)
+
+        fmt = ["Timestamp", "Position"]
+        data = telemetry.serialize(packet, fmt)
+        self.assertEqual(len(data), 16)
+        parsed = telemetry.parse(data, fmt)
+        self.assertEqual(parsed["position"], packet["position"])
+        self.assertIsNone(parsed["gyro"])
+
+        with self.assertRaises(ValueError):
+            telemetry.parse(data[:-1], fmt)
+
+
+class GeoTest(unittest.TestCase):
+    def test_coords(self):
+        base = (8.5, 47.4)
+        lon, lat, alt = geo.gps_from_coord([100.0, 10.0, -50.0], base)
+        self.assertEqual(alt, 10.0)
+        x, y, z = geo.coord_from_gps((lon, lat, alt), base)
+        self.assertAlmostEqual(x, 100.0, places=6)
+        self.assertAlmostEqual(z, -50.0, places=6)
+        self.assertAlmostEqual(geo.quat2heading([0.0, 0.0, 0.0, 1.0]), 0.0)
+
+
+if __name__ == "__main__":
+    unittest.main()